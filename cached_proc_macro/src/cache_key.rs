@@ -0,0 +1,147 @@
+use darling::FromDeriveInput;
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields};
+
+#[derive(FromDeriveInput)]
+#[darling(attributes(cache_key))]
+struct KeyArgs {
+    #[darling(default)]
+    display: bool,
+}
+
+pub fn cache_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let args = match KeyArgs::from_derive_input(&input) {
+        Ok(args) => args,
+        Err(e) => {
+            return TokenStream::from(e.write_errors());
+        }
+    };
+
+    let ident = &input.ident;
+    let type_name = ident.to_string();
+    let write_key = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, write_fields) = gen_fields(&data.fields);
+            quote! {
+                let Self #pattern = self;
+                out.push_str(#type_name);
+                #write_fields
+            }
+        }
+        Data::Enum(data) => {
+            let arms = data.variants.iter().map(|variant| {
+                let variant_ident = &variant.ident;
+                let name = format!("{}::{}", type_name, variant_ident);
+                let (pattern, write_fields) = gen_fields(&variant.fields);
+                quote! {
+                    Self::#variant_ident #pattern => {
+                        out.push_str(#name);
+                        #write_fields
+                    }
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(union) => {
+            return syn::Error::new_spanned(
+                union.union_token,
+                "CacheKey cannot be derived for unions",
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+
+    // the type parameters are encoded with their own `CacheKey`
+    let mut generics = input.generics.clone();
+    let type_params: Vec<_> = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+    for param in type_params {
+        generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote! { #param: ::cached::CacheKey });
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let display = if args.display {
+        quote! {
+            impl #impl_generics ::std::fmt::Display for #ident #ty_generics #where_clause {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                    f.write_str(&::cached::CacheKey::cache_key(self))
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let expanded = quote! {
+        impl #impl_generics ::cached::CacheKey for #ident #ty_generics #where_clause {
+            fn write_cache_key(&self, out: &mut ::std::string::String) {
+                #write_key
+            }
+        }
+        #display
+    };
+    expanded.into()
+}
+
+// the pattern binding the fields, and the statements writing them as `{a=..,b=..}` or `(..,..)`
+fn gen_fields(fields: &Fields) -> (TokenStream2, TokenStream2) {
+    let (bindings, labels, open, close): (Vec<_>, Vec<_>, _, _) = match fields {
+        Fields::Named(fields) => {
+            let bindings: Vec<_> = fields
+                .named
+                .iter()
+                .map(|field| field.ident.clone().unwrap())
+                .collect();
+            let labels = bindings
+                .iter()
+                .map(|ident| {
+                    let label = format!("{}=", ident);
+                    quote! { out.push_str(#label); }
+                })
+                .collect();
+            (bindings, labels, '{', '}')
+        }
+        Fields::Unnamed(fields) => {
+            let bindings: Vec<_> = (0..fields.unnamed.len())
+                .map(|i| format_ident!("field_{}", i))
+                .collect();
+            let labels = bindings.iter().map(|_| quote! {}).collect();
+            (bindings, labels, '(', ')')
+        }
+        Fields::Unit => return (quote! {}, quote! {}),
+    };
+    let pattern = match fields {
+        Fields::Named(_) => quote! { { #(#bindings),* } },
+        _ => quote! { ( #(#bindings),* ) },
+    };
+    let separators = (0..bindings.len()).map(|i| {
+        if i == 0 {
+            quote! {}
+        } else {
+            quote! { out.push(','); }
+        }
+    });
+    let write_fields = quote! {
+        out.push(#open);
+        #(
+            #separators
+            #labels
+            ::cached::CacheKey::write_cache_key(#bindings, out);
+        )*
+        out.push(#close);
+    };
+    (pattern, write_fields)
+}
@@ -1,20 +1,33 @@
 use crate::helpers::*;
 use darling::ast::NestedMeta;
+use darling::util::PathList;
 use darling::FromMeta;
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
-use syn::{parse_macro_input, parse_str, Block, Ident, ItemFn, ReturnType, Type};
+use syn::token::Comma;
+use syn::{
+    parse_macro_input, parse_quote, parse_str, Block, Expr, FnArg, Ident, ItemFn, ReturnType, Type,
+    Visibility,
+};
 
 #[derive(FromMeta)]
 struct MacroArgs {
     #[darling(default)]
     name: Option<String>,
     #[darling(default)]
+    vis: Option<String>,
+    #[darling(default)]
     unbound: bool,
     #[darling(default)]
     size: Option<usize>,
     #[darling(default)]
+    size_bytes: Option<usize>,
+    #[darling(default)]
+    weigher: Option<String>,
+    #[darling(default)]
     time: Option<u64>,
     #[darling(default)]
     time_refresh: bool,
@@ -23,11 +36,17 @@ struct MacroArgs {
     #[darling(default)]
     convert: Option<String>,
     #[darling(default)]
+    key_map: Option<String>,
+    #[darling(default)]
+    hash_key: bool,
+    #[darling(default)]
+    prime_concurrency: Option<usize>,
+    #[darling(default)]
     result: bool,
     #[darling(default)]
     option: bool,
     #[darling(default)]
-    sync_writes: bool,
+    sync_writes: SyncWrites,
     #[darling(default)]
     with_cached_flag: bool,
     #[darling(default)]
@@ -36,6 +55,124 @@ struct MacroArgs {
     create: Option<String>,
     #[darling(default)]
     result_fallback: bool,
+    #[darling(default)]
+    warm_size: Option<usize>,
+    #[darling(default)]
+    quarantine_after: Option<u32>,
+    #[darling(default)]
+    quarantine_secs: Option<u64>,
+    #[darling(default)]
+    config_epoch: bool,
+    #[darling(default)]
+    invalidation: bool,
+    #[darling(default)]
+    instance: Option<String>,
+    #[darling(default)]
+    in_impl: bool,
+    #[darling(default)]
+    store_fn: Option<String>,
+    #[darling(default)]
+    thread_local: bool,
+    #[darling(default)]
+    rw_lock: bool,
+    #[darling(default)]
+    associated: bool,
+    #[darling(default)]
+    ignore: PathList,
+    #[darling(default)]
+    hasher: Option<String>,
+    #[darling(default)]
+    cache_if: Option<String>,
+    #[darling(default)]
+    cache_unless: Option<String>,
+    #[darling(default)]
+    cache_policy: bool,
+    #[darling(default)]
+    cache_errors: bool,
+    #[darling(default)]
+    err_time: Option<u64>,
+    #[darling(default)]
+    wrap: Option<Wrap>,
+    #[darling(default)]
+    store_as: Option<String>,
+    #[darling(default)]
+    from_store: Option<String>,
+    #[darling(default)]
+    instrument: bool,
+    #[darling(default)]
+    timeout_ms: Option<u64>,
+    #[darling(default)]
+    timeout_fallback: TimeoutFallback,
+}
+
+/// How the computation of missing values is synchronized
+#[derive(Default, PartialEq)]
+enum SyncWrites {
+    #[default]
+    Off,
+    /// Hold the lock of the cache, serializing all the callers
+    All,
+    /// Serialize the callers computing the same key
+    ByKey,
+    /// Share the computation of a key with the callers missing it, for async functions
+    SingleFlight,
+}
+
+impl FromMeta for SyncWrites {
+    fn from_word() -> darling::Result<Self> {
+        Ok(SyncWrites::All)
+    }
+
+    fn from_bool(value: bool) -> darling::Result<Self> {
+        Ok(if value {
+            SyncWrites::All
+        } else {
+            SyncWrites::Off
+        })
+    }
+
+    fn from_string(value: &str) -> darling::Result<Self> {
+        match value {
+            "by_key" => Ok(SyncWrites::ByKey),
+            "single_flight" => Ok(SyncWrites::SingleFlight),
+            _ => Err(darling::Error::unknown_value(value)),
+        }
+    }
+}
+
+/// How the values are wrapped in the cache
+enum Wrap {
+    /// Cache `Arc<T>`, so that hits do not clone the values
+    Arc,
+}
+
+impl FromMeta for Wrap {
+    fn from_string(value: &str) -> darling::Result<Self> {
+        match value {
+            "arc" => Ok(Wrap::Arc),
+            _ => Err(darling::Error::unknown_value(value)),
+        }
+    }
+}
+
+/// What a function whose value took longer than `timeout_ms` to compute returns
+#[derive(Default, PartialEq)]
+enum TimeoutFallback {
+    /// Return a `TimeoutError`
+    #[default]
+    Error,
+    /// Return the expired value of the key if it is still cached, else a `TimeoutError`
+    Stale,
+}
+
+impl FromMeta for TimeoutFallback {
+    fn from_string(value: &str) -> darling::Result<Self> {
+        match value {
+            "error" => Ok(TimeoutFallback::Error),
+            "stale" => Ok(TimeoutFallback::Stale),
+            _ => Err(darling::Error::unknown_value(value)),
+        }
+    }
 }
 
 pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
@@ -52,7 +189,13 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
         }
     };
     let input = parse_macro_input!(input as ItemFn);
+    expand(args, input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
 
+// the misuses of the attributes are reported as errors on the attribute
+fn expand(args: MacroArgs, input: ItemFn) -> syn::Result<TokenStream2> {
     // pull out the parts of the input
     let mut attributes = input.attrs;
     let visibility = input.vis;
@@ -61,15 +204,143 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
 
     // pull out the parts of the function signature
     let fn_ident = signature.ident.clone();
+    let fn_name = fn_ident.to_string();
     let inputs = signature.inputs.clone();
     let output = signature.output.clone();
     let asyncness = signature.asyncness;
 
-    let input_tys = get_input_types(&inputs);
-    let input_names = get_input_names(&inputs);
+    if args.instance.is_some() && args.in_impl {
+        return Err(attr_error(
+            "the instance and in_impl attributes are mutually exclusive",
+        ));
+    }
+    if args.associated && (args.instance.is_some() || args.in_impl) {
+        return Err(attr_error(
+            "the associated attribute cannot be combined with instance or in_impl",
+        ));
+    }
+    if args.store_fn.is_some() && (args.instance.is_some() || args.in_impl || args.associated) {
+        return Err(attr_error(
+            "the store_fn attribute cannot be combined with instance, in_impl or associated",
+        ));
+    }
+    if args.thread_local && (args.instance.is_some() || args.store_fn.is_some() || args.associated)
+    {
+        return Err(attr_error(
+            "the thread_local attribute cannot be combined with instance, store_fn or associated",
+        ));
+    }
+    // the caches of instances and those provided by `store_fn` are not statics
+    let external_store = args.instance.is_some() || args.store_fn.is_some();
+
+    // non-async functions returning a future, such as the methods of `#[async_trait]` impls,
+    // are cached by the output of their future
+    let original_output = output.clone();
+    let (future_output, boxed_future) = match asyncness {
+        Some(_) => (None, false),
+        None => match boxed_future_output(&output) {
+            Some(ty) => (Some(ty), true),
+            None => (impl_future_output(&output), false),
+        },
+    };
+    let output = match &future_output {
+        Some(ty) => ReturnType::Type(Default::default(), Box::new(ty.clone())),
+        None => output,
+    };
+    let is_async = asyncness.is_some() || future_output.is_some();
+
+    // the receiver of a method is not part of the key
+    let key_inputs: Punctuated<FnArg, Comma> = match (&args.instance, inputs.first()) {
+        (Some(_), Some(FnArg::Receiver(_))) => inputs.iter().skip(1).cloned().collect(),
+        (Some(_), _) => return Err(attr_error("instance requires a method taking `&self`")),
+        (None, Some(FnArg::Receiver(_))) if args.in_impl || args.associated => {
+            inputs.iter().skip(1).cloned().collect()
+        }
+        (None, _) => inputs.clone(),
+    };
+    let input_tys = get_input_types(&key_inputs)?;
+    let input_names = get_input_names(&key_inputs)?;
+    let (key_tys, key_names) = filter_ignored_inputs(&args.ignore, &input_tys, &input_names)?;
+    if !args.ignore.is_empty() && args.convert.is_some() {
+        return Err(attr_error(
+            "the ignore and convert attributes are mutually exclusive",
+        ));
+    }
+
+    // generic functions share a static for all their type parameters, which are part of
+    // the key, the values being cached as `Box<dyn Any + Send>` and downcast when returned
+    let type_params: Vec<Ident> = signature
+        .generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+    let generic = !type_params.is_empty();
+    if signature.generics.const_params().next().is_some() {
+        return Err(attr_error("cached functions cannot have const parameters"));
+    }
+    if generic {
+        // the `{fn}_no_cache` function is called with its type parameters, which cannot be
+        // given to functions with `impl Trait` arguments
+        if input_tys.iter().any(|ty| matches!(ty, Type::ImplTrait(_))) {
+            return Err(attr_error(
+                "generic functions cannot have `impl Trait` arguments, use type parameters instead",
+            ));
+        }
+        if args.convert.is_none() && key_tys.iter().any(|ty| type_mentions(ty, &type_params)) {
+            return Err(attr_error("the key of a generic function cannot depend on its type parameters, set key and convert"));
+        }
+        if args.cache_policy || args.result_fallback || args.invalidation {
+            return Err(attr_error("cache_policy, result_fallback and invalidation are not supported with generic functions"));
+        }
+    }
+
+    // the output of the cached function, without the `CachePolicy` of `cache_policy` functions
+    let cached_output = if args.cache_policy {
+        if future_output.is_some()
+            || args.result_fallback
+            || args.quarantine_after.is_some()
+            || args.with_cached_flag
+        {
+            return Err(attr_error("cache_policy is not supported with functions returning futures, result_fallback, quarantine_after or with_cached_flag"));
+        }
+        strip_cache_policy(&output)?
+    } else {
+        output.clone()
+    };
+    // the values of `wrap = "arc"` functions are returned and cached in an `Arc`
+    let cached_output = match args.wrap {
+        Some(Wrap::Arc) => {
+            if future_output.is_some() || args.with_cached_flag {
+                return Err(attr_error(
+                    "wrap is not supported with functions returning futures or with_cached_flag",
+                ));
+            }
+            wrap_output_arc(&cached_output, args.result, args.option)?
+        }
+        None => cached_output,
+    };
+
+    // the errors of `cache_errors` functions are cached along with their values, the whole
+    // `Result` being cached, expiring after `err_time` for the errors
+    if args.cache_errors
+        && (!args.result
+            || args.cache_policy
+            || args.wrap.is_some()
+            || args.store_as.is_some()
+            || args.with_cached_flag
+            || args.result_fallback)
+    {
+        return Err(attr_error("cache_errors requires the result attribute to be set, and is not supported with cache_policy, wrap, store_as, with_cached_flag or result_fallback"));
+    }
+    if args.err_time.is_some() && !args.cache_errors {
+        return Err(attr_error("err_time requires cache_errors to also be set"));
+    }
+    let cached_result_attr = args.result && !args.cache_errors;
+    // the values cached as `Expiring`, looked up unless expired
+    let expiring = args.cache_policy || args.err_time.is_some();
 
     // pull out the output type
-    let output_ty = match &output {
+    let output_ty = match &cached_output {
         ReturnType::Default => quote! {()},
         ReturnType::Type(_, ty) => quote! {#ty},
     };
@@ -81,19 +352,229 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
     let output_type_display = output_ts.to_string().replace(' ', "");
 
     if check_with_cache_flag(args.with_cached_flag, output_string) {
-        return with_cache_flag_error(output_span, output_type_display);
+        return Err(with_cache_flag_error(output_span, output_type_display));
     }
 
-    let cache_value_ty = find_value_type(args.result, args.option, &output, output_ty);
+    // the values of `store_as` functions are cached as the output of the `store_as` closure,
+    // and converted back by the `from_store` closure when returned
+    let store = match (&args.store_as, &args.from_store) {
+        (Some(store_as), Some(from_store)) => {
+            if args.wrap.is_some() || args.with_cached_flag {
+                return Err(attr_error(
+                    "store_as is not supported with wrap or with_cached_flag",
+                ));
+            }
+            let store_as = parse_str::<Expr>(store_as)
+                .map_err(|e| attr_error(format!("unable to parse store_as closure: {}", e)))?;
+            let stored_ty = match &store_as {
+                Expr::Closure(closure) => match &closure.output {
+                    ReturnType::Type(_, ty) => Some((**ty).clone()),
+                    ReturnType::Default => None,
+                },
+                _ => None,
+            }
+            .ok_or_else(|| attr_error("store_as requires a closure with an explicit return type, the type of the cached values"))?;
+            let from_store = parse_str::<Expr>(from_store)
+                .map_err(|e| attr_error(format!("unable to parse from_store closure: {}", e)))?;
+            Some((store_as, stored_ty, from_store))
+        }
+        (None, None) => None,
+        _ => return Err(attr_error("store_as and from_store must be set together")),
+    };
+
+    let cache_value_ty = match &store {
+        Some((_, stored_ty, _)) => quote! { #stored_ty },
+        None => find_value_type(
+            cached_result_attr,
+            args.option,
+            &cached_output,
+            output_ty.clone(),
+        )?,
+    };
+    // the values of `cache_policy` functions are cached along with their expiry
+    let returned_value_ty = cache_value_ty.clone();
+    let cache_value_ty = if expiring {
+        quote! { ::cached::proc_macro::Expiring<#cache_value_ty> }
+    } else {
+        cache_value_ty
+    };
+    let cache_value_ty = if generic {
+        quote! { ::std::boxed::Box<dyn ::std::any::Any + Send> }
+    } else {
+        cache_value_ty
+    };
+    // a single `&str` or `&[T]` argument is looked up borrowed, only being made owned when
+    // its value is cached
+    let borrowed_key = match (key_tys.as_slice(), key_names.as_slice()) {
+        ([key_ty], [key_name])
+            if args.convert.is_none()
+                && args.key_map.is_none()
+                && !args.hash_key
+                && !args.config_epoch
+                && !generic
+                && future_output.is_none() =>
+        {
+            owned_key_type(key_ty).map(|owned_ty| (owned_ty, key_name.clone()))
+        }
+        _ => None,
+    };
+    // a reference to the key bound by the generated functions, and its owned value
+    let (key_ref, key_owned) = match &borrowed_key {
+        Some(_) => (quote! {key}, quote! {::std::borrow::ToOwned::to_owned(key)}),
+        None => (quote! {&key}, quote! {key}),
+    };
+    // the caches behind a `RwLock` are looked up through a shared reference
+    let cache_lookup = if args.rw_lock {
+        quote! { ::cached::CachedShared::cache_get_shared(&*cache, #key_ref) }
+    } else {
+        quote! { cache.cache_get(#key_ref) }
+    };
+    let cache_get = if generic {
+        quote! {
+            #cache_lookup
+                .and_then(|cached| cached.downcast_ref::<#returned_value_ty>())
+        }
+    } else if expiring {
+        quote! {
+            #cache_lookup
+                .filter(|cached| !::cached::CanExpire::is_expired(*cached))
+                .map(|cached| &cached.value)
+        }
+    } else {
+        cache_lookup
+    };
 
     // make the cache identifier
     let cache_ident = match args.name {
         Some(ref name) => Ident::new(name, fn_ident.span()),
         None => Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span()),
     };
+    // the visibility of the cached static and of its accessor, defaulting to the function's
+    let cache_visibility = match &args.vis {
+        Some(vis) => parse_str::<Visibility>(vis)
+            .map_err(|e| attr_error(format!("unable to parse vis: {}", e)))?,
+        None => visibility.clone(),
+    };
+    if args.vis.is_some() && (external_store || args.in_impl) {
+        return Err(attr_error("vis is not supported with instance, store_fn or in_impl, which generate no cached static"));
+    }
+
+    // caches stored in the instances or provided by `store_fn` have no statistics, as they
+    // have no statics
+    let (cache_stats_ident, cache_stats, register_cache_stats) =
+        gen_cache_stats(&cache_ident, &fn_ident, &visibility);
+    let (record_hit, record_miss, record_size, record_eviction) = if external_store {
+        (quote! {}, quote! {}, quote! {}, quote! {})
+    } else {
+        (
+            quote! { #cache_stats_ident.record_hit(); },
+            quote! { #cache_stats_ident.record_miss(); },
+            quote! { #cache_stats_ident.record_size(::cached::CachedRead::cache_size(&*cache)); },
+            quote! { #cache_stats_ident.record_eviction(); },
+        )
+    };
+
+    // with `instrument`, the events of a call are emitted in a span of the function and key
+    let (bind_span, record_hit, record_miss, record_eviction) = if args.instrument {
+        (
+            quote! {
+                let cached_span = ::cached::tracing::debug_span!(
+                    "cached",
+                    function = concat!(module_path!(), "::", stringify!(#fn_ident)),
+                    key = ?key,
+                );
+            },
+            quote! {
+                #record_hit
+                ::cached::tracing::debug!(parent: &cached_span, "cache hit");
+            },
+            quote! {
+                #record_miss
+                ::cached::tracing::debug!(parent: &cached_span, "cache miss");
+            },
+            quote! {
+                #record_eviction
+                ::cached::tracing::debug!(parent: &cached_span, size = size_before, "cache eviction");
+            },
+        )
+    } else {
+        (quote! {}, record_hit, record_miss, record_eviction)
+    };
+
+    let (cache_key_ty, key_convert_block) = match &borrowed_key {
+        Some((owned_ty, key_name)) => (
+            quote! {#owned_ty},
+            quote! {::std::borrow::ToOwned::to_owned(#key_name)},
+        ),
+        None => make_cache_key_type(
+            &args.key,
+            &args.convert,
+            &args.ty,
+            key_tys.clone(),
+            &key_names,
+        )?,
+    };
+    // the keys are normalized by `key_map` before being looked up or inserted
+    let key_convert_block = match &args.key_map {
+        Some(key_map) => {
+            let key_map = parse_str::<Expr>(key_map)
+                .map_err(|e| attr_error(format!("unable to parse key_map expression: {}", e)))?;
+            quote! {(#key_map)(&#key_convert_block)}
+        }
+        None => key_convert_block,
+    };
+    // the values of string keys can be removed by key prefix
+    let string_key = !generic && !args.hash_key && is_string_key(&cache_key_ty);
+    // the keys of `hash_key` caches are the hashes of their keys
+    let (cache_key_ty, key_convert_block) = if args.hash_key {
+        (
+            quote! {u128},
+            quote! {::cached::cache_key::hash_key(&#key_convert_block)},
+        )
+    } else {
+        (cache_key_ty, key_convert_block)
+    };
+    let (cache_key_ty, key_convert_block) = if generic {
+        let type_count = type_params.len();
+        (
+            quote! {([::std::any::TypeId; #type_count], #cache_key_ty)},
+            quote! {([#(::std::any::TypeId::of::<#type_params>()),*], #key_convert_block)},
+        )
+    } else {
+        (cache_key_ty, key_convert_block)
+    };
+    let (cache_key_ty, key_convert_block) = if args.config_epoch {
+        (
+            quote! {(u64, #cache_key_ty)},
+            quote! {(::cached::registry::config_epoch(), #key_convert_block)},
+        )
+    } else {
+        (cache_key_ty, key_convert_block)
+    };
+    // the key bound by the generated functions
+    let key_lookup_block = match &borrowed_key {
+        Some((_, key_name)) => quote! {#key_name},
+        None => key_convert_block.clone(),
+    };
 
-    let (cache_key_ty, key_convert_block) =
-        make_cache_key_type(&args.key, &args.convert, &args.ty, input_tys, &input_names);
+    // the `BuildHasher` of the keys, created with its `Default` implementation
+    let hasher = args
+        .hasher
+        .as_ref()
+        .map(|hasher| {
+            parse_str::<Type>(hasher)
+                .map_err(|e| attr_error(format!("unable to parse hasher type: {}", e)))
+        })
+        .transpose()?;
+    if hasher.is_some() && args.ty.is_some() {
+        return Err(attr_error(
+            "hasher requires the cache type to be implied, set the hasher in create instead",
+        ));
+    }
+    let hasher_param = hasher.as_ref().map(|hasher| quote! {, #hasher});
+    if args.size == Some(0) {
+        return Err(attr_error("size must be greater than zero"));
+    }
 
     // make the cache type and create statement
     let (cache_ty, cache_create) = match (
@@ -104,141 +585,698 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
         &args.create,
         &args.time_refresh,
     ) {
-        (true, None, None, None, None, _) => {
-            let cache_ty = quote! {cached::UnboundCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::UnboundCache::new()};
+        (true, None, None, None, None, _) | (false, None, None, None, None, _) => {
+            let cache_ty =
+                quote! {cached::UnboundCache<#cache_key_ty, #cache_value_ty #hasher_param>};
+            let cache_create = match &hasher {
+                Some(hasher) => {
+                    quote! {cached::UnboundCache::with_hasher(<#hasher as Default>::default())}
+                }
+                None => quote! {cached::UnboundCache::new()},
+            };
             (cache_ty, cache_create)
         }
         (false, Some(size), None, None, None, _) => {
-            let cache_ty = quote! {cached::SizedCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::SizedCache::with_size(#size)};
+            let cache_ty =
+                quote! {cached::SizedCache<#cache_key_ty, #cache_value_ty #hasher_param>};
+            let cache_create = match &hasher {
+                Some(hasher) => quote! {
+                    cached::SizedCache::try_with_size_and_hasher(#size, <#hasher as Default>::default())
+                        .unwrap_or_else(|e| panic!("cache of `{}`: {}", #fn_name, e))
+                },
+                None => quote! {
+                    cached::SizedCache::try_with_size(#size)
+                        .unwrap_or_else(|e| panic!("cache of `{}`: {}", #fn_name, e))
+                },
+            };
             (cache_ty, cache_create)
         }
         (false, None, Some(time), None, None, time_refresh) => {
-            let cache_ty = quote! {cached::TimedCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create =
-                quote! {cached::TimedCache::with_lifespan_and_refresh(#time, #time_refresh)};
+            let cache_ty =
+                quote! {cached::TimedCache<#cache_key_ty, #cache_value_ty #hasher_param>};
+            let cache_create = match &hasher {
+                Some(hasher) => quote! {{
+                    let mut cache = cached::TimedCache::with_lifespan_and_hasher(#time, <#hasher as Default>::default());
+                    cache.set_refresh(#time_refresh);
+                    cache
+                }},
+                None => {
+                    quote! {cached::TimedCache::with_lifespan_and_refresh(#time, #time_refresh)}
+                }
+            };
             (cache_ty, cache_create)
         }
         (false, Some(size), Some(time), None, None, time_refresh) => {
-            let cache_ty = quote! {cached::TimedSizedCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::TimedSizedCache::with_size_and_lifespan_and_refresh(#size, #time, #time_refresh)};
-            (cache_ty, cache_create)
-        }
-        (false, None, None, None, None, _) => {
-            let cache_ty = quote! {cached::UnboundCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::UnboundCache::new()};
+            let cache_ty =
+                quote! {cached::TimedSizedCache<#cache_key_ty, #cache_value_ty #hasher_param>};
+            let cache_create = match &hasher {
+                Some(hasher) => quote! {{
+                    let mut cache = cached::TimedSizedCache::try_with_size_and_lifespan_and_hasher(#size, #time, <#hasher as Default>::default())
+                        .unwrap_or_else(|e| panic!("cache of `{}`: {}", #fn_name, e));
+                    cache.set_refresh(#time_refresh);
+                    cache
+                }},
+                None => quote! {
+                    cached::TimedSizedCache::try_with_size_and_lifespan_and_refresh(#size, #time, #time_refresh)
+                        .unwrap_or_else(|e| panic!("cache of `{}`: {}", #fn_name, e))
+                },
+            };
             (cache_ty, cache_create)
         }
         (false, None, None, Some(type_str), Some(create_str), _) => {
-            let ty = parse_str::<Type>(type_str).expect("unable to parse cache type");
+            let ty = parse_str::<Type>(type_str)
+                .map_err(|e| attr_error(format!("unable to parse cache type: {}", e)))?;
 
-            let cache_create =
-                parse_str::<Block>(create_str).expect("unable to parse cache create block");
+            let cache_create = parse_str::<Block>(create_str)
+                .map_err(|e| attr_error(format!("unable to parse cache create block: {}", e)))?;
 
             (quote! { #ty }, quote! { #cache_create })
         }
         (false, None, None, Some(_), None, _) => {
-            panic!("type requires create to also be set")
+            return Err(attr_error("type requires create to also be set"))
         }
         (false, None, None, None, Some(_), _) => {
-            panic!("create requires type to also be set")
+            return Err(attr_error("create requires type to also be set"))
+        }
+        _ => return Err(attr_error(
+            "cache types (unbound, size and/or time, or type and create) are mutually exclusive",
+        )),
+    };
+
+    // a budget of bytes instead of a number of values, weighed by their `MemSize` or `weigher`
+    let (cache_ty, cache_create) = match args.size_bytes {
+        Some(size_bytes) => {
+            if args.unbound
+                || args.size.is_some()
+                || args.time.is_some()
+                || args.ty.is_some()
+                || hasher.is_some()
+            {
+                return Err(attr_error(
+                    "size_bytes cannot be combined with unbound, size, time, ty or hasher",
+                ));
+            }
+            let cache_create = match &args.weigher {
+                Some(weigher) => {
+                    let weigher = parse_str::<Expr>(weigher).map_err(|e| {
+                        attr_error(format!("unable to parse weigher function: {}", e))
+                    })?;
+                    quote! { cached::WeightedCache::with_budget_and_weigher(#size_bytes, #weigher) }
+                }
+                None => quote! { cached::WeightedCache::with_budget(#size_bytes) },
+            };
+            (
+                quote! {cached::WeightedCache<#cache_key_ty, #cache_value_ty>},
+                cache_create,
+            )
+        }
+        None if args.weigher.is_some() => {
+            return Err(attr_error("weigher requires size_bytes to also be set"))
+        }
+        None => (cache_ty, cache_create),
+    };
+
+    // set `result` if `result_ref`, a reference to it, matches the `cache_if` predicate
+    // and not the `cache_unless` one
+    let cache_if = args
+        .cache_if
+        .as_ref()
+        .map(|cache_if| {
+            parse_str::<Expr>(cache_if)
+                .map_err(|e| attr_error(format!("unable to parse cache_if predicate: {}", e)))
+        })
+        .transpose()?;
+    let cache_unless = args
+        .cache_unless
+        .as_ref()
+        .map(|cache_unless| {
+            parse_str::<Expr>(cache_unless)
+                .map_err(|e| attr_error(format!("unable to parse cache_unless predicate: {}", e)))
+        })
+        .transpose()?;
+    let cached_result = if expiring {
+        quote! { ::cached::proc_macro::Expiring::new(result.clone(), expires_at) }
+    } else if generic {
+        quote! { ::std::boxed::Box::new(result.clone()) }
+    } else {
+        quote! { result.clone() }
+    };
+    // a value set without growing the cache nor replacing a value evicted another one
+    let cache_set = if !external_store || args.instrument {
+        quote! {
+            let size_before = ::cached::CachedRead::cache_size(&*cache);
+            if cache.cache_set(#key_owned, #cached_result).is_none()
+                && ::cached::CachedRead::cache_size(&*cache) <= size_before
+            {
+                #record_eviction
+            }
+        }
+    } else {
+        quote! { cache.cache_set(#key_owned, #cached_result); }
+    };
+    // the cache only holds the values of one config epoch: the first value of a new epoch
+    // clears those of the previous ones, and the values of a previous epoch are not cached
+    let cache_set = if args.config_epoch {
+        quote! {
+            let cached_epoch = ::cached::CachedRead::cache_iter(&*cache)
+                .next()
+                .map(|(key, _)| key.0);
+            match cached_epoch {
+                Some(cached_epoch) if cached_epoch > key.0 => {}
+                Some(cached_epoch) if cached_epoch < key.0 => {
+                    cache.cache_clear();
+                    #cache_set
+                }
+                _ => {
+                    #cache_set
+                }
+            }
+        }
+    } else {
+        cache_set
+    };
+    let set_cache_if = |result_ref: TokenStream2| {
+        // the predicates take a reference to the value, not to its `Arc`
+        let result_ref = match args.wrap {
+            Some(Wrap::Arc) => quote! { ::std::ops::Deref::deref(#result_ref) },
+            None => result_ref,
+        };
+        let condition = match (&cache_if, &cache_unless) {
+            (Some(cache_if), Some(cache_unless)) => {
+                quote! { (#cache_if)(#result_ref) && !(#cache_unless)(#result_ref) }
+            }
+            (Some(cache_if), None) => quote! { (#cache_if)(#result_ref) },
+            (None, Some(cache_unless)) => quote! { !(#cache_unless)(#result_ref) },
+            (None, None) => return cache_set.clone(),
+        };
+        quote! {
+            if #condition {
+                #cache_set
+            }
         }
-        _ => panic!(
-            "cache types (unbound, size and/or time, or type and create) are mutually exclusive"
-        ),
     };
 
     // make the set cache and return cache blocks
-    let (set_cache_block, return_cache_block) = match (&args.result, &args.option) {
+    let (set_cache_block, return_cache_block) = match (&cached_result_attr, &args.option) {
         (false, false) => {
-            let set_cache_block = quote! { cache.cache_set(key, result.clone()); };
+            let set_cache_block = set_cache_if(quote! { &result });
             let return_cache_block = if args.with_cached_flag {
                 quote! { let mut r = result.to_owned(); r.was_cached = true; return r }
+            } else if store.is_some() {
+                quote! { return from_store(result) }
             } else {
                 quote! { return result.to_owned() }
             };
             (set_cache_block, return_cache_block)
         }
         (true, false) => {
+            let set_cache = set_cache_if(quote! { result });
             let set_cache_block = quote! {
                 if let Ok(result) = &result {
-                    cache.cache_set(key, result.clone());
+                    #set_cache
                 }
             };
             let return_cache_block = if args.with_cached_flag {
                 quote! { let mut r = result.to_owned(); r.was_cached = true; return Ok(r) }
+            } else if store.is_some() {
+                quote! { return Ok(from_store(result)) }
             } else {
                 quote! { return Ok(result.to_owned()) }
             };
             (set_cache_block, return_cache_block)
         }
         (false, true) => {
+            let set_cache = set_cache_if(quote! { result });
             let set_cache_block = quote! {
                 if let Some(result) = &result {
-                    cache.cache_set(key, result.clone());
+                    #set_cache
                 }
             };
             let return_cache_block = if args.with_cached_flag {
                 quote! { let mut r = result.to_owned(); r.was_cached = true; return Some(r) }
+            } else if store.is_some() {
+                quote! { return Some(from_store(result)) }
             } else {
                 quote! { return Some(result.clone()) }
             };
             (set_cache_block, return_cache_block)
         }
-        _ => panic!("the result and option attributes are mutually exclusive"),
+        _ => {
+            return Err(attr_error(
+                "the result and option attributes are mutually exclusive",
+            ))
+        }
+    };
+    let return_cache_block = quote! {
+        #record_hit
+        #return_cache_block
     };
 
-    if args.result_fallback && args.sync_writes {
-        panic!("the result_fallback and sync_writes attributes are mutually exclusive");
+    if args.result_fallback && args.sync_writes != SyncWrites::Off {
+        return Err(attr_error(
+            "the result_fallback and sync_writes attributes are mutually exclusive",
+        ));
+    }
+
+    let stale_on_timeout = args.timeout_fallback == TimeoutFallback::Stale;
+    match args.timeout_ms {
+        Some(_) => {
+            if !is_async || !args.result {
+                return Err(attr_error(
+                    "timeout_ms requires an async function and the result attribute to be set",
+                ));
+            }
+            if stale_on_timeout
+                && (generic
+                    || args.cache_policy
+                    || args.result_fallback
+                    || args.sync_writes != SyncWrites::Off)
+            {
+                return Err(attr_error("timeout_fallback = \"stale\" is not supported with generic functions, cache_policy, result_fallback or sync_writes"));
+            }
+        }
+        None if stale_on_timeout => {
+            return Err(attr_error(
+                "timeout_fallback requires timeout_ms to also be set",
+            ))
+        }
+        None => {}
+    }
+
+    if (external_store || args.associated)
+        && (args.warm_size.is_some()
+            || args.quarantine_after.is_some()
+            || args.invalidation
+            || matches!(
+                args.sync_writes,
+                SyncWrites::ByKey | SyncWrites::SingleFlight
+            ))
+    {
+        return Err(attr_error("warm_size, quarantine_after, invalidation and sync_writes = \"by_key\" or \"single_flight\" generate statics, not supported with instance, store_fn or associated"));
+    }
+    if args.sync_writes == SyncWrites::SingleFlight && (!is_async || generic) {
+        return Err(attr_error("sync_writes = \"single_flight\" is only supported with async functions without type parameters"));
+    }
+
+    if args.rw_lock {
+        if external_store || args.thread_local {
+            return Err(attr_error(
+                "rw_lock is not supported with instance, store_fn or thread_local",
+            ));
+        }
+        if args.ty.is_none()
+            && (args.size.is_some() || args.size_bytes.is_some() || args.time_refresh)
+        {
+            return Err(attr_error("rw_lock requires a cache implementing cached::CachedShared, the size, size_bytes and time_refresh ones do not"));
+        }
+    }
+
+    // each thread computes the values it misses, with its own cache
+    if args.thread_local
+        && (is_async
+            || args.warm_size.is_some()
+            || args.invalidation
+            || args.sync_writes != SyncWrites::Off
+            || args.prime_concurrency.is_some())
+    {
+        return Err(attr_error("thread_local is not supported with async functions, warm_size, invalidation, sync_writes or prime_concurrency"));
+    }
+
+    if args.quarantine_after.is_some() && !args.result {
+        return Err(attr_error(
+            "quarantine_after requires the result attribute to be set",
+        ));
     }
 
+    let (warmth, register_warmth, record_warmth) =
+        gen_warmth(&cache_ident, &fn_ident, &visibility, args.warm_size);
+
+    let (invalidation, register_invalidation) = if args.invalidation {
+        let lock = match (is_async, args.rw_lock) {
+            (true, false) => quote! {
+                #cache_ident.try_lock().map_err(|_| ::cached::registry::InvalidationError::Locked)?
+            },
+            (true, true) => quote! {
+                #cache_ident.try_write().map_err(|_| ::cached::registry::InvalidationError::Locked)?
+            },
+            (false, false) => quote! { #cache_ident.lock().unwrap() },
+            (false, true) => quote! { #cache_ident.write().unwrap() },
+        };
+        gen_invalidation(
+            &cache_ident,
+            &fn_ident,
+            &visibility,
+            &key_names,
+            &key_tys,
+            &key_convert_block,
+            lock,
+        )
+    } else {
+        (quote! {}, quote! {})
+    };
+
+    let (bind_from_store, returned_result) = match (&store, args.result || args.option) {
+        (Some((_, stored_ty, from_store)), wrapped) => {
+            let bind_from_store = quote! {
+                let from_store = ::cached::proc_macro::from_store::<#stored_ty, _>(#from_store);
+            };
+            let returned_result = if wrapped {
+                quote! { result.map(|result| from_store(&result)) }
+            } else {
+                quote! { from_store(&result) }
+            };
+            (bind_from_store, returned_result)
+        }
+        (None, _) => (quote! {}, quote! { result }),
+    };
+
     let set_cache_and_return = quote! {
         #set_cache_block
-        result
+        #record_warmth
+        #record_size
+        #returned_result
     };
 
     let no_cache_fn_ident = Ident::new(&format!("{}_no_cache", &fn_ident), fn_ident.span());
+    let (generics, _, where_clause) = signature.generics.split_for_impl();
+    let turbofish = if generic {
+        quote! { ::<#(#type_params),*> }
+    } else {
+        quote! {}
+    };
+
+    let (lock_stats_ident, lock_stats, register_lock_stats) =
+        gen_lock_stats(&cache_ident, &fn_ident, &visibility);
+
+    // the `{fn}_no_cache` function of an associated function is associated too
+    let no_cache_fn_path = match (args.associated, inputs.first()) {
+        (true, Some(FnArg::Receiver(_))) => quote! { self.#no_cache_fn_ident },
+        (true, _) => quote! { Self::#no_cache_fn_ident },
+        (false, _) => quote! { #no_cache_fn_ident },
+    };
+
+    // the lock of the static cache, and the name of its method locking it for writes
+    let (cache_mutex_ty, write_lock_fn) = match (is_async, args.rw_lock) {
+        (true, false) => (quote! { ::cached::async_sync::Mutex }, quote! { lock }),
+        (true, true) => (quote! { ::cached::async_sync::RwLock }, quote! { write }),
+        (false, false) => (quote! { std::sync::Mutex }, quote! { lock }),
+        (false, true) => (quote! { std::sync::RwLock }, quote! { write }),
+    };
 
     let lock;
     let function_no_cache;
     let function_call;
     let ty;
-    if asyncness.is_some() {
+    if let Some(field) = &args.instance {
+        // the cache is a field of the instance, created with the generated `{fn}_new_cache`
+        let field = Ident::new(field, fn_ident.span());
+        let new_cache_fn_ident = Ident::new(&format!("{}_new_cache", fn_ident), fn_ident.span());
+        let mutex_ty = if is_async {
+            lock = quote! { let mut cache = self.#field.lock().await; };
+            function_no_cache = quote! {
+                #asyncness fn #no_cache_fn_ident #generics (#inputs) #original_output #where_clause #body
+            };
+            function_call = quote! {
+                self.#no_cache_fn_ident #turbofish (#(#input_names),*).await
+            };
+            quote! { ::cached::async_sync::Mutex }
+        } else {
+            lock = quote! { let mut cache = self.#field.lock().unwrap(); };
+            function_no_cache = quote! {
+                fn #no_cache_fn_ident #generics (#inputs) #output #where_clause #body
+            };
+            function_call = quote! {
+                self.#no_cache_fn_ident #turbofish (#(#input_names),*)
+            };
+            quote! { std::sync::Mutex }
+        };
+        ty = quote! {
+            #[allow(dead_code)]
+            #visibility fn #new_cache_fn_ident() -> #mutex_ty<#cache_ty> {
+                #mutex_ty::new(#cache_create)
+            }
+        };
+    } else if let Some(store_fn) = &args.store_fn {
+        // the cache is provided by `store_fn`, each time it is locked, and can be created
+        // with the generated `{fn}_new_cache`
+        let store_fn = parse_str::<Expr>(store_fn)
+            .map_err(|e| attr_error(format!("unable to parse store_fn function: {}", e)))?;
+        let new_cache_fn_ident = Ident::new(&format!("{}_new_cache", fn_ident), fn_ident.span());
+        let mutex_ty = if is_async {
+            lock = quote! {
+                let cache_store = (#store_fn)();
+                let mut cache = cache_store.lock().await;
+            };
+            function_no_cache = quote! {
+                #asyncness fn #no_cache_fn_ident #generics (#inputs) #original_output #where_clause #body
+            };
+            function_call = quote! {
+                #no_cache_fn_ident #turbofish (#(#input_names),*).await
+            };
+            quote! { ::cached::async_sync::Mutex }
+        } else {
+            lock = quote! {
+                let cache_store = (#store_fn)();
+                let mut cache = cache_store.lock().unwrap();
+            };
+            function_no_cache = quote! {
+                fn #no_cache_fn_ident #generics (#inputs) #output #where_clause #body
+            };
+            function_call = quote! {
+                #no_cache_fn_ident #turbofish (#(#input_names),*)
+            };
+            quote! { std::sync::Mutex }
+        };
+        ty = quote! {
+            #[allow(dead_code)]
+            #visibility fn #new_cache_fn_ident() -> #mutex_ty<#cache_ty> {
+                #mutex_ty::new(#cache_create)
+            }
+        };
+    } else if args.thread_local {
+        // the cache of the thread is shared with the generated functions by an `Rc`, which its
+        // `RefCell` is borrowed from
+        let thread_local_doc = format!(
+            "Thread-local cache of the [`{}`] function, each thread caching its own values.",
+            fn_ident
+        );
         lock = quote! {
-            let mut cache = #cache_ident.lock().await;
+            let cache_cell = #cache_ident.with(::std::rc::Rc::clone);
+            let mut cache = cache_cell.borrow_mut();
         };
 
         function_no_cache = quote! {
-            async fn #no_cache_fn_ident(#inputs) #output #body
+            fn #no_cache_fn_ident #generics (#inputs) #output #where_clause #body
         };
 
         function_call = quote! {
-            let result = #no_cache_fn_ident(#(#input_names),*).await;
+            #no_cache_fn_path #turbofish (#(#input_names),*)
         };
 
         ty = quote! {
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<::cached::async_sync::Mutex<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| ::cached::async_sync::Mutex::new(#cache_create));
+            thread_local! {
+                #[doc = #thread_local_doc]
+                #cache_visibility static #cache_ident: ::std::rc::Rc<::std::cell::RefCell<#cache_ty>> = {
+                    // the statistics of all the threads are registered once
+                    static REGISTER: ::std::sync::Once = ::std::sync::Once::new();
+                    REGISTER.call_once(|| {
+                        #register_cache_stats
+                    });
+                    ::std::rc::Rc::new(::std::cell::RefCell::new(#cache_create))
+                };
+            }
         };
-    } else {
-        lock = quote! {
-            let mut cache = #cache_ident.lock().unwrap();
+    } else if is_async {
+        lock = gen_timed_lock(
+            quote! { mut cache },
+            quote! { #cache_ident.#write_lock_fn().await },
+            &lock_stats_ident,
+        );
+
+        function_no_cache = quote! {
+            #asyncness fn #no_cache_fn_ident #generics (#inputs) #original_output #where_clause #body
+        };
+
+        function_call = quote! {
+            #no_cache_fn_path #turbofish (#(#input_names),*).await
+        };
+
+        ty = quote! {
+            #cache_visibility static #cache_ident: ::cached::once_cell::sync::Lazy<#cache_mutex_ty<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| {
+                #register_lock_stats
+                #register_cache_stats
+                #register_warmth
+                #register_invalidation
+                #cache_mutex_ty::new(#cache_create)
+            });
         };
+    } else {
+        lock = gen_timed_lock(
+            quote! { mut cache },
+            quote! { #cache_ident.#write_lock_fn().unwrap() },
+            &lock_stats_ident,
+        );
 
         function_no_cache = quote! {
-            fn #no_cache_fn_ident(#inputs) #output #body
+            fn #no_cache_fn_ident #generics (#inputs) #output #where_clause #body
         };
 
         function_call = quote! {
-            let result = #no_cache_fn_ident(#(#input_names),*);
+            #no_cache_fn_path #turbofish (#(#input_names),*)
         };
 
         ty = quote! {
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<std::sync::Mutex<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| std::sync::Mutex::new(#cache_create));
+            #cache_visibility static #cache_ident: ::cached::once_cell::sync::Lazy<#cache_mutex_ty<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| {
+                #register_lock_stats
+                #register_cache_stats
+                #register_warmth
+                #register_invalidation
+                #cache_mutex_ty::new(#cache_create)
+            });
         };
     }
 
+    // the lookups of the caches behind a `RwLock` only take its read lock
+    let read_lock = match (args.rw_lock, is_async) {
+        (true, true) => gen_timed_lock(
+            quote! { cache },
+            quote! { #cache_ident.read().await },
+            &lock_stats_ident,
+        ),
+        (true, false) => gen_timed_lock(
+            quote! { cache },
+            quote! { #cache_ident.read().unwrap() },
+            &lock_stats_ident,
+        ),
+        (false, _) => lock.clone(),
+    };
+
+    // the items of trait impls are the items of the trait, so the body of their methods
+    // is run in place rather than in a `{fn}_no_cache` function
+    let function_call = match (args.in_impl, &future_output, &asyncness) {
+        (false, _, _) => function_call,
+        (true, Some(_), _) => quote! { future.await },
+        (true, None, Some(_)) => quote! { async move #body.await },
+        (true, None, None) => quote! { (move || #output #body)() },
+    };
+
+    let function_call = if args.instrument {
+        quote! {{
+            let load_started = ::cached::instant::Instant::now();
+            let result = #function_call;
+            ::cached::tracing::debug!(parent: &cached_span, elapsed = ?load_started.elapsed(), "cache load");
+            result
+        }}
+    } else {
+        function_call
+    };
+    let function_call = if external_store {
+        function_call
+    } else {
+        quote! {{
+            let load_timer = ::cached::registry::LoadTimer::start();
+            let result = #function_call;
+            load_timer.finished(&#cache_stats_ident);
+            result
+        }}
+    };
+
+    let (quarantine, function_call) = gen_quarantine(
+        &cache_ident,
+        &visibility,
+        &output,
+        &cache_key_ty,
+        &key_ref,
+        args.quarantine_after,
+        args.quarantine_secs,
+        function_call,
+    )?;
+    let function_call = if args.cache_policy {
+        quote! {
+            #function_call
+            let (result, expires_at) = ::cached::proc_macro::SplitCachePolicy::split(result);
+        }
+    } else if let Some(err_time) = args.err_time {
+        quote! {
+            #function_call
+            let expires_at = match &result {
+                Ok(_) => None,
+                Err(_) => ::cached::instant::Instant::now()
+                    .checked_add(::std::time::Duration::from_secs(#err_time)),
+            };
+        }
+    } else {
+        function_call
+    };
+    let function_call = match (&args.wrap, args.result || args.option) {
+        (Some(Wrap::Arc), false) => quote! {
+            #function_call
+            let result = ::std::sync::Arc::new(result);
+        },
+        (Some(Wrap::Arc), true) => quote! {
+            #function_call
+            let result = result.map(::std::sync::Arc::new);
+        },
+        (None, _) => function_call,
+    };
+    let function_call = match (&store, args.result || args.option) {
+        (Some((store_as, _, _)), false) => quote! {
+            #function_call
+            let result = ::cached::proc_macro::store_as(result, #store_as);
+        },
+        (Some((store_as, _, _)), true) => quote! {
+            #function_call
+            let result = result.map(#store_as);
+        },
+        (None, _) => function_call,
+    };
+
+    // the computation is cancelled on timeout, returning a `TimeoutError` converted into the
+    // error of the function unless the expired value is cached and returned again instead
+    let function_call = match args.timeout_ms {
+        Some(timeout_ms) => {
+            let timeout_error = quote! {
+                return Err(::cached::proc_macro::TimeoutError(
+                    ::std::time::Duration::from_millis(#timeout_ms),
+                )
+                .into())
+            };
+            let on_timeout = if stale_on_timeout {
+                quote! {
+                    match stale {
+                        Some(stale) => Ok(stale),
+                        None => #timeout_error,
+                    }
+                }
+            } else {
+                timeout_error
+            };
+            quote! {
+                let result = match ::cached::async_sync::timeout(
+                    ::std::time::Duration::from_millis(#timeout_ms),
+                    async {
+                        #function_call
+                        result
+                    },
+                )
+                .await
+                {
+                    Ok(result) => result,
+                    Err(_) => #on_timeout,
+                };
+            }
+        }
+        None => function_call,
+    };
+
+    // priming recomputes the value, returning an error rather than the expired one on timeout
+    let prime_stale = if stale_on_timeout {
+        quote! { let stale: Option<#cache_value_ty> = None; }
+    } else {
+        quote! {}
+    };
     let prime_do_set_return_block = quote! {
+        #prime_stale
         // try to get a lock first
         #lock
         // run the function and cache the result
@@ -246,12 +1284,95 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
         #set_cache_and_return
     };
 
-    let do_set_return_block = if args.sync_writes {
+    let key_locks_ident = Ident::new(&format!("{}_KEY_LOCKS", cache_ident), cache_ident.span());
+    let flights_ident = Ident::new(&format!("{}_FLIGHTS", cache_ident), cache_ident.span());
+    let key_locks = if args.sync_writes == SyncWrites::ByKey {
+        let key_locks_doc = format!(
+            "Locks of the keys being computed for the [`{}`] cached static.",
+            cache_ident
+        );
+        let key_locks_ty = if is_async {
+            quote! { ::cached::key_locks::AsyncKeyLocks }
+        } else {
+            quote! { ::cached::key_locks::KeyLocks }
+        };
+        quote! {
+            #[doc = #key_locks_doc]
+            #visibility static #key_locks_ident: ::cached::once_cell::sync::Lazy<#key_locks_ty> =
+                ::cached::once_cell::sync::Lazy::new(#key_locks_ty::new);
+        }
+    } else if args.sync_writes == SyncWrites::SingleFlight {
+        let flights_doc = format!(
+            "Computations in flight of the keys of the [`{}`] cached static.",
+            cache_ident
+        );
+        quote! {
+            #[doc = #flights_doc]
+            #visibility static #flights_ident: ::cached::once_cell::sync::Lazy<
+                ::cached::key_locks::SingleFlight<#cache_key_ty, #output_ty>,
+            > = ::cached::once_cell::sync::Lazy::new(::cached::key_locks::SingleFlight::new);
+        }
+    } else {
+        quote! {}
+    };
+
+    let do_set_return_block = if args.sync_writes == SyncWrites::ByKey {
+        let lock_key = if is_async {
+            quote! { let _key_lock = #key_locks_ident.lock(#key_ref).await; }
+        } else {
+            quote! { let _key_lock = #key_locks_ident.lock(#key_ref); }
+        };
+        quote! {
+            {
+                #read_lock
+                if let Some(result) = #cache_get {
+                    #return_cache_block
+                }
+            }
+            // wait for the caller computing the key, if any, and check whether it cached it
+            #lock_key
+            {
+                #read_lock
+                if let Some(result) = #cache_get {
+                    #return_cache_block
+                }
+            }
+            #record_miss
+            #function_call
+            #lock
+            #set_cache_and_return
+        }
+    } else if args.sync_writes == SyncWrites::SingleFlight {
+        // the first caller missing the key computes it, the others await its result; it
+        // looks the key up again in case a flight completed since its own lookup
+        quote! {
+            {
+                #read_lock
+                if let Some(result) = #cache_get {
+                    #return_cache_block
+                }
+            }
+            let flight_key = ::std::borrow::ToOwned::to_owned(#key_ref);
+            #flights_ident.run(flight_key, async move {
+                {
+                    #read_lock
+                    if let Some(result) = #cache_get {
+                        #return_cache_block
+                    }
+                }
+                #record_miss
+                #function_call
+                #lock
+                #set_cache_and_return
+            }).await
+        }
+    } else if args.sync_writes == SyncWrites::All {
         quote! {
             #lock
-            if let Some(result) = cache.cache_get(&key) {
+            if let Some(result) = #cache_get {
                 #return_cache_block
             }
+            #record_miss
             #function_call
             #set_cache_and_return
         }
@@ -259,12 +1380,13 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
         quote! {
             let old_val = {
                 #lock
-                let (result, has_expired) = cache.cache_get_expired(&key);
+                let (result, has_expired) = cache.cache_get_expired(#key_ref);
                 if let (Some(result), false) = (&result, has_expired) {
                     #return_cache_block
                 }
                 result
             };
+            #record_miss
             #function_call
             #lock
             let result = match (result.is_err(), old_val) {
@@ -275,63 +1397,448 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
             };
             #set_cache_and_return
         }
+    } else if stale_on_timeout {
+        quote! {
+            let stale = {
+                #lock
+                let (result, has_expired) = cache.cache_get_expired(#key_ref);
+                if let (Some(result), false) = (&result, has_expired) {
+                    #return_cache_block
+                }
+                result
+            };
+            #record_miss
+            #function_call
+            #lock
+            #set_cache_and_return
+        }
     } else {
         quote! {
             {
-                #lock
-                if let Some(result) = cache.cache_get(&key) {
+                #read_lock
+                if let Some(result) = #cache_get {
                     #return_cache_block
                 }
             }
+            #record_miss
             #function_call
             #lock
             #set_cache_and_return
         }
     };
 
-    let signature_no_muts = get_mut_signature(signature);
+    let mut signature = signature;
+    if args.cache_policy || args.wrap.is_some() {
+        signature.output = cached_output;
+    }
+    let signature_no_muts = get_mut_signature(signature.clone());
 
     // create a signature for the cache-priming function
     let prime_fn_ident = Ident::new(&format!("{}_prime_cache", &fn_ident), fn_ident.span());
     let mut prime_sig = signature_no_muts.clone();
     prime_sig.ident = prime_fn_ident;
 
+    // create the signatures of the cache-management functions, taking the same arguments
+    let mut remove_sig = signature_no_muts.clone();
+    remove_sig.ident = Ident::new(&format!("{}_cache_remove", &fn_ident), fn_ident.span());
+    remove_sig.output = parse_quote! { -> Option<#returned_value_ty> };
+    let cache_remove = if generic {
+        quote! {
+            cache
+                .cache_remove(#key_ref)
+                .and_then(|cached| cached.downcast::<#returned_value_ty>().ok())
+                .map(|cached| *cached)
+        }
+    } else if expiring {
+        quote! {
+            cache
+                .cache_remove(#key_ref)
+                .filter(|cached| !::cached::CanExpire::is_expired(cached))
+                .map(|cached| cached.value)
+        }
+    } else {
+        quote! { cache.cache_remove(#key_ref) }
+    };
+    let mut clear_sig = signature_no_muts.clone();
+    clear_sig.ident = Ident::new(&format!("{}_cache_clear", &fn_ident), fn_ident.span());
+    clear_sig.inputs = inputs
+        .iter()
+        .filter(|input| matches!(input, FnArg::Receiver(_)))
+        .cloned()
+        .collect();
+    clear_sig.output = ReturnType::Default;
+    // the values of all the type parameters are cleared
+    clear_sig.generics = Default::default();
+    let remove_prefix_sig = string_key.then(|| {
+        let mut remove_prefix_sig = clear_sig.clone();
+        remove_prefix_sig.ident = Ident::new(
+            &format!("{}_cache_remove_prefix", &fn_ident),
+            fn_ident.span(),
+        );
+        remove_prefix_sig.inputs.push(parse_quote! { prefix: &str });
+        remove_prefix_sig.output = parse_quote! { -> usize };
+        remove_prefix_sig
+    });
+    // the cache of functions returning a future is locked asynchronously
+    if future_output.is_some() {
+        prime_sig.asyncness = Some(Default::default());
+        prime_sig.output = output.clone();
+        remove_sig.asyncness = Some(Default::default());
+        clear_sig.asyncness = Some(Default::default());
+    }
+    let remove_prefix_sig = remove_prefix_sig.map(|mut remove_prefix_sig| {
+        remove_prefix_sig.asyncness = clear_sig.asyncness;
+        remove_prefix_sig
+    });
+
     // make cached static, cached function and prime cached function doc comments
     let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
     let no_cache_fn_indent_doc = format!("Origin of the cached function [`{}`].", fn_ident);
     let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
-    let cache_fn_doc_extra = format!(
-        "This is a cached function that uses the [`{}`] cached static.",
-        cache_ident
+    let remove_fn_doc = format!(
+        "Removes the value cached by [`{}`] for these arguments, returning it.",
+        fn_ident
     );
+    let clear_fn_doc = format!("Clears the values cached by [`{}`].", fn_ident);
+
+    // statics cannot be items of an `impl` block
+    let (cache_ident_doc, lock_stats, cache_stats) = match (&args.instance, &args.store_fn) {
+        (Some(field), _) => (
+            format!(
+                "Creates the cache of the [`Self::{}`] method, to store in the `{}` field.",
+                fn_ident, field
+            ),
+            quote! {},
+            quote! {},
+        ),
+        (None, Some(store_fn)) => (
+            format!(
+                "Creates a cache of the cached function [`{}`], for `{}` to provide.",
+                fn_ident, store_fn
+            ),
+            quote! {},
+            quote! {},
+        ),
+        // a thread-local cache is not locked
+        (None, None) if args.thread_local => (cache_ident_doc, quote! {}, cache_stats),
+        (None, None) => (cache_ident_doc, lock_stats, cache_stats),
+    };
+    // the doc of a thread-local cache is part of its `thread_local!` declaration
+    let cache_ident_doc = if args.thread_local {
+        quote! {}
+    } else {
+        quote! { #[doc = #cache_ident_doc] }
+    };
+    // the statics of associated functions are declared in the body of a hidden associated
+    // function returning them, bound to their names by the generated functions
+    let (statics, bind_statics) = if args.associated {
+        let statics_fn_ident =
+            Ident::new(&format!("__{}_cache_statics", &fn_ident), fn_ident.span());
+        let statics = quote! {
+            #[doc(hidden)]
+            #[allow(dead_code)]
+            #visibility fn #statics_fn_ident() -> (
+                &'static ::cached::once_cell::sync::Lazy<#cache_mutex_ty<#cache_ty>>,
+                &'static ::cached::registry::LockStats,
+                &'static ::cached::registry::CacheStats,
+            ) {
+                #cache_ident_doc
+                #ty
+                #lock_stats
+                #cache_stats
+                (&#cache_ident, &#lock_stats_ident, &#cache_stats_ident)
+            }
+        };
+        let bind_statics = quote! {
+            #[allow(non_snake_case, unused_variables)]
+            let (#cache_ident, #lock_stats_ident, #cache_stats_ident) = Self::#statics_fn_ident();
+        };
+        (statics, bind_statics)
+    } else {
+        let statics = quote! {
+            #cache_ident_doc
+            #ty
+            #lock_stats
+            #cache_stats
+        };
+        (statics, quote! {})
+    };
+    let remove_prefix_fn = remove_prefix_sig.map(|remove_prefix_sig| {
+        let remove_prefix_fn_doc = format!(
+            "Removes the values cached by [`{}`] whose keys start with `prefix`, returning their number.",
+            fn_ident
+        );
+        // the keys are paired with their config epoch
+        let key = if args.config_epoch {
+            quote! { key.1 }
+        } else {
+            quote! { key }
+        };
+        quote! {
+            #[doc = #remove_prefix_fn_doc]
+            #[allow(dead_code)]
+            #visibility #remove_prefix_sig {
+                use cached::CachedWrite;
+                #bind_statics
+                #lock
+                let removed = cache
+                    .cache_remove_where(|key: &#cache_key_ty| {
+                        ::std::convert::AsRef::<str>::as_ref(&#key).starts_with(prefix)
+                    })
+                    .unwrap_or_else(|| panic!("the cache of `{}` cannot remove values by key", #fn_name));
+                #record_size
+                removed
+            }
+        }
+    });
+    // `{fn}_prime_many` calls the function for each of the arguments, `prime_concurrency`
+    // calls running at a time, the values already cached being looked up as usual
+    let prime_many_fn = args.prime_concurrency.map(|concurrency| {
+        if args.instance.is_some() || args.in_impl || matches!(inputs.first(), Some(FnArg::Receiver(_))) {
+            return Err(attr_error("prime_concurrency is not supported with methods"));
+        }
+        if generic {
+            return Err(attr_error("prime_concurrency is not supported with generic functions"));
+        }
+        if concurrency == 0 {
+            return Err(attr_error("prime_concurrency must be at least 1"));
+        }
+        let prime_many_ident =
+            Ident::new(&format!("{}_prime_many", &fn_ident), fn_ident.span());
+        let prime_many_doc = format!(
+            "Calls the cached function [`{}`] for each of the arguments, caching the values not already cached, {} at a time.",
+            fn_ident, concurrency
+        );
+        let fn_path = if args.associated {
+            quote! { Self::#fn_ident }
+        } else {
+            quote! { #fn_ident }
+        };
+        let (item_ty, item_pat) = match (input_tys.as_slice(), input_names.as_slice()) {
+            ([ty], [name]) => (quote! { #ty }, quote! { #name }),
+            _ => (
+                quote! { (#(#input_tys),*) },
+                quote! { (#(#input_names),*) },
+            ),
+        };
+        let generics = &signature.generics;
+        let where_clause = &signature.generics.where_clause;
+        Ok(if is_async {
+            quote! {
+                #[doc = #prime_many_doc]
+                #[allow(dead_code)]
+                #visibility async fn #prime_many_ident #generics (
+                    prime_args: impl ::std::iter::IntoIterator<Item = #item_ty>,
+                ) #where_clause {
+                    use ::cached::futures::StreamExt;
+                    ::cached::futures::stream::iter(prime_args)
+                        .for_each_concurrent(#concurrency, |#item_pat| async move {
+                            let _ = #fn_path(#(#input_names),*).await;
+                        })
+                        .await;
+                }
+            }
+        } else {
+            quote! {
+                #[doc = #prime_many_doc]
+                #[allow(dead_code)]
+                #visibility fn #prime_many_ident #generics (
+                    prime_args: impl ::std::iter::IntoIterator<Item = #item_ty>,
+                ) #where_clause {
+                    let prime_args: ::std::vec::Vec<_> = prime_args.into_iter().collect();
+                    let threads = ::std::cmp::min(#concurrency, prime_args.len());
+                    let prime_args = ::std::sync::Mutex::new(prime_args.into_iter());
+                    ::std::thread::scope(|scope| {
+                        for _ in 0..threads {
+                            scope.spawn(|| loop {
+                                let next = prime_args.lock().unwrap().next();
+                                match next {
+                                    Some(#item_pat) => {
+                                        let _ = #fn_path(#(#input_names),*);
+                                    }
+                                    None => break,
+                                }
+                            });
+                        }
+                    });
+                }
+            }
+        })
+    })
+    .transpose()?;
+    let cache_fn_doc_extra = match &args.instance {
+        Some(field) => format!(
+            "This is a cached method that uses the `{}` field of its instance as cache.",
+            field
+        ),
+        None if args.store_fn.is_some() => format!(
+            "This is a cached function that uses the cache provided by `{}`.",
+            args.store_fn.as_deref().unwrap_or_default()
+        ),
+        None if args.in_impl => format!(
+            "This is a cached function that uses the `{}` cached static declared in its body.",
+            cache_ident
+        ),
+        None if args.associated => format!(
+            "This is a cached associated function that uses the `{}` cached static declared in a hidden associated function.",
+            cache_ident
+        ),
+        None => format!(
+            "This is a cached function that uses the [`{}`] cached static.",
+            cache_ident
+        ),
+    };
     fill_in_attributes(&mut attributes, cache_fn_doc_extra);
+    let stats_fn = if external_store {
+        quote! {}
+    } else {
+        let stats_fn_ident = Ident::new(&format!("{}_cache_stats", &fn_ident), fn_ident.span());
+        let stats_fn_doc = format!(
+            "Returns the hit, miss and size statistics of the cached function [`{}`], without locking its cache.",
+            fn_ident
+        );
+        quote! {
+            #[doc = #stats_fn_doc]
+            #[allow(dead_code)]
+            #visibility fn #stats_fn_ident() -> ::cached::registry::CacheStatsSnapshot {
+                #bind_statics
+                #cache_stats_ident.snapshot()
+            }
+        }
+    };
+    // the accessor of a named static is named after it, the default name being the function's
+    let cache_fn = match (external_store || args.thread_local, &args.name) {
+        (false, Some(name)) => {
+            let cache_fn_ident = Ident::new(&name.to_lowercase(), fn_ident.span());
+            if cache_fn_ident == fn_ident {
+                return Err(attr_error("the name of the cache cannot be the name of the function uppercase, which is the default"));
+            }
+            let cache_fn_doc = format!(
+                "Returns the [`{}`] cached static of the cached function [`{}`].",
+                cache_ident, fn_ident
+            );
+            let cache_ref = if args.associated {
+                quote! { #cache_ident }
+            } else {
+                quote! { &#cache_ident }
+            };
+            quote! {
+                #[doc = #cache_fn_doc]
+                #[allow(dead_code)]
+                #cache_visibility fn #cache_fn_ident() -> &'static #cache_mutex_ty<#cache_ty> {
+                    #bind_statics
+                    #cache_ref
+                }
+            }
+        }
+        _ => quote! {},
+    };
+
+    // functions returning a future return one looking up the cache, the future of the body
+    // of methods of trait impls being created first as it may borrow their arguments
+    let do_set_return_block = match (&future_output, boxed_future) {
+        (None, _) => do_set_return_block,
+        (Some(_), boxed) => {
+            let future = if args.in_impl {
+                quote! { let future = #body; }
+            } else {
+                quote! {}
+            };
+            let lookup = quote! { async move { #do_set_return_block } };
+            if boxed {
+                quote! { #future Box::pin(#lookup) }
+            } else {
+                quote! { #future #lookup }
+            }
+        }
+    };
 
     // put it all together
-    let expanded = quote! {
-        // Cached static
-        #[doc = #cache_ident_doc]
-        #ty
-        // No cache function (origin of the cached function)
-        #[doc = #no_cache_fn_indent_doc]
-        #visibility #function_no_cache
-        // Cached function
-        #(#attributes)*
-        #visibility #signature_no_muts {
-            use cached::Cached;
-            use cached::CloneCached;
-            let key = #key_convert_block;
-            #do_set_return_block
-        }
-        // Prime cached function
-        #[doc = #prime_fn_indent_doc]
-        #[allow(dead_code)]
-        #(#attributes)*
-        #visibility #prime_sig {
-            use cached::Cached;
-            let key = #key_convert_block;
-            #prime_do_set_return_block
-        }
-    };
-
-    expanded.into()
+    let expanded = if args.in_impl {
+        // the statics are declared in the body of the cached function
+        quote! {
+            #(#attributes)*
+            #visibility #signature {
+                #cache_ident_doc
+                #ty
+                #lock_stats
+                #cache_stats
+                #warmth
+                #invalidation
+                #quarantine
+                #key_locks
+                use cached::Cached;
+                use cached::CachedWrite;
+                use cached::CloneCached;
+                #bind_from_store
+                let key = #key_lookup_block;
+                #bind_span
+                #do_set_return_block
+            }
+        }
+    } else {
+        quote! {
+            // Cached static
+            #statics
+            #warmth
+            #invalidation
+            #quarantine
+            #key_locks
+            // No cache function (origin of the cached function)
+            #[doc = #no_cache_fn_indent_doc]
+            #visibility #function_no_cache
+            // Cached function
+            #(#attributes)*
+            #visibility #signature_no_muts {
+                use cached::Cached;
+                use cached::CachedWrite;
+                use cached::CloneCached;
+                #bind_statics
+                #bind_from_store
+                let key = #key_lookup_block;
+                #bind_span
+                #do_set_return_block
+            }
+            // Prime cached function
+            #[doc = #prime_fn_indent_doc]
+            #[allow(dead_code)]
+            #(#attributes)*
+            #visibility #prime_sig {
+                use cached::Cached;
+                use cached::CachedWrite;
+                #bind_statics
+                #bind_from_store
+                let key = #key_lookup_block;
+                #bind_span
+                #prime_do_set_return_block
+            }
+            // Cache-management functions
+            #[doc = #remove_fn_doc]
+            #[allow(dead_code, unused_variables)]
+            #visibility #remove_sig {
+                use cached::CachedWrite;
+                #bind_statics
+                let key = #key_lookup_block;
+                #lock
+                let removed = #cache_remove;
+                #record_size
+                removed
+            }
+            #[doc = #clear_fn_doc]
+            #[allow(dead_code)]
+            #visibility #clear_sig {
+                use cached::CachedWrite;
+                #bind_statics
+                #lock
+                cache.cache_clear();
+                #record_size
+            }
+            #remove_prefix_fn
+            #prime_many_fn
+            #stats_fn
+            #cache_fn
+        }
+    };
+
+    Ok(expanded)
 }
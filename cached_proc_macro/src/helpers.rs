@@ -2,14 +2,21 @@ use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::__private::Span;
 use quote::quote;
+use std::fmt::Display;
 use std::ops::Deref;
 use syn::punctuated::Punctuated;
 use syn::token::Comma;
 use syn::{
-    parse_quote, parse_str, Attribute, Block, FnArg, Pat, PatType, PathArguments, ReturnType,
-    Signature, Type,
+    parse_quote, parse_str, Attribute, Block, FnArg, GenericArgument, Ident, Pat, PatType, Path,
+    PathArguments, ReturnType, Signature, Type, TypeImplTrait, TypeParamBound, Visibility,
 };
 
+// an error of the attributes of a macro, reported at compile time on the attribute,
+// the call site of the macro
+pub(super) fn attr_error<M: Display>(message: M) -> syn::Error {
+    syn::Error::new(Span::call_site(), message)
+}
+
 // if you define arguments as mutable, e.g.
 // #[cached]
 // fn mutable_args(mut a: i32, mut b: i32) -> (i32, i32) {
@@ -65,27 +72,35 @@ pub(super) fn find_value_type(
     option: bool,
     output: &ReturnType,
     output_ty: TokenStream2,
-) -> TokenStream2 {
+) -> syn::Result<TokenStream2> {
     match (result, option) {
-        (false, false) => output_ty,
-        (true, true) => panic!("the result and option attributes are mutually exclusive"),
+        (false, false) => Ok(output_ty),
+        (true, true) => Err(attr_error(
+            "the result and option attributes are mutually exclusive",
+        )),
         _ => match output.clone() {
-            ReturnType::Default => {
-                panic!("function must return something for result or option attributes")
-            }
+            ReturnType::Default => Err(attr_error(
+                "function must return something for result or option attributes",
+            )),
             ReturnType::Type(_, ty) => {
-                if let Type::Path(typepath) = *ty {
-                    let segments = typepath.path.segments;
+                if let Type::Path(typepath) = &*ty {
+                    let segments = &typepath.path.segments;
                     if let PathArguments::AngleBracketed(brackets) =
                         &segments.last().unwrap().arguments
                     {
                         let inner_ty = brackets.args.first().unwrap();
-                        quote! {#inner_ty}
+                        Ok(quote! {#inner_ty})
                     } else {
-                        panic!("function return type has no inner type")
+                        Err(syn::Error::new_spanned(
+                            ty,
+                            "function return type has no inner type",
+                        ))
                     }
                 } else {
-                    panic!("function return type too complex")
+                    Err(syn::Error::new_spanned(
+                        ty,
+                        "function return type too complex",
+                    ))
                 }
             }
         },
@@ -99,31 +114,96 @@ pub(super) fn make_cache_key_type(
     ty: &Option<String>,
     input_tys: Vec<Type>,
     input_names: &Vec<Pat>,
-) -> (TokenStream2, TokenStream2) {
+) -> syn::Result<(TokenStream2, TokenStream2)> {
     match (key, convert, ty) {
         (Some(key_str), Some(convert_str), _) => {
-            let cache_key_ty = parse_str::<Type>(key_str).expect("unable to parse cache key type");
+            let cache_key_ty = parse_str::<Type>(key_str)
+                .map_err(|e| attr_error(format!("unable to parse cache key type: {}", e)))?;
 
-            let key_convert_block =
-                parse_str::<Block>(convert_str).expect("unable to parse key convert block");
+            let key_convert_block = parse_str::<Block>(convert_str)
+                .map_err(|e| attr_error(format!("unable to parse key convert block: {}", e)))?;
 
-            (quote! {#cache_key_ty}, quote! {#key_convert_block})
+            Ok((quote! {#cache_key_ty}, quote! {#key_convert_block}))
         }
         (None, Some(convert_str), Some(_)) => {
-            let key_convert_block =
-                parse_str::<Block>(convert_str).expect("unable to parse key convert block");
+            let key_convert_block = parse_str::<Block>(convert_str)
+                .map_err(|e| attr_error(format!("unable to parse key convert block: {}", e)))?;
 
-            (quote! {}, quote! {#key_convert_block})
+            Ok((quote! {}, quote! {#key_convert_block}))
         }
-        (None, None, _) => (
-            quote! {(#(#input_tys),*)},
-            quote! {(#(#input_names.clone()),*)},
-        ),
-        (Some(_), None, _) => panic!("key requires convert to be set"),
-        (None, Some(_), None) => panic!("convert requires key or type to be set"),
+        (None, None, _) => {
+            // the borrowed arguments are made owned, the key of a static cache outliving them
+            let (key_tys, key_parts): (Vec<_>, Vec<_>) = input_tys
+                .iter()
+                .zip(input_names)
+                .map(|(ty, name)| {
+                    if let Type::ImplTrait(impl_trait) = ty {
+                        // the type of an `impl Trait` argument cannot be named, only the
+                        // value it is `AsRef` of
+                        let referenced = as_ref_target(impl_trait).ok_or_else(|| {
+                            syn::Error::new_spanned(
+                                ty,
+                                format!(
+                                    "the `impl Trait` argument `{}` requires key and convert to be set, unless it is `impl AsRef<T>`",
+                                    quote! {#name}
+                                ),
+                            )
+                        })?;
+                        let owned_ty = owned_component_type(&parse_quote! { &#referenced })
+                            .expect("a reference has an owned type");
+                        return Ok((
+                            quote! {#owned_ty},
+                            quote! {::std::borrow::ToOwned::to_owned(::std::convert::AsRef::<#referenced>::as_ref(&#name))},
+                        ));
+                    }
+                    Ok(match owned_component_type(ty) {
+                        Some(owned_ty) => (
+                            quote! {#owned_ty},
+                            quote! {::std::borrow::ToOwned::to_owned(#name)},
+                        ),
+                        None => (quote! {#ty}, quote! {#name.clone()}),
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?
+                .into_iter()
+                .unzip();
+            Ok((quote! {(#(#key_tys),*)}, quote! {(#(#key_parts),*)}))
+        }
+        (Some(_), None, _) => Err(attr_error("key requires convert to be set")),
+        (None, Some(_), None) => Err(attr_error("convert requires key or type to be set")),
     }
 }
 
+// the types and names of the inputs making up the key, leaving out the `ignored` ones
+pub(super) fn filter_ignored_inputs(
+    ignored: &[Path],
+    input_tys: &[Type],
+    input_names: &[Pat],
+) -> syn::Result<(Vec<Type>, Vec<Pat>)> {
+    let is_ignored = |name: &Pat| match name {
+        Pat::Ident(pat_ident) => ignored.iter().any(|path| path.is_ident(&pat_ident.ident)),
+        _ => false,
+    };
+    for path in ignored {
+        let found = input_names.iter().any(|name| match name {
+            Pat::Ident(pat_ident) => path.is_ident(&pat_ident.ident),
+            _ => false,
+        });
+        if !found {
+            return Err(attr_error(format!(
+                "ignored argument `{}` is not an argument of the function",
+                quote! {#path}
+            )));
+        }
+    }
+    Ok(input_tys
+        .iter()
+        .zip(input_names)
+        .filter(|(_, name)| !is_ignored(name))
+        .map(|(ty, name)| (ty.clone(), name.clone()))
+        .unzip())
+}
+
 // if you define arguments as mutable, e.g.
 // #[once]
 // fn mutable_args(mut a: i32, mut b: i32) -> (i32, i32) {
@@ -134,12 +214,15 @@ pub(super) fn make_cache_key_type(
 // then we need to strip off the `mut` keyword from the
 // variable identifiers, so we can refer to arguments `a` and `b`
 // instead of `mut a` and `mut b`
-pub(super) fn get_input_names(inputs: &Punctuated<FnArg, Comma>) -> Vec<Pat> {
+pub(super) fn get_input_names(inputs: &Punctuated<FnArg, Comma>) -> syn::Result<Vec<Pat>> {
     inputs
         .iter()
         .map(|input| match input {
-            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
-            FnArg::Typed(pat_type) => *match_pattern_type(&pat_type),
+            FnArg::Receiver(receiver) => Err(syn::Error::new_spanned(
+                receiver,
+                "methods (functions taking 'self') are not supported",
+            )),
+            FnArg::Typed(pat_type) => Ok(*match_pattern_type(&pat_type)),
         })
         .collect()
 }
@@ -155,12 +238,15 @@ pub(super) fn fill_in_attributes(attributes: &mut Vec<Attribute>, cache_fn_doc_e
 }
 
 // pull out the names and types of the function inputs
-pub(super) fn get_input_types(inputs: &Punctuated<FnArg, Comma>) -> Vec<Type> {
+pub(super) fn get_input_types(inputs: &Punctuated<FnArg, Comma>) -> syn::Result<Vec<Type>> {
     inputs
         .iter()
         .map(|input| match input {
-            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
-            FnArg::Typed(pat_type) => *pat_type.ty.clone(),
+            FnArg::Receiver(receiver) => Err(syn::Error::new_spanned(
+                receiver,
+                "methods (functions taking 'self') are not supported",
+            )),
+            FnArg::Typed(pat_type) => Ok(*pat_type.ty.clone()),
         })
         .collect()
 }
@@ -176,7 +262,7 @@ pub(super) fn get_output_parts(output_ts: &TokenStream) -> Vec<String> {
         .collect()
 }
 
-pub(super) fn with_cache_flag_error(output_span: Span, output_type_display: String) -> TokenStream {
+pub(super) fn with_cache_flag_error(output_span: Span, output_type_display: String) -> syn::Error {
     syn::Error::new(
         output_span,
         format!(
@@ -190,8 +276,6 @@ pub(super) fn with_cache_flag_error(output_span: Span, output_type_display: Stri
             t = output_type_display
         ),
     )
-    .to_compile_error()
-    .into()
 }
 
 pub(super) fn gen_return_cache_block(
@@ -218,3 +302,495 @@ pub(super) fn check_with_cache_flag(with_cached_flag: bool, output_string: Strin
         && !output_string.contains("Return")
         && !output_string.contains("cached::Return")
 }
+
+// Every cache static generated by `#[cached]` and `#[once]` gets a companion
+// `{CACHE}_LOCK_STATS` static recording the contention on its lock. The stats
+// are registered with `cached::registry` when the cache static is initialized.
+pub(super) fn gen_lock_stats(
+    cache_ident: &Ident,
+    fn_ident: &Ident,
+    visibility: &Visibility,
+) -> (Ident, TokenStream2, TokenStream2) {
+    let lock_stats_ident = Ident::new(&format!("{}_LOCK_STATS", cache_ident), cache_ident.span());
+    let lock_stats_doc = format!("Lock statistics of the [`{}`] cached static.", cache_ident);
+    let lock_stats = quote! {
+        #[doc = #lock_stats_doc]
+        #visibility static #lock_stats_ident: ::cached::registry::LockStats =
+            ::cached::registry::LockStats::new(concat!(module_path!(), "::", stringify!(#fn_ident)));
+    };
+    let register = quote! {
+        ::cached::registry::register_lock_stats(&#lock_stats_ident);
+    };
+    (lock_stats_ident, lock_stats, register)
+}
+
+// The cache static gets a companion `{CACHE}_CACHE_STATS` static, registered with
+// `cached::registry` along with the lock stats, counting the hits and misses and
+// recording the size of the cache, so that they can be read without locking it.
+pub(super) fn gen_cache_stats(
+    cache_ident: &Ident,
+    fn_ident: &Ident,
+    visibility: &Visibility,
+) -> (Ident, TokenStream2, TokenStream2) {
+    let cache_stats_ident = Ident::new(&format!("{}_CACHE_STATS", cache_ident), cache_ident.span());
+    let cache_stats_doc = format!(
+        "Hit, miss and size statistics of the [`{}`] cached static.",
+        cache_ident
+    );
+    let cache_stats = quote! {
+        #[doc = #cache_stats_doc]
+        #visibility static #cache_stats_ident: ::cached::registry::CacheStats =
+            ::cached::registry::CacheStats::new(concat!(module_path!(), "::", stringify!(#fn_ident)));
+    };
+    let register = quote! {
+        ::cached::registry::register_cache_stats(&#cache_stats_ident);
+    };
+    (cache_stats_ident, cache_stats, register)
+}
+
+// With `warm_size`, the cache static gets a companion `{CACHE}_WARMTH` static,
+// registered with `cached::registry` along with the lock stats, recording the
+// size of the cache each time a value is set.
+pub(super) fn gen_warmth(
+    cache_ident: &Ident,
+    fn_ident: &Ident,
+    visibility: &Visibility,
+    warm_size: Option<usize>,
+) -> (TokenStream2, TokenStream2, TokenStream2) {
+    let warm_size = match warm_size {
+        Some(warm_size) => warm_size,
+        None => return (quote! {}, quote! {}, quote! {}),
+    };
+    let warmth_ident = Ident::new(&format!("{}_WARMTH", cache_ident), cache_ident.span());
+    let warmth_doc = format!("Warmth of the [`{}`] cached static.", cache_ident);
+    let warmth = quote! {
+        #[doc = #warmth_doc]
+        #visibility static #warmth_ident: ::cached::registry::Warmth =
+            ::cached::registry::Warmth::new(concat!(module_path!(), "::", stringify!(#fn_ident)), #warm_size);
+    };
+    let register = quote! {
+        ::cached::registry::register_warmth(&#warmth_ident);
+    };
+    let record = quote! {
+        #warmth_ident.record_size(::cached::CachedRead::cache_size(&*cache));
+    };
+    (warmth, register, record)
+}
+
+// With `invalidation`, the cache static gets a companion `{CACHE}_INVALIDATION` static,
+// registered with `cached::registry`, removing the value of the arguments deserialized
+// from a JSON array. `lock` (an expression locking the cache) must not block, the
+// invalidation being called synchronously.
+pub(super) fn gen_invalidation(
+    cache_ident: &Ident,
+    fn_ident: &Ident,
+    visibility: &Visibility,
+    input_names: &[Pat],
+    input_tys: &[Type],
+    key_convert_block: &TokenStream2,
+    lock: TokenStream2,
+) -> (TokenStream2, TokenStream2) {
+    let invalidation_ident =
+        Ident::new(&format!("{}_INVALIDATION", cache_ident), cache_ident.span());
+    let invalidation_doc = format!("Invalidation of the [`{}`] cached static.", cache_ident);
+    // the arguments are deserialized into owned values, e.g. a `String` for a `&str`
+    // whose JSON has escapes, which the borrowed arguments are taken from
+    let owned_names: Vec<Ident> = (0..input_names.len())
+        .map(|i| Ident::new(&format!("owned_{}", i), Span::call_site()))
+        .collect();
+    let (owned_tys, borrows): (Vec<TokenStream2>, Vec<TokenStream2>) = input_tys
+        .iter()
+        .zip(&owned_names)
+        .map(|(ty, owned)| match ty {
+            Type::Reference(reference) if reference.mutability.is_none() => {
+                let referenced = &reference.elem;
+                (
+                    quote! { <#referenced as ::std::borrow::ToOwned>::Owned },
+                    quote! { ::std::borrow::Borrow::<#referenced>::borrow(&#owned) },
+                )
+            }
+            ty => (quote! { #ty }, quote! { #owned }),
+        })
+        .unzip();
+    let invalidation = quote! {
+        #[doc = #invalidation_doc]
+        #visibility static #invalidation_ident: ::cached::registry::Invalidation = ::cached::registry::Invalidation::new(
+            concat!(module_path!(), "::", stringify!(#fn_ident)),
+            |serialized_key| {
+                let (#(#owned_names,)*): (#(#owned_tys,)*) = ::cached::serde_json::from_str(serialized_key)
+                    .map_err(|e| ::cached::registry::InvalidationError::InvalidKey(e.to_string()))?;
+                #(let #input_names = #borrows;)*
+                let key = #key_convert_block;
+                let mut cache = #lock;
+                Ok(::cached::CachedWrite::cache_remove(&mut *cache, &key).is_some())
+            },
+        );
+    };
+    let register = quote! {
+        ::cached::registry::register_invalidation(&#invalidation_ident);
+    };
+    (invalidation, register)
+}
+
+// With `quarantine_after`, the cache static gets a companion `{CACHE}_QUARANTINE`
+// static counting the consecutive errors of each key. `call` (an expression calling
+// the function) is skipped while the key is quarantined, failing with the last error.
+// `key_ref` is a reference to the key, made owned when a failure is recorded.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn gen_quarantine(
+    cache_ident: &Ident,
+    visibility: &Visibility,
+    output: &ReturnType,
+    cache_key_ty: &TokenStream2,
+    key_ref: &TokenStream2,
+    quarantine_after: Option<u32>,
+    quarantine_secs: Option<u64>,
+    call: TokenStream2,
+) -> syn::Result<(TokenStream2, TokenStream2)> {
+    let quarantine_after = match (quarantine_after, quarantine_secs) {
+        (Some(quarantine_after), _) => quarantine_after,
+        (None, Some(_)) => {
+            return Err(attr_error(
+                "quarantine_secs requires quarantine_after to be set",
+            ))
+        }
+        (None, None) => return Ok((quote! {}, quote! { let result = #call; })),
+    };
+    let quarantine_secs = quarantine_secs.unwrap_or(60);
+    // the error type of `Result<T, E>`
+    let error_ty = match output {
+        ReturnType::Type(_, ty) => match &**ty {
+            Type::Path(typepath) => match &typepath.path.segments.last().unwrap().arguments {
+                PathArguments::AngleBracketed(brackets) => match brackets.args.iter().nth(1) {
+                    Some(GenericArgument::Type(error_ty)) => Some(error_ty.clone()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        },
+        ReturnType::Default => None,
+    }
+    .ok_or_else(|| {
+        syn::Error::new_spanned(
+            output,
+            "quarantine_after requires the function to return a `Result<T, E>`",
+        )
+    })?;
+
+    let quarantine_ident = Ident::new(&format!("{}_QUARANTINE", cache_ident), cache_ident.span());
+    let quarantine_doc = format!(
+        "Quarantine of the keys of the [`{}`] cached static.",
+        cache_ident
+    );
+    let quarantine = quote! {
+        #[doc = #quarantine_doc]
+        #visibility static #quarantine_ident: ::cached::once_cell::sync::Lazy<::cached::quarantine::Quarantine<#cache_key_ty, #error_ty>> =
+            ::cached::once_cell::sync::Lazy::new(|| {
+                ::cached::quarantine::Quarantine::new(#quarantine_after, ::cached::instant::Duration::from_secs(#quarantine_secs))
+            });
+    };
+    let call = quote! {
+        let result = match #quarantine_ident.check(#key_ref) {
+            Some(error) => Err(error),
+            None => {
+                let result = #call;
+                match &result {
+                    Ok(_) => #quarantine_ident.record_success(#key_ref),
+                    Err(error) => {
+                        #quarantine_ident.record_failure(
+                            ::std::borrow::ToOwned::to_owned(#key_ref),
+                            error.clone(),
+                        );
+                    }
+                }
+                result
+            }
+        };
+    };
+    Ok((quarantine, call))
+}
+
+// Acquire `lock` (an expression evaluating to a lock guard) into `guard`,
+// recording the wait and hold times in `lock_stats_ident`.
+pub(super) fn gen_timed_lock(
+    guard: TokenStream2,
+    lock: TokenStream2,
+    lock_stats_ident: &Ident,
+) -> TokenStream2 {
+    quote! {
+        let lock_timer = ::cached::registry::LockTimer::start();
+        let #guard = #lock;
+        let _lock_hold = lock_timer.acquired(&#lock_stats_ident);
+    }
+}
+
+// Return `T` when `output` is an `impl Future<Output = T>`.
+pub(super) fn impl_future_output(output: &ReturnType) -> Option<Type> {
+    let impl_trait = match output {
+        ReturnType::Type(_, ty) => match &**ty {
+            Type::ImplTrait(impl_trait) => impl_trait,
+            _ => return None,
+        },
+        ReturnType::Default => return None,
+    };
+    impl_trait.bounds.iter().find_map(|bound| match bound {
+        TypeParamBound::Trait(bound) => match bound.path.segments.last() {
+            Some(segment) if segment.ident == "Future" => match &segment.arguments {
+                PathArguments::AngleBracketed(brackets) => {
+                    brackets.args.iter().find_map(|arg| match arg {
+                        GenericArgument::AssocType(assoc) if assoc.ident == "Output" => {
+                            Some(assoc.ty.clone())
+                        }
+                        _ => None,
+                    })
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+// `#[async_trait]` expands the methods of the impls it is applied to before `#[cached]`,
+// turning `async fn f(..) -> T` into `fn f(..) -> Pin<Box<dyn Future<Output = T> + ..>>`.
+// Return `T` when `output` is such a boxed future.
+pub(super) fn boxed_future_output(output: &ReturnType) -> Option<Type> {
+    // the first type argument of `ty`, when `ty` is an `ident<..>` path
+    fn type_argument<'a>(ty: &'a Type, ident: &str) -> Option<&'a Type> {
+        match ty {
+            Type::Path(typepath) => match typepath.path.segments.last() {
+                Some(segment) if segment.ident == ident => match &segment.arguments {
+                    PathArguments::AngleBracketed(brackets) => match brackets.args.first() {
+                        Some(GenericArgument::Type(ty)) => Some(ty),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    let boxed = match output {
+        ReturnType::Type(_, ty) => type_argument(type_argument(ty, "Pin")?, "Box")?,
+        ReturnType::Default => return None,
+    };
+    match boxed {
+        Type::TraitObject(trait_object) => {
+            trait_object.bounds.iter().find_map(|bound| match bound {
+                TypeParamBound::Trait(bound) => match bound.path.segments.last() {
+                    Some(segment) if segment.ident == "Future" => match &segment.arguments {
+                        PathArguments::AngleBracketed(brackets) => {
+                            brackets.args.iter().find_map(|arg| match arg {
+                                GenericArgument::AssocType(assoc) if assoc.ident == "Output" => {
+                                    Some(assoc.ty.clone())
+                                }
+                                _ => None,
+                            })
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            })
+        }
+        _ => None,
+    }
+}
+
+// With `cache_policy`, the function returns `CachePolicy<T>`, `Result<CachePolicy<T>, E>`
+// or `Option<CachePolicy<T>>`, and the cached function `T`, `Result<T, E>` or `Option<T>`.
+pub(super) fn strip_cache_policy(output: &ReturnType) -> syn::Result<ReturnType> {
+    // the first type argument of `ty`, along with whether `ty` is a `CachePolicy`
+    fn first_argument(ty: &Type) -> Option<(&Type, bool)> {
+        match ty {
+            Type::Path(typepath) => {
+                let segment = typepath.path.segments.last()?;
+                match &segment.arguments {
+                    PathArguments::AngleBracketed(brackets) => match brackets.args.first() {
+                        Some(GenericArgument::Type(inner)) => {
+                            Some((inner, segment.ident == "CachePolicy"))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    let stripped = match output {
+        ReturnType::Type(arrow, ty) => match first_argument(ty) {
+            Some((inner, true)) => Some(ReturnType::Type(*arrow, Box::new(inner.clone()))),
+            Some((inner, false)) => match first_argument(inner) {
+                Some((value, true)) => {
+                    let mut ty = ty.clone();
+                    if let Type::Path(typepath) = &mut *ty {
+                        if let Some(segment) = typepath.path.segments.last_mut() {
+                            if let PathArguments::AngleBracketed(brackets) = &mut segment.arguments
+                            {
+                                brackets.args[0] = GenericArgument::Type(value.clone());
+                            }
+                        }
+                    }
+                    Some(ReturnType::Type(*arrow, ty))
+                }
+                _ => None,
+            },
+            None => None,
+        },
+        ReturnType::Default => None,
+    };
+    stripped.ok_or_else(|| {
+        syn::Error::new_spanned(
+            output,
+            "cache_policy requires the function to return a `CachePolicy<T>`, \
+            `Result<CachePolicy<T>, E>` or `Option<CachePolicy<T>>`",
+        )
+    })
+}
+
+// With `wrap = "arc"`, the function returns `T`, `Result<T, E>` or `Option<T>`, and the
+// cached function `Arc<T>`, `Result<Arc<T>, E>` or `Option<Arc<T>>`.
+pub(super) fn wrap_output_arc(
+    output: &ReturnType,
+    result: bool,
+    option: bool,
+) -> syn::Result<ReturnType> {
+    let (arrow, ty) = match output {
+        ReturnType::Type(arrow, ty) => (*arrow, ty),
+        ReturnType::Default => {
+            return Err(attr_error("wrap requires the function to return something"))
+        }
+    };
+    if !result && !option {
+        return Ok(parse_quote! { -> ::std::sync::Arc<#ty> });
+    }
+    let no_inner_type = || syn::Error::new_spanned(ty, "function return type has no inner type");
+    let mut wrapped = ty.clone();
+    match &mut *wrapped {
+        Type::Path(typepath) => match typepath.path.segments.last_mut().map(|s| &mut s.arguments) {
+            Some(PathArguments::AngleBracketed(brackets)) => match brackets.args.first_mut() {
+                Some(GenericArgument::Type(inner)) => {
+                    *inner = parse_quote! { ::std::sync::Arc<#inner> };
+                }
+                _ => return Err(no_inner_type()),
+            },
+            _ => return Err(no_inner_type()),
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ty,
+                "function return type too complex",
+            ))
+        }
+    }
+    Ok(ReturnType::Type(arrow, wrapped))
+}
+
+// Whether `ty` mentions one of `idents`, e.g. the type parameters of a generic function.
+pub(super) fn type_mentions(ty: &Type, idents: &[Ident]) -> bool {
+    fn mentions(tokens: TokenStream2, idents: &[Ident]) -> bool {
+        tokens.into_iter().any(|token| match token {
+            proc_macro2::TokenTree::Ident(ident) => idents.contains(&ident),
+            proc_macro2::TokenTree::Group(group) => mentions(group.stream(), idents),
+            _ => false,
+        })
+    }
+    mentions(quote! { #ty }, idents)
+}
+
+// The owned type of a key argument looked up borrowed, `String` for `&str` and `Vec<T>`
+// for `&[T]`, whose values are found with the `Borrow`-based lookups of the stores.
+pub(super) fn owned_key_type(ty: &Type) -> Option<Type> {
+    match ty {
+        Type::Reference(reference) if reference.mutability.is_none() => {
+            match reference.elem.as_ref() {
+                Type::Path(path) if path.path.is_ident("str") => {
+                    Some(parse_quote! { ::std::string::String })
+                }
+                Type::Slice(slice) => {
+                    let elem = &slice.elem;
+                    Some(parse_quote! { ::std::vec::Vec<#elem> })
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// The owned type of a borrowed key argument, other than a `'static` one: that of
+// `owned_key_type`, or else the `ToOwned::Owned` type of the referenced type.
+fn owned_component_type(ty: &Type) -> Option<Type> {
+    match ty {
+        Type::Reference(reference)
+            if reference.mutability.is_none()
+                && !matches!(&reference.lifetime, Some(lifetime) if lifetime.ident == "static") =>
+        {
+            let elem = &reference.elem;
+            owned_key_type(ty)
+                .or_else(|| Some(parse_quote! { <#elem as ::std::borrow::ToOwned>::Owned }))
+        }
+        _ => None,
+    }
+}
+
+// The type `T` of an `impl AsRef<T>` argument.
+fn as_ref_target(impl_trait: &TypeImplTrait) -> Option<Type> {
+    impl_trait.bounds.iter().find_map(|bound| match bound {
+        TypeParamBound::Trait(bound) => {
+            let segment = bound.path.segments.last()?;
+            match &segment.arguments {
+                PathArguments::AngleBracketed(brackets) if segment.ident == "AsRef" => {
+                    match brackets.args.first() {
+                        Some(GenericArgument::Type(ty)) => Some(ty.clone()),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    })
+}
+
+// Whether the cache keys of type `key_ty` are strings (`String`, `&str`, `Arc<str>`, ...),
+// whose values can be removed by key prefix.
+pub(super) fn is_string_key(key_ty: &TokenStream2) -> bool {
+    fn is_str(ty: &Type) -> bool {
+        match ty {
+            Type::Paren(paren) => is_str(&paren.elem),
+            Type::Group(group) => is_str(&group.elem),
+            Type::Path(path) => path.path.is_ident("str"),
+            _ => false,
+        }
+    }
+    fn is_string(ty: &Type) -> bool {
+        match ty {
+            Type::Paren(paren) => is_string(&paren.elem),
+            Type::Group(group) => is_string(&group.elem),
+            Type::Reference(reference) => is_str(&reference.elem),
+            Type::Path(path) => {
+                let segment = path.path.segments.last().unwrap();
+                match (segment.ident.to_string().as_str(), &segment.arguments) {
+                    ("String", PathArguments::None) => true,
+                    ("Arc" | "Rc" | "Box" | "Cow", PathArguments::AngleBracketed(brackets)) => {
+                        brackets
+                            .args
+                            .iter()
+                            .any(|arg| matches!(arg, GenericArgument::Type(ty) if is_str(ty)))
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+    syn::parse2::<Type>(key_ty.clone()).is_ok_and(|ty| is_string(&ty))
+}
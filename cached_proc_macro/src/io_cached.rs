@@ -2,23 +2,54 @@ use crate::helpers::*;
 use darling::ast::NestedMeta;
 use darling::FromMeta;
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
 use syn::spanned::Spanned;
 use syn::{
-    parse_macro_input, parse_str, Block, ExprClosure, GenericArgument, Ident, ItemFn,
+    parse_macro_input, parse_str, Block, ExprClosure, GenericArgument, Ident, ItemFn, Pat,
     PathArguments, ReturnType, Type,
 };
 
+/// How functions not returning a `Result` handle the errors of their cache store
+enum OnError {
+    /// Ignore the error, computing the value as if it was not cached
+    Recompute,
+    /// Panic with the error
+    Panic,
+    /// Report the error, then compute the value as if it was not cached
+    Log,
+}
+
+impl FromMeta for OnError {
+    fn from_string(value: &str) -> darling::Result<Self> {
+        match value {
+            "recompute" => Ok(OnError::Recompute),
+            "panic" => Ok(OnError::Panic),
+            "log" => Ok(OnError::Log),
+            _ => Err(darling::Error::unknown_value(value)),
+        }
+    }
+}
+
 #[derive(FromMeta)]
 struct IOMacroArgs {
-    map_error: String,
+    #[darling(default)]
+    map_error: Option<String>,
+    #[darling(default)]
+    on_error: Option<OnError>,
     #[darling(default)]
     disk: bool,
     #[darling(default)]
     disk_dir: Option<String>,
     #[darling(default)]
+    disk_database: Option<String>,
+    #[darling(default)]
     redis: bool,
     #[darling(default)]
+    shared_connection: bool,
+    #[darling(default)]
+    config_epoch: bool,
+    #[darling(default)]
     cache_prefix_block: Option<String>,
     #[darling(default)]
     name: Option<String>,
@@ -36,6 +67,20 @@ struct IOMacroArgs {
     ty: Option<String>,
     #[darling(default)]
     create: Option<String>,
+    #[darling(default)]
+    validator: Option<String>,
+    #[darling(default)]
+    revalidate_after: Option<u64>,
+    #[darling(default)]
+    l1_size: Option<usize>,
+    #[darling(default)]
+    l1_time: Option<u64>,
+    #[darling(default)]
+    breaker_after: Option<u32>,
+    #[darling(default)]
+    breaker_secs: Option<u64>,
+    #[darling(default)]
+    result_fallback: bool,
 }
 
 pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
@@ -52,7 +97,13 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
         }
     };
     let input = parse_macro_input!(input as ItemFn);
+    expand(args, input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
 
+// the misuses of the attributes are reported as errors on the attribute
+fn expand(args: IOMacroArgs, input: ItemFn) -> syn::Result<TokenStream2> {
     // pull out the parts of the input
     let mut attributes = input.attrs;
     let visibility = input.vis;
@@ -65,9 +116,76 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
     let output = signature.output.clone();
     let asyncness = signature.asyncness;
 
-    let input_tys = get_input_types(&inputs);
+    match (&args.map_error, &args.on_error) {
+        (Some(_), Some(_)) => {
+            return Err(attr_error("the map_error and on_error attributes are mutually exclusive"))
+        }
+        (None, None) => return Err(attr_error("#[io_cached] requires `map_error` for functions returning a `Result`, or `on_error` for the others")),
+        _ => {}
+    }
+    if args.on_error.is_some() && (args.with_cached_flag || args.validator.is_some()) {
+        return Err(attr_error("on_error cannot be combined with with_cached_flag or validator, which require a `Result`"));
+    }
+    if args.result_fallback
+        && (args.on_error.is_some() || args.with_cached_flag || args.validator.is_some())
+    {
+        return Err(attr_error(
+            "result_fallback is not supported with on_error, with_cached_flag or validator",
+        ));
+    }
+
+    let input_tys = get_input_types(&inputs)?;
 
-    let input_names = get_input_names(&inputs);
+    let input_names = get_input_names(&inputs)?;
+
+    // the argument receiving the validator of the cached value is not part of the key
+    let validator_index = args.validator.as_ref().map(|validator| {
+        if args.with_cached_flag {
+            return Err(attr_error(
+                "the validator and with_cached_flag attributes are mutually exclusive",
+            ));
+        }
+        input_names
+            .iter()
+            .position(|name| matches!(name, Pat::Ident(name) if name.ident == validator))
+            .ok_or_else(|| {
+                attr_error(format!(
+                    "#[io_cached] function has no `{}` argument",
+                    validator
+                ))
+            })
+    });
+    let validator_index = validator_index.transpose()?;
+    if args.revalidate_after.is_some() && validator_index.is_none() {
+        return Err(attr_error("revalidate_after requires validator to be set"));
+    }
+    if args.l1_time.is_some() && args.l1_size.is_none() {
+        return Err(attr_error("l1_time requires l1_size to be set"));
+    }
+    if args.l1_size == Some(0) {
+        return Err(attr_error("l1_size must be greater than zero"));
+    }
+    if args.l1_size.is_some() && validator_index.is_some() {
+        return Err(attr_error(
+            "the l1_size and validator attributes are mutually exclusive",
+        ));
+    }
+    if args.breaker_secs.is_some() && args.breaker_after.is_none() {
+        return Err(attr_error("breaker_secs requires breaker_after to be set"));
+    }
+    if args.breaker_after == Some(0) {
+        return Err(attr_error("breaker_after must be greater than zero"));
+    }
+    let (key_input_tys, key_input_names) = match validator_index {
+        Some(index) => {
+            let mut input_tys = input_tys;
+            let mut input_names = input_names.clone();
+            input_tys.remove(index);
+            input_names.remove(index);
+            (input_tys, input_names)
+        }
+        None => (input_tys, input_names.clone()),
+    };
 
     // pull out the output type
     let output_ty = match &output {
@@ -88,7 +206,7 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
         && !output_string.contains("Return")
         && !output_string.contains("cached::Return")
     {
-        return syn::Error::new(
+        return Err(syn::Error::new(
             output_span,
             format!(
                 "\nWhen specifying `with_cached_flag = true`, \
@@ -98,20 +216,24 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
                     Found type: {t}.",
                 t = output_type_display
             ),
-        )
-        .to_compile_error()
-        .into();
+        ));
     }
 
     // Find the type of the value to store.
-    // Return type always needs to be a result, so we want the (first) inner type.
-    // For Result<i32, String>, store i32, etc.
+    // Unless the store errors are handled by `on_error`, the return type needs to be a
+    // result, so we want the (first) inner type. For Result<i32, String>, store i32, etc.
     let cache_value_ty = match output.clone() {
+        ReturnType::Default if args.on_error.is_some() => {
+            return Err(attr_error(
+                "#[io_cached] functions must return a value to cache",
+            ))
+        }
+        ReturnType::Type(_, ty) if args.on_error.is_some() => quote! {#ty},
         ReturnType::Default => {
-            panic!(
+            return Err(attr_error(format!(
                 "#[io_cached] functions must return `Result`s, found {:?}",
                 output_type_display
-            );
+            )));
         }
         ReturnType::Type(_, ty) => {
             if let Type::Path(typepath) = *ty {
@@ -119,7 +241,9 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
                 if let PathArguments::AngleBracketed(brackets) = &segments.last().unwrap().arguments
                 {
                     let inner_ty = brackets.args.first().unwrap();
-                    if output_string.contains("Return") || output_string.contains("cached::Return")
+                    if validator_index.is_some()
+                        || output_string.contains("Return")
+                        || output_string.contains("cached::Return")
                     {
                         if let GenericArgument::Type(Type::Path(typepath)) = inner_ty {
                             let segments = &typepath.path.segments;
@@ -129,31 +253,55 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
                                 let inner_ty = brackets.args.first().unwrap();
                                 quote! {#inner_ty}
                             } else {
-                                panic!(
+                                return Err(attr_error(format!(
                                     "#[io_cached] unable to determine cache value type, found {:?}",
                                     output_type_display
-                                );
+                                )));
                             }
                         } else {
-                            panic!(
+                            return Err(attr_error(format!(
                                 "#[io_cached] unable to determine cache value type, found {:?}",
                                 output_type_display
-                            );
+                            )));
                         }
                     } else {
                         quote! {#inner_ty}
                     }
                 } else {
-                    panic!("#[io_cached] functions must return `Result`s")
+                    return Err(attr_error("#[io_cached] functions must return `Result`s"));
                 }
             } else {
-                panic!(
-                    "function return type too complex, #[io_cached] functions must return `Result`s"
-                )
+                return Err(attr_error("function return type too complex, #[io_cached] functions must return `Result`s"));
             }
         }
     };
 
+    // functions revalidating their values return `Result<Revalidation<V>, E>`,
+    // their values are stored along with their validator
+    let (cache_value_ty, public_output) = match validator_index {
+        Some(_) => {
+            let mut public_output = output.clone();
+            if let ReturnType::Type(_, ty) = &mut public_output {
+                if let Type::Path(typepath) = &mut **ty {
+                    if let PathArguments::AngleBracketed(brackets) =
+                        &mut typepath.path.segments.last_mut().unwrap().arguments
+                    {
+                        let value_ty =
+                            syn::parse2::<Type>(cache_value_ty.clone()).map_err(|e| {
+                                attr_error(format!("unable to parse cache value type: {}", e))
+                            })?;
+                        *brackets.args.first_mut().unwrap() = GenericArgument::Type(value_ty);
+                    }
+                }
+            }
+            (
+                quote! { ::cached::Validated<#cache_value_ty> },
+                public_output,
+            )
+        }
+        None => (cache_value_ty, output.clone()),
+    };
+
     // make the cache identifier
     let cache_ident = match args.name {
         Some(ref name) => Ident::new(name, fn_ident.span()),
@@ -161,8 +309,34 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
     };
     let cache_name = cache_ident.to_string();
 
-    let (cache_key_ty, key_convert_block) =
-        make_cache_key_type(&args.key, &args.convert, &args.ty, input_tys, &input_names);
+    // hit, miss and load statistics of the cache static, the size of IO stores being unknown
+    let (cache_stats_ident, cache_stats, register_cache_stats) =
+        gen_cache_stats(&cache_ident, &fn_ident, &visibility);
+    let record_hit = quote! { #cache_stats_ident.record_hit(); };
+    let record_miss = quote! { #cache_stats_ident.record_miss(); };
+
+    let (cache_key_ty, key_convert_block) = make_cache_key_type(
+        &args.key,
+        &args.convert,
+        &args.ty,
+        key_input_tys,
+        &key_input_names,
+    )?;
+    // the keys of the IO stores are displayed, so the epoch is a prefix of the key
+    let (cache_key_ty, key_convert_block) = if args.config_epoch {
+        (
+            quote! {String},
+            quote! {format!("{}:{}", ::cached::registry::config_epoch(), #key_convert_block)},
+        )
+    } else {
+        (cache_key_ty, key_convert_block)
+    };
+
+    if args.shared_connection && (!args.redis || asyncness.is_none() || args.create.is_some()) {
+        return Err(attr_error(
+            "`shared_connection` requires `redis`, an async function and no `create` block",
+        ));
+    }
 
     // make the cache type and create statement
     let (cache_ty, cache_create) = match (
@@ -178,7 +352,8 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
         (true, false, time, time_refresh, cache_prefix, ty, cache_create) => {
             let cache_ty = match ty {
                 Some(ty) => {
-                    let ty = parse_str::<Type>(ty).expect("unable to parse cache type");
+                    let ty = parse_str::<Type>(ty)
+                        .map_err(|e| attr_error(format!("unable to parse cache type: {}", e)))?;
                     quote! { #ty }
                 }
                 None => {
@@ -192,21 +367,23 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
             let cache_create = match cache_create {
                 Some(cache_create) => {
                     if time.is_some() || time_refresh.is_some() || cache_prefix.is_some() {
-                        panic!("cannot specify `time`, `time_refresh`, or `cache_prefix` when passing `create block");
+                        return Err(attr_error("cannot specify `time`, `time_refresh`, or `cache_prefix` when passing `create block"));
                     } else {
-                        let cache_create = parse_str::<Block>(cache_create.as_ref())
-                            .expect("unable to parse cache create block");
+                        let cache_create =
+                            parse_str::<Block>(cache_create.as_ref()).map_err(|e| {
+                                attr_error(format!("unable to parse cache create block: {}", e))
+                            })?;
                         quote! { #cache_create }
                     }
                 }
                 None => {
                     if time.is_none() {
                         if asyncness.is_some() {
-                            panic!("AsyncRedisCache requires a `time` when `create` block is not specified")
+                            return Err(attr_error("AsyncRedisCache requires a `time` when `create` block is not specified"));
                         } else {
-                            panic!(
-                                "RedisCache requires a `time` when `create` block is not specified"
-                            )
+                            return Err(attr_error(
+                                "RedisCache requires a `time` when `create` block is not specified",
+                            ));
                         };
                     } else {
                         let cache_prefix = if let Some(cp) = cache_prefix {
@@ -214,26 +391,24 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
                         } else {
                             format!(" {{ \"cached::proc_macro::io_cached::{}\" }}", cache_ident)
                         };
-                        let cache_prefix = parse_str::<Block>(cache_prefix.as_ref())
-                            .expect("unable to parse cache_prefix_block");
-                        match time_refresh {
-                            Some(time_refresh) => {
-                                if asyncness.is_some() {
-                                    quote! { cached::AsyncRedisCache::new(#cache_prefix, #time).set_refresh(#time_refresh).build().await.expect("error constructing AsyncRedisCache in #[io_cached] macro") }
-                                } else {
-                                    quote! {
-                                        cached::RedisCache::new(#cache_prefix, #time).set_refresh(#time_refresh).build().expect("error constructing RedisCache in #[io_cached] macro")
-                                    }
-                                }
-                            }
-                            None => {
-                                if asyncness.is_some() {
-                                    quote! { cached::AsyncRedisCache::new(#cache_prefix, #time).build().await.expect("error constructing AsyncRedisCache in #[io_cached] macro") }
-                                } else {
-                                    quote! {
-                                        cached::RedisCache::new(#cache_prefix, #time).build().expect("error constructing RedisCache in #[io_cached] macro")
-                                    }
-                                }
+                        let cache_prefix =
+                            parse_str::<Block>(cache_prefix.as_ref()).map_err(|e| {
+                                attr_error(format!("unable to parse cache_prefix_block: {}", e))
+                            })?;
+                        let set_refresh = match time_refresh {
+                            Some(time_refresh) => quote! { .set_refresh(#time_refresh) },
+                            None => quote! {},
+                        };
+                        if asyncness.is_some() {
+                            let set_shared_connection = if args.shared_connection {
+                                quote! { .set_shared_connection(true) }
+                            } else {
+                                quote! {}
+                            };
+                            quote! { cached::AsyncRedisCache::new(#cache_prefix, #time) #set_refresh #set_shared_connection .build().await.expect("error constructing AsyncRedisCache in #[io_cached] macro") }
+                        } else {
+                            quote! {
+                                cached::RedisCache::new(#cache_prefix, #time) #set_refresh .build().expect("error constructing RedisCache in #[io_cached] macro")
                             }
                         }
                     }
@@ -245,7 +420,8 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
         (false, true, time, time_refresh, _, ty, cache_create) => {
             let cache_ty = match ty {
                 Some(ty) => {
-                    let ty = parse_str::<Type>(ty).expect("unable to parse cache type");
+                    let ty = parse_str::<Type>(ty)
+                        .map_err(|e| attr_error(format!("unable to parse cache type: {}", e)))?;
                     quote! { #ty }
                 }
                 None => {
@@ -256,12 +432,14 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
             let cache_create = match cache_create {
                 Some(cache_create) => {
                     if time.is_some() || time_refresh.is_some() {
-                        panic!(
-                            "cannot specify `time` or `time_refresh` when passing `create block"
-                        );
+                        return Err(attr_error(
+                            "cannot specify `time` or `time_refresh` when passing `create block",
+                        ));
                     } else {
-                        let cache_create = parse_str::<Block>(cache_create.as_ref())
-                            .expect("unable to parse cache create block");
+                        let cache_create =
+                            parse_str::<Block>(cache_create.as_ref()).map_err(|e| {
+                                attr_error(format!("unable to parse cache create block: {}", e))
+                            })?;
                         quote! { #cache_create }
                     }
                 }
@@ -291,6 +469,12 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
                             quote! { (#create).set_disk_directory(#disk_dir) }
                         }
                     };
+                    let create = match args.disk_database {
+                        None => create,
+                        Some(disk_database) => {
+                            quote! { (#create).set_database(#disk_database) }
+                        }
+                    };
                     quote! { (#create).build().expect("error constructing DiskCache in #[io_cached] macro") }
                 }
             };
@@ -299,95 +483,342 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
         (_, _, time, time_refresh, cache_prefix, ty, cache_create) => {
             let cache_ty = match ty {
                 Some(ty) => {
-                    let ty = parse_str::<Type>(ty).expect("unable to parse cache type");
+                    let ty = parse_str::<Type>(ty)
+                        .map_err(|e| attr_error(format!("unable to parse cache type: {}", e)))?;
                     quote! { #ty }
                 }
-                None => panic!("#[io_cached] cache `ty` must be specified"),
+                None => return Err(attr_error("#[io_cached] cache `ty` must be specified")),
             };
             let cache_create = match cache_create {
                 Some(cache_create) => {
                     if time.is_some() || time_refresh.is_some() || cache_prefix.is_some() {
-                        panic!("cannot specify `time`, `time_refresh`, or `cache_prefix` when passing `create block");
+                        return Err(attr_error("cannot specify `time`, `time_refresh`, or `cache_prefix` when passing `create block"));
                     } else {
-                        let cache_create = parse_str::<Block>(cache_create.as_ref())
-                            .expect("unable to parse cache create block");
+                        let cache_create =
+                            parse_str::<Block>(cache_create.as_ref()).map_err(|e| {
+                                attr_error(format!("unable to parse cache create block: {}", e))
+                            })?;
                         quote! { #cache_create }
                     }
                 }
                 None => {
-                    panic!("#[io_cached] cache `create` block must be specified");
+                    return Err(attr_error(
+                        "#[io_cached] cache `create` block must be specified",
+                    ));
                 }
             };
             (cache_ty, cache_create)
         }
         #[allow(unreachable_patterns)]
-        _ => panic!("#[io_cached] cache types cache type could not be determined"),
+        _ => {
+            return Err(attr_error(
+                "#[io_cached] cache types cache type could not be determined",
+            ))
+        }
     };
 
-    let map_error = &args.map_error;
-    let map_error = parse_str::<ExprClosure>(map_error).expect("unable to parse map_error block");
+    let await_store = if asyncness.is_some() && !args.disk {
+        quote! { .await }
+    } else {
+        quote! {}
+    };
+
+    // the errors of the store are either returned, mapped by `map_error`, or handled by
+    // the `on_error` policy
+    let map_error = args
+        .map_error
+        .as_ref()
+        .map(|map_error| {
+            parse_str::<ExprClosure>(map_error)
+                .map_err(|e| attr_error(format!("unable to parse map_error block: {}", e)))
+        })
+        .transpose()?;
+    let fn_path = quote! { concat!(module_path!(), "::", stringify!(#fn_ident)) };
+
+    // with `breaker_after`, the operations of the store are skipped while the circuit
+    // breaker is open, and their outcomes are recorded by the breaker otherwise
+    let breaker_secs = args.breaker_secs.unwrap_or(30);
+    let breaker = args.breaker_after.map(|breaker_after| {
+        let breaker_ident = Ident::new(&format!("{}_BREAKER", cache_ident), fn_ident.span());
+        let breaker_doc = format!(
+            "Circuit breaker of the store of the [`{}`] function.",
+            fn_ident
+        );
+        let breaker_static = quote! {
+            #[doc = #breaker_doc]
+            #visibility static #breaker_ident: ::cached::once_cell::sync::Lazy<::cached::circuit_breaker::CircuitBreaker> =
+                ::cached::once_cell::sync::Lazy::new(|| {
+                    ::cached::circuit_breaker::CircuitBreaker::new(
+                        #breaker_after,
+                        ::cached::instant::Duration::from_secs(#breaker_secs),
+                    )
+                });
+        };
+        (breaker_ident, breaker_static)
+    });
+    let breaker_static = match &breaker {
+        Some((_, breaker_static)) => breaker_static.clone(),
+        None => quote! {},
+    };
+    let guard_store = |operations: proc_macro2::TokenStream| match &breaker {
+        Some((breaker_ident, _)) => quote! { if #breaker_ident.allow() { #operations } },
+        None => quote! { { #operations } },
+    };
+    let observe_store = |operation: proc_macro2::TokenStream| match &breaker {
+        Some((breaker_ident, _)) => quote! { #breaker_ident.observe(#operation) },
+        None => operation,
+    };
+    let store_get = observe_store(quote! { cache.cache_get(&key)#await_store });
+    let store_set = |value: proc_macro2::TokenStream| {
+        observe_store(quote! { cache.cache_set(key, #value)#await_store })
+    };
+    let store_set_result = store_set(quote! { result.clone() });
+    let (get_error_arm, set_cache_result) = match &args.on_error {
+        Some(OnError::Recompute) | None => (
+            quote! { Err(_) => None },
+            quote! { let _ = #store_set_result; },
+        ),
+        Some(OnError::Panic) => (
+            quote! { Err(error) => panic!("cache store of `{}` failed: {:?}", #fn_path, error) },
+            quote! {
+                if let Err(error) = #store_set_result {
+                    panic!("cache store of `{}` failed: {:?}", #fn_path, error);
+                }
+            },
+        ),
+        Some(OnError::Log) => (
+            quote! {
+                Err(error) => {
+                    ::cached::proc_macro::report_store_error(#fn_path, &error);
+                    None
+                }
+            },
+            quote! {
+                if let Err(error) = #store_set_result {
+                    ::cached::proc_macro::report_store_error(#fn_path, &error);
+                }
+            },
+        ),
+    };
 
     // make the set cache and return cache blocks
     let (set_cache_block, return_cache_block) = {
-        let (set_cache_block, return_cache_block) = if args.with_cached_flag {
+        let (set_cache_block, return_cache_block) = if args.on_error.is_some() {
             (
-                if asyncness.is_some() && !args.disk {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.value.clone()).await.map_err(#map_error)?;
-                        }
-                    }
-                } else {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.value.clone()).map_err(#map_error)?;
-                        }
+                set_cache_result,
+                quote! { #record_hit return result.clone() },
+            )
+        } else if args.with_cached_flag {
+            let store_set_value = store_set(quote! { result.value.clone() });
+            (
+                quote! {
+                    if let Ok(result) = &result {
+                        #store_set_value.map_err(#map_error)?;
                     }
                 },
-                quote! { let mut r = ::cached::Return::new(result.clone()); r.was_cached = true; return Ok(r) },
+                quote! { #record_hit let mut r = ::cached::Return::new(result.clone()); r.was_cached = true; return Ok(r) },
             )
         } else {
             (
-                if asyncness.is_some() && !args.disk {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.clone()).await.map_err(#map_error)?;
-                        }
-                    }
-                } else {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.clone()).map_err(#map_error)?;
-                        }
+                quote! {
+                    if let Ok(result) = &result {
+                        #store_set_result.map_err(#map_error)?;
                     }
                 },
-                quote! { return Ok(result.clone()) },
+                quote! { #record_hit return Ok(result.clone()) },
             )
         };
-        (set_cache_block, return_cache_block)
+        (guard_store(set_cache_block), return_cache_block)
     };
 
-    let do_set_return_block = if asyncness.is_some() {
-        quote! {
-            // run the function and cache the result
-            async fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*).await;
-            let cache = &#cache_ident.get_or_init(init).await;
-            #set_cache_block
-            result
+    // with `l1_size`, the values are also cached in an in-memory L1 cache, checked before
+    // the store and filled by the hits of the store
+    let (l1_static, l1_get_return_block, set_cache_block, store_return_cache_block) = match args
+        .l1_size
+    {
+        Some(l1_size) => {
+            let l1_ident = Ident::new(&format!("{}_L1", cache_ident), fn_ident.span());
+            let l1_doc = format!("In-memory L1 cache of the [`{}`] function.", fn_ident);
+            let (l1_ty, l1_create) = match args.l1_time {
+                Some(l1_time) => (
+                    quote! { ::cached::TimedSizedCache<#cache_key_ty, #cache_value_ty> },
+                    quote! { ::cached::TimedSizedCache::with_size_and_lifespan(#l1_size, #l1_time) },
+                ),
+                None => (
+                    quote! { ::cached::SizedCache<#cache_key_ty, #cache_value_ty> },
+                    quote! { ::cached::SizedCache::with_size(#l1_size) },
+                ),
+            };
+            let l1_static = quote! {
+                #[doc = #l1_doc]
+                #visibility static #l1_ident: ::cached::once_cell::sync::Lazy<std::sync::Mutex<#l1_ty>> =
+                    ::cached::once_cell::sync::Lazy::new(|| std::sync::Mutex::new(#l1_create));
+            };
+            // the L1 lock is released before awaiting the store
+            let l1_get_return_block = quote! {
+                let l1_cached = {
+                    use cached::Cached;
+                    #l1_ident.lock().unwrap().cache_get(&key).cloned()
+                };
+                if let Some(result) = l1_cached {
+                    #return_cache_block
+                }
+            };
+            let l1_set = |value: proc_macro2::TokenStream| {
+                quote! {
+                    {
+                        use cached::CachedWrite;
+                        #l1_ident.lock().unwrap().cache_set(key.clone(), #value.clone());
+                    }
+                }
+            };
+            let l1_set_result = if args.on_error.is_some() {
+                l1_set(quote! { result })
+            } else if args.with_cached_flag {
+                let l1_set = l1_set(quote! { result.value });
+                quote! { if let Ok(result) = &result { #l1_set } }
+            } else {
+                let l1_set = l1_set(quote! { result });
+                quote! { if let Ok(result) = &result { #l1_set } }
+            };
+            let l1_promote = l1_set(quote! { result });
+            (
+                l1_static,
+                l1_get_return_block,
+                quote! { #l1_set_result #set_cache_block },
+                quote! { #l1_promote #return_cache_block },
+            )
         }
+        None => (quote! {}, quote! {}, set_cache_block, return_cache_block),
+    };
+    let (inner_fn, inner_call) = if asyncness.is_some() {
+        (
+            quote! { async fn inner(#inputs) #output #body },
+            quote! { inner(#(#input_names),*).await },
+        )
     } else {
+        (
+            quote! { fn inner(#inputs) #output #body },
+            quote! { inner(#(#input_names),*) },
+        )
+    };
+    let inner_call = quote! {{
+        let load_timer = ::cached::registry::LoadTimer::start();
+        let result = #inner_call;
+        load_timer.finished(&#cache_stats_ident);
+        result
+    }};
+    let cache_ref = if asyncness.is_some() {
+        quote! { &#cache_ident.get_or_init(init).await }
+    } else {
+        quote! { &#cache_ident }
+    };
+
+    // with `result_fallback`, the errors of the function are replaced by the expired value
+    // of the key, cached again
+    let fallback_block = if args.result_fallback {
         quote! {
-            // run the function and cache the result
-            fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*);
-            let cache = &#cache_ident;
-            #set_cache_block
-            result
+            let result = match (result, stale) {
+                (Err(_), Some(stale)) => Ok(stale),
+                (result, _) => result,
+            };
         }
+    } else {
+        quote! {}
     };
+    let gen_do_set_return_block = |fallback_block: proc_macro2::TokenStream| {
+        if asyncness.is_some() {
+            quote! {
+                // run the function and cache the result
+                async fn inner(#inputs) #output #body;
+                let result = #inner_call;
+                #fallback_block
+                let cache = &#cache_ident.get_or_init(init).await;
+                #set_cache_block
+                result
+            }
+        } else {
+            quote! {
+                // run the function and cache the result
+                fn inner(#inputs) #output #body;
+                let result = #inner_call;
+                #fallback_block
+                let cache = &#cache_ident;
+                #set_cache_block
+                result
+            }
+        }
+    };
+    // priming recomputes the value, returning the errors of the function
+    let do_set_return_block = gen_do_set_return_block(fallback_block);
+    let prime_do_set_return_block = gen_do_set_return_block(quote! {});
 
-    let signature_no_muts = get_mut_signature(signature);
+    let (do_set_return_block, prime_do_set_return_block) = match &args.validator {
+        Some(validator) => {
+            let validator = Ident::new(validator, fn_ident.span());
+            let revalidate_after = args.revalidate_after.unwrap_or(0);
+            let stale_get = match &breaker {
+                Some((breaker_ident, _)) => quote! {
+                    if #breaker_ident.allow() {
+                        #store_get.map_err(#map_error)?
+                    } else {
+                        None
+                    }
+                },
+                None => quote! { #store_get.map_err(#map_error)? },
+            };
+            let store_set_validated = store_set(quote! { validated.clone() });
+            let store_set_validated = guard_store(quote! {
+                #store_set_validated.map_err(#map_error)?;
+            });
+            let revalidate_block = |fresh_check: proc_macro2::TokenStream| {
+                quote! {
+                    let cache = #cache_ref;
+                    let stale = #stale_get;
+                    #fresh_check
+                    // run the function with the validator of the stale value, and cache the result
+                    let #validator = stale.as_ref().and_then(|stale| stale.validator.clone());
+                    #inner_fn
+                    let validated = match #inner_call? {
+                        ::cached::Revalidation::Modified { value, validator } => {
+                            ::cached::Validated::new(value, validator)
+                        }
+                        ::cached::Revalidation::NotModified => stale
+                            .expect("#[io_cached] function returned `Revalidation::NotModified` without a cached value")
+                            .revalidated(),
+                    };
+                    #store_set_validated
+                    Ok(validated.value)
+                }
+            };
+            (
+                revalidate_block(quote! {
+                    if let Some(stale) = &stale {
+                        if stale.is_fresh(#revalidate_after) {
+                            #record_hit
+                            return Ok(stale.value.clone());
+                        }
+                    }
+                    #record_miss
+                }),
+                revalidate_block(quote! {}),
+            )
+        }
+        None => (do_set_return_block, prime_do_set_return_block),
+    };
+
+    // the original function, run without the cache
+    let mut no_cache_sig = signature.clone();
+    no_cache_sig.ident = Ident::new(&format!("{}_no_cache", &fn_ident), fn_ident.span());
+    let mut signature_no_muts = get_mut_signature(signature);
+    if let Some(index) = validator_index {
+        signature_no_muts.inputs = std::mem::take(&mut signature_no_muts.inputs)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, input)| (i != index).then_some(input))
+            .collect();
+        signature_no_muts.output = public_output;
+    }
 
     // create a signature for the cache-priming function
     let prime_fn_ident = Ident::new(&format!("{}_prime_cache", &fn_ident), fn_ident.span());
@@ -396,6 +827,7 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
 
     // make cached static, cached function and prime cached function doc comments
     let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
+    let no_cache_fn_indent_doc = format!("Origin of the cached function [`{}`].", fn_ident);
     let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
     let cache_fn_doc_extra = format!(
         "This is a cached function that uses the [`{}`] cached static.",
@@ -413,36 +845,91 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
         }
     };
 
-    let async_cache_get_return = if asyncness.is_some() && !args.disk {
+    let async_cache_get_return = if args.result_fallback {
+        let store_get_expired = observe_store(quote! { cache.cache_get_expired(&key)#await_store });
         quote! {
-            if let Some(result) = cache.cache_get(&key).await.map_err(#map_error)? {
-                #return_cache_block
+            let (cached, expired) = #store_get_expired.map_err(#map_error)?;
+            if let (Some(result), false) = (&cached, expired) {
+                #store_return_cache_block
+            }
+            stale = cached;
+        }
+    } else if args.on_error.is_some() {
+        quote! {
+            let cached = match #store_get {
+                Ok(cached) => cached,
+                #get_error_arm
+            };
+            if let Some(result) = cached {
+                #store_return_cache_block
             }
         }
     } else {
         quote! {
-            if let Some(result) = cache.cache_get(&key).map_err(#map_error)? {
-                #return_cache_block
+            if let Some(result) = #store_get.map_err(#map_error)? {
+                #store_return_cache_block
             }
         }
     };
+    let store_get_return_block = guard_store(quote! {
+        // check if the result is cached
+        let cache = #cache_ref;
+        #async_cache_get_return
+    });
+    let cache_get_return_block = if validator_index.is_some() {
+        // the cached value is checked while revalidating it
+        quote! {}
+    } else if args.result_fallback {
+        quote! {
+            #l1_get_return_block
+            let mut stale = None;
+            #store_get_return_block
+            #record_miss
+        }
+    } else {
+        quote! {
+            #l1_get_return_block
+            #store_get_return_block
+            #record_miss
+        }
+    };
+
+    let stats_fn_ident = Ident::new(&format!("{}_cache_stats", &fn_ident), fn_ident.span());
+    let stats_fn_doc = format!(
+        "Returns the hit and miss statistics of the cached function [`{}`], without accessing its cache.",
+        fn_ident
+    );
+    let stats_fn = quote! {
+        #[doc = #stats_fn_doc]
+        #[allow(dead_code)]
+        #visibility fn #stats_fn_ident() -> ::cached::registry::CacheStatsSnapshot {
+            #cache_stats_ident.snapshot()
+        }
+    };
+
     // put it all together
     let expanded = if asyncness.is_some() {
         quote! {
             // Cached static
             #[doc = #cache_ident_doc]
             #visibility static #cache_ident: ::cached::async_sync::OnceCell<#cache_ty> = ::cached::async_sync::OnceCell::const_new();
+            #cache_stats
+            #l1_static
+            #breaker_static
+            // No cache function (origin of the cached function)
+            #[doc = #no_cache_fn_indent_doc]
+            #[allow(dead_code)]
+            #visibility #no_cache_sig #body
             // Cached function
             #(#attributes)*
             #visibility #signature_no_muts {
-                let init = || async { #cache_create };
+                let init = || async {
+                    #register_cache_stats
+                    #cache_create
+                };
                 #async_trait
                 let key = #key_convert_block;
-                {
-                    // check if the result is cached
-                    let cache = &#cache_ident.get_or_init(init).await;
-                    #async_cache_get_return
-                }
+                #cache_get_return_block
                 #do_set_return_block
             }
             // Prime cached function
@@ -450,28 +937,36 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
             #[allow(dead_code)]
             #visibility #prime_sig {
                 #async_trait
-                let init = || async { #cache_create };
+                let init = || async {
+                    #register_cache_stats
+                    #cache_create
+                };
                 let key = #key_convert_block;
-                #do_set_return_block
+                #prime_do_set_return_block
             }
+            #stats_fn
         }
     } else {
         quote! {
             // Cached static
             #[doc = #cache_ident_doc]
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<#cache_ty> = ::cached::once_cell::sync::Lazy::new(|| #cache_create);
+            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<#cache_ty> = ::cached::once_cell::sync::Lazy::new(|| {
+                #register_cache_stats
+                #cache_create
+            });
+            #cache_stats
+            #l1_static
+            #breaker_static
+            // No cache function (origin of the cached function)
+            #[doc = #no_cache_fn_indent_doc]
+            #[allow(dead_code)]
+            #visibility #no_cache_sig #body
             // Cached function
             #(#attributes)*
             #visibility #signature_no_muts {
                 use cached::IOCached;
                 let key = #key_convert_block;
-                {
-                    // check if the result is cached
-                    let cache = &#cache_ident;
-                    if let Some(result) = cache.cache_get(&key).map_err(#map_error)? {
-                        #return_cache_block
-                    }
-                }
+                #cache_get_return_block
                 #do_set_return_block
             }
             // Prime cached function
@@ -480,10 +975,11 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
             #visibility #prime_sig {
                 use cached::IOCached;
                 let key = #key_convert_block;
-                #do_set_return_block
+                #prime_do_set_return_block
             }
+            #stats_fn
         }
     };
 
-    expanded.into()
+    Ok(expanded)
 }
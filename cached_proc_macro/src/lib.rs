@@ -1,3 +1,4 @@
+mod cache_key;
 mod cached;
 mod helpers;
 mod io_cached;
@@ -10,34 +11,192 @@ use proc_macro::TokenStream;
 ///
 /// # Attributes
 /// - `name`: (optional, string) specify the name for the generated cache, defaults to the function name uppercase.
+///   A named cache also gets an accessor named after it in lowercase, e.g. `geo_cache() -> &'static Mutex<C>` for
+///   `name = "GEO_CACHE"`, returning the cache typed with its cache type `C`, so that other modules can inspect it.
+///   The accessor is not generated with `instance` or `in_impl`.
+/// - `vis`: (optional, string) specify the visibility of the generated cache and of its accessor, e.g.
+///   `vis = "pub(crate)"`, defaults to the visibility of the function. Not supported with `instance` or `in_impl`.
 /// - `size`: (optional, usize) specify an LRU max size, implies the cache type is a `SizedCache` or `TimedSizedCache`.
+/// - `size_bytes`: (optional, usize) specify a budget of bytes of the cached keys and values, evicting the least recently
+///   used ones when over budget, e.g. `size_bytes = 67108864`. Implies the cache type is a `WeightedCache`, weighing the
+///   keys and values by their `cached::MemSize`. Cannot be combined with `unbound`, `size`, `time`, `ty` or `hasher`.
+/// - `weigher`: (optional, string expr) with `size_bytes`, a function weighing a cached key and value in bytes instead
+///   of their `MemSize`, e.g. `weigher = "|_, image: &Image| image.pixels.len()"`.
 /// - `time`: (optional, u64) specify a cache TTL in seconds, implies the cache type is a `TimedCache` or `TimedSizedCache`.
 /// - `time_refresh`: (optional, bool) specify whether to refresh the TTL on cache hits.
-/// - `sync_writes`: (optional, bool) specify whether to synchronize the execution of writing of uncached values.
+/// - `sync_writes`: (optional, bool or string) specify whether to synchronize the execution of writing of uncached values.
+///   `true` holds the lock of the cache while computing a value, serializing all the callers. `"by_key"` only serializes
+///   the callers computing the same key, see `cached::key_locks`, and generates a `{NAME}_KEY_LOCKS` static.
+///   `"single_flight"`, for async functions, lets the first caller missing a key compute it while the others await its
+///   result, cached or not, and generates a `{NAME}_FLIGHTS` static. The output of the function must be `Clone`.
 /// - `ty`: (optional, string type) The cache store type to use. Defaults to `UnboundCache`. When `unbound` is
 ///   specified, defaults to `UnboundCache`. When `size` is specified, defaults to `SizedCache`.
 ///   When `time` is specified, defaults to `TimedCached`.
 ///   When `size` and `time` are specified, defaults to `TimedSizedCache`. When `ty` is
 ///   specified, `create` must also be specified.
 /// - `create`: (optional, string expr) specify an expression used to create a new cache store, e.g. `create = r##"{ CacheType::new() }"##`.
+/// - `hasher`: (optional, string type) the `BuildHasher` of the keys of the implied cache store, created with its
+///   `Default` implementation, e.g. `hasher = "rustc_hash::FxBuildHasher"`. Cannot be combined with `ty`, whose
+///   `create` expression can use the `*_and_hasher` constructors of the stores instead.
 /// - `key`: (optional, string type) specify what type to use for the cache key, e.g. `key = "u32"`.
-///    When `key` is specified, `convert` must also be specified.
+///   When `key` is specified, `convert` must also be specified. By default, the key is made of the arguments, a
+///   single `&str` or `&[T]` argument being cached as a `String` or `Vec<T>` and looked up without being copied. The
+///   other borrowed arguments, whatever their lifetimes, are made owned with `ToOwned` (`&str` as `String`, `&[T]` as
+///   `Vec<T>`, `&T` as `T::Owned`), except `'static` ones; arguments borrowing data of their own, such as `Query<'a>`,
+///   need a `convert`ed key. An `impl AsRef<T>` argument is keyed by the owned value it is `AsRef` of (`impl AsRef<str>`
+///   as `String`, `impl AsRef<Path>` as `PathBuf`), other `impl Trait` arguments need a `convert`ed key.
 /// - `convert`: (optional, string expr) specify an expression used to convert function arguments to a cache
 ///   key, e.g. `convert = r##"{ format!("{}:{}", arg1, arg2) }"##`. When `convert` is specified,
 ///   `key` or `ty` must also be set.
+/// - `ignore`: (optional, list of arguments) leave the listed arguments out of the cache key, e.g. `ignore(conn, logger)`,
+///   so that they need not implement `Hash`, `Eq` or `Clone`. Cannot be combined with `convert`.
+/// - `prime_concurrency`: (optional, usize) generate `{fn}_prime_many`, calling the function for many arguments at once,
+///   e.g. to warm the cache up, `prime_concurrency` calls running at a time: in scoped threads, the arguments having to be
+///   `Send`, or as concurrent futures of `async` functions. Not supported with methods and generic functions.
+/// - `key_map`: (optional, string closure) normalize the keys before they are looked up or inserted, taking a reference
+///   to the key and returning the key to use, e.g. `key_map = "|s: &str| s.trim().to_ascii_lowercase()"`, so that
+///   arguments differing only in case or whitespace share a value. Applied to the `convert`ed key, if any.
+/// - `hash_key`: (optional, bool) key the cache by a 128-bit hash of the key, see `cached::cache_key::hash_key`, rather
+///   than by the key itself, so that large keys such as URLs or queries are not kept in memory. Keys sharing a hash,
+///   at the odds of a 128-bit hash collision, share a value, the collisions not being checked. A custom `ty` must have `u128` keys, and the values cannot
+///   be removed by key prefix.
 /// - `result`: (optional, bool) If your function returns a `Result`, only cache `Ok` values returned by the function.
 /// - `option`: (optional, bool) If your function returns an `Option`, only cache `Some` values returned by the function.
+/// - `cache_if`: (optional, string expr) a predicate taking a reference to the value to cache, only the values for which
+///   it returns `true` are cached, e.g. `cache_if = r##"|response: &Response| response.status == 200"##`. With `result`
+///   or `option`, it is called on the `Ok` or `Some` values.
+/// - `cache_unless`: (optional, string expr) a predicate taking a reference to the value to cache, the values for which
+///   it returns `true` are not cached, e.g. `cache_unless = r##"|ids: &Vec<u32>| ids.is_empty()"##`. With `result` or
+///   `option`, it is called on the `Ok` or `Some` values. When `cache_if` is also set, the cached values must match
+///   `cache_if` and not `cache_unless`.
+/// - `cache_policy`: (optional, bool) the function returns a `cached::CachePolicy<T>`, or a `Result` or `Option` of one
+///   with `result` or `option`, setting the TTL of each value it returns. The generated function returns `T` in its
+///   place, and the values are cached as `cached::proc_macro::Expiring<T>`, so a custom `ty` must hold these.
+///   Expired values are recomputed when they are looked up.
+/// - `cache_errors`: (optional, bool) with `result`, cache the `Err` values too rather than only the `Ok` ones, the
+///   whole `Result` being cached, so that an error is returned again without calling the function until it expires.
+///   The error type must be `Clone`, and the `cache_if` and `cache_unless` predicates take a reference to the `Result`.
+///   Not supported with `cache_policy`, `wrap`, `store_as`, `with_cached_flag` or `result_fallback`.
+/// - `err_time`: (optional, u64) with `cache_errors`, a TTL in seconds of the cached errors, e.g. `err_time = 10` with
+///   `time = 600` to retry an error after 10 seconds while the `Ok` values are cached for 10 minutes. The values are
+///   cached as `cached::proc_macro::Expiring<Result<T, E>>`, so a custom `ty` must hold these.
+/// - `wrap`: (optional, string) set to `"arc"` to cache the values in an `Arc`: the generated function returns `Arc<T>`
+///   (or a `Result` or `Option` of it with `result` or `option`) where the function returns `T`, so hits return a clone
+///   of the `Arc` rather than of the value, and `T` need not be `Clone`. A custom `ty` must hold `Arc<T>` values. The
+///   `cache_if` and `cache_unless` predicates still take a reference to `T`.
+/// - `store_as`: (optional, string closure) convert the value returned by the function into the value to cache, for
+///   functions returning values that cannot be cached such as `impl Iterator` or `Box<dyn Trait>`. The closure must declare
+///   its return type, the type of the cached values, e.g. `store_as = "|evens| -> Vec<u32> { evens.collect() }"`. With
+///   `result` or `option`, it converts the `Ok` or `Some` values. Requires `from_store`. The `cache_if` and `cache_unless`
+///   predicates take a reference to the cached value.
+/// - `from_store`: (optional, string closure) convert a reference to a cached value into the value to return, e.g.
+///   `from_store = "|evens: &Vec<u32>| evens.clone().into_iter()"`. Requires `store_as`.
+/// - `instrument`: (optional, bool) emit `tracing` events, at the debug level, for the cache hits and misses, the loads
+///   of missing values along with their duration (`elapsed`), and the evictions of values by the values set. The events
+///   of a call are children of a `cached` span recording the `function` and the `key` (which must implement `Debug`).
+///   Requires the `tracing` feature of `cached`.
 /// - `with_cached_flag`: (optional, bool) If your function returns a `cached::Return` or `Result<cached::Return, E>`,
 ///   the `cached::Return.was_cached` flag will be updated when a cached value is returned.
 /// - `result_fallback`: (optional, bool) If your function returns a `Result` and it fails, the cache will instead refresh the recently expired `Ok` value.
+///   Supported by async functions too.
 ///   In other words, refreshes are best-effort - returning `Ok` refreshes as usual but `Err` falls back to the last `Ok`.
 ///   This is useful, for example, for keeping the last successful result of a network operation even during network disconnects.
 ///   *Note*, this option requires the cache type implements `CloneCached`.
+/// - `timeout_ms`: (optional, u64) bound the computation of a missing value of an async function, in milliseconds.
+///   On timeout the computation is cancelled and the function returns `Err(cached::proc_macro::TimeoutError.into())`,
+///   so the error type must implement `From<TimeoutError>`. Requires `result = true`.
+/// - `timeout_fallback`: (optional, string) what to return on timeout, `"error"` (the default) or `"stale"`: the expired
+///   value of the key if the cache still holds it, cached again like the values of `result_fallback`, else the
+///   `TimeoutError`. `"stale"` requires the cache type implements `CloneCached`, e.g. `time` caches, and cannot be
+///   combined with `result_fallback` or `sync_writes`.
+/// - `warm_size`: (optional, usize) the number of values the cache is expected to hold once the application is warmed up.
+///   Generates a `{NAME}_WARMTH` static, registered with `cached::registry`, reporting the warmth of the cache.
+/// - `quarantine_after`: (optional, u32) the number of consecutive errors after which a key is quarantined: calls for the key
+///   return the last error without calling the function until the quarantine is over. Requires `result = true`,
+///   the error type must implement `Clone`, and the cache key type too, the quarantine holding the keys of the failures.
+///   Generates a `{NAME}_QUARANTINE` static, see `cached::quarantine`.
+/// - `quarantine_secs`: (optional, u64) the duration of the quarantine of a key, in seconds. Defaults to 60.
+/// - `config_epoch`: (optional, bool) include the process-wide config epoch in the cache keys, as `(u64, K)`, so that
+///   `cached::registry::bump_config_epoch` invalidates the cached values. The first value cached in a new epoch clears
+///   the values of the previous ones. A custom `ty` must use `(u64, K)` keys.
+/// - `invalidation`: (optional, bool) register the invalidation of the values of the arguments given as a JSON array with
+///   `cached::registry`, see `cached::registry::invalidate`. Requires the `key_invalidation` feature of `cached`, and
+///   arguments implementing `serde::Deserialize`, or whose owned types do for borrowed arguments, e.g. `String` for
+///   `&str` and `Vec<T>` for `&[T]`. Generates a `{NAME}_INVALIDATION` static.
+/// - `instance`: (optional, string) cache a `&self` method in the named field of its instance rather than in a
+///   static, so that each instance has its own cache. The receiver is not part of the key. The field must be a
+///   `std::sync::Mutex` of the cache type (a `cached::async_sync::Mutex` for async methods), which the generated
+///   `{fn}_new_cache` associated function creates. No statics are generated, so `warm_size`, `quarantine_after`,
+///   `invalidation` and `sync_writes = "by_key"` or `"single_flight"` are not supported, and neither are the lock
+///   statistics.
+/// - `store_fn`: (optional, string function) use the cache provided by a function called with no arguments rather than
+///   a static, e.g. the cache of the current tenant or one held by the state of the application. It returns a reference to,
+///   or an `Arc` of, a `std::sync::Mutex` of the cache type (a `cached::async_sync::Mutex` for async functions), which the
+///   generated `{fn}_new_cache` function creates, and is called each time the cache is locked. No statics are generated,
+///   with the same limitations as `instance`.
+/// - `thread_local`: (optional, bool) give each thread its own cache, in a `thread_local!` static, rather than sharing a
+///   cache locked by a mutex, e.g. for CPU-bound functions called by a pool of worker threads. The cache-management
+///   functions manage the cache of the calling thread, while the statistics are those of all the threads. Not supported
+///   with `async` functions, `instance`, `store_fn`, `associated`, `warm_size`, `invalidation`, `sync_writes` or
+///   `prime_concurrency`, and the accessor of a named cache is not generated.
+/// - `rw_lock`: (optional, bool) lock the cache with a `RwLock` rather than a mutex, looking values up with the read lock
+///   through `cached::CachedShared` so that concurrent hits do not wait for each other, and taking the write lock to set
+///   the values missed. Requires a cache implementing `CachedShared`, such as the default unbounded one or a `time` one,
+///   not supported with `size`, `size_bytes`, `time_refresh`, `instance`, `store_fn` or `thread_local`.
+/// - `in_impl`: (optional, bool) declare the cache static and the other generated statics in the body of the function
+///   rather than next to it, so that methods of trait impls, which cannot have companion items, can be cached. The
+///   receiver of a method is not part of the key. The `{fn}_no_cache` function and the cache-management functions are not
+///   generated, and the cache types cannot use `Self` or generic parameters. Methods of `#[async_trait]` impls are supported.
+/// - `associated`: (optional, bool) cache an associated function or a method of an inherent `impl` block, the generated
+///   functions (`{fn}_no_cache`, `{fn}_prime_cache`, the cache-management functions and the accessor of a named cache)
+///   being associated functions of the type too, e.g. `Geo::locate_cache_clear()`. The receiver of a method is not part
+///   of the key. Statics cannot be items of an `impl` block, so the cache and statistics statics are declared in the body
+///   of a hidden associated function, and the cache types cannot use `Self` or the generic parameters of the `impl`.
+///   `warm_size`, `quarantine_after`, `invalidation` and `sync_writes = "by_key"` or `"single_flight"` are not supported.
+///
+/// ## Cache-management functions
+/// Next to the cached function `{fn}`, the following functions are generated, taking the same arguments
+/// so that their callers need not know the key type nor lock the cache static:
+/// - `{fn}_no_cache(args)`: the original function, bypassing the cache, except for methods of trait impls (`in_impl`).
+/// - `{fn}_prime_cache(args)`: call the function and cache its result, even if a value is cached.
+/// - `{fn}_cache_remove(args) -> Option<V>`: remove the value cached for the arguments, returning it.
+/// - `{fn}_cache_clear()`: remove all the cached values.
+/// - `{fn}_cache_remove_prefix(prefix: &str) -> usize`: with string keys (`String`, `&str`, `Arc<str>`, ...), remove
+///   the values whose keys start with `prefix`, returning their number, e.g. all the keys `user:42:*` of a user. Uses
+///   `CachedWrite::cache_remove_where`, and panics if a custom cache type does not support it.
+/// - `{fn}_prime_many(args: impl IntoIterator<Item = Args>)`: with `prime_concurrency`, call the function for each of
+///   the arguments, a tuple of them if it takes several, caching the values not already cached.
+///
+/// They are `async` when the cached function is.
+///
+/// ## Functions returning futures
+/// Non-async functions returning a `Pin<Box<dyn Future<Output = T>>>` or an `impl Future<Output = T>` are cached
+/// like `async` functions returning `T`: they return a future looking up the cache and awaiting the future of the
+/// function on a miss. Their cache-management functions are `async`.
+///
+/// ## Generic functions
+/// The values of all the type parameters of a generic function are cached in the same static: the `TypeId`s of the
+/// type parameters are part of the key, as `([TypeId; N], K)`, and the values are cached as `Box<dyn Any + Send>`,
+/// downcast when returned. The type parameters must therefore be `'static`, and the values `Clone + Send`. The
+/// arguments cannot depend on the type parameters unless `key` and `convert` are set, and `{fn}_cache_clear()`
+/// clears the values of all the type parameters. `cache_policy`, `result_fallback`, `invalidation` and const
+/// parameters are not supported.
 ///
 /// ## Note
 /// The `ty`, `create`, `key`, and `convert` attributes must be in a `String`
 /// This is because darling, which is used for parsing the attributes, does not support directly parsing
 /// attributes into `Type`s or `Block`s.
+///
+/// ## Lock statistics
+/// A `{NAME}_LOCK_STATS` static is generated next to the cache static and registered with
+/// `cached::registry`. It records lock wait and hold times when the `lock_stats` feature of `cached` is enabled.
+///
+/// ## Cache statistics
+/// A `{NAME}_CACHE_STATS` static is generated next to the cache static and registered with `cached::registry`.
+/// It counts the hits, misses and evictions of the function and records the size of the cache in atomic counters,
+/// which the generated `{fn}_cache_stats()` function returns without locking the cache. With the `metrics` feature of
+/// `cached`, they are also reported through the `metrics` crate along with the time taken to compute the missing
+/// values, see `cached::registry`. They are not generated with `instance`.
 #[proc_macro_attribute]
 pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
     cached::cached(args, input)
@@ -51,10 +210,39 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
 /// - `name`: (optional, string) specify the name for the generated cache, defaults to the function name uppercase.
 /// - `time`: (optional, u64) specify a cache TTL in seconds, implies the cache type is a `TimedCached` or `TimedSizedCache`.
 /// - `sync_writes`: (optional, bool) specify whether to synchronize the execution of writing of uncached values.
+///   Async functions always synchronize it, see below.
 /// - `result`: (optional, bool) If your function returns a `Result`, only cache `Ok` values returned by the function.
 /// - `option`: (optional, bool) If your function returns an `Option`, only cache `Some` values returned by the function.
 /// - `with_cached_flag`: (optional, bool) If your function returns a `cached::Return` or `Result<cached::Return, E>`,
 ///   the `cached::Return.was_cached` flag will be updated when a cached value is returned.
+/// - `stale_while_refresh`: (optional, bool) once the `time` elapsed, keep returning the expired value while a single
+///   caller refreshes it in the background, in a new thread or, for async functions, a task of the async runtime (see
+///   `cached::runtime`). Requires `time`, and arguments that can be moved to the thread or task (`Send + 'static`).
+///   Generates a `{NAME}_REFRESHING` static.
+/// - `refresh_every`: (optional, u64) once the value is first computed, recompute it every `refresh_every` seconds in
+///   the background, so that callers always get the cached value without waiting for a refresh. A refresh returning
+///   an `Err` or `None` keeps the previous value. Requires a function without arguments, and cannot be combined with
+///   `time`. Generates a `{NAME}_REFRESHER` static starting the refresher once.
+/// - `spawn`: (optional, string) with `refresh_every`, where the refresher runs: `"thread"`, the default and only option
+///   of functions, for a new thread, `"task"`, the default of async functions, for a task spawned by the `spawner`
+///   or the async runtime, or `"tokio"` for a task of the `tokio` runtime of the first call, whatever the spawner
+///   and runtime features.
+/// - `spawner`: (optional, string expr) with `stale_while_refresh` or `refresh_every`, a `cached::runtime::Spawn` spawning
+///   the background tasks of an async function rather than the async runtime or the spawner set by
+///   `cached::runtime::set_spawner`, e.g. `spawner = "MAINTENANCE.handle()"` for the `Handle` of a `tokio` runtime.
+///
+/// ## Initialization
+/// Concurrent first calls of an async function all await a single execution of the function, which holds
+/// the async write lock of the cache while it runs, instead of each running the function. If that call is
+/// cancelled, or returns an `Err` or `None` not cached, the next caller waiting runs the function.
+///
+/// A `{fn_name}_initialized() -> bool` function is generated next to `{fn_name}_prime_cache`, returning
+/// whether a value is cached (and not expired), without waiting for a value being computed, and a
+/// `{fn_name}_no_cache()` function, the original function, bypassing the cache.
+///
+/// ## Lock statistics
+/// A `{NAME}_LOCK_STATS` static is generated next to the cache static and registered with
+/// `cached::registry`. It records lock wait and hold times when the `lock_stats` feature of `cached` is enabled.
 #[proc_macro_attribute]
 pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
     once::once(args, input)
@@ -66,9 +254,22 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
 /// # Attributes
 /// - `map_error`: (string, expr closure) specify a closure used to map any IO-store errors into
 ///   the error type returned by your function.
+/// - `on_error`: (string, one of `"recompute"`, `"panic"` or `"log"`) instead of `map_error`, cache a function
+///   that does not return a `Result`: its whole return type is cached, and the errors of the store are handled by
+///   this policy. `"recompute"` ignores them, computing the value as if it was not cached, `"panic"` panics with them,
+///   and `"log"` reports them (as `tracing` warnings with the `tracing` feature, on stderr otherwise) before
+///   computing the value. `"panic"` and `"log"` require the `Error` of the store to implement `Debug`. Cannot be
+///   combined with `with_cached_flag` or `validator`.
 /// - `name`: (optional, string) specify the name for the generated cache, defaults to the function name uppercase.
 /// - `redis`: (optional, bool) default to a `RedisCache` or `AsyncRedisCache`
+/// - `config_epoch`: (optional, bool) prefix the cache keys with the process-wide config epoch, formatting them as
+///   `String`s, so that `cached::registry::bump_config_epoch` invalidates the cached values. A custom `ty` must use
+///   `String` keys.
+/// - `shared_connection`: (optional, bool) with `redis` on an async function, share one connection per server
+///   between all the `AsyncRedisCache`s built with a shared connection, see `AsyncRedisCacheBuilder::set_shared_connection`.
 /// - `disk`: (optional, bool) use a `DiskCache`, this must be set to true even if `type` and `create` are specified.
+/// - `disk_database`: (optional, string) with `disk`, store the values in a tree of the database of this name shared
+///   by the functions setting it, instead of in a database of their own, see `DiskCacheBuilder::set_database`.
 /// - `time`: (optional, u64) specify a cache TTL in seconds, implies the cache type is a `TimedCached` or `TimedSizedCache`.
 /// - `time_refresh`: (optional, bool) specify whether to refresh the TTL on cache hits.
 /// - `ty`: (optional, string type) explicitly specify the cache store type to use.
@@ -79,18 +280,61 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
 ///   recommended that you specify a prefix you're sure will be unique.
 /// - `create`: (optional, string expr) specify an expression used to create a new cache store, e.g. `create = r##"{ CacheType::new() }"##`.
 /// - `key`: (optional, string type) specify what type to use for the cache key, e.g. `ty = "TimedCached<u32, u32>"`.
-///    When `key` is specified, `convert` must also be specified.
+///   When `key` is specified, `convert` must also be specified.
 /// - `convert`: (optional, string expr) specify an expression used to convert function arguments to a cache
 ///   key, e.g. `convert = r##"{ format!("{}:{}", arg1, arg2) }"##`. When `convert` is specified,
 ///   `key` or `ty` must also be set.
 /// - `with_cached_flag`: (optional, bool) If your function returns a `cached::Return` or `Result<cached::Return, E>`,
 ///   the `cached::Return.was_cached` flag will be updated when a cached value is returned.
+/// - `validator`: (optional, string) the name of an `Option<String>` argument receiving the validator (e.g. an `ETag`)
+///   of the cached value, so that the function can revalidate it (e.g. with `If-None-Match`). The function must
+///   return a `Result<cached::Revalidation<T>, E>`: `Revalidation::NotModified` keeps the cached value and renews its TTL.
+///   The argument is not part of the cache key nor of the generated function, which returns a `Result<T, E>`.
+///   Values are stored as `cached::Validated<T>`, which a custom `ty` must use.
+/// - `revalidate_after`: (optional, u64) with `validator`, return cached values validated less than `revalidate_after`
+///   seconds ago without revalidating them. Defaults to 0: values are revalidated on every call.
+/// - `l1_size`: (optional, usize) layer an in-memory `SizedCache` of this size in front of the store, in a
+///   `{NAME}_L1` static: it is checked before the store, and filled by the values computed and by the hits of the store.
+///   Keys must be `Clone`. Cannot be combined with `validator`.
+/// - `l1_time`: (optional, u64) with `l1_size`, use a `TimedSizedCache` keeping the values in memory for this many
+///   seconds instead, e.g. so that they are not served longer than the values of the store.
+/// - `breaker_after`: (optional, u32) open a circuit breaker after this many consecutive failures of the store,
+///   skipping the store and computing the values as if they were not cached until its cooldown is over, see
+///   `cached::circuit_breaker`. The breaker is a `{NAME}_BREAKER` static.
+/// - `breaker_secs`: (optional, u64) with `breaker_after`, the cooldown of the open circuit breaker, in seconds.
+///   Defaults to 30.
+/// - `result_fallback`: (optional, bool) when the function returns an `Err`, return the expired value of the key
+///   instead, if the store still holds it, and cache it again. The store looks it up with `cache_get_expired`, which
+///   only returns the expired values of the stores keeping them, such as `DiskCache`. Not supported with `on_error`,
+///   `with_cached_flag` or `validator`.
 ///
 /// ## Note
 /// The `ty`, `create`, `key`, and `convert` attributes must be in a `String`
 /// This is because darling, which is used for parsing the attributes, does not support directly parsing
 /// attributes into `Type`s or `Block`s.
+///
+/// ## Cache statistics
+/// A `{NAME}_CACHE_STATS` static is generated next to the cache static and registered with `cached::registry`.
+/// It counts the hits and misses of the function, which the generated `{fn}_cache_stats()` function returns without
+/// accessing the cache store. The size of the store and its evictions are not recorded. With the `metrics` feature of
+/// `cached`, the statistics are also reported through the `metrics` crate, see `cached::registry`.
+///
+/// ## Uncached function
+/// A `{fn}_no_cache(args)` function is generated next to `{fn}_prime_cache`, the original function, bypassing the
+/// cache. With `validator`, it takes the validator argument too.
 #[proc_macro_attribute]
 pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
     io_cached::io_cached(args, input)
 }
+
+/// Derive `cached::CacheKey` for a struct or an enum whose fields implement it, encoding the name of the type (and
+/// of the variant) along with its fields, e.g. `Query{city="Oslo",limit=Some(10)}` or `Region::City("Oslo")`. The
+/// type parameters must implement `CacheKey`, and the type `Hash` and `Eq`.
+///
+/// # Attributes
+/// - `display`: (optional, bool) also implement `Display` with the encoding, for the stores naming the keys with
+///   `Display`, e.g. `#[cache_key(display)]`.
+#[proc_macro_derive(CacheKey, attributes(cache_key))]
+pub fn cache_key(input: TokenStream) -> TokenStream {
+    cache_key::cache_key(input)
+}
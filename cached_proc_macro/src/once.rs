@@ -2,9 +2,10 @@ use crate::helpers::*;
 use darling::ast::NestedMeta;
 use darling::FromMeta;
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
 use syn::spanned::Spanned;
-use syn::{parse_macro_input, Ident, ItemFn, ReturnType};
+use syn::{parse_macro_input, parse_str, Expr, Ident, ItemFn, ReturnType};
 
 #[derive(FromMeta)]
 struct OnceMacroArgs {
@@ -20,6 +21,14 @@ struct OnceMacroArgs {
     option: bool,
     #[darling(default)]
     with_cached_flag: bool,
+    #[darling(default)]
+    stale_while_refresh: bool,
+    #[darling(default)]
+    refresh_every: Option<u64>,
+    #[darling(default)]
+    spawn: Option<String>,
+    #[darling(default)]
+    spawner: Option<String>,
 }
 
 pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
@@ -36,7 +45,13 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
         }
     };
     let input = parse_macro_input!(input as ItemFn);
+    expand(args, input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
 
+// the misuses of the attributes are reported as errors on the attribute
+fn expand(args: OnceMacroArgs, input: ItemFn) -> syn::Result<TokenStream2> {
     // pull out the parts of the input
     let mut attributes = input.attrs;
     let visibility = input.vis;
@@ -50,7 +65,7 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
     let asyncness = signature.asyncness;
 
     // pull out the names and types of the function inputs
-    let input_names = get_input_names(&inputs);
+    let input_names = get_input_names(&inputs)?;
 
     // pull out the output type
     let output_ty = match &output {
@@ -65,10 +80,10 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
     let output_type_display = output_ts.to_string().replace(' ', "");
 
     if check_with_cache_flag(args.with_cached_flag, output_string) {
-        return with_cache_flag_error(output_span, output_type_display);
+        return Err(with_cache_flag_error(output_span, output_type_display));
     }
 
-    let cache_value_ty = find_value_type(args.result, args.option, &output, output_ty);
+    let cache_value_ty = find_value_type(args.result, args.option, &output, output_ty)?;
 
     // make the cache identifier
     let cache_ident = match args.name {
@@ -103,7 +118,6 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
             } else {
                 quote! { return result.clone() }
             };
-            let return_cache_block = gen_return_cache_block(args.time, return_cache_block);
             (set_cache_block, return_cache_block)
         }
         (true, false) => {
@@ -126,7 +140,6 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
             } else {
                 quote! { return Ok(result.clone()) }
             };
-            let return_cache_block = gen_return_cache_block(args.time, return_cache_block);
             (set_cache_block, return_cache_block)
         }
         (false, true) => {
@@ -149,30 +162,105 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
             } else {
                 quote! { return Some(result.clone()) }
             };
-            let return_cache_block = gen_return_cache_block(args.time, return_cache_block);
             (set_cache_block, return_cache_block)
         }
-        _ => panic!("the result and option attributes are mutually exclusive"),
+        _ => {
+            return Err(attr_error(
+                "the result and option attributes are mutually exclusive",
+            ))
+        }
     };
+    let return_stale_block = return_cache_block.clone();
+    let return_cache_block = gen_return_cache_block(args.time, return_cache_block);
 
-    let set_cache_and_return = quote! {
-        #set_cache_block
-        result
+    if args.stale_while_refresh && args.time.is_none() {
+        return Err(attr_error(
+            "stale_while_refresh requires the time attribute to be set",
+        ));
+    }
+    if args.refresh_every.is_some() {
+        if args.time.is_some() {
+            return Err(attr_error(
+                "the refresh_every and time attributes are mutually exclusive",
+            ));
+        }
+        if !inputs.is_empty() {
+            return Err(attr_error(
+                "refresh_every requires a function without arguments",
+            ));
+        }
+    }
+    // where the refresher runs, a thread for functions and a task for async functions by default
+    let spawn = match (args.spawn.as_deref(), asyncness.is_some()) {
+        (Some(_), _) if args.refresh_every.is_none() => {
+            return Err(attr_error("spawn requires refresh_every to be set"))
+        }
+        (None, false) | (Some("thread"), false) => "thread",
+        (None, true) | (Some("task"), true) => "task",
+        (Some("tokio"), true) => "tokio",
+        (Some(spawn @ "task"), false) | (Some(spawn @ "tokio"), false) => {
+            return Err(attr_error(format!(
+                "spawn = \"{}\" requires an async function",
+                spawn
+            )))
+        }
+        (Some("thread"), true) => {
+            return Err(attr_error(
+                "spawn = \"thread\" requires a function that is not async",
+            ))
+        }
+        (Some(spawn), _) => {
+            return Err(attr_error(format!(
+                "unknown spawn `{}`, expected \"thread\", \"task\" or \"tokio\"",
+                spawn
+            )))
+        }
+    };
+
+    // the background tasks are spawned by the `spawner` of the function, if any
+    let (spawn_fn, spawner_arg) = match &args.spawner {
+        Some(_) if asyncness.is_none() => {
+            return Err(attr_error("spawner requires an async function"))
+        }
+        Some(_) if !args.stale_while_refresh && args.refresh_every.is_none() => {
+            return Err(attr_error(
+                "spawner requires stale_while_refresh or refresh_every to be set",
+            ))
+        }
+        Some(_) if spawn == "tokio" => return Err(attr_error("spawner requires spawn = \"task\"")),
+        Some(spawner) => {
+            let spawner = parse_str::<Expr>(spawner)
+                .map_err(|e| attr_error(format!("unable to parse spawner expression: {}", e)))?;
+            (
+                quote! { ::cached::async_sync::spawn_with },
+                quote! { &(#spawner), },
+            )
+        }
+        None => (quote! { ::cached::async_sync::spawn }, quote! {}),
     };
+
+    let (lock_stats_ident, lock_stats, register_lock_stats) =
+        gen_lock_stats(&cache_ident, &fn_ident, &visibility);
+
+    let refreshing_ident = Ident::new(&format!("{}_REFRESHING", cache_ident), cache_ident.span());
+
     let r_lock;
     let w_lock;
     let function_call;
+    let spawn_refresh;
     let ty;
     if asyncness.is_some() {
-        w_lock = quote! {
-            // try to get a write lock
-            let mut cached = #cache_ident.write().await;
-        };
+        w_lock = gen_timed_lock(
+            quote! { mut cached },
+            quote! { #cache_ident.write().await },
+            &lock_stats_ident,
+        );
 
-        r_lock = quote! {
-            // try to get a read lock
-            let mut cached = #cache_ident.read().await;
-        };
+        r_lock = gen_timed_lock(
+            quote! { mut cached },
+            quote! { #cache_ident.read().await },
+            &lock_stats_ident,
+        );
 
         function_call = quote! {
             // run the function and cache the result
@@ -180,19 +268,34 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
             let result = inner(#(#input_names),*).await;
         };
 
+        spawn_refresh = quote! {
+            async fn inner(#inputs) #output #body;
+            #spawn_fn(#spawner_arg async move {
+                let _refreshed = RefreshedOnDrop;
+                let result = inner(#(#input_names),*).await;
+                #w_lock
+                #set_cache_block
+            });
+        };
+
         ty = quote! {
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<::cached::async_sync::RwLock<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| ::cached::async_sync::RwLock::new(#cache_create));
+            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<::cached::async_sync::RwLock<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| {
+                #register_lock_stats
+                ::cached::async_sync::RwLock::new(#cache_create)
+            });
         };
     } else {
-        w_lock = quote! {
-            // try to get a lock first
-            let mut cached = #cache_ident.write().unwrap();
-        };
+        w_lock = gen_timed_lock(
+            quote! { mut cached },
+            quote! { #cache_ident.write().unwrap() },
+            &lock_stats_ident,
+        );
 
-        r_lock = quote! {
-            // try to get a read lock
-            let mut cached = #cache_ident.read().unwrap();
-        };
+        r_lock = gen_timed_lock(
+            quote! { mut cached },
+            quote! { #cache_ident.read().unwrap() },
+            &lock_stats_ident,
+        );
 
         function_call = quote! {
             // run the function and cache the result
@@ -200,27 +303,147 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
             let result = inner(#(#input_names),*);
         };
 
+        spawn_refresh = quote! {
+            fn inner(#inputs) #output #body;
+            std::thread::spawn(move || {
+                let _refreshed = RefreshedOnDrop;
+                let result = inner(#(#input_names),*);
+                #w_lock
+                #set_cache_block
+            });
+        };
+
         ty = quote! {
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<std::sync::RwLock<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| std::sync::RwLock::new(#cache_create));
+            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<std::sync::RwLock<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| {
+                #register_lock_stats
+                std::sync::RwLock::new(#cache_create)
+            });
         };
     }
 
+    // the refresher recomputing the value in the background is started by the first
+    // computation of the value
+    let (refresher, start_refresher) = match args.refresh_every {
+        Some(refresh_every) => {
+            let refresher_ident =
+                Ident::new(&format!("{}_REFRESHER", cache_ident), cache_ident.span());
+            let refresher_doc = format!(
+                "Starts the refresher of the [`{}`] cached static once.",
+                cache_ident
+            );
+            let refresh_loop = match spawn {
+                "task" => quote! {
+                    async fn inner() #output #body
+                    #spawn_fn(#spawner_arg async {
+                        loop {
+                            ::cached::async_sync::sleep(::std::time::Duration::from_secs(#refresh_every)).await;
+                            let result = inner().await;
+                            #w_lock
+                            #set_cache_block
+                        }
+                    });
+                },
+                "tokio" => quote! {
+                    async fn inner() #output #body
+                    ::cached::async_sync::spawn_tokio(async {
+                        loop {
+                            ::cached::async_sync::sleep(::std::time::Duration::from_secs(#refresh_every)).await;
+                            let result = inner().await;
+                            #w_lock
+                            #set_cache_block
+                        }
+                    });
+                },
+                _ => quote! {
+                    fn inner() #output #body
+                    std::thread::spawn(|| loop {
+                        std::thread::sleep(::std::time::Duration::from_secs(#refresh_every));
+                        let result = inner();
+                        #w_lock
+                        #set_cache_block
+                    });
+                },
+            };
+            (
+                quote! {
+                    #[doc = #refresher_doc]
+                    #visibility static #refresher_ident: std::sync::Once = std::sync::Once::new();
+                },
+                quote! {
+                    #refresher_ident.call_once(|| {
+                        #refresh_loop
+                    });
+                },
+            )
+        }
+        None => (quote! {}, quote! {}),
+    };
+    let set_cache_and_return = quote! {
+        #set_cache_block
+        #start_refresher
+        result
+    };
+
     let prime_do_set_return_block = quote! {
         #w_lock
         #function_call
         #set_cache_and_return
     };
 
-    let r_lock_return_cache_block = quote! {
-        {
-            #r_lock
-            if let Some(result) = &*cached {
-                #return_cache_block
-            }
+    let (refreshing, r_lock_return_cache_block) = match args.time {
+        Some(time) if args.stale_while_refresh => {
+            let refreshing_doc = format!(
+                "Whether the value of the [`{}`] cached static is being refreshed.",
+                cache_ident
+            );
+            let refreshing = quote! {
+                #[doc = #refreshing_doc]
+                #visibility static #refreshing_ident: std::sync::atomic::AtomicBool =
+                    std::sync::atomic::AtomicBool::new(false);
+            };
+            // return the cached value, even expired, the first caller finding it
+            // expired refreshing it in the background
+            let r_lock_return_cache_block = quote! {
+                {
+                    #r_lock
+                    if let Some(result) = &*cached {
+                        let (created_sec, result) = result;
+                        if now.duration_since(*created_sec).as_secs() >= #time
+                            && #refreshing_ident
+                                .compare_exchange(false, true, std::sync::atomic::Ordering::SeqCst, std::sync::atomic::Ordering::SeqCst)
+                                .is_ok()
+                        {
+                            // let a later call refresh the value if this refresh fails or panics
+                            struct RefreshedOnDrop;
+                            impl Drop for RefreshedOnDrop {
+                                fn drop(&mut self) {
+                                    #refreshing_ident.store(false, std::sync::atomic::Ordering::SeqCst);
+                                }
+                            }
+                            #spawn_refresh
+                        }
+                        #return_stale_block
+                    }
+                }
+            };
+            (refreshing, r_lock_return_cache_block)
         }
+        _ => (
+            quote! {},
+            quote! {
+                {
+                    #r_lock
+                    if let Some(result) = &*cached {
+                        #return_cache_block
+                    }
+                }
+            },
+        ),
     };
 
-    let do_set_return_block = if args.sync_writes {
+    // async functions always synchronize their writes: concurrent first callers await the
+    // write lock held by the one initializing the value, the lock being async
+    let do_set_return_block = if args.sync_writes || asyncness.is_some() {
         quote! {
             #r_lock_return_cache_block
             #w_lock
@@ -239,15 +462,32 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
         }
     };
 
+    let is_initialized = match args.time {
+        Some(time) => quote! {
+            cached.as_ref().is_some_and(|(created_sec, _)| created_sec.elapsed().as_secs() < #time)
+        },
+        None => quote! { cached.is_some() },
+    };
+
+    // the original function, run without the cache
+    let mut no_cache_sig = signature.clone();
+    no_cache_sig.ident = Ident::new(&format!("{}_no_cache", &fn_ident), fn_ident.span());
     let signature_no_muts = get_mut_signature(signature);
 
     let prime_fn_ident = Ident::new(&format!("{}_prime_cache", &fn_ident), fn_ident.span());
     let mut prime_sig = signature_no_muts.clone();
     prime_sig.ident = prime_fn_ident;
 
+    let initialized_fn_ident = Ident::new(&format!("{}_initialized", &fn_ident), fn_ident.span());
+
     // make cached static, cached function and prime cached function doc comments
     let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
+    let no_cache_fn_indent_doc = format!("Origin of the cached function [`{}`].", fn_ident);
     let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
+    let initialized_fn_doc = format!(
+        "Returns whether the cached function [`{}`] holds a value, without waiting for one being computed.",
+        fn_ident
+    );
     let cache_fn_doc_extra = format!(
         "This is a cached function that uses the [`{}`] cached static.",
         cache_ident
@@ -259,6 +499,13 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
         // Cached static
         #[doc = #cache_ident_doc]
         #ty
+        #lock_stats
+        #refreshing
+        #refresher
+        // No cache function (origin of the cached function)
+        #[doc = #no_cache_fn_indent_doc]
+        #[allow(dead_code)]
+        #visibility #no_cache_sig #body
         // Cached function
         #(#attributes)*
         #visibility #signature_no_muts {
@@ -272,7 +519,16 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
             let now = ::cached::instant::Instant::now();
             #prime_do_set_return_block
         }
+        // Initialized probe
+        #[doc = #initialized_fn_doc]
+        #[allow(dead_code)]
+        #visibility fn #initialized_fn_ident() -> bool {
+            match #cache_ident.try_read() {
+                Ok(cached) => #is_initialized,
+                Err(_) => false,
+            }
+        }
     };
 
-    expanded.into()
+    Ok(expanded)
 }
@@ -28,7 +28,7 @@ pub fn main() {
 
     // Inspect the cache
     {
-        use cached::Cached; // must be in scope to access cache
+        use cached::CachedRead; // must be in scope to access cache
 
         println!(" ** Cache info **");
         let cache = SLOW_FN.lock().unwrap();
@@ -31,7 +31,7 @@ pub fn main() {
 
     // Inspect the cache
     {
-        use cached::Cached; // must be in scope to access cache
+        use cached::CachedRead; // must be in scope to access cache
 
         println!("[cached] ** Cache info **");
         let cache = SLOW_FN.lock().unwrap();
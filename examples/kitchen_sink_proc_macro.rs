@@ -1,6 +1,6 @@
 use cached::proc_macro::cached;
 use cached::Return;
-use cached::{Cached, SizedCache, UnboundCache};
+use cached::{Cached, CachedRead, CachedWrite, SizedCache, UnboundCache};
 use std::cmp::Eq;
 use std::collections::HashMap;
 use std::hash::Hash;
@@ -90,6 +90,7 @@ impl<K: Hash + Eq, V> Cached<K, V> for MyCache<K, V> {
     {
         self.store.get(k)
     }
+
     fn cache_get_mut<Q>(&mut self, k: &Q) -> Option<&mut V>
     where
         K: std::borrow::Borrow<Q>,
@@ -97,9 +98,40 @@ impl<K: Hash + Eq, V> Cached<K, V> for MyCache<K, V> {
     {
         self.store.get_mut(k)
     }
+    fn cache_drain_where<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        drain: F,
+    ) -> impl Iterator<Item = (K, V)> {
+        self.store.cache_drain_where(drain)
+    }
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V {
         self.store.entry(k).or_insert_with(f)
     }
+}
+
+impl<K: Hash + Eq, V> CachedRead<K, V> for MyCache<K, V> {
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+
+    fn cache_peek<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store.get(k)
+    }
+
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.store.iter()
+    }
+}
+
+impl<K: Hash + Eq, V> CachedWrite<K, V> for MyCache<K, V> {
     fn cache_set(&mut self, k: K, v: V) -> Option<V> {
         self.store.insert(k, v)
     }
@@ -116,9 +148,6 @@ impl<K: Hash + Eq, V> Cached<K, V> for MyCache<K, V> {
     fn cache_reset(&mut self) {
         self.store = HashMap::with_capacity(self.capacity);
     }
-    fn cache_size(&self) -> usize {
-        self.store.len()
-    }
 }
 
 // Specify our custom cache and supply an instance to use
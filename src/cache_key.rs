@@ -0,0 +1,252 @@
+/*!
+Stable encodings of cache keys
+
+A `CacheKey` writes a string encoding of a key which depends only on its value, unlike
+its `Debug` or `Hash` output, so that it can name the values of stores shared by
+processes or kept across runs. `#[derive(CacheKey)]` implements it for structs and
+enums whose fields implement it, encoding the name of the type along with its fields:
+
+```rust
+use cached::proc_macro::cached;
+use cached::CacheKey;
+
+#[derive(CacheKey, Clone, Hash, PartialEq, Eq)]
+struct Query {
+    city: String,
+    limit: Option<u32>,
+}
+
+// `#[cache_key(display)]` also implements `Display` with the encoding, for the stores
+// naming the keys with `Display`
+#[derive(CacheKey, Clone, Hash, PartialEq, Eq)]
+#[cache_key(display)]
+enum Region {
+    City(String),
+    Country { code: String },
+}
+
+#[cached(key = "String", convert = r#"{ query.cache_key() }"#)]
+fn search(query: &Query) -> usize {
+    query.city.len()
+}
+
+assert_eq!(
+    Query { city: "Oslo".into(), limit: Some(10) }.cache_key(),
+    r#"Query{city="Oslo",limit=Some(10)}"#
+);
+assert_eq!(Region::City("Oslo".into()).to_string(), r#"Region::City("Oslo")"#);
+```
+
+Strings are quoted, escaping their quotes and backslashes, so that the encodings of
+different keys are different.
+
+The in-memory caches of keys too large to be kept, such as long URLs or queries, can
+instead be keyed by the 128-bit `hash_key` of their keys, with `#[cached(hash_key = true)]`.
+*/
+
+use once_cell::sync::Lazy;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::rc::Rc;
+use std::sync::Arc;
+
+#[cfg(feature = "proc_macro")]
+#[cfg_attr(docsrs, doc(cfg(feature = "proc_macro")))]
+pub use cached_proc_macro::CacheKey;
+
+/// A key with a stable string encoding
+pub trait CacheKey: Hash + Eq {
+    /// Append the encoding of the key to `out`
+    fn write_cache_key(&self, out: &mut String);
+
+    /// Return the encoding of the key
+    fn cache_key(&self) -> String {
+        let mut out = String::new();
+        self.write_cache_key(&mut out);
+        out
+    }
+
+    /// Return the encoding of the key as bytes
+    fn cache_key_bytes(&self) -> Vec<u8> {
+        self.cache_key().into_bytes()
+    }
+}
+
+/// The keys of the two halves of `hash_key`, random for each process
+static HASH_KEY_STATES: Lazy<(RandomState, RandomState)> =
+    Lazy::new(|| (RandomState::new(), RandomState::new()));
+
+/// Return a 128-bit hash of `key`, the same for equal keys within a process
+///
+/// The hash is made of two 64-bit SipHash hashes keyed by random keys of the process,
+/// so that keys sharing a hash cannot be found without knowing them. Any two keys then
+/// share a hash with a probability of about 2^-128, and some two of 2^32 keys of about
+/// 2^-65. The collisions are not checked: a cache keyed by the hashes of its keys rather
+/// than the keys themselves does not keep them, and returns the value of a key for any
+/// other key sharing its hash. The hash differs between processes, so it does not name
+/// persisted values.
+pub fn hash_key<K: Hash + ?Sized>(key: &K) -> u128 {
+    let (high, low) = &*HASH_KEY_STATES;
+    (u128::from(high.hash_one(key)) << 64) | u128::from(low.hash_one(key))
+}
+
+macro_rules! impl_cache_key_display {
+    ($($ty:ty),*) => {
+        $(
+            impl CacheKey for $ty {
+                fn write_cache_key(&self, out: &mut String) {
+                    out.push_str(&self.to_string());
+                }
+            }
+        )*
+    };
+}
+
+impl_cache_key_display!(bool, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl CacheKey for () {
+    fn write_cache_key(&self, out: &mut String) {
+        out.push_str("()");
+    }
+}
+
+fn write_escaped(out: &mut String, quote: char, chars: impl Iterator<Item = char>) {
+    out.push(quote);
+    for c in chars {
+        if c == quote || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push(quote);
+}
+
+impl CacheKey for char {
+    fn write_cache_key(&self, out: &mut String) {
+        write_escaped(out, '\'', std::iter::once(*self));
+    }
+}
+
+impl CacheKey for str {
+    fn write_cache_key(&self, out: &mut String) {
+        write_escaped(out, '"', self.chars());
+    }
+}
+
+impl CacheKey for String {
+    fn write_cache_key(&self, out: &mut String) {
+        self.as_str().write_cache_key(out);
+    }
+}
+
+impl<T: CacheKey + ?Sized> CacheKey for &T {
+    fn write_cache_key(&self, out: &mut String) {
+        (**self).write_cache_key(out);
+    }
+}
+
+impl<T: CacheKey + ?Sized> CacheKey for Box<T> {
+    fn write_cache_key(&self, out: &mut String) {
+        (**self).write_cache_key(out);
+    }
+}
+
+impl<T: CacheKey + ?Sized> CacheKey for Rc<T> {
+    fn write_cache_key(&self, out: &mut String) {
+        (**self).write_cache_key(out);
+    }
+}
+
+impl<T: CacheKey + ?Sized> CacheKey for Arc<T> {
+    fn write_cache_key(&self, out: &mut String) {
+        (**self).write_cache_key(out);
+    }
+}
+
+impl<T: CacheKey> CacheKey for Option<T> {
+    fn write_cache_key(&self, out: &mut String) {
+        match self {
+            Some(value) => {
+                out.push_str("Some(");
+                value.write_cache_key(out);
+                out.push(')');
+            }
+            None => out.push_str("None"),
+        }
+    }
+}
+
+impl<T: CacheKey> CacheKey for [T] {
+    fn write_cache_key(&self, out: &mut String) {
+        out.push('[');
+        for (i, value) in self.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            value.write_cache_key(out);
+        }
+        out.push(']');
+    }
+}
+
+impl<T: CacheKey> CacheKey for Vec<T> {
+    fn write_cache_key(&self, out: &mut String) {
+        self.as_slice().write_cache_key(out);
+    }
+}
+
+macro_rules! impl_cache_key_tuple {
+    ($first:ident $($name:ident)*) => {
+        impl<$first: CacheKey, $($name: CacheKey),*> CacheKey for ($first, $($name,)*) {
+            #[allow(non_snake_case)]
+            fn write_cache_key(&self, out: &mut String) {
+                let ($first, $($name,)*) = self;
+                out.push('(');
+                $first.write_cache_key(out);
+                $(
+                    out.push(',');
+                    $name.write_cache_key(out);
+                )*
+                out.push(')');
+            }
+        }
+    };
+}
+
+impl_cache_key_tuple!(A);
+impl_cache_key_tuple!(A B);
+impl_cache_key_tuple!(A B C);
+impl_cache_key_tuple!(A B C D);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        assert_eq!(42u32.cache_key(), "42");
+        assert_eq!((-1i8, true).cache_key(), "(-1,true)");
+        assert_eq!(r#"a "b" \c"#.cache_key(), r#""a \"b\" \\c""#);
+        assert_eq!('\''.cache_key(), r"'\''");
+        assert_eq!(
+            vec![Some("a".to_string()), None].cache_key(),
+            r#"[Some("a"),None]"#
+        );
+        assert_eq!(Arc::new(("a", 1u8)).cache_key_bytes(), br#"("a",1)"#);
+        // quoting keeps the encodings of different keys different
+        assert_ne!(("a,b", "c").cache_key(), ("a", "b,c").cache_key());
+    }
+
+    #[test]
+    fn hashes() {
+        let url = "https://example.com/search?q=cached".to_string();
+        assert_eq!(hash_key(&url), hash_key(url.as_str()));
+        assert_ne!(
+            hash_key(&url),
+            hash_key("https://example.com/search?q=cache")
+        );
+        // the halves are different hashes
+        let hash = hash_key(&1u32);
+        assert_ne!(hash >> 64, hash & u128::from(u64::MAX));
+    }
+}
@@ -0,0 +1,240 @@
+/*!
+Circuit breakers skipping the stores failing repeatedly
+
+An unreachable store fails every operation, often after a timeout, making the cached
+function slower than the function itself. A `CircuitBreaker` counts the consecutive
+failures of a store and, after `max_failures` of them, opens for a cooldown period:
+the store is skipped and the values are computed as if they were not cached. Once the
+period is over, the next operation probes the store: a success closes the breaker, a
+failure opens it for another period.
+
+`#[io_cached(breaker_after = N)]` generates a `{CACHE}_BREAKER` static doing so,
+opening for `breaker_secs` seconds (30 by default). The errors of the store failing
+while the breaker is closed are still handled by `map_error` or `on_error`.
+
+```rust
+use cached::proc_macro::io_cached;
+use cached::IOCached;
+
+struct Unreachable;
+
+impl IOCached<u32, u32> for Unreachable {
+    type Error = String;
+    fn cache_get(&self, _: &u32) -> Result<Option<u32>, String> { Err("timeout".into()) }
+    fn cache_set(&self, _: u32, _: u32) -> Result<Option<u32>, String> { Err("timeout".into()) }
+    fn cache_remove(&self, _: &u32) -> Result<Option<u32>, String> { Err("timeout".into()) }
+    fn cache_set_refresh(&mut self, _: bool) -> bool { false }
+}
+
+#[io_cached(
+    on_error = "recompute",
+    ty = "Unreachable",
+    create = "{ Unreachable }",
+    breaker_after = 2,
+    breaker_secs = 60
+)]
+fn double(n: u32) -> u32 {
+    n * 2
+}
+
+assert_eq!(double(1), 2);
+// the store is skipped for a minute
+assert!(DOUBLE_BREAKER.is_open());
+assert_eq!(double(1), 2);
+assert_eq!(DOUBLE_BREAKER.stats().skipped, 2);
+```
+*/
+
+use instant::{Duration, Instant};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Consecutive failures of a store, skipping the store once it fails repeatedly
+pub struct CircuitBreaker {
+    max_failures: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+    trips: AtomicU64,
+    skipped: AtomicU64,
+}
+
+/// The consecutive failures of the store, and the end of the cooldown of the open breaker
+struct State {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Statistics of a `CircuitBreaker`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CircuitBreakerStats {
+    /// Whether the breaker is open, skipping the store
+    pub open: bool,
+    /// The number of times the breaker opened
+    pub trips: u64,
+    /// The number of store operations skipped while the breaker was open
+    pub skipped: u64,
+}
+
+impl CircuitBreaker {
+    /// Create a `CircuitBreaker` opening for `cooldown` after `max_failures` consecutive
+    /// failures
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `max_failures` is 0
+    #[must_use]
+    pub fn new(max_failures: u32, cooldown: Duration) -> Self {
+        if max_failures == 0 {
+            panic!("`max_failures` of `CircuitBreaker` must be greater than zero.");
+        }
+        Self {
+            max_failures,
+            cooldown,
+            state: Mutex::new(State {
+                failures: 0,
+                open_until: None,
+            }),
+            trips: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+        }
+    }
+
+    /// Return whether the store may be used, counting the operation as skipped otherwise
+    ///
+    /// Once the cooldown is over, a single operation is allowed to probe the store, the
+    /// others being skipped for another cooldown unless the probe succeeds.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the breaker is poisoned
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(until) if Instant::now() < until => {
+                self.skipped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Some(_) => {
+                state.open_until = Some(Instant::now() + self.cooldown);
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Record the outcome of a store operation, returning it
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the breaker is poisoned
+    pub fn observe<T, E>(&self, result: Result<T, E>) -> Result<T, E> {
+        if result.is_ok() {
+            self.record_success();
+        } else {
+            self.record_failure();
+        }
+        result
+    }
+
+    /// Record a failure of the store, returns `true` if the breaker is now open
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the breaker is poisoned
+    pub fn record_failure(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.failures = state.failures.saturating_add(1);
+        if state.failures < self.max_failures {
+            return false;
+        }
+        // a failed probe opens the breaker again, without counting another trip
+        if state.open_until.is_none() {
+            self.trips.fetch_add(1, Ordering::Relaxed);
+        }
+        state.open_until = Some(Instant::now() + self.cooldown);
+        true
+    }
+
+    /// Record a success of the store, closing the breaker
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the breaker is poisoned
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures = 0;
+        state.open_until = None;
+    }
+
+    /// Return whether the breaker is open, skipping the store until its cooldown is over
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the breaker is poisoned
+    pub fn is_open(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .open_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Close the breaker and forget the failures of the store
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the breaker is poisoned
+    pub fn reset(&self) {
+        self.record_success();
+    }
+
+    /// Return the statistics of the breaker
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the breaker is poisoned
+    pub fn stats(&self) -> CircuitBreakerStats {
+        CircuitBreakerStats {
+            open: self.is_open(),
+            trips: self.trips.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_max_failures_and_probes_after_cooldown() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+        assert!(breaker.allow());
+        assert!(!breaker.record_failure());
+        assert!(breaker.observe(Err::<(), _>("timeout")).is_err());
+        assert!(breaker.is_open());
+        assert!(!breaker.allow());
+
+        // a single probe after the cooldown, failing again
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+        assert!(breaker.record_failure());
+        assert_eq!(
+            breaker.stats(),
+            CircuitBreakerStats {
+                open: true,
+                trips: 1,
+                skipped: 2,
+            }
+        );
+
+        // a successful probe closes the breaker
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        assert_eq!(breaker.observe(Ok::<_, ()>(1)), Ok(1));
+        assert!(!breaker.is_open());
+        assert!(breaker.allow());
+        assert!(!breaker.record_failure());
+    }
+}
@@ -0,0 +1,157 @@
+/*!
+Entries of the in-memory cache stores
+
+`Cached::cache_entry` returns the `Entry` of a key, modifying its value or inserting
+one with a single lookup, like the entry API of `HashMap`:
+
+```rust
+use cached::{Cached, CachedRead, SizedCache};
+
+let mut visits = SizedCache::with_size(100);
+for page in ["/", "/about", "/"] {
+    visits
+        .cache_entry(page.to_string())
+        .and_modify(|count| *count += 1)
+        .or_insert(1);
+}
+
+// one lookup per entry: two misses, then a hit
+assert_eq!(visits.cache_misses(), Some(2));
+assert_eq!(visits.cache_hits(), Some(1));
+assert_eq!(visits.cache_get("/"), Some(&2));
+assert_eq!(visits.cache_get("/about"), Some(&1));
+```
+
+The modifications of `and_modify` are applied when the entry is resolved by one of the
+`or_insert` methods, which look the key up, so an entry that is not resolved does
+nothing. Use `Cached::cache_get_mut` to modify a value only if there is one.
+*/
+
+use crate::Cached;
+use std::marker::PhantomData;
+
+/// The entry of a key of a cache, see the `entry` module
+#[must_use = "an entry does nothing unless resolved by one of its `or_insert` methods"]
+pub struct Entry<'a, K, V, C: ?Sized, M = fn(&mut V)> {
+    cache: &'a mut C,
+    key: K,
+    modify: Option<M>,
+    _value: PhantomData<fn(&mut V)>,
+}
+
+impl<'a, K, V, C: ?Sized> Entry<'a, K, V, C> {
+    pub(crate) fn new(cache: &'a mut C, key: K) -> Self {
+        Entry {
+            cache,
+            key,
+            modify: None,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, C, M> Entry<'a, K, V, C, M>
+where
+    C: Cached<K, V> + ?Sized,
+    M: FnOnce(&mut V),
+{
+    /// Return the key of the entry
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Modify the value of the key, if there is one when the entry is resolved
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Entry<'a, K, V, C, impl FnOnce(&mut V)> {
+        let modify = self.modify;
+        Entry {
+            cache: self.cache,
+            key: self.key,
+            modify: Some(move |value: &mut V| {
+                if let Some(modify) = modify {
+                    modify(value);
+                }
+                f(value);
+            }),
+            _value: PhantomData,
+        }
+    }
+
+    /// Return the value of the key, inserting `default` if there is none
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Return the value of the key, inserting the value returned by `f` if there is none
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> &'a mut V {
+        let mut inserted = false;
+        let value = self.cache.cache_get_or_set_with(self.key, || {
+            inserted = true;
+            f()
+        });
+        if !inserted {
+            if let Some(modify) = self.modify {
+                modify(value);
+            }
+        }
+        value
+    }
+
+    /// Return the value of the key, inserting the value `f` returns for the key if
+    /// there is none
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, f: F) -> &'a mut V
+    where
+        K: Clone,
+    {
+        let key = self.key.clone();
+        self.or_insert_with(|| f(&key))
+    }
+
+    /// Return the value of the key, inserting the default value if there is none
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stores::{SizedCache, TimedCache};
+    use crate::{Cached, CachedRead, CachedWrite};
+
+    #[test]
+    fn single_lookup() {
+        let mut c = SizedCache::with_size(2);
+        *c.cache_entry(1).or_default() += 10;
+        assert_eq!(c.cache_entry(1).and_modify(|v| *v *= 2).or_insert(0), &20);
+        let entry = c
+            .cache_entry(2)
+            .and_modify(|v| *v += 1)
+            .and_modify(|v| *v *= 3);
+        assert_eq!(entry.key(), &2);
+        assert_eq!(entry.or_insert_with_key(|k| k * 100), &200);
+        assert_eq!(
+            c.cache_entry(2)
+                .and_modify(|v| *v += 1)
+                .and_modify(|v| *v *= 3)
+                .or_insert(0),
+            &603
+        );
+        assert_eq!((c.cache_hits(), c.cache_misses()), (Some(2), Some(2)));
+
+        // the entry is the most recently used value
+        c.cache_entry(1).or_default();
+        c.cache_set(3, 0);
+        assert!(c.cache_contains(&1));
+        assert!(!c.cache_contains(&2));
+    }
+
+    #[test]
+    fn expired_values_are_replaced() {
+        let mut c = TimedCache::with_lifespan(1);
+        c.cache_set(1, 1);
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        assert_eq!(c.cache_entry(1).and_modify(|v| *v += 1).or_insert(10), &10);
+    }
+}
@@ -0,0 +1,238 @@
+/*!
+Mutation feeds of cache stores
+
+The in-memory stores broadcast their mutations to subscribers registered with
+`Cached::cache_subscribe`, so that another cache (in another thread, or another
+process with a transport of your choice) can mirror them. Every event carries a
+sequence number, increasing with each mutation of the cache, so that consumers
+can discard duplicated and out-of-order events, as `FollowerCache` does.
+
+Inserting, replacing and removing values and clearing the cache are broadcast, and so are
+the values a store drops because it is full or because their lifespan ended, as `Remove`
+events. Expired values are only dropped once the store notices them, on lookup or `flush`,
+so a follower keeps them until then. Values modified in place through `cache_get_mut` are
+not broadcast.
+
+Each subscriber buffers up to [`CAPACITY`] events. A subscriber lagging further behind is
+unsubscribed rather than blocking the cache: its receiver yields the events buffered so far,
+then reports that it is disconnected, after which the mirror is incomplete and must be
+rebuilt from a new subscription.
+
+```rust
+use cached::events::CacheEvent;
+use cached::{Cached, CachedWrite, SizedCache};
+
+let mut cache = SizedCache::with_size(10);
+let events = cache.cache_subscribe().unwrap();
+
+cache.cache_set(1, "a");
+cache.cache_set(1, "b");
+cache.cache_remove(&1);
+
+let events: Vec<_> = events.try_iter().collect();
+assert_eq!(
+    events,
+    vec![
+        CacheEvent::Insert { seq: 1, key: 1, value: "a" },
+        CacheEvent::Update { seq: 2, key: 1, value: "b" },
+        CacheEvent::Remove { seq: 3, key: 1 },
+    ]
+);
+```
+*/
+
+use std::fmt;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+/// The number of events buffered for each subscriber, see the lag policy above
+pub const CAPACITY: usize = 1024;
+
+/// A mutation of a cache
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CacheEvent<K, V> {
+    /// A value was set for a key without a value
+    Insert { seq: u64, key: K, value: V },
+    /// The value of a key was replaced
+    Update { seq: u64, key: K, value: V },
+    /// The value of a key was removed
+    Remove { seq: u64, key: K },
+    /// All values were removed
+    Clear { seq: u64 },
+}
+
+impl<K, V> CacheEvent<K, V> {
+    /// Return the sequence number of the event
+    #[must_use]
+    pub fn seq(&self) -> u64 {
+        match self {
+            CacheEvent::Insert { seq, .. }
+            | CacheEvent::Update { seq, .. }
+            | CacheEvent::Remove { seq, .. }
+            | CacheEvent::Clear { seq } => *seq,
+        }
+    }
+
+    /// Return the key the event applies to, `None` for `Clear` events
+    #[must_use]
+    pub fn key(&self) -> Option<&K> {
+        match self {
+            CacheEvent::Insert { key, .. }
+            | CacheEvent::Update { key, .. }
+            | CacheEvent::Remove { key, .. } => Some(key),
+            CacheEvent::Clear { .. } => None,
+        }
+    }
+}
+
+/// The functions cloning the keys and values of a cache
+type Cloners<K, V> = (fn(&K) -> K, fn(&V) -> V);
+
+/// The subscribers to the mutations of a cache
+///
+/// Keys and values are only cloned while there are subscribers. The functions
+/// cloning them are captured when subscribing, so stores do not need `Clone`
+/// keys and values unless they are subscribed to.
+pub struct EventFeed<K, V> {
+    subscribers: Vec<SyncSender<CacheEvent<K, V>>>,
+    seq: u64,
+    cloners: Option<Cloners<K, V>>,
+}
+
+impl<K, V> EventFeed<K, V> {
+    /// Create a feed without subscribers
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            subscribers: Vec::new(),
+            seq: 0,
+            cloners: None,
+        }
+    }
+
+    /// Add a subscriber, returning the receiving end of its events
+    pub fn subscribe(&mut self) -> Receiver<CacheEvent<K, V>>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let (sender, receiver) = sync_channel(CAPACITY);
+        self.cloners = Some((K::clone, V::clone));
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Return whether there are subscribers
+    #[must_use]
+    pub fn has_subscribers(&self) -> bool {
+        !self.subscribers.is_empty()
+    }
+
+    /// Broadcast that `value` was set for `key`, replacing a previous value if `replaced`
+    pub fn set(&mut self, key: &K, value: &V, replaced: bool) {
+        self.send(|seq, (clone_key, clone_value)| {
+            let (key, value) = (clone_key(key), clone_value(value));
+            if replaced {
+                CacheEvent::Update { seq, key, value }
+            } else {
+                CacheEvent::Insert { seq, key, value }
+            }
+        });
+    }
+
+    /// Broadcast that the value of `key` was removed
+    pub fn remove(&mut self, key: &K) {
+        self.send(|seq, (clone_key, _)| CacheEvent::Remove {
+            seq,
+            key: clone_key(key),
+        });
+    }
+
+    /// Broadcast that all values were removed
+    pub fn clear(&mut self) {
+        self.send(|seq, _| CacheEvent::Clear { seq });
+    }
+
+    fn send<F>(&mut self, event: F)
+    where
+        F: Fn(u64, Cloners<K, V>) -> CacheEvent<K, V>,
+    {
+        let cloners = match self.cloners {
+            Some(cloners) if self.has_subscribers() => cloners,
+            _ => return,
+        };
+        self.seq += 1;
+        let seq = self.seq;
+        // forget the subscribers whose receiver was dropped or whose buffer is full
+        self.subscribers
+            .retain(|subscriber| subscriber.try_send(event(seq, cloners)).is_ok());
+    }
+}
+
+impl<K, V> Default for EventFeed<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Clone for EventFeed<K, V> {
+    /// Clones of a cache start without subscribers
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> fmt::Debug for EventFeed<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventFeed")
+            .field("subscribers", &self.subscribers.len())
+            .field("seq", &self.seq)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::TryRecvError;
+
+    #[test]
+    fn dropped_subscribers_are_forgotten() {
+        let mut feed = EventFeed::<u32, u32>::new();
+        feed.set(&1, &1, false);
+        let first = feed.subscribe();
+        let second = feed.subscribe();
+        feed.set(&1, &2, true);
+        drop(second);
+        feed.clear();
+
+        assert!(feed.has_subscribers());
+        assert_eq!(
+            first.try_iter().collect::<Vec<_>>(),
+            vec![
+                CacheEvent::Update {
+                    seq: 1,
+                    key: 1,
+                    value: 2
+                },
+                CacheEvent::Clear { seq: 2 },
+            ]
+        );
+        drop(first);
+        feed.remove(&1);
+        assert!(!feed.has_subscribers());
+    }
+
+    #[test]
+    fn lagging_subscribers_are_disconnected() {
+        let mut feed = EventFeed::<u32, u32>::new();
+        let events = feed.subscribe();
+        for i in 0..=CAPACITY as u32 {
+            feed.set(&i, &i, false);
+        }
+
+        assert!(!feed.has_subscribers());
+        assert_eq!(events.try_iter().count(), CAPACITY);
+        assert_eq!(events.try_recv(), Err(TryRecvError::Disconnected));
+    }
+}
@@ -0,0 +1,367 @@
+/*!
+Locks serializing the computation of the values of each key
+
+`#[cached(sync_writes = true)]` holds the lock of the whole cache while computing a
+missing value, so a miss for a key blocks the callers of every other key until it is
+computed. `#[cached(sync_writes = "by_key")]` instead generates a `{CACHE}_KEY_LOCKS`
+static of `KeyLocks`: concurrent callers missing the same key wait for the first one to
+compute and cache its value, while the callers of other keys are served concurrently.
+
+```rust
+use cached::proc_macro::cached;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(sync_writes = "by_key")]
+fn slow_square(n: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    n * n
+}
+
+let threads: Vec<_> = (0..4)
+    .map(|n| std::thread::spawn(move || slow_square(n % 2)))
+    .collect();
+for thread in threads {
+    thread.join().unwrap();
+}
+// two keys, each computed once
+assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+```
+
+The keys are locked by their hash, so two keys sharing a hash are also serialized.
+
+The callers of async functions waiting for a key still compute it again when its value
+was not cached, e.g. an error of a `result` function. `#[cached(sync_writes =
+"single_flight")]` instead generates a `{CACHE}_FLIGHTS` static of `SingleFlight`: the
+first caller missing a key computes it while the others await its result, whether it is
+cached or not. The output of the function must be `Clone`.
+*/
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::{Condvar, Mutex};
+
+#[cfg(feature = "async")]
+use {
+    std::collections::HashMap,
+    std::future::Future,
+    std::sync::Arc,
+    tokio::sync::{watch, Mutex as AsyncMutex, OwnedMutexGuard},
+};
+
+/// Hash a key, the locks being keyed by the hashes of the keys. Keys sharing a hash
+/// wait for each other, without being mixed up.
+fn key_hash<K: Hash + ?Sized>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Locks of the keys being computed, for synchronous functions
+#[derive(Default)]
+pub struct KeyLocks {
+    locked: Mutex<HashSet<u64>>,
+    unlocked: Condvar,
+}
+
+/// Lock of a key, released when dropped
+#[must_use]
+pub struct KeyLockGuard<'a> {
+    locks: &'a KeyLocks,
+    key: u64,
+}
+
+impl KeyLocks {
+    /// Create the locks of the keys of a cache
+    #[must_use]
+    pub fn new() -> KeyLocks {
+        KeyLocks::default()
+    }
+
+    /// Lock `key`, waiting for the caller holding it to release it
+    pub fn lock<Q: Hash + ?Sized>(&self, key: &Q) -> KeyLockGuard<'_> {
+        let key = key_hash(key);
+        let mut locked = self.locked.lock().unwrap();
+        while locked.contains(&key) {
+            locked = self.unlocked.wait(locked).unwrap();
+        }
+        locked.insert(key);
+        KeyLockGuard { locks: self, key }
+    }
+
+    /// Return the number of keys locked
+    pub fn locked(&self) -> usize {
+        self.locked.lock().unwrap().len()
+    }
+}
+
+impl Drop for KeyLockGuard<'_> {
+    fn drop(&mut self) {
+        // a panic of the locking thread must not leave the key locked
+        let mut locked = self
+            .locks
+            .locked
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        locked.remove(&self.key);
+        drop(locked);
+        self.locks.unlocked.notify_all();
+    }
+}
+
+/// Locks of the keys being computed, for async functions
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[derive(Default)]
+pub struct AsyncKeyLocks {
+    locks: Mutex<HashMap<u64, Arc<AsyncMutex<()>>>>,
+}
+
+/// Lock of a key, released when dropped
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[must_use]
+pub struct AsyncKeyLockGuard<'a> {
+    locks: &'a AsyncKeyLocks,
+    key: u64,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncKeyLocks {
+    /// Create the locks of the keys of a cache
+    #[must_use]
+    pub fn new() -> AsyncKeyLocks {
+        AsyncKeyLocks::default()
+    }
+
+    /// Lock `key`, waiting for the caller holding it to release it
+    pub async fn lock<Q: Hash + ?Sized>(&self, key: &Q) -> AsyncKeyLockGuard<'_> {
+        let key = key_hash(key);
+        let lock = self.locks.lock().unwrap().entry(key).or_default().clone();
+        AsyncKeyLockGuard {
+            locks: self,
+            key,
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+
+    /// Return the number of keys locked or waited for
+    pub fn locked(&self) -> usize {
+        self.locks.lock().unwrap().len()
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for AsyncKeyLockGuard<'_> {
+    fn drop(&mut self) {
+        let mut locks = self
+            .locks
+            .locks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.guard.take();
+        // drop the lock of the key unless other callers are waiting for it
+        if locks
+            .get(&self.key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+/// The computations in flight of the values of async functions, shared by the callers
+/// of the same key
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub struct SingleFlight<K, V> {
+    flights: Mutex<HashMap<K, watch::Receiver<Option<V>>>>,
+}
+
+/// The flight of a key, ended when its computation completes or is cancelled
+#[cfg(feature = "async")]
+struct Flight<'a, K: Hash + Eq, V> {
+    flights: &'a SingleFlight<K, V>,
+    key: K,
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        SingleFlight {
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq + Clone, V: Clone> SingleFlight<K, V> {
+    /// Create the flights of the keys of a cache
+    #[must_use]
+    pub fn new() -> SingleFlight<K, V> {
+        SingleFlight::default()
+    }
+
+    /// Return the value of `key` computed by `compute`, or by the caller already
+    /// computing it
+    ///
+    /// When that caller is cancelled before completing, one of the callers awaiting it
+    /// computes the value instead.
+    pub async fn run<F: Future<Output = V>>(&self, key: K, compute: F) -> V {
+        let mut compute = Some(compute);
+        loop {
+            let (sender, mut receiver) = {
+                let mut flights = self.flights.lock().unwrap();
+                match flights.get(&key) {
+                    Some(receiver) => (None, receiver.clone()),
+                    None => {
+                        let (sender, receiver) = watch::channel(None);
+                        flights.insert(key.clone(), receiver.clone());
+                        (Some(sender), receiver)
+                    }
+                }
+            };
+            match sender {
+                Some(sender) => {
+                    let _flight = Flight { flights: self, key };
+                    // a caller is only cancelled while awaiting, so it computes at most once
+                    let value = compute
+                        .take()
+                        .expect("a flight is only computed once")
+                        .await;
+                    sender.send_replace(Some(value.clone()));
+                    return value;
+                }
+                None => {
+                    if let Ok(value) = receiver.wait_for(Option::is_some).await {
+                        return value.clone().expect("the flights send values");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Return the number of keys in flight
+    pub fn in_flight(&self) -> usize {
+        self.flights.lock().unwrap().len()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq, V> Drop for Flight<'_, K, V> {
+    fn drop(&mut self) {
+        // the callers awaiting a cancelled flight start another one
+        self.flights
+            .flights
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn same_key_waits_other_keys_do_not() {
+        let locks = Arc::new(KeyLocks::new());
+        let guard = locks.lock(&1);
+
+        let (other_tx, other_rx) = mpsc::channel();
+        let other = Arc::clone(&locks);
+        thread::spawn(move || {
+            let _guard = other.lock(&2);
+            other_tx.send(()).unwrap();
+        });
+        other_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+
+        let (same_tx, same_rx) = mpsc::channel();
+        let same = Arc::clone(&locks);
+        let waiter = thread::spawn(move || {
+            let _guard = same.lock(&1);
+            same_tx.send(()).unwrap();
+        });
+        assert!(same_rx.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(guard);
+        same_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        waiter.join().unwrap();
+        assert_eq!(locks.locked(), 0);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_locks_are_dropped_once_released() {
+        let locks = Arc::new(AsyncKeyLocks::new());
+        let guard = locks.lock(&1).await;
+        let _other = locks.lock(&2).await;
+
+        let same = Arc::clone(&locks);
+        let waiter = tokio::spawn(async move {
+            let _guard = same.lock(&1).await;
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+        drop(guard);
+        waiter.await.unwrap();
+        assert_eq!(locks.locked(), 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn flights_are_shared_by_key() {
+        let flights = Arc::new(SingleFlight::new());
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let leader = Arc::clone(&flights);
+        let leader = tokio::spawn(async move {
+            leader
+                .run(1, async {
+                    released.await.unwrap();
+                    "computed"
+                })
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(flights.in_flight(), 1);
+
+        let waiter = Arc::clone(&flights);
+        let waiter = tokio::spawn(async move { waiter.run(1, async { "again" }).await });
+        // the other keys are not waiting
+        assert_eq!(flights.run(2, async { "other" }).await, "other");
+        release.send(()).unwrap();
+        assert_eq!(leader.await.unwrap(), "computed");
+        assert_eq!(waiter.await.unwrap(), "computed");
+        assert_eq!(flights.in_flight(), 0);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn cancelled_flights_are_taken_over() {
+        let flights = Arc::new(SingleFlight::new());
+        let leader = Arc::clone(&flights);
+        let leader = tokio::spawn(async move {
+            leader
+                .run(1, async {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    "cancelled"
+                })
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let waiter = Arc::clone(&flights);
+        let waiter = tokio::spawn(async move { waiter.run(1, async { "taken over" }).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        leader.abort();
+        assert_eq!(waiter.await.unwrap(), "taken over");
+        assert_eq!(flights.in_flight(), 0);
+    }
+}
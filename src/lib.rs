@@ -14,6 +14,12 @@ By default, the function-cache is **not** locked for the duration of the functio
 concurrent calls of long-running functions with the same arguments will each execute fully and each overwrite
 the memoized value as they complete. This mirrors the behavior of Python's `functools.lru_cache`. To synchronize the execution and caching
 of un-cached arguments, specify `#[cached(sync_writes = true)]` / `#[once(sync_writes = true)]` (not supported by `#[io_cached]`.
+Async `#[once]` functions always synchronize it: concurrent first calls await a single execution.
+`#[cached(sync_writes = "by_key")]` only synchronizes the calls with the same arguments, the calls with other
+arguments keep running concurrently.
+Methods taking `&self` can keep their cache in a field of their instance, rather than in a static, with
+`#[cached(instance = "field")]`.
+Methods of trait impls, `#[async_trait]` ones included, are cached with `#[cached(in_impl = true)]`.
 
 - See [`cached::stores` docs](https://docs.rs/cached/latest/cached/stores/index.html) cache stores available.
 - See [`proc_macro`](https://docs.rs/cached/latest/cached/proc_macro/index.html) for more procedural macro examples.
@@ -26,15 +32,51 @@ of un-cached arguments, specify `#[cached(sync_writes = true)]` / `#[once(sync_w
 - `ahash`: Enable the optional `ahash` hasher as default hashing algorithm.
 - `async`: Include support for async functions and async cache stores
 - `async_tokio_rt_multi_thread`: Enable `tokio`'s optional `rt-multi-thread` feature.
+- `async_std_rt`: Run the timers and background tasks of async functions with `async-std` instead of `tokio`,
+  for `async-std` and `smol` applications, implies `async`. See `cached::runtime`
 - `redis_store`: Include Redis cache store
 - `redis_async_std`: Include async Redis support using `async-std` and `async-std` tls support, implies `redis_store` and `async`
 - `redis_tokio`: Include async Redis support using `tokio` and `tokio` tls support, implies `redis_store` and `async`
 - `redis_connection_manager`: Enable the optional `connection-manager` feature of `redis`. Any async redis caches created
-                              will use a connection manager instead of a `MultiplexedConnection`
+  will use a connection manager instead of a `MultiplexedConnection`
 - `redis_ahash`: Enable the optional `ahash` feature of `redis`
+- `redis_cluster`: Include Redis Cluster support in `RedisCache`, implies `redis_store`
+- `redis_cluster_async`: Include Redis Cluster support in `AsyncRedisCache`, implies `redis_cluster`. Use along with
+  `redis_tokio` or `redis_async_std`
+- `redis_sentinel`: Include Redis Sentinel support in `AsyncRedisCache`, reconnecting to the new primary on failover.
+  Use along with `redis_tokio` or `redis_async_std`
+- `redis_rustls`: Connect to `rediss://` servers using `rustls`, with the CA and client certificates set by the
+  `set_tls_root_cert` and `set_tls_client_cert` builder options, implies `redis_store`
+- `redis_tokio_rustls`: Enable `rustls` support of `tokio` connections, implies `redis_tokio` and `redis_rustls`
+- `redis_async_std_rustls`: Enable `rustls` support of `async-std` connections, implies `redis_async_std` and `redis_rustls`
+- `redis_msgpack`: Include `MessagePackSerializer`, storing the values of the redis stores as MessagePack instead of JSON
 - `disk_store`: Include disk cache store
+- `disk_zstd`: Enable `Compression::Zstd`, compressing the values of the disk cache store, implies `disk_store`
+- `disk_encryption`: Enable `Encryption`, encrypting the values and optionally hashing the keys of the disk cache
+  store with AES-256-GCM or ChaCha20-Poly1305, implies `disk_store`
+- `rocksdb_store`: Include RocksDB cache store
+- `sqlite_store`: Include SQLite cache store
+- `file_store`: Include file cache store, keeping each value in a JSON file named by a hash of its key
+- `dynamodb_store`: Include DynamoDB cache store, enables `async`
+- `mongodb`: Include MongoDB cache store, enables `async`
+- `scylla_store`: Include Cassandra/ScyllaDB cache store, enables `async`
+- `object_store`: Include object store (S3, GCS, Azure, ...) cache store for large values, enables `async`
+- `foyer`: Include `FoyerCache`, adapting a `foyer` hybrid (memory and disk) cache, enables `async`
+- `latency_stats`: Record per-operation latency histograms in the cache stores, see the `stats` module
+- `lock_stats`: Record wait and hold times of the locks guarding the caches generated by `#[cached]` and `#[once]`,
+  see the `registry` module
+- `key_invalidation`: Let `#[cached(invalidation = true)]` register the invalidation of the values of its arguments
+  given as JSON, see `registry::invalidate`
+- `metrics`: Report the hits, misses, evictions and load times of the caches generated by `#[cached]` and `#[io_cached]`
+  through the `metrics` crate, see the `registry` module
+- `tracing`: Let `#[cached(instrument = true)]` emit `tracing` events for cache hits, misses, loads and evictions
+- `unicode_keys`: Enable Unicode NFC normalization of the keys of a `CanonicalCache`, see `Canonicalizer::nfc`
 - `wasm`: Enable WASM support. Note that this feature is incompatible with `tokio`'s multi-thread
-   runtime (`async_tokio_rt_multi_thread`) and all Redis features (`redis_store`, `redis_async_std`, `redis_tokio`, `redis_ahash`)
+  runtime (`async_tokio_rt_multi_thread`) and all Redis features (`redis_store`, `redis_async_std`, `redis_tokio`, `redis_ahash`)
+- `indexeddb_store`: Include IndexedDB cache store for `wasm32-unknown-unknown` targets, persisting the values in the
+  browser across sessions, enables `wasm` and `async`
+- `webstorage_store`: Include Web Storage (`localStorage`/`sessionStorage`) cache store for `wasm32-unknown-unknown`
+  targets, for small values, enables `wasm`
 
 The procedural macros (`#[cached]`, `#[once]`, `#[io_cached]`) offer more features, including async support.
 See the [`proc_macro`](crate::proc_macro) and [`macros`](crate::macros) modules for more samples, and the
@@ -123,6 +165,17 @@ fn doesnt_compile() -> Result<String, ()> {
 ```
 ----
 
+```compile_fail
+use cached::proc_macro::cached;
+
+/// Cannot cache no values
+#[cached(size = 0)]
+fn doesnt_compile(n: u32) -> u32 {
+    n
+}
+```
+----
+
 ```rust,no_run,ignore
 use cached::proc_macro::io_cached;
 use cached::AsyncRedisCache;
@@ -220,39 +273,99 @@ Due to the requirements of storing arguments and return values in a global cache
 #[doc(hidden)]
 pub extern crate once_cell;
 
+pub use cache_key::CacheKey;
 #[cfg(feature = "proc_macro")]
 #[cfg_attr(docsrs, doc(cfg(feature = "proc_macro")))]
-pub use proc_macro::Return;
+pub use proc_macro::{CachePolicy, Return, Revalidation, Validated};
 #[cfg(any(feature = "redis_async_std", feature = "redis_tokio"))]
 #[cfg_attr(
     docsrs,
     doc(cfg(any(feature = "redis_async_std", feature = "redis_tokio")))
 )]
 pub use stores::AsyncRedisCache;
+#[cfg(feature = "dynamodb_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "dynamodb_store")))]
+pub use stores::{AsyncDynamoCache, DynamoCacheError};
 pub use stores::{
-    CanExpire, ExpiringValueCache, SizedCache, TimedCache, TimedSizedCache, UnboundCache,
+    CacheBuildError, CanExpire, ExpiringValueCache, FairCache, FollowerCache, LatestValueCache,
+    MemSize, NamespacedCache, ScoredCache, SizedCache, TimedCache, TimedSizedCache, UnboundCache,
+    WeightedCache,
 };
 #[cfg(feature = "disk_store")]
 #[cfg_attr(docsrs, doc(cfg(feature = "disk_store")))]
-pub use stores::{DiskCache, DiskCacheError};
+pub use stores::{DiskCache, DiskCacheError, SpilloverCache};
+#[cfg(feature = "file_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "file_store")))]
+pub use stores::{FileCache, FileCacheError};
+#[cfg(feature = "foyer")]
+#[cfg_attr(docsrs, doc(cfg(feature = "foyer")))]
+pub use stores::{FoyerCache, FoyerCacheError};
+#[cfg(all(
+    feature = "indexeddb_store",
+    target_arch = "wasm32",
+    not(target_feature = "atomics")
+))]
+#[cfg_attr(docsrs, doc(cfg(feature = "indexeddb_store")))]
+pub use stores::{IndexedDbCache, IndexedDbCacheError};
+#[cfg(feature = "mongodb")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mongodb")))]
+pub use stores::{MongoCache, MongoCacheError};
+#[cfg(feature = "object_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "object_store")))]
+pub use stores::{ObjectStoreCache, ObjectStoreCacheError};
 #[cfg(feature = "redis_store")]
 #[cfg_attr(docsrs, doc(cfg(feature = "redis_store")))]
 pub use stores::{RedisCache, RedisCacheError};
+#[cfg(feature = "rocksdb_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rocksdb_store")))]
+pub use stores::{RocksDbCache, RocksDbCacheError};
+#[cfg(feature = "scylla_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "scylla_store")))]
+pub use stores::{ScyllaCache, ScyllaCacheError};
+#[cfg(feature = "sqlite_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sqlite_store")))]
+pub use stores::{SqliteCache, SqliteCacheError};
+#[cfg(all(feature = "webstorage_store", target_arch = "wasm32"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "webstorage_store")))]
+pub use stores::{WebStorageCache, WebStorageCacheError};
 #[cfg(feature = "async")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
 use {async_trait::async_trait, futures::Future};
 
+pub mod cache_key;
+pub mod circuit_breaker;
+pub mod entry;
+pub mod events;
+pub mod key_locks;
 mod lru_list;
 pub mod macros;
 #[cfg(feature = "proc_macro")]
 pub mod proc_macro;
+pub mod quarantine;
+pub mod registry;
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub mod runtime;
+#[cfg(feature = "latency_stats")]
+#[cfg_attr(docsrs, doc(cfg(feature = "latency_stats")))]
+pub mod stats;
 pub mod stores;
+#[cfg(feature = "async")]
+#[doc(hidden)]
+pub use futures;
 #[doc(hidden)]
 pub use instant;
+#[cfg(feature = "key_invalidation")]
+#[doc(hidden)]
+pub use serde_json;
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub use tracing;
 
 #[cfg(feature = "async")]
 #[doc(hidden)]
 pub mod async_sync {
+    pub use crate::runtime::{sleep, spawn, spawn_tokio, spawn_with, timeout};
     pub use tokio::sync::Mutex;
     pub use tokio::sync::OnceCell;
     pub use tokio::sync::RwLock;
@@ -260,8 +373,11 @@ pub mod async_sync {
 
 /// Cache operations
 ///
+/// Lookups, which may update the recency order and metrics of a cache, along with
+/// the read-only operations of `CachedRead` and the modifying operations of `CachedWrite`.
+///
 /// ```rust
-/// use cached::{Cached, UnboundCache};
+/// use cached::{Cached, CachedWrite, UnboundCache};
 ///
 /// let mut cache: UnboundCache<String, String> = UnboundCache::new();
 ///
@@ -273,11 +389,11 @@ pub mod async_sync {
 ///
 /// assert_eq!(borrowed_cache_value, Some(&"owned value".to_string()))
 /// ```
-pub trait Cached<K, V> {
+pub trait Cached<K, V>: CachedRead<K, V> + CachedWrite<K, V> {
     /// Attempt to retrieve a cached value
     ///
     /// ```rust
-    /// # use cached::{Cached, UnboundCache};
+    /// # use cached::{Cached, CachedWrite, UnboundCache};
     /// # let mut cache: UnboundCache<String, String> = UnboundCache::new();
     /// # cache.cache_set("key".to_string(), "owned value".to_string());
     /// // You can use borrowed data, or the data's borrowed type:
@@ -296,7 +412,7 @@ pub trait Cached<K, V> {
     /// Attempt to retrieve a cached value with mutable access
     ///
     /// ```rust
-    /// # use cached::{Cached, UnboundCache};
+    /// # use cached::{Cached, CachedWrite, UnboundCache};
     /// # let mut cache: UnboundCache<String, String> = UnboundCache::new();
     /// # cache.cache_set("key".to_string(), "owned value".to_string());
     /// // You can use borrowed data, or the data's borrowed type:
@@ -312,40 +428,147 @@ pub trait Cached<K, V> {
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized;
 
-    /// Insert a key, value pair and return the previous value
-    fn cache_set(&mut self, k: K, v: V) -> Option<V>;
-
-    /// Get or insert a key, value pair
-    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V;
+    /// Attempt to retrieve a cached value along with its stored key, e.g. to recover the
+    /// interned or normalized key of a lookup by its borrowed form
+    ///
+    /// The default implementation counts the lookup with `cache_get`, then looks for
+    /// the stored key among the values of `cache_iter`, in linear time, so that the
+    /// caches which cannot enumerate their values should override it.
+    ///
+    /// ```rust
+    /// # use cached::{Cached, CachedWrite, UnboundCache};
+    /// # use std::rc::Rc;
+    /// let mut ids: UnboundCache<Rc<str>, u32> = UnboundCache::new();
+    /// ids.cache_set(Rc::from("alice"), 1);
+    ///
+    /// // share the stored key rather than allocating another
+    /// let (name, id) = ids.cache_get_key_value("alice").unwrap();
+    /// let name = Rc::clone(name);
+    /// assert_eq!((&*name, *id), ("alice", 1));
+    /// assert_eq!(Rc::strong_count(&name), 2);
+    /// ```
+    fn cache_get_key_value<Q>(&mut self, k: &Q) -> Option<(&K, &V)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.cache_get(k)?;
+        self.cache_iter().find(|(key, _)| (*key).borrow() == k)
+    }
 
-    /// Remove a cached value
+    /// Attempt to retrieve the cached values of many keys, in the same order, each lookup
+    /// counting a hit or a miss and updating the recency of the value like `cache_get`
     ///
     /// ```rust
-    /// # use cached::{Cached, UnboundCache};
-    /// # let mut cache: UnboundCache<String, String> = UnboundCache::new();
-    /// # cache.cache_set("key1".to_string(), "owned value 1".to_string());
-    /// # cache.cache_set("key2".to_string(), "owned value 2".to_string());
-    /// // You can use borrowed data, or the data's borrowed type:
-    /// let remove_1 = cache.cache_remove("key1");
-    /// let remove_2 = cache.cache_remove(&"key2".to_string());
+    /// # use cached::{Cached, CachedRead, CachedWrite, SizedCache};
+    /// let mut cache = SizedCache::with_size(10);
+    /// cache.cache_set_many(vec![(1, "a"), (2, "b")]);
     ///
-    /// # assert_eq!(remove_1, Some("owned value 1".to_string()));
-    /// # assert_eq!(remove_2, Some("owned value 2".to_string()));
+    /// assert_eq!(cache.cache_get_many(&[2, 3, 1]), [Some(&"b"), None, Some(&"a")]);
+    /// assert_eq!((cache.cache_hits(), cache.cache_misses()), (Some(2), Some(1)));
     /// ```
-    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    fn cache_get_many<Q>(&mut self, keys: &[Q]) -> Vec<Option<&V>>
     where
         K: std::borrow::Borrow<Q>,
-        Q: std::hash::Hash + Eq + ?Sized;
+        Q: std::hash::Hash + Eq,
+    {
+        // the lookups are counted first, the values being borrowed together afterwards
+        for key in keys {
+            self.cache_get(key);
+        }
+        let cache = &*self;
+        keys.iter().map(|key| cache.cache_peek(key)).collect()
+    }
 
-    /// Remove all cached values. Keeps the allocated memory for reuse.
-    fn cache_clear(&mut self);
+    /// Remove the cached values for which `drain` returns `true`, returning them along
+    /// with their keys. The values are removed when called rather than as the iterator is
+    /// consumed, and expired values are dropped rather than returned.
+    ///
+    /// ```rust
+    /// # use cached::{Cached, CachedRead, CachedWrite, UnboundCache};
+    /// let mut cache = UnboundCache::new();
+    /// cache.cache_set("a", 1);
+    /// cache.cache_set("b", 20);
+    ///
+    /// let large: Vec<_> = cache.cache_drain_where(|_, value| *value > 10).collect();
+    /// assert_eq!(large, [("b", 20)]);
+    /// assert_eq!(cache.cache_size(), 1);
+    /// ```
+    fn cache_drain_where<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        drain: F,
+    ) -> impl Iterator<Item = (K, V)>;
+
+    /// Remove all cached values, returning them along with their keys, e.g. to persist
+    /// them on shutdown. See `cache_drain_where`.
+    fn cache_drain(&mut self) -> impl Iterator<Item = (K, V)> {
+        self.cache_drain_where(|_, _| true)
+    }
 
-    /// Remove all cached values. Free memory and return to initial state
-    fn cache_reset(&mut self);
+    /// Keep only the cached values for which `keep` returns `true`, e.g. to drop the
+    /// values of a deleted user. Expired values are dropped without being passed to `keep`.
+    ///
+    /// ```rust
+    /// # use cached::{Cached, CachedRead, CachedWrite, TimedCache};
+    /// let mut cache = TimedCache::with_lifespan(60);
+    /// cache.cache_set("user:1", 1);
+    /// cache.cache_set("user:2", 2);
+    ///
+    /// cache.cache_retain(|key, _| *key != "user:1");
+    /// assert_eq!(cache.cache_size(), 1);
+    /// ```
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut keep: F) {
+        self.cache_drain_where(|key, value| !keep(key, value))
+            .for_each(drop);
+    }
 
-    /// Reset misses/hits counters
-    fn cache_reset_metrics(&mut self) {}
+    /// Return the entry of a key, modifying its value or inserting one with a single
+    /// lookup, see the `entry` module
+    ///
+    /// ```rust
+    /// # use cached::{Cached, SizedCache};
+    /// let mut cache = SizedCache::with_size(10);
+    /// *cache.cache_entry("a").or_insert(0) += 1;
+    /// cache.cache_entry("a").and_modify(|v| *v += 1).or_insert(0);
+    ///
+    /// assert_eq!(cache.cache_get("a"), Some(&2));
+    /// ```
+    fn cache_entry(&mut self, k: K) -> entry::Entry<'_, K, V, Self> {
+        entry::Entry::new(self, k)
+    }
+
+    /// Get or insert a key, value pair
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V;
 
+    /// Subscribe to the mutations of the cache, see the `events` module.
+    /// Returns `None` if the cache does not support subscriptions.
+    fn cache_subscribe(&mut self) -> Option<std::sync::mpsc::Receiver<events::CacheEvent<K, V>>>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        None
+    }
+}
+
+/// Read-only cache operations
+///
+/// Taking `&self`, these never modify the cache, its recency order or its metrics.
+/// Accept a `CachedRead` when a read-only view of a cache is enough, or implement
+/// only this half for caches that cannot be modified (snapshots, replicas, ...).
+///
+/// ```rust
+/// use cached::{CachedRead, CachedWrite, SizedCache};
+///
+/// fn report(cache: &impl CachedRead<u32, u32>) -> String {
+///     format!("{} values, {:?} hits", cache.cache_size(), cache.cache_hits())
+/// }
+///
+/// let mut cache = SizedCache::with_size(10);
+/// cache.cache_set(1, 100);
+/// assert_eq!(report(&cache), "1 values, Some(0) hits");
+/// ```
+pub trait CachedRead<K, V> {
     /// Return the current cache size (number of elements)
     fn cache_size(&self) -> usize;
 
@@ -369,6 +592,169 @@ pub trait Cached<K, V> {
         None
     }
 
+    /// Return the fraction of the `expected` number of values held by the cache,
+    /// from `0.0` (empty) to `1.0` (at least `expected` values)
+    fn cache_warmth(&self, expected: usize) -> f64 {
+        registry::fraction_of(self.cache_size(), expected)
+    }
+
+    /// Return whether the cache holds at least the `expected` number of values,
+    /// e.g. to report a service as ready once its caches are warmed up
+    fn cache_ready(&self, expected: usize) -> bool {
+        self.cache_size() >= expected
+    }
+
+    /// Return latency histograms of this cache's operations
+    #[cfg(feature = "latency_stats")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "latency_stats")))]
+    fn cache_latency_stats(&self) -> Option<stats::LatencyStatsSnapshot> {
+        None
+    }
+
+    /// Attempt to retrieve a cached value without counting a hit or a miss, nor updating
+    /// the recency order or lifespan of the value, e.g. to inspect a cache from monitoring
+    /// code. Expired values are not returned, but neither are they removed.
+    ///
+    /// ```rust
+    /// # use cached::{Cached, CachedRead, CachedWrite, SizedCache};
+    /// let mut cache = SizedCache::with_size(2);
+    /// cache.cache_set(1, "a");
+    /// cache.cache_set(2, "b");
+    ///
+    /// assert_eq!(cache.cache_peek(&1), Some(&"a"));
+    /// assert!(!cache.cache_contains(&3));
+    /// assert_eq!((cache.cache_hits(), cache.cache_misses()), (Some(0), Some(0)));
+    ///
+    /// // 1 is still the least recently used value
+    /// cache.cache_set(3, "c");
+    /// assert!(!cache.cache_contains(&1));
+    /// ```
+    fn cache_peek<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized;
+
+    /// Return whether there is a cached value for a key, like `cache_peek` without
+    /// counting a hit or a miss, nor updating the recency order of the value
+    fn cache_contains<Q>(&self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.cache_peek(k).is_some()
+    }
+
+    /// Return an iterator of the cached keys and values, without counting hits nor
+    /// updating the recency order of the values. Expired values are left out.
+    ///
+    /// The default implementation returns no values, for the caches which cannot
+    /// enumerate theirs.
+    ///
+    /// ```rust
+    /// # use cached::{CachedRead, CachedWrite, SizedCache};
+    /// fn total<C: CachedRead<&'static str, u32>>(cache: &C) -> u32 {
+    ///     cache.cache_iter().map(|(_, value)| value).sum()
+    /// }
+    ///
+    /// let mut cache = SizedCache::with_size(10);
+    /// cache.cache_set("a", 1);
+    /// cache.cache_set("b", 2);
+    /// assert_eq!(total(&cache), 3);
+    /// ```
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        std::iter::empty()
+    }
+}
+
+/// Lookups through a shared reference
+///
+/// Implemented by the caches whose lookups need not update a recency order, counting
+/// their hits and misses with interior-mutable counters, so that they can be read
+/// concurrently behind a `RwLock`, e.g. by `#[cached(rw_lock = true)]` functions.
+///
+/// ```rust
+/// use cached::{CachedRead, CachedShared, CachedWrite, UnboundCache};
+/// use std::sync::RwLock;
+///
+/// let cache = RwLock::new(UnboundCache::new());
+/// cache.write().unwrap().cache_set(1, "a");
+///
+/// // concurrent readers do not wait for each other
+/// let cache = cache.read().unwrap();
+/// assert_eq!(cache.cache_get_shared(&1), Some(&"a"));
+/// assert_eq!(cache.cache_get_shared(&2), None);
+/// assert_eq!((cache.cache_hits(), cache.cache_misses()), (Some(1), Some(1)));
+/// ```
+pub trait CachedShared<K, V>: CachedRead<K, V> {
+    /// Attempt to retrieve a cached value, without modifying the cache
+    fn cache_get_shared<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized;
+}
+
+/// Cache operations modifying the values of a cache or its configuration
+pub trait CachedWrite<K, V> {
+    /// Insert a key, value pair and return the previous value
+    fn cache_set(&mut self, k: K, v: V) -> Option<V>;
+
+    /// Insert many key, value pairs, e.g. to hydrate a cache, dropping the previous values
+    fn cache_set_many<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        for (k, v) in entries {
+            self.cache_set(k, v);
+        }
+    }
+
+    /// Remove a cached value
+    ///
+    /// ```rust
+    /// # use cached::{CachedWrite, UnboundCache};
+    /// # let mut cache: UnboundCache<String, String> = UnboundCache::new();
+    /// # cache.cache_set("key1".to_string(), "owned value 1".to_string());
+    /// # cache.cache_set("key2".to_string(), "owned value 2".to_string());
+    /// // You can use borrowed data, or the data's borrowed type:
+    /// let remove_1 = cache.cache_remove("key1");
+    /// let remove_2 = cache.cache_remove(&"key2".to_string());
+    ///
+    /// # assert_eq!(remove_1, Some("owned value 1".to_string()));
+    /// # assert_eq!(remove_2, Some("owned value 2".to_string()));
+    /// ```
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized;
+
+    /// Remove the cached values whose keys match `remove`, returning the number of values
+    /// removed, or `None` if the cache cannot look its keys up
+    ///
+    /// ```rust
+    /// # use cached::{CachedRead, CachedWrite, UnboundCache};
+    /// let mut cache: UnboundCache<String, u32> = UnboundCache::new();
+    /// cache.cache_set("user:1:name".to_string(), 1);
+    /// cache.cache_set("user:1:email".to_string(), 2);
+    /// cache.cache_set("user:2:name".to_string(), 3);
+    ///
+    /// let removed = cache.cache_remove_where(|key| key.starts_with("user:1:"));
+    /// assert_eq!(removed, Some(2));
+    /// assert_eq!(cache.cache_size(), 1);
+    /// ```
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, _remove: F) -> Option<usize> {
+        None
+    }
+
+    /// Remove all cached values. Keeps the allocated memory for reuse.
+    fn cache_clear(&mut self);
+
+    /// Remove all cached values. Free memory and return to initial state
+    fn cache_reset(&mut self);
+
+    /// Reset misses/hits counters
+    fn cache_reset_metrics(&mut self) {}
+
     /// Set the lifespan of cached values, returns the old value
     fn cache_set_lifespan(&mut self, _seconds: u64) -> Option<u64> {
         None
@@ -399,6 +785,38 @@ pub trait CachedAsync<K, V> {
         V: Send,
         F: FnOnce() -> Fut + Send,
         Fut: Future<Output = Result<V, E>> + Send;
+
+    /// Retrieve the value of `k` if the async `is_valid` predicate accepts it, or set it with `f`
+    /// if it is missing or rejected, e.g. after revalidating its ETag against the origin.
+    ///
+    /// Like `f`, `is_valid` is awaited while the cache is borrowed mutably, so that callers
+    /// sharing the cache behind an async mutex wait for a revalidation instead of repeating it.
+    async fn get_or_set_with_if_valid<F, Fut, P, PFut>(
+        &mut self,
+        k: K,
+        f: F,
+        is_valid: P,
+    ) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send;
+
+    /// Fallible version of `get_or_set_with_if_valid`
+    async fn try_get_or_set_with_if_valid<F, Fut, P, PFut, E>(
+        &mut self,
+        k: K,
+        f: F,
+        is_valid: P,
+    ) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send;
 }
 
 /// Cache operations on an io-connected store
@@ -412,6 +830,19 @@ pub trait IOCached<K, V> {
     /// Should return `Self::Error` if the operation fails
     fn cache_get(&self, k: &K) -> Result<Option<V>, Self::Error>;
 
+    /// Attempt to retrieve a cached value, even if expired, along with whether it expired
+    ///
+    /// Used by `#[io_cached(result_fallback = true)]` to return the expired value of a
+    /// failed function. Stores dropping their expired values return the unexpired ones
+    /// only, which the default does with `cache_get`.
+    ///
+    /// # Errors
+    ///
+    /// Should return `Self::Error` if the operation fails
+    fn cache_get_expired(&self, k: &K) -> Result<(Option<V>, bool), Self::Error> {
+        Ok((self.cache_get(k)?, false))
+    }
+
     /// Insert a key, value pair and return the previous value
     ///
     /// # Errors
@@ -419,6 +850,29 @@ pub trait IOCached<K, V> {
     /// Should return `Self::Error` if the operation fails
     fn cache_set(&self, k: K, v: V) -> Result<Option<V>, Self::Error>;
 
+    /// Attempt to retrieve the cached values of many keys, in the same order. Stores
+    /// reaching their backend over the network look them up in a single round trip.
+    ///
+    /// # Errors
+    ///
+    /// Should return `Self::Error` if the operation fails
+    fn cache_get_many(&self, keys: &[K]) -> Result<Vec<Option<V>>, Self::Error> {
+        keys.iter().map(|k| self.cache_get(k)).collect()
+    }
+
+    /// Insert many key, value pairs, dropping the previous values. Stores reaching their
+    /// backend over the network insert them in a single round trip.
+    ///
+    /// # Errors
+    ///
+    /// Should return `Self::Error` if the operation fails
+    fn cache_set_many(&self, values: Vec<(K, V)>) -> Result<(), Self::Error> {
+        for (k, v) in values {
+            self.cache_set(k, v)?;
+        }
+        Ok(())
+    }
+
     /// Remove a cached value
     ///
     /// # Errors
@@ -438,6 +892,13 @@ pub trait IOCached<K, V> {
     fn cache_set_lifespan(&mut self, _seconds: u64) -> Option<u64> {
         None
     }
+
+    /// Return latency histograms of this cache's operations
+    #[cfg(feature = "latency_stats")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "latency_stats")))]
+    fn cache_latency_stats(&self) -> Option<stats::LatencyStatsSnapshot> {
+        None
+    }
 }
 
 #[cfg(feature = "async")]
@@ -447,8 +908,49 @@ pub trait IOCachedAsync<K, V> {
     type Error;
     async fn cache_get(&self, k: &K) -> Result<Option<V>, Self::Error>;
 
+    /// Attempt to retrieve a cached value, even if expired, along with whether it expired
+    ///
+    /// Stores dropping their expired values return the unexpired ones only, which the
+    /// default does with `cache_get`.
+    async fn cache_get_expired(&self, k: &K) -> Result<(Option<V>, bool), Self::Error>
+    where
+        Self: Sync,
+        K: Sync,
+    {
+        Ok((self.cache_get(k).await?, false))
+    }
+
     async fn cache_set(&self, k: K, v: V) -> Result<Option<V>, Self::Error>;
 
+    /// Attempt to retrieve the cached values of many keys, in the same order. Stores
+    /// reaching their backend over the network look them up in a single round trip.
+    async fn cache_get_many(&self, keys: &[K]) -> Result<Vec<Option<V>>, Self::Error>
+    where
+        Self: Sync,
+        K: Sync,
+        V: Send,
+    {
+        let mut values = Vec::with_capacity(keys.len());
+        for k in keys {
+            values.push(self.cache_get(k).await?);
+        }
+        Ok(values)
+    }
+
+    /// Insert many key, value pairs, dropping the previous values. Stores reaching their
+    /// backend over the network insert them in a single round trip.
+    async fn cache_set_many(&self, values: Vec<(K, V)>) -> Result<(), Self::Error>
+    where
+        Self: Sync,
+        K: Send + 'async_trait,
+        V: Send + 'async_trait,
+    {
+        for (k, v) in values {
+            self.cache_set(k, v).await?;
+        }
+        Ok(())
+    }
+
     /// Remove a cached value
     async fn cache_remove(&self, k: &K) -> Result<Option<V>, Self::Error>;
 
@@ -464,4 +966,11 @@ pub trait IOCachedAsync<K, V> {
     fn cache_set_lifespan(&mut self, _seconds: u64) -> Option<u64> {
         None
     }
+
+    /// Return latency histograms of this cache's operations
+    #[cfg(feature = "latency_stats")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "latency_stats")))]
+    fn cache_latency_stats(&self) -> Option<stats::LatencyStatsSnapshot> {
+        None
+    }
 }
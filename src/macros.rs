@@ -249,7 +249,7 @@ macro_rules! cached {
             }
             let val = (||$body)();
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::CachedWrite::cache_set(&mut *cache, key, val.clone());
             val
         }
     };
@@ -272,7 +272,7 @@ macro_rules! cached {
             let val = inner($($arg),*).await;
 
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::CachedWrite::cache_set(&mut *cache, key, val.clone());
             val
         }
     };
@@ -297,7 +297,7 @@ macro_rules! cached_key {
             }
             let val = (||$body)();
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::CachedWrite::cache_set(&mut *cache, key, val.clone());
             val
         }
     };
@@ -320,7 +320,7 @@ macro_rules! cached_key {
             async fn inner($($arg: $argtype),*) -> $ret $body
             let val = inner($($arg),*).await;
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::CachedWrite::cache_set(&mut *cache, key, val.clone());
             val
         }
     };
@@ -348,7 +348,7 @@ macro_rules! cached_result {
             let val = ret?;
 
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::CachedWrite::cache_set(&mut *cache, key, val.clone());
             Ok(val)
         }
     };
@@ -372,7 +372,7 @@ macro_rules! cached_result {
             let val = inner($($arg),*).await?;
 
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::CachedWrite::cache_set(&mut *cache, key, val.clone());
             Ok(val)
         }
     };
@@ -401,7 +401,7 @@ macro_rules! cached_key_result {
             let val = ret?;
 
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::CachedWrite::cache_set(&mut *cache, key, val.clone());
             Ok(val)
         }
     };
@@ -426,7 +426,7 @@ macro_rules! cached_key_result {
             let val = inner($($arg),*).await?;
 
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::CachedWrite::cache_set(&mut *cache, key, val.clone());
             Ok(val)
         }
     };
@@ -458,7 +458,7 @@ macro_rules! cached_control {
             let $body_value = (||$body)();
             let $set_value = $post_exec;
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, $pre_set);
+            $crate::CachedWrite::cache_set(&mut *cache, key, $pre_set);
             let $ret_value = $set_value;
             $return
         }
@@ -489,7 +489,7 @@ macro_rules! cached_control {
             let $body_value = inner($($arg),*).await?;
             let $set_value = $post_exec;
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, $pre_set);
+            $crate::CachedWrite::cache_set(&mut *cache, key, $pre_set);
             let $ret_value = $set_value;
             $return
         }
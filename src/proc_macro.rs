@@ -331,3 +331,194 @@ pub fn main() {
 pub use cached_proc_macro::{cached, io_cached, once};
 #[doc(inline)]
 pub use cached_proc_macro_types::Return;
+
+use crate::CanExpire;
+use instant::{Duration, Instant};
+use std::time::SystemTime;
+use thiserror::Error;
+
+/// The result of a function revalidating its cached value, see the `validator`
+/// attribute of `#[io_cached]`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Revalidation<V> {
+    /// A new value, and the validator (e.g. an `ETag`) to revalidate it with
+    Modified { value: V, validator: Option<String> },
+    /// The cached value is still valid
+    NotModified,
+}
+
+/// A value cached along with the validator to revalidate it with, see the
+/// `validator` attribute of `#[io_cached]`
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Validated<V> {
+    pub value: V,
+    pub validator: Option<String>,
+    pub validated_at: SystemTime,
+}
+
+impl<V> Validated<V> {
+    /// Wrap a value just validated
+    pub fn new(value: V, validator: Option<String>) -> Self {
+        Self {
+            value,
+            validator,
+            validated_at: SystemTime::now(),
+        }
+    }
+
+    /// Mark the value as validated again
+    #[must_use]
+    pub fn revalidated(mut self) -> Self {
+        self.validated_at = SystemTime::now();
+        self
+    }
+
+    /// Return whether the value was validated less than `seconds` ago
+    pub fn is_fresh(&self, seconds: u64) -> bool {
+        // a clock set back keeps values fresh
+        self.validated_at
+            .elapsed()
+            .map_or(true, |age| age.as_secs() < seconds)
+    }
+}
+
+/// A value returned by a `#[cached(cache_policy = true)]` function along with how long to
+/// cache it, e.g. the `max-age` of an HTTP response. The cached function returns the value.
+///
+/// ```rust
+/// use cached::proc_macro::cached;
+/// use cached::CachePolicy;
+/// use std::time::Duration;
+///
+/// #[cached(cache_policy = true)]
+/// fn fetch(url: String) -> CachePolicy<String> {
+///     let max_age = if url.ends_with(".css") { 3600 } else { 60 };
+///     CachePolicy::with_ttl(format!("body of {}", url), Duration::from_secs(max_age))
+/// }
+///
+/// let body: String = fetch("/style.css".to_string());
+/// assert_eq!(body, "body of /style.css");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachePolicy<V> {
+    pub value: V,
+    /// How long to cache the value, `None` caching it until it is evicted
+    pub ttl: Option<Duration>,
+}
+
+impl<V> CachePolicy<V> {
+    /// Cache the value for `ttl`
+    pub fn with_ttl(value: V, ttl: Duration) -> Self {
+        Self {
+            value,
+            ttl: Some(ttl),
+        }
+    }
+
+    /// Cache the value until it is evicted
+    pub fn forever(value: V) -> Self {
+        Self { value, ttl: None }
+    }
+
+    fn expires_at(&self) -> Option<Instant> {
+        // a TTL too long to be represented does not expire
+        self.ttl.and_then(|ttl| Instant::now().checked_add(ttl))
+    }
+}
+
+/// A value cached by a `#[cached(cache_policy = true)]` function, along with its expiry
+#[derive(Clone, Debug)]
+pub struct Expiring<V> {
+    pub value: V,
+    pub expires_at: Option<Instant>,
+}
+
+impl<V> Expiring<V> {
+    /// Wrap a value expiring at `expires_at`, or never
+    pub fn new(value: V, expires_at: Option<Instant>) -> Self {
+        Self { value, expires_at }
+    }
+}
+
+impl<V> CanExpire for Expiring<V> {
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Instant::now())
+    }
+}
+
+/// The outputs of `cache_policy` functions, split by the cached functions into their
+/// value and its expiry
+#[doc(hidden)]
+pub trait SplitCachePolicy {
+    type Output;
+
+    fn split(self) -> (Self::Output, Option<Instant>);
+}
+
+impl<V> SplitCachePolicy for CachePolicy<V> {
+    type Output = V;
+
+    fn split(self) -> (V, Option<Instant>) {
+        let expires_at = self.expires_at();
+        (self.value, expires_at)
+    }
+}
+
+impl<V, E> SplitCachePolicy for Result<CachePolicy<V>, E> {
+    type Output = Result<V, E>;
+
+    fn split(self) -> (Result<V, E>, Option<Instant>) {
+        match self {
+            Ok(policy) => {
+                let (value, expires_at) = policy.split();
+                (Ok(value), expires_at)
+            }
+            Err(error) => (Err(error), None),
+        }
+    }
+}
+
+impl<V> SplitCachePolicy for Option<CachePolicy<V>> {
+    type Output = Option<V>;
+
+    fn split(self) -> (Option<V>, Option<Instant>) {
+        match self {
+            Some(policy) => {
+                let (value, expires_at) = policy.split();
+                (Some(value), expires_at)
+            }
+            None => (None, None),
+        }
+    }
+}
+
+/// The error of a `#[cached(timeout_ms = ...)]` function whose value took longer than its
+/// timeout to compute, converted into the error of the function with `From`
+#[derive(Error, Clone, Copy, Debug, PartialEq, Eq)]
+#[error("cached function timed out after {0:?}")]
+pub struct TimeoutError(pub Duration);
+
+/// Convert the output of a `store_as` function into the value to cache, giving the
+/// `store_as` closure the type of its argument
+#[doc(hidden)]
+pub fn store_as<T, S>(value: T, store_as: impl FnOnce(T) -> S) -> S {
+    store_as(value)
+}
+
+/// Give the `from_store` closure of a `store_as` function the type of its argument
+#[doc(hidden)]
+pub fn from_store<S, T>(from_store: impl Fn(&S) -> T) -> impl Fn(&S) -> T {
+    from_store
+}
+
+/// Report an error of the store of an `#[io_cached(on_error = "log")]` function, whose
+/// value is then computed as if it was not cached
+#[doc(hidden)]
+pub fn report_store_error(function: &str, error: &dyn std::fmt::Debug) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(function, error = ?error, "cache store error");
+    #[cfg(not(feature = "tracing"))]
+    eprintln!("cache store of `{}` failed: {:?}", function, error);
+}
@@ -0,0 +1,261 @@
+/*!
+Quarantine of keys whose refreshes keep failing
+
+A function failing permanently for some key (a deleted upstream resource, a malformed
+request, ...) is called again on every request for that key, since errors are not cached.
+A `Quarantine` counts the consecutive failures of each key and, after `max_failures`
+of them, quarantines the key for a backoff period: calls for the key fail right away
+with the last error, without calling the function. Once the period is over, the next
+call retries the function, and a new failure quarantines the key again.
+
+`#[cached(result = true, quarantine_after = N)]` generates a `{CACHE}_QUARANTINE`
+static doing so, quarantining keys for `quarantine_secs` seconds (60 by default). Used
+with `result_fallback`, quarantined keys are served their expired value, if any.
+
+```rust
+use cached::proc_macro::cached;
+
+#[cached(result = true, quarantine_after = 2, quarantine_secs = 300)]
+fn fetch(id: u32) -> Result<String, String> {
+    Err(format!("{} not found", id))
+}
+
+assert!(fetch(1).is_err());
+assert!(fetch(1).is_err());
+// the key is quarantined, `fetch` is not called for 5 minutes
+assert!(fetch(1).is_err());
+
+let stats = FETCH_QUARANTINE.stats();
+assert_eq!(stats.quarantined, 1);
+assert_eq!(stats.rejections, 1);
+```
+*/
+
+use instant::{Duration, Instant};
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+#[cfg(feature = "ahash")]
+use hashbrown::{hash_map::Entry, HashMap};
+
+#[cfg(not(feature = "ahash"))]
+use std::collections::{hash_map::Entry, HashMap};
+
+/// Consecutive failures of the keys of a cache, quarantining the keys failing repeatedly
+pub struct Quarantine<K, E> {
+    max_failures: u32,
+    duration: Duration,
+    streaks: Mutex<Streaks<K, E>>,
+    quarantines: AtomicU64,
+    rejections: AtomicU64,
+}
+
+/// The failure streaks of the keys, and when the stale ones were last pruned
+struct Streaks<K, E> {
+    keys: HashMap<K, Streak<E>>,
+    pruned_at: Instant,
+}
+
+/// The consecutive failures of a key, the last one, and the end of its quarantine
+struct Streak<E> {
+    failures: u32,
+    error: E,
+    failed_at: Instant,
+    until: Option<Instant>,
+}
+
+/// Statistics of a `Quarantine`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuarantineStats {
+    /// The number of keys currently quarantined
+    pub quarantined: usize,
+    /// The number of times keys were quarantined
+    pub quarantines: u64,
+    /// The number of calls rejected because their key was quarantined
+    pub rejections: u64,
+}
+
+impl<K: Hash + Eq, E> Quarantine<K, E> {
+    /// Create a `Quarantine` quarantining keys for `duration` after `max_failures`
+    /// consecutive failures
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `max_failures` is 0
+    #[must_use]
+    pub fn new(max_failures: u32, duration: Duration) -> Self {
+        if max_failures == 0 {
+            panic!("`max_failures` of `Quarantine` must be greater than zero.");
+        }
+        Self {
+            max_failures,
+            duration,
+            streaks: Mutex::new(Streaks {
+                keys: HashMap::new(),
+                pruned_at: Instant::now(),
+            }),
+            quarantines: AtomicU64::new(0),
+            rejections: AtomicU64::new(0),
+        }
+    }
+
+    /// Return the last error of `key` if it is quarantined, counting the call as rejected
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the quarantine is poisoned
+    pub fn check<Q>(&self, key: &Q) -> Option<E>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        E: Clone,
+    {
+        let streaks = self.streaks.lock().unwrap();
+        let streak = streaks.keys.get(key)?;
+        match streak.until {
+            Some(until) if Instant::now() < until => {
+                self.rejections.fetch_add(1, Ordering::Relaxed);
+                Some(streak.error.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Record a failure of `key`, returns `true` if the key is now quarantined
+    ///
+    /// The streaks of the other keys which have neither failed nor been quarantined
+    /// for the duration of a quarantine are forgotten, at most once per duration, so
+    /// that the keys failing once do not accumulate.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the quarantine is poisoned
+    pub fn record_failure(&self, key: K, error: E) -> bool {
+        let now = Instant::now();
+        let mut streaks = self.streaks.lock().unwrap();
+        if now.duration_since(streaks.pruned_at) >= self.duration {
+            let duration = self.duration;
+            streaks.keys.retain(|other, streak| {
+                *other == key || now.duration_since(streak.failed_at) < duration
+            });
+            streaks.pruned_at = now;
+        }
+        let streak = match streaks.keys.entry(key) {
+            Entry::Occupied(occupied) => {
+                let streak = occupied.into_mut();
+                streak.failures += 1;
+                streak.error = error;
+                streak.failed_at = now;
+                streak
+            }
+            Entry::Vacant(vacant) => vacant.insert(Streak {
+                failures: 1,
+                error,
+                failed_at: now,
+                until: None,
+            }),
+        };
+        if streak.failures < self.max_failures {
+            return false;
+        }
+        streak.until = Some(now + self.duration);
+        self.quarantines.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Record a success of `key`, ending its failure streak
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the quarantine is poisoned
+    pub fn record_success<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.streaks.lock().unwrap().keys.remove(key);
+    }
+
+    /// Release all the keys and forget their failures
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the quarantine is poisoned
+    pub fn clear(&self) {
+        self.streaks.lock().unwrap().keys.clear();
+    }
+
+    /// Return the statistics of the quarantine
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the lock of the quarantine is poisoned
+    pub fn stats(&self) -> QuarantineStats {
+        let now = Instant::now();
+        let quarantined = self
+            .streaks
+            .lock()
+            .unwrap()
+            .keys
+            .values()
+            .filter(|streak| streak.until.is_some_and(|until| now < until))
+            .count();
+        QuarantineStats {
+            quarantined,
+            quarantines: self.quarantines.load(Ordering::Relaxed),
+            rejections: self.rejections.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quarantine_after_max_failures() {
+        let quarantine = Quarantine::new(2, Duration::from_millis(50));
+        assert!(!quarantine.record_failure(1, "a"));
+        assert_eq!(quarantine.check(&1), None);
+        assert!(quarantine.record_failure(1, "b"));
+        assert_eq!(quarantine.check(&1), Some("b"));
+        assert_eq!(quarantine.check(&2), None);
+        assert_eq!(
+            quarantine.stats(),
+            QuarantineStats {
+                quarantined: 1,
+                quarantines: 1,
+                rejections: 1,
+            }
+        );
+
+        // a retry after the quarantine fails again
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(quarantine.check(&1), None);
+        assert!(quarantine.record_failure(1, "c"));
+        assert_eq!(quarantine.check(&1), Some("c"));
+
+        quarantine.record_success(&1);
+        assert_eq!(quarantine.check(&1), None);
+        assert_eq!(quarantine.stats().quarantined, 0);
+        assert_eq!(quarantine.stats().quarantines, 2);
+    }
+
+    #[test]
+    fn stale_streaks_are_forgotten() {
+        let quarantine = Quarantine::new(2, Duration::from_millis(50));
+        for key in 0..100 {
+            quarantine.record_failure(key, "a");
+        }
+        assert!(quarantine.record_failure(0, "b"));
+        assert_eq!(quarantine.streaks.lock().unwrap().keys.len(), 100);
+
+        std::thread::sleep(Duration::from_millis(60));
+        // the quarantined key retried after its quarantine is quarantined again
+        assert!(quarantine.record_failure(0, "c"));
+        assert_eq!(quarantine.streaks.lock().unwrap().keys.len(), 1);
+        assert_eq!(quarantine.check(&0), Some("c"));
+    }
+}
@@ -0,0 +1,712 @@
+/*!
+Registry of the caches generated by the procedural macros
+
+Caches defined with `#[cached]` and `#[once]` register themselves here the
+first time they are used, so that their runtime statistics can be collected
+from a single place (e.g. a metrics or admin endpoint) without knowing every
+cached function of the application.
+
+## Lock contention
+
+Every cache static generated by `#[cached]` and `#[once]` is guarded by a
+`Mutex`/`RwLock`. With the `lock_stats` feature enabled, the generated code
+records how long each call waited to acquire the lock and how long it then
+held it. Without the feature the statistics stay empty and recording compiles
+down to nothing.
+
+```rust
+use cached::proc_macro::cached;
+
+#[cached]
+fn slow_double(n: u64) -> u64 {
+    n * 2
+}
+
+slow_double(21);
+for (name, stats) in cached::registry::lock_stats() {
+    println!(
+        "{}: {} acquisitions, waited {:?} in total ({:?} at most)",
+        name, stats.acquisitions, stats.total_wait, stats.max_wait
+    );
+}
+```
+
+## Cache statistics
+
+Every cache static generated by `#[cached]` also counts its hits and misses and records its
+size in atomic counters, read by the generated `{fn}_cache_stats()` function and by
+`cache_stats` without locking the cache, e.g. from a metrics endpoint.
+
+```rust
+use cached::proc_macro::cached;
+
+#[cached]
+fn slow_negate(n: i64) -> i64 {
+    -n
+}
+
+slow_negate(1);
+slow_negate(1);
+let stats = slow_negate_cache_stats();
+assert_eq!((stats.hits, stats.misses, stats.size), (1, 1, 1));
+for (name, stats) in cached::registry::cache_stats() {
+    println!("{}: {} hits, {} misses, {} values", name, stats.hits, stats.misses, stats.size);
+}
+```
+
+With the `metrics` feature enabled, these statistics are also reported through the `metrics`
+crate, labeled with the name of the cache as `cache`: the `cache.hits`, `cache.misses` and
+`cache.evictions` counters, and the `cache.load_time` histogram of the time taken to compute
+the missing values, in seconds. The caches show up in whichever exporter (Prometheus,
+StatsD, ...) the application installs, without any code at the call sites.
+
+## Warmth
+
+Caches defined with `#[cached(warm_size = N)]` report how warm they are: the fraction
+of the `N` values expected to be cached once the application is warmed up. `ready`
+aggregates the warmth of all these caches, e.g. for a readiness probe. Caches register
+themselves the first time they are used, so prime them before reporting readiness.
+
+```rust
+use cached::proc_macro::cached;
+
+#[cached(size = 100, warm_size = 2)]
+fn slow_triple(n: u64) -> u64 {
+    n * 3
+}
+
+slow_triple_prime_cache(1);
+assert!(!cached::registry::ready());
+slow_triple_prime_cache(2);
+assert!(cached::registry::ready());
+for (name, warmth) in cached::registry::warmth() {
+    println!("{}: {:.0}% warm", name, warmth * 100.0);
+}
+```
+
+## Config epoch
+
+Caches defined with `#[cached(config_epoch = true)]` or `#[io_cached(config_epoch = true)]`
+include the process-wide config epoch in their keys. Bumping the epoch, e.g. when the
+configuration is reloaded, makes all these functions compute their values again without
+clearing each cache. The values of previous epochs are no longer returned, and the first
+value cached in the new epoch clears them, so that unbound caches do not keep them.
+
+```rust
+use cached::proc_macro::cached;
+
+#[cached(config_epoch = true)]
+fn greeting(name: String) -> String {
+    format!("hello {}", name)
+}
+
+greeting("world".to_string());
+cached::registry::bump_config_epoch();
+// computed again
+greeting("world".to_string());
+```
+
+## Invalidation
+
+Caches defined with `#[cached(invalidation = true)]` (feature `key_invalidation`) register a
+function removing the value of the arguments given as a JSON array, so that operators can
+invalidate specific values, e.g. through an admin endpoint, knowing only the name of the cached
+function (`{module path}::{function}`) and its arguments. The arguments must implement
+`serde::Deserialize`.
+
+```rust,ignore
+use cached::proc_macro::cached;
+
+#[cached(invalidation = true)]
+fn price(sku: String, currency: String) -> u64 {
+    42
+}
+
+price("abc".to_string(), "EUR".to_string());
+let name = concat!(module_path!(), "::price");
+assert_eq!(cached::registry::invalidate(name, r#"["abc", "EUR"]"#), Ok(true));
+```
+*/
+
+use instant::Duration;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use thiserror::Error;
+
+#[derive(Default)]
+struct Registry {
+    locks: BTreeMap<&'static str, &'static LockStats>,
+    caches: BTreeMap<&'static str, &'static CacheStats>,
+    warmths: BTreeMap<&'static str, &'static Warmth>,
+    invalidations: BTreeMap<&'static str, &'static Invalidation>,
+}
+
+static REGISTRY: Lazy<Mutex<Registry>> = Lazy::new(|| Mutex::new(Registry::default()));
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+    // the registry only holds plain maps, so a panic while holding
+    // the lock cannot leave it in an inconsistent state
+    REGISTRY
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Register the lock statistics of a cache, replacing any statistics
+/// previously registered under the same name
+pub fn register_lock_stats(stats: &'static LockStats) {
+    registry().locks.insert(stats.name(), stats);
+}
+
+/// Return a snapshot of the lock statistics of every registered cache, ordered by name
+#[must_use]
+pub fn lock_stats() -> Vec<(&'static str, LockStatsSnapshot)> {
+    registry()
+        .locks
+        .iter()
+        .map(|(name, stats)| (*name, stats.snapshot()))
+        .collect()
+}
+
+/// Register the hit, miss and size statistics of a cache, replacing any statistics
+/// previously registered under the same name
+pub fn register_cache_stats(stats: &'static CacheStats) {
+    registry().caches.insert(stats.name(), stats);
+}
+
+/// Return a snapshot of the hit, miss and size statistics of every registered cache,
+/// ordered by name
+#[must_use]
+pub fn cache_stats() -> Vec<(&'static str, CacheStatsSnapshot)> {
+    registry()
+        .caches
+        .iter()
+        .map(|(name, stats)| (*name, stats.snapshot()))
+        .collect()
+}
+
+/// Register the warmth of a cache, replacing any warmth
+/// previously registered under the same name
+pub fn register_warmth(warmth: &'static Warmth) {
+    registry().warmths.insert(warmth.name(), warmth);
+}
+
+/// Return the warmth of every registered cache, ordered by name
+#[must_use]
+pub fn warmth() -> Vec<(&'static str, f64)> {
+    registry()
+        .warmths
+        .iter()
+        .map(|(name, warmth)| (*name, warmth.warmth()))
+        .collect()
+}
+
+/// Return whether every registered cache holds the number of values it is expected to
+/// hold once warmed up. Caches which were not used yet are not registered.
+#[must_use]
+pub fn ready() -> bool {
+    registry().warmths.values().all(|warmth| warmth.is_ready())
+}
+
+/// Register the invalidation of a cache, replacing any invalidation
+/// previously registered under the same name
+pub fn register_invalidation(invalidation: &'static Invalidation) {
+    registry()
+        .invalidations
+        .insert(invalidation.name(), invalidation);
+}
+
+/// Return the names of the caches which can be invalidated, ordered by name
+#[must_use]
+pub fn invalidations() -> Vec<&'static str> {
+    registry().invalidations.keys().copied().collect()
+}
+
+/// Remove the value of `serialized_key`, the JSON array of the arguments of the cached
+/// function, from the cache called `cache_name`, returning whether a value was removed
+///
+/// # Errors
+///
+/// Will return an `InvalidationError` if the cache is not registered, the arguments
+/// cannot be deserialized, or the lock of an async cache is held
+pub fn invalidate(cache_name: &str, serialized_key: &str) -> Result<bool, InvalidationError> {
+    let invalidation = registry()
+        .invalidations
+        .get(cache_name)
+        .copied()
+        .ok_or_else(|| InvalidationError::UnknownCache(cache_name.to_string()))?;
+    (invalidation.invalidate)(serialized_key)
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum InvalidationError {
+    #[error("no cache `{0}` can be invalidated, or it was not used yet")]
+    UnknownCache(String),
+    #[error("the arguments do not deserialize: {0}")]
+    InvalidKey(String),
+    #[error("the lock of the cache is held, try again")]
+    Locked,
+}
+
+/// Invalidation of a cache: removes the value of the arguments given as a JSON array
+#[derive(Debug)]
+pub struct Invalidation {
+    name: &'static str,
+    invalidate: fn(&str) -> Result<bool, InvalidationError>,
+}
+
+impl Invalidation {
+    /// Create the invalidation of the cache called `name`, calling `invalidate`
+    /// with the JSON array of the arguments
+    #[must_use]
+    pub const fn new(
+        name: &'static str,
+        invalidate: fn(&str) -> Result<bool, InvalidationError>,
+    ) -> Self {
+        Self { name, invalidate }
+    }
+
+    /// Return the name of the cache
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+static CONFIG_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// Return the current config epoch, included in the keys of the caches
+/// defined with `config_epoch = true`
+#[must_use]
+pub fn config_epoch() -> u64 {
+    CONFIG_EPOCH.load(Ordering::Acquire)
+}
+
+/// Bump the config epoch, invalidating the values of all the caches defined
+/// with `config_epoch = true`, and return the new epoch
+pub fn bump_config_epoch() -> u64 {
+    CONFIG_EPOCH.fetch_add(1, Ordering::AcqRel) + 1
+}
+
+/// Hit, miss and size statistics of a cache, readable without locking it
+#[derive(Debug)]
+pub struct CacheStats {
+    name: &'static str,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    size: AtomicUsize,
+}
+
+impl CacheStats {
+    /// Create empty statistics for the cache called `name`
+    #[must_use]
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            size: AtomicUsize::new(0),
+        }
+    }
+
+    /// Return the name of the cache
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Record a call served from the cache
+    pub fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("cache.hits", "cache" => self.name).increment(1);
+    }
+
+    /// Record a call computing its value
+    pub fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("cache.misses", "cache" => self.name).increment(1);
+    }
+
+    /// Record a value evicted from the cache to make room for another one
+    pub fn record_eviction(&self) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("cache.evictions", "cache" => self.name).increment(1);
+    }
+
+    /// Record the time taken to compute a missing value. Only reported through `metrics`.
+    #[allow(unused_variables)]
+    pub fn record_load(&self, load: Duration) {
+        #[cfg(feature = "metrics")]
+        metrics::histogram!("cache.load_time", "cache" => self.name).record(load.as_secs_f64());
+    }
+
+    /// Record the current size of the cache
+    pub fn record_size(&self, size: usize) {
+        self.size.store(size, Ordering::Relaxed);
+    }
+
+    /// Reset the hit, miss and eviction counts to zero
+    pub fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        self.evictions.store(0, Ordering::Relaxed);
+    }
+
+    /// Take a point-in-time copy of the statistics
+    #[must_use]
+    pub fn snapshot(&self) -> CacheStatsSnapshot {
+        CacheStatsSnapshot {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            size: self.size.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A point-in-time copy of `CacheStats`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStatsSnapshot {
+    /// Number of calls served from the cache
+    pub hits: u64,
+    /// Number of calls computing their value
+    pub misses: u64,
+    /// Number of values evicted to make room for other ones
+    pub evictions: u64,
+    /// Number of values cached at the last change of the cache
+    pub size: usize,
+}
+
+/// Warmth of a cache: its size compared to the size it is expected to reach once warmed up
+#[derive(Debug)]
+pub struct Warmth {
+    name: &'static str,
+    expected: usize,
+    size: AtomicUsize,
+}
+
+impl Warmth {
+    /// Create the warmth of the empty cache called `name`, expected to hold `expected` values
+    #[must_use]
+    pub const fn new(name: &'static str, expected: usize) -> Self {
+        Self {
+            name,
+            expected,
+            size: AtomicUsize::new(0),
+        }
+    }
+
+    /// Return the name of the cache
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Return the number of values the cache is expected to hold once warmed up
+    #[must_use]
+    pub fn expected(&self) -> usize {
+        self.expected
+    }
+
+    /// Record the current size of the cache
+    pub fn record_size(&self, size: usize) {
+        self.size.store(size, Ordering::Relaxed);
+    }
+
+    /// Return the fraction of the expected values held by the cache, from `0.0` to `1.0`
+    #[must_use]
+    pub fn warmth(&self) -> f64 {
+        fraction_of(self.size.load(Ordering::Relaxed), self.expected)
+    }
+
+    /// Return whether the cache holds the expected number of values
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.size.load(Ordering::Relaxed) >= self.expected
+    }
+}
+
+/// Return the fraction of `expected` that `size` amounts to, capped to `1.0`
+pub(crate) fn fraction_of(size: usize, expected: usize) -> f64 {
+    if size >= expected {
+        1.0
+    } else {
+        size as f64 / expected as f64
+    }
+}
+
+/// Wait and hold time statistics of the lock guarding a cache
+#[derive(Debug)]
+pub struct LockStats {
+    name: &'static str,
+    acquisitions: AtomicU64,
+    total_wait_nanos: AtomicU64,
+    max_wait_nanos: AtomicU64,
+    total_hold_nanos: AtomicU64,
+    max_hold_nanos: AtomicU64,
+}
+
+impl LockStats {
+    /// Create empty statistics for the lock of the cache called `name`
+    #[must_use]
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            acquisitions: AtomicU64::new(0),
+            total_wait_nanos: AtomicU64::new(0),
+            max_wait_nanos: AtomicU64::new(0),
+            total_hold_nanos: AtomicU64::new(0),
+            max_hold_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Return the name of the cache
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Record the time spent waiting to acquire the lock
+    pub fn record_wait(&self, wait: Duration) {
+        let nanos = as_nanos(wait);
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        self.total_wait_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_wait_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// Record the time the lock was held
+    pub fn record_hold(&self, hold: Duration) {
+        let nanos = as_nanos(hold);
+        self.total_hold_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_hold_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// Reset all statistics to zero
+    pub fn reset(&self) {
+        self.acquisitions.store(0, Ordering::Relaxed);
+        self.total_wait_nanos.store(0, Ordering::Relaxed);
+        self.max_wait_nanos.store(0, Ordering::Relaxed);
+        self.total_hold_nanos.store(0, Ordering::Relaxed);
+        self.max_hold_nanos.store(0, Ordering::Relaxed);
+    }
+
+    /// Take a point-in-time copy of the statistics
+    #[must_use]
+    pub fn snapshot(&self) -> LockStatsSnapshot {
+        let load = |value: &AtomicU64| Duration::from_nanos(value.load(Ordering::Relaxed));
+        LockStatsSnapshot {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            total_wait: load(&self.total_wait_nanos),
+            max_wait: load(&self.max_wait_nanos),
+            total_hold: load(&self.total_hold_nanos),
+            max_hold: load(&self.max_hold_nanos),
+        }
+    }
+}
+
+fn as_nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// A point-in-time copy of `LockStats`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LockStatsSnapshot {
+    /// Number of times the lock was acquired
+    pub acquisitions: u64,
+    /// Total time spent waiting to acquire the lock
+    pub total_wait: Duration,
+    /// Longest time spent waiting to acquire the lock
+    pub max_wait: Duration,
+    /// Total time the lock was held
+    pub total_hold: Duration,
+    /// Longest time the lock was held
+    pub max_hold: Duration,
+}
+
+/// Measures the time spent acquiring a cache lock. Used by the code generated by the macros.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct LockTimer {
+    #[cfg(feature = "lock_stats")]
+    started: instant::Instant,
+}
+
+impl LockTimer {
+    #[inline]
+    #[must_use]
+    pub fn start() -> Self {
+        Self {
+            #[cfg(feature = "lock_stats")]
+            started: instant::Instant::now(),
+        }
+    }
+
+    /// Record the wait time, returning a guard recording the hold time when dropped
+    #[inline]
+    #[must_use]
+    #[allow(unused_variables)]
+    pub fn acquired(self, stats: &'static LockStats) -> LockHold {
+        #[cfg(feature = "lock_stats")]
+        stats.record_wait(self.started.elapsed());
+        LockHold {
+            #[cfg(feature = "lock_stats")]
+            stats,
+            #[cfg(feature = "lock_stats")]
+            acquired: instant::Instant::now(),
+        }
+    }
+}
+
+/// Measures the time taken to compute a missing value when the `metrics` feature is enabled.
+/// Used by the code generated by the macros.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct LoadTimer {
+    #[cfg(feature = "metrics")]
+    started: instant::Instant,
+}
+
+impl LoadTimer {
+    #[inline]
+    #[must_use]
+    pub fn start() -> Self {
+        Self {
+            #[cfg(feature = "metrics")]
+            started: instant::Instant::now(),
+        }
+    }
+
+    /// Record the time elapsed since the timer started as a load of the cache
+    #[inline]
+    #[allow(unused_variables)]
+    pub fn finished(self, stats: &CacheStats) {
+        #[cfg(feature = "metrics")]
+        stats.record_load(self.started.elapsed());
+    }
+}
+
+/// Records the time a cache lock is held when dropped. Used by the code generated by the macros.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct LockHold {
+    #[cfg(feature = "lock_stats")]
+    stats: &'static LockStats,
+    #[cfg(feature = "lock_stats")]
+    acquired: instant::Instant,
+}
+
+#[cfg(feature = "lock_stats")]
+impl Drop for LockHold {
+    fn drop(&mut self) {
+        self.stats.record_hold(self.acquired.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TEST_LOCK_STATS: LockStats = LockStats::new("registry::tests::lock");
+
+    #[test]
+    fn lock_stats_are_registered() {
+        register_lock_stats(&TEST_LOCK_STATS);
+        TEST_LOCK_STATS.record_wait(Duration::from_millis(2));
+        TEST_LOCK_STATS.record_wait(Duration::from_millis(1));
+        TEST_LOCK_STATS.record_hold(Duration::from_millis(5));
+
+        let (_, stats) = lock_stats()
+            .into_iter()
+            .find(|(name, _)| *name == "registry::tests::lock")
+            .unwrap();
+        assert_eq!(stats.acquisitions, 2);
+        assert_eq!(stats.total_wait, Duration::from_millis(3));
+        assert_eq!(stats.max_wait, Duration::from_millis(2));
+        assert_eq!(stats.max_hold, Duration::from_millis(5));
+
+        TEST_LOCK_STATS.reset();
+        assert_eq!(TEST_LOCK_STATS.snapshot(), LockStatsSnapshot::default());
+    }
+
+    static TEST_CACHE_STATS: CacheStats = CacheStats::new("registry::tests::cache");
+
+    #[test]
+    fn cache_stats_are_registered() {
+        register_cache_stats(&TEST_CACHE_STATS);
+        TEST_CACHE_STATS.record_miss();
+        TEST_CACHE_STATS.record_size(1);
+        TEST_CACHE_STATS.record_hit();
+        TEST_CACHE_STATS.record_hit();
+        TEST_CACHE_STATS.record_eviction();
+
+        let (_, stats) = cache_stats()
+            .into_iter()
+            .find(|(name, _)| *name == "registry::tests::cache")
+            .unwrap();
+        assert_eq!(
+            stats,
+            CacheStatsSnapshot {
+                hits: 2,
+                misses: 1,
+                evictions: 1,
+                size: 1
+            }
+        );
+
+        TEST_CACHE_STATS.reset();
+        assert_eq!(TEST_CACHE_STATS.snapshot().hits, 0);
+        assert_eq!(TEST_CACHE_STATS.snapshot().evictions, 0);
+        assert_eq!(TEST_CACHE_STATS.snapshot().size, 1);
+    }
+
+    static TEST_WARMTH: Warmth = Warmth::new("registry::tests::warmth", 4);
+
+    #[test]
+    fn warmth_is_registered() {
+        register_warmth(&TEST_WARMTH);
+        TEST_WARMTH.record_size(1);
+        assert_eq!(
+            warmth()
+                .into_iter()
+                .find(|(name, _)| *name == "registry::tests::warmth"),
+            Some(("registry::tests::warmth", 0.25))
+        );
+        assert!(!ready());
+
+        TEST_WARMTH.record_size(5);
+        assert_eq!(TEST_WARMTH.warmth(), 1.0);
+        assert!(TEST_WARMTH.is_ready());
+    }
+
+    static TEST_INVALIDATION: Invalidation =
+        Invalidation::new("registry::tests::invalidation", |key| match key {
+            "[1]" => Ok(true),
+            "[2]" => Ok(false),
+            _ => Err(InvalidationError::InvalidKey(key.to_string())),
+        });
+
+    #[test]
+    fn invalidation_is_registered() {
+        assert_eq!(
+            invalidate("registry::tests::invalidation", "[1]"),
+            Err(InvalidationError::UnknownCache(
+                "registry::tests::invalidation".to_string()
+            ))
+        );
+        register_invalidation(&TEST_INVALIDATION);
+        assert!(invalidations().contains(&"registry::tests::invalidation"));
+        assert_eq!(invalidate("registry::tests::invalidation", "[1]"), Ok(true));
+        assert_eq!(
+            invalidate("registry::tests::invalidation", "[2]"),
+            Ok(false)
+        );
+        assert!(invalidate("registry::tests::invalidation", "1").is_err());
+    }
+}
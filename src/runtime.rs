@@ -0,0 +1,227 @@
+/*!
+The async runtime running the timers and background tasks of the generated functions
+
+The locks of the caches of async functions are `tokio`'s `sync` primitives, which do not
+depend on a runtime and work with any executor. Only the timers (`timeout_ms`,
+`refresh_every`) and the tasks refreshing values in the background (`stale_while_refresh`,
+`refresh_every`) need a runtime: `tokio`'s by default, whose timers panic outside of a
+`tokio` runtime, or `async-std`'s with the `async_std_rt` feature. The timers and tasks
+of `async-std` are run by their own threads, so they work with `async-std`, `smol` and
+`tokio` applications alike.
+
+```rust,no_run
+use cached::proc_macro::cached;
+
+#[derive(Debug)]
+struct TimedOut;
+
+impl From<cached::proc_macro::TimeoutError> for TimedOut {
+    fn from(_: cached::proc_macro::TimeoutError) -> Self {
+        TimedOut
+    }
+}
+
+#[cached(result = true, timeout_ms = 500)]
+async fn fetch(id: u64) -> Result<String, TimedOut> {
+    Ok(format!("fetched {}", id))
+}
+
+# #[cfg(feature = "async_std_rt")]
+#[async_std::main]
+async fn main() {
+    assert_eq!(fetch(1).await.unwrap(), "fetched 1");
+}
+# #[cfg(not(feature = "async_std_rt"))]
+# fn main() {}
+```
+
+The background tasks can instead be spawned by a `Spawn`, e.g. the `Handle` of a `tokio`
+runtime dedicated to the maintenance of the caches: the one passed to `set_spawner` for
+all of them, or the one of a function given by `#[once(spawner = "..")]`.
+
+```rust,no_run
+use cached::runtime::set_spawner;
+
+// a runtime dedicated to the maintenance of the caches, driven by its own thread
+let maintenance = tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+    .unwrap();
+set_spawner(maintenance.handle().clone()).unwrap();
+std::thread::spawn(move || maintenance.block_on(std::future::pending::<()>()));
+```
+
+The async Redis store picks its runtime with the `redis_tokio` or `redis_async_std` feature.
+*/
+
+use once_cell::sync::OnceCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+#[cfg(feature = "async_std_rt")]
+use async_std::{
+    future::timeout as rt_timeout,
+    task::{sleep as rt_sleep, spawn as rt_spawn},
+};
+#[cfg(not(feature = "async_std_rt"))]
+use tokio::{
+    spawn as rt_spawn,
+    time::{sleep as rt_sleep, timeout as rt_timeout},
+};
+
+/// The error of a future not completed before its timeout
+#[derive(Error, Clone, Copy, Debug, PartialEq, Eq)]
+#[error("deadline has elapsed")]
+pub struct Elapsed;
+
+/// Wait until `duration` has elapsed
+pub async fn sleep(duration: Duration) {
+    rt_sleep(duration).await;
+}
+
+/// Await `future`, cancelling it if it does not complete within `duration`
+///
+/// # Errors
+///
+/// Will return `Elapsed` if `future` did not complete within `duration`
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, Elapsed> {
+    rt_timeout(duration, future).await.map_err(|_| Elapsed)
+}
+
+/// A future run in the background
+pub type BackgroundTask = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Spawner of the background tasks of the caches
+pub trait Spawn: Send + Sync {
+    /// Run `task` in the background, without waiting for it to complete
+    fn spawn_task(&self, task: BackgroundTask);
+}
+
+impl Spawn for tokio::runtime::Handle {
+    fn spawn_task(&self, task: BackgroundTask) {
+        self.spawn(task);
+    }
+}
+
+impl<S: Spawn + ?Sized> Spawn for &S {
+    fn spawn_task(&self, task: BackgroundTask) {
+        (**self).spawn_task(task);
+    }
+}
+
+impl<S: Spawn + ?Sized> Spawn for Box<S> {
+    fn spawn_task(&self, task: BackgroundTask) {
+        (**self).spawn_task(task);
+    }
+}
+
+impl<S: Spawn + ?Sized> Spawn for Arc<S> {
+    fn spawn_task(&self, task: BackgroundTask) {
+        (**self).spawn_task(task);
+    }
+}
+
+/// The error of a spawner set more than once
+#[derive(Error, Clone, Copy, Debug, PartialEq, Eq)]
+#[error("the spawner of the background tasks is already set")]
+pub struct SpawnerAlreadySet;
+
+static SPAWNER: OnceCell<Box<dyn Spawn>> = OnceCell::new();
+
+/// Spawn the background tasks of the caches with `spawner` rather than the async runtime
+///
+/// # Errors
+///
+/// Will return `SpawnerAlreadySet` if a spawner was already set
+pub fn set_spawner<S: Spawn + 'static>(spawner: S) -> Result<(), SpawnerAlreadySet> {
+    SPAWNER
+        .set(Box::new(spawner))
+        .map_err(|_| SpawnerAlreadySet)
+}
+
+/// Run `future` in the background, without waiting for it to complete, with the spawner
+/// set by `set_spawner` or else the async runtime
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    match SPAWNER.get() {
+        Some(spawner) => spawner.spawn_task(Box::pin(future)),
+        None => {
+            rt_spawn(future);
+        }
+    }
+}
+
+/// Run `future` in the background on the `tokio` runtime of the caller, whatever the
+/// spawner set by `set_spawner` and the runtime of the `async_std_rt` feature
+///
+/// # Panics
+///
+/// Will panic if called outside of a `tokio` runtime
+pub fn spawn_tokio<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(future);
+}
+
+/// Run `future` in the background with `spawner`
+pub fn spawn_with<S, F>(spawner: &S, future: F)
+where
+    S: Spawn + ?Sized,
+    F: Future<Output = ()> + Send + 'static,
+{
+    spawner.spawn_task(Box::pin(future));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    async fn timeout_and_spawn() {
+        assert_eq!(timeout(Duration::from_millis(50), async { 1 }).await, Ok(1));
+        assert_eq!(
+            timeout(Duration::from_millis(50), sleep(Duration::from_secs(1))).await,
+            Err(Elapsed)
+        );
+
+        let spawned = Arc::new(AtomicBool::new(false));
+        let done = Arc::clone(&spawned);
+        spawn(async move { done.store(true, Ordering::SeqCst) });
+        sleep(Duration::from_millis(50)).await;
+        assert!(spawned.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn spawn_with_handle() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        spawn_with(runtime.handle(), async move {
+            sleep(Duration::from_millis(10)).await;
+            sender.send(()).unwrap();
+        });
+        runtime.block_on(sleep(Duration::from_millis(50)));
+        assert!(receiver.try_recv().is_ok());
+    }
+
+    #[cfg(not(feature = "async_std_rt"))]
+    #[tokio::test]
+    async fn tokio_runtime() {
+        timeout_and_spawn().await;
+    }
+
+    #[cfg(feature = "async_std_rt")]
+    #[async_std::test]
+    async fn async_std_runtime() {
+        timeout_and_spawn().await;
+    }
+}
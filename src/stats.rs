@@ -0,0 +1,323 @@
+/*!
+Latency statistics of cache operations
+
+Stores record how long their operations take in HDR-style histograms: every
+power-of-two range of nanoseconds is split into a fixed number of linear
+sub-buckets, so the relative error of any recorded duration is bounded
+regardless of its magnitude. Recording is lock-free and only takes `&self`,
+so IO-backed stores can record without extra synchronization.
+
+```rust
+use cached::{Cached, CachedRead, CachedWrite, SizedCache};
+
+let mut cache = SizedCache::with_size(10);
+cache.cache_set(1, 100);
+cache.cache_get(&1);
+cache.cache_get(&2);
+
+let stats = cache.cache_latency_stats().unwrap();
+assert_eq!(stats.insert.count(), 1);
+assert_eq!(stats.get_hit.count(), 1);
+assert_eq!(stats.get_miss.count(), 1);
+println!("p99 hit latency: {:?}", stats.get_hit.quantile(0.99));
+```
+*/
+
+use instant::Duration;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// each power-of-two range of nanoseconds is split into 2^SUB_BUCKET_BITS buckets
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+// durations of 2^MAX_MAGNITUDE nanoseconds (~18 minutes) or longer share the last bucket
+const MAX_MAGNITUDE: u32 = 40;
+const BUCKETS: usize = (MAX_MAGNITUDE - SUB_BUCKET_BITS + 2) as usize * SUB_BUCKETS;
+
+fn bucket_index(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    let magnitude = 63 - nanos.leading_zeros();
+    let shift = magnitude - SUB_BUCKET_BITS;
+    let sub_bucket = (nanos >> shift) as usize - SUB_BUCKETS;
+    ((shift as usize + 1) * SUB_BUCKETS + sub_bucket).min(BUCKETS - 1)
+}
+
+fn bucket_lower_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = index / SUB_BUCKETS - 1;
+    let sub_bucket = index % SUB_BUCKETS;
+    ((SUB_BUCKETS + sub_bucket) as u64) << shift
+}
+
+/// Operations whose latency is recorded
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// A lookup that found a valid value
+    GetHit,
+    /// A lookup that found no (or an expired) value
+    GetMiss,
+    /// Inserting a value
+    Insert,
+    /// A round-trip to the backend of an IO store (redis, disk, ...)
+    Backend,
+}
+
+/// A lock-free latency histogram with logarithmically sized buckets
+pub struct LatencyHistogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    total_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+}
+
+impl LatencyHistogram {
+    /// Create an empty histogram
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            total_nanos: AtomicU64::new(0),
+            max_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a single duration
+    pub fn record(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[bucket_index(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// Remove all recorded durations
+    pub fn reset(&self) {
+        for bucket in self.buckets.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.count.store(0, Ordering::Relaxed);
+        self.total_nanos.store(0, Ordering::Relaxed);
+        self.max_nanos.store(0, Ordering::Relaxed);
+    }
+
+    /// Take a point-in-time copy of the recorded durations
+    #[must_use]
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            buckets: self
+                .buckets
+                .iter()
+                .enumerate()
+                .filter_map(|(index, bucket)| {
+                    let count = bucket.load(Ordering::Relaxed);
+                    (count > 0).then(|| (Duration::from_nanos(bucket_lower_bound(index)), count))
+                })
+                .collect(),
+            count: self.count.load(Ordering::Relaxed),
+            total: Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed)),
+            max: Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for LatencyHistogram {
+    fn clone(&self) -> Self {
+        Self {
+            buckets: self
+                .buckets
+                .iter()
+                .map(|bucket| AtomicU64::new(bucket.load(Ordering::Relaxed)))
+                .collect(),
+            count: AtomicU64::new(self.count.load(Ordering::Relaxed)),
+            total_nanos: AtomicU64::new(self.total_nanos.load(Ordering::Relaxed)),
+            max_nanos: AtomicU64::new(self.max_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("count", &self.count.load(Ordering::Relaxed))
+            .field("max_nanos", &self.max_nanos.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// A point-in-time copy of a `LatencyHistogram`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    buckets: Vec<(Duration, u64)>,
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl HistogramSnapshot {
+    /// Number of recorded durations
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of all recorded durations
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Longest recorded duration
+    #[must_use]
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Average recorded duration
+    #[must_use]
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos((self.total.as_nanos() / u128::from(self.count)) as u64)
+    }
+
+    /// Estimate the duration below which the `quantile` (between `0.0` and `1.0`)
+    /// fraction of recorded durations fall. The estimate is the lower bound of the
+    /// bucket holding the quantile.
+    #[must_use]
+    pub fn quantile(&self, quantile: f64) -> Duration {
+        let target = (quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (lower_bound, count) in &self.buckets {
+            seen += count;
+            if seen >= target.max(1) {
+                return *lower_bound;
+            }
+        }
+        Duration::ZERO
+    }
+
+    /// Non-empty buckets as `(lower bound, count)` pairs in ascending order
+    #[must_use]
+    pub fn buckets(&self) -> &[(Duration, u64)] {
+        &self.buckets
+    }
+}
+
+/// Latency histograms for each kind of cache `Operation`
+#[derive(Clone, Debug, Default)]
+pub struct LatencyStats {
+    get_hit: LatencyHistogram,
+    get_miss: LatencyHistogram,
+    insert: LatencyHistogram,
+    backend: LatencyHistogram,
+}
+
+impl LatencyStats {
+    /// Create empty latency stats
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the duration of an operation
+    pub fn record(&self, operation: Operation, duration: Duration) {
+        let histogram = match operation {
+            Operation::GetHit => &self.get_hit,
+            Operation::GetMiss => &self.get_miss,
+            Operation::Insert => &self.insert,
+            Operation::Backend => &self.backend,
+        };
+        histogram.record(duration);
+    }
+
+    /// Record the duration of a lookup, as a hit if `found` is true and as a miss otherwise
+    pub fn record_get(&self, found: bool, duration: Duration) {
+        let operation = if found {
+            Operation::GetHit
+        } else {
+            Operation::GetMiss
+        };
+        self.record(operation, duration);
+    }
+
+    /// Remove all recorded durations
+    pub fn reset(&self) {
+        self.get_hit.reset();
+        self.get_miss.reset();
+        self.insert.reset();
+        self.backend.reset();
+    }
+
+    /// Take a point-in-time copy of all histograms
+    #[must_use]
+    pub fn snapshot(&self) -> LatencyStatsSnapshot {
+        LatencyStatsSnapshot {
+            get_hit: self.get_hit.snapshot(),
+            get_miss: self.get_miss.snapshot(),
+            insert: self.insert.snapshot(),
+            backend: self.backend.snapshot(),
+        }
+    }
+}
+
+/// A point-in-time copy of `LatencyStats`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStatsSnapshot {
+    /// Lookups that found a valid value
+    pub get_hit: HistogramSnapshot,
+    /// Lookups that found no (or an expired) value
+    pub get_miss: HistogramSnapshot,
+    /// Value insertions
+    pub insert: HistogramSnapshot,
+    /// Round-trips to the backend of IO stores
+    pub backend: HistogramSnapshot,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_bounds_contain_their_values() {
+        for nanos in (0..10_000).chain([1 << 20, (1 << 20) + 12345, 1 << 39]) {
+            let index = bucket_index(nanos);
+            assert!(bucket_lower_bound(index) <= nanos);
+            assert!(nanos < bucket_lower_bound(index + 1));
+        }
+        assert_eq!(bucket_index(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn quantiles() {
+        let histogram = LatencyHistogram::new();
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count(), 100);
+        assert_eq!(snapshot.max(), Duration::from_micros(100));
+        assert_eq!(snapshot.mean(), Duration::from_nanos(50_500));
+
+        // buckets have a relative error of at most 1/8th
+        let p50 = snapshot.quantile(0.5).as_nanos() as f64;
+        assert!((43_750.0..=50_000.0).contains(&p50), "{}", p50);
+        let p99 = snapshot.quantile(0.99).as_nanos() as f64;
+        assert!((86_625.0..=99_000.0).contains(&p99), "{}", p99);
+
+        histogram.reset();
+        assert_eq!(histogram.snapshot(), HistogramSnapshot::default());
+    }
+}
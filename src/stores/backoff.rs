@@ -0,0 +1,375 @@
+use super::{random_fraction, Cached, CachedRead, CachedWrite};
+use instant::{Duration, Instant};
+use std::borrow::Borrow;
+use std::cmp::Eq;
+use std::hash::Hash;
+use thiserror::Error;
+
+#[cfg(feature = "ahash")]
+use hashbrown::HashMap;
+
+#[cfg(not(feature = "ahash"))]
+use std::collections::HashMap;
+
+/// Jittered exponential backoff of the keys failing to be computed
+///
+/// After the `n`th consecutive failure of a key, it is not retried for
+/// `min(initial * 2^(n - 1), max)`, reduced by a random fraction of up to `jitter`
+/// so that the retries of keys failing together are spread out.
+#[derive(Clone, Debug)]
+pub struct Backoff<K> {
+    initial: Duration,
+    max: Duration,
+    jitter: f64,
+    keys: HashMap<K, KeyBackoff>,
+}
+
+/// The consecutive failures of a key, and when it may be retried
+#[derive(Clone, Debug)]
+struct KeyBackoff {
+    failures: u32,
+    retry_at: Instant,
+}
+
+impl<K: Hash + Eq> Backoff<K> {
+    /// Creates a `Backoff` waiting `initial` after a first failure, doubling
+    /// with each consecutive failure up to `max`, without jitter
+    #[must_use]
+    pub fn new(initial: Duration, max: Duration) -> Backoff<K> {
+        Backoff {
+            initial,
+            max,
+            jitter: 0.0,
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Set the fraction of the delays, from `0.0` to `1.0`, that may be randomly
+    /// removed from them
+    ///
+    /// # Panics
+    ///
+    /// Will panic if jitter is not between 0.0 and 1.0
+    #[must_use]
+    pub fn with_jitter(mut self, jitter: f64) -> Backoff<K> {
+        if !(0.0..=1.0).contains(&jitter) {
+            panic!("`jitter` of `Backoff` must be between 0.0 and 1.0.");
+        }
+        self.jitter = jitter;
+        self
+    }
+
+    /// Return when `key` may be retried, `None` if it may be retried now
+    pub fn retry_at<Q>(&self, key: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.keys
+            .get(key)
+            .map(|backoff| backoff.retry_at)
+            .filter(|retry_at| Instant::now() < *retry_at)
+    }
+
+    /// Return the number of consecutive failures of `key`
+    pub fn failures<Q>(&self, key: &Q) -> u32
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.keys.get(key).map_or(0, |backoff| backoff.failures)
+    }
+
+    /// Record a failure of `key`, returns when it may be retried
+    pub fn record_failure(&mut self, key: K) -> Instant {
+        let (initial, max, jitter) = (self.initial, self.max, self.jitter);
+        let backoff = self.keys.entry(key).or_insert(KeyBackoff {
+            failures: 0,
+            retry_at: Instant::now(),
+        });
+        backoff.failures = backoff.failures.saturating_add(1);
+        let delay = initial
+            .checked_mul(1 << (backoff.failures - 1).min(31))
+            .map_or(max, |delay| delay.min(max));
+        backoff.retry_at = Instant::now() + delay.mul_f64(1.0 - jitter * random_fraction());
+        backoff.retry_at
+    }
+
+    /// Record a success of `key`, ending its backoff
+    pub fn record_success<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.keys.remove(key);
+    }
+
+    /// End the backoff of all keys
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+}
+
+/// The error of `BackoffCache::cache_try_get_or_set_with`
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum BackoffError<E> {
+    #[error("key is backing off until {retry_at:?}")]
+    BackingOff { retry_at: Instant },
+    #[error("{0}")]
+    Failed(E),
+}
+
+/// Backoff / Cache wrapper
+///
+/// Wraps a cache, e.g. a `SizedCache`, with the `Backoff` of the keys whose values
+/// fail to be computed by `cache_try_get_or_set_with`: a key is not retried until
+/// its backoff is over, and setting its value ends its backoff.
+///
+/// ```rust
+/// use cached::stores::{Backoff, BackoffCache, BackoffError};
+/// use cached::SizedCache;
+/// use std::time::Duration;
+///
+/// let backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60)).with_jitter(0.5);
+/// let mut cache = BackoffCache::new(SizedCache::with_size(100), backoff);
+///
+/// let fetch = || Err::<String, _>("unavailable");
+/// assert_eq!(
+///     cache.cache_try_get_or_set_with(1, fetch),
+///     Err(BackoffError::Failed("unavailable"))
+/// );
+/// // not retried until the backoff is over
+/// let retried = cache.cache_try_get_or_set_with(1, || Ok::<_, &str>("a".to_string()));
+/// assert!(matches!(retried, Err(BackoffError::BackingOff { .. })));
+/// ```
+#[derive(Clone, Debug)]
+pub struct BackoffCache<C, K> {
+    pub(super) cache: C,
+    pub(super) backoff: Backoff<K>,
+}
+
+impl<C, K: Hash + Eq> BackoffCache<C, K> {
+    /// Wraps `cache` with the `backoff` of its keys
+    #[must_use]
+    pub fn new(cache: C, backoff: Backoff<K>) -> BackoffCache<C, K> {
+        BackoffCache { cache, backoff }
+    }
+
+    /// Return the value of `key`, or compute and set it with `f`. Fails with
+    /// `BackoffError::BackingOff` without calling `f` while `key` is backing off.
+    ///
+    /// # Errors
+    ///
+    /// Will return `BackoffError::Failed` with the error of `f`, starting or extending
+    /// the backoff of `key`
+    pub fn cache_try_get_or_set_with<V, E, F>(&mut self, key: K, f: F) -> Result<V, BackoffError<E>>
+    where
+        C: Cached<K, V>,
+        V: Clone,
+        F: FnOnce() -> Result<V, E>,
+    {
+        if let Some(value) = self.cache.cache_get(&key) {
+            return Ok(value.clone());
+        }
+        if let Some(retry_at) = self.backoff.retry_at(&key) {
+            return Err(BackoffError::BackingOff { retry_at });
+        }
+        match f() {
+            Ok(value) => {
+                self.backoff.record_success(&key);
+                self.cache.cache_set(key, value.clone());
+                Ok(value)
+            }
+            Err(error) => {
+                self.backoff.record_failure(key);
+                Err(BackoffError::Failed(error))
+            }
+        }
+    }
+
+    /// Return the backoff of the keys
+    #[must_use]
+    pub fn backoff(&self) -> &Backoff<K> {
+        &self.backoff
+    }
+
+    /// Return the backoff of the keys with mutable access
+    pub fn backoff_mut(&mut self) -> &mut Backoff<K> {
+        &mut self.backoff
+    }
+
+    /// Return the wrapped cache
+    #[must_use]
+    pub fn into_inner(self) -> C {
+        self.cache
+    }
+}
+
+impl<C, K, V> Cached<K, V> for BackoffCache<C, K>
+where
+    C: Cached<K, V>,
+    K: Hash + Eq,
+{
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.cache_get(key)
+    }
+
+    fn cache_get_key_value<Q>(&mut self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.cache_get_key_value(key)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.cache_get_mut(key)
+    }
+
+    fn cache_drain_where<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        drain: F,
+    ) -> impl Iterator<Item = (K, V)> {
+        self.cache.cache_drain_where(drain)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        self.backoff.record_success(&key);
+        self.cache.cache_get_or_set_with(key, f)
+    }
+}
+
+impl<C, K, V> CachedRead<K, V> for BackoffCache<C, K>
+where
+    C: CachedRead<K, V>,
+{
+    fn cache_size(&self) -> usize {
+        self.cache.cache_size()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        self.cache.cache_hits()
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        self.cache.cache_misses()
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        self.cache.cache_capacity()
+    }
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.cache.cache_lifespan()
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.cache_peek(key)
+    }
+
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.cache.cache_iter()
+    }
+}
+
+impl<C, K, V> CachedWrite<K, V> for BackoffCache<C, K>
+where
+    C: CachedWrite<K, V>,
+    K: Hash + Eq,
+{
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        self.backoff.record_success(&key);
+        self.cache.cache_set(key, val)
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.cache_remove(k)
+    }
+
+    fn cache_clear(&mut self) {
+        self.cache.cache_clear();
+        self.backoff.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.cache.cache_reset();
+        self.backoff.clear();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.cache.cache_reset_metrics();
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.cache.cache_set_lifespan(seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SizedCache;
+
+    #[test]
+    fn delays_double_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(3));
+        let delay = |retry_at: Instant| (retry_at - Instant::now()).as_millis();
+        assert!((900..=1000).contains(&delay(backoff.record_failure(1))));
+        assert!((1900..=2000).contains(&delay(backoff.record_failure(1))));
+        assert!((2900..=3000).contains(&delay(backoff.record_failure(1))));
+        assert_eq!(backoff.failures(&1), 3);
+        assert!(backoff.retry_at(&1).is_some());
+        assert!(backoff.retry_at(&2).is_none());
+
+        let mut jittered =
+            Backoff::new(Duration::from_secs(10), Duration::from_secs(10)).with_jitter(0.5);
+        assert!((4900..=10000).contains(&delay(jittered.record_failure(1))));
+
+        backoff.record_success(&1);
+        assert_eq!(backoff.failures(&1), 0);
+        assert!(backoff.retry_at(&1).is_none());
+    }
+
+    #[test]
+    fn keys_are_retried_once_their_backoff_is_over() {
+        let backoff = Backoff::new(Duration::from_millis(50), Duration::from_secs(1));
+        let mut c: BackoffCache<SizedCache<u32, u32>, u32> =
+            BackoffCache::new(SizedCache::with_size(10), backoff);
+        assert_eq!(
+            c.cache_try_get_or_set_with(1, || Err(())),
+            Err(BackoffError::Failed(()))
+        );
+        assert!(matches!(
+            c.cache_try_get_or_set_with(1, || Ok::<_, ()>(1)),
+            Err(BackoffError::BackingOff { .. })
+        ));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(c.cache_try_get_or_set_with(1, || Ok::<_, ()>(1)), Ok(1));
+        assert_eq!(c.backoff().failures(&1), 0);
+        assert_eq!(c.cache_try_get_or_set_with(1, || Err(())), Ok(1));
+
+        // setting a value ends the backoff of its key
+        c.cache_try_get_or_set_with(2, || Err::<u32, _>(()))
+            .unwrap_err();
+        c.cache_set(2, 2);
+        assert!(c.backoff().retry_at(&2).is_none());
+        assert_eq!(c.cache_hits(), Some(1));
+    }
+}
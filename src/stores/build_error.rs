@@ -0,0 +1,47 @@
+use thiserror::Error;
+
+/// The error of the fallible constructors of the in-memory stores
+///
+/// The stores whose size can be invalid, e.g. zero, have a constructor panicking
+/// on an invalid size, e.g. `SizedCache::with_size`, and a fallible one returning
+/// this error, e.g. `SizedCache::try_with_size`. The macros create their caches
+/// with the fallible constructors.
+///
+/// Converts into the `std::io::Error` returned by the fallible constructors of
+/// previous versions: `EINVAL` for an invalid size, `ENOMEM` for an allocation failure.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CacheBuildError {
+    #[error("`{param}` of `{store}` must be greater than zero")]
+    Zero {
+        store: &'static str,
+        param: &'static str,
+    },
+    #[error("the capacity of `{store}` overflows: {capacity}")]
+    CapacityOverflow {
+        store: &'static str,
+        capacity: usize,
+    },
+    #[error("could not allocate `{store}` with a capacity of {capacity}")]
+    Allocation {
+        store: &'static str,
+        capacity: usize,
+    },
+}
+
+impl CacheBuildError {
+    pub(super) fn zero(store: &'static str, param: &'static str) -> Self {
+        CacheBuildError::Zero { store, param }
+    }
+}
+
+impl From<CacheBuildError> for std::io::Error {
+    fn from(error: CacheBuildError) -> Self {
+        let errcode = match error {
+            // EINVAL
+            CacheBuildError::Zero { .. } | CacheBuildError::CapacityOverflow { .. } => 22,
+            // ENOMEM
+            CacheBuildError::Allocation { .. } => 12,
+        };
+        std::io::Error::from_raw_os_error(errcode)
+    }
+}
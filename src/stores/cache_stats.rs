@@ -0,0 +1,100 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Default)]
+struct Counts {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Shared handle to the hit and miss counters of a cache
+///
+/// Reads the counters without locking or borrowing the cache, e.g. from a
+/// monitoring thread while the cache is shared behind a `Mutex`.
+///
+/// ```rust
+/// use cached::{Cached, CachedWrite, SizedCache};
+/// use std::sync::Mutex;
+///
+/// let cache = Mutex::new(SizedCache::with_size(10));
+/// let stats = cache.lock().unwrap().stats_handle();
+///
+/// cache.lock().unwrap().cache_set(1, "a");
+/// cache.lock().unwrap().cache_get(&1);
+/// cache.lock().unwrap().cache_get(&2);
+/// assert_eq!((stats.hits(), stats.misses()), (1, 1));
+/// ```
+#[derive(Clone, Debug)]
+pub struct CacheStats {
+    counts: Arc<Counts>,
+}
+
+impl CacheStats {
+    /// Return the number of times a cached value was successfully retrieved
+    #[must_use]
+    pub fn hits(&self) -> u64 {
+        self.counts.hits.load(Ordering::Relaxed)
+    }
+
+    /// Return the number of times a cached value was unable to be retrieved
+    #[must_use]
+    pub fn misses(&self) -> u64 {
+        self.counts.misses.load(Ordering::Relaxed)
+    }
+}
+
+/// The hit and miss counters of a cache. Clones of a cache count on their own.
+#[derive(Default)]
+pub(super) struct StatsCounters {
+    counts: Arc<Counts>,
+}
+
+impl StatsCounters {
+    pub(super) fn hit(&self) {
+        self.counts.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn miss(&self) {
+        self.counts.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn hits(&self) -> u64 {
+        self.counts.hits.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn misses(&self) -> u64 {
+        self.counts.misses.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn reset(&self) {
+        self.counts.hits.store(0, Ordering::Relaxed);
+        self.counts.misses.store(0, Ordering::Relaxed);
+    }
+
+    pub(super) fn handle(&self) -> CacheStats {
+        CacheStats {
+            counts: Arc::clone(&self.counts),
+        }
+    }
+}
+
+impl Clone for StatsCounters {
+    fn clone(&self) -> Self {
+        StatsCounters {
+            counts: Arc::new(Counts {
+                hits: AtomicU64::new(self.hits()),
+                misses: AtomicU64::new(self.misses()),
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for StatsCounters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StatsCounters")
+            .field("hits", &self.hits())
+            .field("misses", &self.misses())
+            .finish()
+    }
+}
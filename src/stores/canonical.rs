@@ -0,0 +1,282 @@
+use super::{Cached, CachedRead, CachedWrite};
+use std::borrow::Cow;
+use std::marker::PhantomData;
+
+#[cfg(feature = "unicode_keys")]
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+/// How the keys of a `CanonicalCache` are canonicalized
+///
+/// Keys are normalized to Unicode NFC (feature `unicode_keys`), then trimmed,
+/// then lowercased, each step being enabled separately.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Canonicalizer {
+    nfc: bool,
+    trim: bool,
+    lowercase: bool,
+}
+
+impl Canonicalizer {
+    /// Creates a `Canonicalizer` leaving keys unchanged
+    #[must_use]
+    pub fn new() -> Canonicalizer {
+        Canonicalizer::default()
+    }
+
+    /// Normalize keys to Unicode NFC, so that composed and decomposed
+    /// characters are the same key
+    #[cfg(feature = "unicode_keys")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "unicode_keys")))]
+    #[must_use]
+    pub fn nfc(mut self) -> Canonicalizer {
+        self.nfc = true;
+        self
+    }
+
+    /// Remove the leading and trailing whitespace of keys
+    #[must_use]
+    pub fn trim(mut self) -> Canonicalizer {
+        self.trim = true;
+        self
+    }
+
+    /// Lowercase keys
+    #[must_use]
+    pub fn lowercase(mut self) -> Canonicalizer {
+        self.lowercase = true;
+        self
+    }
+
+    /// Return the canonical form of `key`, borrowing it when it is already canonical
+    #[must_use]
+    pub fn canonicalize<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        let mut key = Cow::Borrowed(key);
+        #[cfg(feature = "unicode_keys")]
+        if self.nfc && is_nfc_quick(key.chars()) != IsNormalized::Yes {
+            key = Cow::Owned(key.nfc().collect());
+        }
+        if self.trim && key.trim().len() != key.len() {
+            key = Cow::Owned(key.trim().to_string());
+        }
+        if self.lowercase && key.chars().any(|c| c.to_lowercase().ne(Some(c))) {
+            key = Cow::Owned(key.to_lowercase());
+        }
+        key
+    }
+}
+
+/// Canonical / Cache wrapper
+///
+/// Wraps a cache of `String` keys, e.g. a `SizedCache`, canonicalizing keys with a
+/// `Canonicalizer` on every insert and lookup, so that keys differing only by case,
+/// whitespace or Unicode normalization share the same value. The key the value was
+/// last set with is kept along with it, see `cache_get_key_value`.
+///
+/// The wrapped cache stores `(key, value)` pairs under the canonical keys.
+///
+/// ```rust
+/// use cached::stores::{CanonicalCache, Canonicalizer};
+/// use cached::SizedCache;
+///
+/// let mut cache = CanonicalCache::new(
+///     SizedCache::with_size(100),
+///     Canonicalizer::new().trim().lowercase(),
+/// );
+/// cache.cache_set("Hello ".to_string(), 1);
+///
+/// assert_eq!(cache.cache_get("hello"), Some(&1));
+/// assert_eq!(
+///     cache.cache_get_key_value("HELLO"),
+///     Some((&"Hello ".to_string(), &1))
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct CanonicalCache<C, V> {
+    pub(super) cache: C,
+    pub(super) canonicalizer: Canonicalizer,
+    _phantom: PhantomData<V>,
+}
+
+impl<C, V> CanonicalCache<C, V>
+where
+    C: Cached<String, (String, V)>,
+{
+    /// Wraps `cache`, canonicalizing its keys with `canonicalizer`
+    #[must_use]
+    pub fn new(cache: C, canonicalizer: Canonicalizer) -> CanonicalCache<C, V> {
+        CanonicalCache {
+            cache,
+            canonicalizer,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Return the canonicalizer of the keys
+    #[must_use]
+    pub fn canonicalizer(&self) -> Canonicalizer {
+        self.canonicalizer
+    }
+
+    /// Return a reference to the wrapped cache
+    #[must_use]
+    pub fn get_inner(&self) -> &C {
+        &self.cache
+    }
+
+    /// Return the wrapped cache
+    #[must_use]
+    pub fn into_inner(self) -> C {
+        self.cache
+    }
+
+    /// Attempt to retrieve the value of the canonical form of `key`
+    pub fn cache_get(&mut self, key: &str) -> Option<&V> {
+        let key = self.canonicalizer.canonicalize(key);
+        self.cache.cache_get(key.as_ref()).map(|(_, v)| v)
+    }
+
+    /// Attempt to retrieve the value of the canonical form of `key`, with mutable access
+    pub fn cache_get_mut(&mut self, key: &str) -> Option<&mut V> {
+        let key = self.canonicalizer.canonicalize(key);
+        self.cache.cache_get_mut(key.as_ref()).map(|(_, v)| v)
+    }
+
+    /// Attempt to retrieve the value of the canonical form of `key`, without counting
+    /// a hit or a miss, nor updating its recency
+    pub fn cache_peek(&self, key: &str) -> Option<&V> {
+        let key = self.canonicalizer.canonicalize(key);
+        self.cache.cache_peek(key.as_ref()).map(|(_, v)| v)
+    }
+
+    /// Return whether there is a value for the canonical form of `key`
+    pub fn cache_contains(&self, key: &str) -> bool {
+        self.cache_peek(key).is_some()
+    }
+
+    /// Attempt to retrieve the value of the canonical form of `key`, along with
+    /// the key it was set with
+    pub fn cache_get_key_value(&mut self, key: &str) -> Option<(&String, &V)> {
+        let key = self.canonicalizer.canonicalize(key);
+        self.cache.cache_get(key.as_ref()).map(|(k, v)| (k, v))
+    }
+
+    /// Get or insert the value of the canonical form of `key`
+    pub fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: String, f: F) -> &mut V {
+        let canonical = self.canonicalizer.canonicalize(&key).into_owned();
+        &mut self.cache.cache_get_or_set_with(canonical, || (key, f())).1
+    }
+
+    /// Insert the value of the canonical form of `key`, returning the previous value
+    pub fn cache_set(&mut self, key: String, val: V) -> Option<V>
+    where
+        C: CachedWrite<String, (String, V)>,
+    {
+        let canonical = self.canonicalizer.canonicalize(&key).into_owned();
+        self.cache.cache_set(canonical, (key, val)).map(|(_, v)| v)
+    }
+
+    /// Remove the value of the canonical form of `key`
+    pub fn cache_remove(&mut self, key: &str) -> Option<V>
+    where
+        C: CachedWrite<String, (String, V)>,
+    {
+        let key = self.canonicalizer.canonicalize(key);
+        self.cache.cache_remove(key.as_ref()).map(|(_, v)| v)
+    }
+
+    /// Remove all cached values. Keeps the allocated memory for reuse.
+    pub fn cache_clear(&mut self)
+    where
+        C: CachedWrite<String, (String, V)>,
+    {
+        self.cache.cache_clear();
+    }
+
+    /// Remove all cached values. Free memory and return to initial state
+    pub fn cache_reset(&mut self)
+    where
+        C: CachedWrite<String, (String, V)>,
+    {
+        self.cache.cache_reset();
+    }
+}
+
+impl<C, V> CachedRead<String, V> for CanonicalCache<C, V>
+where
+    C: CachedRead<String, (String, V)>,
+{
+    fn cache_size(&self) -> usize {
+        self.cache.cache_size()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        self.cache.cache_hits()
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        self.cache.cache_misses()
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        self.cache.cache_capacity()
+    }
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.cache.cache_lifespan()
+    }
+    /// Look up a key already in its canonical form, see the inherent `cache_peek`
+    /// for the lookups of any form
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        String: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.cache.cache_peek(key).map(|(_, v)| v)
+    }
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a String, &'a V)>
+    where
+        V: 'a,
+    {
+        self.cache.cache_iter().map(|(_, (k, v))| (k, v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SizedCache;
+
+    #[test]
+    fn canonicalize() {
+        let c = Canonicalizer::new();
+        assert!(matches!(c.canonicalize(" A "), Cow::Borrowed(" A ")));
+        let c = c.trim().lowercase();
+        assert_eq!(c.canonicalize(" ÀB\t"), "àb");
+        assert!(matches!(c.canonicalize("ab"), Cow::Borrowed("ab")));
+
+        #[cfg(feature = "unicode_keys")]
+        {
+            let c = Canonicalizer::new().nfc();
+            assert_eq!(c.canonicalize("e\u{301}"), "\u{e9}");
+            assert!(matches!(c.canonicalize("\u{e9}"), Cow::Borrowed(_)));
+        }
+    }
+
+    #[test]
+    fn keys_share_their_canonical_value() {
+        let mut c = CanonicalCache::new(
+            SizedCache::with_size(10),
+            Canonicalizer::new().trim().lowercase(),
+        );
+        assert_eq!(c.cache_set("Key".to_string(), 1), None);
+        assert_eq!(c.cache_set(" KEY".to_string(), 2), Some(1));
+        assert_eq!(c.cache_get("key"), Some(&2));
+        assert_eq!(
+            c.cache_get_key_value("kEy"),
+            Some((&" KEY".to_string(), &2))
+        );
+        assert_eq!(*c.cache_get_or_set_with("key ".to_string(), || 3), 2);
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(c.cache_hits(), Some(3));
+
+        assert_eq!(c.cache_remove("KEY"), Some(2));
+        assert_eq!(c.cache_get("key"), None);
+        assert_eq!(c.cache_misses(), Some(1));
+    }
+}
@@ -1,18 +1,36 @@
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
 use crate::IOCached;
 use directories::BaseDirs;
 use instant::Duration;
+use once_cell::sync::Lazy;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use sled::Db;
+use sled::{Db, Tree};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::UNIX_EPOCH;
 use std::{fmt::Display, path::PathBuf, time::SystemTime};
 
-pub struct DiskCacheBuilder<K, V> {
+pub struct DiskCacheBuilder<K, V, S = MessagePackDiskSerializer> {
     seconds: Option<u64>,
     refresh: bool,
     disk_dir: Option<PathBuf>,
     cache_name: String,
+    database: Option<String>,
+    compression: Compression,
+    max_disk_size: Option<u64>,
+    sync_writes: bool,
+    flush_every: Option<Duration>,
+    recover_on_corruption: bool,
+    lock_timeout: Option<Duration>,
+    serializer: S,
+    #[cfg(feature = "disk_encryption")]
+    encryption: Option<Encryption>,
     _phantom: PhantomData<(K, V)>,
 }
 
@@ -22,6 +40,8 @@ use thiserror::Error;
 pub enum DiskCacheBuildError {
     #[error("Storage connection error")]
     ConnectionError(#[from] sled::Error),
+    #[error("Database {0:?} is open in another process")]
+    Locked(PathBuf),
     #[error("Connection string not specified or invalid in env var {env_key:?}: {error:?}")]
     MissingDiskPath {
         env_key: String,
@@ -31,6 +51,316 @@ pub enum DiskCacheBuildError {
 
 static DISK_FILE_PREFIX: &str = "cached_disk_cache";
 const DISK_FILE_VERSION: u64 = 1;
+const DEFAULT_TREE: &[u8] = b"__sled__default";
+
+/// sled only allows a single handle per database directory, so all the
+/// `DiskCache`s sharing a database share the handle and each get their own tree.
+static OPEN_DATABASES: Lazy<Mutex<HashMap<PathBuf, Weak<Db>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// First byte of the compressed values, followed by the algorithm. It is never
+/// used by MessagePack, so values written without compression are read as is.
+/// Values of other formats are prefixed with it when not compressed.
+const COMPRESSED_MARKER: u8 = 0xc1;
+const NO_ALGORITHM: u8 = 0;
+#[cfg(feature = "disk_zstd")]
+const ZSTD_ALGORITHM: u8 = 1;
+/// Algorithms of the encrypted values, followed by the nonce and the sealed value
+const AES_256_GCM_ALGORITHM: u8 = 0x81;
+const CHACHA20_POLY1305_ALGORITHM: u8 = 0x82;
+
+/// Whether opening a database failed because it is open in another process
+fn is_lock_error(error: &std::io::Error) -> bool {
+    error.kind() == std::io::ErrorKind::Other
+        && error.to_string().starts_with("could not acquire lock")
+}
+
+/// Compression of the values of a `DiskCache`
+///
+/// Values are compressed after being serialized, and decompressed before being
+/// deserialized. Values written with another compression, or none, are still read,
+/// so the compression of an existing cache can be changed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Store the serialized values as is
+    #[default]
+    None,
+    /// Compress the values with zstd at this level, from 1 to 22, 0 being the
+    /// default level (3)
+    #[cfg(feature = "disk_zstd")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "disk_zstd")))]
+    Zstd(i32),
+}
+
+impl Compression {
+    fn compress(self, bytes: Vec<u8>, headerless: bool) -> Result<Vec<u8>, DiskCacheError> {
+        match self {
+            Compression::None if headerless => Ok(bytes),
+            Compression::None => {
+                let mut uncompressed = vec![COMPRESSED_MARKER, NO_ALGORITHM];
+                uncompressed.extend(bytes);
+                Ok(uncompressed)
+            }
+            #[cfg(feature = "disk_zstd")]
+            Compression::Zstd(level) => {
+                let mut compressed = vec![COMPRESSED_MARKER, ZSTD_ALGORITHM];
+                compressed.extend(
+                    zstd::encode_all(bytes.as_slice(), level)
+                        .map_err(DiskCacheError::CacheCompressionError)?,
+                );
+                Ok(compressed)
+            }
+        }
+    }
+
+    fn decompress(bytes: &[u8]) -> Result<Cow<'_, [u8]>, DiskCacheError> {
+        match bytes {
+            [COMPRESSED_MARKER, NO_ALGORITHM, uncompressed @ ..] => Ok(Cow::Borrowed(uncompressed)),
+            // encrypted values are decrypted before, unless the cache has no encryption
+            [COMPRESSED_MARKER, AES_256_GCM_ALGORITHM | CHACHA20_POLY1305_ALGORITHM, ..] => {
+                Err(DiskCacheError::CacheEncryptionError)
+            }
+            #[cfg(feature = "disk_zstd")]
+            [COMPRESSED_MARKER, ZSTD_ALGORITHM, compressed @ ..] => zstd::decode_all(compressed)
+                .map(Cow::Owned)
+                .map_err(DiskCacheError::CacheCompressionError),
+            [COMPRESSED_MARKER, algorithm, ..] => Err(DiskCacheError::CacheCompressionError(
+                std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!(
+                        "value compressed with an unsupported algorithm ({}), zstd requires feature `disk_zstd`",
+                        algorithm
+                    ),
+                ),
+            )),
+            _ => Ok(Cow::Borrowed(bytes)),
+        }
+    }
+}
+
+/// The format of the values of a `DiskCache`
+///
+/// Implement it to store values in another format, e.g. bincode or postcard for compactness,
+/// and set it with `set_serializer`. Values written in another format cannot be read, so the
+/// cache name or directory of a cache should change along with its format.
+///
+/// ```rust,ignore
+/// use cached::stores::{DiskCacheError, DiskSerializer};
+///
+/// struct Bincode;
+///
+/// impl DiskSerializer for Bincode {
+///     fn serialize<T: serde::Serialize>(&self, value: &T) -> Result<Vec<u8>, DiskCacheError> {
+///         bincode::serialize(value).map_err(|e| DiskCacheError::Serialization(Box::new(e)))
+///     }
+///
+///     fn deserialize<T: serde::de::DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, DiskCacheError> {
+///         bincode::deserialize(bytes).map_err(|e| DiskCacheError::Deserialization(Box::new(e)))
+///     }
+/// }
+/// ```
+pub trait DiskSerializer {
+    /// Whether the serialized values never start with byte `0xc1`, as with MessagePack,
+    /// so that they are stored as is when not compressed. Otherwise, each value is
+    /// prefixed with two bytes telling it is not compressed.
+    const HEADERLESS: bool = false;
+
+    /// Serialize a cached value
+    ///
+    /// # Errors
+    ///
+    /// Should return `DiskCacheError::Serialization` if the value cannot be serialized
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, DiskCacheError>;
+
+    /// Deserialize a cached value
+    ///
+    /// # Errors
+    ///
+    /// Should return `DiskCacheError::Deserialization` if the value cannot be deserialized
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, DiskCacheError>;
+}
+
+/// MessagePack, the default format of the values of a `DiskCache`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePackDiskSerializer;
+
+impl DiskSerializer for MessagePackDiskSerializer {
+    const HEADERLESS: bool = true;
+
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, DiskCacheError> {
+        Ok(rmp_serde::to_vec(value)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, DiskCacheError> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
+
+/// Cipher of the values of a `DiskCache`
+#[cfg(feature = "disk_encryption")]
+#[cfg_attr(docsrs, doc(cfg(feature = "disk_encryption")))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cipher {
+    /// AES-256 in Galois/Counter Mode
+    Aes256Gcm,
+    /// ChaCha20-Poly1305, faster than AES without hardware acceleration
+    ChaCha20Poly1305,
+}
+
+/// Encryption of the values, and optionally the keys, of a `DiskCache`
+///
+/// Values are encrypted after being serialized and compressed, with a random nonce, and
+/// authenticated along with their key so that they cannot be moved to another key. Hashed
+/// keys are replaced on disk by their HMAC-SHA256, under a key derived from the encryption
+/// key, so that they are still found but cannot be read back.
+///
+/// Values written without encryption, or with another key or cipher, fail to be read.
+/// The encryption of an existing cache cannot be changed, change its name along with it.
+///
+/// ```rust,no_run
+/// use cached::stores::{Cipher, DiskCache, Encryption};
+///
+/// let key = [7; 32]; // read from a secret store
+/// let cache: DiskCache<String, String> = DiskCache::new("encrypted")
+///     .set_encryption(Encryption::new(Cipher::Aes256Gcm, &key).hash_keys(true))
+///     .build()
+///     .unwrap();
+/// ```
+#[cfg(feature = "disk_encryption")]
+#[cfg_attr(docsrs, doc(cfg(feature = "disk_encryption")))]
+pub struct Encryption {
+    cipher: Cipher,
+    key: ring::aead::LessSafeKey,
+    key_hasher: ring::hmac::Key,
+    hash_keys: bool,
+    rng: ring::rand::SystemRandom,
+}
+
+#[cfg(feature = "disk_encryption")]
+impl Encryption {
+    /// Encrypt the values with `cipher` and a 256-bit `key`
+    #[must_use]
+    pub fn new(cipher: Cipher, key: &[u8; 32]) -> Encryption {
+        use ring::{aead, hmac};
+
+        let algorithm = match cipher {
+            Cipher::Aes256Gcm => &aead::AES_256_GCM,
+            Cipher::ChaCha20Poly1305 => &aead::CHACHA20_POLY1305,
+        };
+        let unbound = aead::UnboundKey::new(algorithm, key).expect("both ciphers use 256-bit keys");
+        let derived = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, key),
+            b"cached disk cache keys",
+        );
+        Encryption {
+            cipher,
+            key: aead::LessSafeKey::new(unbound),
+            key_hasher: hmac::Key::new(hmac::HMAC_SHA256, derived.as_ref()),
+            hash_keys: false,
+            rng: ring::rand::SystemRandom::new(),
+        }
+    }
+
+    /// Specify whether the keys are hashed. Defaults to `false`.
+    #[must_use]
+    pub fn hash_keys(mut self, hash_keys: bool) -> Self {
+        self.hash_keys = hash_keys;
+        self
+    }
+
+    fn algorithm(&self) -> u8 {
+        match self.cipher {
+            Cipher::Aes256Gcm => AES_256_GCM_ALGORITHM,
+            Cipher::ChaCha20Poly1305 => CHACHA20_POLY1305_ALGORITHM,
+        }
+    }
+
+    /// The key stored on disk, hex encoded when hashed
+    fn disk_key(&self, key: String) -> String {
+        if !self.hash_keys {
+            return key;
+        }
+        ring::hmac::sign(&self.key_hasher, key.as_bytes())
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn seal(&self, key: &[u8], value: Vec<u8>) -> Result<Vec<u8>, DiskCacheError> {
+        use ring::aead::{Aad, Nonce, NONCE_LEN};
+        use ring::rand::SecureRandom;
+
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| DiskCacheError::CacheEncryptionError)?;
+        let mut sealed = value;
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key),
+                &mut sealed,
+            )
+            .map_err(|_| DiskCacheError::CacheEncryptionError)?;
+
+        let mut encrypted = vec![COMPRESSED_MARKER, self.algorithm()];
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend(sealed);
+        Ok(encrypted)
+    }
+
+    fn open(&self, key: &[u8], bytes: &[u8]) -> Result<Vec<u8>, DiskCacheError> {
+        use ring::aead::{Aad, Nonce, NONCE_LEN};
+
+        let sealed = match bytes {
+            [COMPRESSED_MARKER, algorithm, sealed @ ..]
+                if *algorithm == self.algorithm() && sealed.len() >= NONCE_LEN =>
+            {
+                sealed
+            }
+            _ => return Err(DiskCacheError::CacheEncryptionError),
+        };
+        let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| DiskCacheError::CacheEncryptionError)?;
+        let mut value = sealed.to_vec();
+        let len = self
+            .key
+            .open_in_place(nonce, Aad::from(key), &mut value)
+            .map_err(|_| DiskCacheError::CacheEncryptionError)?
+            .len();
+        value.truncate(len);
+        Ok(value)
+    }
+}
+
+#[cfg(feature = "disk_encryption")]
+impl std::fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encryption")
+            .field("cipher", &self.cipher)
+            .field("hash_keys", &self.hash_keys)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Serialize and compress a value
+fn encode<V: Serialize, S: DiskSerializer>(
+    serializer: &S,
+    cached: &CachedDiskValue<V>,
+    compression: Compression,
+) -> Result<Vec<u8>, DiskCacheError> {
+    compression.compress(serializer.serialize(cached)?, S::HEADERLESS)
+}
+
+/// Decompress and deserialize a value
+fn decode<V: DeserializeOwned, S: DiskSerializer>(
+    serializer: &S,
+    bytes: &[u8],
+) -> Result<CachedDiskValue<V>, DiskCacheError> {
+    serializer.deserialize(&Compression::decompress(bytes)?)
+}
 
 impl<K, V> DiskCacheBuilder<K, V>
 where
@@ -44,9 +374,47 @@ where
             refresh: false,
             disk_dir: None,
             cache_name: cache_name.as_ref().to_string(),
+            database: None,
+            compression: Compression::None,
+            max_disk_size: None,
+            sync_writes: false,
+            flush_every: None,
+            recover_on_corruption: false,
+            lock_timeout: None,
+            serializer: MessagePackDiskSerializer,
+            #[cfg(feature = "disk_encryption")]
+            encryption: None,
             _phantom: Default::default(),
         }
     }
+}
+
+impl<K, V, S> DiskCacheBuilder<K, V, S>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+    S: DiskSerializer,
+{
+    /// Set the format of the values written. Defaults to `MessagePackDiskSerializer`.
+    pub fn set_serializer<G: DiskSerializer>(self, serializer: G) -> DiskCacheBuilder<K, V, G> {
+        DiskCacheBuilder {
+            seconds: self.seconds,
+            refresh: self.refresh,
+            disk_dir: self.disk_dir,
+            cache_name: self.cache_name,
+            database: self.database,
+            compression: self.compression,
+            max_disk_size: self.max_disk_size,
+            sync_writes: self.sync_writes,
+            flush_every: self.flush_every,
+            recover_on_corruption: self.recover_on_corruption,
+            lock_timeout: self.lock_timeout,
+            serializer,
+            #[cfg(feature = "disk_encryption")]
+            encryption: self.encryption,
+            _phantom: PhantomData,
+        }
+    }
 
     /// Specify the cache TTL/lifespan in seconds
     pub fn set_lifespan(mut self, seconds: u64) -> Self {
@@ -66,6 +434,76 @@ where
         self
     }
 
+    /// Store the values in a tree named after the cache, in the database `name` of the disk
+    /// directory shared by all the caches setting it, instead of in a database of their own.
+    /// The options of the database, e.g. `set_flush_every`, are the ones of the first cache
+    /// opening it in the process. See `DiskCache::database_trees` to list its caches.
+    pub fn set_database<N: AsRef<str>>(mut self, name: N) -> Self {
+        self.database = Some(name.as_ref().to_string());
+        self
+    }
+
+    /// Set the compression of the values written. Defaults to `Compression::None`.
+    pub fn set_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Limit the size of the keys and values stored, in bytes. When a value set exceeds
+    /// the limit, the values set (or refreshed, see `set_refresh`) the longest ago are
+    /// evicted until the cache uses at most 90% of it, so that evictions are batched.
+    ///
+    /// The size does not include the overhead of the storage, and is approximate
+    /// when the cache is used concurrently. The cache directory is read when built
+    /// to find the size of the values already stored.
+    pub fn set_max_disk_size(mut self, bytes: u64) -> Self {
+        self.max_disk_size = Some(bytes);
+        self
+    }
+
+    /// Specify whether `cache_set` and `cache_remove` flush their writes to disk before
+    /// returning, trading their latency for durability. Defaults to `false`.
+    pub fn set_sync_writes(mut self, sync_writes: bool) -> Self {
+        self.sync_writes = sync_writes;
+        self
+    }
+
+    /// Set the interval of the background flushes of the writes to disk, defaults to the
+    /// one of sled (500ms). A zero interval disables the background flushes, leaving them
+    /// to `set_sync_writes` or `DiskCache::flush`.
+    pub fn set_flush_every(mut self, every: Duration) -> Self {
+        self.flush_every = Some(every);
+        self
+    }
+
+    /// Specify whether corrupted values are removed and treated as missing, and a corrupted
+    /// storage is emptied, instead of returning errors until they are removed. Values which
+    /// cannot be decoded, e.g. written by another version of the application or with another
+    /// encryption key, are considered corrupted. Defaults to `false`, see `DiskCache::repair`.
+    pub fn set_recover_on_corruption(mut self, recover: bool) -> Self {
+        self.recover_on_corruption = recover;
+        self
+    }
+
+    /// Wait up to `timeout` for the database to be closed by another process when building
+    /// the cache, instead of failing with `DiskCacheBuildError::Locked` right away.
+    ///
+    /// A database can only be open in one process at a time, which reads and writes it, so
+    /// this lets short-lived processes, e.g. the runs of a command line tool, share a cache
+    /// by taking turns. sled has no read-only handles to let other processes read it meanwhile.
+    pub fn set_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Encrypt the values written, and optionally their keys, see `Encryption`
+    #[cfg(feature = "disk_encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "disk_encryption")))]
+    pub fn set_encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
     fn default_disk_dir() -> PathBuf {
         BaseDirs::new()
             .map(|base_dirs| {
@@ -84,31 +522,127 @@ where
             })
     }
 
-    pub fn build(self) -> Result<DiskCache<K, V>, DiskCacheBuildError> {
+    /// Open the database at `path`, or share the one already open when set with `set_database`,
+    /// waiting up to `lock_timeout` for another process to close it
+    fn open_database(
+        config: &sled::Config,
+        path: &Path,
+        shared: bool,
+        recover: bool,
+        lock_timeout: Option<Duration>,
+    ) -> Result<Arc<Db>, DiskCacheBuildError> {
+        let deadline = instant::Instant::now() + lock_timeout.unwrap_or_default();
+        let mut delay = Duration::from_millis(5);
+        loop {
+            let mut databases = OPEN_DATABASES.lock().unwrap();
+            if shared {
+                if let Some(db) = databases.get(path).and_then(Weak::upgrade) {
+                    return Ok(db);
+                }
+            }
+
+            let db = match config.open() {
+                Err(sled::Error::Corruption { .. }) if recover => {
+                    std::fs::remove_dir_all(path).map_err(sled::Error::Io)?;
+                    config.open()
+                }
+                db => db,
+            };
+            let db = match db {
+                Ok(db) => Arc::new(db),
+                Err(sled::Error::Io(error)) if is_lock_error(&error) => {
+                    let now = instant::Instant::now();
+                    if now >= deadline {
+                        return Err(DiskCacheBuildError::Locked(path.to_path_buf()));
+                    }
+                    // let the other caches of this process open theirs meanwhile
+                    drop(databases);
+                    std::thread::sleep(delay.min(deadline - now));
+                    delay = (delay * 2).min(Duration::from_millis(100));
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+            if shared {
+                databases.insert(path.to_path_buf(), Arc::downgrade(&db));
+            }
+            return Ok(db);
+        }
+    }
+
+    pub fn build(self) -> Result<DiskCache<K, V, S>, DiskCacheBuildError> {
         let disk_dir = self.disk_dir.unwrap_or_else(|| Self::default_disk_dir());
-        let disk_path = disk_dir.join(format!("{}_v{}", self.cache_name, DISK_FILE_VERSION));
-        let connection = sled::open(disk_path.clone())?;
+        let database_name = self.database.as_deref().unwrap_or(&self.cache_name);
+        let disk_path = disk_dir.join(format!("{}_v{}", database_name, DISK_FILE_VERSION));
+        let mut config = sled::Config::new().path(disk_path.clone());
+        if let Some(every) = self.flush_every {
+            let millis = (every.as_millis() as u64).max(1);
+            config = config.flush_every_ms((!every.is_zero()).then_some(millis));
+        }
+        let database = Self::open_database(
+            &config,
+            &disk_path,
+            self.database.is_some(),
+            self.recover_on_corruption,
+            self.lock_timeout,
+        )?;
+        let connection = match self.database {
+            Some(_) => database.open_tree(&self.cache_name)?,
+            None => Tree::clone(&database),
+        };
+        let size = match self.max_disk_size {
+            Some(_) => connection
+                .iter()
+                .flatten()
+                .map(|(key, value)| (key.len() + value.len()) as u64)
+                .sum(),
+            None => 0,
+        };
 
         Ok(DiskCache {
             seconds: self.seconds,
             refresh: self.refresh,
             version: DISK_FILE_VERSION,
             disk_path,
+            database,
             connection,
+            compression: self.compression,
+            max_disk_size: self.max_disk_size,
+            size: AtomicU64::new(size),
+            sync_writes: self.sync_writes,
+            recover_on_corruption: self.recover_on_corruption,
+            serializer: self.serializer,
+            #[cfg(feature = "disk_encryption")]
+            encryption: self.encryption,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
             _phantom: self._phantom,
         })
     }
 }
 
 /// Cache store backed by disk
-pub struct DiskCache<K, V> {
+pub struct DiskCache<K, V, S = MessagePackDiskSerializer> {
     pub(super) seconds: Option<u64>,
     pub(super) refresh: bool,
     #[allow(unused)]
     version: u64,
     #[allow(unused)]
     disk_path: PathBuf,
-    connection: Db,
+    database: Arc<Db>,
+    // the tree of the cache in a shared database, or the default tree of its own
+    connection: Tree,
+    compression: Compression,
+    max_disk_size: Option<u64>,
+    // the bytes of the keys and values stored, tracked with a max disk size
+    size: AtomicU64,
+    sync_writes: bool,
+    recover_on_corruption: bool,
+    serializer: S,
+    #[cfg(feature = "disk_encryption")]
+    encryption: Option<Encryption>,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
     _phantom: PhantomData<(K, V)>,
 }
 
@@ -122,24 +656,212 @@ where
     pub fn new(cache_name: &str) -> DiskCacheBuilder<K, V> {
         DiskCacheBuilder::new(cache_name)
     }
+}
 
+impl<K, V, S> DiskCache<K, V, S>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+    S: DiskSerializer,
+{
     pub fn remove_expired_entries(&self) {
         let now = SystemTime::now();
 
         for (key, value) in self.connection.iter().flatten() {
-            if let Ok(cached) = rmp_serde::from_slice::<CachedDiskValue<V>>(&value) {
+            if let Ok(cached) = self.decode_value(&key, &value) {
                 if let Some(lifetime_seconds) = self.seconds {
                     if now
                         .duration_since(cached.created_at)
                         .unwrap_or(Duration::from_secs(0))
                         >= Duration::from_secs(lifetime_seconds)
                     {
-                        let _ = self.connection.remove(key);
+                        if let Ok(Some(old)) = self.connection.remove(&key) {
+                            self.resize(0, key.len() + old.len());
+                        }
                     }
                 }
             }
         }
     }
+
+    /// The key of a value on disk, hashed with the encryption
+    fn disk_key(&self, key: &K) -> String {
+        let key = key.to_string();
+        #[cfg(feature = "disk_encryption")]
+        if let Some(encryption) = &self.encryption {
+            return encryption.disk_key(key);
+        }
+        key
+    }
+
+    /// Serialize, compress and encrypt a value
+    #[cfg_attr(not(feature = "disk_encryption"), allow(unused_variables))]
+    fn encode_value(
+        &self,
+        key: &[u8],
+        cached: &CachedDiskValue<V>,
+    ) -> Result<Vec<u8>, DiskCacheError> {
+        let bytes = encode(&self.serializer, cached, self.compression)?;
+        #[cfg(feature = "disk_encryption")]
+        if let Some(encryption) = &self.encryption {
+            return encryption.seal(key, bytes);
+        }
+        Ok(bytes)
+    }
+
+    /// Decrypt, decompress and deserialize a value
+    #[cfg_attr(not(feature = "disk_encryption"), allow(unused_variables))]
+    fn decode_value(&self, key: &[u8], bytes: &[u8]) -> Result<CachedDiskValue<V>, DiskCacheError> {
+        #[cfg(feature = "disk_encryption")]
+        if let Some(encryption) = &self.encryption {
+            return decode(&self.serializer, &encryption.open(key, bytes)?);
+        }
+        decode(&self.serializer, bytes)
+    }
+
+    /// Flush the writes to disk, returning the number of bytes flushed
+    ///
+    /// # Errors
+    ///
+    /// Will return a `DiskCacheError::StorageError` if the writes cannot be flushed
+    pub fn flush(&self) -> Result<usize, DiskCacheError> {
+        Ok(self.connection.flush()?)
+    }
+
+    /// Return the names of the caches of the database of this cache, see `set_database`
+    pub fn database_trees(&self) -> Vec<String> {
+        self.database
+            .tree_names()
+            .into_iter()
+            .filter(|name| name != DEFAULT_TREE)
+            .map(|name| String::from_utf8_lossy(&name).into_owned())
+            .collect()
+    }
+
+    /// Remove a cache of the database of this cache, and its values, returning whether
+    /// it existed. The caches using it must not be used afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Will return a `DiskCacheError::StorageError` if the cache cannot be removed
+    pub fn drop_database_tree(&self, name: &str) -> Result<bool, DiskCacheError> {
+        Ok(self.database.drop_tree(name)?)
+    }
+
+    /// Remove the values which cannot be decoded, e.g. corrupted, written by another version
+    /// of the application or with another encryption key, returning how many were removed.
+    /// All the values are removed if the storage is corrupted.
+    ///
+    /// # Errors
+    ///
+    /// Will return a `DiskCacheError::StorageError` if the values cannot be removed
+    pub fn repair(&self) -> Result<usize, DiskCacheError> {
+        let mut removed = 0;
+        for entry in self.connection.iter() {
+            let (key, value) = match entry {
+                Ok(entry) => entry,
+                Err(sled::Error::Corruption { .. }) => {
+                    removed += self.connection.len();
+                    self.clear()?;
+                    return Ok(removed);
+                }
+                Err(error) => return Err(error.into()),
+            };
+            if self.decode_value(&key, &value).is_err() {
+                if let Some(old) = self.connection.remove(&key)? {
+                    self.resize(0, key.len() + old.len());
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Recover from a corrupted value, or from a corrupted storage by removing all the
+    /// values, when `set_recover_on_corruption` is set, or else return the error
+    fn recover(&self, error: DiskCacheError) -> Result<(), DiskCacheError> {
+        if !self.recover_on_corruption {
+            return Err(error);
+        }
+        match error {
+            DiskCacheError::StorageError(sled::Error::Corruption { .. }) => self.clear(),
+            DiskCacheError::CacheDeserializtionError(_)
+            | DiskCacheError::Deserialization(_)
+            | DiskCacheError::CacheCompressionError(_)
+            | DiskCacheError::CacheEncryptionError => Ok(()),
+            error => Err(error),
+        }
+    }
+
+    /// Decode a value read, or recover from it being corrupted
+    fn decode_or_recover(
+        &self,
+        key: &str,
+        data: &[u8],
+    ) -> Result<Option<CachedDiskValue<V>>, DiskCacheError> {
+        match self.decode_value(key.as_bytes(), data) {
+            Ok(cached) => Ok(Some(cached)),
+            Err(error) => self.recover(error).map(|()| None),
+        }
+    }
+
+    /// Remove all the values
+    pub(super) fn clear(&self) -> Result<(), DiskCacheError> {
+        self.connection.clear()?;
+        self.size.store(0, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Return the bytes of the keys and values stored, when a max disk size is set
+    pub fn disk_size(&self) -> Option<u64> {
+        self.max_disk_size
+            .map(|_| self.size.load(Ordering::Relaxed))
+    }
+
+    /// Track the bytes of the entries added and removed, with a max disk size
+    fn resize(&self, added: usize, removed: usize) {
+        if self.max_disk_size.is_none() {
+            return;
+        }
+        let _ = self
+            .size
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |size| {
+                Some((size + added as u64).saturating_sub(removed as u64))
+            });
+    }
+
+    /// Evict the values set the longest ago, undecodable values first, until the cache
+    /// uses at most 90% of the max disk size, if it exceeds it
+    fn evict_oldest(&self) -> Result<(), DiskCacheError> {
+        let max_disk_size = match self.max_disk_size {
+            Some(max) if self.size.load(Ordering::Relaxed) > max => max,
+            _ => return Ok(()),
+        };
+        let target = max_disk_size - max_disk_size / 10;
+
+        let mut entries = self
+            .connection
+            .iter()
+            .flatten()
+            .map(|(key, value)| {
+                let created_at = self
+                    .decode_value(&key, &value)
+                    .map(|cached| cached.created_at)
+                    .unwrap_or(UNIX_EPOCH);
+                (created_at, key)
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|(created_at, _)| *created_at);
+        for (_, key) in entries {
+            if self.size.load(Ordering::Relaxed) <= target {
+                break;
+            }
+            if let Some(old) = self.connection.remove(&key)? {
+                self.resize(0, key.len() + old.len());
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Error, Debug)]
@@ -150,6 +872,14 @@ pub enum DiskCacheError {
     CacheDeserializtionError(#[from] rmp_serde::decode::Error),
     #[error("Error serializing cached value")]
     CacheSerializtionError(#[from] rmp_serde::encode::Error),
+    #[error("Error compressing or decompressing cached value")]
+    CacheCompressionError(#[source] std::io::Error),
+    #[error("Error encrypting or decrypting cached value")]
+    CacheEncryptionError,
+    #[error("Error deserializing cached value: {0}")]
+    Deserialization(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Error serializing cached value: {0}")]
+    Serialization(Box<dyn std::error::Error + Send + Sync>),
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -173,24 +903,26 @@ impl<V> CachedDiskValue<V> {
     }
 }
 
-impl<K, V> IOCached<K, V> for DiskCache<K, V>
+impl<K, V, S> IOCached<K, V> for DiskCache<K, V, S>
 where
     K: Display,
     V: Serialize + DeserializeOwned,
+    S: DiskSerializer,
 {
     type Error = DiskCacheError;
 
     fn cache_get(&self, key: &K) -> Result<Option<V>, DiskCacheError> {
-        let key = key.to_string();
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let key = self.disk_key(key);
         let seconds = self.seconds;
         let refresh = self.refresh;
-        let update = |old: Option<&[u8]>| -> Option<Vec<u8>> {
-            let old = old?;
+        let refreshed = |old: &[u8]| -> Option<Vec<u8>> {
             if seconds.is_none() {
                 return Some(old.to_vec());
             }
             let seconds = seconds.unwrap();
-            let mut cached = match rmp_serde::from_slice::<CachedDiskValue<V>>(old) {
+            let mut cached = match self.decode_value(key.as_bytes(), old) {
                 Ok(cached) => cached,
                 Err(_) => {
                     // unable to deserialize, treat it as not existing
@@ -205,29 +937,113 @@ where
                 if refresh {
                     cached.refresh_created_at();
                 }
-                let cache_val =
-                    rmp_serde::to_vec(&cached).expect("error serializing cached disk value");
+                let cache_val = self
+                    .encode_value(key.as_bytes(), &cached)
+                    .expect("error serializing cached disk value");
                 Some(cache_val)
             } else {
                 None
             }
         };
+        // the bytes of the entry before and after the update which was applied
+        let mut resized = (0, 0);
+        let update = |old: Option<&[u8]>| -> Option<Vec<u8>> {
+            let old = old?;
+            let new = refreshed(old);
+            resized = (
+                key.len() + old.len(),
+                new.as_ref().map_or(0, |new| key.len() + new.len()),
+            );
+            new
+        };
 
-        if let Some(data) = self.connection.update_and_fetch(key, update)? {
-            let cached = rmp_serde::from_slice::<CachedDiskValue<V>>(&data)?;
-            Ok(Some(cached.value))
-        } else {
-            Ok(None)
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let data = match self.connection.update_and_fetch(&key, update) {
+            Ok(data) => data,
+            Err(error) => self.recover(error.into()).map(|()| None)?,
+        };
+        self.resize(resized.1, resized.0);
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record_get(data.is_some(), started.elapsed());
+        }
+        let data = match data {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        match self.decode_or_recover(&key, &data)? {
+            Some(cached) => Ok(Some(cached.value)),
+            None => {
+                // the value is corrupted, it is removed
+                if let Some(old) = self.connection.remove(&key)? {
+                    self.resize(0, key.len() + old.len());
+                }
+                Ok(None)
+            }
         }
     }
 
-    fn cache_set(&self, key: K, value: V) -> Result<Option<V>, DiskCacheError> {
-        let key = key.to_string();
-        let value = rmp_serde::to_vec(&CachedDiskValue::new(value))?;
-
-        if let Some(data) = self.connection.insert(key, value)? {
-            let cached = rmp_serde::from_slice::<CachedDiskValue<V>>(&data)?;
+    fn cache_get_expired(&self, key: &K) -> Result<(Option<V>, bool), DiskCacheError> {
+        // the expired values are kept until they are replaced, looked up or purged
+        let disk_key = self.disk_key(key);
+        let data = match self.connection.get(&disk_key) {
+            Ok(data) => data,
+            Err(error) => self.recover(error.into()).map(|()| None)?,
+        };
+        let cached = data
+            .map(|data| self.decode_or_recover(&disk_key, &data))
+            .transpose()?
+            .flatten();
+        match (cached, self.seconds) {
+            (Some(cached), Some(lifetime_seconds))
+                if SystemTime::now()
+                    .duration_since(cached.created_at)
+                    .unwrap_or(Duration::from_secs(0))
+                    >= Duration::from_secs(lifetime_seconds) =>
+            {
+                Ok((Some(cached.value), true))
+            }
+            (Some(_), _) => Ok((self.cache_get(key)?, false)),
+            (None, _) => Ok((None, false)),
+        }
+    }
 
+    fn cache_set(&self, key: K, value: V) -> Result<Option<V>, DiskCacheError> {
+        let key = self.disk_key(&key);
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let value =
+            sled::IVec::from(self.encode_value(key.as_bytes(), &CachedDiskValue::new(value))?);
+        let added = key.len() + value.len();
+
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let data = match self.connection.insert(&key, value.clone()) {
+            Ok(data) => data,
+            Err(error) => {
+                self.recover(error.into())?;
+                self.connection.insert(&key, value)?
+            }
+        };
+        self.resize(added, data.as_ref().map_or(0, |old| key.len() + old.len()));
+        self.evict_oldest()?;
+        if self.sync_writes {
+            self.connection.flush()?;
+        }
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record(Operation::Insert, started.elapsed());
+        }
+        if let Some(cached) = data
+            .map(|data| self.decode_or_recover(&key, &data))
+            .transpose()?
+            .flatten()
+        {
             if let Some(lifetime_seconds) = self.seconds {
                 if SystemTime::now()
                     .duration_since(cached.created_at)
@@ -247,10 +1063,25 @@ where
     }
 
     fn cache_remove(&self, key: &K) -> Result<Option<V>, DiskCacheError> {
-        let key = key.to_string();
-        if let Some(data) = self.connection.remove(key)? {
-            let cached = rmp_serde::from_slice::<CachedDiskValue<V>>(&data)?;
-
+        let key = self.disk_key(key);
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let data = match self.connection.remove(&key) {
+            Ok(data) => data,
+            Err(error) => self.recover(error.into()).map(|()| None)?,
+        };
+        if self.sync_writes && data.is_some() {
+            self.connection.flush()?;
+        }
+        #[cfg(feature = "latency_stats")]
+        self.latency
+            .record(Operation::Backend, round_trip.elapsed());
+        self.resize(0, data.as_ref().map_or(0, |old| key.len() + old.len()));
+        if let Some(cached) = data
+            .map(|data| self.decode_or_recover(&key, &data))
+            .transpose()?
+            .flatten()
+        {
             if let Some(lifetime_seconds) = self.seconds {
                 if SystemTime::now()
                     .duration_since(cached.created_at)
@@ -284,6 +1115,11 @@ where
         self.refresh = refresh;
         old
     }
+
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
 }
 
 #[cfg(test)]
@@ -291,7 +1127,7 @@ where
 mod test_DiskCache {
     use googletest::{
         assert_that,
-        matchers::{anything, eq, none, ok, some},
+        matchers::{anything, eq, err, none, ok, some},
         GoogleTestSupport as _,
     };
     use std::thread::sleep;
@@ -401,6 +1237,33 @@ mod test_DiskCache {
         );
     }
 
+    #[test]
+    fn expired_values_are_returned_as_expired() {
+        let tmp_dir = temp_dir!();
+        let cache: DiskCache<u32, u32> = DiskCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .set_lifespan(LIFE_SPAN_1_SEC)
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_get_expired(&TEST_KEY), ok(eq((None, false))));
+        cache.cache_set(TEST_KEY, TEST_VAL).unwrap();
+        assert_that!(
+            cache.cache_get_expired(&TEST_KEY),
+            ok(eq((Some(TEST_VAL), false)))
+        );
+
+        sleep(Duration::from_secs(LIFE_SPAN_1_SEC));
+        assert_that!(
+            cache.cache_get_expired(&TEST_KEY),
+            ok(eq((Some(TEST_VAL), true))),
+            "Getting an expired key-value should return it as expired"
+        );
+        // until it is looked up
+        assert_that!(cache.cache_get(&TEST_KEY), ok(none()));
+        assert_that!(cache.cache_get_expired(&TEST_KEY), ok(eq((None, false))));
+    }
+
     #[test]
     fn set_lifespan_to_a_different_lifespan_is_respected() {
         // COPY PASTE of [values_expire_when_lifespan_elapses_returning_none]
@@ -561,4 +1424,308 @@ mod test_DiskCache {
         // remove the cache dir to clean up the test as we're not using a temp dir
         std::fs::remove_dir_all(cache.disk_path).expect("error in clean up removeing the cache dir")
     }
+
+    #[test]
+    fn oldest_values_are_evicted_over_max_disk_size() {
+        let tmp_dir = temp_dir!();
+        let cache: DiskCache<u32, String> = DiskCache::new("test-cache-max-size")
+            .set_disk_directory(tmp_dir.path())
+            .set_max_disk_size(1000)
+            .build()
+            .unwrap();
+
+        let value = "x".repeat(200);
+        for key in 0..4 {
+            assert_that!(cache.cache_set(key, value.clone()), ok(none()));
+            sleep(Duration::from_millis(10));
+        }
+        assert_that!(cache.disk_size(), some(anything()));
+        // the fifth value exceeds the limit, the oldest values are evicted down to 90%
+        assert_that!(cache.cache_set(4, value.clone()), ok(none()));
+        assert!(cache.disk_size().unwrap() <= 900);
+        assert_that!(cache.cache_get(&0), ok(none()));
+        assert_that!(cache.cache_get(&4), ok(some(eq(value))));
+
+        cache.cache_remove(&4).unwrap();
+        cache.cache_remove(&3).unwrap();
+        cache.cache_remove(&2).unwrap();
+        cache.cache_remove(&1).unwrap();
+        assert_that!(cache.disk_size(), some(eq(0)));
+
+        drop(cache);
+    }
+
+    #[test]
+    fn values_written_without_compression_are_still_read() {
+        let serializer = MessagePackDiskSerializer;
+        let bytes = encode(
+            &serializer,
+            &CachedDiskValue::new(TEST_VAL),
+            Compression::None,
+        )
+        .unwrap();
+        assert_that!(
+            decode::<u32, _>(&serializer, &bytes).map(|cached| cached.value),
+            ok(eq(TEST_VAL))
+        );
+        assert_that!(
+            decode::<u32, _>(&serializer, &[COMPRESSED_MARKER, u8::MAX]).map(|cached| cached.value),
+            err(anything()),
+            "Decoding a value compressed with an unknown algorithm should fail"
+        );
+    }
+
+    #[cfg(feature = "disk_zstd")]
+    #[test]
+    fn compressed_values_are_read_back() {
+        let tmp_dir = temp_dir!();
+        let cache: DiskCache<u32, String> = DiskCache::new("test-cache-zstd")
+            .set_disk_directory(tmp_dir.path())
+            .set_compression(Compression::Zstd(3))
+            .build()
+            .unwrap();
+
+        let value = "compressible ".repeat(100);
+        assert_that!(cache.cache_set(TEST_KEY, value.clone()), ok(none()));
+        let stored = cache.connection.get(TEST_KEY.to_string()).unwrap().unwrap();
+        assert_that!(stored[0], eq(COMPRESSED_MARKER));
+        assert!(stored.len() < value.len() / 4);
+        assert_that!(cache.cache_get(&TEST_KEY), ok(some(eq(value))));
+
+        drop(cache);
+    }
+
+    /// MessagePack with the field names, which may start with any byte
+    struct NamedMessagePack;
+
+    impl DiskSerializer for NamedMessagePack {
+        fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, DiskCacheError> {
+            rmp_serde::to_vec_named(value).map_err(|e| DiskCacheError::Serialization(Box::new(e)))
+        }
+
+        fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, DiskCacheError> {
+            rmp_serde::from_slice(bytes).map_err(|e| DiskCacheError::Deserialization(Box::new(e)))
+        }
+    }
+
+    #[test]
+    fn values_are_written_with_the_serializer_set() {
+        let tmp_dir = temp_dir!();
+        let cache: DiskCache<u32, String, _> = DiskCache::new("test-cache-serializer")
+            .set_disk_directory(tmp_dir.path())
+            .set_lifespan(3600)
+            .set_serializer(NamedMessagePack)
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_set(TEST_KEY, "value".to_string()), ok(none()));
+        let stored = cache.connection.get(TEST_KEY.to_string()).unwrap().unwrap();
+        assert_eq!(stored[..2], [COMPRESSED_MARKER, NO_ALGORITHM]);
+        assert!(stored
+            .windows(b"created_at".len())
+            .any(|w| w == b"created_at"));
+        assert_that!(
+            cache.cache_get(&TEST_KEY),
+            ok(some(eq("value".to_string())))
+        );
+
+        cache.remove_expired_entries();
+        assert_that!(
+            cache.cache_remove(&TEST_KEY),
+            ok(some(eq("value".to_string())))
+        );
+
+        drop(cache);
+    }
+
+    #[cfg(feature = "disk_encryption")]
+    #[test]
+    fn encrypted_values_are_only_read_with_the_key() {
+        let tmp_dir = temp_dir!();
+        let build = |encryption: Option<Encryption>| {
+            let builder =
+                DiskCache::new("test-cache-encryption").set_disk_directory(tmp_dir.path());
+            match encryption {
+                Some(encryption) => builder.set_encryption(encryption),
+                None => builder,
+            }
+            .build()
+            .unwrap()
+        };
+
+        let cache: DiskCache<u32, String> = build(Some(
+            Encryption::new(Cipher::Aes256Gcm, &[1; 32]).hash_keys(true),
+        ));
+        assert_that!(cache.cache_set(TEST_KEY, "secret".to_string()), ok(none()));
+        assert_that!(
+            cache.cache_get(&TEST_KEY),
+            ok(some(eq("secret".to_string())))
+        );
+        // the key is hashed, and the value sealed
+        assert_that!(cache.connection.get(TEST_KEY.to_string()), ok(none()));
+        let (key, stored) = cache.connection.iter().next().unwrap().unwrap();
+        assert_that!(key.len(), eq(64));
+        assert_that!(stored[..2], eq([COMPRESSED_MARKER, AES_256_GCM_ALGORITHM]));
+        assert!(!stored.windows(b"secret".len()).any(|w| w == b"secret"));
+        drop(cache);
+
+        let cache: DiskCache<u32, String> = build(Some(
+            Encryption::new(Cipher::Aes256Gcm, &[2; 32]).hash_keys(true),
+        ));
+        assert_that!(
+            cache.cache_get(&TEST_KEY),
+            ok(none()),
+            "The hashed key should not be found with another key"
+        );
+        drop(cache);
+
+        let cache: DiskCache<u32, String> = build(None);
+        cache
+            .connection
+            .insert(TEST_KEY.to_string(), stored)
+            .unwrap();
+        assert_that!(
+            cache.cache_remove(&TEST_KEY),
+            err(anything()),
+            "Reading an encrypted value without encryption should fail"
+        );
+        assert_that!(cache.cache_set(TEST_KEY, "plain".to_string()), ok(none()));
+        drop(cache);
+
+        let cache: DiskCache<u32, String> =
+            build(Some(Encryption::new(Cipher::ChaCha20Poly1305, &[1; 32])));
+        assert_that!(
+            cache.cache_remove(&TEST_KEY),
+            err(anything()),
+            "Reading a plaintext value with encryption should fail"
+        );
+        assert_that!(cache.cache_set(TEST_KEY, "secret".to_string()), ok(none()));
+        assert_that!(
+            cache.cache_get(&TEST_KEY),
+            ok(some(eq("secret".to_string())))
+        );
+        drop(cache);
+    }
+
+    #[test]
+    fn synced_writes_are_read_after_reopening() {
+        let tmp_dir = temp_dir!();
+        let build = || -> DiskCache<u32, u32> {
+            DiskCache::new("test-cache-sync-writes")
+                .set_disk_directory(tmp_dir.path())
+                .set_sync_writes(true)
+                .set_flush_every(Duration::ZERO)
+                .build()
+                .unwrap()
+        };
+
+        let cache = build();
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        assert_that!(cache.flush(), ok(anything()));
+        drop(cache);
+
+        let cache = build();
+        assert_that!(cache.cache_remove(&TEST_KEY), ok(some(eq(TEST_VAL))));
+        drop(cache);
+
+        let cache = build();
+        assert_that!(cache.cache_get(&TEST_KEY), ok(none()));
+        drop(cache);
+    }
+
+    #[test]
+    fn corrupted_values_are_removed_when_recovering() {
+        let tmp_dir = temp_dir!();
+        let build = |recover: bool| -> DiskCache<u32, u32> {
+            DiskCache::new("test-cache-corruption")
+                .set_disk_directory(tmp_dir.path())
+                .set_recover_on_corruption(recover)
+                .build()
+                .unwrap()
+        };
+        let corrupted = [COMPRESSED_MARKER, u8::MAX, 1, 2, 3];
+
+        let cache = build(false);
+        cache.connection.insert("1", &corrupted).unwrap();
+        assert_that!(cache.cache_get(&1), err(anything()));
+        assert_that!(cache.cache_get(&1), err(anything()));
+        drop(cache);
+
+        let cache = build(true);
+        assert_that!(cache.cache_get(&1), ok(none()));
+        assert_that!(cache.connection.get("1"), ok(none()));
+
+        cache.connection.insert("1", &corrupted).unwrap();
+        assert_that!(cache.cache_set(1, TEST_VAL), ok(none()));
+        assert_that!(cache.cache_get(&1), ok(some(eq(TEST_VAL))));
+        cache.connection.insert("2", &corrupted).unwrap();
+        assert_that!(cache.cache_remove(&2), ok(none()));
+        drop(cache);
+
+        let cache = build(false);
+        cache.connection.insert("2", &corrupted).unwrap();
+        cache.connection.insert("3", &b"garbage"[..]).unwrap();
+        assert_that!(cache.repair(), ok(eq(2)));
+        assert_that!(cache.cache_get(&1), ok(some(eq(TEST_VAL))));
+        assert_that!(cache.connection.len(), eq(1));
+        drop(cache);
+    }
+
+    #[test]
+    fn caches_sharing_a_database_use_their_own_tree() {
+        let tmp_dir = temp_dir!();
+        let build = |name: &str| -> DiskCache<u32, u32> {
+            DiskCache::new(name)
+                .set_disk_directory(tmp_dir.path())
+                .set_database("test-database")
+                .build()
+                .unwrap()
+        };
+
+        let a = build("a");
+        let b = build("b");
+        assert_that!(a.cache_set(TEST_KEY, 1), ok(none()));
+        assert_that!(b.cache_set(TEST_KEY, 2), ok(none()));
+        assert_that!(a.cache_get(&TEST_KEY), ok(some(eq(1))));
+        assert_that!(b.cache_get(&TEST_KEY), ok(some(eq(2))));
+
+        let mut trees = a.database_trees();
+        trees.sort();
+        assert_that!(trees, eq(vec!["a".to_string(), "b".to_string()]));
+        drop(b);
+        assert_that!(a.drop_database_tree("b"), ok(eq(true)));
+        assert_that!(a.database_trees(), eq(vec!["a".to_string()]));
+        drop(a);
+
+        let a = build("a");
+        assert_that!(a.cache_get(&TEST_KEY), ok(some(eq(1))));
+        drop(a);
+    }
+
+    #[test]
+    fn opening_a_database_open_elsewhere_waits_for_the_lock() {
+        let tmp_dir = temp_dir!();
+        let build = |timeout: Duration| {
+            DiskCache::<u32, u32>::new("test-cache-lock")
+                .set_disk_directory(tmp_dir.path())
+                .set_lock_timeout(timeout)
+                .build()
+        };
+
+        // the lock is per open file, so it is also held within a process
+        let cache = build(Duration::ZERO).unwrap();
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        assert!(matches!(
+            build(Duration::from_millis(20)),
+            Err(DiskCacheBuildError::Locked(_))
+        ));
+
+        let closing = std::thread::spawn(move || {
+            sleep(Duration::from_millis(50));
+            drop(cache);
+        });
+        let cache = build(Duration::from_secs(10)).unwrap();
+        assert_that!(cache.cache_get(&TEST_KEY), ok(some(eq(TEST_VAL))));
+        closing.join().unwrap();
+    }
 }
@@ -0,0 +1,558 @@
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
+use crate::IOCachedAsync;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+use aws_sdk_dynamodb::Client;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct AsyncDynamoCacheBuilder<K, V> {
+    seconds: u64,
+    refresh: bool,
+    namespace: String,
+    prefix: String,
+    table_name: Option<String>,
+    key_attribute: String,
+    value_attribute: String,
+    ttl_attribute: String,
+    consistent_reads: bool,
+    client: Option<Client>,
+    sdk_config: Option<aws_config::SdkConfig>,
+    _phantom: PhantomData<(K, V)>,
+}
+
+const ENV_KEY: &str = "CACHED_DYNAMODB_TABLE";
+const DEFAULT_NAMESPACE: &str = "cached-dynamodb-store:";
+const DEFAULT_KEY_ATTRIBUTE: &str = "key";
+const DEFAULT_VALUE_ATTRIBUTE: &str = "value";
+const DEFAULT_TTL_ATTRIBUTE: &str = "expires_at";
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DynamoCacheBuildError {
+    #[error("Table name not specified or invalid in env var {env_key:?}: {error:?}")]
+    MissingTableName {
+        env_key: String,
+        error: std::env::VarError,
+    },
+}
+
+impl<K, V> AsyncDynamoCacheBuilder<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Initialize an `AsyncDynamoCacheBuilder`
+    pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> AsyncDynamoCacheBuilder<K, V> {
+        Self {
+            seconds,
+            refresh: false,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            prefix: prefix.as_ref().to_string(),
+            table_name: None,
+            key_attribute: DEFAULT_KEY_ATTRIBUTE.to_string(),
+            value_attribute: DEFAULT_VALUE_ATTRIBUTE.to_string(),
+            ttl_attribute: DEFAULT_TTL_ATTRIBUTE.to_string(),
+            consistent_reads: false,
+            client: None,
+            sdk_config: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Specify the cache TTL/lifespan in seconds
+    #[must_use]
+    pub fn set_lifespan(mut self, seconds: u64) -> Self {
+        self.seconds = seconds;
+        self
+    }
+
+    /// Specify whether cache hits refresh the TTL
+    #[must_use]
+    pub fn set_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Set the namespace for cache keys. Defaults to `cached-dynamodb-store:`.
+    /// Used to generate keys formatted as: `{namespace}{prefix}{key}`
+    /// Note that no delimiters are implicitly added so you may pass
+    /// an empty string if you want there to be no namespace on keys.
+    #[must_use]
+    pub fn set_namespace<S: AsRef<str>>(mut self, namespace: S) -> Self {
+        self.namespace = namespace.as_ref().to_string();
+        self
+    }
+
+    /// Set the prefix for cache keys.
+    /// Used to generate keys formatted as: `{namespace}{prefix}{key}`
+    /// Note that no delimiters are implicitly added so you may pass
+    /// an empty string if you want there to be no prefix on keys.
+    #[must_use]
+    pub fn set_prefix<S: AsRef<str>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.as_ref().to_string();
+        self
+    }
+
+    /// Set the name of the DynamoDB table holding the cached values
+    #[must_use]
+    pub fn set_table_name<S: AsRef<str>>(mut self, table_name: S) -> Self {
+        self.table_name = Some(table_name.as_ref().to_string());
+        self
+    }
+
+    /// Set the name of the table's (string) partition key attribute. Defaults to `key`.
+    #[must_use]
+    pub fn set_key_attribute<S: AsRef<str>>(mut self, attribute: S) -> Self {
+        self.key_attribute = attribute.as_ref().to_string();
+        self
+    }
+
+    /// Set the name of the attribute holding the serialized values. Defaults to `value`.
+    #[must_use]
+    pub fn set_value_attribute<S: AsRef<str>>(mut self, attribute: S) -> Self {
+        self.value_attribute = attribute.as_ref().to_string();
+        self
+    }
+
+    /// Set the name of the attribute holding the expiry time of values, in seconds
+    /// since the unix epoch. Defaults to `expires_at`. Enable DynamoDB's time to live
+    /// on this attribute to have expired values deleted by DynamoDB.
+    #[must_use]
+    pub fn set_ttl_attribute<S: AsRef<str>>(mut self, attribute: S) -> Self {
+        self.ttl_attribute = attribute.as_ref().to_string();
+        self
+    }
+
+    /// Specify whether reads are strongly consistent. Defaults to `false`,
+    /// in which case a value may not be visible right after it is set.
+    #[must_use]
+    pub fn set_consistent_reads(mut self, consistent_reads: bool) -> Self {
+        self.consistent_reads = consistent_reads;
+        self
+    }
+
+    /// Use an already configured DynamoDB client
+    #[must_use]
+    pub fn set_client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Create the DynamoDB client from this AWS SDK configuration. Ignored if a client is set.
+    #[must_use]
+    pub fn set_sdk_config(mut self, sdk_config: aws_config::SdkConfig) -> Self {
+        self.sdk_config = Some(sdk_config);
+        self
+    }
+
+    /// Return the current table name or load from the env var: `CACHED_DYNAMODB_TABLE`
+    ///
+    /// # Errors
+    ///
+    /// Will return `DynamoCacheBuildError::MissingTableName` if the table name is not set
+    pub fn table_name(&self) -> Result<String, DynamoCacheBuildError> {
+        match self.table_name {
+            Some(ref table_name) => Ok(table_name.clone()),
+            None => std::env::var(ENV_KEY).map_err(|e| DynamoCacheBuildError::MissingTableName {
+                env_key: ENV_KEY.to_string(),
+                error: e,
+            }),
+        }
+    }
+
+    async fn create_client(&mut self) -> Client {
+        if let Some(client) = self.client.take() {
+            return client;
+        }
+        let sdk_config = match self.sdk_config.take() {
+            Some(sdk_config) => sdk_config,
+            None => aws_config::load_from_env().await,
+        };
+        Client::new(&sdk_config)
+    }
+
+    /// The last step in building an `AsyncDynamoCache` is to call `build()`.
+    /// Without a client or SDK configuration, the AWS configuration is loaded
+    /// from the environment.
+    ///
+    /// # Errors
+    ///
+    /// Will return a `DynamoCacheBuildError`, depending on the error
+    pub async fn build(mut self) -> Result<AsyncDynamoCache<K, V>, DynamoCacheBuildError> {
+        let table_name = self.table_name()?;
+        let client = self.create_client().await;
+        Ok(AsyncDynamoCache {
+            seconds: self.seconds,
+            refresh: self.refresh,
+            namespace: self.namespace,
+            prefix: self.prefix,
+            table_name,
+            key_attribute: self.key_attribute,
+            value_attribute: self.value_attribute,
+            ttl_attribute: self.ttl_attribute,
+            consistent_reads: self.consistent_reads,
+            client,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            _phantom: PhantomData,
+        })
+    }
+}
+
+/// Cache store backed by DynamoDB
+///
+/// Each value is stored as an item of a table whose (string) partition key is the
+/// cache key, along with its expiry time in seconds since the unix epoch. Expired
+/// items are never returned; enable DynamoDB's time to live on the expiry attribute
+/// to have them deleted.
+pub struct AsyncDynamoCache<K, V> {
+    pub(super) seconds: u64,
+    pub(super) refresh: bool,
+    pub(super) namespace: String,
+    pub(super) prefix: String,
+    table_name: String,
+    key_attribute: String,
+    value_attribute: String,
+    ttl_attribute: String,
+    consistent_reads: bool,
+    client: Client,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
+    _phantom: PhantomData<(K, V)>,
+}
+
+type Item = HashMap<String, AttributeValue>;
+
+impl<K, V> AsyncDynamoCache<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    #[allow(clippy::new_ret_no_self)]
+    /// Initialize an `AsyncDynamoCacheBuilder`
+    pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> AsyncDynamoCacheBuilder<K, V> {
+        AsyncDynamoCacheBuilder::new(prefix, seconds)
+    }
+
+    /// Return the name of the table holding the cached values
+    #[must_use]
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    /// Set a cached value only if there is no (unexpired) value cached for the key yet,
+    /// using a conditional write. Returns whether the value was set.
+    ///
+    /// # Errors
+    ///
+    /// Will return a `DynamoCacheError`, depending on the error
+    pub async fn cache_set_if_absent(&self, key: K, val: V) -> Result<bool, DynamoCacheError> {
+        let item = self.item(&key, &val)?;
+        let res = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .condition_expression("attribute_not_exists(#key) OR #ttl <= :now")
+            .expression_attribute_names("#key", &self.key_attribute)
+            .expression_attribute_names("#ttl", &self.ttl_attribute)
+            .expression_attribute_values(":now", AttributeValue::N(now_secs().to_string()))
+            .send()
+            .await;
+        match res {
+            Ok(_) => Ok(true),
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(aws_sdk_dynamodb::Error::from(e).into()),
+        }
+    }
+
+    fn generate_key(&self, key: &K) -> AttributeValue {
+        AttributeValue::S(format!("{}{}{}", self.namespace, self.prefix, key))
+    }
+
+    fn new_expiry(&self) -> AttributeValue {
+        AttributeValue::N(now_secs().saturating_add(self.seconds).to_string())
+    }
+
+    fn item(&self, key: &K, val: &V) -> Result<Item, DynamoCacheError> {
+        let value = serde_json::to_string(&CachedDynamoValue::new(val))
+            .map_err(|e| DynamoCacheError::CacheSerializationError { error: e })?;
+        Ok(HashMap::from([
+            (self.key_attribute.clone(), self.generate_key(key)),
+            (self.value_attribute.clone(), AttributeValue::S(value)),
+            (self.ttl_attribute.clone(), self.new_expiry()),
+        ]))
+    }
+
+    /// Decode a stored item, returning `None` if it has expired
+    fn decode(&self, item: &Item) -> Result<Option<V>, DynamoCacheError> {
+        let expires_at = item
+            .get(&self.ttl_attribute)
+            .and_then(|ttl| ttl.as_n().ok())
+            .and_then(|ttl| ttl.parse::<u64>().ok());
+        if expires_at.is_some_and(|expires_at| expires_at <= now_secs()) {
+            return Ok(None);
+        }
+        let s = item
+            .get(&self.value_attribute)
+            .and_then(|value| value.as_s().ok())
+            .ok_or_else(|| DynamoCacheError::MissingValue(self.value_attribute.clone()))?;
+        let v: CachedDynamoValue<V> =
+            serde_json::from_str(s).map_err(|e| DynamoCacheError::CacheDeserializationError {
+                cached_value: s.clone(),
+                error: e,
+            })?;
+        Ok(Some(v.value))
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Error, Debug)]
+pub enum DynamoCacheError {
+    #[error("dynamodb error")]
+    DynamoError(#[source] Box<aws_sdk_dynamodb::Error>),
+    #[error("Cached item is missing its {0:?} attribute")]
+    MissingValue(String),
+    #[error("Error deserializing cached value {cached_value:?}: {error:?}")]
+    CacheDeserializationError {
+        cached_value: String,
+        error: serde_json::Error,
+    },
+    #[error("Error serializing cached value: {error:?}")]
+    CacheSerializationError { error: serde_json::Error },
+}
+
+impl From<aws_sdk_dynamodb::Error> for DynamoCacheError {
+    fn from(error: aws_sdk_dynamodb::Error) -> Self {
+        // the SDK error is large, boxing it keeps the `Result`s returned by the cache small
+        Self::DynamoError(Box::new(error))
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedDynamoValue<V> {
+    pub(crate) value: V,
+    pub(crate) version: Option<u64>,
+}
+
+impl<V> CachedDynamoValue<V> {
+    fn new(value: V) -> Self {
+        Self {
+            value,
+            version: Some(1),
+        }
+    }
+}
+
+#[async_trait]
+impl<K, V> IOCachedAsync<K, V> for AsyncDynamoCache<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    type Error = DynamoCacheError;
+
+    /// Get a cached value
+    async fn cache_get(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let key = self.generate_key(key);
+        let res = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key(&self.key_attribute, key.clone())
+            .consistent_read(self.consistent_reads)
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?;
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Backend, started.elapsed());
+
+        let value = match res.item() {
+            Some(item) => self.decode(item)?,
+            None => None,
+        };
+        if value.is_some() && self.refresh {
+            // only refresh values that still exist, a concurrent removal must not be undone
+            let res = self
+                .client
+                .update_item()
+                .table_name(&self.table_name)
+                .key(&self.key_attribute, key)
+                .update_expression("SET #ttl = :ttl")
+                .condition_expression("attribute_exists(#key)")
+                .expression_attribute_names("#key", &self.key_attribute)
+                .expression_attribute_names("#ttl", &self.ttl_attribute)
+                .expression_attribute_values(":ttl", self.new_expiry())
+                .send()
+                .await;
+            if let Err(e) = res {
+                if !e
+                    .as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception())
+                {
+                    return Err(aws_sdk_dynamodb::Error::from(e).into());
+                }
+            }
+        }
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(value.is_some(), started.elapsed());
+        Ok(value)
+    }
+
+    /// Set a cached value
+    async fn cache_set(&self, key: K, val: V) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let item = self.item(&key, &val)?;
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let res = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .return_values(ReturnValue::AllOld)
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?;
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record(Operation::Insert, started.elapsed());
+        }
+        match res.attributes() {
+            Some(old) => self.decode(old),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove a cached value
+    async fn cache_remove(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let res = self
+            .client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key(&self.key_attribute, self.generate_key(key))
+            .return_values(ReturnValue::AllOld)
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?;
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Backend, started.elapsed());
+        match res.attributes() {
+            Some(old) => self.decode(old),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the flag to control whether cache hits refresh the ttl of cached values, returns the old flag value
+    fn cache_set_refresh(&mut self, refresh: bool) -> bool {
+        let old = self.refresh;
+        self.refresh = refresh;
+        old
+    }
+
+    /// Return the lifespan of cached values (time to eviction)
+    fn cache_lifespan(&self) -> Option<u64> {
+        Some(self.seconds)
+    }
+
+    /// Set the lifespan of cached values, returns the old value
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let old = self.seconds;
+        self.seconds = seconds;
+        Some(old)
+    }
+
+    /// Return latency histograms of this cache's operations
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn cache(seconds: u64) -> AsyncDynamoCache<u32, u32> {
+        let sdk_config = aws_config::SdkConfig::builder()
+            .behavior_version(aws_config::BehaviorVersion::latest())
+            .region(aws_config::Region::new("us-east-1"))
+            .build();
+        AsyncDynamoCache::new("test:", seconds)
+            .set_table_name("cached-test")
+            .set_sdk_config(sdk_config)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn items_contain_key_value_and_expiry() {
+        let cache = cache(10).await;
+        let item = cache.item(&1, &100).unwrap();
+        assert_eq!(
+            item.get("key"),
+            Some(&AttributeValue::S(
+                "cached-dynamodb-store:test:1".to_string()
+            ))
+        );
+        let expires_at: u64 = item
+            .get("expires_at")
+            .unwrap()
+            .as_n()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(expires_at > now_secs() && expires_at <= now_secs() + 10);
+        assert_eq!(cache.decode(&item).unwrap(), Some(100));
+    }
+
+    #[tokio::test]
+    async fn expired_items_decode_to_none() {
+        let cache = cache(10).await;
+        let mut item = cache.item(&1, &100).unwrap();
+        item.insert(
+            "expires_at".to_string(),
+            AttributeValue::N((now_secs() - 1).to_string()),
+        );
+        assert_eq!(cache.decode(&item).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn missing_table_name_is_an_error() {
+        std::env::remove_var(ENV_KEY);
+        let res = AsyncDynamoCache::<u32, u32>::new("test:", 1)
+            .set_client(cache(1).await.client)
+            .build()
+            .await;
+        assert!(matches!(
+            res,
+            Err(DynamoCacheBuildError::MissingTableName { .. })
+        ));
+    }
+}
@@ -1,4 +1,7 @@
-use super::{Cached, SizedCache};
+use super::{CacheBuildError, Cached, CachedRead, CachedWrite, SizedCache};
+use crate::events::CacheEvent;
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
 use crate::{stores::timed::Status, CloneCached};
 use std::hash::Hash;
 
@@ -21,20 +24,44 @@ pub struct ExpiringValueCache<K: Hash + Eq, V: CanExpire> {
     pub(super) store: SizedCache<K, V>,
     pub(super) hits: u64,
     pub(super) misses: u64,
+    #[cfg(feature = "latency_stats")]
+    pub(super) latency: LatencyStats,
 }
 
 impl<K: Clone + Hash + Eq, V: CanExpire> ExpiringValueCache<K, V> {
     /// Creates a new `ExpiringValueCache` with a given size limit and
     /// pre-allocated backing data.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0, see `try_with_size`
     #[must_use]
     pub fn with_size(size: usize) -> ExpiringValueCache<K, V> {
         ExpiringValueCache {
             store: SizedCache::with_size(size),
             hits: 0,
             misses: 0,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
         }
     }
 
+    /// Creates a new `ExpiringValueCache` with a given size limit and
+    /// pre-allocated backing data.
+    ///
+    /// # Errors
+    ///
+    /// Will return a `CacheBuildError` if size is 0 or the backing data cannot be allocated
+    pub fn try_with_size(size: usize) -> Result<ExpiringValueCache<K, V>, CacheBuildError> {
+        Ok(ExpiringValueCache {
+            store: SizedCache::try_with_size(size)?,
+            hits: 0,
+            misses: 0,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+        })
+    }
+
     fn status<Q>(&mut self, k: &Q) -> Status
     where
         K: std::borrow::Borrow<Q>,
@@ -53,6 +80,23 @@ impl<K: Clone + Hash + Eq, V: CanExpire> ExpiringValueCache<K, V> {
         }
     }
 
+    /// Remove the least recently used value, returning it along with its key. The
+    /// expired values found on the way are dropped.
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        while let Some((key, value)) = self.store.pop_lru() {
+            if !value.is_expired() {
+                return Some((key, value));
+            }
+        }
+        None
+    }
+
+    /// Release the memory the cache keeps beyond its values, e.g. after most of them
+    /// were removed
+    pub fn shrink_to_fit(&mut self) {
+        self.store.shrink_to_fit();
+    }
+
     /// Remove any expired values from the cache
     pub fn flush(&mut self) {
         self.store.retain(|_, v| !v.is_expired());
@@ -66,14 +110,28 @@ impl<K: Hash + Eq + Clone, V: CanExpire> Cached<K, V> for ExpiringValueCache<K,
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        match self.status(k) {
+        self.cache_get_key_value(k).map(|(_, value)| value)
+    }
+
+    fn cache_get_key_value<Q>(&mut self, k: &Q) -> Option<(&K, &V)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let status = self.status(k);
+        #[cfg(feature = "latency_stats")]
+        self.latency
+            .record_get(matches!(status, Status::Found), started.elapsed());
+        match status {
             Status::NotFound => {
                 self.misses += 1;
                 None
             }
             Status::Found => {
                 self.hits += 1;
-                self.store.cache_get(k)
+                self.store.cache_get_key_value(k)
             }
             Status::Expired => {
                 self.misses += 1;
@@ -88,7 +146,13 @@ impl<K: Hash + Eq + Clone, V: CanExpire> Cached<K, V> for ExpiringValueCache<K,
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        match self.status(k) {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let status = self.status(k);
+        #[cfg(feature = "latency_stats")]
+        self.latency
+            .record_get(matches!(status, Status::Found), started.elapsed());
+        match status {
             Status::NotFound => {
                 self.misses += 1;
                 None
@@ -105,6 +169,18 @@ impl<K: Hash + Eq + Clone, V: CanExpire> Cached<K, V> for ExpiringValueCache<K,
         }
     }
 
+    /// The values are returned from most to least recently used.
+    fn cache_drain_where<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        mut drain: F,
+    ) -> impl Iterator<Item = (K, V)> {
+        let drained = self
+            .store
+            .cache_drain_where(|key, value| value.is_expired() || drain(key, value))
+            .collect::<Vec<_>>();
+        drained.into_iter().filter(|(_, value)| !value.is_expired())
+    }
+
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V {
         // get_or_set_with_if will set the value in the cache if an existing
         // value is not valid, which, in our case, is if the value has expired.
@@ -116,9 +192,63 @@ impl<K: Hash + Eq + Clone, V: CanExpire> Cached<K, V> for ExpiringValueCache<K,
         }
         v
     }
+
+    fn cache_subscribe(&mut self) -> Option<std::sync::mpsc::Receiver<CacheEvent<K, V>>>
+    where
+        V: Clone,
+    {
+        self.store.cache_subscribe()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: CanExpire> CachedRead<K, V> for ExpiringValueCache<K, V> {
+    fn cache_size(&self) -> usize {
+        self.store.cache_size()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+
+    fn cache_peek<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store.cache_peek(k).filter(|v| !v.is_expired())
+    }
+
+    /// The values are iterated from most to least recently used.
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.store
+            .cache_iter()
+            .filter(|(_, value)| !value.is_expired())
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: CanExpire> CachedWrite<K, V> for ExpiringValueCache<K, V> {
     fn cache_set(&mut self, k: K, v: V) -> Option<V> {
-        self.store.cache_set(k, v)
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let old = self.store.cache_set(k, v);
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Insert, started.elapsed());
+        old
     }
+
     fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
     where
         K: std::borrow::Borrow<Q>,
@@ -126,24 +256,24 @@ impl<K: Hash + Eq + Clone, V: CanExpire> Cached<K, V> for ExpiringValueCache<K,
     {
         self.store.cache_remove(k)
     }
+
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, remove: F) -> Option<usize> {
+        self.store.cache_remove_where(remove)
+    }
+
     fn cache_clear(&mut self) {
         self.store.cache_clear();
     }
+
     fn cache_reset(&mut self) {
         self.store.cache_reset();
     }
-    fn cache_size(&self) -> usize {
-        self.store.cache_size()
-    }
-    fn cache_hits(&self) -> Option<u64> {
-        Some(self.hits)
-    }
-    fn cache_misses(&self) -> Option<u64> {
-        Some(self.misses)
-    }
+
     fn cache_reset_metrics(&mut self) {
         self.hits = 0;
         self.misses = 0;
+        #[cfg(feature = "latency_stats")]
+        self.latency.reset();
     }
 }
 
@@ -0,0 +1,318 @@
+use super::partitioned::Partitions;
+use super::{CacheBuildError, Cached, CachedRead, CachedWrite};
+use std::cmp::Eq;
+use std::hash::Hash;
+
+#[cfg(feature = "ahash")]
+use hashbrown::HashMap;
+
+#[cfg(not(feature = "ahash"))]
+use std::collections::HashMap;
+
+/// Fair / `Sized` Cache
+///
+/// Stores values under `(class, key)` pairs, e.g. `(Kind::Render, key)`, sharing
+/// a single capacity between key classes according to their registered shares.
+/// When the cache is full, the least recently used value of the class exceeding
+/// its target share the most is evicted, so that a class used heavily cannot evict
+/// the values of the classes staying within their share.
+///
+/// The target of a class is its share of the sum of the shares of the registered
+/// classes. Classes without a registered share have a target of zero: they can use
+/// the capacity left over by the other classes, but are evicted first.
+///
+/// Note: This cache is in-memory only
+///
+/// ```rust
+/// use cached::{Cached, CachedRead, CachedWrite, FairCache};
+///
+/// let mut cache = FairCache::with_size(4);
+/// cache.set_share("metadata", 1);
+/// cache.set_share("render", 3);
+/// cache.cache_set(("render", 1), "a");
+/// for i in 0..4 {
+///     cache.cache_set(("metadata", i), "b");
+/// }
+///
+/// // "metadata" exceeds its target of 1 value, "render" is kept
+/// assert_eq!(cache.cache_get(&("render", 1)), Some(&"a"));
+/// assert_eq!(cache.class_size(&"metadata"), 3);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FairCache<C, K, V> {
+    pub(super) partitions: Partitions<C, K, V>,
+    pub(super) capacity: usize,
+    pub(super) shares: HashMap<C, u32>,
+    pub(super) total_shares: u64,
+}
+
+impl<C, K, V> FairCache<C, K, V>
+where
+    C: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    /// Creates a new `FairCache` with a given size limit, shared by all classes
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0, see `try_with_size`
+    #[must_use]
+    pub fn with_size(size: usize) -> FairCache<C, K, V> {
+        Self::try_with_size(size).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Creates a new `FairCache` with a given size limit, shared by all classes
+    ///
+    /// # Errors
+    ///
+    /// Will return a `CacheBuildError` if size is 0
+    pub fn try_with_size(size: usize) -> Result<FairCache<C, K, V>, CacheBuildError> {
+        if size == 0 {
+            return Err(CacheBuildError::zero("FairCache", "size"));
+        }
+        Ok(FairCache {
+            partitions: Partitions::new(),
+            capacity: size,
+            shares: HashMap::new(),
+            total_shares: 0,
+        })
+    }
+
+    /// Register the share of a class, or replace it. A share of 0 unregisters the class.
+    ///
+    /// Values are only evicted when the cache is full, so classes exceeding their new
+    /// target keep their values until then.
+    pub fn set_share(&mut self, class: C, share: u32) {
+        let previous = if share == 0 {
+            self.shares.remove(&class)
+        } else {
+            self.shares.insert(class, share)
+        };
+        self.total_shares = self.total_shares - u64::from(previous.unwrap_or(0)) + u64::from(share);
+    }
+
+    /// Return the registered share of a class, 0 if it is not registered
+    #[must_use]
+    pub fn share(&self, class: &C) -> u32 {
+        self.shares.get(class).copied().unwrap_or(0)
+    }
+
+    /// Return the number of values a class can hold before being preferred for eviction
+    #[must_use]
+    pub fn class_target(&self, class: &C) -> usize {
+        if self.total_shares == 0 {
+            return 0;
+        }
+        // at most the capacity, since the share is part of the total
+        (self.capacity as u128 * u128::from(self.share(class)) / u128::from(self.total_shares))
+            as usize
+    }
+
+    /// Return the number of values of a class
+    #[must_use]
+    pub fn class_size(&self, class: &C) -> usize {
+        self.partitions.partition_size(class)
+    }
+
+    /// Release the memory the cache keeps beyond its values, e.g. after most of them
+    /// were removed
+    pub fn shrink_to_fit(&mut self) {
+        self.partitions.shrink_to_fit();
+    }
+
+    /// Evict the least recently used value of the class exceeding its target the most,
+    /// other than the value just set in `class`
+    fn evict(&mut self, class: &C) {
+        // compare `size / target` through `size * total_shares - capacity * share`,
+        // which is also positive for the classes without a share
+        let capacity = self.capacity as u128;
+        let total_shares = u128::from(self.total_shares);
+        let shares = &self.shares;
+        self.partitions.evict_max_by_key(class, |class, size| {
+            let share = u128::from(shares.get(class).copied().unwrap_or(0));
+            (size as u128 * total_shares.max(1)) as i128 - (capacity * share) as i128
+        });
+    }
+}
+
+impl<C, K, V> Cached<(C, K), V> for FairCache<C, K, V>
+where
+    C: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        (C, K): std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.cache_get_key_value(key).map(|(_, value)| value)
+    }
+
+    fn cache_get_key_value<Q>(&mut self, key: &Q) -> Option<(&(C, K), &V)>
+    where
+        (C, K): std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.partitions.get_key_value(key)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        (C, K): std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.partitions.get_mut(key)
+    }
+
+    fn cache_drain_where<F: FnMut(&(C, K), &V) -> bool>(
+        &mut self,
+        drain: F,
+    ) -> impl Iterator<Item = ((C, K), V)> {
+        self.partitions.drain_where(drain).into_iter()
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: (C, K), f: F) -> &mut V {
+        let found = self.partitions.contains_key(&key);
+        self.partitions.count(found);
+        if !found {
+            self.cache_set(key.clone(), f());
+        }
+        self.partitions
+            .get_uncounted(&key)
+            .expect("FairCache::cache_get_or_set_with value was just set")
+    }
+}
+
+impl<C, K, V> CachedRead<(C, K), V> for FairCache<C, K, V>
+where
+    C: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    fn cache_size(&self) -> usize {
+        self.partitions.len()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.partitions.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.partitions.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        (C, K): std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.partitions.peek(key)
+    }
+
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a (C, K), &'a V)>
+    where
+        (C, K): 'a,
+        V: 'a,
+    {
+        self.partitions.iter()
+    }
+}
+
+impl<C, K, V> CachedWrite<(C, K), V> for FairCache<C, K, V>
+where
+    C: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    fn cache_set(&mut self, key: (C, K), val: V) -> Option<V> {
+        let class = key.0.clone();
+        // classes never evict on their own, the cache picks the victim
+        let old = self.partitions.set(key, val, usize::MAX);
+        if self.partitions.len() > self.capacity {
+            self.evict(&class);
+        }
+        old
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        (C, K): std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.partitions.remove(k)
+    }
+
+    fn cache_clear(&mut self) {
+        self.partitions.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.partitions.reset();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.partitions.reset_metrics();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_over_their_share_are_evicted_first() {
+        let mut c = FairCache::with_size(4);
+        c.set_share("a", 1);
+        c.set_share("b", 1);
+        c.cache_set(("a", 1), 1);
+        c.cache_set(("a", 2), 2);
+        c.cache_set(("b", 1), 10);
+        c.cache_set(("b", 2), 20);
+        c.cache_set(("b", 3), 30);
+
+        assert_eq!(c.class_target(&"a"), 2);
+        assert_eq!(c.class_size(&"a"), 2);
+        assert_eq!(c.class_size(&"b"), 2);
+        assert_eq!(c.cache_get(&("b", 1)), None);
+        assert_eq!(c.cache_get(&("a", 1)), Some(&1));
+
+        // "b" is within its share again, the least recently used value of "a" goes
+        c.cache_set(("a", 3), 3);
+        assert_eq!(c.cache_get(&("a", 2)), None);
+        assert_eq!(c.cache_size(), 4);
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut c = FairCache::with_size(100);
+        c.cache_set(("a", 1), 1);
+        c.cache_set(("b", 1), 2);
+        c.cache_remove(&("b", 1));
+        c.shrink_to_fit();
+        assert_eq!(c.partitions.caches.len(), 1);
+        assert_eq!(c.cache_get(&("a", 1)), Some(&1));
+
+        c.cache_set(("b", 2), 3);
+        assert_eq!(c.class_size(&"b"), 1);
+        assert_eq!(c.cache_size(), 2);
+    }
+
+    #[test]
+    fn classes_without_share_are_evicted_first() {
+        let mut c = FairCache::with_size(2);
+        c.set_share("a", 1);
+        c.cache_set(("z", 1), 1);
+        c.cache_set(("z", 2), 2);
+        c.cache_set(("a", 1), 10);
+
+        assert_eq!(c.cache_get(&("z", 1)), None);
+        assert_eq!(*c.cache_get_or_set_with(("z", 3), || 3), 3);
+        assert_eq!(c.cache_get(&("a", 1)), Some(&10));
+        assert_eq!(c.cache_get(&("z", 2)), None);
+
+        c.set_share("a", 0);
+        assert_eq!(c.class_target(&"a"), 0);
+        assert_eq!(c.cache_remove(&("z", 3)), Some(3));
+        c.cache_clear();
+        assert_eq!(c.cache_size(), 0);
+    }
+}
@@ -0,0 +1,494 @@
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
+use crate::IOCached;
+use directories::BaseDirs;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::{fmt::Display, path::PathBuf, time::SystemTime};
+
+pub struct FileCacheBuilder<K, V> {
+    seconds: Option<u64>,
+    refresh: bool,
+    disk_dir: Option<PathBuf>,
+    cache_name: String,
+    _phantom: PhantomData<(K, V)>,
+}
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FileCacheBuildError {
+    #[error("Unable to create cache directory")]
+    DirectoryError(#[from] std::io::Error),
+}
+
+static FILE_CACHE_PREFIX: &str = "cached_file_cache";
+const FILE_CACHE_VERSION: u64 = 1;
+const FILE_EXTENSION: &str = "json";
+const TEMP_EXTENSION: &str = "tmp";
+
+/// Distinguishes the temporary files written concurrently by the caches of a process
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+impl<K, V> FileCacheBuilder<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    /// Initialize a `FileCacheBuilder`
+    pub fn new<S: AsRef<str>>(cache_name: S) -> FileCacheBuilder<K, V> {
+        Self {
+            seconds: None,
+            refresh: false,
+            disk_dir: None,
+            cache_name: cache_name.as_ref().to_string(),
+            _phantom: Default::default(),
+        }
+    }
+
+    /// Specify the cache TTL/lifespan in seconds
+    pub fn set_lifespan(mut self, seconds: u64) -> Self {
+        self.seconds = Some(seconds);
+        self
+    }
+
+    /// Specify whether cache hits refresh the TTL
+    pub fn set_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Set the disk path for where the directory of the cache will be stored.
+    /// Each cache gets a directory named after it.
+    pub fn set_disk_directory<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.disk_dir = Some(dir.as_ref().into());
+        self
+    }
+
+    fn default_disk_dir() -> PathBuf {
+        BaseDirs::new()
+            .map(|base_dirs| {
+                let exe_name = std::env::current_exe()
+                    .ok()
+                    .and_then(|path| {
+                        path.file_name()
+                            .and_then(|os_str| os_str.to_str().map(|s| format!("{}_", s)))
+                    })
+                    .unwrap_or_default();
+                let dir_prefix = format!("{}{}", exe_name, FILE_CACHE_PREFIX);
+                base_dirs.cache_dir().join(dir_prefix)
+            })
+            .unwrap_or_else(|| {
+                std::env::current_dir().expect("file cache unable to determine current directory")
+            })
+    }
+
+    pub fn build(self) -> Result<FileCache<K, V>, FileCacheBuildError> {
+        let disk_dir = self.disk_dir.unwrap_or_else(|| Self::default_disk_dir());
+        let directory = disk_dir.join(format!("{}_v{}", self.cache_name, FILE_CACHE_VERSION));
+        fs::create_dir_all(&directory)?;
+
+        Ok(FileCache {
+            seconds: self.seconds,
+            refresh: self.refresh,
+            version: FILE_CACHE_VERSION,
+            directory,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            _phantom: self._phantom,
+        })
+    }
+}
+
+/// Cache store keeping each value in a file of a directory tree
+///
+/// Each value is stored as JSON, along with its key, in the file
+/// `{disk_dir}/{cache_name}_v1/{hash:2}/{hash:2}/{hash}.json` named by a hash of the key,
+/// so that the cache can be inspected, copied or synchronized with the usual tools.
+/// Values are written to a temporary file which is then renamed, so that readers never
+/// see a partially written value.
+///
+/// The modification time of a file is the time its value was set, or last refreshed, and
+/// values whose lifespan elapsed since then are never returned. They are removed when read,
+/// or all at once with `remove_expired_entries`.
+///
+/// The hash is 64 bits long: in the unlikely event of two keys sharing a file, the value
+/// of one replaces the value of the other.
+pub struct FileCache<K, V> {
+    pub(super) seconds: Option<u64>,
+    pub(super) refresh: bool,
+    #[allow(unused)]
+    version: u64,
+    directory: PathBuf,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> FileCache<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    #[allow(clippy::new_ret_no_self)]
+    /// Initialize a `FileCacheBuilder`
+    pub fn new(cache_name: &str) -> FileCacheBuilder<K, V> {
+        FileCacheBuilder::new(cache_name)
+    }
+
+    /// Return the directory holding the files of the cache
+    #[must_use]
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Return the path of the file holding the value of `key`
+    #[must_use]
+    pub fn file_path(&self, key: &K) -> PathBuf {
+        let hash = format!("{:016x}", fnv1a(key.to_string().as_bytes()));
+        self.directory
+            .join(&hash[..2])
+            .join(&hash[2..4])
+            .join(format!("{}.{}", hash, FILE_EXTENSION))
+    }
+
+    /// Remove the files of the expired values, returning how many were removed
+    pub fn remove_expired_entries(&self) -> Result<usize, FileCacheError> {
+        let mut removed = 0;
+        for path in value_files(&self.directory)? {
+            if let Some(modified) = modified(&path)? {
+                if self.is_expired(modified) && remove(&path)? {
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Remove the files of all the values
+    pub fn clear(&self) -> Result<(), FileCacheError> {
+        for path in value_files(&self.directory)? {
+            remove(&path)?;
+        }
+        Ok(())
+    }
+
+    fn is_expired(&self, modified: SystemTime) -> bool {
+        self.seconds.is_some_and(|seconds| {
+            SystemTime::now()
+                .duration_since(modified)
+                .is_ok_and(|age| age.as_secs() >= seconds)
+        })
+    }
+
+    /// Read the value of `key` from `path`, returning `None` if there is none, if it has
+    /// expired or if the file holds the value of another key
+    fn read(&self, path: &Path, key: &str) -> Result<Option<V>, FileCacheError> {
+        let modified = match modified(path)? {
+            Some(modified) => modified,
+            None => return Ok(None),
+        };
+        if self.is_expired(modified) {
+            return Ok(None);
+        }
+        let stored = match fs::read_to_string(path) {
+            Ok(stored) => stored,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let cached: CachedFileValue<V> = serde_json::from_str(&stored).map_err(|e| {
+            FileCacheError::CacheDeserializationError {
+                cached_value: stored.clone(),
+                error: e,
+            }
+        })?;
+        Ok((cached.key == key).then_some(cached.value))
+    }
+
+    /// Write the value of `key` to a temporary file, renamed to `path` once complete
+    fn write(&self, path: &Path, key: String, value: &V) -> Result<(), FileCacheError> {
+        let cached = CachedFileValue { key, value };
+        let contents = serde_json::to_vec(&cached)
+            .map_err(|e| FileCacheError::CacheSerializationError { error: e })?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = path.with_extension(format!(
+            "{}.{}.{}",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+            TEMP_EXTENSION
+        ));
+        let res = File::create(&temp_path)
+            .and_then(|mut file| file.write_all(&contents))
+            .and_then(|()| fs::rename(&temp_path, path));
+        if res.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        Ok(res?)
+    }
+}
+
+/// 64-bit FNV-1a hash, stable across platforms and releases unlike the std hashers
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Return the modification time of a file, or `None` if it does not exist
+fn modified(path: &Path) -> io::Result<Option<SystemTime>> {
+    match fs::metadata(path) {
+        Ok(metadata) => metadata.modified().map(Some),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Remove a file, returning whether it existed
+fn remove(path: &Path) -> io::Result<bool> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// List the files of the values in the directory tree of a cache
+fn value_files(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                directories.push(path);
+            } else if path.extension().is_some_and(|ext| ext == FILE_EXTENSION) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[derive(Error, Debug)]
+pub enum FileCacheError {
+    #[error("Storage error")]
+    StorageError(#[from] std::io::Error),
+    #[error("Error deserializing cached value {cached_value:?}: {error:?}")]
+    CacheDeserializationError {
+        cached_value: String,
+        error: serde_json::Error,
+    },
+    #[error("Error serializing cached value: {error:?}")]
+    CacheSerializationError { error: serde_json::Error },
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedFileValue<V> {
+    key: String,
+    value: V,
+}
+
+impl<K, V> IOCached<K, V> for FileCache<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    type Error = FileCacheError;
+
+    fn cache_get(&self, key: &K) -> Result<Option<V>, FileCacheError> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let path = self.file_path(key);
+        let key = key.to_string();
+        let value = self.read(&path, &key)?;
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Backend, started.elapsed());
+
+        match value {
+            Some(_) => {
+                if self.refresh && self.seconds.is_some() {
+                    File::options()
+                        .write(true)
+                        .open(&path)?
+                        .set_modified(SystemTime::now())?;
+                }
+            }
+            None => {
+                // drop the value if it has expired, keeping the value of another key
+                if modified(&path)?.is_some_and(|modified| self.is_expired(modified)) {
+                    remove(&path)?;
+                }
+            }
+        }
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(value.is_some(), started.elapsed());
+        Ok(value)
+    }
+
+    fn cache_set(&self, key: K, value: V) -> Result<Option<V>, FileCacheError> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let path = self.file_path(&key);
+        let key = key.to_string();
+        let old = self.read(&path, &key)?;
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        self.write(&path, key, &value)?;
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record(Operation::Insert, started.elapsed());
+        }
+        Ok(old)
+    }
+
+    fn cache_remove(&self, key: &K) -> Result<Option<V>, FileCacheError> {
+        let path = self.file_path(key);
+        let old = self.read(&path, &key.to_string())?;
+        remove(&path)?;
+        Ok(old)
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.seconds
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let old = self.seconds;
+        self.seconds = Some(seconds);
+        old
+    }
+
+    fn cache_set_refresh(&mut self, refresh: bool) -> bool {
+        let old = self.refresh;
+        self.refresh = refresh;
+        old
+    }
+
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod test_FileCache {
+    use googletest::{
+        assert_that,
+        matchers::{eq, none, ok, some},
+        GoogleTestSupport as _,
+    };
+    use std::thread::sleep;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    macro_rules! temp_dir {
+        () => {
+            TempDir::new().expect("Error creating temp dir")
+        };
+    }
+
+    const TEST_KEY: u32 = 1;
+    const TEST_VAL: u32 = 100;
+    const TEST_VAL_1: u32 = 200;
+    const LIFE_SPAN_1_SEC: u64 = 1;
+
+    #[test]
+    fn cache_get_after_cache_remove_returns_none() {
+        let tmp_dir = temp_dir!();
+        let cache: FileCache<u32, u32> = FileCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_get(&TEST_KEY), ok(none()));
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        assert_that!(
+            cache.cache_set(TEST_KEY, TEST_VAL_1),
+            ok(some(eq(TEST_VAL))),
+            "Setting an existing key-value should return the old value"
+        );
+        assert_that!(cache.cache_remove(&TEST_KEY), ok(some(eq(TEST_VAL_1))));
+        assert_that!(cache.cache_get(&TEST_KEY), ok(none()));
+    }
+
+    #[test]
+    fn values_are_readable_json_files_named_by_the_key_hash() {
+        let tmp_dir = temp_dir!();
+        let cache: FileCache<u32, String> = FileCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_set(TEST_KEY, "a".to_string()), ok(none()));
+        let path = cache.file_path(&TEST_KEY);
+        assert_that!(
+            path.strip_prefix(tmp_dir.path().join("test-cache_v1"))
+                .is_ok(),
+            eq(true)
+        );
+        assert_that!(
+            fs::read_to_string(&path).unwrap(),
+            eq(r#"{"key":"1","value":"a"}"#)
+        );
+        // no temporary file is left behind
+        assert_that!(fs::read_dir(path.parent().unwrap()).unwrap().count(), eq(1));
+
+        // a file holding the value of another key is a miss
+        fs::write(&path, r#"{"key":"2","value":"b"}"#).unwrap();
+        assert_that!(cache.cache_get(&TEST_KEY), ok(none()));
+        assert_that!(path.exists(), eq(true));
+    }
+
+    #[test]
+    fn values_expire_when_lifespan_elapses_since_modification() {
+        let tmp_dir = temp_dir!();
+        let cache: FileCache<u32, u32> = FileCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .set_lifespan(LIFE_SPAN_1_SEC)
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        assert_that!(cache.cache_set(2, TEST_VAL), ok(none()));
+        assert_that!(cache.cache_get(&TEST_KEY), ok(some(eq(TEST_VAL))));
+
+        sleep(Duration::from_secs(LIFE_SPAN_1_SEC));
+        assert_that!(cache.cache_get(&TEST_KEY), ok(none()));
+        assert_that!(cache.file_path(&TEST_KEY).exists(), eq(false));
+        assert_that!(cache.remove_expired_entries(), ok(eq(1)));
+        assert_that!(cache.file_path(&2).exists(), eq(false));
+    }
+
+    #[test]
+    fn cache_hits_refresh_the_modification_time() {
+        let tmp_dir = temp_dir!();
+        let cache: FileCache<u32, u32> = FileCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .set_lifespan(2)
+            .set_refresh(true)
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        sleep(Duration::from_millis(1200));
+        assert_that!(cache.cache_get(&TEST_KEY), ok(some(eq(TEST_VAL))));
+        sleep(Duration::from_millis(1200));
+        assert_that!(cache.cache_get(&TEST_KEY), ok(some(eq(TEST_VAL))));
+
+        cache.clear().unwrap();
+        assert_that!(cache.cache_get(&TEST_KEY), ok(none()));
+    }
+}
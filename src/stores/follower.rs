@@ -0,0 +1,309 @@
+use super::{Cached, CachedRead, SizedCache};
+use crate::events::CacheEvent;
+use std::borrow::Borrow;
+use std::cmp::Eq;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::mpsc::Receiver;
+
+#[cfg(feature = "ahash")]
+use hashbrown::HashMap;
+
+#[cfg(not(feature = "ahash"))]
+use std::collections::HashMap;
+
+/// Read-only replica of a cache, applying the events of its mutation feed
+///
+/// The replicated values are kept in a store of your choice, e.g. a `SizedCache` or a
+/// `TimedSizedCache` bounding the replica by its own size and lifespan, along with the
+/// sequence number of their last event.
+///
+/// Events may be applied out of order and more than once: the sequence number of
+/// the last event applied to each key is kept, and older events are ignored. To
+/// do so, removed keys are remembered until the low-water mark passes their removal,
+/// see `set_low_water`. A value the store itself evicted is forgotten along with its
+/// sequence number, so a stale event of its key arriving afterwards is applied.
+///
+/// ```rust
+/// use cached::{Cached, CachedRead, CachedWrite, FollowerCache, SizedCache};
+///
+/// let mut primary = SizedCache::with_size(10);
+/// let mut follower = FollowerCache::with_size(10);
+/// let events = primary.cache_subscribe().unwrap();
+///
+/// primary.cache_set(1, "a");
+/// primary.cache_set(2, "b");
+/// primary.cache_remove(&1);
+/// assert_eq!(follower.apply_pending(&events), 3);
+///
+/// assert_eq!(follower.cache_get(&1), None);
+/// assert_eq!(follower.cache_get(&2), Some(&"b"));
+/// assert_eq!(follower.cache_size(), 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FollowerCache<K, V, C = SizedCache<K, (u64, V)>> {
+    pub(super) store: C,
+    pub(super) tombstones: HashMap<K, u64>,
+    pub(super) cleared: u64,
+    pub(super) low_water: u64,
+    pub(super) last_seq: u64,
+    _phantom: PhantomData<V>,
+}
+
+impl<K: Hash + Eq + Clone, V> FollowerCache<K, V> {
+    /// Creates a `FollowerCache` replicating up to `size` values, evicting the least
+    /// recently used ones
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0
+    #[must_use]
+    pub fn with_size(size: usize) -> FollowerCache<K, V> {
+        Self::with_store(SizedCache::with_size(size))
+    }
+}
+
+impl<K: Hash + Eq, V, C: Cached<K, (u64, V)>> FollowerCache<K, V, C> {
+    /// Creates a `FollowerCache` keeping the replicated values, along with the sequence
+    /// number of their last event, in `store`
+    pub fn with_store(store: C) -> FollowerCache<K, V, C> {
+        FollowerCache {
+            store,
+            tombstones: HashMap::new(),
+            cleared: 0,
+            low_water: 0,
+            last_seq: 0,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Apply an event, returns `false` if it was ignored because a later event
+    /// was already applied to its key, or the replica was cleared after it, or it
+    /// is below the low-water mark
+    pub fn apply(&mut self, event: CacheEvent<K, V>) -> bool {
+        let seq = event.seq();
+        if seq <= self.cleared.max(self.low_water) {
+            return false;
+        }
+        match event {
+            CacheEvent::Insert { key, value, .. } | CacheEvent::Update { key, value, .. } => {
+                if seq <= self.key_seq(&key) {
+                    return false;
+                }
+                self.tombstones.remove(&key);
+                self.store.cache_set(key, (seq, value));
+            }
+            CacheEvent::Remove { key, .. } => {
+                if seq <= self.key_seq(&key) {
+                    return false;
+                }
+                self.store.cache_remove(&key);
+                self.tombstones.insert(key, seq);
+            }
+            CacheEvent::Clear { seq } => {
+                self.store.cache_retain(|_, (key_seq, _)| *key_seq > seq);
+                self.tombstones.retain(|_, key_seq| *key_seq > seq);
+                self.cleared = seq;
+            }
+        }
+        self.last_seq = self.last_seq.max(seq);
+        true
+    }
+
+    /// Apply the events received so far, without waiting for more.
+    /// Returns the number of events applied.
+    ///
+    /// The events of a channel being received in order, the low-water mark is then
+    /// raised to the last event applied.
+    pub fn apply_pending(&mut self, events: &Receiver<CacheEvent<K, V>>) -> usize {
+        let applied = events
+            .try_iter()
+            .fold(0, |applied, event| applied + usize::from(self.apply(event)));
+        self.set_low_water(self.last_seq);
+        applied
+    }
+
+    /// Declare that every event up to the sequence number `seq` was received: the
+    /// removals remembered up to it are forgotten, and such events are ignored from
+    /// now on. Raise it as the events are delivered, e.g. to the last sequence number
+    /// below which a transport reordering events guarantees delivery.
+    pub fn set_low_water(&mut self, seq: u64) {
+        self.low_water = self.low_water.max(seq);
+        let low_water = self.low_water;
+        self.tombstones.retain(|_, key_seq| *key_seq > low_water);
+    }
+
+    /// Attempt to retrieve a replicated value
+    pub fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.cache_get(key).map(|(_, value)| value)
+    }
+
+    /// Return the sequence number of the last event applied
+    #[must_use]
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Return the low-water mark, see `set_low_water`
+    #[must_use]
+    pub fn low_water(&self) -> u64 {
+        self.low_water
+    }
+
+    /// Return the sequence number of the last event applied to `key`
+    fn key_seq(&self, key: &K) -> u64 {
+        match self.store.cache_peek(key) {
+            Some((seq, _)) => *seq,
+            None => self.tombstones.get(key).copied().unwrap_or(0),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, C: Cached<K, (u64, V)>> CachedRead<K, V> for FollowerCache<K, V, C> {
+    fn cache_size(&self) -> usize {
+        self.store.cache_size()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        self.store.cache_hits()
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        self.store.cache_misses()
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        self.store.cache_capacity()
+    }
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.store.cache_lifespan()
+    }
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.cache_peek(key).map(|(_, value)| value)
+    }
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.store
+            .cache_iter()
+            .map(|(key, (_, value))| (key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(seq: u64, key: u32, value: u32) -> CacheEvent<u32, u32> {
+        CacheEvent::Insert { seq, key, value }
+    }
+
+    #[test]
+    fn duplicate_and_stale_events_are_ignored() {
+        let mut c = FollowerCache::with_size(10);
+        assert!(c.apply(insert(2, 1, 200)));
+        assert!(!c.apply(insert(2, 1, 200)));
+        assert!(!c.apply(insert(1, 1, 100)));
+        assert_eq!(c.cache_get(&1), Some(&200));
+
+        // a removal received before the insertion it follows
+        assert!(c.apply(CacheEvent::Remove { seq: 4, key: 2 }));
+        assert!(!c.apply(insert(3, 2, 300)));
+        assert_eq!(c.cache_get(&2), None);
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(c.last_seq(), 4);
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(1));
+    }
+
+    #[test]
+    fn clear() {
+        let mut c = FollowerCache::with_size(10);
+        c.apply(insert(1, 1, 100));
+        c.apply(insert(3, 2, 200));
+        assert!(c.apply(CacheEvent::Clear { seq: 2 }));
+        assert!(!c.apply(insert(1, 3, 300)));
+
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_get(&2), Some(&200));
+        assert_eq!(c.cache_size(), 1);
+        assert!(c.apply(insert(4, 1, 400)));
+        assert_eq!(c.cache_size(), 2);
+    }
+
+    #[test]
+    fn evictions_are_followed() {
+        use crate::{Cached, CachedWrite, SizedCache, TimedSizedCache};
+
+        let mut primary = SizedCache::with_size(3);
+        let events = primary.cache_subscribe().unwrap();
+        let mut c = FollowerCache::with_size(10);
+        for i in 0..10 {
+            primary.cache_set(i, i * 100);
+        }
+        primary.cache_get_or_set_with(10, || 1000);
+        c.apply_pending(&events);
+
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.cache_get(&7), None);
+        assert_eq!(c.cache_get(&8), Some(&800));
+        assert_eq!(c.cache_get(&10), Some(&1000));
+
+        let mut primary = TimedSizedCache::with_size_and_lifespan(2, 60);
+        let events = primary.cache_subscribe().unwrap();
+        let mut c = FollowerCache::with_size(10);
+        primary.cache_set(1, 100);
+        primary.cache_set(2, 200);
+        primary.cache_get_or_set_with(3, || 300);
+        primary.cache_set(4, 400);
+        c.apply_pending(&events);
+
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.cache_get(&2), None);
+        assert_eq!(c.cache_get(&3), Some(&300));
+    }
+
+    #[test]
+    fn bounded_by_its_store() {
+        use crate::TimedSizedCache;
+
+        let mut c = FollowerCache::with_size(2);
+        for i in 1..=3 {
+            c.apply(insert(i, i as u32, 100));
+        }
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.cache_capacity(), Some(2));
+        assert_eq!(c.cache_get(&1), None);
+
+        let mut c = FollowerCache::with_store(TimedSizedCache::with_size_and_lifespan(10, 60));
+        c.apply(insert(1, 1, 100));
+        assert_eq!(c.cache_lifespan(), Some(60));
+        assert_eq!(c.cache_get(&1), Some(&100));
+    }
+
+    #[test]
+    fn tombstones_below_low_water_are_forgotten() {
+        let mut c = FollowerCache::with_size(10);
+        c.apply(CacheEvent::Remove { seq: 1, key: 1 });
+        c.apply(CacheEvent::Remove { seq: 3, key: 2 });
+        c.set_low_water(2);
+        assert_eq!(c.tombstones.len(), 1);
+        assert!(!c.apply(insert(2, 1, 100)));
+        assert!(!c.apply(insert(2, 2, 200)));
+        assert!(c.apply(insert(4, 1, 400)));
+
+        let (sender, events) = std::sync::mpsc::channel();
+        sender.send(CacheEvent::Remove { seq: 5, key: 3 }).unwrap();
+        assert_eq!(c.apply_pending(&events), 1);
+        assert_eq!(c.low_water(), 5);
+        assert!(c.tombstones.is_empty());
+        assert_eq!(c.cache_get(&1), Some(&400));
+    }
+}
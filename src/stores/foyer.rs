@@ -0,0 +1,193 @@
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
+use crate::IOCachedAsync;
+use async_trait::async_trait;
+use foyer::{HybridCache, StorageKey, StorageValue};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FoyerCacheError {
+    #[error("foyer error")]
+    FoyerError(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Cache store adapting a `foyer` hybrid (memory and disk) cache
+///
+/// `foyer` keeps the most used values in memory and the others on disk, evicting them
+/// by capacity. The cache is built with `foyer`'s own `HybridCacheBuilder`, which sets
+/// the capacities of both tiers, the disk device and the eviction policies:
+///
+/// ```rust,ignore
+/// use cached::proc_macro::io_cached;
+/// use cached::stores::FoyerCache;
+/// use foyer::{DirectFsDeviceOptions, Engine, HybridCacheBuilder};
+///
+/// #[io_cached(
+///     map_error = r##"|e| format!("{e}")"##,
+///     ty = "FoyerCache<u64, String>",
+///     create = r##" {
+///         let hybrid = HybridCacheBuilder::new()
+///             .memory(64 * 1024 * 1024)
+///             .storage(Engine::Large)
+///             .with_device_options(DirectFsDeviceOptions::new("/tmp/foyer").with_capacity(1 << 30))
+///             .build()
+///             .await
+///             .expect("error building foyer cache");
+///         FoyerCache::new(hybrid)
+///     } "##
+/// )]
+/// async fn render(id: u64) -> Result<String, String> {
+///     // ...
+/// }
+/// ```
+///
+/// Values do not expire, and since `foyer` does not return the value replaced by an
+/// insertion or a removal, `cache_set` and `cache_remove` look it up first.
+pub struct FoyerCache<K, V>
+where
+    K: StorageKey,
+    V: StorageValue,
+{
+    hybrid: HybridCache<K, V>,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
+}
+
+impl<K, V> FoyerCache<K, V>
+where
+    K: StorageKey,
+    V: StorageValue,
+{
+    /// Adapt a `foyer` hybrid cache
+    #[must_use]
+    pub fn new(hybrid: HybridCache<K, V>) -> FoyerCache<K, V> {
+        FoyerCache {
+            hybrid,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+        }
+    }
+
+    /// Return a reference to the `foyer` hybrid cache
+    #[must_use]
+    pub fn hybrid(&self) -> &HybridCache<K, V> {
+        &self.hybrid
+    }
+
+    /// Remove all the values from memory and from the disk
+    ///
+    /// # Errors
+    ///
+    /// Will return a `FoyerCacheError` if the disk cannot be written
+    pub async fn clear(&self) -> Result<(), FoyerCacheError> {
+        self.hybrid
+            .clear()
+            .await
+            .map_err(|e| FoyerCacheError::FoyerError(e.into()))
+    }
+
+    /// Close the disk tier, waiting for its pending writes
+    ///
+    /// # Errors
+    ///
+    /// Will return a `FoyerCacheError` if the pending writes fail
+    pub async fn close(&self) -> Result<(), FoyerCacheError> {
+        self.hybrid
+            .close()
+            .await
+            .map_err(|e| FoyerCacheError::FoyerError(e.into()))
+    }
+
+    async fn get(&self, key: &K) -> Result<Option<V>, FoyerCacheError>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let entry = self
+            .hybrid
+            .get(key)
+            .await
+            .map_err(|e| FoyerCacheError::FoyerError(e.into()))?;
+        Ok(entry.map(|entry| entry.value().clone()))
+    }
+}
+
+#[async_trait]
+impl<K, V> IOCachedAsync<K, V> for FoyerCache<K, V>
+where
+    K: StorageKey + Clone,
+    V: StorageValue + Clone,
+{
+    type Error = FoyerCacheError;
+
+    /// Get a cached value, from memory or else from the disk
+    async fn cache_get(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let value = self.get(key).await?;
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(value.is_some(), started.elapsed());
+        Ok(value)
+    }
+
+    /// Set a cached value
+    async fn cache_set(&self, key: K, val: V) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let previous = self.get(&key).await?;
+        self.hybrid.insert(key, val);
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Insert, started.elapsed());
+        Ok(previous)
+    }
+
+    /// Remove a cached value
+    async fn cache_remove(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        let previous = self.get(key).await?;
+        self.hybrid.remove(key);
+        Ok(previous)
+    }
+
+    /// Values do not expire, the flag is ignored
+    fn cache_set_refresh(&mut self, _refresh: bool) -> bool {
+        false
+    }
+
+    /// Return latency histograms of this cache's operations
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use foyer::{DirectFsDeviceOptions, Engine, HybridCacheBuilder};
+
+    #[tokio::test]
+    async fn values_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let hybrid = HybridCacheBuilder::new()
+            .memory(1024)
+            .storage(Engine::Large)
+            .with_device_options(
+                DirectFsDeviceOptions::new(dir.path()).with_capacity(16 * 1024 * 1024),
+            )
+            .build()
+            .await
+            .unwrap();
+        let cache = FoyerCache::new(hybrid);
+
+        assert_eq!(cache.cache_get(&1u64).await.unwrap(), None);
+        assert_eq!(cache.cache_set(1, "a".to_string()).await.unwrap(), None);
+        assert_eq!(
+            cache.cache_set(1, "b".to_string()).await.unwrap(),
+            Some("a".to_string())
+        );
+        assert_eq!(cache.cache_get(&1).await.unwrap(), Some("b".to_string()));
+        assert_eq!(cache.cache_remove(&1).await.unwrap(), Some("b".to_string()));
+        assert_eq!(cache.cache_get(&1).await.unwrap(), None);
+        cache.close().await.unwrap();
+    }
+}
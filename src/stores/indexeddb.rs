@@ -0,0 +1,408 @@
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
+use crate::IOCachedAsync;
+use async_trait::async_trait;
+use js_sys::Promise;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Display;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use thiserror::Error;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    DomException, IdbDatabase, IdbFactory, IdbObjectStore, IdbRequest, IdbTransactionMode,
+};
+
+const DEFAULT_DATABASE_PREFIX: &str = "cached_indexeddb_";
+const DATABASE_VERSION: u32 = 1;
+const OBJECT_STORE: &str = "values";
+
+pub struct IndexedDbCacheBuilder<K, V> {
+    seconds: Option<u64>,
+    refresh: bool,
+    database_name: String,
+    _phantom: PhantomData<(K, V)>,
+}
+
+#[derive(Error, Debug)]
+pub enum IndexedDbCacheError {
+    #[error("IndexedDB is not available in this context")]
+    IndexedDbUnavailable,
+    #[error("IndexedDB error: {0}")]
+    IndexedDbError(String),
+    #[error("Error deserializing cached value {cached_value:?}: {error:?}")]
+    CacheDeserializationError {
+        cached_value: String,
+        error: serde_json::Error,
+    },
+    #[error("Error serializing cached value: {error:?}")]
+    CacheSerializationError { error: serde_json::Error },
+}
+
+impl From<JsValue> for IndexedDbCacheError {
+    fn from(error: JsValue) -> Self {
+        let message = match error.dyn_ref::<DomException>() {
+            Some(exception) => format!("{}: {}", exception.name(), exception.message()),
+            None => error.as_string().unwrap_or_else(|| format!("{error:?}")),
+        };
+        Self::IndexedDbError(message)
+    }
+}
+
+impl<K, V> IndexedDbCacheBuilder<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Initialize an `IndexedDbCacheBuilder`
+    pub fn new<S: AsRef<str>>(cache_name: S) -> IndexedDbCacheBuilder<K, V> {
+        Self {
+            seconds: None,
+            refresh: false,
+            database_name: format!("{}{}", DEFAULT_DATABASE_PREFIX, cache_name.as_ref()),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Specify the cache TTL/lifespan in seconds
+    #[must_use]
+    pub fn set_lifespan(mut self, seconds: u64) -> Self {
+        self.seconds = Some(seconds);
+        self
+    }
+
+    /// Specify whether cache hits refresh the TTL
+    #[must_use]
+    pub fn set_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Set the name of the IndexedDB database holding the cached values.
+    /// Defaults to `cached_indexeddb_{cache_name}`.
+    #[must_use]
+    pub fn set_database_name<S: AsRef<str>>(mut self, database_name: S) -> Self {
+        self.database_name = database_name.as_ref().to_string();
+        self
+    }
+
+    /// The last step in building an `IndexedDbCache` is to call `build()`,
+    /// which creates the database if it does not exist yet
+    ///
+    /// # Errors
+    ///
+    /// Will return an `IndexedDbCacheError::IndexedDbUnavailable` if the page or worker
+    /// has no access to IndexedDB, or an `IndexedDbCacheError::IndexedDbError` if the
+    /// database cannot be opened
+    pub async fn build(self) -> Result<IndexedDbCache<K, V>, IndexedDbCacheError> {
+        let cache = IndexedDbCache {
+            seconds: self.seconds,
+            refresh: self.refresh,
+            database_name: self.database_name,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            _phantom: PhantomData,
+        };
+        SingleThreaded(async { cache.open().await.map(|db| db.close()) }).await?;
+        Ok(cache)
+    }
+}
+
+/// Cache store backed by the IndexedDB of the browser, for `wasm32-unknown-unknown` targets
+///
+/// Each cache is an IndexedDB database of the origin, named after the cache, whose values
+/// persist across sessions. Values are stored as JSON along with their expiry time, and
+/// expired values are deleted when they are read.
+///
+/// IndexedDB handles cannot be shared between threads, so the cache only keeps the name
+/// of its database and opens it for each operation. This lets the cache be stored in a
+/// `static`, as done by `#[io_cached]`:
+///
+/// ```rust,ignore
+/// use cached::proc_macro::io_cached;
+/// use cached::IndexedDbCache;
+///
+/// #[io_cached(
+///     map_error = r##"|e| format!("{e}")"##,
+///     ty = "IndexedDbCache<String, String>",
+///     create = r##" { IndexedDbCache::new("fetch").set_lifespan(3600).build().await.expect("error opening the cache") } "##
+/// )]
+/// async fn fetch(url: String) -> Result<String, String> {
+///     // ...
+/// }
+/// ```
+pub struct IndexedDbCache<K, V> {
+    pub(super) seconds: Option<u64>,
+    pub(super) refresh: bool,
+    database_name: String,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
+    _phantom: PhantomData<(K, V)>,
+}
+
+/// Begin a read-write transaction on the object store of the values
+fn values(db: &IdbDatabase) -> Result<IdbObjectStore, IndexedDbCacheError> {
+    let transaction =
+        db.transaction_with_str_and_mode(OBJECT_STORE, IdbTransactionMode::Readwrite)?;
+    Ok(transaction.object_store(OBJECT_STORE)?)
+}
+
+/// Wait for the completion of an IndexedDB request, returning its result
+async fn request(req: &IdbRequest) -> Result<JsValue, IndexedDbCacheError> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        req.set_onsuccess(Some(&resolve));
+        req.set_onerror(Some(&reject));
+    });
+    let res = JsFuture::from(promise).await;
+    req.set_onsuccess(None);
+    req.set_onerror(None);
+    match res {
+        Ok(_) => Ok(req.result()?),
+        Err(e) => Err(match req.error() {
+            Ok(Some(exception)) => JsValue::from(exception).into(),
+            _ => e.into(),
+        }),
+    }
+}
+
+/// Run an operation on the values of the cache, closing the database once it is done
+macro_rules! with_values {
+    ($cache:expr, |$store:ident| $op:expr) => {
+        SingleThreaded(async {
+            let db = $cache.open().await?;
+            let res = match values(&db) {
+                Ok($store) => $op.await,
+                Err(e) => Err(e),
+            };
+            db.close();
+            res
+        })
+    };
+}
+
+/// Future holding JS values, which are neither `Send` nor `Sync`
+///
+/// `IOCachedAsync` requires its futures to be `Send`. The JS values of a wasm32 module
+/// without threads are only ever used by the thread of its page or worker.
+struct SingleThreaded<F>(F);
+
+// SAFETY: wasm32 modules built without the `atomics` target feature run on a single thread
+unsafe impl<F> Send for SingleThreaded<F> {}
+
+impl<F: Future> Future for SingleThreaded<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: the inner future is pinned along with its wrapper and never moved
+        unsafe { self.map_unchecked_mut(|f| &mut f.0) }.poll(cx)
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedIndexedDbValue<V> {
+    pub(crate) value: V,
+    pub(crate) expires_at: Option<f64>,
+    pub(crate) version: Option<u64>,
+}
+
+impl<V> CachedIndexedDbValue<V> {
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= js_sys::Date::now())
+    }
+}
+
+impl<K, V> IndexedDbCache<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    #[allow(clippy::new_ret_no_self)]
+    /// Initialize an `IndexedDbCacheBuilder`
+    pub fn new<S: AsRef<str>>(cache_name: S) -> IndexedDbCacheBuilder<K, V> {
+        IndexedDbCacheBuilder::new(cache_name)
+    }
+
+    /// Return the name of the IndexedDB database holding the cached values
+    #[must_use]
+    pub fn database_name(&self) -> &str {
+        &self.database_name
+    }
+
+    /// Remove all the cached values
+    ///
+    /// # Errors
+    ///
+    /// Will return an `IndexedDbCacheError::IndexedDbError` if the database cannot be written
+    pub async fn clear(&self) -> Result<(), IndexedDbCacheError> {
+        with_values!(self, |store| async {
+            request(&store.clear()?).await.map(drop)
+        })
+        .await
+    }
+
+    /// Open the database, creating its object store on the first open
+    async fn open(&self) -> Result<IdbDatabase, IndexedDbCacheError> {
+        let factory = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))?;
+        if factory.is_undefined() || factory.is_null() {
+            return Err(IndexedDbCacheError::IndexedDbUnavailable);
+        }
+        let req = factory
+            .unchecked_into::<IdbFactory>()
+            .open_with_u32(&self.database_name, DATABASE_VERSION)?;
+        let upgrading = req.clone();
+        let on_upgrade = Closure::<dyn FnMut()>::new(move || {
+            if let Ok(db) = upgrading.result() {
+                let db = db.unchecked_into::<IdbDatabase>();
+                if !db.object_store_names().contains(OBJECT_STORE) {
+                    // a failure surfaces as the error of the open request
+                    let _ = db.create_object_store(OBJECT_STORE);
+                }
+            }
+        });
+        req.set_onupgradeneeded(Some(on_upgrade.as_ref().unchecked_ref()));
+        let res = request(&req).await;
+        req.set_onupgradeneeded(None);
+        Ok(res?.unchecked_into())
+    }
+
+    fn new_expiry(&self) -> Option<f64> {
+        self.seconds
+            .map(|seconds| js_sys::Date::now() + (seconds as f64) * 1000.0)
+    }
+
+    fn encode(&self, val: &V) -> Result<JsValue, IndexedDbCacheError> {
+        let cached = CachedIndexedDbValue {
+            value: val,
+            expires_at: self.new_expiry(),
+            version: Some(1),
+        };
+        serde_json::to_string(&cached)
+            .map(JsValue::from)
+            .map_err(|e| IndexedDbCacheError::CacheSerializationError { error: e })
+    }
+
+    /// Read the value of `key`, expired or not
+    async fn read(
+        store: &IdbObjectStore,
+        key: &JsValue,
+    ) -> Result<Option<CachedIndexedDbValue<V>>, IndexedDbCacheError> {
+        let stored = match request(&store.get(key)?).await?.as_string() {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        let cached: CachedIndexedDbValue<V> = serde_json::from_str(&stored).map_err(|e| {
+            IndexedDbCacheError::CacheDeserializationError {
+                cached_value: stored.clone(),
+                error: e,
+            }
+        })?;
+        Ok(Some(cached))
+    }
+
+    async fn get(&self, store: &IdbObjectStore, key: &K) -> Result<Option<V>, IndexedDbCacheError> {
+        let key = JsValue::from_str(&key.to_string());
+        match Self::read(store, &key).await? {
+            Some(cached) if cached.is_expired() => {
+                request(&store.delete(&key)?).await?;
+                Ok(None)
+            }
+            Some(cached) => {
+                if self.refresh {
+                    request(&store.put_with_key(&self.encode(&cached.value)?, &key)?).await?;
+                }
+                Ok(Some(cached.value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set(
+        &self,
+        store: &IdbObjectStore,
+        key: K,
+        val: V,
+    ) -> Result<Option<V>, IndexedDbCacheError> {
+        let key = JsValue::from_str(&key.to_string());
+        let previous = Self::read(store, &key).await?;
+        request(&store.put_with_key(&self.encode(&val)?, &key)?).await?;
+        Ok(previous
+            .filter(|cached| !cached.is_expired())
+            .map(|cached| cached.value))
+    }
+
+    async fn remove(
+        &self,
+        store: &IdbObjectStore,
+        key: &K,
+    ) -> Result<Option<V>, IndexedDbCacheError> {
+        let key = JsValue::from_str(&key.to_string());
+        let previous = Self::read(store, &key).await?;
+        request(&store.delete(&key)?).await?;
+        Ok(previous
+            .filter(|cached| !cached.is_expired())
+            .map(|cached| cached.value))
+    }
+}
+
+#[async_trait]
+impl<K, V> IOCachedAsync<K, V> for IndexedDbCache<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    type Error = IndexedDbCacheError;
+
+    /// Get a cached value
+    async fn cache_get(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let value = with_values!(self, |store| self.get(&store, key)).await?;
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(value.is_some(), started.elapsed());
+        Ok(value)
+    }
+
+    /// Set a cached value
+    async fn cache_set(&self, key: K, val: V) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let previous = with_values!(self, |store| self.set(&store, key, val)).await?;
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Insert, started.elapsed());
+        Ok(previous)
+    }
+
+    /// Remove a cached value
+    async fn cache_remove(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        with_values!(self, |store| self.remove(&store, key)).await
+    }
+
+    /// Set the flag to control whether cache hits refresh the ttl of cached values, returns the old flag value
+    fn cache_set_refresh(&mut self, refresh: bool) -> bool {
+        let old = self.refresh;
+        self.refresh = refresh;
+        old
+    }
+
+    /// Return the lifespan of cached values (time to eviction)
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.seconds
+    }
+
+    /// Set the lifespan of cached values, returns the old value
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.seconds.replace(seconds)
+    }
+
+    /// Return latency histograms of this cache's operations
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+}
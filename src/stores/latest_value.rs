@@ -0,0 +1,415 @@
+use super::{CacheBuildError, Cached, CachedRead, CachedWrite};
+use instant::{Duration, Instant};
+use std::borrow::Borrow;
+use std::cmp::Eq;
+use std::collections::VecDeque;
+use std::hash::Hash;
+
+#[cfg(feature = "ahash")]
+use hashbrown::{hash_map::Entry, HashMap};
+
+#[cfg(not(feature = "ahash"))]
+use std::collections::{hash_map::Entry, HashMap};
+
+/// Latest Value / Time-bucketed Cache
+///
+/// Stores the latest value of each key for last-write-wins data, e.g. telemetry:
+/// setting a value always overwrites the previous one and stamps it with the time
+/// it was set. Values older than the `horizon` are never returned, and
+/// `cache_get_max_age` only returns values younger than a given age.
+///
+/// Keys are grouped in buckets by the time their values were set, each bucket
+/// spanning `bucket_width`. Once all the values of the oldest bucket are older than
+/// the horizon, it is dropped along with them, in time proportional to its size,
+/// without keeping any LRU order. Buckets are dropped when values are set in a new
+/// bucket, or when calling `compact`.
+///
+/// Note: This cache is in-memory only
+///
+/// ```rust
+/// use cached::{Cached, CachedWrite, LatestValueCache};
+/// use std::time::Duration;
+///
+/// let mut cache = LatestValueCache::with_horizon(Duration::from_secs(1), Duration::from_secs(60));
+/// cache.cache_set("cpu", 0.5);
+/// cache.cache_set("cpu", 0.7);
+///
+/// assert_eq!(cache.cache_get(&"cpu"), Some(&0.7));
+/// assert_eq!(cache.cache_get_max_age(&"cpu", Duration::from_secs(5)), Some(&0.7));
+/// ```
+#[derive(Clone, Debug)]
+pub struct LatestValueCache<K, V> {
+    pub(super) store: HashMap<K, Stamped<V>>,
+    // the keys set during each bucket, from oldest to newest, identified by
+    // their start. Keys set again in a newer bucket are stale in the older ones.
+    pub(super) buckets: VecDeque<(Instant, Vec<K>)>,
+    pub(super) bucket_width: Duration,
+    pub(super) horizon: Duration,
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+}
+
+/// A value, when it was set, and the start of its bucket
+#[derive(Clone, Debug)]
+pub(super) struct Stamped<V> {
+    set_at: Instant,
+    bucket: Instant,
+    value: V,
+}
+
+impl<K: Hash + Eq + Clone, V> LatestValueCache<K, V> {
+    /// Creates a new `LatestValueCache` grouping values in buckets of `bucket_width`,
+    /// and dropping them once older than `horizon`
+    ///
+    /// # Panics
+    ///
+    /// Will panic if bucket_width is zero, see `try_with_horizon`
+    #[must_use]
+    pub fn with_horizon(bucket_width: Duration, horizon: Duration) -> LatestValueCache<K, V> {
+        Self::try_with_horizon(bucket_width, horizon).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Creates a new `LatestValueCache` grouping values in buckets of `bucket_width`,
+    /// and dropping them once older than `horizon`
+    ///
+    /// # Errors
+    ///
+    /// Will return a `CacheBuildError` if bucket_width is zero
+    pub fn try_with_horizon(
+        bucket_width: Duration,
+        horizon: Duration,
+    ) -> Result<LatestValueCache<K, V>, CacheBuildError> {
+        if bucket_width.is_zero() {
+            return Err(CacheBuildError::zero("LatestValueCache", "bucket_width"));
+        }
+        Ok(LatestValueCache {
+            store: HashMap::new(),
+            buckets: VecDeque::new(),
+            bucket_width,
+            horizon,
+            hits: 0,
+            misses: 0,
+        })
+    }
+
+    /// Return the age after which values are dropped
+    #[must_use]
+    pub fn horizon(&self) -> Duration {
+        self.horizon
+    }
+
+    /// Return the number of buckets
+    #[must_use]
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Return when the value of `key` was set, if it is younger than the horizon
+    pub fn set_at<Q>(&self, key: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .get(key)
+            .filter(|stamped| stamped.set_at.elapsed() < self.horizon)
+            .map(|stamped| stamped.set_at)
+    }
+
+    /// Attempt to retrieve the value of `key` if it is younger than `max_age`
+    pub fn cache_get_max_age<Q>(&mut self, key: &Q, max_age: Duration) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let max_age = max_age.min(self.horizon);
+        match self.store.get(key) {
+            Some(stamped) if stamped.set_at.elapsed() < max_age => {
+                self.hits += 1;
+                Some(&stamped.value)
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Drop the buckets whose values are all older than the horizon, returning
+    /// the number of values dropped
+    pub fn compact(&mut self) -> usize {
+        let mut dropped = 0;
+        while let Some((start, _)) = self.buckets.front() {
+            if start.elapsed() < self.bucket_width + self.horizon {
+                break;
+            }
+            let (start, keys) = self.buckets.pop_front().unwrap();
+            for key in keys {
+                if let Entry::Occupied(entry) = self.store.entry(key) {
+                    if entry.get().bucket == start {
+                        entry.remove();
+                        dropped += 1;
+                    }
+                }
+            }
+        }
+        dropped
+    }
+
+    /// Release the memory the cache keeps beyond its values and buckets, e.g. after
+    /// most of them were compacted
+    pub fn shrink_to_fit(&mut self) {
+        self.store.shrink_to_fit();
+        self.buckets.shrink_to_fit();
+    }
+
+    /// Return the start of the bucket of values set now, starting a new bucket
+    /// and compacting the older ones once the current bucket is over
+    fn current_bucket(&mut self, now: Instant) -> Instant {
+        match self.buckets.back() {
+            Some((start, _)) if now.duration_since(*start) < self.bucket_width => *start,
+            _ => {
+                self.buckets.push_back((now, Vec::new()));
+                self.compact();
+                now
+            }
+        }
+    }
+
+    fn stamp(&mut self, key: &K, value: V) -> Stamped<V> {
+        let now = Instant::now();
+        let bucket = self.current_bucket(now);
+        let in_bucket = self
+            .store
+            .get(key)
+            .is_some_and(|stamped| stamped.bucket == bucket);
+        if !in_bucket {
+            if let Some((_, keys)) = self.buckets.back_mut() {
+                keys.push(key.clone());
+            }
+        }
+        Stamped {
+            set_at: now,
+            bucket,
+            value,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cached<K, V> for LatestValueCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let horizon = self.horizon;
+        self.cache_get_max_age(key, horizon)
+    }
+
+    fn cache_get_key_value<Q>(&mut self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.get_key_value(key) {
+            Some((key, stamped)) if stamped.set_at.elapsed() < self.horizon => {
+                self.hits += 1;
+                Some((key, &stamped.value))
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.get_mut(key) {
+            Some(stamped) if stamped.set_at.elapsed() < self.horizon => {
+                self.hits += 1;
+                Some(&mut stamped.value)
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_drain_where<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        mut drain: F,
+    ) -> impl Iterator<Item = (K, V)> {
+        let mut drained = Vec::new();
+        let mut kept = Vec::new();
+        for (key, stamped) in self.store.drain() {
+            if stamped.set_at.elapsed() >= self.horizon {
+                continue;
+            }
+            if drain(&key, &stamped.value) {
+                drained.push((key, stamped.value));
+            } else {
+                kept.push((key, stamped));
+            }
+        }
+        self.store.extend(kept);
+        drained.into_iter()
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let fresh = self
+            .store
+            .get(&key)
+            .is_some_and(|stamped| stamped.set_at.elapsed() < self.horizon);
+        if fresh {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            let stamped = self.stamp(&key, f());
+            self.store.insert(key.clone(), stamped);
+        }
+        &mut self.store.get_mut(&key).unwrap().value
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> CachedRead<K, V> for LatestValueCache<K, V> {
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        Some(self.horizon.as_secs())
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .get(key)
+            .filter(|stamped| stamped.set_at.elapsed() < self.horizon)
+            .map(|stamped| &stamped.value)
+    }
+
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let horizon = self.horizon;
+        self.store
+            .iter()
+            .filter(move |(_, stamped)| stamped.set_at.elapsed() < horizon)
+            .map(|(key, stamped)| (key, &stamped.value))
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> CachedWrite<K, V> for LatestValueCache<K, V> {
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        let stamped = self.stamp(&key, val);
+        let horizon = self.horizon;
+        self.store
+            .insert(key, stamped)
+            .filter(|old| old.set_at.elapsed() < horizon)
+            .map(|old| old.value)
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .remove(key)
+            .filter(|old| old.set_at.elapsed() < self.horizon)
+            .map(|old| old.value)
+    }
+
+    fn cache_clear(&mut self) {
+        self.store.clear();
+        self.buckets.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.store = HashMap::new();
+        self.buckets = VecDeque::new();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let old = self.horizon.as_secs();
+        self.horizon = Duration::from_secs(seconds);
+        Some(old)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latest_value_wins() {
+        let mut c = LatestValueCache::with_horizon(Duration::from_secs(1), Duration::from_secs(60));
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(1, 200), Some(100));
+        assert_eq!(c.cache_get(&1), Some(&200));
+        assert!(c.set_at(&1).is_some());
+        assert_eq!(c.cache_get_max_age(&1, Duration::ZERO), None);
+        assert_eq!(*c.cache_get_or_set_with(1, || 300), 200);
+        assert_eq!(*c.cache_get_or_set_with(2, || 300), 300);
+        assert_eq!((c.cache_hits(), c.cache_misses()), (Some(2), Some(2)));
+        // a key set twice in the same bucket is listed once
+        assert_eq!(c.buckets.back().unwrap().1, [1, 2]);
+    }
+
+    #[test]
+    fn try_new() {
+        let c: Result<LatestValueCache<i32, i32>, _> =
+            LatestValueCache::try_with_horizon(Duration::ZERO, Duration::from_secs(1));
+        assert_eq!(
+            c.unwrap_err(),
+            CacheBuildError::zero("LatestValueCache", "bucket_width")
+        );
+    }
+
+    #[test]
+    fn old_buckets_are_compacted() {
+        let mut c =
+            LatestValueCache::with_horizon(Duration::from_millis(20), Duration::from_millis(30));
+        c.cache_set(1, 100);
+        c.cache_set(2, 100);
+        std::thread::sleep(Duration::from_millis(30));
+        c.cache_set(2, 200);
+        assert_eq!(c.bucket_count(), 2);
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_size(), 2);
+
+        std::thread::sleep(Duration::from_millis(25));
+        // the first bucket is over the horizon, the value of 2 moved to the second
+        assert_eq!(c.compact(), 1);
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(c.bucket_count(), 1);
+
+        std::thread::sleep(Duration::from_millis(30));
+        c.cache_set(3, 300);
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(c.bucket_count(), 1);
+    }
+}
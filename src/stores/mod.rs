@@ -1,35 +1,145 @@
-use crate::Cached;
+use crate::{Cached, CachedRead, CachedShared, CachedWrite};
 use std::cmp::Eq;
 #[cfg(feature = "async")]
 use std::collections::hash_map::Entry;
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
-use std::hash::Hash;
+#[cfg(feature = "async")]
+use std::convert::Infallible;
+use std::hash::{BuildHasher, Hash, Hasher};
 
 #[cfg(feature = "async")]
 use {super::CachedAsync, async_trait::async_trait, futures::Future};
 
+mod backoff;
+mod build_error;
+mod cache_stats;
+mod canonical;
 #[cfg(feature = "disk_store")]
 mod disk;
+#[cfg(feature = "dynamodb_store")]
+mod dynamodb;
 mod expiring_value_cache;
+mod fair;
+#[cfg(feature = "file_store")]
+mod file;
+mod follower;
+#[cfg(feature = "foyer")]
+mod foyer;
+#[cfg(feature = "file_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "file_store")))]
+pub use crate::stores::file::{FileCache, FileCacheBuildError, FileCacheBuilder, FileCacheError};
+#[cfg(feature = "foyer")]
+#[cfg_attr(docsrs, doc(cfg(feature = "foyer")))]
+pub use crate::stores::foyer::{FoyerCache, FoyerCacheError};
+#[cfg(all(
+    feature = "indexeddb_store",
+    target_arch = "wasm32",
+    not(target_feature = "atomics")
+))]
+mod indexeddb;
+mod latest_value;
+#[cfg(feature = "mongodb")]
+mod mongodb;
+mod namespaced;
+#[cfg(feature = "object_store")]
+mod object_store;
+mod partitioned;
 #[cfg(feature = "redis_store")]
 mod redis;
+#[cfg(feature = "rocksdb_store")]
+mod rocksdb;
+mod scored;
+#[cfg(feature = "scylla_store")]
+mod scylla;
 mod sized;
+#[cfg(feature = "disk_store")]
+mod spillover;
+#[cfg(feature = "sqlite_store")]
+mod sqlite;
 mod timed;
 mod timed_sized;
 mod unbound;
+#[cfg(all(feature = "webstorage_store", target_arch = "wasm32"))]
+mod webstorage;
+mod weighted;
 
+#[cfg(feature = "disk_encryption")]
+#[cfg_attr(docsrs, doc(cfg(feature = "disk_encryption")))]
+pub use crate::stores::disk::{Cipher, Encryption};
 #[cfg(feature = "disk_store")]
-pub use crate::stores::disk::{DiskCache, DiskCacheBuildError, DiskCacheBuilder, DiskCacheError};
+pub use crate::stores::disk::{
+    Compression, DiskCache, DiskCacheBuildError, DiskCacheBuilder, DiskCacheError, DiskSerializer,
+    MessagePackDiskSerializer,
+};
+#[cfg(feature = "dynamodb_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "dynamodb_store")))]
+pub use crate::stores::dynamodb::{
+    AsyncDynamoCache, AsyncDynamoCacheBuilder, DynamoCacheBuildError, DynamoCacheError,
+};
+#[cfg(all(
+    feature = "indexeddb_store",
+    target_arch = "wasm32",
+    not(target_feature = "atomics")
+))]
+#[cfg_attr(docsrs, doc(cfg(feature = "indexeddb_store")))]
+pub use crate::stores::indexeddb::{IndexedDbCache, IndexedDbCacheBuilder, IndexedDbCacheError};
+#[cfg(feature = "mongodb")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mongodb")))]
+pub use crate::stores::mongodb::{
+    MongoCache, MongoCacheBuildError, MongoCacheBuilder, MongoCacheError,
+};
+#[cfg(feature = "object_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "object_store")))]
+pub use crate::stores::object_store::{
+    ObjectStoreCache, ObjectStoreCacheBuildError, ObjectStoreCacheBuilder, ObjectStoreCacheError,
+};
+#[cfg(feature = "redis_msgpack")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis_msgpack")))]
+pub use crate::stores::redis::MessagePackSerializer;
 #[cfg(feature = "redis_store")]
 #[cfg_attr(docsrs, doc(cfg(feature = "redis_store")))]
 pub use crate::stores::redis::{
-    RedisCache, RedisCacheBuildError, RedisCacheBuilder, RedisCacheError,
+    Invalidation, JsonSerializer, RedisCache, RedisCacheBuildError, RedisCacheBuilder,
+    RedisCacheError, RedisInvalidations, RedisSerializer,
 };
+#[cfg(feature = "rocksdb_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rocksdb_store")))]
+pub use crate::stores::rocksdb::{
+    RocksDbCache, RocksDbCacheBuildError, RocksDbCacheBuilder, RocksDbCacheError,
+};
+#[cfg(feature = "scylla_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "scylla_store")))]
+pub use crate::stores::scylla::{
+    ScyllaCache, ScyllaCacheBuildError, ScyllaCacheBuilder, ScyllaCacheError,
+};
+#[cfg(feature = "disk_store")]
+pub use crate::stores::spillover::SpilloverCache;
+#[cfg(feature = "sqlite_store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sqlite_store")))]
+pub use crate::stores::sqlite::{
+    SqliteCache, SqliteCacheBuildError, SqliteCacheBuilder, SqliteCacheError,
+};
+#[cfg(all(feature = "webstorage_store", target_arch = "wasm32"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "webstorage_store")))]
+pub use crate::stores::webstorage::{
+    WebStorage, WebStorageCache, WebStorageCacheBuilder, WebStorageCacheError,
+};
+pub use backoff::{Backoff, BackoffCache, BackoffError};
+pub use build_error::CacheBuildError;
+pub use cache_stats::CacheStats;
+pub use canonical::{CanonicalCache, Canonicalizer};
 pub use expiring_value_cache::{CanExpire, ExpiringValueCache};
+pub use fair::FairCache;
+pub use follower::FollowerCache;
+pub use latest_value::LatestValueCache;
+pub use namespaced::NamespacedCache;
+pub use scored::{EntryMeta, ScoredCache};
 pub use sized::SizedCache;
 pub use timed::TimedCache;
 pub use timed_sized::TimedSizedCache;
 pub use unbound::UnboundCache;
+pub use weighted::{MemSize, WeightedCache};
 
 #[cfg(all(
     feature = "async",
@@ -44,7 +154,13 @@ pub use unbound::UnboundCache;
         any(feature = "redis_async_std", feature = "redis_tokio")
     )))
 )]
-pub use crate::stores::redis::{AsyncRedisCache, AsyncRedisCacheBuilder};
+pub use crate::stores::redis::{AsyncRedisCache, AsyncRedisCacheBuilder, AsyncRedisInvalidations};
+
+/// A random number in `[0, 1)`, from the random keys of a new `RandomState`
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().build_hasher().finish() >> 11;
+    bits as f64 / (1u64 << 53) as f64
+}
 
 impl<K, V, S> Cached<K, V> for HashMap<K, V, S>
 where
@@ -58,6 +174,13 @@ where
     {
         self.get(k)
     }
+    fn cache_get_key_value<Q>(&mut self, k: &Q) -> Option<(&K, &V)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.get_key_value(k)
+    }
     fn cache_get_mut<Q>(&mut self, k: &Q) -> Option<&mut V>
     where
         K: std::borrow::Borrow<Q>,
@@ -65,12 +188,67 @@ where
     {
         self.get_mut(k)
     }
-    fn cache_set(&mut self, k: K, v: V) -> Option<V> {
-        self.insert(k, v)
+    fn cache_drain_where<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        mut drain: F,
+    ) -> impl Iterator<Item = (K, V)> {
+        let mut drained = Vec::new();
+        let mut kept = Vec::new();
+        for (key, value) in self.drain() {
+            if drain(&key, &value) {
+                drained.push((key, value));
+            } else {
+                kept.push((key, value));
+            }
+        }
+        self.extend(kept);
+        drained.into_iter()
+    }
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut keep: F) {
+        self.retain(|key, value| keep(key, value));
     }
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
         self.entry(key).or_insert_with(f)
     }
+}
+
+impl<K, V, S> CachedRead<K, V> for HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: std::hash::BuildHasher + Default,
+{
+    fn cache_size(&self) -> usize {
+        self.len()
+    }
+
+    fn cache_peek<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.get(k)
+    }
+
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.iter()
+    }
+}
+
+impl<K, V, S> CachedWrite<K, V> for HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: std::hash::BuildHasher + Default,
+{
+    fn cache_set(&mut self, k: K, v: V) -> Option<V> {
+        self.insert(k, v)
+    }
+    fn cache_set_many<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        self.extend(entries);
+    }
     fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
     where
         K: std::borrow::Borrow<Q>,
@@ -78,15 +256,17 @@ where
     {
         self.remove(k)
     }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut remove: F) -> Option<usize> {
+        let size = self.len();
+        self.retain(|key, _| !remove(key));
+        Some(size - self.len())
+    }
     fn cache_clear(&mut self) {
         self.clear();
     }
     fn cache_reset(&mut self) {
         *self = HashMap::default();
     }
-    fn cache_size(&self) -> usize {
-        self.len()
-    }
 }
 
 #[cfg(feature = "async")]
@@ -121,6 +301,47 @@ where
 
         Ok(v)
     }
+
+    async fn get_or_set_with_if_valid<F, Fut, P, PFut>(&mut self, k: K, f: F, is_valid: P) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send,
+    {
+        let setter = || async { Ok::<_, Infallible>(f().await) };
+        match self.try_get_or_set_with_if_valid(k, setter, is_valid).await {
+            Ok(v) => v,
+            Err(never) => match never {},
+        }
+    }
+
+    async fn try_get_or_set_with_if_valid<F, Fut, P, PFut, E>(
+        &mut self,
+        k: K,
+        f: F,
+        is_valid: P,
+    ) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send,
+    {
+        let v = match self.entry(k) {
+            Entry::Occupied(mut o) => {
+                if !is_valid(o.get()).await {
+                    o.insert(f().await?);
+                }
+                o.into_mut()
+            }
+            Entry::Vacant(v) => v.insert(f().await?),
+        };
+
+        Ok(v)
+    }
 }
 
 #[cfg(test)]
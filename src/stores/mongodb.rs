@@ -0,0 +1,446 @@
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
+use crate::IOCachedAsync;
+use async_trait::async_trait;
+use mongodb::bson::{self, doc, DateTime, Document};
+use mongodb::options::{
+    FindOneAndReplaceOptions, FindOneAndUpdateOptions, FindOneOptions, IndexOptions, ReturnDocument,
+};
+use mongodb::{Client, Collection, IndexModel};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+pub struct MongoCacheBuilder<K, V> {
+    seconds: u64,
+    refresh: bool,
+    namespace: String,
+    prefix: String,
+    connection_string: Option<String>,
+    client: Option<Client>,
+    database: String,
+    collection: String,
+    create_ttl_index: bool,
+    _phantom: PhantomData<(K, V)>,
+}
+
+const ENV_KEY: &str = "CACHED_MONGODB_URL";
+const DEFAULT_NAMESPACE: &str = "cached-mongodb-store:";
+const DEFAULT_DATABASE: &str = "cached";
+const DEFAULT_COLLECTION: &str = "cached_values";
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MongoCacheBuildError {
+    #[error("mongodb connection error")]
+    Connection(#[from] mongodb::error::Error),
+    #[error("Connection string not specified or invalid in env var {env_key:?}: {error:?}")]
+    MissingConnectionString {
+        env_key: String,
+        error: std::env::VarError,
+    },
+}
+
+impl<K, V> MongoCacheBuilder<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Initialize a `MongoCacheBuilder`
+    pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> MongoCacheBuilder<K, V> {
+        Self {
+            seconds,
+            refresh: false,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            prefix: prefix.as_ref().to_string(),
+            connection_string: None,
+            client: None,
+            database: DEFAULT_DATABASE.to_string(),
+            collection: DEFAULT_COLLECTION.to_string(),
+            create_ttl_index: true,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Specify the cache TTL/lifespan in seconds
+    #[must_use]
+    pub fn set_lifespan(mut self, seconds: u64) -> Self {
+        self.seconds = seconds;
+        self
+    }
+
+    /// Specify whether cache hits refresh the TTL
+    #[must_use]
+    pub fn set_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Set the namespace for cache keys. Defaults to `cached-mongodb-store:`.
+    /// Used to generate keys formatted as: `{namespace}{prefix}{key}`
+    /// Note that no delimiters are implicitly added so you may pass
+    /// an empty string if you want there to be no namespace on keys.
+    #[must_use]
+    pub fn set_namespace<S: AsRef<str>>(mut self, namespace: S) -> Self {
+        self.namespace = namespace.as_ref().to_string();
+        self
+    }
+
+    /// Set the prefix for cache keys.
+    /// Used to generate keys formatted as: `{namespace}{prefix}{key}`
+    /// Note that no delimiters are implicitly added so you may pass
+    /// an empty string if you want there to be no prefix on keys.
+    #[must_use]
+    pub fn set_prefix<S: AsRef<str>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.as_ref().to_string();
+        self
+    }
+
+    /// Set the connection string for mongodb
+    #[must_use]
+    pub fn set_connection_string(mut self, cs: &str) -> Self {
+        self.connection_string = Some(cs.to_string());
+        self
+    }
+
+    /// Use an already configured mongodb client, the connection string is then ignored
+    #[must_use]
+    pub fn set_client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Set the database holding the cache collection. Defaults to `cached`.
+    #[must_use]
+    pub fn set_database<S: AsRef<str>>(mut self, database: S) -> Self {
+        self.database = database.as_ref().to_string();
+        self
+    }
+
+    /// Set the collection holding the cached values. Defaults to `cached_values`.
+    #[must_use]
+    pub fn set_collection<S: AsRef<str>>(mut self, collection: S) -> Self {
+        self.collection = collection.as_ref().to_string();
+        self
+    }
+
+    /// Specify whether `build()` creates the TTL index deleting expired values
+    /// on the `expires_at` field of the collection. Defaults to `true`.
+    /// Disable it when the index is managed separately, or the cache user
+    /// is not allowed to create indexes.
+    #[must_use]
+    pub fn set_create_ttl_index(mut self, create_ttl_index: bool) -> Self {
+        self.create_ttl_index = create_ttl_index;
+        self
+    }
+
+    /// Return the current connection string or load from the env var: `CACHED_MONGODB_URL`
+    ///
+    /// # Errors
+    ///
+    /// Will return `MongoCacheBuildError::MissingConnectionString` if the connection string is not set
+    pub fn connection_string(&self) -> Result<String, MongoCacheBuildError> {
+        match self.connection_string {
+            Some(ref s) => Ok(s.to_string()),
+            None => {
+                std::env::var(ENV_KEY).map_err(|e| MongoCacheBuildError::MissingConnectionString {
+                    env_key: ENV_KEY.to_string(),
+                    error: e,
+                })
+            }
+        }
+    }
+
+    /// The last step in building a `MongoCache` is to call `build()`
+    ///
+    /// # Errors
+    ///
+    /// Will return a `MongoCacheBuildError`, depending on the error
+    pub async fn build(self) -> Result<MongoCache<K, V>, MongoCacheBuildError> {
+        let client = match self.client {
+            Some(ref client) => client.clone(),
+            None => Client::with_uri_str(self.connection_string()?).await?,
+        };
+        let collection = client
+            .database(&self.database)
+            .collection::<Document>(&self.collection);
+        if self.create_ttl_index {
+            // documents expire at the time stored in their `expires_at` field
+            let index = IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .expire_after(Duration::from_secs(0))
+                        .build(),
+                )
+                .build();
+            collection.create_index(index, None).await?;
+        }
+        Ok(MongoCache {
+            seconds: self.seconds,
+            refresh: self.refresh,
+            namespace: self.namespace,
+            prefix: self.prefix,
+            collection,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            _phantom: PhantomData,
+        })
+    }
+}
+
+/// Cache store backed by a mongodb collection
+///
+/// Each value is stored in a document along with its expiry time, which a TTL index
+/// uses to delete expired documents. Mongodb only deletes expired documents every
+/// minute or so, expired documents which have not been deleted yet are never returned.
+pub struct MongoCache<K, V> {
+    pub(super) seconds: u64,
+    pub(super) refresh: bool,
+    pub(super) namespace: String,
+    pub(super) prefix: String,
+    collection: Collection<Document>,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> MongoCache<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    #[allow(clippy::new_ret_no_self)]
+    /// Initialize a `MongoCacheBuilder`
+    pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> MongoCacheBuilder<K, V> {
+        MongoCacheBuilder::new(prefix, seconds)
+    }
+
+    /// Return the collection holding the cached values
+    #[must_use]
+    pub fn collection(&self) -> &Collection<Document> {
+        &self.collection
+    }
+
+    fn generate_key(&self, key: &K) -> String {
+        format!("{}{}{}", self.namespace, self.prefix, key)
+    }
+
+    fn new_expiry(&self) -> DateTime {
+        let lifespan = i64::try_from(self.seconds.saturating_mul(1000)).unwrap_or(i64::MAX);
+        DateTime::from_millis(DateTime::now().timestamp_millis().saturating_add(lifespan))
+    }
+
+    fn document(&self, key: &K, val: V) -> Result<Document, MongoCacheError> {
+        let value = bson::to_bson(&CachedMongoValue {
+            value: val,
+            version: Some(1),
+        })?;
+        Ok(doc! {
+            "_id": self.generate_key(key),
+            "value": value,
+            "expires_at": self.new_expiry(),
+        })
+    }
+
+    /// Decode a stored document, returning `None` if it has expired
+    fn decode(document: Document) -> Result<Option<V>, MongoCacheError> {
+        match document.get_datetime("expires_at") {
+            Ok(expires_at) if *expires_at > DateTime::now() => {}
+            _ => return Ok(None),
+        }
+        let value = document
+            .get("value")
+            .cloned()
+            .ok_or(MongoCacheError::MissingValue)?;
+        let cached: CachedMongoValue<V> = bson::from_bson(value)?;
+        Ok(Some(cached.value))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MongoCacheError {
+    #[error("mongodb error")]
+    MongoError(#[from] mongodb::error::Error),
+    #[error("Cached document has no value")]
+    MissingValue,
+    #[error("Error deserializing cached value")]
+    CacheDeserializationError(#[from] bson::de::Error),
+    #[error("Error serializing cached value")]
+    CacheSerializationError(#[from] bson::ser::Error),
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedMongoValue<V> {
+    value: V,
+    version: Option<u64>,
+}
+
+#[async_trait]
+impl<K, V> IOCachedAsync<K, V> for MongoCache<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    type Error = MongoCacheError;
+
+    /// Get a cached value
+    async fn cache_get(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let filter = doc! {
+            "_id": self.generate_key(key),
+            "expires_at": { "$gt": DateTime::now() },
+        };
+        let document = if self.refresh {
+            self.collection
+                .find_one_and_update(
+                    filter,
+                    doc! { "$set": { "expires_at": self.new_expiry() } },
+                    FindOneAndUpdateOptions::builder()
+                        .return_document(ReturnDocument::After)
+                        .build(),
+                )
+                .await?
+        } else {
+            self.collection
+                .find_one(filter, FindOneOptions::default())
+                .await?
+        };
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Backend, started.elapsed());
+        let value = match document {
+            Some(document) => Self::decode(document)?,
+            None => None,
+        };
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(value.is_some(), started.elapsed());
+        Ok(value)
+    }
+
+    /// Set a cached value
+    async fn cache_set(&self, key: K, val: V) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let document = self.document(&key, val)?;
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let old = self
+            .collection
+            .find_one_and_replace(
+                doc! { "_id": self.generate_key(&key) },
+                document,
+                FindOneAndReplaceOptions::builder()
+                    .upsert(true)
+                    .return_document(ReturnDocument::Before)
+                    .build(),
+            )
+            .await?;
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record(Operation::Insert, started.elapsed());
+        }
+        match old {
+            Some(old) => Self::decode(old),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove a cached value
+    async fn cache_remove(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let old = self
+            .collection
+            .find_one_and_delete(doc! { "_id": self.generate_key(key) }, None)
+            .await?;
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Backend, started.elapsed());
+        match old {
+            Some(old) => Self::decode(old),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the flag to control whether cache hits refresh the ttl of cached values, returns the old flag value
+    fn cache_set_refresh(&mut self, refresh: bool) -> bool {
+        let old = self.refresh;
+        self.refresh = refresh;
+        old
+    }
+
+    /// Return the lifespan of cached values (time to eviction)
+    fn cache_lifespan(&self) -> Option<u64> {
+        Some(self.seconds)
+    }
+
+    /// Set the lifespan of cached values, returns the old value
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let old = self.seconds;
+        self.seconds = seconds;
+        Some(old)
+    }
+
+    /// Return latency histograms of this cache's operations
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the client connects lazily, so documents can be built without a running server
+    async fn cache(seconds: u64) -> MongoCache<u32, Vec<String>> {
+        MongoCache::new("test:", seconds)
+            .set_connection_string("mongodb://127.0.0.1:27017")
+            .set_create_ttl_index(false)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn documents_round_trip() {
+        let cache = cache(60).await;
+        let document = cache.document(&1, vec!["a".to_string()]).unwrap();
+        assert_eq!(
+            document.get_str("_id").unwrap(),
+            "cached-mongodb-store:test:1"
+        );
+        let expires_at = document.get_datetime("expires_at").unwrap();
+        assert!(*expires_at > DateTime::now());
+        assert_eq!(
+            MongoCache::<u32, Vec<String>>::decode(document).unwrap(),
+            Some(vec!["a".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_documents_decode_to_none() {
+        let cache = cache(0).await;
+        let document = cache.document(&1, vec![]).unwrap();
+        assert_eq!(
+            MongoCache::<u32, Vec<String>>::decode(document).unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_connection_string() {
+        std::env::remove_var(ENV_KEY);
+        let res = MongoCache::<u32, u32>::new("test:", 1).build().await;
+        assert!(matches!(
+            res,
+            Err(MongoCacheBuildError::MissingConnectionString { .. })
+        ));
+    }
+}
@@ -0,0 +1,318 @@
+use super::partitioned::Partitions;
+use super::{CacheBuildError, Cached, CachedRead, CachedWrite};
+use std::cmp::Eq;
+use std::hash::Hash;
+
+#[cfg(feature = "ahash")]
+use hashbrown::HashMap;
+
+#[cfg(not(feature = "ahash"))]
+use std::collections::HashMap;
+
+/// Namespaced / `Sized` Cache
+///
+/// Stores values under `(namespace, key)` pairs, e.g. `(tenant, key)`, with a quota
+/// on the number of values of each namespace. When a namespace exceeds its quota,
+/// its own least recently used value is evicted: a namespace filling up cannot evict
+/// the values of the other namespaces.
+///
+/// The values of all namespaces are also bounded by the size of the cache. When it
+/// is full, the least recently used value of the namespace holding the most values
+/// is evicted, so that many namespaces cannot grow the cache without bound.
+///
+/// Note: This cache is in-memory only
+///
+/// ```rust
+/// use cached::{Cached, CachedRead, CachedWrite, NamespacedCache};
+///
+/// let mut cache = NamespacedCache::with_size_and_quota(100, 2);
+/// cache.set_quota("noisy", 1);
+/// cache.cache_set(("quiet", 1), "a");
+/// cache.cache_set(("noisy", 1), "b");
+/// cache.cache_set(("noisy", 2), "c");
+///
+/// assert_eq!(cache.cache_get(&("quiet", 1)), Some(&"a"));
+/// assert_eq!(cache.cache_get(&("noisy", 1)), None);
+/// assert_eq!(cache.namespace_size(&"noisy"), 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct NamespacedCache<N, K, V> {
+    pub(super) partitions: Partitions<N, K, V>,
+    pub(super) capacity: usize,
+    pub(super) default_quota: usize,
+    pub(super) quotas: HashMap<N, usize>,
+}
+
+impl<N, K, V> NamespacedCache<N, K, V>
+where
+    N: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    /// Creates a new `NamespacedCache` holding up to `size` values, each namespace
+    /// holding up to `quota` values
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size or quota is 0, see `try_with_size_and_quota`
+    #[must_use]
+    pub fn with_size_and_quota(size: usize, quota: usize) -> NamespacedCache<N, K, V> {
+        Self::try_with_size_and_quota(size, quota).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Creates a new `NamespacedCache` holding up to `size` values, each namespace
+    /// holding up to `quota` values
+    ///
+    /// # Errors
+    ///
+    /// Will return a `CacheBuildError` if size or quota is 0
+    pub fn try_with_size_and_quota(
+        size: usize,
+        quota: usize,
+    ) -> Result<NamespacedCache<N, K, V>, CacheBuildError> {
+        if size == 0 {
+            return Err(CacheBuildError::zero("NamespacedCache", "size"));
+        }
+        if quota == 0 {
+            return Err(CacheBuildError::zero("NamespacedCache", "quota"));
+        }
+        Ok(NamespacedCache {
+            partitions: Partitions::new(),
+            capacity: size,
+            default_quota: quota,
+            quotas: HashMap::new(),
+        })
+    }
+
+    /// Set the quota of a namespace, evicting its least recently used values
+    /// if it holds more values than the new quota
+    ///
+    /// # Panics
+    ///
+    /// Will panic if quota is 0
+    pub fn set_quota(&mut self, namespace: N, quota: usize) {
+        if quota == 0 {
+            panic!("`quota` of `NamespacedCache` must be greater than zero.");
+        }
+        self.partitions.set_capacity(&namespace, quota);
+        self.quotas.insert(namespace, quota);
+    }
+
+    /// Return the quota of a namespace
+    #[must_use]
+    pub fn quota(&self, namespace: &N) -> usize {
+        self.quotas
+            .get(namespace)
+            .copied()
+            .unwrap_or(self.default_quota)
+    }
+
+    /// Return the number of values of a namespace
+    #[must_use]
+    pub fn namespace_size(&self, namespace: &N) -> usize {
+        self.partitions.partition_size(namespace)
+    }
+
+    /// Release the memory the cache keeps beyond its values, e.g. after most of them
+    /// were removed
+    pub fn shrink_to_fit(&mut self) {
+        self.partitions.shrink_to_fit();
+    }
+}
+
+impl<N, K, V> Cached<(N, K), V> for NamespacedCache<N, K, V>
+where
+    N: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        (N, K): std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.cache_get_key_value(key).map(|(_, value)| value)
+    }
+
+    fn cache_get_key_value<Q>(&mut self, key: &Q) -> Option<(&(N, K), &V)>
+    where
+        (N, K): std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.partitions.get_key_value(key)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        (N, K): std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.partitions.get_mut(key)
+    }
+
+    fn cache_drain_where<F: FnMut(&(N, K), &V) -> bool>(
+        &mut self,
+        drain: F,
+    ) -> impl Iterator<Item = ((N, K), V)> {
+        self.partitions.drain_where(drain).into_iter()
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: (N, K), f: F) -> &mut V {
+        let found = self.partitions.contains_key(&key);
+        self.partitions.count(found);
+        if !found {
+            self.cache_set(key.clone(), f());
+        }
+        self.partitions
+            .get_uncounted(&key)
+            .expect("NamespacedCache::cache_get_or_set_with value was just set")
+    }
+}
+
+impl<N, K, V> CachedRead<(N, K), V> for NamespacedCache<N, K, V>
+where
+    N: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    fn cache_size(&self) -> usize {
+        self.partitions.len()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.partitions.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.partitions.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        (N, K): std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.partitions.peek(key)
+    }
+
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a (N, K), &'a V)>
+    where
+        (N, K): 'a,
+        V: 'a,
+    {
+        self.partitions.iter()
+    }
+}
+
+impl<N, K, V> CachedWrite<(N, K), V> for NamespacedCache<N, K, V>
+where
+    N: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    fn cache_set(&mut self, key: (N, K), val: V) -> Option<V> {
+        let namespace = key.0.clone();
+        let quota = self.quota(&namespace);
+        let old = self.partitions.set(key, val, quota);
+        if self.partitions.len() > self.capacity {
+            self.partitions
+                .evict_max_by_key(&namespace, |_, size| size as i128);
+        }
+        old
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        (N, K): std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.partitions.remove(k)
+    }
+
+    fn cache_clear(&mut self) {
+        self.partitions.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.partitions.reset();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.partitions.reset_metrics();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespaces_evict_their_own_values() {
+        let mut c = NamespacedCache::with_size_and_quota(100, 2);
+        c.cache_set(("a", 1), 1);
+        c.cache_set(("b", 1), 10);
+        c.cache_set(("b", 2), 20);
+        c.cache_set(("b", 3), 30);
+
+        assert_eq!(c.cache_get(&("a", 1)), Some(&1));
+        assert_eq!(c.cache_get(&("b", 1)), None);
+        assert_eq!(c.cache_get(&("b", 3)), Some(&30));
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.namespace_size(&"b"), 2);
+        assert_eq!(c.cache_hits(), Some(2));
+        assert_eq!(c.cache_misses(), Some(1));
+    }
+
+    #[test]
+    fn set_quota() {
+        let mut c = NamespacedCache::with_size_and_quota(100, 3);
+        for i in 0..3 {
+            c.cache_set(("a", i), i);
+        }
+        c.cache_get(&("a", 0));
+        c.set_quota("a", 1);
+
+        assert_eq!(c.quota(&"a"), 1);
+        assert_eq!(c.quota(&"b"), 3);
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(c.cache_get(&("a", 0)), Some(&0));
+        c.cache_set(("a", 4), 4);
+        assert_eq!(c.cache_get(&("a", 0)), None);
+    }
+
+    #[test]
+    fn remove_and_clear() {
+        let mut c = NamespacedCache::with_size_and_quota(100, 2);
+        assert_eq!(*c.cache_get_or_set_with(("a", 1), || 1), 1);
+        assert_eq!(*c.cache_get_or_set_with(("a", 1), || 2), 1);
+        c.cache_set(("b", 1), 10);
+
+        assert_eq!(c.cache_remove(&("a", 1)), Some(1));
+        assert_eq!(c.cache_remove(&("a", 1)), None);
+        assert_eq!(c.cache_size(), 1);
+        c.cache_clear();
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(c.cache_get(&("b", 1)), None);
+    }
+
+    #[test]
+    fn the_largest_namespace_is_evicted_when_full() {
+        let mut c = NamespacedCache::with_size_and_quota(4, 3);
+        c.cache_set(("a", 1), 1);
+        for i in 0..3 {
+            c.cache_set(("b", i), i);
+        }
+        c.cache_set(("c", 1), 100);
+
+        assert_eq!(c.cache_size(), 4);
+        assert_eq!(c.cache_capacity(), Some(4));
+        assert_eq!(c.namespace_size(&"b"), 2);
+        assert_eq!(c.cache_get(&("b", 0)), None);
+
+        // many namespaces of a single value each stay within the size
+        let mut c = NamespacedCache::with_size_and_quota(4, 3);
+        for namespace in 0..10 {
+            c.cache_set((namespace, 1), namespace);
+        }
+        assert_eq!(c.cache_size(), 4);
+        assert_eq!(c.partitions.caches.len(), 4);
+        assert_eq!(c.cache_get(&(9, 1)), Some(&9));
+    }
+}
@@ -0,0 +1,399 @@
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
+use crate::IOCachedAsync;
+use async_trait::async_trait;
+use object_store::path::Path;
+use object_store::{Attribute, AttributeValue, Attributes, ObjectStore, PutOptions, PutPayload};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct ObjectStoreCacheBuilder<K, V> {
+    seconds: u64,
+    refresh: bool,
+    prefix: String,
+    store: Option<Arc<dyn ObjectStore>>,
+    ttl_metadata: bool,
+    _phantom: PhantomData<(K, V)>,
+}
+
+const EXPIRES_AT_METADATA: &str = "cached-expires-at";
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ObjectStoreCacheBuildError {
+    #[error("No object store specified, see `set_store`")]
+    MissingStore,
+}
+
+impl<K, V> ObjectStoreCacheBuilder<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Initialize an `ObjectStoreCacheBuilder`
+    pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> ObjectStoreCacheBuilder<K, V> {
+        Self {
+            seconds,
+            refresh: false,
+            prefix: prefix.as_ref().to_string(),
+            store: None,
+            ttl_metadata: true,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Specify the cache TTL/lifespan in seconds
+    #[must_use]
+    pub fn set_lifespan(mut self, seconds: u64) -> Self {
+        self.seconds = seconds;
+        self
+    }
+
+    /// Specify whether cache hits refresh the TTL.
+    /// Object stores cannot update the metadata of an object in place,
+    /// so refreshing a value uploads it again.
+    #[must_use]
+    pub fn set_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Set the path prefix under which values are stored, values are
+    /// stored at `{prefix}/{key}`
+    #[must_use]
+    pub fn set_prefix<S: AsRef<str>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.as_ref().to_string();
+        self
+    }
+
+    /// Set the object store holding the cached values (S3, GCS, Azure, local files, ...)
+    #[must_use]
+    pub fn set_store(mut self, store: Arc<dyn ObjectStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Specify whether the expiry time of values is stored in the `cached-expires-at`
+    /// metadata of their objects. Defaults to `true`. Disable it for stores that do not
+    /// support object metadata (e.g. the local file system), the expiry time is then
+    /// derived from the last modification time of objects.
+    #[must_use]
+    pub fn set_ttl_metadata(mut self, ttl_metadata: bool) -> Self {
+        self.ttl_metadata = ttl_metadata;
+        self
+    }
+
+    /// The last step in building an `ObjectStoreCache` is to call `build()`
+    ///
+    /// # Errors
+    ///
+    /// Will return `ObjectStoreCacheBuildError::MissingStore` if no object store is set
+    pub fn build(self) -> Result<ObjectStoreCache<K, V>, ObjectStoreCacheBuildError> {
+        let store = self.store.ok_or(ObjectStoreCacheBuildError::MissingStore)?;
+        Ok(ObjectStoreCache {
+            seconds: self.seconds,
+            refresh: self.refresh,
+            prefix: Path::from(self.prefix),
+            store,
+            ttl_metadata: self.ttl_metadata,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            _phantom: PhantomData,
+        })
+    }
+}
+
+/// Cache store backed by an [`object_store`](https://docs.rs/object_store) object store,
+/// meant for large values (reports, renders, ...)
+///
+/// Each value is stored as an object at `{prefix}/{key}`. Expired objects are never
+/// returned but are not deleted either, configure a lifecycle rule on the bucket
+/// to have them deleted.
+pub struct ObjectStoreCache<K, V> {
+    pub(super) seconds: u64,
+    pub(super) refresh: bool,
+    prefix: Path,
+    store: Arc<dyn ObjectStore>,
+    ttl_metadata: bool,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> ObjectStoreCache<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    #[allow(clippy::new_ret_no_self)]
+    /// Initialize an `ObjectStoreCacheBuilder`
+    pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> ObjectStoreCacheBuilder<K, V> {
+        ObjectStoreCacheBuilder::new(prefix, seconds)
+    }
+
+    /// Return the path at which the value of `key` is stored
+    pub fn path(&self, key: &K) -> Path {
+        self.prefix.child(key.to_string())
+    }
+
+    fn put_options(&self) -> PutOptions {
+        let mut attributes = Attributes::new();
+        if self.ttl_metadata {
+            let expires_at = now_secs().saturating_add(self.seconds);
+            attributes.insert(
+                Attribute::Metadata(EXPIRES_AT_METADATA.into()),
+                AttributeValue::from(expires_at.to_string()),
+            );
+        }
+        PutOptions {
+            attributes,
+            ..PutOptions::default()
+        }
+    }
+
+    /// Fetch the stored value at `path`, returning `None` if there is none or it has expired.
+    /// The serialized value is returned along with the value so that it can be uploaded again.
+    async fn fetch(&self, path: &Path) -> Result<Option<(V, PutPayload)>, ObjectStoreCacheError> {
+        let res = match self.store.get(path).await {
+            Ok(res) => res,
+            Err(object_store::Error::NotFound { .. }) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let expires_at = res
+            .attributes
+            .get(&Attribute::Metadata(EXPIRES_AT_METADATA.into()))
+            .and_then(|expires_at| expires_at.parse::<u64>().ok())
+            .unwrap_or_else(|| {
+                u64::try_from(res.meta.last_modified.timestamp())
+                    .unwrap_or(0)
+                    .saturating_add(self.seconds)
+            });
+        if expires_at <= now_secs() {
+            return Ok(None);
+        }
+        let bytes = res.bytes().await?;
+        let cached = rmp_serde::from_slice::<CachedObjectValue<V>>(&bytes)
+            .map_err(ObjectStoreCacheError::CacheDeserializationError)?;
+        Ok(Some((cached.value, bytes.into())))
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Error, Debug)]
+pub enum ObjectStoreCacheError {
+    #[error("Object store error")]
+    StoreError(#[source] Box<object_store::Error>),
+    #[error("Error deserializing cached value")]
+    CacheDeserializationError(#[from] rmp_serde::decode::Error),
+    #[error("Error serializing cached value")]
+    CacheSerializationError(#[from] rmp_serde::encode::Error),
+}
+
+impl From<object_store::Error> for ObjectStoreCacheError {
+    fn from(error: object_store::Error) -> Self {
+        Self::StoreError(Box::new(error))
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedObjectValue<V> {
+    value: V,
+    version: Option<u64>,
+}
+
+#[async_trait]
+impl<K, V> IOCachedAsync<K, V> for ObjectStoreCache<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    type Error = ObjectStoreCacheError;
+
+    /// Get a cached value
+    async fn cache_get(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let path = self.path(key);
+        let value = match self.fetch(&path).await? {
+            Some((value, payload)) => {
+                if self.refresh {
+                    self.store
+                        .put_opts(&path, payload, self.put_options())
+                        .await?;
+                }
+                Some(value)
+            }
+            None => None,
+        };
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency.record(Operation::Backend, started.elapsed());
+            self.latency.record_get(value.is_some(), started.elapsed());
+        }
+        Ok(value)
+    }
+
+    /// Set a cached value
+    async fn cache_set(&self, key: K, val: V) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let path = self.path(&key);
+        let bytes = rmp_serde::to_vec(&CachedObjectValue {
+            value: val,
+            version: Some(1),
+        })?;
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let old = self.fetch(&path).await?.map(|(value, _)| value);
+        self.store
+            .put_opts(&path, bytes.into(), self.put_options())
+            .await?;
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record(Operation::Insert, started.elapsed());
+        }
+        Ok(old)
+    }
+
+    /// Remove a cached value
+    async fn cache_remove(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let path = self.path(key);
+        let old = self.fetch(&path).await?.map(|(value, _)| value);
+        match self.store.delete(&path).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+            Err(e) => return Err(e.into()),
+        }
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Backend, started.elapsed());
+        Ok(old)
+    }
+
+    /// Set the flag to control whether cache hits refresh the ttl of cached values, returns the old flag value
+    fn cache_set_refresh(&mut self, refresh: bool) -> bool {
+        let old = self.refresh;
+        self.refresh = refresh;
+        old
+    }
+
+    /// Return the lifespan of cached values (time to eviction)
+    fn cache_lifespan(&self) -> Option<u64> {
+        Some(self.seconds)
+    }
+
+    /// Set the lifespan of cached values, returns the old value
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let old = self.seconds;
+        self.seconds = seconds;
+        Some(old)
+    }
+
+    /// Return latency histograms of this cache's operations
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    fn cache(seconds: u64) -> ObjectStoreCache<String, Vec<u8>> {
+        ObjectStoreCache::new("artifacts", seconds)
+            .set_store(Arc::new(InMemory::new()))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn set_get_remove() {
+        let cache = cache(60);
+        let key = "report/2024".to_string();
+        assert_eq!(cache.cache_get(&key).await.unwrap(), None);
+        assert_eq!(
+            cache.cache_set(key.clone(), vec![1; 1024]).await.unwrap(),
+            None
+        );
+        assert_eq!(cache.cache_get(&key).await.unwrap(), Some(vec![1; 1024]));
+        assert_eq!(
+            cache.cache_set(key.clone(), vec![2; 16]).await.unwrap(),
+            Some(vec![1; 1024])
+        );
+        assert_eq!(cache.cache_remove(&key).await.unwrap(), Some(vec![2; 16]));
+        assert_eq!(cache.cache_remove(&key).await.unwrap(), None);
+        assert_eq!(cache.cache_get(&key).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn keys_are_a_single_path_segment() {
+        let cache = cache(60);
+        assert_eq!(
+            cache.path(&"report/2024".to_string()).as_ref(),
+            "artifacts/report%2F2024"
+        );
+    }
+
+    #[tokio::test]
+    async fn expiry_is_read_from_metadata() {
+        let cache = cache(60);
+        let key = "render".to_string();
+        cache.cache_set(key.clone(), vec![1]).await.unwrap();
+
+        let res = cache.store.get(&cache.path(&key)).await.unwrap();
+        let expires_at: u64 = res
+            .attributes
+            .get(&Attribute::Metadata(EXPIRES_AT_METADATA.into()))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(expires_at > now_secs() && expires_at <= now_secs() + 60);
+
+        let mut attributes = Attributes::new();
+        attributes.insert(
+            Attribute::Metadata(EXPIRES_AT_METADATA.into()),
+            AttributeValue::from((now_secs() - 1).to_string()),
+        );
+        let bytes = res.bytes().await.unwrap();
+        cache
+            .store
+            .put_opts(
+                &cache.path(&key),
+                bytes.into(),
+                PutOptions {
+                    attributes,
+                    ..PutOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(cache.cache_get(&key).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn expiry_falls_back_to_last_modified() {
+        let mut cache = cache(60);
+        cache.ttl_metadata = false;
+        let key = "render".to_string();
+        cache.cache_set(key.clone(), vec![1]).await.unwrap();
+        assert_eq!(cache.cache_get(&key).await.unwrap(), Some(vec![1]));
+        cache.cache_set_lifespan(0);
+        assert_eq!(cache.cache_get(&key).await.unwrap(), None);
+    }
+}
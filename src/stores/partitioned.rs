@@ -0,0 +1,252 @@
+use super::{Cached, CachedRead, CachedWrite, SizedCache};
+use std::cmp::Eq;
+use std::hash::Hash;
+
+#[cfg(feature = "ahash")]
+use hashbrown::{HashMap, HashSet};
+
+#[cfg(not(feature = "ahash"))]
+use std::collections::{HashMap, HashSet};
+
+/// Values stored under `(partition, key)` pairs, each partition in its own `SizedCache`
+///
+/// The stores built on it decide how many values each partition may hold, and which
+/// partition to evict from when they are full. A partition left without values is
+/// dropped, so that the partitions do not outnumber the values.
+#[derive(Clone, Debug)]
+pub(super) struct Partitions<P, K, V> {
+    pub(super) caches: HashMap<P, SizedCache<(P, K), V>>,
+    // the keys of all partitions, to find the partition of borrowed keys
+    pub(super) keys: HashSet<(P, K)>,
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+}
+
+impl<P, K, V> Partitions<P, K, V>
+where
+    P: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    pub(super) fn new() -> Partitions<P, K, V> {
+        Partitions {
+            caches: HashMap::new(),
+            keys: HashSet::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Return the number of values of all partitions
+    pub(super) fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Return the number of values of a partition
+    pub(super) fn partition_size(&self, partition: &P) -> usize {
+        self.caches.get(partition).map_or(0, CachedRead::cache_size)
+    }
+
+    pub(super) fn contains_key(&self, key: &(P, K)) -> bool {
+        self.keys.contains(key)
+    }
+
+    /// Count a hit or a miss depending on whether `found`
+    pub(super) fn count(&mut self, found: bool) {
+        if found {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+
+    pub(super) fn get_key_value<Q>(&mut self, key: &Q) -> Option<(&(P, K), &V)>
+    where
+        (P, K): std::borrow::Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = match self.keys.get(key) {
+            Some((partition, _)) => self
+                .caches
+                .get_mut(partition)
+                .and_then(|cache| cache.cache_get_key_value(key)),
+            None => None,
+        };
+        if entry.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        entry
+    }
+
+    pub(super) fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        (P, K): std::borrow::Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let value = match self.keys.get(key) {
+            Some((partition, _)) => self
+                .caches
+                .get_mut(partition)
+                .and_then(|cache| cache.cache_get_mut(key)),
+            None => None,
+        };
+        if value.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        value
+    }
+
+    /// Return the value of `key`, updating its recency without counting a hit or a miss
+    pub(super) fn get_uncounted(&mut self, key: &(P, K)) -> Option<&mut V> {
+        self.caches
+            .get_mut(&key.0)
+            .and_then(|cache| cache.get_mut_if(key, |_| true))
+    }
+
+    pub(super) fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        (P, K): std::borrow::Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (partition, _) = self.keys.get(key)?;
+        self.caches.get(partition)?.cache_peek(key)
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = (&(P, K), &V)> {
+        self.caches.values().flat_map(CachedRead::cache_iter)
+    }
+
+    /// Set a value, the partition of the key evicting its least recently used value
+    /// once it holds more than `capacity` values, and return the previous value of the key
+    pub(super) fn set(&mut self, key: (P, K), val: V, capacity: usize) -> Option<V> {
+        self.keys.insert(key.clone());
+        let cache = self.caches.entry(key.0.clone()).or_insert_with(|| {
+            // not pre-allocated, most partitions holding fewer values than they may
+            let mut cache = SizedCache::without_limit();
+            cache.capacity = capacity;
+            cache
+        });
+        let (old, evicted) = cache.set_evicting(key, val);
+        if let Some((evicted, _)) = evicted {
+            self.keys.remove(&evicted);
+        }
+        old
+    }
+
+    /// Set the number of values a partition may hold, evicting its least recently used
+    /// values over it
+    pub(super) fn set_capacity(&mut self, partition: &P, capacity: usize) {
+        if let Some(cache) = self.caches.get_mut(partition) {
+            for (key, _) in cache.set_capacity(capacity) {
+                self.keys.remove(&key);
+            }
+        }
+    }
+
+    /// Evict the least recently used value of the partition `score` is the highest for,
+    /// given the number of values of the partition, other than the value just set in
+    /// the partition `set_in`
+    pub(super) fn evict_max_by_key<F>(&mut self, set_in: &P, mut score: F) -> Option<((P, K), V)>
+    where
+        F: FnMut(&P, usize) -> i128,
+    {
+        let victim = self
+            .caches
+            .iter()
+            .filter(|(partition, cache)| cache.cache_size() > usize::from(*partition == set_in))
+            .max_by_key(|(partition, cache)| score(partition, cache.cache_size()))
+            .map(|(partition, _)| partition.clone())?;
+        let cache = self.caches.get_mut(&victim)?;
+        let evicted = cache.evict_lru()?;
+        if cache.cache_size() == 0 {
+            self.caches.remove(&victim);
+        }
+        self.keys.remove(&evicted.0);
+        Some(evicted)
+    }
+
+    pub(super) fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        (P, K): std::borrow::Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (partition, _) = self.keys.take(key)?;
+        let cache = self.caches.get_mut(&partition)?;
+        let value = cache.cache_remove(key);
+        if cache.cache_size() == 0 {
+            self.caches.remove(&partition);
+        }
+        value
+    }
+
+    pub(super) fn drain_where<F: FnMut(&(P, K), &V) -> bool>(
+        &mut self,
+        mut drain: F,
+    ) -> Vec<((P, K), V)> {
+        let mut drained = Vec::new();
+        for cache in self.caches.values_mut() {
+            drained.extend(cache.cache_drain_where(&mut drain));
+        }
+        self.caches.retain(|_, cache| cache.cache_size() > 0);
+        for (key, _) in &drained {
+            self.keys.remove(key);
+        }
+        drained
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.caches.clear();
+        self.keys.clear();
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.caches = HashMap::new();
+        self.keys = HashSet::new();
+    }
+
+    pub(super) fn reset_metrics(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// Release the memory kept beyond the values, e.g. after most of them were removed
+    pub(super) fn shrink_to_fit(&mut self) {
+        self.caches.values_mut().for_each(SizedCache::shrink_to_fit);
+        self.caches.shrink_to_fit();
+        self.keys.shrink_to_fit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_partitions_are_dropped() {
+        let mut p = Partitions::new();
+        p.set(("a", 1), 1, 2);
+        p.set(("b", 1), 10, 2);
+        p.set(("b", 2), 20, 2);
+        p.set(("b", 3), 30, 2);
+        assert_eq!(p.len(), 3);
+        assert_eq!(p.partition_size(&"b"), 2);
+
+        assert_eq!(p.remove(&("a", 1)), Some(1));
+        assert!(!p.caches.contains_key(&"a"));
+
+        // "b" holds the most values, but the value just set there is kept
+        p.set(("c", 1), 100, 2);
+        let evicted = p.evict_max_by_key(&"c", |_, size| size as i128);
+        assert_eq!(evicted, Some((("b", 2), 20)));
+        p.set(("b", 4), 40, 2);
+        p.set_capacity(&"b", 1);
+        assert_eq!(p.partition_size(&"b"), 1);
+        assert_eq!(p.len(), 2);
+
+        p.drain_where(|_, _| true);
+        assert!(p.caches.is_empty());
+    }
+}
@@ -1,25 +1,52 @@
+use super::random_fraction;
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
 use crate::IOCached;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::fmt::Display;
 use std::marker::PhantomData;
 
-pub struct RedisCacheBuilder<K, V> {
+pub struct RedisCacheBuilder<K, V, F = JsonSerializer> {
     seconds: u64,
     refresh: bool,
     namespace: String,
     prefix: String,
     connection_string: Option<String>,
+    cluster_nodes: Option<Vec<String>>,
+    client: Option<redis::Client>,
+    #[cfg(feature = "redis_rustls")]
+    tls_certificates: Option<redis::TlsCertificates>,
+    invalidation_channel: Option<String>,
+    ttl_jitter: f64,
     pool_max_size: Option<u32>,
     pool_min_idle: Option<u32>,
     pool_max_lifetime: Option<std::time::Duration>,
     pool_idle_timeout: Option<std::time::Duration>,
+    serializer: F,
     _phantom: PhantomData<(K, V)>,
 }
 
 const ENV_KEY: &str = "CACHED_REDIS_CONNECTION_STRING";
+
+#[cfg(feature = "redis_rustls")]
+fn no_tls_certificates() -> redis::TlsCertificates {
+    redis::TlsCertificates {
+        client_tls: None,
+        root_cert: None,
+    }
+}
 const DEFAULT_NAMESPACE: &str = "cached-redis-store:";
 
+/// Return the TTL of a value set now: `seconds`, randomly moved by up to `jitter` of it
+fn jittered_ttl(seconds: u64, jitter: f64) -> u64 {
+    if jitter <= 0.0 || seconds == 0 {
+        return seconds;
+    }
+    let shift = jitter * (2.0 * random_fraction() - 1.0);
+    ((seconds as f64 * (1.0 + shift)).round() as u64).max(1)
+}
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -48,10 +75,48 @@ where
             namespace: DEFAULT_NAMESPACE.to_string(),
             prefix: prefix.as_ref().to_string(),
             connection_string: None,
+            cluster_nodes: None,
+            client: None,
+            #[cfg(feature = "redis_rustls")]
+            tls_certificates: None,
+            invalidation_channel: None,
+            ttl_jitter: 0.0,
             pool_max_size: None,
             pool_min_idle: None,
             pool_max_lifetime: None,
             pool_idle_timeout: None,
+            serializer: JsonSerializer,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<K, V, F> RedisCacheBuilder<K, V, F>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+    F: RedisSerializer,
+{
+    /// Set the wire format of the cached values. Defaults to `JsonSerializer`.
+    #[must_use]
+    pub fn set_serializer<G: RedisSerializer>(self, serializer: G) -> RedisCacheBuilder<K, V, G> {
+        RedisCacheBuilder {
+            seconds: self.seconds,
+            refresh: self.refresh,
+            namespace: self.namespace,
+            prefix: self.prefix,
+            connection_string: self.connection_string,
+            cluster_nodes: self.cluster_nodes,
+            client: self.client,
+            #[cfg(feature = "redis_rustls")]
+            tls_certificates: self.tls_certificates,
+            invalidation_channel: self.invalidation_channel,
+            ttl_jitter: self.ttl_jitter,
+            pool_max_size: self.pool_max_size,
+            pool_min_idle: self.pool_min_idle,
+            pool_max_lifetime: self.pool_max_lifetime,
+            pool_idle_timeout: self.pool_idle_timeout,
+            serializer,
             _phantom: PhantomData,
         }
     }
@@ -70,6 +135,22 @@ where
         self
     }
 
+    /// Randomly move the TTL of each value set, earlier or later, by up to this fraction
+    /// of the lifespan, from `0.0` to `1.0`, so that values set together do not all
+    /// expire together
+    ///
+    /// # Panics
+    ///
+    /// Will panic if jitter is not between 0.0 and 1.0
+    #[must_use]
+    pub fn set_ttl_jitter(mut self, jitter: f64) -> Self {
+        if !(0.0..=1.0).contains(&jitter) {
+            panic!("`ttl_jitter` of the redis cache must be between 0.0 and 1.0.");
+        }
+        self.ttl_jitter = jitter;
+        self
+    }
+
     /// Set the namespace for cache keys. Defaults to `cached-redis-store:`.
     /// Used to generate keys formatted as: `{namespace}{prefix}{key}`
     /// Note that no delimiters are implicitly added so you may pass
@@ -97,6 +178,65 @@ where
         self
     }
 
+    /// Connect with this client, e.g. the one already configured by the application
+    /// (auth, DB index, TLS...), instead of creating one from the connection string.
+    /// The connection string and TLS certificates are ignored.
+    #[must_use]
+    pub fn set_client(mut self, client: redis::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Connect to a Redis Cluster through these initial nodes (connection strings)
+    /// instead of a single server. Commands are routed to the node serving the hash
+    /// slot of their key. The connection string is ignored.
+    #[cfg(feature = "redis_cluster")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "redis_cluster")))]
+    #[must_use]
+    pub fn set_cluster<I, S>(mut self, nodes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.cluster_nodes = Some(nodes.into_iter().map(|n| n.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Verify the certificate of the server with this PEM encoded CA certificate instead of
+    /// the platform's root certificates. TLS requires a `rediss://` connection string.
+    #[cfg(feature = "redis_rustls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "redis_rustls")))]
+    #[must_use]
+    pub fn set_tls_root_cert(mut self, root_cert: Vec<u8>) -> Self {
+        self.tls_certificates
+            .get_or_insert_with(no_tls_certificates)
+            .root_cert = Some(root_cert);
+        self
+    }
+
+    /// Authenticate with this PEM encoded client certificate and private key (mutual TLS).
+    /// TLS requires a `rediss://` connection string.
+    #[cfg(feature = "redis_rustls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "redis_rustls")))]
+    #[must_use]
+    pub fn set_tls_client_cert(mut self, client_cert: Vec<u8>, client_key: Vec<u8>) -> Self {
+        self.tls_certificates
+            .get_or_insert_with(no_tls_certificates)
+            .client_tls = Some(redis::ClientTlsConfig {
+            client_cert,
+            client_key,
+        });
+        self
+    }
+
+    /// Publish the keys removed from the cache on this channel, so that other processes
+    /// can drop them from their local caches, see `RedisCache::subscribe_invalidations`
+    #[must_use]
+    pub fn set_invalidation_channel<S: AsRef<str>>(mut self, channel: S) -> Self {
+        self.invalidation_channel = Some(channel.as_ref().to_string());
+        self
+    }
+
     /// Set the max size of the underlying redis connection pool
     #[must_use]
     pub fn set_connection_pool_max_size(mut self, max_size: u32) -> Self {
@@ -143,9 +283,7 @@ where
         }
     }
 
-    fn create_pool(&self) -> Result<r2d2::Pool<redis::Client>, RedisCacheBuildError> {
-        let s = self.connection_string()?;
-        let client: redis::Client = redis::Client::open(s)?;
+    fn pool_builder<M: r2d2::ManageConnection>(&self) -> r2d2::Builder<M> {
         // some pool-builder defaults are set when the builder is initialized
         // so we can't overwrite any values with Nones...
         let pool_builder = r2d2::Pool::builder();
@@ -164,14 +302,65 @@ where
         } else {
             pool_builder
         };
-        let pool_builder = if let Some(idle_timeout) = self.pool_idle_timeout {
+        if let Some(idle_timeout) = self.pool_idle_timeout {
             pool_builder.idle_timeout(Some(idle_timeout))
         } else {
             pool_builder
-        };
+        }
+    }
 
-        let pool: r2d2::Pool<redis::Client> = pool_builder.build(client)?;
-        Ok(pool)
+    fn create_pool(&self) -> Result<RedisPool, RedisCacheBuildError> {
+        #[cfg(feature = "redis_cluster")]
+        if let Some(ref nodes) = self.cluster_nodes {
+            let client = self.create_cluster_client(nodes)?;
+            return Ok(RedisPool::Cluster(self.pool_builder().build(client)?));
+        }
+        Ok(RedisPool::Single(
+            self.pool_builder().build(self.client()?)?,
+        ))
+    }
+
+    /// Return the client set, or create one connecting to the connection string
+    fn client(&self) -> Result<redis::Client, RedisCacheBuildError> {
+        match self.client {
+            Some(ref client) => Ok(client.clone()),
+            None => self.create_client(self.connection_string()?),
+        }
+    }
+
+    /// Create a client, using the TLS certificates set
+    fn create_client(&self, s: String) -> Result<redis::Client, RedisCacheBuildError> {
+        #[cfg(feature = "redis_rustls")]
+        if let Some(ref certificates) = self.tls_certificates {
+            return Ok(redis::Client::build_with_tls(s, certificates.clone())?);
+        }
+        Ok(redis::Client::open(s)?)
+    }
+
+    /// Create the client subscribing to the invalidation channel. A cluster
+    /// broadcasts the messages published to all its nodes, so any node will do.
+    fn create_invalidation_client(&self) -> Result<Option<redis::Client>, RedisCacheBuildError> {
+        if self.invalidation_channel.is_none() {
+            return Ok(None);
+        }
+        match self.cluster_nodes.as_ref().and_then(|nodes| nodes.first()) {
+            Some(node) => self.create_client(node.clone()).map(Some),
+            None => self.client().map(Some),
+        }
+    }
+
+    #[cfg(feature = "redis_cluster")]
+    fn create_cluster_client(
+        &self,
+        nodes: &[String],
+    ) -> Result<redis::cluster::ClusterClient, RedisCacheBuildError> {
+        let builder = redis::cluster::ClusterClient::builder(nodes.to_vec());
+        #[cfg(feature = "redis_rustls")]
+        let builder = match self.tls_certificates {
+            Some(ref certificates) => builder.certs(certificates.clone()),
+            None => builder,
+        };
+        Ok(builder.build()?)
     }
 
     /// The last step in building a `RedisCache` is to call `build()`
@@ -179,14 +368,24 @@ where
     /// # Errors
     ///
     /// Will return a `RedisCacheBuildError`, depending on the error
-    pub fn build(self) -> Result<RedisCache<K, V>, RedisCacheBuildError> {
+    pub fn build(self) -> Result<RedisCache<K, V, F>, RedisCacheBuildError> {
         Ok(RedisCache {
             seconds: self.seconds,
             refresh: self.refresh,
-            connection_string: self.connection_string()?,
+            connection_string: match (&self.cluster_nodes, &self.client) {
+                (Some(nodes), _) => nodes.join(","),
+                (None, Some(client)) => client.get_connection_info().addr.to_string(),
+                (None, None) => self.connection_string()?,
+            },
             pool: self.create_pool()?,
+            invalidation_client: self.create_invalidation_client()?,
+            invalidation_channel: self.invalidation_channel,
+            ttl_jitter: self.ttl_jitter,
             namespace: self.namespace,
             prefix: self.prefix,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            serializer: self.serializer,
             _phantom: PhantomData,
         })
     }
@@ -196,13 +395,20 @@ where
 ///
 /// Values have a ttl applied and enforced by redis.
 /// Uses an r2d2 connection pool under the hood.
-pub struct RedisCache<K, V> {
+/// With feature `redis_cluster`, the pool may hold Redis Cluster connections.
+pub struct RedisCache<K, V, F = JsonSerializer> {
     pub(super) seconds: u64,
     pub(super) refresh: bool,
     pub(super) namespace: String,
     pub(super) prefix: String,
     connection_string: String,
-    pool: r2d2::Pool<redis::Client>,
+    pool: RedisPool,
+    invalidation_channel: Option<String>,
+    invalidation_client: Option<redis::Client>,
+    ttl_jitter: f64,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
+    serializer: F,
     _phantom: PhantomData<(K, V)>,
 }
 
@@ -216,16 +422,317 @@ where
     pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> RedisCacheBuilder<K, V> {
         RedisCacheBuilder::new(prefix, seconds)
     }
+}
 
+impl<K, V, F> RedisCache<K, V, F>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+    F: RedisSerializer,
+{
     fn generate_key(&self, key: &K) -> String {
         format!("{}{}{}", self.namespace, self.prefix, key)
     }
 
-    /// Return the redis connection string used
+    /// Return the redis connection string used, the address of the client set,
+    /// or the comma separated initial nodes of a cluster
     #[must_use]
     pub fn connection_string(&self) -> String {
         self.connection_string.clone()
     }
+
+    fn query<T: redis::FromRedisValue>(
+        &self,
+        pipe: &redis::Pipeline,
+    ) -> Result<T, RedisCacheError> {
+        // ugh: https://github.com/mitsuhiko/redis-rs/pull/388#issuecomment-910919137
+        let res = match self.pool {
+            RedisPool::Single(ref pool) => pipe.query(&mut *pool.get()?)?,
+            #[cfg(feature = "redis_cluster")]
+            RedisPool::Cluster(ref pool) => pipe.query(&mut *pool.get()?)?,
+        };
+        Ok(res)
+    }
+
+    /// Get the cached values of `keys`, in the same order, with a single `MGET`
+    ///
+    /// With Redis Cluster, the keys must hash to the same slot, e.g. by
+    /// putting a `{hash tag}` in the prefix.
+    pub fn cache_get_many(&self, keys: &[K]) -> Result<Vec<Option<V>>, RedisCacheError> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys: Vec<String> = keys.iter().map(|key| self.generate_key(key)).collect();
+        let mut pipe = redis::pipe();
+        pipe.get(&keys);
+        if self.refresh {
+            for key in keys {
+                pipe.expire(key, jittered_ttl(self.seconds, self.ttl_jitter) as i64)
+                    .ignore();
+            }
+        }
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let res: (Vec<Option<Vec<u8>>>,) = self.query(&pipe)?;
+        #[cfg(feature = "latency_stats")]
+        self.latency
+            .record(Operation::Backend, round_trip.elapsed());
+        res.0
+            .into_iter()
+            .map(|value| {
+                value
+                    .map(|value| CachedRedisValue::decode(&self.serializer, &value))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    /// Set the cached values of many keys with a single pipeline. Unlike
+    /// `cache_set`, the previous values are not returned.
+    ///
+    /// With Redis Cluster, the keys must hash to the same slot, e.g. by
+    /// putting a `{hash tag}` in the prefix.
+    pub fn cache_set_many(&self, values: Vec<(K, V)>) -> Result<(), RedisCacheError> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        let mut pipe = redis::pipe();
+        for (key, val) in values {
+            pipe.set_ex::<String, Vec<u8>>(
+                self.generate_key(&key),
+                CachedRedisValue::encode(&self.serializer, val)?,
+                jittered_ttl(self.seconds, self.ttl_jitter),
+            )
+            .ignore();
+        }
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        self.query::<()>(&pipe)?;
+        #[cfg(feature = "latency_stats")]
+        self.latency
+            .record(Operation::Backend, round_trip.elapsed());
+        Ok(())
+    }
+
+    /// Publish an invalidation on the invalidation channel, e.g. `Invalidation::Clear`
+    /// after removing the values of the cache by other means. Does nothing if no
+    /// invalidation channel is set.
+    pub fn publish_invalidation(&self, invalidation: &Invalidation) -> Result<(), RedisCacheError> {
+        let channel = match self.invalidation_channel {
+            Some(ref channel) => channel,
+            None => return Ok(()),
+        };
+        let mut pipe = redis::pipe();
+        pipe.publish(channel, invalidation.encode()).ignore();
+        self.query::<()>(&pipe)
+    }
+
+    /// Remove all the values of the cache, the keys starting with `{namespace}{prefix}`,
+    /// returning the number of values removed. Keys are found with `SCAN`, so values set
+    /// while clearing the cache may be kept. Publishes `Invalidation::Clear`.
+    ///
+    /// Note that the values of caches whose prefix starts with the prefix of this cache
+    /// are removed as well.
+    ///
+    /// # Errors
+    ///
+    /// Will return `RedisCacheError::ScanUnsupported` with Redis Cluster
+    pub fn cache_clear(&self) -> Result<u64, RedisCacheError> {
+        let removed = self.remove_matching(&scan_pattern(&self.namespace, &self.prefix, ""))?;
+        self.publish_invalidation(&Invalidation::Clear)?;
+        Ok(removed)
+    }
+
+    /// Remove the values of the keys starting with `key_prefix`, as formatted by `Display`,
+    /// returning the number of values removed. Keys are found with `SCAN`, so values set
+    /// meanwhile may be kept. Publishes `Invalidation::RemovePrefix`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `RedisCacheError::ScanUnsupported` with Redis Cluster
+    pub fn cache_remove_prefix(&self, key_prefix: &str) -> Result<u64, RedisCacheError> {
+        let removed =
+            self.remove_matching(&scan_pattern(&self.namespace, &self.prefix, key_prefix))?;
+        self.publish_invalidation(&Invalidation::RemovePrefix(key_prefix.to_string()))?;
+        Ok(removed)
+    }
+
+    fn remove_matching(&self, pattern: &str) -> Result<u64, RedisCacheError> {
+        #[cfg(feature = "redis_cluster")]
+        if let RedisPool::Cluster(_) = self.pool {
+            return Err(RedisCacheError::ScanUnsupported);
+        }
+        let mut removed = 0;
+        let mut cursor = 0;
+        loop {
+            let ((next, keys),): ((u64, Vec<String>),) = self.query(&scan(cursor, pattern))?;
+            if !keys.is_empty() {
+                let mut pipe = redis::pipe();
+                pipe.del(keys);
+                let (deleted,): (u64,) = self.query(&pipe)?;
+                removed += deleted;
+            }
+            if next == 0 {
+                return Ok(removed);
+            }
+            cursor = next;
+        }
+    }
+
+    /// Subscribe to the invalidation channel with a dedicated connection
+    ///
+    /// # Errors
+    ///
+    /// Will return `RedisCacheError::NoInvalidationChannel` if no invalidation channel is set
+    pub fn subscribe_invalidations(&self) -> Result<RedisInvalidations, RedisCacheError> {
+        match (&self.invalidation_client, &self.invalidation_channel) {
+            (Some(client), Some(channel)) => RedisInvalidations::subscribe(client, channel),
+            _ => Err(RedisCacheError::NoInvalidationChannel),
+        }
+    }
+}
+
+/// The number of keys each `SCAN` call is asked to look at
+const SCAN_COUNT: usize = 1000;
+
+/// The `SCAN` pattern matching the keys of a cache starting with `key_prefix`
+fn scan_pattern(namespace: &str, prefix: &str, key_prefix: &str) -> String {
+    let mut pattern = String::new();
+    for c in namespace
+        .chars()
+        .chain(prefix.chars())
+        .chain(key_prefix.chars())
+    {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('*');
+    pattern
+}
+
+/// The `SCAN` call continuing from `cursor`
+fn scan(cursor: u64, pattern: &str) -> redis::Pipeline {
+    let mut pipe = redis::pipe();
+    pipe.cmd("SCAN")
+        .arg(cursor)
+        .arg("MATCH")
+        .arg(pattern)
+        .arg("COUNT")
+        .arg(SCAN_COUNT);
+    pipe
+}
+
+/// A message of the invalidation channel of a redis cache
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Invalidation {
+    /// The value of a key was removed, the key being formatted with `Display`
+    Remove(String),
+    /// The values of the keys starting with a prefix were removed
+    RemovePrefix(String),
+    /// All values were removed
+    Clear,
+}
+
+impl Invalidation {
+    fn encode(&self) -> String {
+        serde_json::to_string(self).expect("invalidations are serializable")
+    }
+
+    fn decode(payload: String) -> Result<Self, RedisCacheError> {
+        serde_json::from_str(&payload).map_err(|e| RedisCacheError::CacheDeserializationError {
+            cached_value: payload,
+            error: e,
+        })
+    }
+}
+
+/// Subscription to the invalidation channel of redis caches
+///
+/// Other processes keeping the values of a redis cache in a local cache can drop
+/// them when they are removed from redis:
+///
+/// ```rust,no_run
+/// use cached::stores::{Invalidation, RedisCache};
+/// use cached::{Cached, CachedWrite, IOCached, SizedCache};
+/// use std::sync::{Arc, Mutex};
+///
+/// let cache: RedisCache<u32, String> = RedisCache::new("users:", 60)
+///     .set_invalidation_channel("users-invalidations")
+///     .build()
+///     .unwrap();
+/// let local = Arc::new(Mutex::new(SizedCache::<String, String>::with_size(100)));
+///
+/// let mut invalidations = cache.subscribe_invalidations().unwrap();
+/// let local_ = local.clone();
+/// std::thread::spawn(move || {
+///     while let Ok(invalidation) = invalidations.recv() {
+///         match invalidation {
+///             Invalidation::Remove(key) => {
+///                 local_.lock().unwrap().cache_remove(&key);
+///             }
+///             Invalidation::RemovePrefix(_) | Invalidation::Clear => {
+///                 local_.lock().unwrap().cache_clear();
+///             }
+///         }
+///     }
+/// });
+///
+/// // in another process
+/// cache.cache_remove(&1).unwrap();
+/// ```
+pub struct RedisInvalidations {
+    connection: redis::Connection,
+}
+
+impl RedisInvalidations {
+    /// Subscribe to an invalidation channel with a new connection of `client`
+    ///
+    /// # Errors
+    ///
+    /// Will return a `RedisCacheError::RedisCacheError` if the subscription fails
+    pub fn subscribe(client: &redis::Client, channel: &str) -> Result<Self, RedisCacheError> {
+        let mut connection = client.get_connection()?;
+        // not through `Connection::as_pubsub`, which unsubscribes when dropped
+        connection
+            .send_packed_command(&redis::cmd("SUBSCRIBE").arg(channel).get_packed_command())?;
+        Ok(Self { connection })
+    }
+
+    /// Wait for the next invalidation
+    ///
+    /// # Errors
+    ///
+    /// Will return a `RedisCacheError::RedisCacheError` if the connection fails or
+    /// the read timeout elapses
+    pub fn recv(&mut self) -> Result<Invalidation, RedisCacheError> {
+        loop {
+            // skip the confirmation of the subscription
+            if let Some(msg) = redis::Msg::from_value(&self.connection.recv_response()?) {
+                return Invalidation::decode(msg.get_payload()?);
+            }
+        }
+    }
+
+    /// Set the maximum time `recv` waits for an invalidation, `None` to wait indefinitely
+    ///
+    /// # Errors
+    ///
+    /// Will return a `RedisCacheError::RedisCacheError` if the timeout is zero
+    pub fn set_read_timeout(
+        &self,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<(), RedisCacheError> {
+        Ok(self.connection.set_read_timeout(timeout)?)
+    }
+}
+
+/// Connection pool to a single redis server or to a cluster
+enum RedisPool {
+    Single(r2d2::Pool<redis::Client>),
+    #[cfg(feature = "redis_cluster")]
+    Cluster(r2d2::Pool<redis::cluster::ClusterClient>),
 }
 
 #[derive(Error, Debug)]
@@ -241,6 +748,69 @@ pub enum RedisCacheError {
     },
     #[error("Error serializing cached value: {error:?}")]
     CacheSerializationError { error: serde_json::Error },
+    #[error("Error deserializing cached value: {0}")]
+    Deserialization(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Error serializing cached value: {0}")]
+    Serialization(Box<dyn std::error::Error + Send + Sync>),
+    #[error("no invalidation channel set")]
+    NoInvalidationChannel,
+    #[error("SCAN is not supported with Redis Cluster")]
+    ScanUnsupported,
+}
+
+/// The wire format of the values of the redis stores
+///
+/// Implement it to store values in another format, e.g. bincode or postcard, and set it
+/// with `set_serializer`. Values set in another format cannot be read, so the namespace or
+/// prefix of a cache should change along with its format.
+pub trait RedisSerializer {
+    /// Serialize a cached value
+    ///
+    /// # Errors
+    ///
+    /// Should return `RedisCacheError::Serialization` if the value cannot be serialized
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, RedisCacheError>;
+
+    /// Deserialize a cached value
+    ///
+    /// # Errors
+    ///
+    /// Should return `RedisCacheError::Deserialization` if the value cannot be deserialized
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, RedisCacheError>;
+}
+
+/// JSON, the default wire format of the values of the redis stores
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonSerializer;
+
+impl RedisSerializer for JsonSerializer {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, RedisCacheError> {
+        serde_json::to_vec(value).map_err(|e| RedisCacheError::CacheSerializationError { error: e })
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, RedisCacheError> {
+        serde_json::from_slice(bytes).map_err(|e| RedisCacheError::CacheDeserializationError {
+            cached_value: String::from_utf8_lossy(bytes).into_owned(),
+            error: e,
+        })
+    }
+}
+
+/// MessagePack, a wire format of the values of the redis stores more compact than JSON
+#[cfg(feature = "redis_msgpack")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis_msgpack")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePackSerializer;
+
+#[cfg(feature = "redis_msgpack")]
+impl RedisSerializer for MessagePackSerializer {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, RedisCacheError> {
+        rmp_serde::to_vec(value).map_err(|e| RedisCacheError::Serialization(Box::new(e)))
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, RedisCacheError> {
+        rmp_serde::from_slice(bytes).map_err(|e| RedisCacheError::Deserialization(Box::new(e)))
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -255,90 +825,112 @@ impl<V> CachedRedisValue<V> {
             version: Some(1),
         }
     }
+
+    fn encode<F: RedisSerializer>(serializer: &F, value: V) -> Result<Vec<u8>, RedisCacheError>
+    where
+        V: Serialize,
+    {
+        serializer.serialize(&Self::new(value))
+    }
+
+    fn decode<F: RedisSerializer>(serializer: &F, cached_value: &[u8]) -> Result<V, RedisCacheError>
+    where
+        V: DeserializeOwned,
+    {
+        let v: Self = serializer.deserialize(cached_value)?;
+        Ok(v.value)
+    }
 }
 
-impl<K, V> IOCached<K, V> for RedisCache<K, V>
+impl<K, V, F> IOCached<K, V> for RedisCache<K, V, F>
 where
     K: Display,
     V: Serialize + DeserializeOwned,
+    F: RedisSerializer,
 {
     type Error = RedisCacheError;
 
     fn cache_get(&self, key: &K) -> Result<Option<V>, RedisCacheError> {
-        let mut conn = self.pool.get()?;
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
         let mut pipe = redis::pipe();
         let key = self.generate_key(key);
 
         pipe.get(key.clone());
         if self.refresh {
-            pipe.expire(key, self.seconds as i64).ignore();
+            pipe.expire(key, jittered_ttl(self.seconds, self.ttl_jitter) as i64)
+                .ignore();
         }
-        // ugh: https://github.com/mitsuhiko/redis-rs/pull/388#issuecomment-910919137
-        let res: (Option<String>,) = pipe.query(&mut *conn)?;
-        match res.0 {
-            None => Ok(None),
-            Some(s) => {
-                let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                    RedisCacheError::CacheDeserializationError {
-                        cached_value: s,
-                        error: e,
-                    }
-                })?;
-                Ok(Some(v.value))
-            }
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let res: (Option<Vec<u8>>,) = self.query(&pipe)?;
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record_get(res.0.is_some(), started.elapsed());
         }
+        res.0
+            .map(|s| CachedRedisValue::decode(&self.serializer, &s))
+            .transpose()
     }
 
     fn cache_set(&self, key: K, val: V) -> Result<Option<V>, RedisCacheError> {
-        let mut conn = self.pool.get()?;
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
         let mut pipe = redis::pipe();
         let key = self.generate_key(&key);
 
-        let val = CachedRedisValue::new(val);
         pipe.get(key.clone());
-        pipe.set_ex::<String, String>(
+        pipe.set_ex::<String, Vec<u8>>(
             key,
-            serde_json::to_string(&val)
-                .map_err(|e| RedisCacheError::CacheSerializationError { error: e })?,
-            self.seconds,
+            CachedRedisValue::encode(&self.serializer, val)?,
+            jittered_ttl(self.seconds, self.ttl_jitter),
         )
         .ignore();
 
-        let res: (Option<String>,) = pipe.query(&mut *conn)?;
-        match res.0 {
-            None => Ok(None),
-            Some(s) => {
-                let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                    RedisCacheError::CacheDeserializationError {
-                        cached_value: s,
-                        error: e,
-                    }
-                })?;
-                Ok(Some(v.value))
-            }
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let res: (Option<Vec<u8>>,) = self.query(&pipe)?;
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record(Operation::Insert, started.elapsed());
         }
+        res.0
+            .map(|s| CachedRedisValue::decode(&self.serializer, &s))
+            .transpose()
+    }
+
+    /// Get the cached values of `keys` with a single `MGET`, see the inherent method
+    fn cache_get_many(&self, keys: &[K]) -> Result<Vec<Option<V>>, RedisCacheError> {
+        RedisCache::cache_get_many(self, keys)
+    }
+
+    /// Set the cached values with a single pipeline, see the inherent method
+    fn cache_set_many(&self, values: Vec<(K, V)>) -> Result<(), RedisCacheError> {
+        RedisCache::cache_set_many(self, values)
     }
 
     fn cache_remove(&self, key: &K) -> Result<Option<V>, RedisCacheError> {
-        let mut conn = self.pool.get()?;
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
         let mut pipe = redis::pipe();
-        let key = self.generate_key(key);
+        let redis_key = self.generate_key(key);
 
-        pipe.get(key.clone());
-        pipe.del::<String>(key).ignore();
-        let res: (Option<String>,) = pipe.query(&mut *conn)?;
-        match res.0 {
-            None => Ok(None),
-            Some(s) => {
-                let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                    RedisCacheError::CacheDeserializationError {
-                        cached_value: s,
-                        error: e,
-                    }
-                })?;
-                Ok(Some(v.value))
-            }
+        pipe.get(redis_key.clone());
+        pipe.del::<String>(redis_key).ignore();
+        if let Some(ref channel) = self.invalidation_channel {
+            pipe.publish(channel, Invalidation::Remove(key.to_string()).encode())
+                .ignore();
         }
+        let res: (Option<Vec<u8>>,) = self.query(&pipe)?;
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Backend, started.elapsed());
+        res.0
+            .map(|s| CachedRedisValue::decode(&self.serializer, &s))
+            .transpose()
     }
 
     fn cache_lifespan(&self) -> Option<u64> {
@@ -356,6 +948,11 @@ where
         self.refresh = refresh;
         old
     }
+
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
 }
 
 #[cfg(all(
@@ -363,18 +960,52 @@ where
     any(feature = "redis_async_std", feature = "redis_tokio")
 ))]
 mod async_redis {
+    #[cfg(feature = "redis_rustls")]
+    use super::no_tls_certificates;
     use super::{
-        CachedRedisValue, DeserializeOwned, Display, PhantomData, RedisCacheBuildError,
-        RedisCacheError, Serialize, DEFAULT_NAMESPACE, ENV_KEY,
+        jittered_ttl, scan, scan_pattern, CachedRedisValue, DeserializeOwned, Display,
+        Invalidation, JsonSerializer, PhantomData, RedisCacheBuildError, RedisCacheError,
+        RedisSerializer, Serialize, DEFAULT_NAMESPACE, ENV_KEY,
     };
-    use {crate::IOCachedAsync, async_trait::async_trait};
-
-    pub struct AsyncRedisCacheBuilder<K, V> {
+    use {crate::IOCachedAsync, async_trait::async_trait, futures::StreamExt};
+
+    #[cfg(feature = "latency_stats")]
+    use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
+    use once_cell::sync::Lazy;
+    use std::collections::HashMap;
+    #[cfg(feature = "redis_sentinel")]
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    #[cfg(not(feature = "redis_connection_manager"))]
+    type SingleConnection = redis::aio::MultiplexedConnection;
+    #[cfg(feature = "redis_connection_manager")]
+    type SingleConnection = redis::aio::ConnectionManager;
+
+    /// Connections shared by the `AsyncRedisCache`s built with `set_shared_connection`,
+    /// keyed by the connection info of their client. The connections are multiplexed,
+    /// so all the caches of a process connected to a server use a single connection,
+    /// kept open until the process exits.
+    static SHARED_CONNECTIONS: Lazy<Mutex<HashMap<String, SingleConnection>>> =
+        Lazy::new(|| Mutex::new(HashMap::new()));
+
+    pub struct AsyncRedisCacheBuilder<K, V, F = JsonSerializer> {
         seconds: u64,
         refresh: bool,
         namespace: String,
         prefix: String,
         connection_string: Option<String>,
+        cluster_nodes: Option<Vec<String>>,
+        sentinel: Option<(Vec<String>, String)>,
+        client: Option<redis::Client>,
+        #[cfg(feature = "redis_connection_manager")]
+        connection_manager: Option<redis::aio::ConnectionManager>,
+        shared_connection: bool,
+        #[cfg(feature = "redis_rustls")]
+        tls_certificates: Option<redis::TlsCertificates>,
+        invalidation_channel: Option<String>,
+        ttl_jitter: f64,
+        serializer: F,
         _phantom: PhantomData<(K, V)>,
     }
 
@@ -391,6 +1022,51 @@ mod async_redis {
                 namespace: DEFAULT_NAMESPACE.to_string(),
                 prefix: prefix.as_ref().to_string(),
                 connection_string: None,
+                cluster_nodes: None,
+                sentinel: None,
+                client: None,
+                #[cfg(feature = "redis_connection_manager")]
+                connection_manager: None,
+                shared_connection: false,
+                #[cfg(feature = "redis_rustls")]
+                tls_certificates: None,
+                invalidation_channel: None,
+                ttl_jitter: 0.0,
+                serializer: JsonSerializer,
+                _phantom: PhantomData,
+            }
+        }
+    }
+
+    impl<K, V, F> AsyncRedisCacheBuilder<K, V, F>
+    where
+        K: Display,
+        V: Serialize + DeserializeOwned,
+        F: RedisSerializer,
+    {
+        /// Set the wire format of the cached values. Defaults to `JsonSerializer`.
+        #[must_use]
+        pub fn set_serializer<G: RedisSerializer>(
+            self,
+            serializer: G,
+        ) -> AsyncRedisCacheBuilder<K, V, G> {
+            AsyncRedisCacheBuilder {
+                seconds: self.seconds,
+                refresh: self.refresh,
+                namespace: self.namespace,
+                prefix: self.prefix,
+                connection_string: self.connection_string,
+                cluster_nodes: self.cluster_nodes,
+                sentinel: self.sentinel,
+                client: self.client,
+                #[cfg(feature = "redis_connection_manager")]
+                connection_manager: self.connection_manager,
+                shared_connection: self.shared_connection,
+                #[cfg(feature = "redis_rustls")]
+                tls_certificates: self.tls_certificates,
+                invalidation_channel: self.invalidation_channel,
+                ttl_jitter: self.ttl_jitter,
+                serializer,
                 _phantom: PhantomData,
             }
         }
@@ -409,6 +1085,22 @@ mod async_redis {
             self
         }
 
+        /// Randomly move the TTL of each value set, earlier or later, by up to this fraction
+        /// of the lifespan, from `0.0` to `1.0`, so that values set together do not all
+        /// expire together
+        ///
+        /// # Panics
+        ///
+        /// Will panic if jitter is not between 0.0 and 1.0
+        #[must_use]
+        pub fn set_ttl_jitter(mut self, jitter: f64) -> Self {
+            if !(0.0..=1.0).contains(&jitter) {
+                panic!("`ttl_jitter` of the redis cache must be between 0.0 and 1.0.");
+            }
+            self.ttl_jitter = jitter;
+            self
+        }
+
         /// Set the namespace for cache keys. Defaults to `cached-redis-store:`.
         /// Used to generate keys formatted as: `{namespace}{prefix}{key}`
         /// Note that no delimiters are implicitly added so you may pass
@@ -436,6 +1128,113 @@ mod async_redis {
             self
         }
 
+        /// Connect with this client, e.g. the one already configured by the application
+        /// (auth, DB index, TLS...), instead of creating one from the connection string.
+        /// The connection string and TLS certificates are ignored.
+        #[must_use]
+        pub fn set_client(mut self, client: redis::Client) -> Self {
+            self.client = Some(client);
+            self
+        }
+
+        /// Share this connection manager, e.g. the one already used by the application,
+        /// instead of connecting with a new one. The client, connection string and TLS
+        /// certificates are ignored, except to subscribe to the invalidation channel
+        /// which needs a client or connection string.
+        #[cfg(feature = "redis_connection_manager")]
+        #[cfg_attr(docsrs, doc(cfg(feature = "redis_connection_manager")))]
+        #[must_use]
+        pub fn set_connection_manager(mut self, manager: redis::aio::ConnectionManager) -> Self {
+            self.connection_manager = Some(manager);
+            self
+        }
+
+        /// Share the connection of the other caches of the process built with a shared
+        /// connection to the same server (same connection info of the client), instead
+        /// of opening a new one, so that the number of connections does not grow with
+        /// the number of cached functions. Ignored with a cluster, sentinels or a
+        /// connection manager set.
+        #[must_use]
+        pub fn set_shared_connection(mut self, shared: bool) -> Self {
+            self.shared_connection = shared;
+            self
+        }
+
+        /// Connect to a Redis Cluster through these initial nodes (connection strings)
+        /// instead of a single server. Commands are routed to the node serving the hash
+        /// slot of their key. The connection string is ignored.
+        #[cfg(feature = "redis_cluster_async")]
+        #[cfg_attr(docsrs, doc(cfg(feature = "redis_cluster_async")))]
+        #[must_use]
+        pub fn set_cluster<I, S>(mut self, nodes: I) -> Self
+        where
+            I: IntoIterator<Item = S>,
+            S: AsRef<str>,
+        {
+            self.cluster_nodes = Some(nodes.into_iter().map(|n| n.as_ref().to_string()).collect());
+            self
+        }
+
+        /// Connect to the primary of the `service_name` master monitored by these sentinels
+        /// (connection strings) instead of a single server. When the primary fails over,
+        /// the cache asks the sentinels for the new primary and reconnects to it.
+        /// The connection string is ignored.
+        #[cfg(feature = "redis_sentinel")]
+        #[cfg_attr(docsrs, doc(cfg(feature = "redis_sentinel")))]
+        #[must_use]
+        pub fn set_sentinel<I, S, N>(mut self, sentinels: I, service_name: N) -> Self
+        where
+            I: IntoIterator<Item = S>,
+            S: AsRef<str>,
+            N: AsRef<str>,
+        {
+            self.sentinel = Some((
+                sentinels
+                    .into_iter()
+                    .map(|s| s.as_ref().to_string())
+                    .collect(),
+                service_name.as_ref().to_string(),
+            ));
+            self
+        }
+
+        /// Verify the certificate of the server with this PEM encoded CA certificate instead of
+        /// the platform's root certificates. TLS requires a `rediss://` connection string,
+        /// and is not used to connect through sentinels.
+        #[cfg(feature = "redis_rustls")]
+        #[cfg_attr(docsrs, doc(cfg(feature = "redis_rustls")))]
+        #[must_use]
+        pub fn set_tls_root_cert(mut self, root_cert: Vec<u8>) -> Self {
+            self.tls_certificates
+                .get_or_insert_with(no_tls_certificates)
+                .root_cert = Some(root_cert);
+            self
+        }
+
+        /// Authenticate with this PEM encoded client certificate and private key (mutual TLS).
+        /// TLS requires a `rediss://` connection string,
+        /// and is not used to connect through sentinels.
+        #[cfg(feature = "redis_rustls")]
+        #[cfg_attr(docsrs, doc(cfg(feature = "redis_rustls")))]
+        #[must_use]
+        pub fn set_tls_client_cert(mut self, client_cert: Vec<u8>, client_key: Vec<u8>) -> Self {
+            self.tls_certificates
+                .get_or_insert_with(no_tls_certificates)
+                .client_tls = Some(redis::ClientTlsConfig {
+                client_cert,
+                client_key,
+            });
+            self
+        }
+
+        /// Publish the keys removed from the cache on this channel, so that other processes
+        /// can drop them from their local caches, see `AsyncRedisCache::subscribe_invalidations`
+        #[must_use]
+        pub fn set_invalidation_channel<S: AsRef<str>>(mut self, channel: S) -> Self {
+            self.invalidation_channel = Some(channel.as_ref().to_string());
+            self
+        }
+
         /// Return the current connection string or load from the env var: `CACHED_REDIS_CONNECTION_STRING`
         ///
         /// # Errors
@@ -457,10 +1256,8 @@ mod async_redis {
         /// be used asynchronously by multiple futures.
         #[cfg(not(feature = "redis_connection_manager"))]
         async fn create_multiplexed_connection(
-            &self,
+            client: redis::Client,
         ) -> Result<redis::aio::MultiplexedConnection, RedisCacheBuildError> {
-            let s = self.connection_string()?;
-            let client = redis::Client::open(s)?;
             let conn = client.get_multiplexed_async_connection().await?;
             Ok(conn)
         }
@@ -470,30 +1267,150 @@ mod async_redis {
         /// necessary.
         #[cfg(feature = "redis_connection_manager")]
         async fn create_connection_manager(
-            &self,
+            client: redis::Client,
         ) -> Result<redis::aio::ConnectionManager, RedisCacheBuildError> {
-            let s = self.connection_string()?;
-            let client = redis::Client::open(s)?;
             let conn = redis::aio::ConnectionManager::new(client).await?;
             Ok(conn)
         }
 
+        /// Return the connection manager set, the shared connection to the server,
+        /// or a new connection
+        async fn create_single_connection(&self) -> Result<SingleConnection, RedisCacheBuildError> {
+            #[cfg(feature = "redis_connection_manager")]
+            if let Some(ref manager) = self.connection_manager {
+                return Ok(manager.clone());
+            }
+            let client = self.client()?;
+            if !self.shared_connection {
+                #[cfg(not(feature = "redis_connection_manager"))]
+                return Self::create_multiplexed_connection(client).await;
+                #[cfg(feature = "redis_connection_manager")]
+                return Self::create_connection_manager(client).await;
+            }
+
+            let key = format!("{:?}", client.get_connection_info());
+            let shared = SHARED_CONNECTIONS.lock().unwrap().get(&key).cloned();
+            if let Some(conn) = shared {
+                return Ok(conn);
+            }
+            #[cfg(not(feature = "redis_connection_manager"))]
+            let conn = Self::create_multiplexed_connection(client).await?;
+            #[cfg(feature = "redis_connection_manager")]
+            let conn = Self::create_connection_manager(client).await?;
+            // a cache built concurrently may have shared its connection in the meantime
+            let mut connections = SHARED_CONNECTIONS.lock().unwrap();
+            Ok(connections.entry(key).or_insert(conn).clone())
+        }
+
+        /// Return the client set, or create one connecting to the connection string
+        fn client(&self) -> Result<redis::Client, RedisCacheBuildError> {
+            match self.client {
+                Some(ref client) => Ok(client.clone()),
+                None => self.create_client(self.connection_string()?),
+            }
+        }
+
+        /// Return the address of the client set or the connection string, which
+        /// may not be known with a connection manager set
+        fn address(&self) -> Result<String, RedisCacheBuildError> {
+            if let Some(ref client) = self.client {
+                return Ok(client.get_connection_info().addr.to_string());
+            }
+            #[cfg(feature = "redis_connection_manager")]
+            if self.connection_manager.is_some() {
+                return Ok(self.connection_string.clone().unwrap_or_default());
+            }
+            self.connection_string()
+        }
+
+        /// Create a client, using the TLS certificates set
+        fn create_client(&self, s: String) -> Result<redis::Client, RedisCacheBuildError> {
+            #[cfg(feature = "redis_rustls")]
+            if let Some(ref certificates) = self.tls_certificates {
+                return Ok(redis::Client::build_with_tls(s, certificates.clone())?);
+            }
+            Ok(redis::Client::open(s)?)
+        }
+
+        /// Create the client subscribing to the invalidation channel. A cluster
+        /// broadcasts the messages published to all its nodes, so any node will do.
+        /// The primary of a master monitored by sentinels is not known in advance,
+        /// nor the server of a connection manager set without a client or connection string.
+        fn create_invalidation_client(
+            &self,
+        ) -> Result<Option<redis::Client>, RedisCacheBuildError> {
+            if self.invalidation_channel.is_none() || self.sentinel.is_some() {
+                return Ok(None);
+            }
+            #[cfg(feature = "redis_connection_manager")]
+            if self.connection_manager.is_some()
+                && self.client.is_none()
+                && self.connection_string.is_none()
+            {
+                return Ok(None);
+            }
+            match self.cluster_nodes.as_ref().and_then(|nodes| nodes.first()) {
+                Some(node) => self.create_client(node.clone()).map(Some),
+                None => self.client().map(Some),
+            }
+        }
+
+        #[cfg(feature = "redis_cluster_async")]
+        fn create_cluster_client(
+            &self,
+            nodes: &[String],
+        ) -> Result<redis::cluster::ClusterClient, RedisCacheBuildError> {
+            let builder = redis::cluster::ClusterClient::builder(nodes.to_vec());
+            #[cfg(feature = "redis_rustls")]
+            let builder = match self.tls_certificates {
+                Some(ref certificates) => builder.certs(certificates.clone()),
+                None => builder,
+            };
+            Ok(builder.build()?)
+        }
+
+        async fn create_connection(&self) -> Result<AsyncConnection, RedisCacheBuildError> {
+            #[cfg(feature = "redis_cluster_async")]
+            if let Some(ref nodes) = self.cluster_nodes {
+                let client = self.create_cluster_client(nodes)?;
+                return Ok(AsyncConnection::Cluster(
+                    client.get_async_connection().await?,
+                ));
+            }
+            #[cfg(feature = "redis_sentinel")]
+            if let Some((ref sentinels, ref service_name)) = self.sentinel {
+                let sentinel = SentinelConnection::new(sentinels.clone(), service_name.clone())?;
+                sentinel.reconnect().await?;
+                return Ok(AsyncConnection::Sentinel(Arc::new(sentinel)));
+            }
+            Ok(AsyncConnection::Single(Box::new(
+                self.create_single_connection().await?,
+            )))
+        }
+
         /// The last step in building a `RedisCache` is to call `build()`
         ///
         /// # Errors
         ///
         /// Will return a `RedisCacheBuildError`, depending on the error
-        pub async fn build(self) -> Result<AsyncRedisCache<K, V>, RedisCacheBuildError> {
+        pub async fn build(self) -> Result<AsyncRedisCache<K, V, F>, RedisCacheBuildError> {
             Ok(AsyncRedisCache {
                 seconds: self.seconds,
                 refresh: self.refresh,
-                connection_string: self.connection_string()?,
-                #[cfg(not(feature = "redis_connection_manager"))]
-                connection: self.create_multiplexed_connection().await?,
-                #[cfg(feature = "redis_connection_manager")]
-                connection: self.create_connection_manager().await?,
+                connection_string: match (&self.cluster_nodes, &self.sentinel) {
+                    (Some(nodes), _) => nodes.join(","),
+                    (None, Some((sentinels, _))) => sentinels.join(","),
+                    (None, None) => self.address()?,
+                },
+                connection: self.create_connection().await?,
+                invalidation_client: self.create_invalidation_client()?,
+                invalidation_channel: self.invalidation_channel,
+                ttl_jitter: self.ttl_jitter,
                 namespace: self.namespace,
                 prefix: self.prefix,
+                #[cfg(feature = "latency_stats")]
+                latency: LatencyStats::new(),
+                serializer: self.serializer,
                 _phantom: PhantomData,
             })
         }
@@ -503,20 +1420,99 @@ mod async_redis {
     ///
     /// Values have a ttl applied and enforced by redis.
     /// Uses a `redis::aio::MultiplexedConnection` or `redis::aio::ConnectionManager`
-    /// under the hood depending if feature `redis_connection_manager` is used or not.
-    pub struct AsyncRedisCache<K, V> {
+    /// under the hood depending if feature `redis_connection_manager` is used or not,
+    /// or a `redis::cluster_async::ClusterConnection` when connected to a cluster.
+    /// When connected through sentinels, a `redis::aio::MultiplexedConnection` to the
+    /// current primary is used and replaced when the primary fails over.
+    pub struct AsyncRedisCache<K, V, F = JsonSerializer> {
         pub(super) seconds: u64,
         pub(super) refresh: bool,
         pub(super) namespace: String,
         pub(super) prefix: String,
         connection_string: String,
-        #[cfg(not(feature = "redis_connection_manager"))]
-        connection: redis::aio::MultiplexedConnection,
-        #[cfg(feature = "redis_connection_manager")]
-        connection: redis::aio::ConnectionManager,
+        connection: AsyncConnection,
+        invalidation_channel: Option<String>,
+        invalidation_client: Option<redis::Client>,
+        ttl_jitter: f64,
+        #[cfg(feature = "latency_stats")]
+        latency: LatencyStats,
+        serializer: F,
         _phantom: PhantomData<(K, V)>,
     }
 
+    /// Connection to a single redis server or to a cluster
+    #[derive(Clone)]
+    enum AsyncConnection {
+        // boxed, the connection manager being much larger than the other connections
+        Single(Box<SingleConnection>),
+        #[cfg(feature = "redis_cluster_async")]
+        Cluster(redis::cluster_async::ClusterConnection),
+        #[cfg(feature = "redis_sentinel")]
+        Sentinel(Arc<SentinelConnection>),
+    }
+
+    /// Connection to the primary of a master monitored by sentinels
+    #[cfg(feature = "redis_sentinel")]
+    struct SentinelConnection {
+        client: tokio::sync::Mutex<redis::sentinel::SentinelClient>,
+        connection: std::sync::RwLock<Option<redis::aio::MultiplexedConnection>>,
+    }
+
+    #[cfg(feature = "redis_sentinel")]
+    impl SentinelConnection {
+        fn new(sentinels: Vec<String>, service_name: String) -> redis::RedisResult<Self> {
+            let client = redis::sentinel::SentinelClient::build(
+                sentinels,
+                service_name,
+                None,
+                redis::sentinel::SentinelServerType::Master,
+            )?;
+            Ok(Self {
+                client: tokio::sync::Mutex::new(client),
+                connection: std::sync::RwLock::new(None),
+            })
+        }
+
+        fn connection(&self) -> Option<redis::aio::MultiplexedConnection> {
+            self.connection
+                .read()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .clone()
+        }
+
+        /// Ask the sentinels for the current primary and connect to it
+        async fn reconnect(&self) -> redis::RedisResult<redis::aio::MultiplexedConnection> {
+            let conn = self.client.lock().await.get_async_connection().await?;
+            *self
+                .connection
+                .write()
+                .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(conn.clone());
+            Ok(conn)
+        }
+
+        async fn query<T: redis::FromRedisValue>(
+            &self,
+            pipe: &redis::Pipeline,
+        ) -> redis::RedisResult<T> {
+            let mut conn = match self.connection() {
+                Some(conn) => conn,
+                None => self.reconnect().await?,
+            };
+            match pipe.query_async(&mut conn).await {
+                // the primary is gone or was demoted to a replica: reconnect to the new primary
+                Err(e)
+                    if e.is_io_error()
+                        || e.is_connection_dropped()
+                        || e.is_connection_refusal()
+                        || e.kind() == redis::ErrorKind::ReadOnly =>
+                {
+                    pipe.query_async(&mut self.reconnect().await?).await
+                }
+                res => res,
+            }
+        }
+    }
+
     impl<K, V> AsyncRedisCache<K, V>
     where
         K: Display + Send + Sync,
@@ -527,103 +1523,326 @@ mod async_redis {
         pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> AsyncRedisCacheBuilder<K, V> {
             AsyncRedisCacheBuilder::new(prefix, seconds)
         }
+    }
 
+    impl<K, V, F> AsyncRedisCache<K, V, F>
+    where
+        K: Display + Send + Sync,
+        V: Serialize + DeserializeOwned + Send + Sync,
+        F: RedisSerializer + Send + Sync,
+    {
         fn generate_key(&self, key: &K) -> String {
             format!("{}{}{}", self.namespace, self.prefix, key)
         }
 
-        /// Return the redis connection string used
+        /// Return the redis connection string used, the address of the client set,
+        /// or the comma separated initial nodes of a cluster or sentinels
         #[must_use]
         pub fn connection_string(&self) -> String {
             self.connection_string.clone()
         }
+
+        async fn query<T: redis::FromRedisValue>(
+            &self,
+            pipe: &redis::Pipeline,
+        ) -> Result<T, RedisCacheError> {
+            let res = match self.connection.clone() {
+                AsyncConnection::Single(mut conn) => pipe.query_async(&mut *conn).await?,
+                #[cfg(feature = "redis_cluster_async")]
+                AsyncConnection::Cluster(mut conn) => pipe.query_async(&mut conn).await?,
+                #[cfg(feature = "redis_sentinel")]
+                AsyncConnection::Sentinel(sentinel) => sentinel.query(pipe).await?,
+            };
+            Ok(res)
+        }
+
+        /// Get the cached values of `keys`, in the same order, with a single `MGET`
+        ///
+        /// With Redis Cluster, the keys must hash to the same slot, e.g. by
+        /// putting a `{hash tag}` in the prefix.
+        pub async fn cache_get_many(&self, keys: &[K]) -> Result<Vec<Option<V>>, RedisCacheError> {
+            if keys.is_empty() {
+                return Ok(Vec::new());
+            }
+            let keys: Vec<String> = keys.iter().map(|key| self.generate_key(key)).collect();
+            let mut pipe = redis::pipe();
+            pipe.get(&keys);
+            if self.refresh {
+                for key in keys {
+                    pipe.expire(key, jittered_ttl(self.seconds, self.ttl_jitter) as i64)
+                        .ignore();
+                }
+            }
+            #[cfg(feature = "latency_stats")]
+            let round_trip = instant::Instant::now();
+            let res: (Vec<Option<Vec<u8>>>,) = self.query(&pipe).await?;
+            #[cfg(feature = "latency_stats")]
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            res.0
+                .into_iter()
+                .map(|value| {
+                    value
+                        .map(|value| CachedRedisValue::decode(&self.serializer, &value))
+                        .transpose()
+                })
+                .collect()
+        }
+
+        /// Set the cached values of many keys with a single pipeline. Unlike
+        /// `cache_set`, the previous values are not returned.
+        ///
+        /// With Redis Cluster, the keys must hash to the same slot, e.g. by
+        /// putting a `{hash tag}` in the prefix.
+        pub async fn cache_set_many(&self, values: Vec<(K, V)>) -> Result<(), RedisCacheError> {
+            if values.is_empty() {
+                return Ok(());
+            }
+            let mut pipe = redis::pipe();
+            for (key, val) in values {
+                pipe.set_ex::<String, Vec<u8>>(
+                    self.generate_key(&key),
+                    CachedRedisValue::encode(&self.serializer, val)?,
+                    jittered_ttl(self.seconds, self.ttl_jitter),
+                )
+                .ignore();
+            }
+            #[cfg(feature = "latency_stats")]
+            let round_trip = instant::Instant::now();
+            self.query::<()>(&pipe).await?;
+            #[cfg(feature = "latency_stats")]
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            Ok(())
+        }
+
+        /// Publish an invalidation on the invalidation channel, e.g. `Invalidation::Clear`
+        /// after removing the values of the cache by other means. Does nothing if no
+        /// invalidation channel is set.
+        pub async fn publish_invalidation(
+            &self,
+            invalidation: &Invalidation,
+        ) -> Result<(), RedisCacheError> {
+            let channel = match self.invalidation_channel {
+                Some(ref channel) => channel,
+                None => return Ok(()),
+            };
+            let mut pipe = redis::pipe();
+            pipe.publish(channel, invalidation.encode()).ignore();
+            self.query::<()>(&pipe).await
+        }
+
+        /// Remove all the values of the cache, see `RedisCache::cache_clear`
+        ///
+        /// # Errors
+        ///
+        /// Will return `RedisCacheError::ScanUnsupported` with Redis Cluster
+        pub async fn cache_clear(&self) -> Result<u64, RedisCacheError> {
+            let removed = self
+                .remove_matching(&scan_pattern(&self.namespace, &self.prefix, ""))
+                .await?;
+            self.publish_invalidation(&Invalidation::Clear).await?;
+            Ok(removed)
+        }
+
+        /// Remove the values of the keys starting with `key_prefix`, see
+        /// `RedisCache::cache_remove_prefix`
+        ///
+        /// # Errors
+        ///
+        /// Will return `RedisCacheError::ScanUnsupported` with Redis Cluster
+        pub async fn cache_remove_prefix(&self, key_prefix: &str) -> Result<u64, RedisCacheError> {
+            let removed = self
+                .remove_matching(&scan_pattern(&self.namespace, &self.prefix, key_prefix))
+                .await?;
+            self.publish_invalidation(&Invalidation::RemovePrefix(key_prefix.to_string()))
+                .await?;
+            Ok(removed)
+        }
+
+        async fn remove_matching(&self, pattern: &str) -> Result<u64, RedisCacheError> {
+            #[cfg(feature = "redis_cluster_async")]
+            if let AsyncConnection::Cluster(_) = self.connection {
+                return Err(RedisCacheError::ScanUnsupported);
+            }
+            let mut removed = 0;
+            let mut cursor = 0;
+            loop {
+                let ((next, keys),): ((u64, Vec<String>),) =
+                    self.query(&scan(cursor, pattern)).await?;
+                if !keys.is_empty() {
+                    let mut pipe = redis::pipe();
+                    pipe.del(keys);
+                    let (deleted,): (u64,) = self.query(&pipe).await?;
+                    removed += deleted;
+                }
+                if next == 0 {
+                    return Ok(removed);
+                }
+                cursor = next;
+            }
+        }
+
+        /// Subscribe to the invalidation channel with a dedicated connection
+        ///
+        /// # Errors
+        ///
+        /// Will return `RedisCacheError::NoInvalidationChannel` if no invalidation channel
+        /// is set, or if the cache is connected through sentinels: subscribe with
+        /// `AsyncRedisInvalidations::subscribe` instead.
+        pub async fn subscribe_invalidations(
+            &self,
+        ) -> Result<AsyncRedisInvalidations, RedisCacheError> {
+            match (&self.invalidation_client, &self.invalidation_channel) {
+                (Some(client), Some(channel)) => {
+                    AsyncRedisInvalidations::subscribe(client, channel).await
+                }
+                _ => Err(RedisCacheError::NoInvalidationChannel),
+            }
+        }
+    }
+
+    /// Asynchronous subscription to the invalidation channel of redis caches,
+    /// see `RedisInvalidations`
+    pub struct AsyncRedisInvalidations {
+        pubsub: redis::aio::PubSub,
+    }
+
+    impl AsyncRedisInvalidations {
+        /// Subscribe to an invalidation channel with a new connection of `client`
+        ///
+        /// # Errors
+        ///
+        /// Will return a `RedisCacheError::RedisCacheError` if the subscription fails
+        pub async fn subscribe(
+            client: &redis::Client,
+            channel: &str,
+        ) -> Result<Self, RedisCacheError> {
+            let mut pubsub = client.get_async_pubsub().await?;
+            pubsub.subscribe(channel).await?;
+            Ok(Self { pubsub })
+        }
+
+        /// Wait for the next invalidation
+        ///
+        /// # Errors
+        ///
+        /// Will return a `RedisCacheError::RedisCacheError` if the connection was closed
+        pub async fn recv(&mut self) -> Result<Invalidation, RedisCacheError> {
+            match self.pubsub.on_message().next().await {
+                Some(msg) => Invalidation::decode(msg.get_payload()?),
+                None => Err(redis::RedisError::from((
+                    redis::ErrorKind::IoError,
+                    "invalidation subscription closed",
+                ))
+                .into()),
+            }
+        }
     }
 
     #[async_trait]
-    impl<K, V> IOCachedAsync<K, V> for AsyncRedisCache<K, V>
+    impl<K, V, F> IOCachedAsync<K, V> for AsyncRedisCache<K, V, F>
     where
         K: Display + Send + Sync,
         V: Serialize + DeserializeOwned + Send + Sync,
+        F: RedisSerializer + Send + Sync,
     {
         type Error = RedisCacheError;
 
         /// Get a cached value
         async fn cache_get(&self, key: &K) -> Result<Option<V>, Self::Error> {
-            let mut conn = self.connection.clone();
+            #[cfg(feature = "latency_stats")]
+            let started = instant::Instant::now();
             let mut pipe = redis::pipe();
             let key = self.generate_key(key);
 
             pipe.get(key.clone());
             if self.refresh {
-                pipe.expire(key, self.seconds as i64).ignore();
-            }
-            let res: (Option<String>,) = pipe.query_async(&mut conn).await?;
-            match res.0 {
-                None => Ok(None),
-                Some(s) => {
-                    let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                        RedisCacheError::CacheDeserializationError {
-                            cached_value: s,
-                            error: e,
-                        }
-                    })?;
-                    Ok(Some(v.value))
-                }
+                pipe.expire(key, jittered_ttl(self.seconds, self.ttl_jitter) as i64)
+                    .ignore();
             }
+            #[cfg(feature = "latency_stats")]
+            let round_trip = instant::Instant::now();
+            let res: (Option<Vec<u8>>,) = self.query(&pipe).await?;
+            #[cfg(feature = "latency_stats")]
+            {
+                self.latency
+                    .record(Operation::Backend, round_trip.elapsed());
+                self.latency.record_get(res.0.is_some(), started.elapsed());
+            }
+            res.0
+                .map(|s| CachedRedisValue::decode(&self.serializer, &s))
+                .transpose()
         }
 
         /// Set a cached value
         async fn cache_set(&self, key: K, val: V) -> Result<Option<V>, Self::Error> {
-            let mut conn = self.connection.clone();
+            #[cfg(feature = "latency_stats")]
+            let started = instant::Instant::now();
             let mut pipe = redis::pipe();
             let key = self.generate_key(&key);
 
-            let val = CachedRedisValue::new(val);
             pipe.get(key.clone());
-            pipe.set_ex::<String, String>(
+            pipe.set_ex::<String, Vec<u8>>(
                 key,
-                serde_json::to_string(&val)
-                    .map_err(|e| RedisCacheError::CacheSerializationError { error: e })?,
-                self.seconds,
+                CachedRedisValue::encode(&self.serializer, val)?,
+                jittered_ttl(self.seconds, self.ttl_jitter),
             )
             .ignore();
 
-            let res: (Option<String>,) = pipe.query_async(&mut conn).await?;
-            match res.0 {
-                None => Ok(None),
-                Some(s) => {
-                    let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                        RedisCacheError::CacheDeserializationError {
-                            cached_value: s,
-                            error: e,
-                        }
-                    })?;
-                    Ok(Some(v.value))
-                }
+            #[cfg(feature = "latency_stats")]
+            let round_trip = instant::Instant::now();
+            let res: (Option<Vec<u8>>,) = self.query(&pipe).await?;
+            #[cfg(feature = "latency_stats")]
+            {
+                self.latency
+                    .record(Operation::Backend, round_trip.elapsed());
+                self.latency.record(Operation::Insert, started.elapsed());
             }
+            res.0
+                .map(|s| CachedRedisValue::decode(&self.serializer, &s))
+                .transpose()
         }
 
         /// Remove a cached value
+        /// Get the cached values of `keys` with a single `MGET`, see the inherent method
+        async fn cache_get_many(&self, keys: &[K]) -> Result<Vec<Option<V>>, Self::Error>
+        where
+            Self: Sync,
+            K: Sync,
+            V: Send,
+        {
+            AsyncRedisCache::cache_get_many(self, keys).await
+        }
+
+        /// Set the cached values with a single pipeline, see the inherent method
+        async fn cache_set_many(&self, values: Vec<(K, V)>) -> Result<(), Self::Error>
+        where
+            Self: Sync,
+            K: Send + 'async_trait,
+            V: Send + 'async_trait,
+        {
+            AsyncRedisCache::cache_set_many(self, values).await
+        }
+
         async fn cache_remove(&self, key: &K) -> Result<Option<V>, Self::Error> {
-            let mut conn = self.connection.clone();
+            #[cfg(feature = "latency_stats")]
+            let started = instant::Instant::now();
             let mut pipe = redis::pipe();
-            let key = self.generate_key(key);
+            let redis_key = self.generate_key(key);
 
-            pipe.get(key.clone());
-            pipe.del::<String>(key).ignore();
-            let res: (Option<String>,) = pipe.query_async(&mut conn).await?;
-            match res.0 {
-                None => Ok(None),
-                Some(s) => {
-                    let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                        RedisCacheError::CacheDeserializationError {
-                            cached_value: s,
-                            error: e,
-                        }
-                    })?;
-                    Ok(Some(v.value))
-                }
+            pipe.get(redis_key.clone());
+            pipe.del::<String>(redis_key).ignore();
+            if let Some(ref channel) = self.invalidation_channel {
+                pipe.publish(channel, Invalidation::Remove(key.to_string()).encode())
+                    .ignore();
             }
+            let res: (Option<Vec<u8>>,) = self.query(&pipe).await?;
+            #[cfg(feature = "latency_stats")]
+            self.latency.record(Operation::Backend, started.elapsed());
+            res.0
+                .map(|s| CachedRedisValue::decode(&self.serializer, &s))
+                .transpose()
         }
 
         /// Set the flag to control whether cache hits refresh the ttl of cached values, returns the old flag value
@@ -644,6 +1863,12 @@ mod async_redis {
             self.seconds = seconds;
             Some(old)
         }
+
+        /// Return latency histograms of this cache's operations
+        #[cfg(feature = "latency_stats")]
+        fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+            Some(self.latency.snapshot())
+        }
     }
 
     #[cfg(test)]
@@ -689,6 +1914,81 @@ mod async_redis {
             assert_eq!(c.cache_get(&1).await.unwrap().unwrap(), 100);
             assert_eq!(c.cache_get(&1).await.unwrap().unwrap(), 100);
         }
+
+        #[tokio::test]
+        async fn get_many_set_many() {
+            let c: AsyncRedisCache<u32, u32> =
+                AsyncRedisCache::new(format!("{}:async-redis-cache-test-many", now_millis()), 60)
+                    .build()
+                    .await
+                    .unwrap();
+
+            c.cache_set_many(vec![(1, 100), (3, 300)]).await.unwrap();
+            assert_eq!(
+                c.cache_get_many(&[1, 2, 3]).await.unwrap(),
+                vec![Some(100), None, Some(300)]
+            );
+        }
+
+        #[cfg(feature = "redis_connection_manager")]
+        #[tokio::test]
+        async fn shared_connection_manager() {
+            let client = redis::Client::open(std::env::var(ENV_KEY).unwrap()).unwrap();
+            let manager = redis::aio::ConnectionManager::new(client).await.unwrap();
+            let c: AsyncRedisCache<u32, u32> = AsyncRedisCache::new(
+                format!("{}:async-redis-cache-test-manager", now_millis()),
+                60,
+            )
+            .set_connection_manager(manager)
+            .build()
+            .await
+            .unwrap();
+
+            assert!(c.cache_set(1, 100).await.unwrap().is_none());
+            assert_eq!(c.cache_get(&1).await.unwrap(), Some(100));
+        }
+
+        #[tokio::test]
+        async fn shared_connection() {
+            let build = |prefix: &str| {
+                AsyncRedisCache::<u32, u32>::new(
+                    format!("{}:async-redis-cache-test-{}", now_millis(), prefix),
+                    60,
+                )
+                .set_shared_connection(true)
+                .build()
+            };
+            let c1 = build("shared-1").await.unwrap();
+            let c2 = build("shared-2").await.unwrap();
+            assert_eq!(SHARED_CONNECTIONS.lock().unwrap().len(), 1);
+
+            assert!(c1.cache_set(1, 100).await.unwrap().is_none());
+            assert!(c2.cache_set(1, 200).await.unwrap().is_none());
+            assert_eq!(c1.cache_get(&1).await.unwrap(), Some(100));
+            assert_eq!(c2.cache_get(&1).await.unwrap(), Some(200));
+        }
+
+        #[tokio::test]
+        async fn invalidations() {
+            let c: AsyncRedisCache<u32, u32> = AsyncRedisCache::new(
+                format!("{}:async-redis-cache-test-invalidations", now_millis()),
+                60,
+            )
+            .set_invalidation_channel(format!("{}:async-invalidations", now_millis()))
+            .build()
+            .await
+            .unwrap();
+            let mut invalidations = c.subscribe_invalidations().await.unwrap();
+
+            c.cache_set(1, 100).await.unwrap();
+            c.cache_remove(&1).await.unwrap();
+            c.publish_invalidation(&Invalidation::Clear).await.unwrap();
+            assert_eq!(
+                invalidations.recv().await.unwrap(),
+                Invalidation::Remove("1".to_string())
+            );
+            assert_eq!(invalidations.recv().await.unwrap(), Invalidation::Clear);
+        }
     }
 }
 
@@ -703,7 +2003,7 @@ mod async_redis {
         any(feature = "redis_async_std", feature = "redis_tokio")
     )))
 )]
-pub use async_redis::{AsyncRedisCache, AsyncRedisCacheBuilder};
+pub use async_redis::{AsyncRedisCache, AsyncRedisCacheBuilder, AsyncRedisInvalidations};
 
 #[cfg(test)]
 /// Cache store tests
@@ -764,4 +2064,140 @@ mod tests {
 
         assert_eq!(100, c.cache_remove(&1).unwrap().unwrap());
     }
+
+    #[test]
+    fn injected_client() {
+        let client = redis::Client::open(std::env::var(ENV_KEY).unwrap()).unwrap();
+        let address = client.get_connection_info().addr.to_string();
+        let c: RedisCache<u32, u32> =
+            RedisCache::new(format!("{}:redis-cache-test-client", now_millis()), 3600)
+                .set_connection_string("redis://ignored.invalid")
+                .set_client(client)
+                .build()
+                .unwrap();
+        assert_eq!(c.connection_string(), address);
+
+        assert!(c.cache_set(1, 100).unwrap().is_none());
+        assert_eq!(c.cache_get(&1).unwrap(), Some(100));
+    }
+
+    #[test]
+    fn clear_and_remove_prefix() {
+        let c: RedisCache<String, u32> =
+            RedisCache::new(format!("{}:redis-cache-test-clear:", now_millis()), 3600)
+                .build()
+                .unwrap();
+        c.cache_set("a:1".to_string(), 1).unwrap();
+        c.cache_set("a:2".to_string(), 2).unwrap();
+        c.cache_set("b:1".to_string(), 3).unwrap();
+
+        assert_eq!(c.cache_remove_prefix("a:").unwrap(), 2);
+        assert_eq!(c.cache_get(&"a:1".to_string()).unwrap(), None);
+        assert_eq!(c.cache_get(&"b:1".to_string()).unwrap(), Some(3));
+        assert_eq!(c.cache_clear().unwrap(), 1);
+        assert_eq!(c.cache_get(&"b:1".to_string()).unwrap(), None);
+    }
+
+    #[test]
+    fn serializers_round_trip() {
+        let json = CachedRedisValue::encode(&JsonSerializer, 1u32).unwrap();
+        assert_eq!(json, br#"{"value":1,"version":1}"#);
+        assert_eq!(
+            CachedRedisValue::<u32>::decode(&JsonSerializer, &json).unwrap(),
+            1
+        );
+        assert!(matches!(
+            CachedRedisValue::<u32>::decode(&JsonSerializer, b"1"),
+            Err(RedisCacheError::CacheDeserializationError { .. })
+        ));
+
+        #[cfg(feature = "redis_msgpack")]
+        {
+            let msgpack = CachedRedisValue::encode(&MessagePackSerializer, 1u32).unwrap();
+            assert!(msgpack.len() < json.len());
+            assert_eq!(
+                CachedRedisValue::<u32>::decode(&MessagePackSerializer, &msgpack).unwrap(),
+                1
+            );
+        }
+    }
+
+    #[cfg(feature = "redis_msgpack")]
+    #[test]
+    fn msgpack_cache() {
+        let c: RedisCache<u32, String, MessagePackSerializer> =
+            RedisCache::new(format!("{}:redis-cache-test-msgpack", now_millis()), 3600)
+                .set_serializer(MessagePackSerializer)
+                .build()
+                .unwrap();
+        c.cache_set(1, "a".to_string()).unwrap();
+        assert_eq!(c.cache_get(&1).unwrap(), Some("a".to_string()));
+    }
+
+    #[test]
+    fn ttl_jitter() {
+        assert_eq!(jittered_ttl(100, 0.0), 100);
+        let ttls: Vec<u64> = (0..20).map(|_| jittered_ttl(100, 0.2)).collect();
+        assert!(ttls.iter().all(|ttl| (80..=120).contains(ttl)));
+        assert!(ttls.iter().any(|ttl| *ttl != 100));
+        assert!(jittered_ttl(1, 1.0) >= 1);
+    }
+
+    #[test]
+    fn scan_patterns_escape_globs() {
+        assert_eq!(scan_pattern("ns:", "fn[1]:", "*?"), "ns:fn\\[1\\]:\\*\\?*");
+        assert_eq!(scan_pattern("", "", ""), "*");
+    }
+
+    #[test]
+    fn invalidations() {
+        let c: RedisCache<u32, u32> = RedisCache::new(
+            format!("{}:redis-cache-test-invalidations", now_millis()),
+            3600,
+        )
+        .set_invalidation_channel(format!("{}:invalidations", now_millis()))
+        .build()
+        .unwrap();
+        let mut invalidations = c.subscribe_invalidations().unwrap();
+        invalidations
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        c.cache_set(1, 100).unwrap();
+        c.cache_remove(&1).unwrap();
+        c.publish_invalidation(&Invalidation::Clear).unwrap();
+        assert_eq!(
+            invalidations.recv().unwrap(),
+            Invalidation::Remove("1".to_string())
+        );
+        assert_eq!(invalidations.recv().unwrap(), Invalidation::Clear);
+    }
+
+    #[test]
+    fn no_invalidation_channel() {
+        let c: RedisCache<u32, u32> = RedisCache::new("redis-cache-test-no-invalidations", 3600)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            c.subscribe_invalidations(),
+            Err(RedisCacheError::NoInvalidationChannel)
+        ));
+        // nothing is published
+        c.publish_invalidation(&Invalidation::Clear).unwrap();
+    }
+
+    #[test]
+    fn get_many_set_many() {
+        let c: RedisCache<u32, u32> =
+            RedisCache::new(format!("{}:redis-cache-test-many", now_millis()), 3600)
+                .build()
+                .unwrap();
+
+        c.cache_set_many(vec![(1, 100), (3, 300)]).unwrap();
+        assert_eq!(
+            c.cache_get_many(&[1, 2, 3]).unwrap(),
+            vec![Some(100), None, Some(300)]
+        );
+        assert!(c.cache_get_many(&[]).unwrap().is_empty());
+    }
 }
@@ -0,0 +1,469 @@
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
+use crate::IOCached;
+use directories::BaseDirs;
+use once_cell::sync::Lazy;
+use rocksdb::{
+    BoundColumnFamily, ColumnFamilyDescriptor, CompactionDecision, DBWithThreadMode, IteratorMode,
+    MultiThreaded, Options,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::UNIX_EPOCH;
+use std::{fmt::Display, path::PathBuf, time::SystemTime};
+
+type Db = DBWithThreadMode<MultiThreaded>;
+
+/// Databases currently opened by this process, keyed by their path.
+/// RocksDB only allows a single handle per database directory, so all
+/// `RocksDbCache`s sharing a directory share the handle and each get
+/// their own column family.
+static OPEN_DATABASES: Lazy<Mutex<HashMap<PathBuf, Weak<Db>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub struct RocksDbCacheBuilder<K, V> {
+    seconds: Option<u64>,
+    refresh: bool,
+    disk_dir: Option<PathBuf>,
+    cache_name: String,
+    _phantom: PhantomData<(K, V)>,
+}
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RocksDbCacheBuildError {
+    #[error("Storage connection error")]
+    ConnectionError(#[from] rocksdb::Error),
+}
+
+static ROCKSDB_FILE_PREFIX: &str = "cached_rocksdb_cache";
+const ROCKSDB_FILE_VERSION: u64 = 1;
+// Each stored value is prefixed with its expiry time so that the compaction
+// filter can drop expired entries without knowing how to deserialize them.
+const EXPIRY_PREFIX_LEN: usize = 8;
+const NEVER_EXPIRES: u64 = 0;
+
+impl<K, V> RocksDbCacheBuilder<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    /// Initialize a `RocksDbCacheBuilder`
+    pub fn new<S: AsRef<str>>(cache_name: S) -> RocksDbCacheBuilder<K, V> {
+        Self {
+            seconds: None,
+            refresh: false,
+            disk_dir: None,
+            cache_name: cache_name.as_ref().to_string(),
+            _phantom: Default::default(),
+        }
+    }
+
+    /// Specify the cache TTL/lifespan in seconds
+    pub fn set_lifespan(mut self, seconds: u64) -> Self {
+        self.seconds = Some(seconds);
+        self
+    }
+
+    /// Specify whether cache hits refresh the TTL
+    pub fn set_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Set the disk path for where the database will be stored.
+    /// Caches built with the same directory share a database, each
+    /// in a separate column family named after the cache.
+    pub fn set_disk_directory<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.disk_dir = Some(dir.as_ref().into());
+        self
+    }
+
+    fn default_disk_dir() -> PathBuf {
+        BaseDirs::new()
+            .map(|base_dirs| {
+                let exe_name = std::env::current_exe()
+                    .ok()
+                    .and_then(|path| {
+                        path.file_name()
+                            .and_then(|os_str| os_str.to_str().map(|s| format!("{}_", s)))
+                    })
+                    .unwrap_or_default();
+                let dir_prefix = format!("{}{}", exe_name, ROCKSDB_FILE_PREFIX);
+                base_dirs.cache_dir().join(dir_prefix)
+            })
+            .unwrap_or_else(|| {
+                std::env::current_dir()
+                    .expect("rocksdb cache unable to determine current directory")
+            })
+    }
+
+    fn column_family_options() -> Options {
+        let mut opts = Options::default();
+        opts.set_compaction_filter("cached_ttl", |_level: u32, _key: &[u8], value: &[u8]| {
+            if is_expired(expires_at(value), now_millis()) {
+                CompactionDecision::Remove
+            } else {
+                CompactionDecision::Keep
+            }
+        });
+        opts
+    }
+
+    fn open_database(path: &Path, cache_name: &str) -> Result<Arc<Db>, RocksDbCacheBuildError> {
+        let mut databases = OPEN_DATABASES.lock().unwrap();
+        if let Some(db) = databases.get(path).and_then(Weak::upgrade) {
+            if db.cf_handle(cache_name).is_none() {
+                db.create_cf(cache_name, &Self::column_family_options())?;
+            }
+            return Ok(db);
+        }
+
+        let mut db_opts = Options::default();
+        db_opts.create_if_missing(true);
+        db_opts.create_missing_column_families(true);
+
+        // every existing column family must be opened along with the database
+        let mut names = Db::list_cf(&db_opts, path)
+            .unwrap_or_else(|_| vec![rocksdb::DEFAULT_COLUMN_FAMILY_NAME.to_string()]);
+        if !names.iter().any(|name| name == cache_name) {
+            names.push(cache_name.to_string());
+        }
+        let descriptors = names
+            .into_iter()
+            .map(|name| ColumnFamilyDescriptor::new(name, Self::column_family_options()));
+
+        let db = Arc::new(Db::open_cf_descriptors(&db_opts, path, descriptors)?);
+        databases.insert(path.to_path_buf(), Arc::downgrade(&db));
+        Ok(db)
+    }
+
+    pub fn build(self) -> Result<RocksDbCache<K, V>, RocksDbCacheBuildError> {
+        let disk_dir = self.disk_dir.unwrap_or_else(|| Self::default_disk_dir());
+        let disk_path = disk_dir.join(format!("rocksdb_v{}", ROCKSDB_FILE_VERSION));
+        let connection = Self::open_database(&disk_path, &self.cache_name)?;
+
+        Ok(RocksDbCache {
+            seconds: self.seconds,
+            refresh: self.refresh,
+            version: ROCKSDB_FILE_VERSION,
+            disk_path,
+            column_family: self.cache_name,
+            connection,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            _phantom: self._phantom,
+        })
+    }
+}
+
+/// Cache store backed by RocksDB
+///
+/// Every cache is stored in its own column family (named after the cache)
+/// of a database that may be shared with other caches using the same directory.
+/// Expired values are never returned and are dropped from disk by a
+/// compaction filter when RocksDB compacts the files holding them.
+pub struct RocksDbCache<K, V> {
+    pub(super) seconds: Option<u64>,
+    pub(super) refresh: bool,
+    #[allow(unused)]
+    version: u64,
+    #[allow(unused)]
+    disk_path: PathBuf,
+    column_family: String,
+    connection: Arc<Db>,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> RocksDbCache<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    #[allow(clippy::new_ret_no_self)]
+    /// Initialize a `RocksDbCacheBuilder`
+    pub fn new(cache_name: &str) -> RocksDbCacheBuilder<K, V> {
+        RocksDbCacheBuilder::new(cache_name)
+    }
+
+    /// Return the name of the column family holding this cache's values
+    #[must_use]
+    pub fn column_family(&self) -> &str {
+        &self.column_family
+    }
+
+    /// Remove expired values now instead of waiting for them to be compacted away
+    pub fn remove_expired_entries(&self) -> Result<(), RocksDbCacheError> {
+        let cf = self.cf()?;
+        let now = now_millis();
+        for item in self.connection.iterator_cf(&cf, IteratorMode::Start) {
+            let (key, value) = item?;
+            if is_expired(expires_at(&value), now) {
+                self.connection.delete_cf(&cf, key)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn cf(&self) -> Result<Arc<BoundColumnFamily<'_>>, RocksDbCacheError> {
+        self.connection
+            .cf_handle(&self.column_family)
+            .ok_or_else(|| RocksDbCacheError::MissingColumnFamily(self.column_family.clone()))
+    }
+
+    fn new_expiry(&self) -> u64 {
+        match self.seconds {
+            Some(seconds) => now_millis().saturating_add(seconds.saturating_mul(1000)),
+            None => NEVER_EXPIRES,
+        }
+    }
+
+    fn encode(&self, value: &V) -> Result<Vec<u8>, RocksDbCacheError> {
+        let mut data = self.new_expiry().to_be_bytes().to_vec();
+        rmp_serde::encode::write(&mut data, value)?;
+        Ok(data)
+    }
+
+    /// Decode a stored value, returning `None` if it has expired
+    fn decode(data: &[u8]) -> Result<Option<V>, RocksDbCacheError> {
+        if data.len() < EXPIRY_PREFIX_LEN {
+            return Err(RocksDbCacheError::CorruptedValue);
+        }
+        if is_expired(expires_at(data), now_millis()) {
+            return Ok(None);
+        }
+        Ok(Some(rmp_serde::from_slice(&data[EXPIRY_PREFIX_LEN..])?))
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn expires_at(data: &[u8]) -> u64 {
+    data.get(..EXPIRY_PREFIX_LEN)
+        .and_then(|prefix| prefix.try_into().ok())
+        .map(u64::from_be_bytes)
+        .unwrap_or(NEVER_EXPIRES)
+}
+
+fn is_expired(expires_at: u64, now: u64) -> bool {
+    expires_at != NEVER_EXPIRES && expires_at <= now
+}
+
+#[derive(Error, Debug)]
+pub enum RocksDbCacheError {
+    #[error("Storage error")]
+    StorageError(#[from] rocksdb::Error),
+    #[error("Column family {0:?} is missing")]
+    MissingColumnFamily(String),
+    #[error("Cached value is missing its expiry header")]
+    CorruptedValue,
+    #[error("Error deserializing cached value")]
+    CacheDeserializtionError(#[from] rmp_serde::decode::Error),
+    #[error("Error serializing cached value")]
+    CacheSerializtionError(#[from] rmp_serde::encode::Error),
+}
+
+impl<K, V> IOCached<K, V> for RocksDbCache<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    type Error = RocksDbCacheError;
+
+    fn cache_get(&self, key: &K) -> Result<Option<V>, RocksDbCacheError> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let cf = self.cf()?;
+        let key = key.to_string();
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let data = self.connection.get_cf(&cf, &key)?;
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record_get(data.is_some(), started.elapsed());
+        }
+        let data = match data {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        match Self::decode(&data)? {
+            Some(value) => {
+                if self.refresh && self.seconds.is_some() {
+                    let mut refreshed = data;
+                    refreshed[..EXPIRY_PREFIX_LEN]
+                        .copy_from_slice(&self.new_expiry().to_be_bytes());
+                    self.connection.put_cf(&cf, &key, refreshed)?;
+                }
+                Ok(Some(value))
+            }
+            None => {
+                self.connection.delete_cf(&cf, &key)?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn cache_set(&self, key: K, value: V) -> Result<Option<V>, RocksDbCacheError> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let cf = self.cf()?;
+        let key = key.to_string();
+        let data = self.encode(&value)?;
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let old = self.connection.get_cf(&cf, &key)?;
+        self.connection.put_cf(&cf, &key, data)?;
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record(Operation::Insert, started.elapsed());
+        }
+        match old {
+            Some(old) => Self::decode(&old),
+            None => Ok(None),
+        }
+    }
+
+    fn cache_remove(&self, key: &K) -> Result<Option<V>, RocksDbCacheError> {
+        let cf = self.cf()?;
+        let key = key.to_string();
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let old = self.connection.get_cf(&cf, &key)?;
+        self.connection.delete_cf(&cf, &key)?;
+        #[cfg(feature = "latency_stats")]
+        self.latency
+            .record(Operation::Backend, round_trip.elapsed());
+        match old {
+            Some(old) => Self::decode(&old),
+            None => Ok(None),
+        }
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.seconds
+    }
+
+    /// Set the lifespan of cached values, returns the old value.
+    /// Only values written after the change use the new lifespan.
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let old = self.seconds;
+        self.seconds = Some(seconds);
+        old
+    }
+
+    fn cache_set_refresh(&mut self, refresh: bool) -> bool {
+        let old = self.refresh;
+        self.refresh = refresh;
+        old
+    }
+
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod test_RocksDbCache {
+    use googletest::{
+        assert_that,
+        matchers::{eq, none, ok, some},
+        GoogleTestSupport as _,
+    };
+    use std::thread::sleep;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    macro_rules! temp_dir {
+        () => {
+            TempDir::new().expect("Error creating temp dir")
+        };
+    }
+
+    const TEST_KEY: u32 = 1;
+    const TEST_VAL: u32 = 100;
+    const TEST_VAL_1: u32 = 200;
+    const LIFE_SPAN_2_SECS: u64 = 2;
+
+    #[test]
+    fn cache_get_after_cache_remove_returns_none() {
+        let tmp_dir = temp_dir!();
+        let cache: RocksDbCache<u32, u32> = RocksDbCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_get(&TEST_KEY), ok(none()));
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        assert_that!(
+            cache.cache_set(TEST_KEY, TEST_VAL_1),
+            ok(some(eq(TEST_VAL))),
+            "Setting an existing key-value should return the old value"
+        );
+        assert_that!(cache.cache_get(&TEST_KEY), ok(some(eq(TEST_VAL_1))));
+        assert_that!(cache.cache_remove(&TEST_KEY), ok(some(eq(TEST_VAL_1))));
+        assert_that!(
+            cache.cache_get(&TEST_KEY),
+            ok(none()),
+            "Getting a removed key should return None"
+        );
+    }
+
+    #[test]
+    fn values_expire_when_lifespan_elapses_returning_none() {
+        let tmp_dir = temp_dir!();
+        let cache: RocksDbCache<u32, u32> = RocksDbCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .set_lifespan(LIFE_SPAN_2_SECS)
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        assert_that!(cache.cache_get(&TEST_KEY), ok(some(eq(TEST_VAL))));
+
+        sleep(Duration::from_secs(LIFE_SPAN_2_SECS));
+        sleep(Duration::from_micros(500));
+        assert_that!(
+            cache.cache_get(&TEST_KEY),
+            ok(none()),
+            "Getting an expired key-value should return None"
+        );
+    }
+
+    #[test]
+    fn caches_sharing_a_directory_are_isolated_by_column_family() {
+        let tmp_dir = temp_dir!();
+        let first: RocksDbCache<u32, u32> = RocksDbCache::new("first")
+            .set_disk_directory(tmp_dir.path())
+            .build()
+            .unwrap();
+        let second: RocksDbCache<u32, u32> = RocksDbCache::new("second")
+            .set_disk_directory(tmp_dir.path())
+            .build()
+            .unwrap();
+
+        assert_that!(first.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        assert_that!(second.cache_set(TEST_KEY, TEST_VAL_1), ok(none()));
+        assert_that!(first.cache_get(&TEST_KEY), ok(some(eq(TEST_VAL))));
+        assert_that!(second.cache_get(&TEST_KEY), ok(some(eq(TEST_VAL_1))));
+    }
+}
@@ -0,0 +1,461 @@
+use super::{random_fraction, CacheBuildError, Cached, CachedRead, CachedWrite};
+use instant::{Duration, Instant};
+use std::borrow::Borrow;
+use std::cmp::Eq;
+use std::hash::Hash;
+
+#[cfg(feature = "ahash")]
+use hashbrown::HashMap;
+
+#[cfg(not(feature = "ahash"))]
+use std::collections::HashMap;
+
+/// The number of values scored to pick the one to evict, by default
+const DEFAULT_SAMPLES: usize = 5;
+
+/// What a `ScoredCache` knows of a value, passed to its scorer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryMeta {
+    /// When the value was set
+    pub set_at: Instant,
+    /// When the value was last read or set
+    pub accessed_at: Instant,
+    /// The number of times the value was read since it was set
+    pub hits: u64,
+}
+
+impl EntryMeta {
+    fn new(now: Instant) -> EntryMeta {
+        EntryMeta {
+            set_at: now,
+            accessed_at: now,
+            hits: 0,
+        }
+    }
+
+    /// Return the time elapsed since the value was set
+    #[must_use]
+    pub fn age(&self) -> Duration {
+        self.set_at.elapsed()
+    }
+
+    /// Return the time elapsed since the value was last read or set
+    #[must_use]
+    pub fn idle(&self) -> Duration {
+        self.accessed_at.elapsed()
+    }
+}
+
+/// Scored / `Sized` Cache
+///
+/// Evicts, when full, the value with the lowest score among a few values sampled at
+/// random (5 by default), as scored by a function of the key, the value and its
+/// `EntryMeta`. The score can combine the recency and frequency of use of the values
+/// with signals of the application, e.g. the cost of computing a value or the priority
+/// of a customer. Sampling keeps evictions constant time, at the price of not always
+/// evicting the lowest score of the whole cache: more samples evict closer to it.
+///
+/// Note: This cache is in-memory only
+///
+/// ```rust
+/// use cached::stores::{EntryMeta, ScoredCache};
+/// use cached::{Cached, CachedWrite};
+///
+/// // keep the values of premium customers, then the most used ones
+/// let score = |key: &(bool, u32), _: &String, meta: EntryMeta| {
+///     if key.0 { f64::MAX } else { meta.hits as f64 }
+/// };
+/// let mut cache = ScoredCache::with_size(3, score);
+/// cache.cache_set((true, 1), "premium".to_string());
+/// cache.cache_set((false, 2), "hot".to_string());
+/// cache.cache_get(&(false, 2));
+/// cache.cache_set((false, 3), "cold".to_string());
+/// cache.cache_set((false, 4), "new".to_string());
+///
+/// assert!(cache.cache_get(&(true, 1)).is_some());
+/// assert!(cache.cache_get(&(false, 2)).is_some());
+/// assert!(cache.cache_get(&(false, 3)).is_none());
+/// ```
+#[derive(Clone)]
+pub struct ScoredCache<K, V, F> {
+    // the values, in no order, sampled by their index
+    pub(super) entries: Vec<(K, V, EntryMeta)>,
+    pub(super) index: HashMap<K, usize>,
+    pub(super) capacity: usize,
+    pub(super) samples: usize,
+    pub(super) scorer: F,
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+    pub(super) evictions: u64,
+}
+
+impl<K, V, F> std::fmt::Debug for ScoredCache<K, V, F>
+where
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScoredCache")
+            .field("entries", &self.entries)
+            .field("capacity", &self.capacity)
+            .field("samples", &self.samples)
+            .field("hits", &self.hits)
+            .field("misses", &self.misses)
+            .field("evictions", &self.evictions)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, V, F> ScoredCache<K, V, F>
+where
+    K: Hash + Eq + Clone,
+    F: Fn(&K, &V, EntryMeta) -> f64,
+{
+    /// Creates a new `ScoredCache` with a given size limit, evicting the lowest score
+    /// of `scorer` among the sampled values
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0, see `try_with_size`
+    #[must_use]
+    pub fn with_size(size: usize, scorer: F) -> ScoredCache<K, V, F> {
+        Self::try_with_size(size, scorer).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Creates a new `ScoredCache` with a given size limit, evicting the lowest score
+    /// of `scorer` among the sampled values
+    ///
+    /// # Errors
+    ///
+    /// Will return a `CacheBuildError` if size is 0
+    pub fn try_with_size(size: usize, scorer: F) -> Result<ScoredCache<K, V, F>, CacheBuildError> {
+        if size == 0 {
+            return Err(CacheBuildError::zero("ScoredCache", "size"));
+        }
+        Ok(ScoredCache {
+            entries: Vec::new(),
+            index: HashMap::new(),
+            capacity: size,
+            samples: DEFAULT_SAMPLES,
+            scorer,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        })
+    }
+
+    /// Set the number of values sampled and scored to pick the one to evict, at least 1.
+    /// Sampling as many values as the capacity scores all of them.
+    #[must_use]
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// Return the number of values sampled and scored to pick the one to evict
+    #[must_use]
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Return the number of values evicted
+    #[must_use]
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// Release the memory the cache keeps beyond its values, e.g. after most of them
+    /// were removed
+    pub fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit();
+        self.index.shrink_to_fit();
+    }
+
+    /// Return the metadata of a value, without counting a hit
+    pub fn entry_meta<Q>(&self, key: &Q) -> Option<EntryMeta>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.index.get(key).map(|&i| self.entries[i].2)
+    }
+
+    /// Return the index of the sampled value with the lowest score, other than the
+    /// value at `keep`
+    fn victim(&self, keep: usize) -> Option<usize> {
+        let len = self.entries.len();
+        if len < 2 {
+            return None;
+        }
+        // score all the values when there are fewer than the samples
+        let sampled = self.samples < len - 1;
+        (0..if sampled { self.samples } else { len })
+            .map(|n| {
+                if sampled {
+                    ((random_fraction() * len as f64) as usize).min(len - 1)
+                } else {
+                    n
+                }
+            })
+            .filter(|&i| i != keep)
+            .map(|i| {
+                let (key, value, meta) = &self.entries[i];
+                ((self.scorer)(key, value, *meta), i)
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, i)| i)
+            // every sample was the kept value
+            .or(Some(usize::from(keep == 0)))
+    }
+
+    /// Remove the value at index `i`, moving the last value in its place
+    fn remove_at(&mut self, i: usize) -> (K, V, EntryMeta) {
+        let removed = self.entries.swap_remove(i);
+        self.index.remove(&removed.0);
+        if let Some((moved, _, _)) = self.entries.get(i) {
+            if let Some(index) = self.index.get_mut(moved) {
+                *index = i;
+            }
+        }
+        removed
+    }
+
+    /// Record a hit of the value at index `i`
+    fn hit(&mut self, i: usize) {
+        self.hits += 1;
+        let meta = &mut self.entries[i].2;
+        meta.hits += 1;
+        meta.accessed_at = Instant::now();
+    }
+}
+
+impl<K, V, F> Cached<K, V> for ScoredCache<K, V, F>
+where
+    K: Hash + Eq + Clone,
+    F: Fn(&K, &V, EntryMeta) -> f64,
+{
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache_get_key_value(key).map(|(_, value)| value)
+    }
+
+    fn cache_get_key_value<Q>(&mut self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.index.get(key).copied() {
+            Some(i) => {
+                self.hit(i);
+                let (key, value, _) = &self.entries[i];
+                Some((key, value))
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.index.get(key).copied() {
+            Some(i) => {
+                self.hit(i);
+                Some(&mut self.entries[i].1)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_drain_where<G: FnMut(&K, &V) -> bool>(
+        &mut self,
+        mut drain: G,
+    ) -> impl Iterator<Item = (K, V)> {
+        let mut drained = Vec::new();
+        // the last values, moved in place of the removed ones, were already looked at
+        for i in (0..self.entries.len()).rev() {
+            let (key, value, _) = &self.entries[i];
+            if drain(key, value) {
+                let (key, value, _) = self.remove_at(i);
+                drained.push((key, value));
+            }
+        }
+        drained.into_iter()
+    }
+
+    fn cache_get_or_set_with<G: FnOnce() -> V>(&mut self, key: K, f: G) -> &mut V {
+        let i = match self.index.get(&key).copied() {
+            Some(i) => {
+                self.hit(i);
+                i
+            }
+            None => {
+                self.misses += 1;
+                self.cache_set(key.clone(), f());
+                self.index[&key]
+            }
+        };
+        &mut self.entries[i].1
+    }
+}
+
+impl<K, V, F> CachedRead<K, V> for ScoredCache<K, V, F>
+where
+    K: Hash + Eq + Clone,
+    F: Fn(&K, &V, EntryMeta) -> f64,
+{
+    fn cache_size(&self) -> usize {
+        self.entries.len()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.index.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.entries.iter().map(|(key, value, _)| (key, value))
+    }
+}
+
+impl<K, V, F> CachedWrite<K, V> for ScoredCache<K, V, F>
+where
+    K: Hash + Eq + Clone,
+    F: Fn(&K, &V, EntryMeta) -> f64,
+{
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        let now = Instant::now();
+        if let Some(&i) = self.index.get(&key) {
+            let (_, value, meta) = &mut self.entries[i];
+            *meta = EntryMeta::new(now);
+            return Some(std::mem::replace(value, val));
+        }
+        self.index.insert(key.clone(), self.entries.len());
+        self.entries.push((key, val, EntryMeta::new(now)));
+        if self.entries.len() > self.capacity {
+            if let Some(victim) = self.victim(self.entries.len() - 1) {
+                self.remove_at(victim);
+                self.evictions += 1;
+            }
+        }
+        None
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.index.get(key).copied()?;
+        Some(self.remove_at(i).1)
+    }
+
+    fn cache_clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.entries = Vec::new();
+        self.index = HashMap::new();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lowest_score_is_evicted_when_all_values_are_sampled() {
+        let mut c = ScoredCache::with_size(3, |key: &u32, _: &u32, meta: EntryMeta| {
+            f64::from(*key) + meta.hits as f64 * 10.0
+        })
+        .with_samples(3);
+        c.cache_set(1, 1);
+        c.cache_set(2, 2);
+        c.cache_set(3, 3);
+        assert_eq!(c.cache_get(&1), Some(&1));
+
+        // 1 is scored 11 with its hit, 2 goes
+        assert_eq!(c.cache_set(4, 4), None);
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.evictions(), 1);
+        assert_eq!(c.cache_get(&2), None);
+        assert_eq!(c.entry_meta(&1).map(|meta| meta.hits), Some(1));
+
+        // the value just set is never evicted, even with the lowest score
+        c.cache_set(0, 0);
+        assert_eq!(c.cache_get(&0), Some(&0));
+        assert_eq!(c.cache_get(&3), None);
+
+        assert_eq!(c.cache_remove(&1), Some(1));
+        assert_eq!(*c.cache_get_or_set_with(5, || 5), 5);
+        assert_eq!(c.cache_get_mut(&4).map(|v| *v), Some(4));
+        assert_eq!((c.cache_hits(), c.cache_misses()), (Some(3), Some(3)));
+        assert_eq!(c.cache_set(5, 50), Some(5));
+        assert_eq!(c.entry_meta(&5).map(|meta| meta.hits), Some(0));
+    }
+
+    #[test]
+    fn sampled_evictions_keep_the_index_consistent() {
+        let mut c = ScoredCache::with_size(10, |key: &u32, _: &u32, _| f64::from(*key));
+        for i in 0..1000 {
+            c.cache_set(i, i);
+            assert!(c.cache_size() <= 10);
+        }
+        assert_eq!(c.evictions(), 990);
+        for (i, (key, _, _)) in c.entries.iter().enumerate() {
+            assert_eq!(c.index[key], i);
+        }
+        assert_eq!(c.cache_get(&999), Some(&999));
+        assert!(ScoredCache::<u32, u32, _>::try_with_size(0, |_: &u32, _: &u32, _| 0.0).is_err());
+    }
+
+    #[test]
+    fn drain_keeps_the_index_consistent() {
+        let mut c = ScoredCache::with_size(10, |key: &u32, _: &u32, _| f64::from(*key));
+        for i in 0..10 {
+            c.cache_set(i, i * 10);
+        }
+        let mut odd: Vec<_> = c.cache_drain_where(|key, _| key % 2 == 1).collect();
+        odd.sort_unstable();
+        assert_eq!(odd, [(1, 10), (3, 30), (5, 50), (7, 70), (9, 90)]);
+        for (i, (key, _, _)) in c.entries.iter().enumerate() {
+            assert_eq!(c.index[key], i);
+        }
+        assert_eq!(c.cache_drain().count(), 5);
+        assert_eq!(c.cache_size(), 0);
+    }
+}
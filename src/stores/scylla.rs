@@ -0,0 +1,469 @@
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
+use crate::IOCachedAsync;
+use async_trait::async_trait;
+use scylla::prepared_statement::PreparedStatement;
+use scylla::statement::Consistency;
+use scylla::transport::errors::{NewSessionError, QueryError};
+use scylla::transport::query_result::MaybeFirstRowTypedError;
+use scylla::{Session, SessionBuilder};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+pub struct ScyllaCacheBuilder<K, V> {
+    seconds: u64,
+    refresh: bool,
+    namespace: String,
+    prefix: String,
+    known_nodes: Option<Vec<String>>,
+    session: Option<Arc<Session>>,
+    keyspace: String,
+    table: String,
+    consistency: Consistency,
+    create_table: bool,
+    _phantom: PhantomData<(K, V)>,
+}
+
+const ENV_KEY: &str = "CACHED_SCYLLA_NODES";
+const DEFAULT_NAMESPACE: &str = "cached-scylla-store:";
+const DEFAULT_KEYSPACE: &str = "cached";
+const DEFAULT_TABLE: &str = "cached_values";
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ScyllaCacheBuildError {
+    #[error("scylla connection error")]
+    Connection(#[from] NewSessionError),
+    #[error("scylla query error")]
+    Query(#[from] QueryError),
+    #[error("Known nodes not specified or invalid in env var {env_key:?}: {error:?}")]
+    MissingKnownNodes {
+        env_key: String,
+        error: std::env::VarError,
+    },
+}
+
+impl<K, V> ScyllaCacheBuilder<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Initialize a `ScyllaCacheBuilder`
+    pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> ScyllaCacheBuilder<K, V> {
+        Self {
+            seconds,
+            refresh: false,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            prefix: prefix.as_ref().to_string(),
+            known_nodes: None,
+            session: None,
+            keyspace: DEFAULT_KEYSPACE.to_string(),
+            table: DEFAULT_TABLE.to_string(),
+            consistency: Consistency::LocalQuorum,
+            create_table: true,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Specify the cache TTL/lifespan in seconds
+    #[must_use]
+    pub fn set_lifespan(mut self, seconds: u64) -> Self {
+        self.seconds = seconds;
+        self
+    }
+
+    /// Specify whether cache hits refresh the TTL
+    #[must_use]
+    pub fn set_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Set the namespace for cache keys. Defaults to `cached-scylla-store:`.
+    /// Used to generate keys formatted as: `{namespace}{prefix}{key}`
+    /// Note that no delimiters are implicitly added so you may pass
+    /// an empty string if you want there to be no namespace on keys.
+    #[must_use]
+    pub fn set_namespace<S: AsRef<str>>(mut self, namespace: S) -> Self {
+        self.namespace = namespace.as_ref().to_string();
+        self
+    }
+
+    /// Set the prefix for cache keys.
+    /// Used to generate keys formatted as: `{namespace}{prefix}{key}`
+    /// Note that no delimiters are implicitly added so you may pass
+    /// an empty string if you want there to be no prefix on keys.
+    #[must_use]
+    pub fn set_prefix<S: AsRef<str>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.as_ref().to_string();
+        self
+    }
+
+    /// Set the addresses (`host:port`) of the nodes to connect to
+    #[must_use]
+    pub fn set_known_nodes<I, S>(mut self, known_nodes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.known_nodes = Some(
+            known_nodes
+                .into_iter()
+                .map(|node| node.as_ref().to_string())
+                .collect(),
+        );
+        self
+    }
+
+    /// Use an already connected session, which may be shared with other caches
+    #[must_use]
+    pub fn set_session(mut self, session: Arc<Session>) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    /// Set the keyspace of the table holding the cached values. Defaults to `cached`.
+    /// The keyspace is not created by the cache, its replication is up to you.
+    #[must_use]
+    pub fn set_keyspace<S: AsRef<str>>(mut self, keyspace: S) -> Self {
+        self.keyspace = keyspace.as_ref().to_string();
+        self
+    }
+
+    /// Set the table holding the cached values. Defaults to `cached_values`.
+    #[must_use]
+    pub fn set_table<S: AsRef<str>>(mut self, table: S) -> Self {
+        self.table = table.as_ref().to_string();
+        self
+    }
+
+    /// Set the consistency level of reads and writes. Defaults to `LocalQuorum`.
+    #[must_use]
+    pub fn set_consistency(mut self, consistency: Consistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Specify whether the table is created, if it does not exist yet, when building the cache.
+    /// Defaults to `true`.
+    #[must_use]
+    pub fn set_create_table(mut self, create_table: bool) -> Self {
+        self.create_table = create_table;
+        self
+    }
+
+    /// Return the current known nodes or load them from the comma separated
+    /// env var: `CACHED_SCYLLA_NODES`
+    ///
+    /// # Errors
+    ///
+    /// Will return `ScyllaCacheBuildError::MissingKnownNodes` if the known nodes are not set
+    pub fn known_nodes(&self) -> Result<Vec<String>, ScyllaCacheBuildError> {
+        match self.known_nodes {
+            Some(ref known_nodes) => Ok(known_nodes.clone()),
+            None => std::env::var(ENV_KEY)
+                .map(|nodes| {
+                    nodes
+                        .split(',')
+                        .map(|node| node.trim().to_string())
+                        .collect()
+                })
+                .map_err(|e| ScyllaCacheBuildError::MissingKnownNodes {
+                    env_key: ENV_KEY.to_string(),
+                    error: e,
+                }),
+        }
+    }
+
+    async fn prepare(
+        &self,
+        session: &Session,
+        query: String,
+    ) -> Result<PreparedStatement, ScyllaCacheBuildError> {
+        let mut statement = session.prepare(query).await?;
+        statement.set_consistency(self.consistency);
+        Ok(statement)
+    }
+
+    /// The last step in building a `ScyllaCache` is to call `build()`
+    ///
+    /// # Errors
+    ///
+    /// Will return a `ScyllaCacheBuildError`, depending on the error
+    pub async fn build(self) -> Result<ScyllaCache<K, V>, ScyllaCacheBuildError> {
+        let session = match self.session {
+            Some(ref session) => session.clone(),
+            None => Arc::new(
+                SessionBuilder::new()
+                    .known_nodes(self.known_nodes()?)
+                    .build()
+                    .await?,
+            ),
+        };
+        let table = format!("{}.{}", self.keyspace, self.table);
+        if self.create_table {
+            session
+                .query(
+                    format!(
+                        "CREATE TABLE IF NOT EXISTS {} (key text PRIMARY KEY, value text)",
+                        table
+                    ),
+                    (),
+                )
+                .await?;
+        }
+        let select = self
+            .prepare(
+                &session,
+                format!("SELECT value FROM {} WHERE key = ?", table),
+            )
+            .await?;
+        let insert = self
+            .prepare(
+                &session,
+                format!(
+                    "INSERT INTO {} (key, value) VALUES (?, ?) USING TTL ?",
+                    table
+                ),
+            )
+            .await?;
+        let delete = self
+            .prepare(&session, format!("DELETE FROM {} WHERE key = ?", table))
+            .await?;
+        Ok(ScyllaCache {
+            seconds: self.seconds,
+            refresh: self.refresh,
+            namespace: self.namespace,
+            prefix: self.prefix,
+            session,
+            select,
+            insert,
+            delete,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            _phantom: PhantomData,
+        })
+    }
+}
+
+/// Cache store backed by a Cassandra or ScyllaDB table
+///
+/// Each value is written with a TTL, so that the database drops it when its lifespan ends.
+/// `cache_set` and `cache_remove` read the previous value before writing, these two
+/// queries are not atomic. Refreshing a value on a cache hit rewrites it with a new TTL.
+pub struct ScyllaCache<K, V> {
+    pub(super) seconds: u64,
+    pub(super) refresh: bool,
+    pub(super) namespace: String,
+    pub(super) prefix: String,
+    session: Arc<Session>,
+    select: PreparedStatement,
+    insert: PreparedStatement,
+    delete: PreparedStatement,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> ScyllaCache<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    #[allow(clippy::new_ret_no_self)]
+    /// Initialize a `ScyllaCacheBuilder`
+    pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> ScyllaCacheBuilder<K, V> {
+        ScyllaCacheBuilder::new(prefix, seconds)
+    }
+
+    /// Return the session used by the cache
+    #[must_use]
+    pub fn session(&self) -> &Arc<Session> {
+        &self.session
+    }
+
+    fn generate_key(&self, key: &K) -> String {
+        format!("{}{}{}", self.namespace, self.prefix, key)
+    }
+
+    async fn select(&self, key: &str) -> Result<Option<String>, ScyllaCacheError> {
+        let row = self
+            .session
+            .execute(&self.select, (key,))
+            .await?
+            .maybe_first_row_typed::<(String,)>()?;
+        Ok(row.map(|(value,)| value))
+    }
+
+    async fn insert(&self, key: &str, value: &str) -> Result<(), ScyllaCacheError> {
+        self.session
+            .execute(&self.insert, (key, value, ttl(self.seconds)))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Return the TTL of written values, which Cassandra expects to be a positive `i32`
+fn ttl(seconds: u64) -> i32 {
+    i32::try_from(seconds).unwrap_or(i32::MAX).max(1)
+}
+
+fn encode<V: Serialize>(value: V) -> Result<String, ScyllaCacheError> {
+    serde_json::to_string(&CachedScyllaValue {
+        value,
+        version: Some(1),
+    })
+    .map_err(|e| ScyllaCacheError::CacheSerializationError { error: e })
+}
+
+fn decode<V: DeserializeOwned>(value: String) -> Result<V, ScyllaCacheError> {
+    match serde_json::from_str::<CachedScyllaValue<V>>(&value) {
+        Ok(cached) => Ok(cached.value),
+        Err(e) => Err(ScyllaCacheError::CacheDeserializationError {
+            cached_value: value,
+            error: e,
+        }),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ScyllaCacheError {
+    #[error("scylla query error")]
+    QueryError(#[from] QueryError),
+    #[error("scylla row error")]
+    RowError(#[from] MaybeFirstRowTypedError),
+    #[error("Error deserializing cached value {cached_value:?}: {error:?}")]
+    CacheDeserializationError {
+        cached_value: String,
+        error: serde_json::Error,
+    },
+    #[error("Error serializing cached value: {error:?}")]
+    CacheSerializationError { error: serde_json::Error },
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedScyllaValue<V> {
+    pub(crate) value: V,
+    pub(crate) version: Option<u64>,
+}
+
+#[async_trait]
+impl<K, V> IOCachedAsync<K, V> for ScyllaCache<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    type Error = ScyllaCacheError;
+
+    /// Get a cached value
+    async fn cache_get(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let key = self.generate_key(key);
+        let value = self.select(&key).await?;
+        if let (Some(value), true) = (&value, self.refresh) {
+            self.insert(&key, value).await?;
+        }
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Backend, started.elapsed());
+        let value = value.map(decode).transpose()?;
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(value.is_some(), started.elapsed());
+        Ok(value)
+    }
+
+    /// Set a cached value
+    async fn cache_set(&self, key: K, val: V) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let key = self.generate_key(&key);
+        let value = encode(val)?;
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let old = self.select(&key).await?;
+        self.insert(&key, &value).await?;
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record(Operation::Insert, started.elapsed());
+        }
+        old.map(decode).transpose()
+    }
+
+    /// Remove a cached value
+    async fn cache_remove(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let key = self.generate_key(key);
+        let old = self.select(&key).await?;
+        if old.is_some() {
+            self.session.execute(&self.delete, (key.as_str(),)).await?;
+        }
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Backend, started.elapsed());
+        old.map(decode).transpose()
+    }
+
+    /// Set the flag to control whether cache hits refresh the ttl of cached values, returns the old flag value
+    fn cache_set_refresh(&mut self, refresh: bool) -> bool {
+        let old = self.refresh;
+        self.refresh = refresh;
+        old
+    }
+
+    /// Return the lifespan of cached values (time to eviction)
+    fn cache_lifespan(&self) -> Option<u64> {
+        Some(self.seconds)
+    }
+
+    /// Set the lifespan of cached values, returns the old value
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let old = self.seconds;
+        self.seconds = seconds;
+        Some(old)
+    }
+
+    /// Return latency histograms of this cache's operations
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip() {
+        let value = encode(vec!["a".to_string()]).unwrap();
+        assert_eq!(decode::<Vec<String>>(value).unwrap(), vec!["a".to_string()]);
+        assert!(matches!(
+            decode::<u32>("1".to_string()),
+            Err(ScyllaCacheError::CacheDeserializationError { .. })
+        ));
+    }
+
+    #[test]
+    fn ttls_are_positive_i32() {
+        assert_eq!(ttl(0), 1);
+        assert_eq!(ttl(60), 60);
+        assert_eq!(ttl(u64::MAX), i32::MAX);
+    }
+
+    #[tokio::test]
+    async fn missing_known_nodes() {
+        std::env::remove_var(ENV_KEY);
+        let res = ScyllaCache::<u32, u32>::new("test:", 1).build().await;
+        assert!(matches!(
+            res,
+            Err(ScyllaCacheBuildError::MissingKnownNodes { .. })
+        ));
+    }
+}
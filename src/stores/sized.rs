@@ -1,5 +1,10 @@
-use super::Cached;
+use super::cache_stats::{CacheStats, StatsCounters};
+use super::CacheBuildError;
+use super::{Cached, CachedRead, CachedWrite};
+use crate::events::{CacheEvent, EventFeed};
 use crate::lru_list::LRUList;
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
 use hashbrown::raw::RawTable;
 use std::cmp::Eq;
 use std::fmt;
@@ -12,26 +17,30 @@ use ahash::RandomState;
 use std::collections::hash_map::RandomState;
 
 #[cfg(feature = "async")]
-use {super::CachedAsync, async_trait::async_trait, futures::Future};
+use {super::CachedAsync, async_trait::async_trait, futures::Future, std::convert::Infallible};
 
 /// Least Recently Used / `Sized` Cache
 ///
 /// Stores up to a specified size before beginning
 /// to evict the least recently used keys
 ///
+/// Keys are hashed with the `S` hasher, see `try_with_size_and_hasher`.
+///
 /// Note: This cache is in-memory only
 #[derive(Clone)]
-pub struct SizedCache<K, V> {
+pub struct SizedCache<K, V, S = RandomState> {
     // `store` contains a hash of K -> index of (K, V) tuple in `order`
     pub(super) store: RawTable<usize>,
-    pub(super) hash_builder: RandomState,
+    pub(super) hash_builder: S,
     pub(super) order: LRUList<(K, V)>,
     pub(super) capacity: usize,
-    pub(super) hits: u64,
-    pub(super) misses: u64,
+    pub(super) stats: StatsCounters,
+    #[cfg(feature = "latency_stats")]
+    pub(super) latency: LatencyStats,
+    pub(super) events: EventFeed<K, V>,
 }
 
-impl<K, V> fmt::Debug for SizedCache<K, V>
+impl<K, V, S> fmt::Debug for SizedCache<K, V, S>
 where
     K: fmt::Debug,
     V: fmt::Debug,
@@ -40,18 +49,18 @@ where
         f.debug_struct("SizedCache")
             .field("order", &self.order)
             .field("capacity", &self.capacity)
-            .field("hits", &self.hits)
-            .field("misses", &self.misses)
+            .field("stats", &self.stats)
             .finish()
     }
 }
 
-impl<K, V> PartialEq for SizedCache<K, V>
+impl<K, V, S> PartialEq for SizedCache<K, V, S>
 where
     K: Eq + Hash + Clone,
     V: PartialEq,
+    S: BuildHasher,
 {
-    fn eq(&self, other: &SizedCache<K, V>) -> bool {
+    fn eq(&self, other: &SizedCache<K, V, S>) -> bool {
         self.store.len() == other.store.len() && {
             self.order
                 .iter()
@@ -63,10 +72,11 @@ where
     }
 }
 
-impl<K, V> Eq for SizedCache<K, V>
+impl<K, V, S> Eq for SizedCache<K, V, S>
 where
     K: Eq + Hash + Clone,
     V: PartialEq,
+    S: BuildHasher,
 {
 }
 
@@ -81,53 +91,95 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
     ///
     /// # Panics
     ///
-    /// Will panic if size is 0
+    /// Will panic if size is 0, see `try_with_size`
     #[must_use]
     pub fn with_size(size: usize) -> SizedCache<K, V> {
+        Self::with_size_and_hasher(size, RandomState::new())
+    }
+
+    /// Creates a new `SizedCache` with a given size limit and pre-allocated backing data
+    ///
+    /// # Errors
+    ///
+    /// Will return a `CacheBuildError` if size is 0 or the backing data cannot be allocated
+    pub fn try_with_size(size: usize) -> Result<SizedCache<K, V>, CacheBuildError> {
+        Self::try_with_size_and_hasher(size, RandomState::new())
+    }
+
+    /// Creates a `SizedCache` which never evicts on its own, for the stores
+    /// evicting by another measure than the number of values
+    pub(super) fn without_limit() -> SizedCache<K, V> {
+        SizedCache {
+            store: RawTable::new(),
+            hash_builder: RandomState::new(),
+            order: LRUList::<(K, V)>::with_capacity(0),
+            capacity: usize::MAX,
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            events: EventFeed::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> SizedCache<K, V, S> {
+    /// Creates a new `SizedCache` with a given size limit and pre-allocated backing data,
+    /// hashing its keys with `hash_builder`
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0, see `try_with_size_and_hasher`
+    #[must_use]
+    pub fn with_size_and_hasher(size: usize, hash_builder: S) -> SizedCache<K, V, S> {
         if size == 0 {
             panic!("`size` of `SizedCache` must be greater than zero.");
         }
         SizedCache {
             store: RawTable::with_capacity(size),
-            hash_builder: RandomState::new(),
+            hash_builder,
             order: LRUList::<(K, V)>::with_capacity(size),
             capacity: size,
-            hits: 0,
-            misses: 0,
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            events: EventFeed::new(),
         }
     }
 
-    /// Creates a new `SizedCache` with a given size limit and pre-allocated backing data
+    /// Creates a new `SizedCache` with a given size limit and pre-allocated backing data,
+    /// hashing its keys with `hash_builder`
     ///
     /// # Errors
     ///
-    /// Will return a `std::io::Error`, depending on the error
-    pub fn try_with_size(size: usize) -> std::io::Result<SizedCache<K, V>> {
+    /// Will return a `CacheBuildError` if size is 0 or the backing data cannot be allocated
+    pub fn try_with_size_and_hasher(
+        size: usize,
+        hash_builder: S,
+    ) -> Result<SizedCache<K, V, S>, CacheBuildError> {
         if size == 0 {
-            // EINVAL
-            return Err(std::io::Error::from_raw_os_error(22));
+            return Err(CacheBuildError::zero("SizedCache", "size"));
         }
 
-        let store = match RawTable::try_with_capacity(size) {
-            Ok(store) => store,
-            Err(e) => {
-                let errcode = match e {
-                    // ENOMEM
-                    hashbrown::TryReserveError::AllocError { .. } => 12,
-                    // EINVAL
-                    hashbrown::TryReserveError::CapacityOverflow => 22,
-                };
-                return Err(std::io::Error::from_raw_os_error(errcode));
-            }
-        };
+        let store = RawTable::try_with_capacity(size).map_err(|e| match e {
+            hashbrown::TryReserveError::AllocError { .. } => CacheBuildError::Allocation {
+                store: "SizedCache",
+                capacity: size,
+            },
+            hashbrown::TryReserveError::CapacityOverflow => CacheBuildError::CapacityOverflow {
+                store: "SizedCache",
+                capacity: size,
+            },
+        })?;
 
         Ok(SizedCache {
             store,
-            hash_builder: RandomState::new(),
+            hash_builder,
             order: LRUList::<(K, V)>::with_capacity(size),
             capacity: size,
-            hits: 0,
-            misses: 0,
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            events: EventFeed::new(),
         })
     }
 
@@ -135,6 +187,13 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         self.order.iter()
     }
 
+    /// Return a handle to the hit and miss counters of the cache, readable
+    /// without locking or borrowing the cache
+    #[must_use]
+    pub fn stats_handle(&self) -> CacheStats {
+        self.stats.handle()
+    }
+
     /// Return an iterator of keys in the current order from most
     /// to least recently used.
     pub fn key_order(&self) -> impl Iterator<Item = &K> {
@@ -147,6 +206,50 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         self.order.iter().map(|(_k, v)| v)
     }
 
+    /// Remove the least recently used value, returning it along with its key, e.g. to
+    /// trim the cache by hand and move the values it drops to another tier
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        let (key, value) = self.evict_lru()?;
+        self.events.remove(&key);
+        Some((key, value))
+    }
+
+    /// Set the maximum number of values, returning the least recently used values evicted
+    /// to stay within it, from least to most recently used
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0
+    pub fn set_capacity(&mut self, size: usize) -> Vec<(K, V)> {
+        if size == 0 {
+            panic!("`size` of `SizedCache` must be greater than zero.");
+        }
+        self.capacity = size;
+        let mut evicted = Vec::new();
+        while let Some(entry) = self.check_capacity() {
+            evicted.push(entry);
+        }
+        evicted
+    }
+
+    /// Release the memory the cache keeps beyond its values, e.g. after most of them
+    /// were removed, keeping their recency order
+    pub fn shrink_to_fit(&mut self) {
+        let len = self.store.len();
+        let mut order = LRUList::with_capacity(len);
+        let mut indices = Vec::with_capacity(len);
+        // move the values from least to most recently used, each to the front
+        while let Some(entry) = self.evict_lru() {
+            let hash = self.hash(&entry.0);
+            indices.push((hash, order.push_front(entry)));
+        }
+        self.order = order;
+        self.store = RawTable::with_capacity(len);
+        for (hash, index) in indices {
+            self.insert_index(hash, index);
+        }
+    }
+
     fn hash<Q>(&self, key: &Q) -> u64
     where
         K: std::borrow::Borrow<Q>,
@@ -199,30 +302,67 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         store.remove_entry(hash, |&i| key == order.get(i).0.borrow())
     }
 
-    fn check_capacity(&mut self) {
+    fn check_capacity(&mut self) -> Option<(K, V)> {
+        if self.store.len() > self.capacity {
+            // store has reached capacity, evict the oldest item.
+            let (key, value) = self.evict_lru()?;
+            self.events.remove(&key);
+            Some((key, value))
+        } else {
+            None
+        }
+    }
+
+    /// Evict the least recently used entry, returning it
+    pub(super) fn evict_lru(&mut self) -> Option<(K, V)> {
         let Self {
             ref mut store,
             ref mut order,
             ref hash_builder,
-            capacity,
             ..
         } = *self;
-        let len = store.len();
-        if len > capacity {
-            // store has reached capacity, evict the oldest item.
-            // store capacity cannot be zero, so there must be content in `self.order`.
-            let index = order.back();
-            let key = &order.get(index).0;
-            let hasher = &mut hash_builder.build_hasher();
-            key.hash(hasher);
-            let hash = hasher.finish();
-
-            let order_ = &order;
-            let erased = store.erase_entry(hash, |&i| *key == order_.get(i).0);
-            assert!(erased, "SizedCache::cache_set failed evicting cache key");
-            store.remove_entry(hash, |&i| *key == order_.get(i).0);
-            order.remove(index);
+        if store.is_empty() {
+            return None;
         }
+        let index = order.back();
+        let key = &order.get(index).0;
+        let hasher = &mut hash_builder.build_hasher();
+        key.hash(hasher);
+        let hash = hasher.finish();
+
+        let order_ = &order;
+        let erased = store.erase_entry(hash, |&i| *key == order_.get(i).0);
+        assert!(erased, "SizedCache::cache_set failed evicting cache key");
+        store.remove_entry(hash, |&i| *key == order_.get(i).0);
+        Some(order.remove(index))
+    }
+
+    /// Return the key of the least recently used value
+    pub(super) fn lru_key(&self) -> Option<&K> {
+        if self.store.is_empty() {
+            return None;
+        }
+        Some(&self.order.get(self.order.back()).0)
+    }
+
+    /// Set a value, returning the previous value of the key and the entry evicted to make room for it
+    pub(super) fn set_evicting(&mut self, key: K, val: V) -> (Option<V>, Option<(K, V)>) {
+        let v = self.set_unchecked(key, val);
+        (v, self.check_capacity())
+    }
+
+    /// Set a value without evicting the values over capacity, returning the previous value of the key
+    fn set_unchecked(&mut self, key: K, val: V) -> Option<V> {
+        let hash = self.hash(&key);
+        let (index, v) = if let Some(index) = self.get_index(hash, &key) {
+            (index, self.order.set(index, (key, val)).map(|(_, v)| v))
+        } else {
+            let index = self.order.push_front((key, val));
+            self.insert_index(hash, index);
+            (index, None)
+        };
+        self.emit_set(index, v.is_some());
+        v
     }
 
     pub(super) fn get_if<F: FnOnce(&V) -> bool, Q>(&mut self, key: &Q, is_valid: F) -> Option<&V>
@@ -230,14 +370,33 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
+        self.get_key_value_if(key, is_valid).map(|(_, value)| value)
+    }
+
+    pub(super) fn get_key_value_if<F: FnOnce(&V) -> bool, Q>(
+        &mut self,
+        key: &Q,
+        is_valid: F,
+    ) -> Option<(&K, &V)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
         if let Some(index) = self.get_index(self.hash(key), key) {
             if is_valid(&self.order.get(index).1) {
                 self.order.move_to_front(index);
-                self.hits += 1;
-                return Some(&self.order.get(index).1);
+                self.stats.hit();
+                #[cfg(feature = "latency_stats")]
+                self.latency.record(Operation::GetHit, started.elapsed());
+                let (key, value) = self.order.get(index);
+                return Some((key, value));
             }
         }
-        self.misses += 1;
+        self.stats.miss();
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::GetMiss, started.elapsed());
         None
     }
 
@@ -250,14 +409,20 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
         if let Some(index) = self.get_index(self.hash(key), key) {
             if is_valid(&self.order.get(index).1) {
                 self.order.move_to_front(index);
-                self.hits += 1;
+                self.stats.hit();
+                #[cfg(feature = "latency_stats")]
+                self.latency.record(Operation::GetHit, started.elapsed());
                 return Some(&mut self.order.get_mut(index).1);
             }
         }
-        self.misses += 1;
+        self.stats.miss();
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::GetMiss, started.elapsed());
         None
     }
 
@@ -276,20 +441,22 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         let hash = self.hash(&key);
         let index = self.get_index(hash, &key);
         if let Some(index) = index {
-            self.hits += 1;
+            self.stats.hit();
             let replace_existing = {
                 let v = &self.order.get(index).1;
                 !is_valid(v)
             };
             if replace_existing {
                 self.order.set(index, (key, f()));
+                self.emit_set(index, true);
             }
             self.order.move_to_front(index);
             (true, !replace_existing, &mut self.order.get_mut(index).1)
         } else {
-            self.misses += 1;
+            self.stats.miss();
             let index = self.order.push_front((key, f()));
             self.insert_index(hash, index);
+            self.emit_set(index, false);
             self.check_capacity();
             (false, false, &mut self.order.get_mut(index).1)
         }
@@ -305,32 +472,65 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         let hash = self.hash(&key);
         let index = self.get_index(hash, &key);
         if let Some(index) = index {
-            self.hits += 1;
+            self.stats.hit();
             let replace_existing = {
                 let v = &self.order.get(index).1;
                 !is_valid(v)
             };
             if replace_existing {
                 self.order.set(index, (key, f()?));
+                self.emit_set(index, true);
             }
             self.order.move_to_front(index);
             Ok((true, !replace_existing, &mut self.order.get_mut(index).1))
         } else {
-            self.misses += 1;
+            self.stats.miss();
             let index = self.order.push_front((key, f()?));
             self.insert_index(hash, index);
+            self.emit_set(index, false);
             self.check_capacity();
             Ok((false, false, &mut self.order.get_mut(index).1))
         }
     }
 
+    /// Return whether there is a value for `key`, without updating the recency order
+    pub(super) fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.get_index(self.hash(key), key).is_some()
+    }
+
+    /// Broadcast the value set at `index` of `order` to the subscribers
+    fn emit_set(&mut self, index: usize, replaced: bool) {
+        let (key, value) = self.order.get(index);
+        self.events.set(key, value, replaced);
+    }
+
+    /// Remove a cached value, returning it along with its key
+    pub(super) fn remove_entry<Q>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        // try and remove item from mapping, and then from order list if it was in mapping
+        let hash = self.hash(k);
+        let index = self.remove_index(hash, k)?;
+        // need to remove the key in the order list
+        let (key, value) = self.order.remove(index);
+        self.events.remove(&key);
+        Some((key, value))
+    }
+
     /// Returns a reference to the cache's `order`
     #[must_use]
     pub fn get_order(&self) -> &LRUList<(K, V)> {
         &self.order
     }
 
-    pub fn retain<F: Fn(&K, &V) -> bool>(&mut self, keep: F) {
+    /// Keep only the values for which `keep` returns `true`, see `Cached::cache_retain`
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut keep: F) {
         let remove_keys = self
             .iter_order()
             .filter_map(|(k, v)| if keep(k, v) { None } else { Some(k.clone()) })
@@ -342,9 +542,10 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
 }
 
 #[cfg(feature = "async")]
-impl<K, V> SizedCache<K, V>
+impl<K, V, S> SizedCache<K, V, S>
 where
     K: Hash + Eq + Clone + Send,
+    S: BuildHasher,
 {
     /// Get the cached value, or set it using `f` if the value
     /// is either not-set or if `is_valid` returns `false` for
@@ -367,20 +568,22 @@ where
         let hash = self.hash(&key);
         let index = self.get_index(hash, &key);
         if let Some(index) = index {
-            self.hits += 1;
+            self.stats.hit();
             let replace_existing = {
                 let v = &self.order.get(index).1;
                 !is_valid(v)
             };
             if replace_existing {
                 self.order.set(index, (key, f().await));
+                self.emit_set(index, true);
             }
             self.order.move_to_front(index);
             (true, !replace_existing, &mut self.order.get_mut(index).1)
         } else {
-            self.misses += 1;
+            self.stats.miss();
             let index = self.order.push_front((key, f().await));
             self.insert_index(hash, index);
+            self.emit_set(index, false);
             self.check_capacity();
             (false, false, &mut self.order.get_mut(index).1)
         }
@@ -401,27 +604,65 @@ where
         let hash = self.hash(&key);
         let index = self.get_index(hash, &key);
         if let Some(index) = index {
-            self.hits += 1;
+            self.stats.hit();
             let replace_existing = {
                 let v = &self.order.get(index).1;
                 !is_valid(v)
             };
             if replace_existing {
                 self.order.set(index, (key, f().await?));
+                self.emit_set(index, true);
             }
             self.order.move_to_front(index);
             Ok((true, !replace_existing, &mut self.order.get_mut(index).1))
         } else {
-            self.misses += 1;
+            self.stats.miss();
             let index = self.order.push_front((key, f().await?));
             self.insert_index(hash, index);
+            self.emit_set(index, false);
+            self.check_capacity();
+            Ok((false, false, &mut self.order.get_mut(index).1))
+        }
+    }
+
+    pub(super) async fn try_get_or_set_with_if_valid_async<E, F, Fut, P, PFut>(
+        &mut self,
+        key: K,
+        f: F,
+        is_valid: P,
+    ) -> Result<(bool, bool, &mut V), E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+        P: FnOnce(&V) -> PFut,
+        PFut: Future<Output = bool>,
+    {
+        let hash = self.hash(&key);
+        let index = self.get_index(hash, &key);
+        if let Some(index) = index {
+            let valid = is_valid(&self.order.get(index).1).await;
+            if valid {
+                self.stats.hit();
+            } else {
+                self.stats.miss();
+                self.order.set(index, (key, f().await?));
+                self.emit_set(index, true);
+            }
+            self.order.move_to_front(index);
+            Ok((true, valid, &mut self.order.get_mut(index).1))
+        } else {
+            self.stats.miss();
+            let index = self.order.push_front((key, f().await?));
+            self.insert_index(hash, index);
+            self.emit_set(index, false);
             self.check_capacity();
             Ok((false, false, &mut self.order.get_mut(index).1))
         }
     }
 }
 
-impl<K: Hash + Eq + Clone, V> Cached<K, V> for SizedCache<K, V> {
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> Cached<K, V> for SizedCache<K, V, S> {
     fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
     where
         K: std::borrow::Borrow<Q>,
@@ -438,17 +679,32 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for SizedCache<K, V> {
         self.get_mut_if(key, |_| true)
     }
 
-    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
-        let hash = self.hash(&key);
-        let v = if let Some(index) = self.get_index(hash, &key) {
-            self.order.set(index, (key, val)).map(|(_, v)| v)
-        } else {
-            let index = self.order.push_front((key, val));
-            self.insert_index(hash, index);
-            None
-        };
-        self.check_capacity();
-        v
+    fn cache_get_key_value<Q>(&mut self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.get_key_value_if(key, |_| true)
+    }
+
+    /// The values are returned from most to least recently used.
+    fn cache_drain_where<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        mut drain: F,
+    ) -> impl Iterator<Item = (K, V)> {
+        let keys = self
+            .iter_order()
+            .filter(|(key, value)| drain(key, value))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        keys.into_iter()
+            .filter_map(|key| self.remove_entry(&key))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, keep: F) {
+        self.retain(keep);
     }
 
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
@@ -456,53 +712,110 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for SizedCache<K, V> {
         v
     }
 
+    fn cache_subscribe(&mut self) -> Option<std::sync::mpsc::Receiver<CacheEvent<K, V>>>
+    where
+        V: Clone,
+    {
+        Some(self.events.subscribe())
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> CachedRead<K, V> for SizedCache<K, V, S> {
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.stats.hits())
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.stats.misses())
+    }
+
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        let index = self.get_index(self.hash(key), key)?;
+        Some(&self.order.get(index).1)
+    }
+
+    /// The values are iterated from most to least recently used.
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.order.iter().map(|(key, value)| (key, value))
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> CachedWrite<K, V> for SizedCache<K, V, S> {
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let (v, _) = self.set_evicting(key, val);
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Insert, started.elapsed());
+        v
+    }
+
     fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
     where
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        // try and remove item from mapping, and then from order list if it was in mapping
-        let hash = self.hash(k);
-        if let Some(index) = self.remove_index(hash, k) {
-            // need to remove the key in the order list
-            let (_key, value) = self.order.remove(index);
-            Some(value)
-        } else {
-            None
+        self.remove_entry(k).map(|(_key, value)| value)
+    }
+
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut remove: F) -> Option<usize> {
+        let keys = self
+            .iter_order()
+            .filter(|(key, _)| remove(key))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in &keys {
+            self.remove_entry(key);
         }
+        Some(keys.len())
     }
+
     fn cache_clear(&mut self) {
         // clear both the store and the order list
         self.store.clear();
         self.order.clear();
+        self.events.clear();
     }
+
     fn cache_reset(&mut self) {
-        // SizedCache uses cache_clear because capacity is fixed.
+        // SizedCache uses cache_clear because it keeps its capacity.
         self.cache_clear();
     }
+
     fn cache_reset_metrics(&mut self) {
-        self.misses = 0;
-        self.hits = 0;
-    }
-    fn cache_size(&self) -> usize {
-        self.store.len()
-    }
-    fn cache_hits(&self) -> Option<u64> {
-        Some(self.hits)
-    }
-    fn cache_misses(&self) -> Option<u64> {
-        Some(self.misses)
-    }
-    fn cache_capacity(&self) -> Option<usize> {
-        Some(self.capacity)
+        self.stats.reset();
+        #[cfg(feature = "latency_stats")]
+        self.latency.reset();
     }
 }
 
 #[cfg(feature = "async")]
 #[async_trait]
-impl<K, V> CachedAsync<K, V> for SizedCache<K, V>
+impl<K, V, S> CachedAsync<K, V> for SizedCache<K, V, S>
 where
     K: Hash + Eq + Clone + Send,
+    S: BuildHasher + Send,
 {
     async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
     where
@@ -523,6 +836,40 @@ where
         let (_, _, v) = self.try_get_or_set_with_if_async(k, f, |_| true).await?;
         Ok(v)
     }
+
+    async fn get_or_set_with_if_valid<F, Fut, P, PFut>(&mut self, k: K, f: F, is_valid: P) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send,
+    {
+        let setter = || async { Ok::<_, Infallible>(f().await) };
+        match self.try_get_or_set_with_if_valid(k, setter, is_valid).await {
+            Ok(v) => v,
+            Err(never) => match never {},
+        }
+    }
+
+    async fn try_get_or_set_with_if_valid<F, Fut, P, PFut, E>(
+        &mut self,
+        k: K,
+        f: F,
+        is_valid: P,
+    ) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send,
+    {
+        let (_, _, v) = self
+            .try_get_or_set_with_if_valid_async(k, f, is_valid)
+            .await?;
+        Ok(v)
+    }
 }
 
 #[cfg(test)]
@@ -618,10 +965,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn stats_handle() {
+        let mut c = SizedCache::with_size(5);
+        let stats = c.stats_handle();
+        c.cache_set(1, 100);
+        c.cache_get(&1);
+        c.cache_get(&2);
+        assert_eq!((stats.hits(), stats.misses()), (1, 1));
+
+        // clones count on their own
+        let mut cloned = c.clone();
+        cloned.cache_get(&1);
+        assert_eq!(cloned.cache_hits(), Some(2));
+        assert_eq!(stats.hits(), 1);
+
+        std::thread::spawn(move || assert_eq!(stats.misses(), 1))
+            .join()
+            .unwrap();
+        c.cache_reset_metrics();
+        assert_eq!(c.stats_handle().misses(), 0);
+    }
+
     #[test]
     fn try_new() {
-        let c: std::io::Result<SizedCache<i32, i32>> = SizedCache::try_with_size(0);
-        assert_eq!(c.unwrap_err().raw_os_error(), Some(22));
+        let c: Result<SizedCache<i32, i32>, _> = SizedCache::try_with_size(0);
+        let error = c.unwrap_err();
+        assert_eq!(error, CacheBuildError::zero("SizedCache", "size"));
+        assert_eq!(std::io::Error::from(error).raw_os_error(), Some(22));
+        assert!(matches!(
+            SizedCache::<i32, i32>::try_with_size(usize::MAX),
+            Err(CacheBuildError::CapacityOverflow { .. })
+        ));
     }
 
     #[test]
@@ -685,6 +1060,112 @@ mod tests {
         assert_eq!(0, c.cache_size());
     }
 
+    #[test]
+    fn pop_lru() {
+        let mut c = SizedCache::with_size(3);
+        let events = c.cache_subscribe().unwrap();
+        c.cache_set(1, "a");
+        c.cache_set(2, "b");
+        c.cache_get(&1);
+        assert_eq!(c.pop_lru(), Some((2, "b")));
+        assert_eq!(c.pop_lru(), Some((1, "a")));
+        assert_eq!(c.pop_lru(), None);
+        assert_eq!(c.cache_size(), 0);
+        assert!(matches!(
+            events.try_iter().last(),
+            Some(CacheEvent::Remove { key: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn set_many() {
+        let mut c = SizedCache::with_size(3);
+        c.cache_set(0, "z");
+        c.cache_set_many([(1, "a"), (2, "b"), (3, "c"), (1, "d"), (4, "e")]);
+        // as with `cache_set`, replacing a value does not update its recency
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&4, &3, &2]);
+        assert_eq!(c.cache_get(&3), Some(&"c"));
+        assert_eq!(c.cache_size(), 3);
+
+        #[cfg(feature = "latency_stats")]
+        assert_eq!(c.cache_latency_stats().unwrap().insert.count(), 6);
+    }
+
+    #[test]
+    fn set_capacity() {
+        let mut c = SizedCache::with_size(3);
+        c.cache_set(1, "a");
+        c.cache_set(2, "b");
+        c.cache_set(3, "c");
+        c.cache_get(&1);
+        assert_eq!(c.set_capacity(1), [(2, "b"), (3, "c")]);
+        assert_eq!((c.cache_size(), c.cache_capacity()), (1, Some(1)));
+
+        assert!(c.set_capacity(2).is_empty());
+        c.cache_set(4, "d");
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&4, &1]);
+        c.cache_set(5, "e");
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&5, &4]);
+    }
+
+    #[test]
+    fn get_key_value() {
+        let mut c: SizedCache<String, u32> = SizedCache::with_size(2);
+        c.cache_set("a".to_string(), 1);
+        c.cache_set("b".to_string(), 2);
+        assert_eq!(c.cache_get_key_value("a"), Some((&"a".to_string(), &1)));
+        assert_eq!(c.cache_get_key_value("c"), None);
+        assert_eq!((c.cache_hits(), c.cache_misses()), (Some(1), Some(1)));
+
+        // the value is the most recently used
+        c.cache_set("c".to_string(), 3);
+        assert!(c.cache_contains("a"));
+        assert!(!c.cache_contains("b"));
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut c = SizedCache::with_size(1000);
+        for i in 0..1000 {
+            c.cache_set(i, i);
+        }
+        c.cache_get(&1);
+        c.cache_drain_where(|k, _| *k > 2).for_each(drop);
+        c.shrink_to_fit();
+        assert!(c.store.capacity() < 1000);
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&1, &2, &0]);
+        assert_eq!(c.cache_get(&2), Some(&2));
+        assert_eq!(c.cache_remove(&0), Some(0));
+
+        c.cache_set(3, 3);
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&3, &2, &1]);
+    }
+
+    #[test]
+    fn iter() {
+        let mut c = SizedCache::with_size(3);
+        c.cache_set(1, "a");
+        c.cache_set(2, "b");
+        c.cache_get(&1);
+        let pairs: Vec<_> = c.cache_iter().collect();
+        assert_eq!(pairs, [(&1, &"a"), (&2, &"b")]);
+        assert_eq!(c.cache_hits(), Some(1));
+    }
+
+    #[test]
+    fn drain() {
+        let mut c = SizedCache::with_size(3);
+        let events = c.cache_subscribe().unwrap();
+        c.cache_set(1, "a");
+        c.cache_set(2, "b");
+        c.cache_set(3, "c");
+        let drained: Vec<_> = c.cache_drain_where(|key, _| *key != 2).collect();
+        assert_eq!(drained, [(3, "c"), (1, "a")]);
+        assert_eq!(c.cache_drain().collect::<Vec<_>>(), [(2, "b")]);
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(events.try_iter().count(), 6);
+    }
+
     #[test]
     fn sized_cache_get_mut() {
         let mut c = SizedCache::with_size(5);
@@ -735,6 +1216,71 @@ mod tests {
         assert!(cache.cache_get(&4).is_some());
     }
 
+    #[test]
+    fn sized_cache_with_hasher() {
+        // every key collides, the keys are told apart by equality only
+        #[derive(Default)]
+        struct ZeroHasher;
+
+        impl Hasher for ZeroHasher {
+            fn finish(&self) -> u64 {
+                0
+            }
+
+            fn write(&mut self, _bytes: &[u8]) {}
+        }
+
+        let hash_builder = std::hash::BuildHasherDefault::<ZeroHasher>::default();
+        let mut c = SizedCache::try_with_size_and_hasher(2, hash_builder.clone()).unwrap();
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(1, 101), Some(100));
+        assert_eq!(c.cache_get(&1), Some(&101));
+        assert_eq!(c.cache_set(3, 300), None);
+        assert_eq!(c.cache_get(&2), None);
+        assert_eq!(c.cache_get(&1), Some(&101));
+        assert_eq!(c.cache_get(&3), Some(&300));
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&3, &1]);
+        assert!(SizedCache::<u32, u32, _>::try_with_size_and_hasher(0, hash_builder).is_err());
+    }
+
+    #[test]
+    fn sized_cache_remove_where() {
+        let mut c = SizedCache::with_size(4);
+        for n in 1..=4 {
+            c.cache_set(n, n * 100);
+        }
+        assert_eq!(c.cache_remove_where(|k| k % 2 == 0), Some(2));
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&3, &1]);
+        // the freed slots are reused without evicting
+        c.cache_set(5, 500);
+        c.cache_set(6, 600);
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&6, &5, &3, &1]);
+    }
+
+    #[test]
+    #[cfg(feature = "latency_stats")]
+    fn latency_stats() {
+        let mut c = SizedCache::with_size(2);
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_get_mut(&2), Some(&mut 200));
+        assert_eq!(c.cache_get(&3), None);
+
+        let stats = c.cache_latency_stats().unwrap();
+        assert_eq!(stats.insert.count(), 2);
+        assert_eq!(stats.get_hit.count(), 2);
+        assert_eq!(stats.get_miss.count(), 1);
+        assert_eq!(stats.backend.count(), 0);
+
+        c.cache_reset_metrics();
+        let stats = c.cache_latency_stats().unwrap();
+        assert_eq!(stats.get_hit.count(), 0);
+        assert_eq!(stats.insert.count(), 0);
+    }
+
     #[test]
     fn get_or_set_with() {
         let mut c = SizedCache::with_size(5);
@@ -829,4 +1375,41 @@ mod tests {
             .await;
         assert_eq!(res.unwrap(), &1);
     }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_trait_is_valid() {
+        use crate::CachedAsync;
+        let mut c = SizedCache::with_size(5);
+
+        // stands in for a request revalidating the value against its origin
+        async fn _revalidate(etag: u32) -> bool {
+            etag == 2
+        }
+
+        let v = c
+            .get_or_set_with_if_valid(0, || async { 1 }, |_| async { unreachable!() })
+            .await;
+        assert_eq!(v, &1);
+        let v = c
+            .get_or_set_with_if_valid(0, || async { 2 }, |etag| _revalidate(*etag))
+            .await;
+        assert_eq!(v, &2);
+        let v = c
+            .get_or_set_with_if_valid(0, || async { 3 }, |etag| _revalidate(*etag))
+            .await;
+        assert_eq!(v, &2);
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(2));
+
+        let res: Result<&mut u32, String> = c
+            .try_get_or_set_with_if_valid(
+                0,
+                || async { Err("dead".to_string()) },
+                |_| async { false },
+            )
+            .await;
+        assert!(res.is_err());
+        assert_eq!(c.cache_get(&0), Some(&2));
+    }
 }
@@ -0,0 +1,302 @@
+use super::{
+    CachedRead, CachedWrite, DiskCache, DiskCacheError, DiskSerializer, MessagePackDiskSerializer,
+    SizedCache,
+};
+use crate::IOCached;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Display;
+use std::hash::Hash;
+
+/// Spillover / Memory and `Disk` Cache
+///
+/// Keeps the most recently used values in memory, up to a budget of bytes, and spills
+/// the least recently used ones to a `DiskCache`. Values read from the disk are promoted
+/// back to memory. Each value lives in a single tier: a spilled value is written to the
+/// disk before being removed from memory, and a promoted value is put in memory before
+/// being removed from the disk, so no value is lost between the tiers.
+///
+/// The bytes of each value are measured by a weigher, by default the size of the key and
+/// value themselves (`std::mem::size_of::<(K, V)>()`), not including what they point to.
+/// The most recently used value is kept in memory even if it exceeds the budget.
+///
+/// The values in memory do not expire, the lifespan of the disk cache applies to the
+/// spilled values.
+///
+/// ```rust,no_run
+/// use cached::stores::{DiskCache, SpilloverCache};
+/// use cached::CachedRead;
+///
+/// let disk = DiskCache::new("spillover").build().unwrap();
+/// let mut cache = SpilloverCache::with_weigher(disk, 1 << 20, |key: &u32, value: &String| {
+///     std::mem::size_of_val(key) + value.len()
+/// });
+/// cache.cache_set(1, "a".to_string()).unwrap();
+///
+/// assert_eq!(cache.cache_get(&1).unwrap(), Some(&"a".to_string()));
+/// assert_eq!(cache.cache_hits(), Some(1));
+/// ```
+pub struct SpilloverCache<K, V, S = MessagePackDiskSerializer> {
+    pub(super) memory: SizedCache<K, V>,
+    pub(super) disk: DiskCache<K, V, S>,
+    pub(super) budget: usize,
+    pub(super) weight: usize,
+    pub(super) weigher: fn(&K, &V) -> usize,
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+    pub(super) disk_hits: u64,
+    pub(super) spills: u64,
+}
+
+fn shallow_size<K, V>(_key: &K, _value: &V) -> usize {
+    std::mem::size_of::<(K, V)>()
+}
+
+impl<K, V, S> SpilloverCache<K, V, S>
+where
+    K: Display + Hash + Eq + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+    S: DiskSerializer,
+{
+    /// Creates a `SpilloverCache` keeping up to `budget` bytes of values in memory,
+    /// and spilling the others to `disk`
+    #[must_use]
+    pub fn new(disk: DiskCache<K, V, S>, budget: usize) -> SpilloverCache<K, V, S> {
+        Self::with_weigher(disk, budget, shallow_size::<K, V>)
+    }
+
+    /// Creates a `SpilloverCache` keeping up to `budget` bytes of values in memory,
+    /// as measured by `weigher`, and spilling the others to `disk`
+    #[must_use]
+    pub fn with_weigher(
+        disk: DiskCache<K, V, S>,
+        budget: usize,
+        weigher: fn(&K, &V) -> usize,
+    ) -> SpilloverCache<K, V, S> {
+        SpilloverCache {
+            memory: SizedCache::without_limit(),
+            disk,
+            budget,
+            weight: 0,
+            weigher,
+            hits: 0,
+            misses: 0,
+            disk_hits: 0,
+            spills: 0,
+        }
+    }
+
+    /// Return the budget of bytes of the values in memory
+    #[must_use]
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Return the bytes of the values in memory
+    #[must_use]
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// Return the number of values read from the disk and promoted to memory
+    #[must_use]
+    pub fn disk_hits(&self) -> u64 {
+        self.disk_hits
+    }
+
+    /// Return the number of values spilled to the disk
+    #[must_use]
+    pub fn spills(&self) -> u64 {
+        self.spills
+    }
+
+    /// Return a reference to the disk cache
+    #[must_use]
+    pub fn disk(&self) -> &DiskCache<K, V, S> {
+        &self.disk
+    }
+
+    /// Attempt to retrieve the value of `key` from memory, or else from the disk,
+    /// promoting it to memory
+    ///
+    /// # Errors
+    ///
+    /// Will return a `DiskCacheError` if the disk cannot be read or written
+    pub fn cache_get(&mut self, key: &K) -> Result<Option<&V>, DiskCacheError> {
+        if self.memory.contains_key(key) {
+            self.hits += 1;
+        } else {
+            let value = match self.disk.cache_get(key)? {
+                Some(value) => value,
+                None => {
+                    self.misses += 1;
+                    return Ok(None);
+                }
+            };
+            self.hits += 1;
+            self.disk_hits += 1;
+            self.weight += (self.weigher)(key, &value);
+            self.memory.cache_set(key.clone(), value);
+            self.disk.cache_remove(key)?;
+            self.spill()?;
+        }
+        Ok(self.memory.get_if(key, |_| true))
+    }
+
+    /// Insert a value in memory, spilling the least recently used values to the disk
+    /// when over budget, and return the previous value of `key`
+    ///
+    /// # Errors
+    ///
+    /// Will return a `DiskCacheError` if the disk cannot be read or written
+    pub fn cache_set(&mut self, key: K, value: V) -> Result<Option<V>, DiskCacheError> {
+        self.weight += (self.weigher)(&key, &value);
+        let previous = match self.memory.cache_set(key.clone(), value) {
+            Some(previous) => {
+                self.weight = self.weight.saturating_sub((self.weigher)(&key, &previous));
+                Some(previous)
+            }
+            None => self.disk.cache_remove(&key)?,
+        };
+        self.spill()?;
+        Ok(previous)
+    }
+
+    /// Remove the value of `key` from memory and from the disk
+    ///
+    /// # Errors
+    ///
+    /// Will return a `DiskCacheError` if the disk cannot be written
+    pub fn cache_remove(&mut self, key: &K) -> Result<Option<V>, DiskCacheError> {
+        let removed = self.disk.cache_remove(key)?;
+        match self.memory.cache_remove(key) {
+            Some(value) => {
+                self.weight = self.weight.saturating_sub((self.weigher)(key, &value));
+                Ok(Some(value))
+            }
+            None => Ok(removed),
+        }
+    }
+
+    /// Remove all the values from memory and from the disk
+    ///
+    /// # Errors
+    ///
+    /// Will return a `DiskCacheError` if the disk cannot be written
+    pub fn cache_clear(&mut self) -> Result<(), DiskCacheError> {
+        self.disk.clear()?;
+        self.memory.cache_clear();
+        self.weight = 0;
+        Ok(())
+    }
+
+    /// Reset the hit and miss counters
+    pub fn cache_reset_metrics(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.disk_hits = 0;
+        self.spills = 0;
+    }
+
+    /// Spill the least recently used values to the disk while over budget,
+    /// writing each value to the disk before removing it from memory
+    fn spill(&mut self) -> Result<(), DiskCacheError> {
+        while self.weight > self.budget && self.memory.cache_size() > 1 {
+            let (key, value) = self.memory.order.get(self.memory.order.back());
+            self.disk.cache_set(key.clone(), value.clone())?;
+            if let Some((key, value)) = self.memory.evict_lru() {
+                self.weight = self.weight.saturating_sub((self.weigher)(&key, &value));
+                self.spills += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<K, V, S> CachedRead<K, V> for SpilloverCache<K, V, S>
+where
+    K: Display + Hash + Eq + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+    S: DiskSerializer,
+{
+    /// Return the number of values in memory
+    fn cache_size(&self) -> usize {
+        self.memory.cache_size()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.disk.cache_lifespan()
+    }
+
+    /// Look up a value in memory, the spilled values not being returned
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.memory.cache_peek(key)
+    }
+
+    /// Iterate the values in memory
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.memory.cache_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cold_values_spill_and_are_promoted() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let disk = DiskCache::new("spillover")
+            .set_disk_directory(dir.path())
+            .build()
+            .unwrap();
+        let mut c = SpilloverCache::with_weigher(disk, 10, |_: &u32, value: &String| value.len());
+
+        assert_eq!(c.cache_set(1, "aaaa".to_string()).unwrap(), None);
+        assert_eq!(c.cache_set(2, "bbbb".to_string()).unwrap(), None);
+        assert_eq!(c.cache_set(3, "cccc".to_string()).unwrap(), None);
+        // 1 was spilled to stay within 10 bytes
+        assert_eq!((c.cache_size(), c.weight(), c.spills()), (2, 8, 1));
+        assert_eq!(c.disk().cache_get(&1).unwrap(), Some("aaaa".to_string()));
+
+        // reading 1 promotes it, spilling 2
+        assert_eq!(c.cache_get(&1).unwrap(), Some(&"aaaa".to_string()));
+        assert_eq!(c.disk().cache_get(&1).unwrap(), None);
+        assert_eq!(c.disk_hits(), 1);
+        assert_eq!(c.spills(), 2);
+
+        // setting a spilled key returns its value from the disk
+        assert_eq!(
+            c.cache_set(2, "dd".to_string()).unwrap(),
+            Some("bbbb".to_string())
+        );
+        assert_eq!(c.cache_remove(&2).unwrap(), Some("dd".to_string()));
+        assert_eq!(c.cache_get(&2).unwrap(), None);
+        assert_eq!((c.cache_hits(), c.cache_misses()), (Some(1), Some(1)));
+
+        // the most recent value is kept in memory even over budget
+        c.cache_set(4, "e".repeat(20)).unwrap();
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(c.weight(), 20);
+
+        c.cache_clear().unwrap();
+        assert_eq!(c.cache_get(&1).unwrap(), None);
+        assert_eq!(c.weight(), 0);
+    }
+}
@@ -0,0 +1,504 @@
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
+use crate::IOCached;
+use directories::BaseDirs;
+use instant::Duration;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::UNIX_EPOCH;
+use std::{fmt::Display, path::PathBuf, time::SystemTime};
+
+pub struct SqliteCacheBuilder<K, V> {
+    seconds: Option<u64>,
+    refresh: bool,
+    disk_dir: Option<PathBuf>,
+    cache_name: String,
+    _phantom: PhantomData<(K, V)>,
+}
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SqliteCacheBuildError {
+    #[error("Storage connection error")]
+    ConnectionError(#[from] rusqlite::Error),
+    #[error("Unable to create cache directory")]
+    DirectoryError(#[from] std::io::Error),
+}
+
+static SQLITE_FILE_PREFIX: &str = "cached_sqlite_cache";
+const SQLITE_FILE_VERSION: u64 = 1;
+// How long a connection waits for another connection (or process)
+// holding a lock on the database file before giving up.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl<K, V> SqliteCacheBuilder<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    /// Initialize a `SqliteCacheBuilder`
+    pub fn new<S: AsRef<str>>(cache_name: S) -> SqliteCacheBuilder<K, V> {
+        Self {
+            seconds: None,
+            refresh: false,
+            disk_dir: None,
+            cache_name: cache_name.as_ref().to_string(),
+            _phantom: Default::default(),
+        }
+    }
+
+    /// Specify the cache TTL/lifespan in seconds
+    pub fn set_lifespan(mut self, seconds: u64) -> Self {
+        self.seconds = Some(seconds);
+        self
+    }
+
+    /// Specify whether cache hits refresh the TTL
+    pub fn set_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Set the disk path for where the database file will be stored.
+    /// Caches built with the same directory share a database file,
+    /// their values are told apart by the cache name.
+    pub fn set_disk_directory<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.disk_dir = Some(dir.as_ref().into());
+        self
+    }
+
+    fn default_disk_dir() -> PathBuf {
+        BaseDirs::new()
+            .map(|base_dirs| {
+                let exe_name = std::env::current_exe()
+                    .ok()
+                    .and_then(|path| {
+                        path.file_name()
+                            .and_then(|os_str| os_str.to_str().map(|s| format!("{}_", s)))
+                    })
+                    .unwrap_or_default();
+                let dir_prefix = format!("{}{}", exe_name, SQLITE_FILE_PREFIX);
+                base_dirs.cache_dir().join(dir_prefix)
+            })
+            .unwrap_or_else(|| {
+                std::env::current_dir().expect("sqlite cache unable to determine current directory")
+            })
+    }
+
+    fn open_database(path: &Path) -> Result<Connection, SqliteCacheBuildError> {
+        let connection = Connection::open(path)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS cached_values (
+                cache_name TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value BLOB NOT NULL,
+                expires_at INTEGER,
+                PRIMARY KEY (cache_name, key)
+            ) WITHOUT ROWID;
+            CREATE INDEX IF NOT EXISTS cached_values_expires_at
+                ON cached_values (expires_at) WHERE expires_at IS NOT NULL;",
+        )?;
+        Ok(connection)
+    }
+
+    pub fn build(self) -> Result<SqliteCache<K, V>, SqliteCacheBuildError> {
+        let disk_dir = self.disk_dir.unwrap_or_else(|| Self::default_disk_dir());
+        std::fs::create_dir_all(&disk_dir)?;
+        let disk_path = disk_dir.join(format!("cached_v{}.sqlite3", SQLITE_FILE_VERSION));
+        let connection = Self::open_database(&disk_path)?;
+
+        Ok(SqliteCache {
+            seconds: self.seconds,
+            refresh: self.refresh,
+            version: SQLITE_FILE_VERSION,
+            disk_path,
+            cache_name: self.cache_name,
+            connection: Mutex::new(connection),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            _phantom: self._phantom,
+        })
+    }
+}
+
+/// Cache store backed by a SQLite database file
+///
+/// Values of all caches sharing a directory are stored in a single
+/// `cached_values` table of one database file, keyed by cache name and key,
+/// along with their expiry time. Expired values are never returned, and can be
+/// dropped with `remove_expired_entries` (followed by `vacuum` to shrink the file).
+pub struct SqliteCache<K, V> {
+    pub(super) seconds: Option<u64>,
+    pub(super) refresh: bool,
+    #[allow(unused)]
+    version: u64,
+    disk_path: PathBuf,
+    cache_name: String,
+    connection: Mutex<Connection>,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> SqliteCache<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    #[allow(clippy::new_ret_no_self)]
+    /// Initialize a `SqliteCacheBuilder`
+    pub fn new(cache_name: &str) -> SqliteCacheBuilder<K, V> {
+        SqliteCacheBuilder::new(cache_name)
+    }
+
+    /// Return the path of the database file
+    #[must_use]
+    pub fn disk_path(&self) -> &Path {
+        &self.disk_path
+    }
+
+    /// Remove this cache's expired values from the database
+    pub fn remove_expired_entries(&self) -> Result<(), SqliteCacheError> {
+        self.connection()?.execute(
+            "DELETE FROM cached_values WHERE cache_name = ?1 AND expires_at <= ?2",
+            params![self.cache_name, now_millis()],
+        )?;
+        Ok(())
+    }
+
+    /// Rebuild the database file, returning the space freed by removed values
+    /// to the file system. This affects all caches sharing the database file.
+    pub fn vacuum(&self) -> Result<(), SqliteCacheError> {
+        self.connection()?.execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    fn connection(&self) -> Result<MutexGuard<'_, Connection>, SqliteCacheError> {
+        self.connection
+            .lock()
+            .map_err(|_| SqliteCacheError::PoisonedConnection)
+    }
+
+    fn new_expiry(&self) -> Option<i64> {
+        // lifespans too long to be represented never expire
+        self.seconds.map(|seconds| {
+            let millis = i64::try_from(seconds.saturating_mul(1000)).unwrap_or(i64::MAX);
+            now_millis().saturating_add(millis)
+        })
+    }
+
+    /// Decode a stored value, returning `None` if it has expired
+    fn decode(value: &[u8], expires_at: Option<i64>) -> Result<Option<V>, SqliteCacheError> {
+        if is_expired(expires_at, now_millis()) {
+            return Ok(None);
+        }
+        Ok(Some(rmp_serde::from_slice(value)?))
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn is_expired(expires_at: Option<i64>, now: i64) -> bool {
+    expires_at.is_some_and(|expires_at| expires_at <= now)
+}
+
+#[derive(Error, Debug)]
+pub enum SqliteCacheError {
+    #[error("Storage error")]
+    StorageError(#[from] rusqlite::Error),
+    #[error("Connection mutex is poisoned")]
+    PoisonedConnection,
+    #[error("Error deserializing cached value")]
+    CacheDeserializtionError(#[from] rmp_serde::decode::Error),
+    #[error("Error serializing cached value")]
+    CacheSerializtionError(#[from] rmp_serde::encode::Error),
+}
+
+type StoredValue = (Vec<u8>, Option<i64>);
+
+const SELECT_VALUE: &str =
+    "SELECT value, expires_at FROM cached_values WHERE cache_name = ?1 AND key = ?2";
+
+impl<K, V> IOCached<K, V> for SqliteCache<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    type Error = SqliteCacheError;
+
+    fn cache_get(&self, key: &K) -> Result<Option<V>, SqliteCacheError> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let key = key.to_string();
+        let connection = self.connection()?;
+        let stored: Option<StoredValue> = connection
+            .prepare_cached(SELECT_VALUE)?
+            .query_row(params![self.cache_name, key], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?;
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Backend, started.elapsed());
+
+        let value = match stored {
+            Some((value, expires_at)) => match Self::decode(&value, expires_at)? {
+                Some(value) => {
+                    if self.refresh && self.seconds.is_some() {
+                        connection
+                            .prepare_cached(
+                                "UPDATE cached_values SET expires_at = ?3
+                                 WHERE cache_name = ?1 AND key = ?2",
+                            )?
+                            .execute(params![self.cache_name, key, self.new_expiry()])?;
+                    }
+                    Some(value)
+                }
+                None => {
+                    connection
+                        .prepare_cached(
+                            "DELETE FROM cached_values WHERE cache_name = ?1 AND key = ?2",
+                        )?
+                        .execute(params![self.cache_name, key])?;
+                    None
+                }
+            },
+            None => None,
+        };
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(value.is_some(), started.elapsed());
+        Ok(value)
+    }
+
+    fn cache_set(&self, key: K, value: V) -> Result<Option<V>, SqliteCacheError> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let key = key.to_string();
+        let value = rmp_serde::to_vec(&value)?;
+        let mut connection = self.connection()?;
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let transaction = connection.transaction()?;
+        let old: Option<StoredValue> = transaction
+            .prepare_cached(SELECT_VALUE)?
+            .query_row(params![self.cache_name, key], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?;
+        transaction
+            .prepare_cached(
+                "INSERT OR REPLACE INTO cached_values (cache_name, key, value, expires_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?
+            .execute(params![self.cache_name, key, value, self.new_expiry()])?;
+        transaction.commit()?;
+        #[cfg(feature = "latency_stats")]
+        {
+            self.latency
+                .record(Operation::Backend, round_trip.elapsed());
+            self.latency.record(Operation::Insert, started.elapsed());
+        }
+
+        match old {
+            Some((value, expires_at)) => Self::decode(&value, expires_at),
+            None => Ok(None),
+        }
+    }
+
+    fn cache_remove(&self, key: &K) -> Result<Option<V>, SqliteCacheError> {
+        let key = key.to_string();
+        let mut connection = self.connection()?;
+        #[cfg(feature = "latency_stats")]
+        let round_trip = instant::Instant::now();
+        let transaction = connection.transaction()?;
+        let old: Option<StoredValue> = transaction
+            .prepare_cached(SELECT_VALUE)?
+            .query_row(params![self.cache_name, key], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?;
+        transaction
+            .prepare_cached("DELETE FROM cached_values WHERE cache_name = ?1 AND key = ?2")?
+            .execute(params![self.cache_name, key])?;
+        transaction.commit()?;
+        #[cfg(feature = "latency_stats")]
+        self.latency
+            .record(Operation::Backend, round_trip.elapsed());
+
+        match old {
+            Some((value, expires_at)) => Self::decode(&value, expires_at),
+            None => Ok(None),
+        }
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.seconds
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let old = self.seconds;
+        self.seconds = Some(seconds);
+        old
+    }
+
+    fn cache_set_refresh(&mut self, refresh: bool) -> bool {
+        let old = self.refresh;
+        self.refresh = refresh;
+        old
+    }
+
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod test_SqliteCache {
+    use googletest::{
+        assert_that,
+        matchers::{eq, none, ok, some},
+        GoogleTestSupport as _,
+    };
+    use std::thread::sleep;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    macro_rules! temp_dir {
+        () => {
+            TempDir::new().expect("Error creating temp dir")
+        };
+    }
+
+    const TEST_KEY: u32 = 1;
+    const TEST_VAL: u32 = 100;
+    const TEST_KEY_1: u32 = 2;
+    const TEST_VAL_1: u32 = 200;
+    const LIFE_SPAN_2_SECS: u64 = 2;
+    const LIFE_SPAN_1_SEC: u64 = 1;
+
+    #[test]
+    fn cache_get_after_cache_remove_returns_none() {
+        let tmp_dir = temp_dir!();
+        let cache: SqliteCache<u32, u32> = SqliteCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_get(&TEST_KEY), ok(none()));
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        assert_that!(
+            cache.cache_set(TEST_KEY, TEST_VAL_1),
+            ok(some(eq(TEST_VAL))),
+            "Setting an existing key-value should return the old value"
+        );
+        assert_that!(cache.cache_remove(&TEST_KEY), ok(some(eq(TEST_VAL_1))));
+        assert_that!(cache.cache_get(&TEST_KEY), ok(none()));
+    }
+
+    #[test]
+    fn values_expire_when_lifespan_elapses_returning_none() {
+        let tmp_dir = temp_dir!();
+        let cache: SqliteCache<u32, u32> = SqliteCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .set_lifespan(LIFE_SPAN_2_SECS)
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        assert_that!(cache.cache_get(&TEST_KEY), ok(some(eq(TEST_VAL))));
+
+        sleep(Duration::from_secs(LIFE_SPAN_2_SECS));
+        assert_that!(cache.cache_get(&TEST_KEY), ok(none()));
+    }
+
+    #[test]
+    fn longest_lifespan_does_not_expire_values() {
+        let tmp_dir = temp_dir!();
+        let mut cache: SqliteCache<u32, u32> = SqliteCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .build()
+            .unwrap();
+        cache.cache_set_lifespan(u64::MAX);
+
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        assert_that!(cache.cache_get(&TEST_KEY), ok(some(eq(TEST_VAL))));
+    }
+
+    #[test]
+    fn refreshing_on_cache_get_delays_cache_expiry() {
+        let tmp_dir = temp_dir!();
+        let cache: SqliteCache<u32, u32> = SqliteCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .set_lifespan(LIFE_SPAN_2_SECS)
+            .set_refresh(true)
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        sleep(Duration::from_secs(LIFE_SPAN_1_SEC));
+        assert_that!(cache.cache_get(&TEST_KEY), ok(some(eq(TEST_VAL))));
+        sleep(Duration::from_secs(LIFE_SPAN_1_SEC));
+        assert_that!(
+            cache.cache_get(&TEST_KEY),
+            ok(some(eq(TEST_VAL))),
+            "Getting a value should have refreshed its lifespan"
+        );
+    }
+
+    #[test]
+    fn caches_sharing_a_directory_are_separate() {
+        let tmp_dir = temp_dir!();
+        let cache: SqliteCache<u32, u32> = SqliteCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .build()
+            .unwrap();
+        let other: SqliteCache<u32, u32> = SqliteCache::new("other-cache")
+            .set_disk_directory(tmp_dir.path())
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        assert_that!(other.cache_set(TEST_KEY_1, TEST_VAL_1), ok(none()));
+        assert_that!(other.cache_get(&TEST_KEY), ok(none()));
+        assert_that!(cache.cache_get(&TEST_KEY_1), ok(none()));
+        assert_that!(cache.disk_path(), eq(other.disk_path()));
+    }
+
+    #[test]
+    fn remove_expired_entries_drops_expired_values() {
+        let tmp_dir = temp_dir!();
+        let cache: SqliteCache<u32, u32> = SqliteCache::new("test-cache")
+            .set_disk_directory(tmp_dir.path())
+            .set_lifespan(LIFE_SPAN_1_SEC)
+            .build()
+            .unwrap();
+
+        assert_that!(cache.cache_set(TEST_KEY, TEST_VAL), ok(none()));
+        sleep(Duration::from_secs(LIFE_SPAN_1_SEC));
+        cache.remove_expired_entries().unwrap();
+        cache.vacuum().unwrap();
+
+        let count: i64 = cache
+            .connection()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM cached_values", [], |row| row.get(0))
+            .unwrap();
+        assert_that!(count, eq(0));
+    }
+}
@@ -1,19 +1,29 @@
-use instant::Instant;
+use instant::{Duration, Instant};
 use std::cmp::Eq;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
 
 #[cfg(feature = "ahash")]
-use hashbrown::{hash_map::Entry, HashMap};
+use hashbrown::{
+    hash_map::{DefaultHashBuilder, Entry},
+    HashMap,
+};
 
 #[cfg(not(feature = "ahash"))]
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{
+    hash_map::{Entry, RandomState as DefaultHashBuilder},
+    HashMap,
+};
 
 #[cfg(feature = "async")]
-use {super::CachedAsync, async_trait::async_trait, futures::Future};
+use {super::CachedAsync, async_trait::async_trait, futures::Future, std::convert::Infallible};
 
 use crate::CloneCached;
 
-use super::Cached;
+use super::cache_stats::{CacheStats, StatsCounters};
+use super::{random_fraction, Cached, CachedRead, CachedShared, CachedWrite};
+use crate::events::{CacheEvent, EventFeed};
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
 
 /// Enum used for defining the status of time-cached values
 #[derive(Debug)]
@@ -23,20 +33,48 @@ pub(super) enum Status {
     Expired,
 }
 
+/// Return the stamp of a value set now: the current time, randomly moved by up to
+/// `jitter` of the lifespan of `seconds`, so that values set together expire apart
+pub(super) fn stamp(seconds: u64, jitter: f64) -> Instant {
+    let now = Instant::now();
+    if jitter <= 0.0 {
+        return now;
+    }
+    let shift = jitter * (2.0 * random_fraction() - 1.0);
+    let offset = Duration::from_secs(seconds).mul_f64(shift.abs());
+    let stamp = if shift > 0.0 {
+        now.checked_add(offset)
+    } else {
+        now.checked_sub(offset)
+    };
+    stamp.unwrap_or(now)
+}
+
+/// Return the whole seconds elapsed since a stamp, which is in the future
+/// when moved later by a TTL jitter
+pub(super) fn elapsed_secs(stamp: Instant) -> u64 {
+    Instant::now().saturating_duration_since(stamp).as_secs()
+}
+
 /// Cache store bound by time
 ///
 /// Values are timestamped when inserted and are
 /// evicted if expired at time of retrieval.
 ///
+/// Keys are hashed with the `S` hasher, see `with_lifespan_and_hasher`.
+///
 /// Note: This cache is in-memory only
 #[derive(Clone, Debug)]
-pub struct TimedCache<K, V> {
-    pub(super) store: HashMap<K, (Instant, V)>,
+pub struct TimedCache<K, V, S = DefaultHashBuilder> {
+    pub(super) store: HashMap<K, (Instant, V), S>,
     pub(super) seconds: u64,
-    pub(super) hits: u64,
-    pub(super) misses: u64,
+    pub(super) stats: StatsCounters,
+    #[cfg(feature = "latency_stats")]
+    pub(super) latency: LatencyStats,
     pub(super) initial_capacity: Option<usize>,
     pub(super) refresh: bool,
+    pub(super) ttl_jitter: f64,
+    pub(super) events: EventFeed<K, V>,
 }
 
 impl<K: Hash + Eq, V> TimedCache<K, V> {
@@ -51,12 +89,15 @@ impl<K: Hash + Eq, V> TimedCache<K, V> {
     #[must_use]
     pub fn with_lifespan_and_capacity(seconds: u64, size: usize) -> TimedCache<K, V> {
         TimedCache {
-            store: Self::new_store(Some(size)),
+            store: HashMap::with_capacity(size),
             seconds,
-            hits: 0,
-            misses: 0,
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
             initial_capacity: Some(size),
             refresh: false,
+            ttl_jitter: 0.0,
+            events: EventFeed::new(),
         }
     }
 
@@ -65,15 +106,43 @@ impl<K: Hash + Eq, V> TimedCache<K, V> {
     #[must_use]
     pub fn with_lifespan_and_refresh(seconds: u64, refresh: bool) -> TimedCache<K, V> {
         TimedCache {
-            store: Self::new_store(None),
+            store: HashMap::new(),
             seconds,
-            hits: 0,
-            misses: 0,
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
             initial_capacity: None,
             refresh,
+            ttl_jitter: 0.0,
+            events: EventFeed::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> TimedCache<K, V, S> {
+    /// Creates a new `TimedCache` with a specified lifespan, hashing its keys with `hash_builder`
+    #[must_use]
+    pub fn with_lifespan_and_hasher(seconds: u64, hash_builder: S) -> TimedCache<K, V, S> {
+        TimedCache {
+            store: HashMap::with_hasher(hash_builder),
+            seconds,
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            initial_capacity: None,
+            refresh: false,
+            ttl_jitter: 0.0,
+            events: EventFeed::new(),
         }
     }
 
+    /// Return a handle to the hit and miss counters of the cache, readable
+    /// without locking or borrowing the cache
+    #[must_use]
+    pub fn stats_handle(&self) -> CacheStats {
+        self.stats.handle()
+    }
+
     /// Returns if the lifetime is refreshed when the value is retrieved
     #[must_use]
     pub fn refresh(&self) -> bool {
@@ -85,21 +154,48 @@ impl<K: Hash + Eq, V> TimedCache<K, V> {
         self.refresh = refresh;
     }
 
-    fn new_store(capacity: Option<usize>) -> HashMap<K, (Instant, V)> {
-        capacity.map_or_else(HashMap::new, HashMap::with_capacity)
+    /// Returns the fraction of the lifespan by which the expiry of values is randomized
+    #[must_use]
+    pub fn ttl_jitter(&self) -> f64 {
+        self.ttl_jitter
+    }
+
+    /// Sets the fraction of the lifespan, from `0.0` to `1.0`, by which the expiry of
+    /// each value set from now on is randomly moved earlier or later, so that values
+    /// set together do not all expire together
+    ///
+    /// # Panics
+    ///
+    /// Will panic if jitter is not between 0.0 and 1.0
+    pub fn set_ttl_jitter(&mut self, jitter: f64) {
+        if !(0.0..=1.0).contains(&jitter) {
+            panic!("`ttl_jitter` of `TimedCache` must be between 0.0 and 1.0.");
+        }
+        self.ttl_jitter = jitter;
     }
 
     /// Returns a reference to the cache's `store`
     #[must_use]
-    pub fn get_store(&self) -> &HashMap<K, (Instant, V)> {
+    pub fn get_store(&self) -> &HashMap<K, (Instant, V), S> {
         &self.store
     }
 
     /// Remove any expired values from the cache
     pub fn flush(&mut self) {
-        let seconds = self.seconds;
-        self.store
-            .retain(|_, (instant, _)| instant.elapsed().as_secs() < seconds);
+        let (seconds, events) = (self.seconds, &mut self.events);
+        self.store.retain(|key, (instant, _)| {
+            if elapsed_secs(*instant) < seconds {
+                return true;
+            }
+            events.remove(key);
+            false
+        });
+    }
+
+    /// Release the memory the cache keeps beyond its values, e.g. after most of them
+    /// were removed
+    pub fn shrink_to_fit(&mut self) {
+        self.store.shrink_to_fit();
     }
 
     fn status<Q>(&mut self, key: &Q) -> Status
@@ -109,9 +205,9 @@ impl<K: Hash + Eq, V> TimedCache<K, V> {
     {
         let mut val = self.store.get_mut(key);
         if let Some(&mut (instant, _)) = val.as_mut() {
-            if instant.elapsed().as_secs() < self.seconds {
+            if elapsed_secs(*instant) < self.seconds {
                 if self.refresh {
-                    *instant = Instant::now();
+                    *instant = stamp(self.seconds, self.ttl_jitter);
                 }
                 Status::Found
             } else {
@@ -121,26 +217,53 @@ impl<K: Hash + Eq, V> TimedCache<K, V> {
             Status::NotFound
         }
     }
+
+    /// Remove the expired value of `key`, broadcasting its removal
+    fn remove_expired<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        let (key, (_, value)) = self.store.remove_entry(key)?;
+        self.events.remove(&key);
+        Some(value)
+    }
 }
 
-impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> Cached<K, V> for TimedCache<K, V, S> {
     fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
     where
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        match self.status(key) {
+        self.cache_get_key_value(key).map(|(_, value)| value)
+    }
+
+    fn cache_get_key_value<Q>(&mut self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        #[cfg(feature = "latency_stats")]
+        let started = Instant::now();
+        let status = self.status(key);
+        #[cfg(feature = "latency_stats")]
+        self.latency
+            .record_get(matches!(status, Status::Found), started.elapsed());
+        match status {
             Status::NotFound => {
-                self.misses += 1;
+                self.stats.miss();
                 None
             }
             Status::Found => {
-                self.hits += 1;
-                self.store.get(key).map(|stamped| &stamped.1)
+                self.stats.hit();
+                self.store
+                    .get_key_value(key)
+                    .map(|(key, stamped)| (key, &stamped.1))
             }
             Status::Expired => {
-                self.misses += 1;
-                self.store.remove(key).unwrap();
+                self.stats.miss();
+                self.remove_expired(key);
                 None
             }
         }
@@ -151,90 +274,246 @@ impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        match self.status(key) {
+        #[cfg(feature = "latency_stats")]
+        let started = Instant::now();
+        let status = self.status(key);
+        #[cfg(feature = "latency_stats")]
+        self.latency
+            .record_get(matches!(status, Status::Found), started.elapsed());
+        match status {
             Status::NotFound => {
-                self.misses += 1;
+                self.stats.miss();
                 None
             }
             Status::Found => {
-                self.hits += 1;
+                self.stats.hit();
                 self.store.get_mut(key).map(|stamped| &mut stamped.1)
             }
             Status::Expired => {
-                self.misses += 1;
-                self.store.remove(key).unwrap();
+                self.stats.miss();
+                self.remove_expired(key);
                 None
             }
         }
     }
 
+    fn cache_drain_where<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        mut drain: F,
+    ) -> impl Iterator<Item = (K, V)> {
+        let mut drained = Vec::new();
+        let mut kept = Vec::new();
+        for (key, (instant, value)) in self.store.drain() {
+            if elapsed_secs(instant) >= self.seconds {
+                self.events.remove(&key);
+                continue;
+            }
+            if drain(&key, &value) {
+                self.events.remove(&key);
+                drained.push((key, value));
+            } else {
+                kept.push((key, (instant, value)));
+            }
+        }
+        self.store.extend(kept);
+        drained.into_iter()
+    }
+
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut keep: F) {
+        let (seconds, events) = (self.seconds, &mut self.events);
+        self.store.retain(|key, (instant, value)| {
+            if elapsed_secs(*instant) >= seconds {
+                events.remove(key);
+                return false;
+            }
+            if keep(key, value) {
+                return true;
+            }
+            events.remove(key);
+            false
+        });
+    }
+
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
         match self.store.entry(key) {
             Entry::Occupied(mut occupied) => {
-                if occupied.get().0.elapsed().as_secs() < self.seconds {
+                if elapsed_secs(occupied.get().0) < self.seconds {
                     if self.refresh {
-                        occupied.get_mut().0 = Instant::now();
+                        occupied.get_mut().0 = stamp(self.seconds, self.ttl_jitter);
                     }
-                    self.hits += 1;
+                    self.stats.hit();
                 } else {
-                    self.misses += 1;
+                    self.stats.miss();
                     let val = f();
-                    occupied.insert((Instant::now(), val));
+                    self.events.set(occupied.key(), &val, true);
+                    occupied.insert((stamp(self.seconds, self.ttl_jitter), val));
                 }
                 &mut occupied.into_mut().1
             }
             Entry::Vacant(vacant) => {
-                self.misses += 1;
+                self.stats.miss();
                 let val = f();
-                &mut vacant.insert((Instant::now(), val)).1
+                self.events.set(vacant.key(), &val, false);
+                &mut vacant.insert((stamp(self.seconds, self.ttl_jitter), val)).1
             }
         }
     }
 
+    fn cache_subscribe(&mut self) -> Option<std::sync::mpsc::Receiver<CacheEvent<K, V>>>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        Some(self.events.subscribe())
+    }
+}
+
+/// Expired values are not removed by shared lookups, and the lifespan of the values
+/// found is not refreshed.
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> CachedShared<K, V> for TimedCache<K, V, S> {
+    fn cache_get_shared<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        #[cfg(feature = "latency_stats")]
+        let started = Instant::now();
+        let v = self
+            .store
+            .get(key)
+            .filter(|(instant, _)| elapsed_secs(*instant) < self.seconds)
+            .map(|(_, value)| value);
+        if v.is_some() {
+            self.stats.hit();
+        } else {
+            self.stats.miss();
+        }
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(v.is_some(), started.elapsed());
+        v
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> CachedRead<K, V> for TimedCache<K, V, S> {
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.stats.hits())
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.stats.misses())
+    }
+
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        Some(self.seconds)
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store
+            .get(key)
+            .filter(|(instant, _)| elapsed_secs(*instant) < self.seconds)
+            .map(|(_, value)| value)
+    }
+
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let seconds = self.seconds;
+        self.store
+            .iter()
+            .filter(move |(_, (instant, _))| elapsed_secs(*instant) < seconds)
+            .map(|(key, (_, value))| (key, value))
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> CachedWrite<K, V> for TimedCache<K, V, S> {
     fn cache_set(&mut self, key: K, val: V) -> Option<V> {
-        let stamped = (Instant::now(), val);
-        self.store.insert(key, stamped).and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
+        #[cfg(feature = "latency_stats")]
+        let started = Instant::now();
+        let stamped = (stamp(self.seconds, self.ttl_jitter), val);
+        if self.events.has_subscribers() {
+            self.events
+                .set(&key, &stamped.1, self.store.contains_key(&key));
+        }
+        let old = self.store.insert(key, stamped);
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Insert, started.elapsed());
+        old.and_then(|(instant, v)| {
+            if elapsed_secs(instant) < self.seconds {
                 Some(v)
             } else {
                 None
             }
         })
     }
+
+    fn cache_set_many<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        let entries = entries.into_iter();
+        self.store.reserve(entries.size_hint().0);
+        for (key, val) in entries {
+            self.cache_set(key, val);
+        }
+    }
+
     fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
     where
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        self.store.remove(k).and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
-                Some(v)
-            } else {
-                None
+        let (key, (instant, v)) = self.store.remove_entry(k)?;
+        self.events.remove(&key);
+        if elapsed_secs(instant) < self.seconds {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut remove: F) -> Option<usize> {
+        let events = &mut self.events;
+        let mut removed = 0;
+        self.store.retain(|key, _| {
+            if !remove(key) {
+                return true;
             }
-        })
+            events.remove(key);
+            removed += 1;
+            false
+        });
+        Some(removed)
     }
+
     fn cache_clear(&mut self) {
         self.store.clear();
+        self.events.clear();
     }
+
     fn cache_reset_metrics(&mut self) {
-        self.misses = 0;
-        self.hits = 0;
+        self.stats.reset();
+        #[cfg(feature = "latency_stats")]
+        self.latency.reset();
     }
+
     fn cache_reset(&mut self) {
-        self.store = Self::new_store(self.initial_capacity);
-    }
-    fn cache_size(&self) -> usize {
-        self.store.len()
-    }
-    fn cache_hits(&self) -> Option<u64> {
-        Some(self.hits)
-    }
-    fn cache_misses(&self) -> Option<u64> {
-        Some(self.misses)
-    }
-    fn cache_lifespan(&self) -> Option<u64> {
-        Some(self.seconds)
+        self.store = HashMap::with_capacity_and_hasher(
+            self.initial_capacity.unwrap_or(0),
+            self.store.hasher().clone(),
+        );
+        self.events.clear();
     }
 
     fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
@@ -244,7 +523,9 @@ impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
     }
 }
 
-impl<K: Hash + Eq + Clone, V: Clone> CloneCached<K, V> for TimedCache<K, V> {
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> CloneCached<K, V>
+    for TimedCache<K, V, S>
+{
     fn cache_get_expired<Q>(&mut self, k: &Q) -> (Option<V>, bool)
     where
         K: std::borrow::Borrow<Q>,
@@ -252,16 +533,16 @@ impl<K: Hash + Eq + Clone, V: Clone> CloneCached<K, V> for TimedCache<K, V> {
     {
         match self.status(k) {
             Status::NotFound => {
-                self.misses += 1;
+                self.stats.miss();
                 (None, false)
             }
             Status::Found => {
-                self.hits += 1;
+                self.stats.hit();
                 (self.store.get(k).map(|stamped| &stamped.1).cloned(), false)
             }
             Status::Expired => {
-                self.misses += 1;
-                (self.store.remove(k).map(|stamped| stamped.1), true)
+                self.stats.miss();
+                (self.remove_expired(k), true)
             }
         }
     }
@@ -269,9 +550,10 @@ impl<K: Hash + Eq + Clone, V: Clone> CloneCached<K, V> for TimedCache<K, V> {
 
 #[cfg(feature = "async")]
 #[async_trait]
-impl<K, V> CachedAsync<K, V> for TimedCache<K, V>
+impl<K, V, S> CachedAsync<K, V> for TimedCache<K, V, S>
 where
     K: Hash + Eq + Clone + Send,
+    S: BuildHasher + Clone + Send,
 {
     async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
     where
@@ -281,20 +563,24 @@ where
     {
         match self.store.entry(k) {
             Entry::Occupied(mut occupied) => {
-                if occupied.get().0.elapsed().as_secs() < self.seconds {
+                if elapsed_secs(occupied.get().0) < self.seconds {
                     if self.refresh {
-                        occupied.get_mut().0 = Instant::now();
+                        occupied.get_mut().0 = stamp(self.seconds, self.ttl_jitter);
                     }
-                    self.hits += 1;
+                    self.stats.hit();
                 } else {
-                    self.misses += 1;
-                    occupied.insert((Instant::now(), f().await));
+                    self.stats.miss();
+                    let val = f().await;
+                    self.events.set(occupied.key(), &val, true);
+                    occupied.insert((stamp(self.seconds, self.ttl_jitter), val));
                 }
                 &mut occupied.into_mut().1
             }
             Entry::Vacant(vacant) => {
-                self.misses += 1;
-                &mut vacant.insert((Instant::now(), f().await)).1
+                self.stats.miss();
+                let val = f().await;
+                self.events.set(vacant.key(), &val, false);
+                &mut vacant.insert((stamp(self.seconds, self.ttl_jitter), val)).1
             }
         }
     }
@@ -307,20 +593,80 @@ where
     {
         let v = match self.store.entry(k) {
             Entry::Occupied(mut occupied) => {
-                if occupied.get().0.elapsed().as_secs() < self.seconds {
+                if elapsed_secs(occupied.get().0) < self.seconds {
+                    if self.refresh {
+                        occupied.get_mut().0 = stamp(self.seconds, self.ttl_jitter);
+                    }
+                    self.stats.hit();
+                } else {
+                    self.stats.miss();
+                    let val = f().await?;
+                    self.events.set(occupied.key(), &val, true);
+                    occupied.insert((stamp(self.seconds, self.ttl_jitter), val));
+                }
+                &mut occupied.into_mut().1
+            }
+            Entry::Vacant(vacant) => {
+                self.stats.miss();
+                let val = f().await?;
+                self.events.set(vacant.key(), &val, false);
+                &mut vacant.insert((stamp(self.seconds, self.ttl_jitter), val)).1
+            }
+        };
+
+        Ok(v)
+    }
+
+    async fn get_or_set_with_if_valid<F, Fut, P, PFut>(&mut self, k: K, f: F, is_valid: P) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send,
+    {
+        let setter = || async { Ok::<_, Infallible>(f().await) };
+        match self.try_get_or_set_with_if_valid(k, setter, is_valid).await {
+            Ok(v) => v,
+            Err(never) => match never {},
+        }
+    }
+
+    async fn try_get_or_set_with_if_valid<F, Fut, P, PFut, E>(
+        &mut self,
+        k: K,
+        f: F,
+        is_valid: P,
+    ) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send,
+    {
+        let v = match self.store.entry(k) {
+            Entry::Occupied(mut occupied) => {
+                if elapsed_secs(occupied.get().0) < self.seconds
+                    && is_valid(&occupied.get().1).await
+                {
                     if self.refresh {
-                        occupied.get_mut().0 = Instant::now();
+                        occupied.get_mut().0 = stamp(self.seconds, self.ttl_jitter);
                     }
-                    self.hits += 1;
+                    self.stats.hit();
                 } else {
-                    self.misses += 1;
-                    occupied.insert((Instant::now(), f().await?));
+                    self.stats.miss();
+                    let val = f().await?;
+                    self.events.set(occupied.key(), &val, true);
+                    occupied.insert((stamp(self.seconds, self.ttl_jitter), val));
                 }
                 &mut occupied.into_mut().1
             }
             Entry::Vacant(vacant) => {
-                self.misses += 1;
-                &mut vacant.insert((Instant::now(), f().await?)).1
+                self.stats.miss();
+                let val = f().await?;
+                self.events.set(vacant.key(), &val, false);
+                &mut vacant.insert((stamp(self.seconds, self.ttl_jitter), val)).1
             }
         };
 
@@ -483,6 +829,47 @@ mod tests {
         assert_eq!(0, c.cache_size());
     }
 
+    #[test]
+    fn peek_and_drain() {
+        let mut c = TimedCache::with_lifespan_and_refresh(1, true);
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_peek(&1), Some(&100));
+        assert!(c.cache_contains(&1));
+        assert!(!c.cache_contains(&2));
+        assert_eq!((c.cache_hits(), c.cache_misses()), (Some(0), Some(0)));
+
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        // the lifespan was not refreshed, and the expired value is not removed
+        assert_eq!(c.cache_peek(&1), None);
+        assert_eq!(1, c.cache_size());
+        assert_eq!(c.cache_iter().count(), 0);
+
+        // the expired value is dropped rather than drained
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_drain().collect::<Vec<_>>(), [(2, 200)]);
+        assert_eq!(0, c.cache_size());
+    }
+
+    #[test]
+    fn retain() {
+        let mut c = TimedCache::with_lifespan(1);
+        c.cache_set(1, 100);
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        c.cache_set(2, 200);
+        c.cache_set(3, 300);
+        let mut seen = Vec::new();
+        c.cache_retain(|k, _| {
+            seen.push(*k);
+            *k != 2
+        });
+        // 1 expired, and is dropped without being passed to `keep`
+        seen.sort_unstable();
+        assert_eq!(seen, [2, 3]);
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(c.cache_peek(&3), Some(&300));
+    }
+
     #[test]
     fn get_mut_expired() {
         let mut c = TimedCache::with_lifespan(1);
@@ -498,6 +885,31 @@ mod tests {
         assert_eq!(0, c.cache_size());
     }
 
+    #[test]
+    fn ttl_jitter() {
+        let now = Instant::now();
+        let stamps: Vec<_> = (0..20).map(|_| stamp(100, 0.5)).collect();
+        assert!(stamps.iter().all(|s| now
+            .checked_duration_since(*s)
+            .unwrap_or_default()
+            .as_secs()
+            <= 51));
+        assert!(stamps.iter().any(|s| *s > now + Duration::from_secs(1)));
+        assert!(stamps.iter().any(|s| *s < now));
+        assert_eq!(elapsed_secs(now + Duration::from_secs(10)), 0);
+
+        let mut c = TimedCache::with_lifespan(100);
+        c.set_ttl_jitter(0.5);
+        c.cache_set(1, 100);
+        assert_eq!(c.cache_get(&1), Some(&100));
+    }
+
+    #[test]
+    #[should_panic]
+    fn ttl_jitter_out_of_range() {
+        TimedCache::<u32, u32>::with_lifespan(1).set_ttl_jitter(1.5);
+    }
+
     #[test]
     fn flush_expired() {
         let mut c = TimedCache::with_lifespan(1);
@@ -505,12 +917,33 @@ mod tests {
         assert_eq!(c.cache_set(1, 100), None);
         assert_eq!(c.cache_set(1, 200), Some(100));
         assert_eq!(c.cache_size(), 1);
+        let events = c.cache_subscribe().unwrap();
 
         std::thread::sleep(std::time::Duration::from_secs(1));
         // still around until we flush
         assert_eq!(1, c.cache_size());
         c.flush();
         assert_eq!(0, c.cache_size());
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [CacheEvent::Remove { seq: 1, key: 1 }]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "latency_stats")]
+    fn latency_stats() {
+        let mut c = TimedCache::with_lifespan(1);
+        c.cache_set(1, 100);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        sleep(Duration::new(1, 0));
+        // expired values are recorded as misses
+        assert_eq!(c.cache_get(&1), None);
+
+        let stats = c.cache_latency_stats().unwrap();
+        assert_eq!(stats.insert.count(), 1);
+        assert_eq!(stats.get_hit.count(), 1);
+        assert_eq!(stats.get_miss.count(), 1);
     }
 
     #[test]
@@ -540,4 +973,17 @@ mod tests {
 
         assert_eq!(c.cache_misses(), Some(7));
     }
+
+    #[test]
+    fn get_shared() {
+        let mut c = TimedCache::with_lifespan(1);
+        c.cache_set(1, 100);
+        assert_eq!(c.cache_get_shared(&1), Some(&100));
+        assert_eq!(c.cache_get_shared(&2), None);
+        sleep(Duration::new(1, 0));
+        // the expired value is a miss, left in the cache
+        assert_eq!(c.cache_get_shared(&1), None);
+        assert_eq!((c.cache_hits(), c.cache_misses()), (Some(1), Some(2)));
+        assert_eq!(c.cache_size(), 1);
+    }
 }
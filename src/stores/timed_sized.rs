@@ -1,14 +1,28 @@
 use std::cmp::Eq;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
 
 use instant::Instant;
 
+#[cfg(feature = "ahash")]
+use ahash::RandomState;
+
+#[cfg(not(feature = "ahash"))]
+use std::collections::hash_map::RandomState;
+
 #[cfg(feature = "async")]
-use {super::CachedAsync, async_trait::async_trait, futures::Future};
+use {super::CachedAsync, async_trait::async_trait, futures::Future, std::convert::Infallible};
 
-use crate::{stores::timed::Status, CloneCached};
+use crate::{
+    stores::timed::{elapsed_secs, stamp, Status},
+    CloneCached,
+};
 
-use super::{Cached, SizedCache};
+use super::cache_stats::{CacheStats, StatsCounters};
+use super::CacheBuildError;
+use super::{Cached, CachedRead, CachedWrite, SizedCache};
+use crate::events::{CacheEvent, EventFeed};
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
 
 /// Timed LRU Cache
 ///
@@ -17,15 +31,20 @@ use super::{Cached, SizedCache};
 /// Time expiration is determined based on entry insertion time..
 /// The TTL of an entry is not updated when retrieved.
 ///
+/// Keys are hashed with the `S` hasher, see `try_with_size_and_lifespan_and_hasher`.
+///
 /// Note: This cache is in-memory only
 #[derive(Clone, Debug)]
-pub struct TimedSizedCache<K, V> {
-    pub(super) store: SizedCache<K, (Instant, V)>,
+pub struct TimedSizedCache<K, V, S = RandomState> {
+    pub(super) store: SizedCache<K, (Instant, V), S>,
     pub(super) size: usize,
     pub(super) seconds: u64,
-    pub(super) hits: u64,
-    pub(super) misses: u64,
+    pub(super) stats: StatsCounters,
+    #[cfg(feature = "latency_stats")]
+    pub(super) latency: LatencyStats,
     pub(super) refresh: bool,
+    pub(super) ttl_jitter: f64,
+    pub(super) events: EventFeed<K, V>,
 }
 
 impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
@@ -40,7 +59,7 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
     ///
     /// # Panics
     ///
-    /// Will panic if size is 0
+    /// Will panic if size is 0, see `try_with_size_and_lifespan_and_refresh`
     #[must_use]
     pub fn with_size_and_lifespan_and_refresh(
         size: usize,
@@ -54,9 +73,12 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
             store: SizedCache::with_size(size),
             size,
             seconds,
-            hits: 0,
-            misses: 0,
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
             refresh,
+            ttl_jitter: 0.0,
+            events: EventFeed::new(),
         }
     }
 
@@ -64,22 +86,71 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
     ///
     /// # Errors
     ///
-    /// Will return a `std::io::Error`, depending on the error
+    /// Will return a `CacheBuildError` if size is 0 or the backing data cannot be allocated
     pub fn try_with_size_and_lifespan(
         size: usize,
         seconds: u64,
-    ) -> std::io::Result<TimedSizedCache<K, V>> {
+    ) -> Result<TimedSizedCache<K, V>, CacheBuildError> {
+        Self::try_with_size_and_lifespan_and_refresh(size, seconds, false)
+    }
+
+    /// Creates a new `TimedSizedCache` with a specified lifespan and a given size limit and pre-allocated backing data.
+    /// Also set if the ttl should be refreshed on retrieving
+    ///
+    /// # Errors
+    ///
+    /// Will return a `CacheBuildError` if size is 0 or the backing data cannot be allocated
+    pub fn try_with_size_and_lifespan_and_refresh(
+        size: usize,
+        seconds: u64,
+        refresh: bool,
+    ) -> Result<TimedSizedCache<K, V>, CacheBuildError> {
+        let mut cache =
+            Self::try_with_size_and_lifespan_and_hasher(size, seconds, RandomState::new())?;
+        cache.refresh = refresh;
+        Ok(cache)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> TimedSizedCache<K, V, S> {
+    /// Creates a new `TimedSizedCache` with a specified lifespan and a given size limit and pre-allocated backing data,
+    /// hashing its keys with `hash_builder`
+    ///
+    /// # Errors
+    ///
+    /// Will return a `CacheBuildError` if size is 0 or the backing data cannot be allocated
+    pub fn try_with_size_and_lifespan_and_hasher(
+        size: usize,
+        seconds: u64,
+        hash_builder: S,
+    ) -> Result<TimedSizedCache<K, V, S>, CacheBuildError> {
         if size == 0 {
-            // EINVAL
-            return Err(std::io::Error::from_raw_os_error(22));
+            return Err(CacheBuildError::zero("TimedSizedCache", "size"));
         }
         Ok(TimedSizedCache {
-            store: SizedCache::try_with_size(size)?,
+            store: SizedCache::try_with_size_and_hasher(size, hash_builder).map_err(
+                |e| match e {
+                    CacheBuildError::CapacityOverflow { capacity, .. } => {
+                        CacheBuildError::CapacityOverflow {
+                            store: "TimedSizedCache",
+                            capacity,
+                        }
+                    }
+                    CacheBuildError::Allocation { capacity, .. } => CacheBuildError::Allocation {
+                        store: "TimedSizedCache",
+                        capacity,
+                    },
+                    e => e,
+                },
+            )?,
             size,
             seconds,
-            hits: 0,
-            misses: 0,
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
             refresh: false,
+            ttl_jitter: 0.0,
+            events: EventFeed::new(),
         })
     }
 
@@ -87,7 +158,7 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
         let max_seconds = self.seconds;
         self.store
             .iter_order()
-            .filter(move |(_k, stamped)| stamped.0.elapsed().as_secs() < max_seconds)
+            .filter(move |(_k, stamped)| elapsed_secs(stamped.0) < max_seconds)
     }
 
     /// Return an iterator of keys in the current order from most
@@ -104,6 +175,50 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
         self.iter_order().map(|(_k, v)| v)
     }
 
+    /// Remove the least recently used value, returning it along with its key. The
+    /// expired values found on the way are dropped.
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        while let Some((key, (instant, value))) = self.store.pop_lru() {
+            self.events.remove(&key);
+            if elapsed_secs(instant) < self.seconds {
+                return Some((key, value));
+            }
+        }
+        None
+    }
+
+    /// Set the maximum number of values, returning the unexpired values evicted to stay
+    /// within it, from least to most recently used
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0
+    pub fn set_capacity(&mut self, size: usize) -> Vec<(K, V)> {
+        let seconds = self.seconds;
+        let evicted = self.store.set_capacity(size);
+        for (key, _) in &evicted {
+            self.events.remove(key);
+        }
+        evicted
+            .into_iter()
+            .filter(|(_, (instant, _))| elapsed_secs(*instant) < seconds)
+            .map(|(key, (_, value))| (key, value))
+            .collect()
+    }
+
+    /// Release the memory the cache keeps beyond its values, e.g. after most of them
+    /// were removed
+    pub fn shrink_to_fit(&mut self) {
+        self.store.shrink_to_fit();
+    }
+
+    /// Return a handle to the hit and miss counters of the cache, readable
+    /// without locking or borrowing the cache
+    #[must_use]
+    pub fn stats_handle(&self) -> CacheStats {
+        self.stats.handle()
+    }
+
     /// Returns if the lifetime is refreshed when the value is retrieved
     #[must_use]
     pub fn refresh(&self) -> bool {
@@ -115,17 +230,72 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
         self.refresh = refresh;
     }
 
+    /// Returns the fraction of the lifespan by which the expiry of values is randomized
+    #[must_use]
+    pub fn ttl_jitter(&self) -> f64 {
+        self.ttl_jitter
+    }
+
+    /// Sets the fraction of the lifespan, from `0.0` to `1.0`, by which the expiry of
+    /// each value set from now on is randomly moved earlier or later, so that values
+    /// set together do not all expire together
+    ///
+    /// # Panics
+    ///
+    /// Will panic if jitter is not between 0.0 and 1.0
+    pub fn set_ttl_jitter(&mut self, jitter: f64) {
+        if !(0.0..=1.0).contains(&jitter) {
+            panic!("`ttl_jitter` of `TimedSizedCache` must be between 0.0 and 1.0.");
+        }
+        self.ttl_jitter = jitter;
+    }
+
     /// Returns a reference to the cache's `store`
     #[must_use]
-    pub fn get_store(&self) -> &SizedCache<K, (Instant, V)> {
+    pub fn get_store(&self) -> &SizedCache<K, (Instant, V), S> {
         &self.store
     }
 
     /// Remove any expired values from the cache
     pub fn flush(&mut self) {
         let seconds = self.seconds;
-        self.store
-            .retain(|_, (instant, _)| instant.elapsed().as_secs() < seconds);
+        self.drain_stamped(|_, (instant, _)| elapsed_secs(*instant) >= seconds);
+    }
+
+    /// Remove the values `drain` returns `true` for, expired or not, broadcasting their
+    /// removal, and return them from most to least recently used
+    fn drain_stamped<F: FnMut(&K, &(Instant, V)) -> bool>(
+        &mut self,
+        drain: F,
+    ) -> Vec<(K, (Instant, V))> {
+        let drained = self.store.cache_drain_where(drain).collect::<Vec<_>>();
+        for (key, _) in &drained {
+            self.events.remove(key);
+        }
+        drained
+    }
+
+    /// Remove the expired value of `key`, broadcasting its removal
+    fn remove_expired<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        let (key, (_, value)) = self.store.remove_entry(key)?;
+        self.events.remove(&key);
+        Some(value)
+    }
+
+    /// Return the key of the value that setting `key` would evict, when there are
+    /// subscribers to broadcast its eviction to
+    fn victim(&self, key: &K) -> Option<K> {
+        if !self.events.has_subscribers()
+            || self.store.cache_size() < self.store.capacity
+            || self.store.contains_key(key)
+        {
+            return None;
+        }
+        self.store.lru_key().cloned()
     }
 
     fn status<Q>(&mut self, key: &Q) -> Status
@@ -135,9 +305,9 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
     {
         let mut val = self.store.get_mut_if(key, |_| true);
         if let Some(&mut (instant, _)) = val.as_mut() {
-            if instant.elapsed().as_secs() < self.seconds {
+            if elapsed_secs(*instant) < self.seconds {
                 if self.refresh {
-                    *instant = Instant::now();
+                    *instant = stamp(self.seconds, self.ttl_jitter);
                 }
                 Status::Found
             } else {
@@ -149,24 +319,40 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
     }
 }
 
-impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> Cached<K, V> for TimedSizedCache<K, V, S> {
     fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
     where
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        match self.status(key) {
+        self.cache_get_key_value(key).map(|(_, value)| value)
+    }
+
+    fn cache_get_key_value<Q>(&mut self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        #[cfg(feature = "latency_stats")]
+        let started = Instant::now();
+        let status = self.status(key);
+        #[cfg(feature = "latency_stats")]
+        self.latency
+            .record_get(matches!(status, Status::Found), started.elapsed());
+        match status {
             Status::NotFound => {
-                self.misses += 1;
+                self.stats.miss();
                 None
             }
             Status::Found => {
-                self.hits += 1;
-                self.store.cache_get(key).map(|stamped| &stamped.1)
+                self.stats.hit();
+                self.store
+                    .cache_get_key_value(key)
+                    .map(|(key, stamped)| (key, &stamped.1))
             }
             Status::Expired => {
-                self.misses += 1;
-                self.store.cache_remove(key);
+                self.stats.miss();
+                self.remove_expired(key);
                 None
             }
         }
@@ -177,45 +363,146 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        match self.status(key) {
+        #[cfg(feature = "latency_stats")]
+        let started = Instant::now();
+        let status = self.status(key);
+        #[cfg(feature = "latency_stats")]
+        self.latency
+            .record_get(matches!(status, Status::Found), started.elapsed());
+        match status {
             Status::NotFound => {
-                self.misses += 1;
+                self.stats.miss();
                 None
             }
             Status::Found => {
-                self.hits += 1;
+                self.stats.hit();
                 self.store.cache_get_mut(key).map(|stamped| &mut stamped.1)
             }
             Status::Expired => {
-                self.misses += 1;
-                self.store.cache_remove(key);
+                self.stats.miss();
+                self.remove_expired(key);
                 None
             }
         }
     }
 
+    /// The values are returned from most to least recently used.
+    fn cache_drain_where<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        mut drain: F,
+    ) -> impl Iterator<Item = (K, V)> {
+        let seconds = self.seconds;
+        let live = move |instant: &Instant| elapsed_secs(*instant) < seconds;
+        self.drain_stamped(|key, (instant, value)| !live(instant) || drain(key, value))
+            .into_iter()
+            .filter(move |(_, (instant, _))| live(instant))
+            .map(|(key, (_, value))| (key, value))
+    }
+
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut keep: F) {
+        let seconds = self.seconds;
+        self.drain_stamped(|key, (instant, value)| {
+            elapsed_secs(*instant) >= seconds || !keep(key, value)
+        });
+    }
+
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
-        let setter = || (Instant::now(), f());
-        let max_seconds = self.seconds;
+        let (max_seconds, jitter) = (self.seconds, self.ttl_jitter);
+        let setter = || (stamp(max_seconds, jitter), f());
+        let event_key = self.events.has_subscribers().then(|| key.clone());
+        let victim = self.victim(&key);
         let (was_present, was_valid, stamped) =
-            self.store.get_or_set_with_if(key, setter, |stamped| {
-                stamped.0.elapsed().as_secs() < max_seconds
-            });
+            self.store
+                .get_or_set_with_if(key, setter, |stamped| elapsed_secs(stamped.0) < max_seconds);
         if was_present && was_valid {
             if self.refresh {
-                stamped.0 = Instant::now();
+                stamped.0 = stamp(max_seconds, jitter);
             }
-            self.hits += 1;
+            self.stats.hit();
         } else {
-            self.misses += 1;
+            self.stats.miss();
+            if let Some(key) = event_key {
+                self.events.set(&key, &stamped.1, was_present);
+            }
+            if let (false, Some(victim)) = (was_present, victim) {
+                self.events.remove(&victim);
+            }
         }
         &mut stamped.1
     }
 
+    fn cache_subscribe(&mut self) -> Option<std::sync::mpsc::Receiver<CacheEvent<K, V>>>
+    where
+        V: Clone,
+    {
+        Some(self.events.subscribe())
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> CachedRead<K, V> for TimedSizedCache<K, V, S> {
+    fn cache_size(&self) -> usize {
+        self.store.cache_size()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.stats.hits())
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.stats.misses())
+    }
+
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.size)
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        Some(self.seconds)
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store
+            .cache_peek(key)
+            .filter(|(instant, _)| elapsed_secs(*instant) < self.seconds)
+            .map(|(_, value)| value)
+    }
+
+    /// The values are iterated from most to least recently used.
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.iter_order().map(|(key, (_, value))| (key, value))
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> CachedWrite<K, V> for TimedSizedCache<K, V, S> {
     fn cache_set(&mut self, key: K, val: V) -> Option<V> {
-        let stamped = self.store.cache_set(key, (Instant::now(), val));
+        #[cfg(feature = "latency_stats")]
+        let started = Instant::now();
+        if self.events.has_subscribers() {
+            self.events.set(&key, &val, self.store.contains_key(&key));
+        }
+        let (stamped, evicted) = self
+            .store
+            .set_evicting(key, (stamp(self.seconds, self.ttl_jitter), val));
+        if let Some((key, _)) = evicted {
+            self.events.remove(&key);
+        }
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Insert, started.elapsed());
         stamped.and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
+            if elapsed_secs(instant) < self.seconds {
                 Some(v)
             } else {
                 None
@@ -228,40 +515,44 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        let stamped = self.store.cache_remove(k);
-        stamped.and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
-                Some(v)
-            } else {
-                None
-            }
-        })
+        let (key, (instant, v)) = self.store.remove_entry(k)?;
+        self.events.remove(&key);
+        if elapsed_secs(instant) < self.seconds {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut remove: F) -> Option<usize> {
+        let keys = self
+            .store
+            .iter_order()
+            .filter(|(key, _)| remove(key))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in &keys {
+            self.store.remove_entry(key);
+            self.events.remove(key);
+        }
+        Some(keys.len())
     }
+
     fn cache_clear(&mut self) {
         self.store.cache_clear();
+        self.events.clear();
     }
+
     fn cache_reset(&mut self) {
         self.cache_clear();
     }
+
     fn cache_reset_metrics(&mut self) {
-        self.misses = 0;
-        self.hits = 0;
-    }
-    fn cache_size(&self) -> usize {
-        self.store.cache_size()
-    }
-    fn cache_hits(&self) -> Option<u64> {
-        Some(self.hits)
-    }
-    fn cache_misses(&self) -> Option<u64> {
-        Some(self.misses)
-    }
-    fn cache_capacity(&self) -> Option<usize> {
-        Some(self.size)
-    }
-    fn cache_lifespan(&self) -> Option<u64> {
-        Some(self.seconds)
+        self.stats.reset();
+        #[cfg(feature = "latency_stats")]
+        self.latency.reset();
     }
+
     fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
         let old = self.seconds;
         self.seconds = seconds;
@@ -269,7 +560,9 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
     }
 }
 
-impl<K: Hash + Eq + Clone, V: Clone> CloneCached<K, V> for TimedSizedCache<K, V> {
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher> CloneCached<K, V>
+    for TimedSizedCache<K, V, S>
+{
     fn cache_get_expired<Q>(&mut self, k: &Q) -> (Option<V>, bool)
     where
         K: std::borrow::Borrow<Q>,
@@ -277,19 +570,19 @@ impl<K: Hash + Eq + Clone, V: Clone> CloneCached<K, V> for TimedSizedCache<K, V>
     {
         match self.status(k) {
             Status::NotFound => {
-                self.misses += 1;
+                self.stats.miss();
                 (None, false)
             }
             Status::Found => {
-                self.hits += 1;
+                self.stats.hit();
                 (
                     self.store.cache_get(k).map(|stamped| stamped.1.clone()),
                     false,
                 )
             }
             Status::Expired => {
-                self.misses += 1;
-                (self.store.cache_remove(k).map(|stamped| stamped.1), true)
+                self.stats.miss();
+                (self.remove_expired(k), true)
             }
         }
     }
@@ -297,9 +590,10 @@ impl<K: Hash + Eq + Clone, V: Clone> CloneCached<K, V> for TimedSizedCache<K, V>
 
 #[cfg(feature = "async")]
 #[async_trait]
-impl<K, V> CachedAsync<K, V> for TimedSizedCache<K, V>
+impl<K, V, S> CachedAsync<K, V> for TimedSizedCache<K, V, S>
 where
     K: Hash + Eq + Clone + Send,
+    S: BuildHasher + Send,
 {
     async fn get_or_set_with<F, Fut>(&mut self, key: K, f: F) -> &mut V
     where
@@ -307,21 +601,27 @@ where
         F: FnOnce() -> Fut + Send,
         Fut: Future<Output = V> + Send,
     {
-        let setter = || async { (Instant::now(), f().await) };
-        let max_seconds = self.seconds;
+        let (max_seconds, jitter) = (self.seconds, self.ttl_jitter);
+        let setter = || async { (stamp(max_seconds, jitter), f().await) };
+        let event_key = self.events.has_subscribers().then(|| key.clone());
+        let victim = self.victim(&key);
         let (was_present, was_valid, stamped) = self
             .store
-            .get_or_set_with_if_async(key, setter, |stamped| {
-                stamped.0.elapsed().as_secs() < max_seconds
-            })
+            .get_or_set_with_if_async(key, setter, |stamped| elapsed_secs(stamped.0) < max_seconds)
             .await;
         if was_present && was_valid {
             if self.refresh {
-                stamped.0 = Instant::now();
+                stamped.0 = stamp(max_seconds, jitter);
             }
-            self.hits += 1;
+            self.stats.hit();
         } else {
-            self.misses += 1;
+            self.stats.miss();
+            if let Some(key) = event_key {
+                self.events.set(&key, &stamped.1, was_present);
+            }
+            if let (false, Some(victim)) = (was_present, victim) {
+                self.events.remove(&victim);
+            }
         }
         &mut stamped.1
     }
@@ -332,24 +632,106 @@ where
         F: FnOnce() -> Fut + Send,
         Fut: Future<Output = Result<V, E>> + Send,
     {
+        let (max_seconds, jitter) = (self.seconds, self.ttl_jitter);
         let setter = || async {
             let new_val = f().await?;
-            Ok((Instant::now(), new_val))
+            Ok((stamp(max_seconds, jitter), new_val))
         };
-        let max_seconds = self.seconds;
+        let event_key = self.events.has_subscribers().then(|| key.clone());
+        let victim = self.victim(&key);
         let (was_present, was_valid, stamped) = self
             .store
             .try_get_or_set_with_if_async(key, setter, |stamped| {
-                stamped.0.elapsed().as_secs() < max_seconds
+                elapsed_secs(stamped.0) < max_seconds
+            })
+            .await?;
+        if was_present && was_valid {
+            if self.refresh {
+                stamped.0 = stamp(max_seconds, jitter);
+            }
+            self.stats.hit();
+        } else {
+            self.stats.miss();
+            if let Some(key) = event_key {
+                self.events.set(&key, &stamped.1, was_present);
+            }
+            if let (false, Some(victim)) = (was_present, victim) {
+                self.events.remove(&victim);
+            }
+        }
+        Ok(&mut stamped.1)
+    }
+
+    async fn get_or_set_with_if_valid<F, Fut, P, PFut>(
+        &mut self,
+        key: K,
+        f: F,
+        is_valid: P,
+    ) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send,
+    {
+        let setter = || async { Ok::<_, Infallible>(f().await) };
+        match self
+            .try_get_or_set_with_if_valid(key, setter, is_valid)
+            .await
+        {
+            Ok(v) => v,
+            Err(never) => match never {},
+        }
+    }
+
+    async fn try_get_or_set_with_if_valid<F, Fut, P, PFut, E>(
+        &mut self,
+        key: K,
+        f: F,
+        is_valid: P,
+    ) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send,
+    {
+        let (max_seconds, jitter) = (self.seconds, self.ttl_jitter);
+        let setter = || async {
+            let new_val = f().await?;
+            Ok((stamp(max_seconds, jitter), new_val))
+        };
+        let event_key = self.events.has_subscribers().then(|| key.clone());
+        let victim = self.victim(&key);
+        let (was_present, was_valid, stamped) = self
+            .store
+            .try_get_or_set_with_if_valid_async(key, setter, |stamped| {
+                // expired values are not revalidated
+                let is_valid =
+                    (elapsed_secs(stamped.0) < max_seconds).then(|| is_valid(&stamped.1));
+                async move {
+                    match is_valid {
+                        Some(is_valid) => is_valid.await,
+                        None => false,
+                    }
+                }
             })
             .await?;
         if was_present && was_valid {
             if self.refresh {
-                stamped.0 = Instant::now();
+                stamped.0 = stamp(max_seconds, jitter);
             }
-            self.hits += 1;
+            self.stats.hit();
         } else {
-            self.misses += 1;
+            self.stats.miss();
+            if let Some(key) = event_key {
+                self.events.set(&key, &stamped.1, was_present);
+            }
+            if let (false, Some(victim)) = (was_present, victim) {
+                self.events.remove(&victim);
+            }
         }
         Ok(&mut stamped.1)
     }
@@ -362,6 +744,38 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn subscribe() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(5, 2);
+        c.cache_set(1, 100);
+        let events = c.cache_subscribe().unwrap();
+
+        c.cache_set(1, 200);
+        c.cache_get_or_set_with(2, || 300);
+        c.cache_get_or_set_with(2, || 400);
+        c.cache_remove(&1);
+        c.cache_remove(&1);
+        c.cache_clear();
+
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            vec![
+                CacheEvent::Update {
+                    seq: 1,
+                    key: 1,
+                    value: 200
+                },
+                CacheEvent::Insert {
+                    seq: 2,
+                    key: 2,
+                    value: 300
+                },
+                CacheEvent::Remove { seq: 3, key: 1 },
+                CacheEvent::Clear { seq: 4 },
+            ]
+        );
+    }
+
     #[test]
     fn timed_sized_cache() {
         let mut c = TimedSizedCache::with_size_and_lifespan(5, 2);
@@ -443,6 +857,65 @@ mod tests {
         assert_eq!(c.cache_get_or_set_with(1, setter), &1);
     }
 
+    #[test]
+    fn pop_lru() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(5, 1);
+        c.cache_set(1, 100);
+        sleep(Duration::new(1, 0));
+        c.cache_set(2, 200);
+        c.cache_set(3, 300);
+        c.cache_get(&2);
+        // 1 expired, and is dropped rather than returned
+        assert_eq!(c.pop_lru(), Some((3, 300)));
+        assert_eq!(c.pop_lru(), Some((2, 200)));
+        assert_eq!(c.pop_lru(), None);
+    }
+
+    #[test]
+    fn set_many() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(2, 60);
+        c.cache_set_many([(1, 100), (2, 200), (3, 300)]);
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&3, &2]);
+        assert_eq!(c.cache_get(&2), Some(&200));
+
+        let events = c.cache_subscribe().unwrap();
+        c.cache_set_many([(2, 201), (4, 400)]);
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&4, &2]);
+        // 3 is evicted by 4
+        assert_eq!(
+            events.try_iter().last(),
+            Some(CacheEvent::Remove { seq: 3, key: 3 })
+        );
+    }
+
+    #[test]
+    fn get_key_value() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(5, 1);
+        c.cache_set(1, 100);
+        assert_eq!(c.cache_get_key_value(&1), Some((&1, &100)));
+        sleep(Duration::new(1, 0));
+        assert_eq!(c.cache_get_key_value(&1), None);
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!((c.cache_hits(), c.cache_misses()), (Some(1), Some(1)));
+    }
+
+    #[test]
+    fn retain() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(5, 1);
+        c.cache_set(1, 100);
+        sleep(Duration::new(1, 0));
+        c.cache_set(2, 200);
+        c.cache_set(3, 300);
+        let mut seen = Vec::new();
+        c.cache_retain(|k, _| {
+            seen.push(*k);
+            *k != 2
+        });
+        // 1 expired, and is dropped without being passed to `keep`
+        assert_eq!(seen, [3, 2]);
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&3]);
+    }
+
     #[test]
     fn timed_cache_refresh() {
         let mut c = TimedSizedCache::with_size_and_lifespan_and_refresh(2, 2, true);
@@ -469,9 +942,15 @@ mod tests {
 
     #[test]
     fn try_new() {
-        let c: std::io::Result<TimedSizedCache<i32, i32>> =
+        let c: Result<TimedSizedCache<i32, i32>, _> =
             TimedSizedCache::try_with_size_and_lifespan(0, 2);
-        assert_eq!(c.unwrap_err().raw_os_error(), Some(22));
+        assert_eq!(
+            c.unwrap_err(),
+            CacheBuildError::zero("TimedSizedCache", "size")
+        );
+        let c: TimedSizedCache<i32, i32> =
+            TimedSizedCache::try_with_size_and_lifespan_and_refresh(1, 2, true).unwrap();
+        assert!(c.refresh());
     }
 
     #[test]
@@ -577,6 +1056,20 @@ mod tests {
         assert_eq!(0, c.cache_size());
     }
 
+    #[test]
+    fn remove_where() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(3, 60);
+        let events = c.cache_subscribe().unwrap();
+        c.cache_set("a:1", 1);
+        c.cache_set("a:2", 2);
+        c.cache_set("b:1", 3);
+        assert_eq!(c.cache_remove_where(|k| k.starts_with("a:")), Some(2));
+        assert_eq!(c.cache_get(&"a:1"), None);
+        assert_eq!(c.cache_get(&"b:1"), Some(&3));
+        // the removals are broadcast
+        assert_eq!(events.try_iter().count(), 5);
+    }
+
     #[test]
     fn flush_expired() {
         let mut c = TimedSizedCache::with_size_and_lifespan(3, 1);
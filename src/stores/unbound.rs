@@ -1,44 +1,60 @@
-use super::Cached;
+use super::cache_stats::StatsCounters;
+use super::{CacheStats, Cached, CachedRead, CachedShared, CachedWrite};
+use crate::events::{CacheEvent, EventFeed};
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
 
 use std::cmp::Eq;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
 
 #[cfg(feature = "ahash")]
-use hashbrown::{hash_map::Entry, HashMap};
+use hashbrown::{
+    hash_map::{DefaultHashBuilder, Entry},
+    HashMap,
+};
 
 #[cfg(not(feature = "ahash"))]
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{
+    hash_map::{Entry, RandomState as DefaultHashBuilder},
+    HashMap,
+};
 
 #[cfg(feature = "async")]
-use {super::CachedAsync, async_trait::async_trait, futures::Future};
+use {super::CachedAsync, async_trait::async_trait, futures::Future, std::convert::Infallible};
 
 /// Default unbounded cache
 ///
 /// This cache has no size limit or eviction policy.
 ///
+/// Keys are hashed with the `S` hasher, see `with_hasher`.
+///
 /// Note: This cache is in-memory only
 #[derive(Clone, Debug)]
-pub struct UnboundCache<K, V> {
-    pub(super) store: HashMap<K, V>,
-    pub(super) hits: u64,
-    pub(super) misses: u64,
+pub struct UnboundCache<K, V, S = DefaultHashBuilder> {
+    pub(super) store: HashMap<K, V, S>,
+    pub(super) stats: StatsCounters,
+    #[cfg(feature = "latency_stats")]
+    pub(super) latency: LatencyStats,
     pub(super) initial_capacity: Option<usize>,
+    pub(super) events: EventFeed<K, V>,
 }
 
-impl<K, V> PartialEq for UnboundCache<K, V>
+impl<K, V, S> PartialEq for UnboundCache<K, V, S>
 where
     K: Eq + Hash,
     V: PartialEq,
+    S: BuildHasher,
 {
-    fn eq(&self, other: &UnboundCache<K, V>) -> bool {
+    fn eq(&self, other: &UnboundCache<K, V, S>) -> bool {
         self.store.eq(&other.store)
     }
 }
 
-impl<K, V> Eq for UnboundCache<K, V>
+impl<K, V, S> Eq for UnboundCache<K, V, S>
 where
     K: Eq + Hash,
     V: PartialEq,
+    S: BuildHasher,
 {
 }
 
@@ -48,10 +64,12 @@ impl<K: Hash + Eq, V> UnboundCache<K, V> {
     #[must_use]
     pub fn new() -> UnboundCache<K, V> {
         UnboundCache {
-            store: Self::new_store(None),
-            hits: 0,
-            misses: 0,
+            store: HashMap::new(),
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
             initial_capacity: None,
+            events: EventFeed::new(),
         }
     }
 
@@ -59,100 +77,289 @@ impl<K: Hash + Eq, V> UnboundCache<K, V> {
     #[must_use]
     pub fn with_capacity(size: usize) -> UnboundCache<K, V> {
         UnboundCache {
-            store: Self::new_store(Some(size)),
-            hits: 0,
-            misses: 0,
+            store: HashMap::with_capacity(size),
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
             initial_capacity: Some(size),
+            events: EventFeed::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> UnboundCache<K, V, S> {
+    /// Creates an empty `UnboundCache` hashing its keys with `hash_builder`
+    #[must_use]
+    pub fn with_hasher(hash_builder: S) -> UnboundCache<K, V, S> {
+        UnboundCache {
+            store: HashMap::with_hasher(hash_builder),
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            initial_capacity: None,
+            events: EventFeed::new(),
         }
     }
 
-    fn new_store(capacity: Option<usize>) -> HashMap<K, V> {
-        capacity.map_or_else(HashMap::new, HashMap::with_capacity)
+    /// Creates an empty `UnboundCache` with a given pre-allocated capacity, hashing
+    /// its keys with `hash_builder`
+    #[must_use]
+    pub fn with_capacity_and_hasher(size: usize, hash_builder: S) -> UnboundCache<K, V, S> {
+        UnboundCache {
+            store: HashMap::with_capacity_and_hasher(size, hash_builder),
+            stats: StatsCounters::default(),
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            initial_capacity: Some(size),
+            events: EventFeed::new(),
+        }
     }
 
     /// Returns a reference to the cache's `store`
     #[must_use]
-    pub fn get_store(&self) -> &HashMap<K, V> {
+    pub fn get_store(&self) -> &HashMap<K, V, S> {
         &self.store
     }
+
+    /// Release the memory the cache keeps beyond its values, e.g. after most of them
+    /// were removed
+    pub fn shrink_to_fit(&mut self) {
+        self.store.shrink_to_fit();
+    }
+
+    /// Return a handle to the hit and miss counters of the cache, readable
+    /// without locking or borrowing the cache
+    #[must_use]
+    pub fn stats_handle(&self) -> CacheStats {
+        self.stats.handle()
+    }
 }
 
-impl<K: Hash + Eq, V> Cached<K, V> for UnboundCache<K, V> {
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> Cached<K, V> for UnboundCache<K, V, S> {
     fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
     where
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        if let Some(v) = self.store.get(key) {
-            self.hits += 1;
-            Some(v)
+        self.cache_get_key_value(key).map(|(_, value)| value)
+    }
+
+    fn cache_get_key_value<Q>(&mut self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let entry = self.store.get_key_value(key);
+        if entry.is_some() {
+            self.stats.hit();
         } else {
-            self.misses += 1;
-            None
+            self.stats.miss();
         }
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(entry.is_some(), started.elapsed());
+        entry
     }
+
     fn cache_get_mut<Q>(&mut self, key: &Q) -> std::option::Option<&mut V>
     where
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        if let Some(v) = self.store.get_mut(key) {
-            self.hits += 1;
-            Some(v)
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let v = self.store.get_mut(key);
+        if v.is_some() {
+            self.stats.hit();
         } else {
-            self.misses += 1;
-            None
+            self.stats.miss();
         }
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(v.is_some(), started.elapsed());
+        v
     }
-    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
-        self.store.insert(key, val)
+
+    fn cache_drain_where<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        mut drain: F,
+    ) -> impl Iterator<Item = (K, V)> {
+        let mut drained = Vec::new();
+        let mut kept = Vec::new();
+        for (key, value) in self.store.drain() {
+            if drain(&key, &value) {
+                self.events.remove(&key);
+                drained.push((key, value));
+            } else {
+                kept.push((key, value));
+            }
+        }
+        self.store.extend(kept);
+        drained.into_iter()
+    }
+
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut keep: F) {
+        let events = &mut self.events;
+        self.store.retain(|key, value| {
+            if keep(key, value) {
+                return true;
+            }
+            events.remove(key);
+            false
+        });
     }
+
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
         match self.store.entry(key) {
             Entry::Occupied(occupied) => {
-                self.hits += 1;
+                self.stats.hit();
                 occupied.into_mut()
             }
 
             Entry::Vacant(vacant) => {
-                self.misses += 1;
-                vacant.insert(f())
+                self.stats.miss();
+                let value = f();
+                self.events.set(vacant.key(), &value, false);
+                vacant.insert(value)
             }
         }
     }
+
+    fn cache_subscribe(&mut self) -> Option<std::sync::mpsc::Receiver<CacheEvent<K, V>>>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        Some(self.events.subscribe())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> CachedShared<K, V> for UnboundCache<K, V, S> {
+    fn cache_get_shared<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let v = self.store.get(key);
+        if v.is_some() {
+            self.stats.hit();
+        } else {
+            self.stats.miss();
+        }
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(v.is_some(), started.elapsed());
+        v
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> CachedRead<K, V> for UnboundCache<K, V, S> {
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.stats.hits())
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.stats.misses())
+    }
+
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store.get(key)
+    }
+
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.store.iter()
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> CachedWrite<K, V> for UnboundCache<K, V, S> {
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        if self.events.has_subscribers() {
+            self.events.set(&key, &val, self.store.contains_key(&key));
+        }
+        let old = self.store.insert(key, val);
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Insert, started.elapsed());
+        old
+    }
+
+    fn cache_set_many<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        let entries = entries.into_iter();
+        self.store.reserve(entries.size_hint().0);
+        for (key, val) in entries {
+            self.cache_set(key, val);
+        }
+    }
+
     fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
     where
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized,
     {
-        self.store.remove(k)
+        let (key, value) = self.store.remove_entry(k)?;
+        self.events.remove(&key);
+        Some(value)
+    }
+
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut remove: F) -> Option<usize> {
+        let events = &mut self.events;
+        let mut removed = 0;
+        self.store.retain(|key, _| {
+            if !remove(key) {
+                return true;
+            }
+            events.remove(key);
+            removed += 1;
+            false
+        });
+        Some(removed)
     }
+
     fn cache_clear(&mut self) {
         self.store.clear();
+        self.events.clear();
     }
+
     fn cache_reset(&mut self) {
-        self.store = Self::new_store(self.initial_capacity);
+        self.store = HashMap::with_capacity_and_hasher(
+            self.initial_capacity.unwrap_or(0),
+            self.store.hasher().clone(),
+        );
+        self.events.clear();
     }
+
     fn cache_reset_metrics(&mut self) {
-        self.misses = 0;
-        self.hits = 0;
-    }
-    fn cache_size(&self) -> usize {
-        self.store.len()
-    }
-    fn cache_hits(&self) -> Option<u64> {
-        Some(self.hits)
-    }
-    fn cache_misses(&self) -> Option<u64> {
-        Some(self.misses)
+        self.stats.reset();
+        #[cfg(feature = "latency_stats")]
+        self.latency.reset();
     }
 }
 
 #[cfg(feature = "async")]
 #[async_trait]
-impl<K, V> CachedAsync<K, V> for UnboundCache<K, V>
+impl<K, V, S> CachedAsync<K, V> for UnboundCache<K, V, S>
 where
     K: Hash + Eq + Clone + Send,
+    S: BuildHasher + Clone + Send,
 {
     async fn get_or_set_with<F, Fut>(&mut self, key: K, f: F) -> &mut V
     where
@@ -162,13 +369,15 @@ where
     {
         match self.store.entry(key) {
             Entry::Occupied(occupied) => {
-                self.hits += 1;
+                self.stats.hit();
                 occupied.into_mut()
             }
 
             Entry::Vacant(vacant) => {
-                self.misses += 1;
-                vacant.insert(f().await)
+                self.stats.miss();
+                let value = f().await;
+                self.events.set(vacant.key(), &value, false);
+                vacant.insert(value)
             }
         }
     }
@@ -181,13 +390,74 @@ where
     {
         let v = match self.store.entry(key) {
             Entry::Occupied(occupied) => {
-                self.hits += 1;
+                self.stats.hit();
+                occupied.into_mut()
+            }
+
+            Entry::Vacant(vacant) => {
+                self.stats.miss();
+                let value = f().await?;
+                self.events.set(vacant.key(), &value, false);
+                vacant.insert(value)
+            }
+        };
+        Ok(v)
+    }
+
+    async fn get_or_set_with_if_valid<F, Fut, P, PFut>(
+        &mut self,
+        key: K,
+        f: F,
+        is_valid: P,
+    ) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send,
+    {
+        let setter = || async { Ok::<_, Infallible>(f().await) };
+        match self
+            .try_get_or_set_with_if_valid(key, setter, is_valid)
+            .await
+        {
+            Ok(v) => v,
+            Err(never) => match never {},
+        }
+    }
+
+    async fn try_get_or_set_with_if_valid<F, Fut, P, PFut, E>(
+        &mut self,
+        key: K,
+        f: F,
+        is_valid: P,
+    ) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+        P: FnOnce(&V) -> PFut + Send,
+        PFut: Future<Output = bool> + Send,
+    {
+        let v = match self.store.entry(key) {
+            Entry::Occupied(mut occupied) => {
+                if is_valid(occupied.get()).await {
+                    self.stats.hit();
+                } else {
+                    self.stats.miss();
+                    let value = f().await?;
+                    self.events.set(occupied.key(), &value, true);
+                    occupied.insert(value);
+                }
                 occupied.into_mut()
             }
 
             Entry::Vacant(vacant) => {
-                self.misses += 1;
-                vacant.insert(f().await?)
+                self.stats.miss();
+                let value = f().await?;
+                self.events.set(vacant.key(), &value, false);
+                vacant.insert(value)
             }
         };
         Ok(v)
@@ -321,6 +591,21 @@ mod tests {
         assert_eq!(1, c.cache_size());
     }
 
+    #[test]
+    fn many() {
+        let mut c = UnboundCache::new();
+        let events = c.cache_subscribe().unwrap();
+        c.cache_set_many((0..100).map(|i| (i, i * 2)));
+        assert_eq!(c.cache_size(), 100);
+        assert_eq!(events.try_iter().count(), 100);
+
+        assert_eq!(
+            c.cache_get_many(&[1, 100, 99]),
+            [Some(&2), None, Some(&198)]
+        );
+        assert_eq!((c.cache_hits(), c.cache_misses()), (Some(2), Some(1)));
+    }
+
     #[test]
     fn get_or_set_with() {
         let mut c = UnboundCache::new();
@@ -346,4 +631,15 @@ mod tests {
 
         assert_eq!(c.cache_misses(), Some(6));
     }
+
+    #[test]
+    fn get_shared() {
+        let mut c = UnboundCache::new();
+        let stats = c.stats_handle();
+        c.cache_set(1, 100);
+        let c = &c;
+        assert_eq!(c.cache_get_shared(&1), Some(&100));
+        assert_eq!(c.cache_get_shared(&2), None);
+        assert_eq!((stats.hits(), stats.misses()), (1, 1));
+    }
 }
@@ -0,0 +1,334 @@
+#[cfg(feature = "latency_stats")]
+use crate::stats::{LatencyStats, LatencyStatsSnapshot, Operation};
+use crate::IOCached;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use thiserror::Error;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{DomException, Storage};
+
+const DEFAULT_NAMESPACE: &str = "cached-webstorage:";
+
+/// The Web Storage area holding the values of a `WebStorageCache`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebStorage {
+    /// `localStorage`, kept across sessions
+    Local,
+    /// `sessionStorage`, cleared when the page session ends
+    Session,
+}
+
+impl WebStorage {
+    fn property(self) -> &'static str {
+        match self {
+            WebStorage::Local => "localStorage",
+            WebStorage::Session => "sessionStorage",
+        }
+    }
+}
+
+pub struct WebStorageCacheBuilder<K, V> {
+    seconds: Option<u64>,
+    refresh: bool,
+    namespace: String,
+    prefix: String,
+    storage: WebStorage,
+    _phantom: PhantomData<(K, V)>,
+}
+
+#[derive(Error, Debug)]
+pub enum WebStorageCacheError {
+    #[error("{0} is not available in this context")]
+    StorageUnavailable(&'static str),
+    #[error("Web Storage error: {0}")]
+    WebStorageError(String),
+    #[error("Error deserializing cached value {cached_value:?}: {error:?}")]
+    CacheDeserializationError {
+        cached_value: String,
+        error: serde_json::Error,
+    },
+    #[error("Error serializing cached value: {error:?}")]
+    CacheSerializationError { error: serde_json::Error },
+}
+
+impl From<JsValue> for WebStorageCacheError {
+    fn from(error: JsValue) -> Self {
+        let message = match error.dyn_ref::<DomException>() {
+            Some(exception) => format!("{}: {}", exception.name(), exception.message()),
+            None => error.as_string().unwrap_or_else(|| format!("{error:?}")),
+        };
+        Self::WebStorageError(message)
+    }
+}
+
+impl<K, V> WebStorageCacheBuilder<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    /// Initialize a `WebStorageCacheBuilder`
+    pub fn new<S: AsRef<str>>(prefix: S) -> WebStorageCacheBuilder<K, V> {
+        Self {
+            seconds: None,
+            refresh: false,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            prefix: prefix.as_ref().to_string(),
+            storage: WebStorage::Local,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Specify the cache TTL/lifespan in seconds
+    #[must_use]
+    pub fn set_lifespan(mut self, seconds: u64) -> Self {
+        self.seconds = Some(seconds);
+        self
+    }
+
+    /// Specify whether cache hits refresh the TTL
+    #[must_use]
+    pub fn set_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Set the namespace for cache keys. Defaults to `cached-webstorage:`.
+    /// Used to generate keys formatted as: `{namespace}{prefix}{key}`
+    /// Note that no delimiters are implicitly added so you may pass
+    /// an empty string if you want there to be no namespace on keys.
+    #[must_use]
+    pub fn set_namespace<S: AsRef<str>>(mut self, namespace: S) -> Self {
+        self.namespace = namespace.as_ref().to_string();
+        self
+    }
+
+    /// Set the prefix for cache keys.
+    /// Used to generate keys formatted as: `{namespace}{prefix}{key}`
+    /// Note that no delimiters are implicitly added so you may pass
+    /// an empty string if you want there to be no prefix on keys.
+    #[must_use]
+    pub fn set_prefix<S: AsRef<str>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.as_ref().to_string();
+        self
+    }
+
+    /// Set the storage area holding the values. Defaults to `WebStorage::Local`.
+    #[must_use]
+    pub fn set_storage(mut self, storage: WebStorage) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// The last step in building a `WebStorageCache` is to call `build()`
+    ///
+    /// # Errors
+    ///
+    /// Will return a `WebStorageCacheError::StorageUnavailable` if the page has no
+    /// access to the storage area, as in workers or when storage is disabled
+    pub fn build(self) -> Result<WebStorageCache<K, V>, WebStorageCacheError> {
+        let cache = WebStorageCache {
+            seconds: self.seconds,
+            refresh: self.refresh,
+            namespace: self.namespace,
+            prefix: self.prefix,
+            storage: self.storage,
+            #[cfg(feature = "latency_stats")]
+            latency: LatencyStats::new(),
+            _phantom: PhantomData,
+        };
+        cache.storage()?;
+        Ok(cache)
+    }
+}
+
+/// Cache store backed by the Web Storage (`localStorage` or `sessionStorage`) of the
+/// browser, for `wasm32-unknown-unknown` targets
+///
+/// Each value is stored as JSON along with its expiry time, under the key
+/// `{namespace}{prefix}{key}`. Expired values are removed when they are read.
+///
+/// Web Storage is synchronous and limited to a few megabytes per origin, shared by all
+/// the caches of the origin: prefer it for small values, and an `IndexedDbCache` for
+/// larger ones. Setting a value over the quota returns a `WebStorageCacheError::WebStorageError`.
+pub struct WebStorageCache<K, V> {
+    pub(super) seconds: Option<u64>,
+    pub(super) refresh: bool,
+    pub(super) namespace: String,
+    pub(super) prefix: String,
+    storage: WebStorage,
+    #[cfg(feature = "latency_stats")]
+    latency: LatencyStats,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> WebStorageCache<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    #[allow(clippy::new_ret_no_self)]
+    /// Initialize a `WebStorageCacheBuilder`
+    pub fn new<S: AsRef<str>>(prefix: S) -> WebStorageCacheBuilder<K, V> {
+        WebStorageCacheBuilder::new(prefix)
+    }
+
+    /// Remove all the values of this cache, leaving the other keys of the storage area
+    ///
+    /// # Errors
+    ///
+    /// Will return a `WebStorageCacheError` if the storage area cannot be accessed
+    pub fn clear(&self) -> Result<(), WebStorageCacheError> {
+        let storage = self.storage()?;
+        let prefix = format!("{}{}", self.namespace, self.prefix);
+        let mut keys = Vec::new();
+        for index in 0..storage.length()? {
+            if let Some(key) = storage.key(index)? {
+                if key.starts_with(&prefix) {
+                    keys.push(key);
+                }
+            }
+        }
+        for key in keys {
+            storage.remove_item(&key)?;
+        }
+        Ok(())
+    }
+
+    /// The storage area is looked up for each operation, since its handle cannot be
+    /// shared between threads, which lets the cache be stored in a `static`
+    fn storage(&self) -> Result<Storage, WebStorageCacheError> {
+        let property = self.storage.property();
+        let storage = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str(property))
+            .map_err(|_| WebStorageCacheError::StorageUnavailable(property))?;
+        if storage.is_undefined() || storage.is_null() {
+            return Err(WebStorageCacheError::StorageUnavailable(property));
+        }
+        Ok(storage.unchecked_into())
+    }
+
+    fn generate_key(&self, key: &K) -> String {
+        format!("{}{}{}", self.namespace, self.prefix, key)
+    }
+
+    fn new_expiry(&self) -> Option<f64> {
+        self.seconds
+            .map(|seconds| js_sys::Date::now() + (seconds as f64) * 1000.0)
+    }
+
+    fn encode(&self, val: &V) -> Result<String, WebStorageCacheError> {
+        let cached = CachedWebStorageValue {
+            value: val,
+            expires_at: self.new_expiry(),
+            version: Some(1),
+        };
+        serde_json::to_string(&cached)
+            .map_err(|e| WebStorageCacheError::CacheSerializationError { error: e })
+    }
+
+    /// Read the value of `key`, expired or not
+    fn read(
+        storage: &Storage,
+        key: &str,
+    ) -> Result<Option<CachedWebStorageValue<V>>, WebStorageCacheError> {
+        let stored = match storage.get_item(key)? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        serde_json::from_str(&stored).map(Some).map_err(|e| {
+            WebStorageCacheError::CacheDeserializationError {
+                cached_value: stored,
+                error: e,
+            }
+        })
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedWebStorageValue<V> {
+    pub(crate) value: V,
+    pub(crate) expires_at: Option<f64>,
+    pub(crate) version: Option<u64>,
+}
+
+impl<V> CachedWebStorageValue<V> {
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= js_sys::Date::now())
+    }
+}
+
+impl<K, V> IOCached<K, V> for WebStorageCache<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    type Error = WebStorageCacheError;
+
+    fn cache_get(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let storage = self.storage()?;
+        let key = self.generate_key(key);
+        let value = match Self::read(&storage, &key)? {
+            Some(cached) if cached.is_expired() => {
+                storage.remove_item(&key)?;
+                None
+            }
+            Some(cached) => {
+                if self.refresh {
+                    storage.set_item(&key, &self.encode(&cached.value)?)?;
+                }
+                Some(cached.value)
+            }
+            None => None,
+        };
+        #[cfg(feature = "latency_stats")]
+        self.latency.record_get(value.is_some(), started.elapsed());
+        Ok(value)
+    }
+
+    fn cache_set(&self, key: K, val: V) -> Result<Option<V>, Self::Error> {
+        #[cfg(feature = "latency_stats")]
+        let started = instant::Instant::now();
+        let storage = self.storage()?;
+        let key = self.generate_key(&key);
+        let previous = Self::read(&storage, &key)?;
+        storage.set_item(&key, &self.encode(&val)?)?;
+        #[cfg(feature = "latency_stats")]
+        self.latency.record(Operation::Insert, started.elapsed());
+        Ok(previous
+            .filter(|cached| !cached.is_expired())
+            .map(|cached| cached.value))
+    }
+
+    fn cache_remove(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        let storage = self.storage()?;
+        let key = self.generate_key(key);
+        let previous = Self::read(&storage, &key)?;
+        storage.remove_item(&key)?;
+        Ok(previous
+            .filter(|cached| !cached.is_expired())
+            .map(|cached| cached.value))
+    }
+
+    fn cache_set_refresh(&mut self, refresh: bool) -> bool {
+        let old = self.refresh;
+        self.refresh = refresh;
+        old
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.seconds
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.seconds.replace(seconds)
+    }
+
+    #[cfg(feature = "latency_stats")]
+    fn cache_latency_stats(&self) -> Option<LatencyStatsSnapshot> {
+        Some(self.latency.snapshot())
+    }
+}
@@ -0,0 +1,453 @@
+use super::{Cached, CachedRead, CachedWrite, SizedCache};
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The approximate number of bytes of a value, including those it owns on the heap
+///
+/// Used by `WeightedCache::with_budget` to weigh its keys and values. Implemented for
+/// the primitive types, strings and the common containers; shared pointers (`Rc`, `Arc`)
+/// count the whole value they point to.
+pub trait MemSize {
+    /// Return the approximate number of bytes of `self`
+    fn mem_size(&self) -> usize;
+}
+
+macro_rules! impl_mem_size_of {
+    ($($ty:ty),*) => {
+        $(
+            impl MemSize for $ty {
+                fn mem_size(&self) -> usize {
+                    std::mem::size_of::<$ty>()
+                }
+            }
+        )*
+    };
+}
+
+impl_mem_size_of!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64
+);
+
+impl MemSize for str {
+    fn mem_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl MemSize for String {
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<String>() + self.capacity()
+    }
+}
+
+impl<T: MemSize + ?Sized> MemSize for &T {
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<&T>()
+    }
+}
+
+impl<T: MemSize + ?Sized> MemSize for Box<T> {
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<Box<T>>() + (**self).mem_size()
+    }
+}
+
+impl<T: MemSize + ?Sized> MemSize for Rc<T> {
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<Rc<T>>() + (**self).mem_size()
+    }
+}
+
+impl<T: MemSize + ?Sized> MemSize for Arc<T> {
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<Arc<T>>() + (**self).mem_size()
+    }
+}
+
+impl<T: MemSize> MemSize for Option<T> {
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<Option<T>>() - std::mem::size_of::<T>()
+            + self
+                .as_ref()
+                .map_or(std::mem::size_of::<T>(), MemSize::mem_size)
+    }
+}
+
+impl<T: MemSize, E: MemSize> MemSize for Result<T, E> {
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<Result<T, E>>()
+            + match self {
+                Ok(value) => value.mem_size().saturating_sub(std::mem::size_of::<T>()),
+                Err(error) => error.mem_size().saturating_sub(std::mem::size_of::<E>()),
+            }
+    }
+}
+
+impl<T: MemSize> MemSize for [T] {
+    fn mem_size(&self) -> usize {
+        self.iter().map(MemSize::mem_size).sum()
+    }
+}
+
+impl<T: MemSize> MemSize for Vec<T> {
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<Vec<T>>()
+            + (self.capacity() - self.len()) * std::mem::size_of::<T>()
+            + self.as_slice().mem_size()
+    }
+}
+
+impl<K: MemSize, V: MemSize, S> MemSize for HashMap<K, V, S> {
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<HashMap<K, V, S>>()
+            + (self.capacity() - self.len()) * std::mem::size_of::<(K, V)>()
+            + self
+                .iter()
+                .map(|(key, value)| key.mem_size() + value.mem_size())
+                .sum::<usize>()
+    }
+}
+
+impl<K: MemSize, V: MemSize> MemSize for BTreeMap<K, V> {
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<BTreeMap<K, V>>()
+            + self
+                .iter()
+                .map(|(key, value)| key.mem_size() + value.mem_size())
+                .sum::<usize>()
+    }
+}
+
+macro_rules! impl_mem_size_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: MemSize),+> MemSize for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn mem_size(&self) -> usize {
+                let ($($name,)+) = self;
+                std::mem::size_of::<Self>() $(+ $name.mem_size() - std::mem::size_of::<$name>())+
+            }
+        }
+    };
+}
+
+impl_mem_size_tuple!(A);
+impl_mem_size_tuple!(A B);
+impl_mem_size_tuple!(A B C);
+impl_mem_size_tuple!(A B C D);
+
+fn mem_size<K: MemSize, V: MemSize>(key: &K, value: &V) -> usize {
+    key.mem_size() + value.mem_size()
+}
+
+/// Weighted / Memory-bounded Cache
+///
+/// Keeps the most recently used values up to a budget of bytes, evicting the least
+/// recently used ones when over budget, for values whose sizes vary too much for a
+/// number of values to bound the memory of the cache.
+///
+/// The bytes of each value are measured when it is set by a weigher, by default the
+/// `MemSize` of the key and the value. A value heavier than the whole budget is not
+/// kept. Values modified through `cache_get_mut` are not weighed again.
+///
+/// Note: This cache is in-memory only
+///
+/// ```rust
+/// use cached::stores::WeightedCache;
+/// use cached::{Cached, CachedWrite};
+///
+/// let mut cache = WeightedCache::with_budget(1 << 20);
+/// cache.cache_set(1, "a".repeat(600 << 10));
+/// cache.cache_set(2, "b".repeat(600 << 10));
+///
+/// // 1 was evicted to stay within 1 MiB
+/// assert!(cache.cache_get(&1).is_none());
+/// assert!(cache.cache_get(&2).is_some());
+/// ```
+pub struct WeightedCache<K, V> {
+    pub(super) store: SizedCache<K, V>,
+    pub(super) budget: usize,
+    pub(super) weight: usize,
+    pub(super) weigher: fn(&K, &V) -> usize,
+    pub(super) evictions: u64,
+}
+
+impl<K, V> WeightedCache<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Creates a `WeightedCache` keeping up to `budget` bytes of values, weighing the
+    /// keys and values by their `MemSize`
+    #[must_use]
+    pub fn with_budget(budget: usize) -> WeightedCache<K, V>
+    where
+        K: MemSize,
+        V: MemSize,
+    {
+        Self::with_budget_and_weigher(budget, mem_size::<K, V>)
+    }
+
+    /// Creates a `WeightedCache` keeping up to `budget` bytes of values, as measured
+    /// by `weigher`
+    #[must_use]
+    pub fn with_budget_and_weigher(
+        budget: usize,
+        weigher: fn(&K, &V) -> usize,
+    ) -> WeightedCache<K, V> {
+        WeightedCache {
+            store: SizedCache::without_limit(),
+            budget,
+            weight: 0,
+            weigher,
+            evictions: 0,
+        }
+    }
+
+    /// Return the budget of bytes of the values
+    #[must_use]
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Return the bytes of the values, as measured when they were set
+    #[must_use]
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// Return the number of values evicted to stay within the budget
+    #[must_use]
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// Remove the least recently used value, returning it along with its key
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        let (key, value) = self.store.pop_lru()?;
+        self.weight = self.weight.saturating_sub((self.weigher)(&key, &value));
+        Some((key, value))
+    }
+
+    /// Release the memory the cache keeps beyond its values, e.g. after most of them
+    /// were removed
+    pub fn shrink_to_fit(&mut self) {
+        self.store.shrink_to_fit();
+    }
+
+    /// Evict the least recently used values while over budget
+    fn evict(&mut self) {
+        while self.weight > self.budget {
+            match self.store.evict_lru() {
+                Some((key, value)) => {
+                    self.weight = self.weight.saturating_sub((self.weigher)(&key, &value));
+                    self.evictions += 1;
+                }
+                None => return,
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cached<K, V> for WeightedCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.cache_get(key)
+    }
+
+    fn cache_get_key_value<Q>(&mut self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.cache_get_key_value(key)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.cache_get_mut(key)
+    }
+
+    /// The values are returned from most to least recently used.
+    fn cache_drain_where<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        drain: F,
+    ) -> impl Iterator<Item = (K, V)> {
+        let drained = self.store.cache_drain_where(drain).collect::<Vec<_>>();
+        for (key, value) in &drained {
+            self.weight = self.weight.saturating_sub((self.weigher)(key, value));
+        }
+        drained.into_iter()
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        if !self.store.contains_key(&key) {
+            self.cache_set(key.clone(), f());
+        }
+        // a value heavier than the budget is kept until the next insertion
+        self.store.cache_get_or_set_with(key, || unreachable!())
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> CachedRead<K, V> for WeightedCache<K, V> {
+    fn cache_size(&self) -> usize {
+        self.store.cache_size()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        self.store.cache_hits()
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        self.store.cache_misses()
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.cache_peek(key)
+    }
+
+    /// The values are iterated from most to least recently used.
+    fn cache_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.store.cache_iter()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> CachedWrite<K, V> for WeightedCache<K, V> {
+    fn cache_set(&mut self, key: K, value: V) -> Option<V> {
+        self.weight += (self.weigher)(&key, &value);
+        let previous = self.store.cache_set(key.clone(), value);
+        if let Some(previous) = &previous {
+            self.weight = self.weight.saturating_sub((self.weigher)(&key, previous));
+        }
+        self.evict();
+        previous
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, value) = self.store.remove_entry(key)?;
+        self.weight = self.weight.saturating_sub((self.weigher)(&key, &value));
+        Some(value)
+    }
+
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut remove: F) -> Option<usize> {
+        let keys = self
+            .store
+            .key_order()
+            .filter(|key| remove(key))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in &keys {
+            self.cache_remove(key);
+        }
+        Some(keys.len())
+    }
+
+    fn cache_clear(&mut self) {
+        self.store.cache_clear();
+        self.weight = 0;
+    }
+
+    fn cache_reset(&mut self) {
+        self.cache_clear();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.store.cache_reset_metrics();
+        self.evictions = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_over_budget() {
+        let mut c =
+            WeightedCache::with_budget_and_weigher(10, |_: &u32, value: &String| value.len());
+        assert_eq!(c.cache_set(1, "aaaa".to_string()), None);
+        assert_eq!(c.cache_set(2, "bbbb".to_string()), None);
+        assert_eq!(c.cache_get(&1), Some(&"aaaa".to_string()));
+        assert_eq!(c.cache_set(3, "cccc".to_string()), None);
+        // 2 was the least recently used
+        assert_eq!((c.cache_size(), c.weight(), c.evictions()), (2, 8, 1));
+        assert_eq!(c.cache_get(&2), None);
+
+        assert_eq!(c.cache_set(3, "c".to_string()), Some("cccc".to_string()));
+        assert_eq!(c.weight(), 5);
+        assert_eq!(c.cache_remove(&1), Some("aaaa".to_string()));
+        assert_eq!(c.weight(), 1);
+
+        // a value heavier than the budget is not kept
+        c.cache_set(4, "d".repeat(11));
+        assert_eq!((c.cache_size(), c.weight()), (0, 0));
+    }
+
+    #[test]
+    fn drain() {
+        let mut c =
+            WeightedCache::with_budget_and_weigher(10, |_: &u32, value: &String| value.len());
+        c.cache_set(1, "aaaa".to_string());
+        c.cache_set(2, "bb".to_string());
+        assert_eq!(
+            c.cache_drain_where(|key, _| *key == 1).collect::<Vec<_>>(),
+            [(1, "aaaa".to_string())]
+        );
+        assert_eq!(c.weight(), 2);
+        assert_eq!(c.cache_drain().count(), 1);
+        assert_eq!((c.cache_size(), c.weight()), (0, 0));
+    }
+
+    #[test]
+    fn mem_size() {
+        assert_eq!(1u8.mem_size(), 1);
+        assert_eq!(
+            String::from("abc").mem_size(),
+            std::mem::size_of::<String>() + 3
+        );
+        assert_eq!(
+            vec![1u32, 2].mem_size(),
+            std::mem::size_of::<Vec<u32>>() + 8
+        );
+        assert_eq!((1u32, 2u64).mem_size(), std::mem::size_of::<(u32, u64)>());
+        assert_eq!(
+            Some("ab".to_string()).mem_size(),
+            std::mem::size_of::<Option<String>>() + 2
+        );
+    }
+}
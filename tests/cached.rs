@@ -5,12 +5,13 @@ Full tests of macro-defined functions
 extern crate cached;
 
 use cached::{
-    proc_macro::cached, proc_macro::once, Cached, CanExpire, ExpiringValueCache, SizedCache,
-    TimedCache, TimedSizedCache, UnboundCache,
+    proc_macro::cached, proc_macro::once, CacheKey, CachedRead, CachedWrite, CanExpire,
+    ExpiringValueCache, SizedCache, TimedCache, TimedSizedCache, UnboundCache,
 };
 use serial_test::serial;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::thread::{self, sleep};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 cached! {
     UNBOUND_FIB;
@@ -327,12 +328,7 @@ cached_control! {
     CONTROL_CACHE: UnboundCache<String, String> = UnboundCache::new();
     Key = { input.to_owned() };
     PostGet(cached_val) = return Ok(cached_val.clone());
-    PostExec(body_result) = {
-        match body_result {
-            Ok(v) => v,
-            Err(e) => return Err(e),
-        }
-    };
+    PostExec(body_result) = body_result?;
     Set(set_value) = set_value.clone();
     Return(return_value) = {
         println!("{return_value}");
@@ -898,6 +894,63 @@ async fn test_cached_sync_writes_a() {
     assert_eq!(a, c.await.unwrap());
 }
 
+static SYNC_WRITES_BY_KEY_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(sync_writes = "by_key", key = "u32", convert = "{ n }")]
+fn cached_sync_writes_by_key(n: u32, s: String) -> String {
+    SYNC_WRITES_BY_KEY_CALLS.fetch_add(1, Ordering::SeqCst);
+    sleep(Duration::from_secs(1));
+    format!("{}{}", s, n)
+}
+
+#[test]
+fn test_cached_sync_writes_by_key() {
+    let a = std::thread::spawn(|| cached_sync_writes_by_key(1, "a".to_string()));
+    sleep(Duration::from_millis(100));
+    let b = std::thread::spawn(|| cached_sync_writes_by_key(1, "b".to_string()));
+    let c = std::thread::spawn(|| {
+        let started = Instant::now();
+        (
+            cached_sync_writes_by_key(2, "c".to_string()),
+            started.elapsed(),
+        )
+    });
+    assert_eq!(a.join().unwrap(), "a1");
+    // b waited for a to compute the value of key 1
+    assert_eq!(b.join().unwrap(), "a1");
+    // c computed key 2 without waiting for key 1
+    let (c, elapsed) = c.join().unwrap();
+    assert_eq!(c, "c2");
+    assert!(elapsed < Duration::from_millis(1500));
+    assert_eq!(SYNC_WRITES_BY_KEY_CALLS.load(Ordering::SeqCst), 2);
+    assert_eq!(CACHED_SYNC_WRITES_BY_KEY_KEY_LOCKS.locked(), 0);
+}
+
+#[cfg(feature = "async")]
+static ASYNC_SYNC_WRITES_BY_KEY_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[cached(sync_writes = "by_key", key = "u32", convert = "{ n }")]
+async fn cached_sync_writes_by_key_a(n: u32, s: String) -> String {
+    ASYNC_SYNC_WRITES_BY_KEY_CALLS.fetch_add(1, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    format!("{}{}", s, n)
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_sync_writes_by_key_a() {
+    let a = tokio::spawn(cached_sync_writes_by_key_a(1, "a".to_string()));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let b = tokio::spawn(cached_sync_writes_by_key_a(1, "b".to_string()));
+    let started = Instant::now();
+    assert_eq!(cached_sync_writes_by_key_a(2, "c".to_string()).await, "c2");
+    assert!(started.elapsed() < Duration::from_millis(1500));
+    assert_eq!(a.await.unwrap(), "a1");
+    assert_eq!(b.await.unwrap(), "a1");
+    assert_eq!(ASYNC_SYNC_WRITES_BY_KEY_CALLS.load(Ordering::SeqCst), 2);
+}
+
 #[cfg(feature = "async")]
 #[once(sync_writes = true)]
 async fn once_sync_writes_a(s: &tokio::sync::Mutex<String>) -> String {
@@ -925,6 +978,109 @@ async fn test_once_sync_writes_a() {
     assert_eq!("b", b_mutex.lock().await.to_string());
 }
 
+static STALE_WHILE_REFRESH_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[once(time = 1, stale_while_refresh = true)]
+fn once_stale_while_refresh() -> u32 {
+    sleep(Duration::from_millis(200));
+    STALE_WHILE_REFRESH_CALLS.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+#[test]
+fn test_once_stale_while_refresh() {
+    assert_eq!(once_stale_while_refresh(), 1);
+    sleep(Duration::from_millis(1100));
+    assert!(!once_stale_while_refresh_initialized());
+
+    // the expired value is returned right away, a single refresh runs in the background
+    let started = Instant::now();
+    assert_eq!(once_stale_while_refresh(), 1);
+    assert_eq!(once_stale_while_refresh(), 1);
+    assert!(started.elapsed() < Duration::from_millis(100));
+    assert!(ONCE_STALE_WHILE_REFRESH_REFRESHING.load(Ordering::SeqCst));
+
+    sleep(Duration::from_millis(400));
+    assert_eq!(once_stale_while_refresh(), 2);
+    assert_eq!(STALE_WHILE_REFRESH_CALLS.load(Ordering::SeqCst), 2);
+    assert!(!ONCE_STALE_WHILE_REFRESH_REFRESHING.load(Ordering::SeqCst));
+}
+
+#[cfg(feature = "async")]
+static ASYNC_STALE_WHILE_REFRESH_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[once(time = 1, result = true, stale_while_refresh = true)]
+async fn once_stale_while_refresh_a() -> Result<u32, ()> {
+    // refreshed in a task of the runtime of `cached::runtime`, which may not be `tokio`
+    cached::runtime::sleep(Duration::from_millis(200)).await;
+    match ASYNC_STALE_WHILE_REFRESH_CALLS.fetch_add(1, Ordering::SeqCst) {
+        1 => Err(()),
+        calls => Ok(calls + 1),
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_once_stale_while_refresh_a() {
+    assert_eq!(once_stale_while_refresh_a().await, Ok(1));
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    // the failed refresh keeps the expired value
+    let started = Instant::now();
+    assert_eq!(once_stale_while_refresh_a().await, Ok(1));
+    assert!(started.elapsed() < Duration::from_millis(100));
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(once_stale_while_refresh_a().await, Ok(1));
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(once_stale_while_refresh_a().await, Ok(3));
+    assert_eq!(ASYNC_STALE_WHILE_REFRESH_CALLS.load(Ordering::SeqCst), 3);
+}
+
+#[cfg(feature = "async")]
+static ONCE_BARRIER_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[once]
+async fn once_barrier(s: String) -> String {
+    ONCE_BARRIER_CALLS.fetch_add(1, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    s
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_once_barrier() {
+    assert!(!once_barrier_initialized());
+    let tasks = (0..5)
+        .map(|i| tokio::spawn(once_barrier(i.to_string())))
+        .collect::<Vec<_>>();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    // the value is being computed
+    assert!(!once_barrier_initialized());
+    let mut results = Vec::new();
+    for task in tasks {
+        results.push(task.await.unwrap());
+    }
+    assert!(results.iter().all(|r| r == &results[0]));
+    assert_eq!(ONCE_BARRIER_CALLS.load(Ordering::SeqCst), 1);
+    assert!(once_barrier_initialized());
+}
+
+#[once(time = 1)]
+fn once_initialized_timed() -> u32 {
+    1
+}
+
+#[test]
+fn test_once_initialized_timed() {
+    assert!(!once_initialized_timed_initialized());
+    once_initialized_timed();
+    assert!(once_initialized_timed_initialized());
+    sleep(Duration::new(1, 0));
+    assert!(!once_initialized_timed_initialized());
+}
+
 #[cached(size = 2)]
 fn cached_smartstring(s: smartstring::alias::String) -> smartstring::alias::String {
     if s == "very stringy" {
@@ -1212,7 +1368,8 @@ fn test_mutable_args_once() {
 mod disk_tests {
     use super::*;
     use cached::proc_macro::io_cached;
-    use cached::DiskCache;
+    use cached::{DiskCache, IOCached};
+    use std::sync::atomic::{AtomicU32, Ordering};
     use thiserror::Error;
 
     #[derive(Error, Debug, PartialEq, Clone)]
@@ -1244,6 +1401,153 @@ mod disk_tests {
         assert_eq!(cached_disk(6), Err(TestError::Count(6)));
     }
 
+    #[io_cached(
+        disk = true,
+        disk_database = "shared_disk",
+        map_error = r##"|e| TestError::DiskError(format!("{:?}", e))"##
+    )]
+    fn shared_disk_double(n: u32) -> Result<u32, TestError> {
+        Ok(n * 2)
+    }
+
+    #[io_cached(
+        disk = true,
+        disk_database = "shared_disk",
+        map_error = r##"|e| TestError::DiskError(format!("{:?}", e))"##
+    )]
+    fn shared_disk_triple(n: u32) -> Result<u32, TestError> {
+        Ok(n * 3)
+    }
+
+    #[test]
+    fn test_shared_disk_database() {
+        assert_eq!(shared_disk_double(1), Ok(2));
+        assert_eq!(shared_disk_triple(1), Ok(3));
+        assert_eq!(
+            SHARED_DISK_DOUBLE.cache_get(&1).unwrap(),
+            Some(2),
+            "each function should keep its values in its own tree"
+        );
+        assert_eq!(SHARED_DISK_TRIPLE.cache_get(&1).unwrap(), Some(3));
+        let trees = SHARED_DISK_DOUBLE.database_trees();
+        assert!(trees.contains(&"SHARED_DISK_DOUBLE".to_string()));
+        assert!(trees.contains(&"SHARED_DISK_TRIPLE".to_string()));
+    }
+
+    #[io_cached(
+        disk = true,
+        map_error = r##"|e| TestError::DiskError(format!("{:?}", e))"##
+    )]
+    fn cached_disk_stats(n: u32) -> Result<u32, TestError> {
+        if n < 5 {
+            Ok(n)
+        } else {
+            Err(TestError::Count(n))
+        }
+    }
+
+    #[test]
+    fn test_cached_disk_cache_stats() {
+        cached_disk_stats_prime_cache(1).unwrap();
+        assert_eq!(cached_disk_stats(1), Ok(1));
+        assert_eq!(cached_disk_stats(1), Ok(1));
+        assert!(cached_disk_stats(5).is_err());
+        let stats = cached_disk_stats_cache_stats();
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+    }
+
+    #[io_cached(disk = true, on_error = "panic")]
+    fn cached_disk_infallible(n: u32) -> Vec<u32> {
+        vec![n; 2]
+    }
+
+    #[test]
+    fn test_cached_disk_infallible() {
+        // the value may be left on disk by a previous run
+        assert_eq!(cached_disk_infallible_prime_cache(3), vec![3, 3]);
+        assert_eq!(cached_disk_infallible(3), vec![3, 3]);
+        assert_eq!(
+            CACHED_DISK_INFALLIBLE.cache_get(&3).unwrap(),
+            Some(vec![3, 3])
+        );
+        assert_eq!(cached_disk_infallible_cache_stats().hits, 1);
+    }
+
+    #[test]
+    fn test_cached_disk_no_cache() {
+        cached_disk_infallible_prime_cache(4);
+        let hits = cached_disk_infallible_cache_stats().hits;
+        assert_eq!(cached_disk_infallible_no_cache(4), vec![4, 4]);
+        assert_eq!(cached_disk_infallible_cache_stats().hits, hits);
+    }
+
+    static CACHED_DISK_FALLBACK_FAILING: std::sync::atomic::AtomicBool =
+        std::sync::atomic::AtomicBool::new(false);
+
+    #[io_cached(
+        disk = true,
+        time = 1,
+        result_fallback = true,
+        map_error = r##"|e| TestError::DiskError(format!("{:?}", e))"##
+    )]
+    fn cached_disk_fallback(n: u32) -> Result<u32, TestError> {
+        if CACHED_DISK_FALLBACK_FAILING.load(Ordering::SeqCst) {
+            Err(TestError::Count(n))
+        } else {
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_cached_disk_result_fallback() {
+        assert_eq!(cached_disk_fallback_prime_cache(7), Ok(7));
+        CACHED_DISK_FALLBACK_FAILING.store(true, Ordering::SeqCst);
+        assert_eq!(cached_disk_fallback(7), Ok(7));
+
+        // the expired value is returned instead of the error, and cached again
+        sleep(Duration::from_millis(1100));
+        assert_eq!(cached_disk_fallback(7), Ok(7));
+        assert_eq!(CACHED_DISK_FALLBACK.cache_get(&7).unwrap(), Some(7));
+
+        CACHED_DISK_FALLBACK.cache_remove(&7).unwrap();
+        assert_eq!(cached_disk_fallback(7), Err(TestError::Count(7)));
+    }
+
+    static CACHED_DISK_L1_CALLS: AtomicU32 = AtomicU32::new(0);
+
+    #[io_cached(
+        disk = true,
+        l1_size = 2,
+        l1_time = 60,
+        map_error = r##"|e| TestError::DiskError(format!("{:?}", e))"##
+    )]
+    fn cached_disk_l1(n: u32) -> Result<u32, TestError> {
+        CACHED_DISK_L1_CALLS.fetch_add(1, Ordering::SeqCst);
+        Ok(n * 10)
+    }
+
+    #[test]
+    fn test_cached_disk_l1() {
+        use cached::Cached;
+
+        // the values may be left on disk by a previous run
+        assert_eq!(cached_disk_l1_prime_cache(1), Ok(10));
+        assert_eq!(CACHED_DISK_L1_L1.lock().unwrap().cache_get(&1), Some(&10));
+
+        // served by the L1 cache without reading the disk
+        CACHED_DISK_L1.cache_remove(&1).unwrap();
+        assert_eq!(cached_disk_l1(1), Ok(10));
+
+        // a hit of the disk is promoted to the L1 cache
+        cached_disk_l1_prime_cache(2).unwrap();
+        CACHED_DISK_L1_L1.lock().unwrap().cache_clear();
+        assert_eq!(cached_disk_l1(2), Ok(20));
+        assert_eq!(CACHED_DISK_L1_L1.lock().unwrap().cache_get(&2), Some(&20));
+
+        assert_eq!(CACHED_DISK_L1_CALLS.load(Ordering::SeqCst), 2);
+        assert_eq!(cached_disk_l1_cache_stats().hits, 2);
+    }
+
     #[io_cached(
         disk = true,
         time = 1,
@@ -1287,6 +1591,65 @@ mod disk_tests {
         assert_eq!(cached_disk_cache_create(6), Err(TestError::Count(6)));
     }
 
+    static REVALIDATIONS: AtomicU32 = AtomicU32::new(0);
+
+    #[io_cached(
+        disk = true,
+        time = 60,
+        validator = "etag",
+        map_error = r##"|e| TestError::DiskError(format!("{:?}", e))"##
+    )]
+    fn cached_disk_revalidated(
+        n: u64,
+        etag: Option<String>,
+    ) -> Result<cached::Revalidation<u64>, TestError> {
+        if etag == Some(n.to_string()) {
+            REVALIDATIONS.fetch_add(1, Ordering::SeqCst);
+            Ok(cached::Revalidation::NotModified)
+        } else {
+            Ok(cached::Revalidation::Modified {
+                value: n,
+                validator: Some(n.to_string()),
+            })
+        }
+    }
+
+    #[io_cached(
+        disk = true,
+        time = 60,
+        validator = "etag",
+        revalidate_after = 60,
+        map_error = r##"|e| TestError::DiskError(format!("{:?}", e))"##
+    )]
+    fn cached_disk_revalidated_after(
+        n: u64,
+        etag: Option<String>,
+    ) -> Result<cached::Revalidation<u64>, TestError> {
+        assert!(etag.is_none(), "fresh values are not revalidated");
+        Ok(cached::Revalidation::Modified {
+            value: n,
+            validator: Some(n.to_string()),
+        })
+    }
+
+    #[test]
+    fn test_cached_disk_revalidated() {
+        // the disk cache outlives the test run
+        let n = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        assert_eq!(cached_disk_revalidated(n), Ok(n));
+        assert_eq!(REVALIDATIONS.load(Ordering::SeqCst), 0);
+        assert_eq!(cached_disk_revalidated(n), Ok(n));
+        assert_eq!(REVALIDATIONS.load(Ordering::SeqCst), 1);
+        assert_eq!(cached_disk_revalidated_prime_cache(n), Ok(n));
+        assert_eq!(REVALIDATIONS.load(Ordering::SeqCst), 2);
+
+        assert_eq!(cached_disk_revalidated_after(n), Ok(n));
+        assert_eq!(cached_disk_revalidated_after(n), Ok(n));
+    }
+
     #[cfg(feature = "async")]
     mod async_test {
         use super::*;
@@ -1310,6 +1673,30 @@ mod disk_tests {
             assert_eq!(async_cached_disk(5).await, Err(TestError::Count(5)));
             assert_eq!(async_cached_disk(6).await, Err(TestError::Count(6)));
         }
+
+        #[io_cached(
+            disk = true,
+            validator = "etag",
+            map_error = r##"|e| TestError::DiskError(format!("{:?}", e))"##
+        )]
+        async fn async_cached_disk_revalidated(
+            n: u32,
+            etag: Option<String>,
+        ) -> Result<cached::Revalidation<u32>, TestError> {
+            match etag {
+                Some(etag) if etag == n.to_string() => Ok(cached::Revalidation::NotModified),
+                _ => Ok(cached::Revalidation::Modified {
+                    value: n,
+                    validator: Some(n.to_string()),
+                }),
+            }
+        }
+
+        #[tokio::test]
+        async fn test_async_cached_disk_revalidated() {
+            assert_eq!(async_cached_disk_revalidated(1).await, Ok(1));
+            assert_eq!(async_cached_disk_revalidated(1).await, Ok(1));
+        }
     }
 }
 
@@ -1419,6 +1806,99 @@ mod redis_tests {
             assert_eq!(async_cached_redis(6).await, Err(TestError::Count(6)));
         }
 
+        #[io_cached(
+            redis = true,
+            time = 1,
+            shared_connection = true,
+            map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+        )]
+        async fn async_cached_redis_shared_1(n: u32) -> Result<u32, TestError> {
+            Ok(n)
+        }
+
+        #[io_cached(
+            redis = true,
+            time = 1,
+            shared_connection = true,
+            map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+        )]
+        async fn async_cached_redis_shared_2(n: u32) -> Result<u32, TestError> {
+            Ok(n + 1)
+        }
+
+        #[io_cached(
+            redis = true,
+            time = 60,
+            config_epoch = true,
+            key = "u32",
+            convert = r##"{ n }"##,
+            map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+        )]
+        async fn async_cached_redis_config_epoch(n: u32) -> Result<u32, TestError> {
+            Ok(n)
+        }
+
+        #[tokio::test]
+        #[serial(ConfigEpoch)]
+        async fn test_async_cached_redis_config_epoch() {
+            assert_eq!(async_cached_redis_config_epoch(1).await, Ok(1));
+            cached::registry::bump_config_epoch();
+            assert_eq!(async_cached_redis_config_epoch(1).await, Ok(1));
+        }
+
+        #[io_cached(
+            redis = true,
+            time = 60,
+            l1_size = 100,
+            l1_time = 30,
+            map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+        )]
+        async fn async_cached_redis_l1(n: u32) -> Result<u32, TestError> {
+            Ok(n)
+        }
+
+        #[tokio::test]
+        async fn test_async_cached_redis_l1() {
+            assert_eq!(async_cached_redis_l1(1).await, Ok(1));
+            assert_eq!(async_cached_redis_l1(1).await, Ok(1));
+            assert_eq!(ASYNC_CACHED_REDIS_L1_L1.lock().unwrap().cache_size(), 1);
+        }
+
+        #[tokio::test]
+        async fn test_async_cached_redis_shared_connection() {
+            assert_eq!(async_cached_redis_shared_1(1).await, Ok(1));
+            assert_eq!(async_cached_redis_shared_2(1).await, Ok(2));
+            assert_eq!(async_cached_redis_shared_1(1).await, Ok(1));
+        }
+
+        #[io_cached(
+            redis = true,
+            time = 60,
+            validator = "etag",
+            revalidate_after = 1,
+            map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+        )]
+        async fn async_cached_redis_revalidated(
+            n: u32,
+            etag: Option<String>,
+        ) -> Result<cached::Revalidation<u32>, TestError> {
+            if etag.is_some() {
+                Ok(cached::Revalidation::NotModified)
+            } else {
+                Ok(cached::Revalidation::Modified {
+                    value: n,
+                    validator: Some(n.to_string()),
+                })
+            }
+        }
+
+        #[tokio::test]
+        async fn test_async_cached_redis_revalidated() {
+            assert_eq!(async_cached_redis_revalidated(1).await, Ok(1));
+            assert_eq!(async_cached_redis_revalidated(1).await, Ok(1));
+            assert_eq!(async_cached_redis_revalidated_prime_cache(1).await, Ok(1));
+        }
+
         #[io_cached(
             redis = true,
             time = 1,
@@ -1614,3 +2094,1840 @@ fn test_result_fallback() {
         assert_eq!(cache.cache_misses(), Some(2));
     }
 }
+
+#[cached]
+fn lock_stats_double(n: u32) -> u32 {
+    n * 2
+}
+
+#[once]
+fn lock_stats_once() -> u32 {
+    1
+}
+
+#[test]
+fn test_lock_stats_registered() {
+    assert_eq!(lock_stats_double(1), 2);
+    assert_eq!(lock_stats_double(1), 2);
+    assert_eq!(lock_stats_once(), 1);
+
+    let registered = cached::registry::lock_stats();
+    let stats = |name: &str| {
+        registered
+            .iter()
+            .find(|(n, _)| *n == format!("cached::{}", name))
+            .map(|(_, stats)| stats.clone())
+            .unwrap()
+    };
+    let double = stats("lock_stats_double");
+    let once = stats("lock_stats_once");
+    assert_eq!(double, LOCK_STATS_DOUBLE_LOCK_STATS.snapshot());
+    if cfg!(feature = "lock_stats") {
+        // a miss takes the lock twice (lookup, then insert), a hit once
+        assert_eq!(double.acquisitions, 3);
+        assert_eq!(once.acquisitions, 2);
+        assert!(double.max_wait <= double.total_wait);
+        assert!(double.max_hold <= double.total_hold);
+    } else {
+        assert_eq!(double.acquisitions, 0);
+        assert_eq!(once.acquisitions, 0);
+    }
+}
+
+#[cached(size = 10, warm_size = 2)]
+fn warmth_double(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+fn test_warmth_registered() {
+    assert_eq!(warmth_double(1), 2);
+    assert_eq!(warmth_double(1), 2);
+    let warmth = |name: &str| {
+        cached::registry::warmth()
+            .into_iter()
+            .find(|(n, _)| *n == format!("cached::{}", name))
+            .map(|(_, warmth)| warmth)
+    };
+    assert_eq!(warmth("warmth_double"), Some(0.5));
+    assert!(!WARMTH_DOUBLE_WARMTH.is_ready());
+
+    warmth_double_prime_cache(2);
+    assert_eq!(warmth("warmth_double"), Some(1.0));
+    assert!(WARMTH_DOUBLE.lock().unwrap().cache_ready(2));
+    // caches without `warm_size` do not report their warmth
+    assert_eq!(warmth("lock_stats_double"), None);
+}
+
+static QUARANTINED_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(result = true, quarantine_after = 2, quarantine_secs = 1)]
+fn quarantined_fetch(n: u32) -> Result<u32, String> {
+    QUARANTINED_CALLS.fetch_add(1, Ordering::SeqCst);
+    if n == 0 {
+        Err("not found".to_string())
+    } else {
+        Ok(n)
+    }
+}
+
+#[test]
+fn test_quarantine() {
+    assert!(quarantined_fetch(0).is_err());
+    assert!(quarantined_fetch(0).is_err());
+    assert_eq!(QUARANTINED_CALLS.load(Ordering::SeqCst), 2);
+
+    // the key is quarantined, the function is not called
+    assert_eq!(quarantined_fetch(0), Err("not found".to_string()));
+    assert_eq!(QUARANTINED_CALLS.load(Ordering::SeqCst), 2);
+    assert_eq!(quarantined_fetch(1), Ok(1));
+    assert_eq!(QUARANTINED_CALLS.load(Ordering::SeqCst), 3);
+
+    let stats = QUARANTINED_FETCH_QUARANTINE.stats();
+    assert_eq!(stats.quarantined, 1);
+    assert_eq!(stats.quarantines, 1);
+    assert_eq!(stats.rejections, 1);
+
+    // the function is retried once the quarantine is over
+    sleep(Duration::from_millis(1100));
+    assert!(quarantined_fetch(0).is_err());
+    assert_eq!(QUARANTINED_CALLS.load(Ordering::SeqCst), 4);
+    assert_eq!(QUARANTINED_FETCH_QUARANTINE.stats().quarantines, 2);
+}
+
+#[cached(result = true, quarantine_after = 1)]
+fn quarantined_lookup(name: &str) -> Result<usize, String> {
+    if name.starts_with('_') {
+        Err(format!("{} is private", name))
+    } else {
+        Ok(name.len())
+    }
+}
+
+#[test]
+fn test_quarantine_of_borrowed_keys() {
+    assert!(quarantined_lookup("_a").is_err());
+    assert_eq!(quarantined_lookup("b"), Ok(1));
+    // the keys themselves are quarantined
+    assert!(QUARANTINED_LOOKUP_QUARANTINE.check("_a").is_some());
+    assert!(QUARANTINED_LOOKUP_QUARANTINE.check("b").is_none());
+    assert_eq!(QUARANTINED_LOOKUP_QUARANTINE.stats().quarantined, 1);
+}
+
+static CONFIG_EPOCH_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(size = 10, config_epoch = true)]
+fn config_epoch_keyed(n: u32) -> u32 {
+    CONFIG_EPOCH_CALLS.fetch_add(1, Ordering::SeqCst);
+    n
+}
+
+#[cached(config_epoch = true)]
+fn unbound_config_epoch_keyed(n: u32) -> u32 {
+    n
+}
+
+#[test]
+#[serial(ConfigEpoch)]
+fn test_config_epoch_invalidates() {
+    config_epoch_keyed(1);
+    config_epoch_keyed(1);
+    assert_eq!(CONFIG_EPOCH_CALLS.load(Ordering::SeqCst), 1);
+    unbound_config_epoch_keyed(1);
+    unbound_config_epoch_keyed(2);
+
+    let epoch = cached::registry::config_epoch();
+    assert!(cached::registry::bump_config_epoch() > epoch);
+    config_epoch_keyed(1);
+    assert_eq!(CONFIG_EPOCH_CALLS.load(Ordering::SeqCst), 2);
+    // the value of the previous epoch is cleared
+    assert_eq!(CONFIG_EPOCH_KEYED.lock().unwrap().cache_size(), 1);
+
+    // the unbound caches do not keep the values of the previous epochs either
+    unbound_config_epoch_keyed(3);
+    let cache = UNBOUND_CONFIG_EPOCH_KEYED.lock().unwrap();
+    assert_eq!(cache.cache_size(), 1);
+    assert!(cached::CachedRead::cache_iter(&*cache).all(|(key, _)| key.1 == 3));
+}
+
+#[cfg(feature = "key_invalidation")]
+#[cached(invalidation = true)]
+fn invalidated_concat(a: String, b: u32) -> String {
+    format!("{}{}", a, b)
+}
+
+#[cfg(feature = "key_invalidation")]
+#[test]
+fn test_invalidation_by_arguments() {
+    let name = concat!(module_path!(), "::invalidated_concat");
+    invalidated_concat("a".to_string(), 1);
+    invalidated_concat("b".to_string(), 2);
+    assert_eq!(cached::registry::invalidate(name, r#"["a", 1]"#), Ok(true));
+    assert_eq!(cached::registry::invalidate(name, r#"["a", 1]"#), Ok(false));
+    assert!(matches!(
+        cached::registry::invalidate(name, r#"["a"]"#),
+        Err(cached::registry::InvalidationError::InvalidKey(_))
+    ));
+    assert_eq!(INVALIDATED_CONCAT.lock().unwrap().cache_size(), 1);
+}
+
+#[cfg(feature = "key_invalidation")]
+#[cached(invalidation = true)]
+fn invalidated_borrowed(text: &str, ids: &[u32]) -> usize {
+    text.len() + ids.len()
+}
+
+#[cfg(feature = "key_invalidation")]
+#[test]
+fn test_invalidation_of_borrowed_arguments() {
+    let name = concat!(module_path!(), "::invalidated_borrowed");
+    invalidated_borrowed("say \"hi\"\n", &[1, 2]);
+    invalidated_borrowed("plain", &[]);
+    // the escapes of the JSON strings are unescaped into owned keys
+    assert_eq!(
+        cached::registry::invalidate(name, r#"["say \"hi\"\n", [1, 2]]"#),
+        Ok(true)
+    );
+    assert_eq!(
+        cached::registry::invalidate(name, r#"["plain", []]"#),
+        Ok(true)
+    );
+    assert_eq!(INVALIDATED_BORROWED.lock().unwrap().cache_size(), 0);
+}
+
+#[cfg(all(feature = "key_invalidation", feature = "async"))]
+#[cached(invalidation = true)]
+async fn async_invalidated_double(n: u32) -> u32 {
+    n * 2
+}
+
+#[cfg(all(feature = "key_invalidation", feature = "async"))]
+#[tokio::test]
+async fn test_async_invalidation_by_arguments() {
+    let name = concat!(module_path!(), "::async_invalidated_double");
+    async_invalidated_double(1).await;
+    assert_eq!(cached::registry::invalidate(name, "[1]"), Ok(true));
+    assert_eq!(ASYNC_INVALIDATED_DOUBLE.lock().await.cache_size(), 0);
+}
+
+struct Repository {
+    prefix: String,
+    calls: AtomicU32,
+    cache: std::sync::Mutex<SizedCache<u32, String>>,
+}
+
+impl Repository {
+    fn new(prefix: &str) -> Repository {
+        Repository {
+            prefix: prefix.to_string(),
+            calls: AtomicU32::new(0),
+            cache: Repository::lookup_new_cache(),
+        }
+    }
+
+    #[cached(instance = "cache", size = 2)]
+    fn lookup(&self, id: u32) -> String {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        format!("{}{}", self.prefix, id)
+    }
+}
+
+#[test]
+fn test_cached_instance() {
+    let a = Repository::new("a");
+    let b = Repository::new("b");
+    assert_eq!(a.lookup(1), "a1");
+    assert_eq!(a.lookup(1), "a1");
+    assert_eq!(b.lookup(1), "b1");
+    assert_eq!(a.calls.load(Ordering::SeqCst), 1);
+    assert_eq!(b.calls.load(Ordering::SeqCst), 1);
+
+    b.lookup_prime_cache(1);
+    assert_eq!(b.calls.load(Ordering::SeqCst), 2);
+    assert_eq!(a.cache.lock().unwrap().cache_capacity(), Some(2));
+}
+
+#[cfg(feature = "async")]
+struct AsyncRepository {
+    prefix: String,
+    cache: cached::async_sync::Mutex<UnboundCache<u32, String>>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncRepository {
+    #[cached(instance = "cache")]
+    async fn lookup(&self, id: u32) -> String {
+        format!("{}{}", self.prefix, id)
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_instance_a() {
+    let a = AsyncRepository {
+        prefix: "a".to_string(),
+        cache: AsyncRepository::lookup_new_cache(),
+    };
+    let b = AsyncRepository {
+        prefix: "b".to_string(),
+        cache: AsyncRepository::lookup_new_cache(),
+    };
+    assert_eq!(a.lookup(1).await, "a1");
+    assert_eq!(b.lookup(1).await, "b1");
+    assert_eq!(a.cache.lock().await.cache_size(), 1);
+    assert_eq!(b.cache.lock().await.cache_size(), 1);
+    assert_eq!(b.lookup_no_cache(2).await, "b2");
+}
+
+trait Describe {
+    fn describe(&self, id: u32) -> String;
+    fn parse(text: String) -> Result<u32, String>;
+}
+
+struct Describer;
+
+static DESCRIBE_CALLS: AtomicU32 = AtomicU32::new(0);
+
+impl Describe for Describer {
+    #[cached(in_impl = true, size = 10)]
+    fn describe(&self, id: u32) -> String {
+        DESCRIBE_CALLS.fetch_add(1, Ordering::SeqCst);
+        format!("item {}", id)
+    }
+
+    #[cached(in_impl = true, result = true)]
+    fn parse(mut text: String) -> Result<u32, String> {
+        text.retain(|c| !c.is_whitespace());
+        let n = text.parse::<u32>().map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("zero".to_string());
+        }
+        Ok(n)
+    }
+}
+
+#[test]
+fn test_cached_in_trait_impl() {
+    assert_eq!(Describer.describe(1), "item 1");
+    assert_eq!(Describer.describe(1), "item 1");
+    assert_eq!(Describer.describe(2), "item 2");
+    assert_eq!(DESCRIBE_CALLS.load(Ordering::SeqCst), 2);
+
+    assert_eq!(Describer::parse(" 12".to_string()), Ok(12));
+    assert_eq!(Describer::parse("0".to_string()), Err("zero".to_string()));
+    assert!(Describer::parse("x".to_string()).is_err());
+}
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+trait Fetch {
+    async fn fetch(&self, id: u32) -> Result<String, String>;
+}
+
+#[cfg(feature = "async")]
+struct Fetcher;
+
+#[cfg(feature = "async")]
+static FETCH_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl Fetch for Fetcher {
+    #[cached(in_impl = true, result = true)]
+    async fn fetch(&self, id: u32) -> Result<String, String> {
+        FETCH_CALLS.fetch_add(1, Ordering::SeqCst);
+        if id == 0 {
+            return Err("not found".to_string());
+        }
+        tokio::task::yield_now().await;
+        Ok(format!("fetched {}", id))
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_in_async_trait_impl() {
+    assert_eq!(Fetcher.fetch(1).await, Ok("fetched 1".to_string()));
+    assert_eq!(Fetcher.fetch(1).await, Ok("fetched 1".to_string()));
+    assert!(Fetcher.fetch(0).await.is_err());
+    assert!(Fetcher.fetch(0).await.is_err());
+    assert_eq!(FETCH_CALLS.load(Ordering::SeqCst), 3);
+}
+
+#[cfg(feature = "async")]
+trait Render {
+    async fn render(&self, id: u32) -> Result<String, String>;
+}
+
+#[cfg(feature = "async")]
+impl Render for Fetcher {
+    #[cached(in_impl = true, result = true)]
+    async fn render(&self, id: u32) -> Result<String, String> {
+        if id == 0 {
+            return Err("not found".to_string());
+        }
+        tokio::task::yield_now().await;
+        Ok(format!("<p>{}</p>", id))
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_in_native_async_trait_impl() {
+    assert_eq!(Fetcher.render(2).await, Ok("<p>2</p>".to_string()));
+    assert_eq!(Fetcher.render(2).await, Ok("<p>2</p>".to_string()));
+    assert!(Fetcher.render(0).await.is_err());
+}
+
+struct Connection {
+    queries: AtomicU32,
+}
+
+#[cached(ignore(conn, verbose))]
+fn query_user(conn: &Connection, id: u32, verbose: bool) -> String {
+    conn.queries.fetch_add(1, Ordering::SeqCst);
+    if verbose {
+        format!("user {} (verbose)", id)
+    } else {
+        format!("user {}", id)
+    }
+}
+
+#[test]
+fn test_cached_ignore() {
+    let conn = Connection {
+        queries: AtomicU32::new(0),
+    };
+    let other = Connection {
+        queries: AtomicU32::new(0),
+    };
+    assert_eq!(query_user(&conn, 1, false), "user 1");
+    assert_eq!(query_user(&other, 1, true), "user 1");
+    assert_eq!(query_user(&other, 2, true), "user 2 (verbose)");
+    assert_eq!(conn.queries.load(Ordering::SeqCst), 1);
+    assert_eq!(other.queries.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        QUERY_USER.lock().unwrap().get_store().get(&2),
+        Some(&"user 2 (verbose)".to_string())
+    );
+}
+
+/// FNV-1a, a fast hasher for small keys
+#[derive(Default)]
+struct FnvHasher(u64);
+
+impl std::hash::Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let mut hash = if self.0 == 0 {
+            0xcbf2_9ce4_8422_2325
+        } else {
+            self.0
+        };
+        for byte in bytes {
+            hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+        self.0 = hash;
+    }
+}
+
+type FnvBuildHasher = std::hash::BuildHasherDefault<FnvHasher>;
+
+#[cached(size = 2, hasher = "FnvBuildHasher")]
+fn fnv_sized_square(n: u64) -> u64 {
+    n * n
+}
+
+#[cached(time = 60, time_refresh = true, hasher = "FnvBuildHasher")]
+fn fnv_timed_square(n: u64) -> u64 {
+    n * n
+}
+
+#[test]
+fn test_cached_hasher() {
+    assert_eq!(fnv_sized_square(3), 9);
+    assert_eq!(fnv_sized_square(3), 9);
+    assert_eq!(fnv_timed_square(4), 16);
+    assert_eq!(fnv_timed_square(4), 16);
+
+    let sized: &SizedCache<u64, u64, FnvBuildHasher> = &FNV_SIZED_SQUARE.lock().unwrap();
+    assert_eq!(sized.cache_hits(), Some(1));
+    let timed: &TimedCache<u64, u64, FnvBuildHasher> = &FNV_TIMED_SQUARE.lock().unwrap();
+    assert_eq!(timed.cache_hits(), Some(1));
+    assert!(timed.refresh());
+}
+
+static MANAGED_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(size = 10)]
+fn managed_concat(a: String, b: u32) -> String {
+    MANAGED_CALLS.fetch_add(1, Ordering::SeqCst);
+    format!("{}{}", a, b)
+}
+
+#[test]
+fn test_cached_management_functions() {
+    managed_concat_prime_cache("a".to_string(), 1);
+    managed_concat("a".to_string(), 1);
+    managed_concat("b".to_string(), 2);
+    assert_eq!(MANAGED_CALLS.load(Ordering::SeqCst), 2);
+
+    assert_eq!(
+        managed_concat_cache_remove("a".to_string(), 1),
+        Some("a1".to_string())
+    );
+    assert_eq!(managed_concat_cache_remove("a".to_string(), 1), None);
+    managed_concat("a".to_string(), 1);
+    assert_eq!(MANAGED_CALLS.load(Ordering::SeqCst), 3);
+
+    managed_concat_cache_clear();
+    assert_eq!(MANAGED_CONCAT.lock().unwrap().cache_size(), 0);
+}
+
+#[cfg(feature = "async")]
+#[cached(result = true)]
+async fn async_managed_double(n: u32) -> Result<u32, String> {
+    Ok(n * 2)
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_cached_management_functions() {
+    assert_eq!(async_managed_double(1).await, Ok(2));
+    assert_eq!(async_managed_double(2).await, Ok(4));
+    assert_eq!(async_managed_double_cache_remove(1).await, Some(2));
+    assert_eq!(async_managed_double_cache_remove(1).await, None);
+    async_managed_double_cache_clear().await;
+    assert_eq!(ASYNC_MANAGED_DOUBLE.lock().await.cache_size(), 0);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Response {
+    status: u16,
+    body: String,
+}
+
+static CACHE_IF_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(cache_if = "|response: &Response| response.status == 200")]
+fn fetch_response(path: String) -> Response {
+    CACHE_IF_CALLS.fetch_add(1, Ordering::SeqCst);
+    let status = if path.starts_with("/ok") { 200 } else { 503 };
+    Response { status, body: path }
+}
+
+#[cached(result = true, cache_if = "|n: &u32| *n > 1")]
+fn parse_above_one(text: &'static str) -> Result<u32, String> {
+    CACHE_IF_CALLS.fetch_add(1, Ordering::SeqCst);
+    text.parse().map_err(|_| text.to_string())
+}
+
+#[test]
+fn test_cached_cache_if() {
+    fetch_response("/ok".to_string());
+    fetch_response("/ok".to_string());
+    fetch_response("/degraded".to_string());
+    fetch_response("/degraded".to_string());
+    assert_eq!(CACHE_IF_CALLS.load(Ordering::SeqCst), 3);
+    assert_eq!(FETCH_RESPONSE.lock().unwrap().cache_size(), 1);
+
+    parse_above_one("1").unwrap();
+    parse_above_one("2").unwrap();
+    parse_above_one("x").unwrap_err();
+    assert_eq!(PARSE_ABOVE_ONE.lock().unwrap().cache_size(), 1);
+}
+
+#[cached(result = true, cache_unless = "|ids: &Vec<u32>| ids.is_empty()")]
+fn pending_ids(batch: u32) -> Result<Vec<u32>, String> {
+    match batch {
+        0 => Err("no batch".to_string()),
+        1 => Ok(vec![]),
+        _ => Ok(vec![batch, batch + 1]),
+    }
+}
+
+#[cached(
+    option = true,
+    cache_if = "|n: &u32| *n < 100",
+    cache_unless = "|n: &u32| *n == 0"
+)]
+fn bounded_len(text: String) -> Option<u32> {
+    Some(text.len() as u32)
+}
+
+#[test]
+fn test_cached_cache_unless() {
+    assert!(pending_ids(0).is_err());
+    assert_eq!(pending_ids(1), Ok(vec![]));
+    assert_eq!(pending_ids(2), Ok(vec![2, 3]));
+    assert_eq!(PENDING_IDS.lock().unwrap().cache_size(), 1);
+
+    bounded_len(String::new());
+    bounded_len("a".repeat(3));
+    bounded_len("a".repeat(300));
+    assert_eq!(BOUNDED_LEN.lock().unwrap().cache_size(), 1);
+}
+
+static POLICY_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(cache_policy = true)]
+fn max_age_fetch(path: String, max_age_ms: u64) -> cached::CachePolicy<String> {
+    POLICY_CALLS.fetch_add(1, Ordering::SeqCst);
+    cached::CachePolicy::with_ttl(
+        format!("body of {}", path),
+        Duration::from_millis(max_age_ms),
+    )
+}
+
+#[cached(size = 10, result = true, cache_policy = true)]
+fn max_age_parse(text: String) -> Result<cached::CachePolicy<u32>, String> {
+    let n: u32 = text.parse().map_err(|_| text.clone())?;
+    Ok(cached::CachePolicy::forever(n))
+}
+
+#[test]
+fn test_cached_cache_policy() {
+    let body: String = max_age_fetch("/a".to_string(), 50);
+    assert_eq!(body, "body of /a");
+    max_age_fetch("/a".to_string(), 50);
+    max_age_fetch("/b".to_string(), 60_000);
+    assert_eq!(POLICY_CALLS.load(Ordering::SeqCst), 2);
+
+    sleep(Duration::from_millis(100));
+    max_age_fetch("/a".to_string(), 50);
+    max_age_fetch("/b".to_string(), 60_000);
+    assert_eq!(POLICY_CALLS.load(Ordering::SeqCst), 3);
+    assert_eq!(
+        max_age_fetch_cache_remove("/b".to_string(), 60_000),
+        Some("body of /b".to_string())
+    );
+
+    assert_eq!(max_age_parse("7".to_string()), Ok(7));
+    assert_eq!(max_age_parse("7".to_string()), Ok(7));
+    assert!(max_age_parse("x".to_string()).is_err());
+    assert_eq!(MAX_AGE_PARSE.lock().unwrap().cache_hits(), Some(1));
+}
+
+#[cfg(feature = "async")]
+#[cached(option = true, cache_policy = true)]
+async fn async_max_age(n: u32) -> Option<cached::CachePolicy<u32>> {
+    if n == 0 {
+        return None;
+    }
+    Some(cached::CachePolicy::with_ttl(n, Duration::from_secs(60)))
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_cached_cache_policy() {
+    assert_eq!(async_max_age(0).await, None);
+    assert_eq!(async_max_age(3).await, Some(3));
+    assert_eq!(async_max_age(3).await, Some(3));
+    assert_eq!(ASYNC_MAX_AGE.lock().await.cache_hits(), Some(1));
+}
+
+/// A value that cannot be cloned, only shared
+#[derive(Debug, PartialEq)]
+struct ParsedDocument {
+    words: Vec<String>,
+}
+
+#[cached(size = 10, wrap = "arc")]
+fn parse_document(text: String) -> ParsedDocument {
+    ParsedDocument {
+        words: text.split_whitespace().map(str::to_string).collect(),
+    }
+}
+
+#[cached(
+    result = true,
+    wrap = "arc",
+    cache_unless = "|doc: &ParsedDocument| doc.words.is_empty()"
+)]
+fn try_parse_document(text: String) -> Result<ParsedDocument, String> {
+    if text.contains('\0') {
+        return Err("binary document".to_string());
+    }
+    Ok(ParsedDocument {
+        words: text.split_whitespace().map(str::to_string).collect(),
+    })
+}
+
+#[test]
+fn test_cached_wrap_arc() {
+    let first: std::sync::Arc<ParsedDocument> = parse_document("a b c".to_string());
+    let second = parse_document("a b c".to_string());
+    assert!(std::sync::Arc::ptr_eq(&first, &second));
+    assert_eq!(first.words, vec!["a", "b", "c"]);
+    assert_eq!(PARSE_DOCUMENT.lock().unwrap().cache_hits(), Some(1));
+
+    let first = try_parse_document("x y".to_string()).unwrap();
+    let second = try_parse_document("x y".to_string()).unwrap();
+    assert!(std::sync::Arc::ptr_eq(&first, &second));
+    assert!(try_parse_document("\0".to_string()).is_err());
+    try_parse_document(String::new()).unwrap();
+    assert_eq!(TRY_PARSE_DOCUMENT.lock().unwrap().cache_size(), 1);
+    let removed = try_parse_document_cache_remove("x y".to_string()).unwrap();
+    assert!(std::sync::Arc::ptr_eq(&first, &removed));
+}
+
+static EVENS_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(
+    size = 10,
+    store_as = "|evens| -> Vec<u32> { evens.collect() }",
+    from_store = "|evens: &Vec<u32>| evens.clone().into_iter()"
+)]
+fn evens_below(limit: u32) -> impl Iterator<Item = u32> {
+    EVENS_CALLS.fetch_add(1, Ordering::SeqCst);
+    (0..limit).filter(|n| n % 2 == 0)
+}
+
+#[cached(
+    result = true,
+    store_as = "|label: Box<dyn std::fmt::Display>| -> String { label.to_string() }",
+    from_store = "|label: &String| Box::new(label.clone()) as Box<dyn std::fmt::Display>"
+)]
+fn boxed_label(n: i32) -> Result<Box<dyn std::fmt::Display>, String> {
+    if n < 0 {
+        return Err(format!("negative: {}", n));
+    }
+    Ok(Box::new(n))
+}
+
+#[test]
+fn test_cached_store_as() {
+    assert_eq!(evens_below(7).collect::<Vec<_>>(), vec![0, 2, 4, 6]);
+    assert_eq!(evens_below(7).collect::<Vec<_>>(), vec![0, 2, 4, 6]);
+    assert_eq!(EVENS_CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(evens_below_cache_remove(7), Some(vec![0, 2, 4, 6]));
+
+    assert_eq!(boxed_label(3).unwrap().to_string(), "3");
+    assert_eq!(boxed_label(3).unwrap().to_string(), "3");
+    assert!(boxed_label(-1).is_err());
+    let cache = BOXED_LABEL.lock().unwrap();
+    assert_eq!(cache.cache_hits(), Some(1));
+    assert_eq!(cache.get_store().get(&3), Some(&"3".to_string()));
+}
+
+#[cfg(feature = "async")]
+static FUTURE_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[cached]
+fn boxed_future_len(
+    text: String,
+) -> std::pin::Pin<Box<dyn std::future::Future<Output = usize> + Send>> {
+    Box::pin(async move {
+        FUTURE_CALLS.fetch_add(1, Ordering::SeqCst);
+        text.len()
+    })
+}
+
+#[cfg(feature = "async")]
+#[cached(option = true)]
+fn impl_future_first(text: String) -> impl std::future::Future<Output = Option<char>> {
+    async move { text.chars().next() }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_future_returning_fns() {
+    let spawned = tokio::spawn(boxed_future_len("abc".to_string()));
+    assert_eq!(spawned.await.unwrap(), 3);
+    assert_eq!(boxed_future_len("abc".to_string()).await, 3);
+    assert_eq!(FUTURE_CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        boxed_future_len_cache_remove("abc".to_string()).await,
+        Some(3)
+    );
+    boxed_future_len_prime_cache("abc".to_string()).await;
+    assert_eq!(FUTURE_CALLS.load(Ordering::SeqCst), 2);
+
+    assert_eq!(impl_future_first(String::new()).await, None);
+    assert_eq!(impl_future_first("xy".to_string()).await, Some('x'));
+    assert_eq!(impl_future_first("xy".to_string()).await, Some('x'));
+    assert_eq!(IMPL_FUTURE_FIRST.lock().await.cache_hits(), Some(1));
+    impl_future_first_cache_clear().await;
+    assert_eq!(IMPL_FUTURE_FIRST.lock().await.cache_size(), 0);
+}
+
+#[cached(size = 2)]
+fn stats_square(n: u64) -> u64 {
+    n * n
+}
+
+#[test]
+fn test_cached_cache_stats() {
+    stats_square(1);
+    stats_square(1);
+    stats_square(2);
+    stats_square(3);
+    // read without the lock of the cache
+    let cache = STATS_SQUARE.lock().unwrap();
+    let stats = stats_square_cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.size), (1, 3, 2));
+    assert_eq!(stats.evictions, 1);
+    drop(cache);
+
+    assert_eq!(stats_square_cache_remove(3), Some(9));
+    assert_eq!(stats_square_cache_stats().size, 1);
+    stats_square_cache_clear();
+    assert_eq!(stats_square_cache_stats().size, 0);
+    assert!(cached::registry::cache_stats()
+        .iter()
+        .any(|(name, stats)| name.ends_with("::stats_square") && stats.hits == 1));
+}
+
+#[cfg(feature = "tracing")]
+mod instrumented {
+    use cached::proc_macro::cached;
+    use cached::tracing::field::{Field, Visit};
+    use cached::tracing::span::{Attributes, Id, Record};
+    use cached::tracing::{subscriber, Event, Metadata, Subscriber};
+    use std::sync::{Arc, Mutex};
+
+    /// Records the messages of the events along with the fields of their span
+    #[derive(Clone, Default)]
+    struct Recorder {
+        spans: Arc<Mutex<Vec<String>>>,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[derive(Default)]
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!("{}={:?} ", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Fields::default();
+            span.record(&mut fields);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(fields.0);
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            let span = event
+                .parent()
+                .map(|id| self.spans.lock().unwrap()[id.into_u64() as usize - 1].clone())
+                .unwrap_or_default();
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("{}| {}", span.trim(), fields.0.trim()));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[cached(size = 1, instrument = true)]
+    fn traced_double(n: u32) -> u32 {
+        n * 2
+    }
+
+    #[test]
+    fn test_cached_instrument() {
+        let recorder = Recorder::default();
+        subscriber::with_default(recorder.clone(), || {
+            traced_double(1);
+            traced_double(1);
+            traced_double(2);
+        });
+        let events = recorder.events.lock().unwrap();
+        let span = |key: u32| {
+            format!(
+                "function=\"cached::instrumented::traced_double\" key={}",
+                key
+            )
+        };
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[0], format!("{}| message=cache miss", span(1)));
+        assert!(events[1].starts_with(&format!("{}| message=cache load elapsed=", span(1))));
+        assert_eq!(events[2], format!("{}| message=cache hit", span(1)));
+        assert_eq!(events[3], format!("{}| message=cache miss", span(2)));
+        assert!(events[4].starts_with(&format!("{}| message=cache load", span(2))));
+        assert_eq!(
+            events[5],
+            format!("{}| message=cache eviction size=1", span(2))
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[cached(instrument = true)]
+    async fn traced_async_double(n: u32) -> u32 {
+        n * 2
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_cached_instrument() {
+        assert_eq!(tokio::spawn(traced_async_double(2)).await.unwrap(), 4);
+        assert_eq!(traced_async_double(2).await, 4);
+        assert_eq!(traced_async_double_cache_stats().hits, 1);
+    }
+}
+
+static PARSE_AS_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached]
+fn parse_as<T>(text: String) -> Option<T>
+where
+    T: std::str::FromStr + Clone + Send + 'static,
+{
+    PARSE_AS_CALLS.fetch_add(1, Ordering::SeqCst);
+    text.parse().ok()
+}
+
+#[cached(key = "String", convert = r#"{ value.to_string() }"#)]
+fn bracketed<T: std::fmt::Display + 'static>(value: T) -> String {
+    format!("<{}>", value)
+}
+
+#[test]
+fn test_cached_generic() {
+    assert_eq!(parse_as::<u32>("7".to_string()), Some(7));
+    assert_eq!(parse_as::<u32>("7".to_string()), Some(7));
+    assert_eq!(parse_as::<f64>("7".to_string()), Some(7.0));
+    assert_eq!(parse_as::<u32>("x".to_string()), None);
+    assert_eq!(PARSE_AS_CALLS.load(Ordering::SeqCst), 3);
+    assert_eq!(PARSE_AS.lock().unwrap().cache_size(), 3);
+    assert_eq!(
+        parse_as_cache_remove::<f64>("7".to_string()),
+        Some(Some(7.0))
+    );
+    parse_as_cache_clear();
+    assert_eq!(PARSE_AS.lock().unwrap().cache_size(), 0);
+
+    assert_eq!(bracketed(1), "<1>");
+    assert_eq!(bracketed("1"), "<1>");
+    assert_eq!(bracketed(1), "<1>");
+    assert_eq!(bracketed_cache_stats().misses, 2);
+}
+
+/// A store whose every operation fails
+struct UnavailableStore;
+
+impl cached::IOCached<u32, u32> for UnavailableStore {
+    type Error = String;
+
+    fn cache_get(&self, _key: &u32) -> Result<Option<u32>, String> {
+        Err("store unavailable".to_string())
+    }
+
+    fn cache_set(&self, _key: u32, _val: u32) -> Result<Option<u32>, String> {
+        Err("store unavailable".to_string())
+    }
+
+    fn cache_remove(&self, _key: &u32) -> Result<Option<u32>, String> {
+        Err("store unavailable".to_string())
+    }
+
+    fn cache_set_refresh(&mut self, _refresh: bool) -> bool {
+        false
+    }
+}
+
+static RECOMPUTED_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached::proc_macro::io_cached(
+    on_error = "recompute",
+    ty = "UnavailableStore",
+    create = "{ UnavailableStore }"
+)]
+fn recomputed(n: u32) -> u32 {
+    RECOMPUTED_CALLS.fetch_add(1, Ordering::SeqCst);
+    n + 1
+}
+
+#[cached::proc_macro::io_cached(
+    on_error = "log",
+    ty = "UnavailableStore",
+    create = "{ UnavailableStore }"
+)]
+fn logged(n: u32) -> u32 {
+    n + 1
+}
+
+#[cached::proc_macro::io_cached(
+    on_error = "panic",
+    ty = "UnavailableStore",
+    create = "{ UnavailableStore }"
+)]
+fn panicking(n: u32) -> u32 {
+    n + 1
+}
+
+#[test]
+fn test_io_cached_on_error() {
+    assert_eq!(recomputed(1), 2);
+    assert_eq!(recomputed(1), 2);
+    assert_eq!(RECOMPUTED_CALLS.load(Ordering::SeqCst), 2);
+    assert_eq!(recomputed_cache_stats().misses, 2);
+    assert_eq!(logged(1), 2);
+}
+
+static L1_FRONTED_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached::proc_macro::io_cached(
+    on_error = "recompute",
+    ty = "UnavailableStore",
+    create = "{ UnavailableStore }",
+    l1_size = 10
+)]
+fn l1_fronted(n: u32) -> u32 {
+    L1_FRONTED_CALLS.fetch_add(1, Ordering::SeqCst);
+    n + 1
+}
+
+#[test]
+fn test_io_cached_l1() {
+    assert_eq!(l1_fronted(1), 2);
+    assert_eq!(l1_fronted(1), 2);
+    assert_eq!(L1_FRONTED_CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(L1_FRONTED_L1.lock().unwrap().cache_size(), 1);
+}
+
+#[cached::proc_macro::io_cached(
+    map_error = r##"|e| e"##,
+    ty = "UnavailableStore",
+    create = "{ UnavailableStore }",
+    breaker_after = 1
+)]
+fn guarded(n: u32) -> Result<u32, String> {
+    Ok(n + 1)
+}
+
+#[test]
+fn test_io_cached_circuit_breaker() {
+    assert_eq!(guarded(1), Err("store unavailable".to_string()));
+    assert!(GUARDED_BREAKER.is_open());
+    // the store is skipped, for both getting and setting the value
+    assert_eq!(guarded(1), Ok(2));
+    assert_eq!(GUARDED_BREAKER.stats().skipped, 2);
+    assert_eq!(GUARDED_BREAKER.stats().trips, 1);
+}
+
+#[test]
+#[should_panic(expected = "store unavailable")]
+fn test_io_cached_on_error_panic() {
+    panicking(1);
+}
+
+#[cached(key = "String", convert = r#"{ format!("user:{}:{}", user, field) }"#)]
+fn user_field(user: u32, field: &str) -> String {
+    format!("{} of {}", field, user)
+}
+
+#[cached(size = 10, config_epoch = true)]
+fn greeting(name: String) -> String {
+    format!("hello {}", name)
+}
+
+#[test]
+#[serial(ConfigEpoch)]
+fn test_cached_remove_prefix() {
+    user_field(1, "name");
+    user_field(1, "email");
+    user_field(2, "name");
+    assert_eq!(user_field_cache_remove_prefix("user:1:"), 2);
+    assert_eq!(user_field_cache_remove_prefix("user:1:"), 0);
+    assert_eq!(USER_FIELD.lock().unwrap().cache_size(), 1);
+
+    greeting("alice".to_string());
+    greeting("bob".to_string());
+    assert_eq!(greeting_cache_remove_prefix("al"), 1);
+    assert_eq!(greeting_cache_stats().size, 1);
+}
+
+static REFRESHED_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[once(refresh_every = 1)]
+fn once_refreshed() -> u32 {
+    REFRESHED_CALLS.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+#[test]
+fn test_once_refresh_every() {
+    assert_eq!(once_refreshed(), 1);
+    assert_eq!(once_refreshed(), 1);
+    // refreshed in the background, the value never expiring
+    sleep(Duration::from_millis(1500));
+    assert!(once_refreshed_initialized());
+    assert_eq!(once_refreshed(), 2);
+    assert_eq!(REFRESHED_CALLS.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "async")]
+static ASYNC_REFRESHED_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[once(refresh_every = 1, spawn = "task", result = true)]
+async fn once_refreshed_a() -> Result<u32, ()> {
+    match ASYNC_REFRESHED_CALLS.fetch_add(1, Ordering::SeqCst) {
+        1 => Err(()),
+        calls => Ok(calls + 1),
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_once_refresh_every_a() {
+    assert_eq!(once_refreshed_a().await, Ok(1));
+    // the failed refresh keeps the previous value
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(once_refreshed_a().await, Ok(1));
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    assert_eq!(once_refreshed_a().await, Ok(3));
+}
+
+#[cached(size_bytes = 4096)]
+fn padded(n: usize) -> String {
+    "x".repeat(n)
+}
+
+#[test]
+fn test_cached_size_bytes() {
+    padded(1000);
+    padded(1000);
+    padded(2000);
+    {
+        let cache = PADDED.lock().unwrap();
+        assert_eq!(cache.cache_size(), 2);
+        assert!(cache.weight() <= cache.budget());
+    }
+    // 1000 is evicted to stay within 4 KiB
+    padded(2000);
+    padded(1500);
+    let cache = PADDED.lock().unwrap();
+    assert_eq!(cache.cache_size(), 2);
+    assert_eq!(cache.cache_hits(), Some(2));
+    assert_eq!(cache.evictions(), 1);
+}
+
+#[cached(size_bytes = 10, weigher = "|_: &u32, value: &Vec<u8>| value.len()")]
+fn weighed(n: u32) -> Vec<u8> {
+    vec![0; n as usize]
+}
+
+#[test]
+fn test_cached_weigher() {
+    weighed(4);
+    weighed(5);
+    weighed(6);
+    let cache = WEIGHED.lock().unwrap();
+    assert_eq!((cache.cache_size(), cache.weight()), (1, 6));
+}
+
+#[cfg(feature = "async")]
+#[derive(Debug, PartialEq)]
+enum UpstreamError {
+    TimedOut,
+}
+
+#[cfg(feature = "async")]
+impl From<cached::proc_macro::TimeoutError> for UpstreamError {
+    fn from(_: cached::proc_macro::TimeoutError) -> Self {
+        UpstreamError::TimedOut
+    }
+}
+
+#[cfg(feature = "async")]
+#[cached(result = true, timeout_ms = 50)]
+async fn bounded(delay_ms: u64) -> Result<u64, UpstreamError> {
+    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+    Ok(delay_ms)
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_timeout_ms() {
+    assert_eq!(bounded(10).await, Ok(10));
+    assert_eq!(bounded(200).await, Err(UpstreamError::TimedOut));
+    let cache = BOUNDED.lock().await;
+    assert_eq!(cache.cache_size(), 1);
+}
+
+#[cfg(feature = "async")]
+static UPSTREAM_DELAY_MS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[cached(time = 1, result = true, timeout_ms = 50, timeout_fallback = "stale")]
+async fn stale_on_timeout(n: u32) -> Result<u32, UpstreamError> {
+    let delay = UPSTREAM_DELAY_MS.load(Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(delay.into())).await;
+    Ok(n + delay)
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_timeout_stale() {
+    assert_eq!(stale_on_timeout(1).await, Ok(1));
+    UPSTREAM_DELAY_MS.store(200, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    // the expired value is returned and cached again
+    assert_eq!(stale_on_timeout(1).await, Ok(1));
+    assert_eq!(stale_on_timeout(2).await, Err(UpstreamError::TimedOut));
+    UPSTREAM_DELAY_MS.store(10, Ordering::SeqCst);
+    assert_eq!(stale_on_timeout(1).await, Ok(1));
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(stale_on_timeout(1).await, Ok(11));
+}
+
+#[cfg(feature = "async_std_rt")]
+#[cached(result = true, timeout_ms = 50)]
+async fn bounded_async_std(delay_ms: u64) -> Result<u64, UpstreamError> {
+    async_std::task::sleep(Duration::from_millis(delay_ms)).await;
+    Ok(delay_ms)
+}
+
+#[cfg(feature = "async_std_rt")]
+#[async_std::test]
+async fn test_cached_timeout_ms_async_std() {
+    assert_eq!(bounded_async_std(10).await, Ok(10));
+    assert_eq!(bounded_async_std(200).await, Err(UpstreamError::TimedOut));
+}
+
+mod geo {
+    use cached::proc_macro::cached;
+
+    #[cached(name = "GEO_CACHE", vis = "pub(crate)", size = 2)]
+    fn geo(city: String) -> (i32, i32) {
+        (city.len() as i32, -(city.len() as i32))
+    }
+
+    pub fn locate(city: &str) -> (i32, i32) {
+        geo(city.to_string())
+    }
+}
+
+#[test]
+fn test_cached_vis_and_accessor() {
+    assert_eq!(geo::locate("Paris"), (5, -5));
+    assert_eq!(geo::locate("Oslo"), (4, -4));
+    let cache: &'static std::sync::Mutex<SizedCache<String, (i32, i32)>> = geo::geo_cache();
+    assert!(std::ptr::eq(cache, &*geo::GEO_CACHE));
+    let mut cache = cache.lock().unwrap();
+    assert_eq!(cache.cache_size(), 2);
+    assert_eq!(cache.cache_remove("Paris"), Some((5, -5)));
+}
+
+#[derive(CacheKey, Clone, Hash, PartialEq, Eq)]
+struct Forecast {
+    city: String,
+    days: u8,
+}
+
+#[derive(CacheKey, Clone, Hash, PartialEq, Eq)]
+#[cache_key(display)]
+enum Lookup<T> {
+    ById(T),
+    ByName { name: String, exact: bool },
+    All,
+}
+
+#[cached(key = "String", convert = r#"{ forecast.cache_key() }"#)]
+fn forecast_days(forecast: &Forecast) -> u8 {
+    forecast.days
+}
+
+#[test]
+fn test_derive_cache_key() {
+    use cached::Cached;
+
+    let forecast = Forecast {
+        city: "Oslo".to_string(),
+        days: 3,
+    };
+    assert_eq!(forecast.cache_key(), r#"Forecast{city="Oslo",days=3}"#);
+    assert_eq!(forecast_days(&forecast), 3);
+    assert!(FORECAST_DAYS
+        .lock()
+        .unwrap()
+        .cache_get(r#"Forecast{city="Oslo",days=3}"#)
+        .is_some());
+
+    assert_eq!(Lookup::ById(7u32).to_string(), "Lookup::ById(7)");
+    let by_name: Lookup<u32> = Lookup::ByName {
+        name: "a\"b".to_string(),
+        exact: true,
+    };
+    assert_eq!(
+        by_name.cache_key(),
+        r#"Lookup::ByName{name="a\"b",exact=true}"#
+    );
+    assert_eq!(Lookup::<u32>::All.cache_key_bytes(), b"Lookup::All");
+}
+
+struct Geocoder {
+    precision: u32,
+}
+
+impl Geocoder {
+    #[cached(associated = true, size = 10)]
+    fn locate(city: String) -> usize {
+        city.len()
+    }
+
+    #[cached(
+        associated = true,
+        name = "GEOCODER_ROUNDED",
+        key = "u32",
+        convert = "{ n }"
+    )]
+    fn rounded(&self, n: u32) -> u32 {
+        n / self.precision * self.precision
+    }
+}
+
+#[test]
+fn test_cached_associated() {
+    assert_eq!(Geocoder::locate("Oslo".to_string()), 4);
+    assert_eq!(Geocoder::locate("Oslo".to_string()), 4);
+    assert_eq!(Geocoder::locate_cache_stats().hits, 1);
+    assert_eq!(Geocoder::locate_cache_remove("Oslo".to_string()), Some(4));
+    Geocoder::locate_prime_cache("Rome".to_string());
+    Geocoder::locate_cache_clear();
+    assert_eq!(Geocoder::locate_no_cache("Lima".to_string()), 4);
+
+    // the receiver is not part of the key
+    let geocoder = Geocoder { precision: 10 };
+    assert_eq!(geocoder.rounded(42), 40);
+    assert_eq!(Geocoder { precision: 100 }.rounded(42), 40);
+    assert_eq!(Geocoder::geocoder_rounded().lock().unwrap().cache_size(), 1);
+    geocoder.rounded_cache_clear();
+    assert_eq!(Geocoder { precision: 100 }.rounded(42), 0);
+}
+
+#[cfg(feature = "async")]
+struct AsyncGeocoder;
+
+#[cfg(feature = "async")]
+impl AsyncGeocoder {
+    #[cached(associated = true)]
+    async fn locate(city: String) -> usize {
+        city.len()
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_associated_a() {
+    assert_eq!(AsyncGeocoder::locate("Oslo".to_string()).await, 4);
+    assert_eq!(AsyncGeocoder::locate("Oslo".to_string()).await, 4);
+    assert_eq!(AsyncGeocoder::locate_cache_stats().hits, 1);
+    AsyncGeocoder::locate_cache_clear().await;
+}
+
+#[cached(key_map = "|city: &str| city.trim().to_ascii_lowercase()")]
+fn normalized_city(city: String) -> usize {
+    city.len()
+}
+
+#[cached(
+    key = "(String, u32)",
+    convert = r#"{ (city.to_string(), zoom) }"#,
+    key_map = "|(city, zoom): &(String, u32)| (city.to_uppercase(), *zoom)"
+)]
+fn normalized_tile(city: &str, zoom: u32) -> String {
+    format!("{}/{}", city, zoom)
+}
+
+#[test]
+fn test_cached_key_map() {
+    assert_eq!(normalized_city("Oslo".to_string()), 4);
+    // variants of the same key share the value computed first
+    assert_eq!(normalized_city(" OSLO  ".to_string()), 4);
+    assert_eq!(normalized_city_cache_stats().hits, 1);
+    assert_eq!(normalized_city_cache_remove(" oslo".to_string()), Some(4));
+
+    assert_eq!(normalized_tile("oslo", 3), "oslo/3");
+    assert_eq!(normalized_tile("Oslo", 3), "oslo/3");
+    assert_eq!(normalized_tile("Oslo", 4), "Oslo/4");
+    assert_eq!(normalized_tile_cache_stats().hits, 1);
+}
+
+#[cached]
+fn borrowed_word_count(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+#[cached(size = 2)]
+fn borrowed_checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().map(|&b| u32::from(b)).sum()
+}
+
+#[test]
+fn test_cached_borrowed_key() {
+    // the `&str` is looked up borrowed and cached as a `String`
+    assert_eq!(borrowed_word_count("a b c"), 3);
+    assert_eq!(borrowed_word_count(&String::from("a b c")), 3);
+    assert_eq!(borrowed_word_count_cache_stats().hits, 1);
+    {
+        let cache = BORROWED_WORD_COUNT.lock().unwrap();
+        let keys: Vec<&String> = cache.get_store().keys().collect();
+        assert_eq!(keys, ["a b c"]);
+    }
+    borrowed_word_count("a b");
+    assert_eq!(borrowed_word_count_cache_remove_prefix("a b "), 1);
+    assert_eq!(borrowed_word_count_cache_remove("a b"), Some(2));
+
+    assert_eq!(borrowed_checksum(&[1, 2, 3]), 6);
+    assert_eq!(borrowed_checksum(&[1u8, 2, 3][..]), 6);
+    assert_eq!(borrowed_checksum_cache_stats().hits, 1);
+    assert_eq!(borrowed_checksum_cache_remove(b"\x01\x02\x03"), Some(6));
+}
+
+static ONCE_NO_CACHE_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[once]
+fn once_no_cache() -> u32 {
+    ONCE_NO_CACHE_CALLS.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+#[test]
+fn test_once_no_cache() {
+    assert_eq!(once_no_cache(), 1);
+    assert_eq!(once_no_cache(), 1);
+    // the original function bypasses the cached value
+    assert_eq!(once_no_cache_no_cache(), 2);
+    assert_eq!(once_no_cache(), 1);
+}
+
+static PRIME_MANY_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(prime_concurrency = 4)]
+fn prime_many_area(width: u32, height: u32) -> u32 {
+    PRIME_MANY_CALLS.fetch_add(1, Ordering::SeqCst);
+    width * height
+}
+
+#[test]
+fn test_cached_prime_many() {
+    prime_many_area(1, 1);
+    prime_many_area_prime_many((1..=10).map(|width| (width, 2)).chain([(1, 1)]));
+    // the value already cached was not computed again
+    assert_eq!(PRIME_MANY_CALLS.load(Ordering::SeqCst), 11);
+    assert_eq!(PRIME_MANY_AREA.lock().unwrap().cache_size(), 11);
+    assert_eq!(prime_many_area(7, 2), 14);
+    assert_eq!(PRIME_MANY_CALLS.load(Ordering::SeqCst), 11);
+}
+
+#[cfg(feature = "async")]
+#[cached(prime_concurrency = 2)]
+async fn prime_many_slow_len(text: String) -> usize {
+    cached::runtime::sleep(Duration::from_millis(50)).await;
+    text.len()
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_prime_many_a() {
+    prime_many_slow_len_prime_many(vec!["a".to_string(), "bb".to_string(), "ccc".to_string()])
+        .await;
+    assert_eq!(PRIME_MANY_SLOW_LEN.lock().await.cache_size(), 3);
+    assert_eq!(prime_many_slow_len_cache_stats().misses, 3);
+}
+
+thread_local! {
+    static TENANT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+static TENANT_CACHES: cached::once_cell::sync::Lazy<
+    [std::sync::Mutex<UnboundCache<u32, String>>; 2],
+> = cached::once_cell::sync::Lazy::new(|| [tenant_label_new_cache(), tenant_label_new_cache()]);
+
+fn tenant_cache() -> &'static std::sync::Mutex<UnboundCache<u32, String>> {
+    &TENANT_CACHES[TENANT.with(|tenant| tenant.get())]
+}
+
+#[cached(store_fn = "tenant_cache")]
+fn tenant_label(id: u32) -> String {
+    format!("{}@{}", id, TENANT.with(|tenant| tenant.get()))
+}
+
+#[cfg(feature = "async")]
+#[cached(
+    store_fn = "|| std::sync::Arc::clone(&SHARED_LEN_CACHE)",
+    ty = "SizedCache<String, usize>",
+    create = "{ SizedCache::with_size(2) }"
+)]
+async fn shared_len(text: String) -> usize {
+    text.len()
+}
+
+#[cfg(feature = "async")]
+static SHARED_LEN_CACHE: cached::once_cell::sync::Lazy<
+    std::sync::Arc<cached::async_sync::Mutex<SizedCache<String, usize>>>,
+> = cached::once_cell::sync::Lazy::new(|| std::sync::Arc::new(shared_len_new_cache()));
+
+#[test]
+fn test_cached_store_fn() {
+    assert_eq!(tenant_label(1), "1@0");
+    TENANT.with(|tenant| tenant.set(1));
+    // each tenant has its own cache
+    assert_eq!(tenant_label(1), "1@1");
+    assert_eq!(tenant_label_cache_remove(1), Some("1@1".to_string()));
+    TENANT.with(|tenant| tenant.set(0));
+    assert_eq!(tenant_label(1), "1@0");
+    assert_eq!(TENANT_CACHES[0].lock().unwrap().cache_hits(), Some(1));
+    assert_eq!(TENANT_CACHES[1].lock().unwrap().cache_size(), 0);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_store_fn_a() {
+    assert_eq!(shared_len("abc".to_string()).await, 3);
+    assert_eq!(shared_len("abc".to_string()).await, 3);
+    assert_eq!(SHARED_LEN_CACHE.lock().await.cache_hits(), Some(1));
+    shared_len_cache_clear().await;
+    assert_eq!(SHARED_LEN_CACHE.lock().await.cache_size(), 0);
+}
+
+static THREAD_LOCAL_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(thread_local = true, size = 10)]
+fn thread_local_square(n: u64) -> u64 {
+    THREAD_LOCAL_CALLS.fetch_add(1, Ordering::SeqCst);
+    n * n
+}
+
+#[test]
+fn test_cached_thread_local() {
+    assert_eq!(thread_local_square(3), 9);
+    assert_eq!(thread_local_square(3), 9);
+    assert_eq!(THREAD_LOCAL_CALLS.load(Ordering::SeqCst), 1);
+    // another thread has its own cache
+    thread::spawn(|| {
+        assert_eq!(thread_local_square(3), 9);
+        assert_eq!(
+            THREAD_LOCAL_SQUARE.with(|cache| cache.borrow().cache_size()),
+            1
+        );
+        thread_local_square_cache_clear();
+    })
+    .join()
+    .unwrap();
+    assert_eq!(THREAD_LOCAL_CALLS.load(Ordering::SeqCst), 2);
+    assert_eq!(thread_local_square(3), 9);
+    assert_eq!(thread_local_square_cache_remove(3), Some(9));
+    let stats = thread_local_square_cache_stats();
+    assert_eq!((stats.hits, stats.misses), (2, 2));
+}
+
+static RW_LOCK_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(rw_lock = true)]
+fn rw_lock_double(n: u32) -> u32 {
+    RW_LOCK_CALLS.fetch_add(1, Ordering::SeqCst);
+    n * 2
+}
+
+#[test]
+fn test_cached_rw_lock() {
+    assert_eq!(rw_lock_double(4), 8);
+    // concurrent hits share the read lock
+    let guard = RW_LOCK_DOUBLE.read().unwrap();
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| assert_eq!(rw_lock_double(4), 8));
+        }
+    });
+    drop(guard);
+    assert_eq!(RW_LOCK_CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(rw_lock_double(5), 10);
+    let cache = RW_LOCK_DOUBLE.read().unwrap();
+    assert_eq!(
+        (cache.cache_hits(), cache.cache_misses()),
+        (Some(4), Some(2))
+    );
+}
+
+#[cfg(feature = "async")]
+#[cached(rw_lock = true, time = 60)]
+async fn rw_lock_len_a(s: String) -> usize {
+    s.len()
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_rw_lock_a() {
+    assert_eq!(rw_lock_len_a("abc".to_string()).await, 3);
+    assert_eq!(rw_lock_len_a("abc".to_string()).await, 3);
+    let cache = RW_LOCK_LEN_A.read().await;
+    assert_eq!(
+        (cache.cache_hits(), cache.cache_misses()),
+        (Some(1), Some(1))
+    );
+}
+
+#[cfg(feature = "async")]
+static SINGLE_FLIGHT_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[cached(sync_writes = "single_flight", result = true)]
+async fn single_flight_lookup(n: u32) -> Result<u32, String> {
+    SINGLE_FLIGHT_CALLS.fetch_add(1, Ordering::SeqCst);
+    cached::async_sync::sleep(Duration::from_millis(100)).await;
+    if n == 0 {
+        Err("not found".to_string())
+    } else {
+        Ok(n * 10)
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_single_flight_a() {
+    let (a, b, c) = tokio::join!(
+        single_flight_lookup(1),
+        single_flight_lookup(1),
+        single_flight_lookup(2)
+    );
+    assert_eq!((a, b, c), (Ok(10), Ok(10), Ok(20)));
+    assert_eq!(SINGLE_FLIGHT_CALLS.load(Ordering::SeqCst), 2);
+    // the errors, not cached, are shared too
+    let (a, b) = tokio::join!(single_flight_lookup(0), single_flight_lookup(0));
+    assert_eq!(a, Err("not found".to_string()));
+    assert_eq!(b, Err("not found".to_string()));
+    assert_eq!(SINGLE_FLIGHT_CALLS.load(Ordering::SeqCst), 3);
+    assert_eq!(SINGLE_FLIGHT_LOOKUP_FLIGHTS.in_flight(), 0);
+}
+
+#[cfg(feature = "async")]
+static TOKIO_REFRESHED_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[once(refresh_every = 1, spawn = "tokio")]
+async fn once_refreshed_on_tokio() -> u32 {
+    TOKIO_REFRESHED_CALLS.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_once_spawn_tokio() {
+    assert_eq!(once_refreshed_on_tokio().await, 1);
+    // refreshed by a task of the tokio runtime of the test
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(once_refreshed_on_tokio().await, 2);
+}
+
+// the handle of a runtime driven by its own thread
+#[cfg(feature = "async")]
+static MAINTENANCE: cached::once_cell::sync::Lazy<tokio::runtime::Handle> =
+    cached::once_cell::sync::Lazy::new(|| {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        thread::Builder::new()
+            .name("cache-maintenance".to_string())
+            .spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                sender.send(runtime.handle().clone()).unwrap();
+                runtime.block_on(std::future::pending::<()>());
+            })
+            .unwrap();
+        receiver.recv().unwrap()
+    });
+
+#[cfg(feature = "async")]
+#[once(refresh_every = 1, spawner = "&*MAINTENANCE")]
+async fn once_maintained() -> String {
+    thread::current().name().unwrap_or_default().to_string()
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_once_spawner() {
+    assert_ne!(once_maintained().await, "cache-maintenance");
+    // refreshed by the maintenance runtime
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(once_maintained().await, "cache-maintenance");
+}
+
+static ERR_TIME_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(time = 60, err_time = 1, result = true, cache_errors = true)]
+fn err_time_lookup(n: u32) -> Result<u32, String> {
+    ERR_TIME_CALLS.fetch_add(1, Ordering::SeqCst);
+    if n == 0 {
+        Err("not found".to_string())
+    } else {
+        Ok(n + 1)
+    }
+}
+
+#[test]
+fn test_cached_err_time() {
+    assert_eq!(err_time_lookup(0), Err("not found".to_string()));
+    assert_eq!(err_time_lookup(1), Ok(2));
+    // the errors are cached too
+    assert_eq!(err_time_lookup(0), Err("not found".to_string()));
+    assert_eq!(ERR_TIME_CALLS.load(Ordering::SeqCst), 2);
+
+    // only the error expired
+    sleep(Duration::from_millis(1100));
+    assert_eq!(err_time_lookup(1), Ok(2));
+    assert_eq!(err_time_lookup(0), Err("not found".to_string()));
+    assert_eq!(ERR_TIME_CALLS.load(Ordering::SeqCst), 3);
+    assert_eq!(err_time_lookup_cache_remove(1), Some(Ok(2)));
+}
+
+#[cfg(feature = "async")]
+#[cached(result = true, time = 1, result_fallback = true)]
+async fn always_failing_a(n: u32) -> Result<u32, ()> {
+    if n == 0 {
+        Ok(n)
+    } else {
+        Err(())
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_result_fallback_a() {
+    ALWAYS_FAILING_A.lock().await.cache_set(1, 10);
+    assert_eq!(always_failing_a(1).await, Ok(10));
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    // the expired value is returned instead of the error
+    assert_eq!(always_failing_a(1).await, Ok(10));
+    assert_eq!(always_failing_a(2).await, Err(()));
+}
+
+static HASHED_URL_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(hash_key = true, size = 100)]
+fn hashed_url_length(url: String) -> usize {
+    HASHED_URL_CALLS.fetch_add(1, Ordering::SeqCst);
+    url.len()
+}
+
+#[test]
+fn test_cached_hash_key() {
+    let url = format!("https://example.com/search?q={}", "a".repeat(200));
+    assert_eq!(hashed_url_length(url.clone()), url.len());
+    assert_eq!(hashed_url_length(url.clone()), url.len());
+    assert_eq!(HASHED_URL_CALLS.load(Ordering::SeqCst), 1);
+    {
+        let cache = HASHED_URL_LENGTH.lock().unwrap();
+        // only the hash of the key is kept
+        let keys: Vec<&u128> = cache.key_order().collect();
+        assert_eq!(keys, [&cached::cache_key::hash_key(&url)]);
+    }
+    assert_eq!(hashed_url_length_cache_remove(url.clone()), Some(url.len()));
+}
+
+static JOIN_PATHS_CALLS: AtomicU32 = AtomicU32::new(0);
+
+// the borrowed arguments are owned by the key
+#[cached(size = 10)]
+fn join_paths<'a, 'b>(base: &'a str, parts: &'b [String], separator: &'b char) -> String
+where
+    'a: 'b,
+{
+    JOIN_PATHS_CALLS.fetch_add(1, Ordering::SeqCst);
+    let mut path = base.to_string();
+    for part in parts {
+        path.push(*separator);
+        path.push_str(part);
+    }
+    path
+}
+
+#[test]
+fn test_cached_lifetimes() {
+    let base = String::from("/srv");
+    let parts = vec!["www".to_string(), "index.html".to_string()];
+    assert_eq!(join_paths(&base, &parts, &'/'), "/srv/www/index.html");
+    drop(parts);
+    let parts = ["www".to_string(), "index.html".to_string()];
+    assert_eq!(join_paths(&base, &parts, &'/'), "/srv/www/index.html");
+    assert_eq!(JOIN_PATHS_CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        join_paths_cache_remove(&base, &parts, &'/'),
+        Some("/srv/www/index.html".to_string())
+    );
+}
+
+#[cfg(feature = "async")]
+#[cached]
+async fn greet_a<'a>(greeting: &'a str, name: &'a str) -> String {
+    format!("{}, {}", greeting, name)
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_lifetimes_a() {
+    let name = String::from("Ada");
+    assert_eq!(greet_a("Hello", &name).await, "Hello, Ada");
+    drop(name);
+    assert_eq!(greet_a("Hello", "Ada").await, "Hello, Ada");
+    assert_eq!(GREET_A.lock().await.cache_hits(), Some(1));
+}
+
+static FILE_NAME_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached]
+fn file_name(path: impl AsRef<std::path::Path>, lowercase: bool) -> Option<String> {
+    FILE_NAME_CALLS.fetch_add(1, Ordering::SeqCst);
+    let name = path.as_ref().file_name()?.to_str()?;
+    Some(if lowercase {
+        name.to_lowercase()
+    } else {
+        name.to_string()
+    })
+}
+
+#[cached(
+    key = "String",
+    convert = r#"{ words.clone().into_iter().collect::<Vec<_>>().join(" ") }"#
+)]
+fn word_count(words: impl IntoIterator<Item = &'static str> + Clone) -> usize {
+    words.into_iter().count()
+}
+
+#[test]
+fn test_cached_impl_trait() {
+    assert_eq!(
+        file_name("/srv/Index.html", true),
+        Some("index.html".to_string())
+    );
+    assert_eq!(
+        file_name(std::path::PathBuf::from("/srv/Index.html"), true),
+        Some("index.html".to_string())
+    );
+    assert_eq!(
+        file_name("/srv/Index.html", false),
+        Some("Index.html".to_string())
+    );
+    assert_eq!(FILE_NAME_CALLS.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        file_name_cache_remove(std::path::Path::new("/srv/Index.html"), true),
+        Some(Some("index.html".to_string()))
+    );
+
+    assert_eq!(word_count(vec!["a", "b"]), 2);
+    assert_eq!(word_count(["a", "b"]), 2);
+    assert_eq!(WORD_COUNT.lock().unwrap().cache_hits(), Some(1));
+}